@@ -63,12 +63,12 @@
 | JMP  | 1  | 1  | 1  | 7       |
 */
 use crate::{
-    parser::{Address, Instruction, Node},
+    parser::{Address, DESTINATIONS, Instruction, JUMPS, Node},
     scanner::{Token, TokenType},
 };
 
 #[derive(Debug)]
-pub(crate) struct Assembler<'de, I: IntoIterator<Item = Node<'de>>> {
+pub struct Assembler<'de, I: IntoIterator<Item = Node<'de>>> {
     nodes: I,
 }
 
@@ -80,29 +80,60 @@ where
         Self { nodes }
     }
 
-    pub fn assemble(self) -> Vec<Address> {
-        let nodes = self.nodes;
+    /// Fails on the first node that is not a preprocessed instruction or `.org`, or
+    /// whose `dest` or `jump` the CPU has no encoding for.
+    pub fn assemble(self) -> anyhow::Result<Vec<Address>> {
+        self.words().collect()
+    }
+
+    /// The words of the nodes, each encoded as it is taken, so that nodes parsed one
+    /// at a time are never all held together.
+    pub fn words(self) -> impl Iterator<Item = anyhow::Result<Address>> {
+        self.nodes.into_iter().flat_map(|node| {
+            let (padding, word) = match node {
+                Node::Instruction(instruction) => {
+                    (0, Some(Assembler::<I>::assemble_instruction(instruction)))
+                }
+                Node::Org { padding, .. } => (padding, None),
+                Node::Label { name, .. } => {
+                    let message = format!(
+                        "Label ({}) is left to encode; preprocess the nodes first",
+                        name.lexeme
+                    );
+                    (0, Some(Err(name.error(message).into())))
+                }
+                Node::Section { directive, .. } | Node::Data { directive, .. } => {
+                    let message = format!(
+                        "`{}` is left to encode; preprocess the nodes first",
+                        directive.lexeme
+                    );
+                    (0, Some(Err(directive.error(message).into())))
+                }
+            };
 
-        nodes
-            .into_iter()
-            .map(|node| match node {
-                Node::Instruction(instruction) => Assembler::<I>::assemble_instruction(instruction),
-                Node::Label { .. } => unreachable!(),
-            })
-            .collect::<Vec<_>>()
+            std::iter::repeat_n(0, padding as usize).map(Ok).chain(word)
+        })
     }
 
-    fn assemble_instruction(instruction: Instruction) -> Address {
+    fn assemble_instruction(instruction: Instruction) -> anyhow::Result<Address> {
         match instruction {
             Instruction::A { token, .. } => match token {
                 Token {
                     token_type: TokenType::NUMBER(value),
                     ..
                 } => {
-                    assert!((value >> 15) == 0);
-                    value
+                    debug_assert!(
+                        value >> 15 == 0,
+                        "the parser and the preprocessor reject A-values past 32767"
+                    );
+                    Ok(value)
                 }
-                _ => unreachable!(),
+                token => Err(token
+                    .error(format!(
+                        "`@{}` is not resolved to an address; preprocess the nodes first",
+                        token.lexeme
+                    ))
+                    .into()),
             },
             Instruction::C {
                 dest, comp, jump, ..
@@ -114,26 +145,25 @@ where
                 result |= 1 << 13;
 
                 if let Some(token) = jump {
-                    let jump = Assembler::<I>::assemble_jump(&token);
+                    let jump = Assembler::<I>::assemble_jump(&token)?;
                     result |= jump;
                 }
 
                 if let Some(token) = dest {
-                    let dest = Assembler::<I>::assemble_dest(&token);
+                    let dest = Assembler::<I>::assemble_dest(&token)?;
                     result |= dest << 3;
                 }
 
-                let comp = Assembler::<I>::assemble_comp(&comp);
-                result |= comp << 6;
+                result |= comp.bits() << 6;
 
-                result
+                Ok(result)
             }
         }
     }
 
     #[rustfmt::skip]
-    fn assemble_jump(token: &Token<'_>) -> u16 {
-        match token {
+    fn assemble_jump(token: &Token<'_>) -> anyhow::Result<u16> {
+        let bits = match token {
             Token {
                 token_type: TokenType::NUMBER(0), ..
             } => 0,
@@ -158,13 +188,18 @@ where
             Token {
                 token_type: TokenType::JMP, ..
             } => 7,
-            _ => unreachable!("Expect a correct `jump` in the assemble step"),
-        }
+            _ => {
+                let message = format!("`{}` is not a jump; expected {JUMPS}", token.lexeme);
+                return Err(token.error(message).into());
+            }
+        };
+
+        Ok(bits)
     }
 
     #[rustfmt::skip]
-    fn assemble_dest(token: &Token<'_>) -> u16 {
-        match token {
+    fn assemble_dest(token: &Token<'_>) -> anyhow::Result<u16> {
+        let bits = match token {
             &Token {
                 token_type: TokenType::NUMBER(0), ..
             } => 0,
@@ -189,176 +224,80 @@ where
             &Token {
                 token_type: TokenType::AMD, ..
             } => 7,
-            _ => unreachable!("Expect a correct `dest` in the assemble step"),
-        }
+            _ => {
+                let message = format!("`{}` is not a destination; expected {DESTINATIONS}", token.lexeme);
+                return Err(token.error(message).into());
+            }
+        };
+
+        Ok(bits)
     }
+}
 
-    #[rustfmt::skip]
-    fn assemble_comp(tokens: &[Token<'_>]) -> u16 {
-        match tokens {
-            &[Token {
-                token_type: TokenType::NUMBER(0), ..
-            }] => 42,
-            &[Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 63,
-            &[Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 58,
-            &[Token {
-                token_type: TokenType::D, ..
-            }] => 12,
-            &[Token {
-                token_type: TokenType::A, ..
-            }] => 48,
-            &[Token {
-                token_type: TokenType::BANG, ..
-            },Token {
-                token_type: TokenType::D, ..
-            }] => 13,
-            &[Token {
-                token_type: TokenType::BANG, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 49,
-            &[Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::D, ..
-            }] => 15,
-            &[Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 51,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::PLUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 31,
-            &[Token {
-                token_type: TokenType::A, ..
-            },Token {
-                token_type: TokenType::PLUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 55,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 14,
-            &[Token {
-                token_type: TokenType::A, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 50,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::PLUS, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 2,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 19,
-            &[Token {
-                token_type: TokenType::A, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::D, ..
-            }] => 7,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::AMPERSAND, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 0,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::BAR, ..
-            },Token {
-                token_type: TokenType::A, ..
-            }] => 21,
-            &[Token {
-                token_type: TokenType::M, ..
-            }] => 112,
-            &[Token {
-                token_type: TokenType::BANG, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 113,
-            &[Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 115,
-            &[Token {
-                token_type: TokenType::M, ..
-            },Token {
-                token_type: TokenType::PLUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 119,
-            &[Token {
-                token_type: TokenType::M, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::NUMBER(1), ..
-            }] => 114,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::PLUS, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 66,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 83,
-            &[Token {
-                token_type: TokenType::M, ..
-            },Token {
-                token_type: TokenType::MINUS, ..
-            },Token {
-                token_type: TokenType::D, ..
-            }] => 71,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::AMPERSAND, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 64,
-            &[Token {
-                token_type: TokenType::D, ..
-            },Token {
-                token_type: TokenType::BAR, ..
-            },Token {
-                token_type: TokenType::M, ..
-            }] => 85,
-            _ => unreachable!("Expect a correct `comp` in the assemble step"),
+/// `.hack` text: one word per line, without a trailing newline.
+pub fn to_hack_text(words: &[Address]) -> String {
+    words
+        .iter()
+        .map(|x| format!("{:016b}", x))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `.hack.bin` contents: big-endian words.
+pub fn to_hack_bin(words: &[Address]) -> Vec<u8> {
+    words.iter().flat_map(|x| x.to_be_bytes()).collect()
+}
+
+#[cfg(test)]
+mod assembler_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn parse(source: &str) -> Vec<Node<'_>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        nodes.unwrap()
+    }
+
+    fn error(nodes: Vec<Node<'_>>) -> String {
+        Assembler::new(nodes).assemble().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn encodes_preprocessed_instructions() {
+        assert_eq!(
+            Assembler::new(parse("@21\nAM=M-1;JNE")).assemble().unwrap(),
+            [21, 0b1111_1100_1010_1101]
+        );
+    }
+
+    #[test]
+    fn names_what_it_cannot_encode() {
+        assert_eq!(
+            error(parse("@1\n(LOOP)")),
+            "[line 2:2] Error: Label (LOOP) is left to encode; preprocess the nodes first"
+        );
+        assert_eq!(
+            error(parse("@1\n@LOOP")),
+            "[line 2:2] Error: `@LOOP` is not resolved to an address; preprocess the nodes first"
+        );
+
+        // Tokens swapped in after parsing, as a program building its own nodes may
+        let mut nodes = parse("D;JGT\nAM=M");
+        if let Node::Instruction(Instruction::C { jump, .. }) = &mut nodes[0] {
+            jump.as_mut().unwrap().token_type = TokenType::D;
+        }
+        if let Node::Instruction(Instruction::C { dest, .. }) = &mut nodes[1] {
+            dest.as_mut().unwrap().token_type = TokenType::JMP;
         }
+        assert_eq!(
+            error(nodes.drain(..1).collect()),
+            format!("[line 1:3] Error: `JGT` is not a jump; expected {JUMPS}")
+        );
+        assert_eq!(
+            error(nodes),
+            format!("[line 2:1] Error: `AM` is not a destination; expected {DESTINATIONS}")
+        );
     }
 }
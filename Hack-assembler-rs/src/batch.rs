@@ -0,0 +1,212 @@
+//! Batch mode: every `.asm` file of a directory, or matching a `*`/`?` file-name
+//! pattern, assembled on worker threads into a `.hack` file each.
+
+use std::fs::{create_dir_all, read_dir};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::limits::Usage;
+
+/// Files `input` names in batch mode, sorted; `None` for a single file.
+pub fn inputs(input: &str) -> anyhow::Result<Option<Vec<PathBuf>>> {
+    let path = Path::new(input);
+    let (dir, pattern) = if path.is_dir() {
+        (path, "*.asm")
+    } else {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.contains(['*', '?']) => (
+                path.parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .unwrap_or(Path::new(".")),
+                name,
+            ),
+            _ => return Ok(None),
+        }
+    };
+
+    let pattern: Vec<_> = pattern.chars().collect();
+    let mut inputs = vec![];
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let name: Vec<_> = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .chars()
+            .collect();
+        if path.is_file() && matches(&pattern, &name) {
+            inputs.push(path);
+        }
+    }
+    if inputs.is_empty() {
+        anyhow::bail!("No .asm files match `{input}`");
+    }
+    inputs.sort();
+
+    Ok(Some(inputs))
+}
+
+/// Whether `name` matches `pattern`, where `*` is any run of characters and `?` any
+/// one character.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some((&c, rest)) => name
+            .split_first()
+            .is_some_and(|(&n, name)| (c == '?' || c == n) && matches(rest, name)),
+    }
+}
+
+/// Assembles every input into `output_dir` with `assemble(input, output)`, one input
+/// at a time per thread, and prints a summary table. Fails if any input failed.
+pub fn assemble_all<F>(inputs: &[PathBuf], output_dir: &Path, assemble: F) -> anyhow::Result<()>
+where
+    F: Fn(&Path, &Path) -> anyhow::Result<Usage> + Sync,
+{
+    create_dir_all(output_dir)?;
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(inputs.len());
+    let outputs: Vec<_> = inputs
+        .iter()
+        .map(|input| {
+            let name = input.file_stem().unwrap_or(input.as_os_str());
+            output_dir.join(name).with_extension("hack")
+        })
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, anyhow::Result<Usage>, Duration)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= inputs.len() {
+                            return results;
+                        }
+                        let start = Instant::now();
+                        // A panic fails its input only, not the whole batch
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            assemble(&inputs[i], &outputs[i])
+                        }))
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("The assembler panicked")));
+                        results.push((i, result, start.elapsed()));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Assembling thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, ..)| *i);
+
+    let width = inputs
+        .iter()
+        .map(|input| input.display().to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max("File".len());
+    println!(
+        "{:<width$}  {:>6}  {:>9}  {:>8}  Output",
+        "File", "ROM", "Variables", "Time"
+    );
+    let mut failures = vec![];
+    for (i, result, elapsed) in results.iter() {
+        let input = inputs[*i].display();
+        let time = format!("{:.1}ms", elapsed.as_secs_f64() * 1000.0);
+        match result {
+            Ok(usage) => println!(
+                "{input:<width$}  {:>6}  {:>9}  {time:>8}  {}",
+                usage.rom,
+                usage.variables,
+                outputs[*i].display()
+            ),
+            Err(error) => {
+                println!("{input:<width$}  {:>6}  {:>9}  {time:>8}  failed", "-", "-");
+                failures.push(format!("{input}: {error}"));
+            }
+        }
+    }
+    println!(
+        "[~] Assembled {} of {} files on {threads} thread{}",
+        inputs.len() - failures.len(),
+        inputs.len(),
+        if threads == 1 { "" } else { "s" }
+    );
+
+    if !failures.is_empty() {
+        anyhow::bail!("{}", failures.join("\n"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use std::fs::{read_to_string, write};
+
+    use super::*;
+
+    fn matches_str(pattern: &str, name: &str) -> bool {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        matches(&chars(pattern), &chars(name))
+    }
+
+    #[test]
+    fn matches_file_name_patterns() {
+        assert!(matches_str("*.asm", "Add.asm"));
+        assert!(matches_str("*.asm", ".asm"));
+        assert!(!matches_str("*.asm", "Add.hack"));
+        assert!(matches_str("Max?.asm", "MaxL.asm"));
+        assert!(!matches_str("Max?.asm", "Max.asm"));
+        assert!(matches_str("*a*b*", "xxaYYbzz"));
+    }
+
+    #[test]
+    fn assembles_every_file_of_a_directory() {
+        let dir = std::env::temp_dir().join(format!("hack-batch-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        for name in ["B.asm", "A.asm", "notes.txt"] {
+            write(dir.join(name), "@1").unwrap();
+        }
+
+        let found = inputs(dir.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(found, [dir.join("A.asm"), dir.join("B.asm")]);
+        assert_eq!(
+            inputs(dir.join("?.asm").to_str().unwrap())
+                .unwrap()
+                .unwrap(),
+            found
+        );
+        assert!(
+            inputs(dir.join("A.asm").to_str().unwrap())
+                .unwrap()
+                .is_none()
+        );
+
+        let output_dir = dir.join("out");
+        let result = assemble_all(&found, &output_dir, |input, output| {
+            if input.ends_with("B.asm") {
+                anyhow::bail!("broken");
+            }
+            write(output, "0000000000000001\n")?;
+            Ok(Usage::default())
+        });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("{}: broken", dir.join("B.asm").display())
+        );
+        assert!(read_to_string(output_dir.join("A.hack")).is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
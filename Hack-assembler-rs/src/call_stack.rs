@@ -0,0 +1,253 @@
+//! Call stacks of a program translated from VM code, read off the frames the VM
+//! translator lays out: `call` pushes the return address and the caller's LCL, ARG,
+//! THIS and THAT, then the callee's LCL points right after them and its ARG at the
+//! first of its arguments. Walking them stops at a return address outside the
+//! program or an LCL that does not go down, so a program not translated from VM code
+//! only shows its PC.
+//!
+//! The frames are named after the function their address is in, when the translator's
+//! `--functions` table is given, and after the label scope of the source map
+//! otherwise. Their `.asm` lines are traced back to `.vm` and `.jack` lines through
+//! the line maps given.
+
+use std::fmt::Write;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use n2t_core::line_map::{self, LineMap};
+use serde::Deserialize;
+
+use crate::emulator::{Cpu, SCREEN};
+use crate::parser::Address;
+use crate::source_map::SourceMap;
+
+/// Frames listed before the walk gives up, deep recursion aside.
+const MAX_FRAMES: usize = 32;
+
+/// More arguments than this between ARG and LCL mean that the pointers are not those
+/// of a VM frame.
+const MAX_ARGUMENTS: usize = 32;
+
+/// The VM translator's `--functions` table.
+#[derive(Debug, Deserialize)]
+struct Table {
+    functions: Vec<TableEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TableEntry {
+    name: String,
+    locals: u16,
+}
+
+/// The functions of a program with their first ROM address, by address.
+#[derive(Debug, Default)]
+pub struct Functions(Vec<(Address, String, u16)>);
+
+impl Functions {
+    /// The `--functions` table at `path`, placed by the labels of `map`.
+    pub fn read(path: &Path, map: &SourceMap) -> anyhow::Result<Self> {
+        let table: Table = serde_json::from_str(&read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+
+        let mut functions = vec![];
+        for function in table.functions {
+            let Some(&start) = map.labels.get(&function.name) else {
+                anyhow::bail!(
+                    "{}: function {} has no ({}) label in the program",
+                    path.display(),
+                    function.name,
+                    function.name
+                );
+            };
+            functions.push((start, function.name, function.locals));
+        }
+        functions.sort();
+
+        Ok(Self(functions))
+    }
+
+    /// The function whose code `address` is in, with its locals.
+    pub fn containing(&self, address: Address) -> Option<(&str, u16)> {
+        let i = self.0.partition_point(|(start, ..)| *start <= address);
+
+        i.checked_sub(1)
+            .map(|i| (self.0[i].1.as_str(), self.0[i].2))
+    }
+}
+
+/// The PC of a function call and the segment pointers it sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// The PC for the innermost frame, the address its callee returns to for the others.
+    pub pc: Address,
+    pub lcl: Address,
+    pub arg: Address,
+    pub this: Address,
+    pub that: Address,
+}
+
+impl Frame {
+    /// Words between ARG and the saved return address, unless there is no room for
+    /// the saved words or too much for arguments.
+    fn arguments<'a>(&self, ram: &'a [Address]) -> Option<&'a [Address]> {
+        let (arg, lcl) = (self.arg as usize, self.lcl as usize);
+        match lcl >= arg + 5 && lcl - 5 - arg <= MAX_ARGUMENTS && lcl < SCREEN {
+            true => Some(&ram[arg..lcl - 5]),
+            false => None,
+        }
+    }
+}
+
+/// The frames of `cpu`, innermost first.
+pub fn frames(cpu: &Cpu, map: &SourceMap) -> Vec<Frame> {
+    let ram = &cpu.ram;
+    let mut frames = vec![Frame {
+        pc: cpu.pc,
+        lcl: ram[1],
+        arg: ram[2],
+        this: ram[3],
+        that: ram[4],
+    }];
+    while frames.len() < MAX_FRAMES {
+        let lcl = frames[frames.len() - 1].lcl as usize;
+        if !(5..SCREEN).contains(&lcl) || map.at(ram[lcl - 5]).is_none() {
+            break;
+        }
+        let caller = Frame {
+            pc: ram[lcl - 5],
+            lcl: ram[lcl - 4],
+            arg: ram[lcl - 3],
+            this: ram[lcl - 2],
+            that: ram[lcl - 1],
+        };
+        frames.push(caller);
+        if caller.lcl as usize >= lcl {
+            break;
+        }
+    }
+
+    frames
+}
+
+/// `ROM[n]`, with the line and label scope of the instruction there when known, and
+/// the lines `line_maps` trace that line back to.
+pub fn describe(map: &SourceMap, line_maps: &[LineMap], address: Address) -> String {
+    match map.at(address) {
+        Some(entry) => {
+            let file = entry.file.as_ref().unwrap_or(&map.file);
+            let mut at = format!("ROM[{address}], {file}:{}", entry.line);
+            for (source, line) in line_map::trace(line_maps, file, entry.line) {
+                let _ = write!(at, ", {source}:{line}");
+            }
+            match &entry.scope {
+                Some(scope) => format!("{at} in {scope}"),
+                None => at,
+            }
+        }
+        None => format!("ROM[{address}]"),
+    }
+}
+
+/// A line per frame, innermost first: the function with its arguments (`?` when ARG
+/// and LCL do not frame them), where it is, and its locals when `functions` tells how
+/// many there are.
+pub fn lines(
+    cpu: &Cpu,
+    map: &SourceMap,
+    line_maps: &[LineMap],
+    functions: &Functions,
+) -> Vec<String> {
+    let signed = |words: &[Address]| {
+        let words: Vec<_> = words
+            .iter()
+            .map(|&word| (word as i16).to_string())
+            .collect();
+        words.join(", ")
+    };
+
+    let mut lines = vec![];
+    for (i, frame) in frames(cpu, map).iter().enumerate() {
+        let location = describe(map, line_maps, frame.pc);
+        let arguments = frame
+            .arguments(&cpu.ram)
+            .map_or_else(|| "?".to_string(), signed);
+        let line = match functions.containing(frame.pc) {
+            Some((name, locals)) => {
+                let lcl = frame.lcl as usize;
+                let locals = cpu.ram.get(lcl..lcl + locals as usize).unwrap_or(&[]);
+                format!(
+                    "#{i} {name}({arguments}) at {location}, locals [{}]",
+                    signed(locals)
+                )
+            }
+            None => format!("#{i} ({arguments}) at {location}"),
+        };
+        lines.push(format!("{line}, THIS {}, THAT {}", frame.this, frame.that));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod call_stack_tests {
+    use super::*;
+    use crate::{
+        assembler::Assembler, parser::Parser, preprocessor::Preprocessor, scanner::Scanner,
+    };
+
+    fn load(source: &str) -> (Cpu, SourceMap) {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let map = SourceMap::of("Prog.asm", &nodes);
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+
+        (Cpu::new(Assembler::new(nodes).assemble().unwrap()), map)
+    }
+
+    #[test]
+    fn names_the_frames_of_the_function_table() {
+        let (mut cpu, map) = load(
+            "(Sys.init)
+@Main.main
+0;JMP
+(Main.main)
+@Main.spin
+0;JMP
+(Main.spin)
+(Main.spin$LOOP)
+D=D+1
+@Main.spin$LOOP
+0;JMP",
+        );
+        // Sys.init, called from outside the program, called Main.main from ROM[1];
+        // Main.main, with one local, called Main.spin with 7 and -1 from ROM[3]
+        cpu.preload(&[(1, 274), (2, 267), (3, 3000), (4, 4000)]);
+        cpu.preload(&[(256, 0x7FFF), (257, 0), (258, 0), (259, 0), (260, 0)]);
+        cpu.preload(&[(261, 1), (262, 261), (263, 256), (264, 0), (265, 0)]);
+        cpu.preload(&[(266, 42), (267, 7), (268, 0xFFFF)]);
+        cpu.preload(&[(269, 3), (270, 266), (271, 261), (272, 3000), (273, 0)]);
+        cpu.run(10);
+
+        let functions = Functions(vec![
+            (0, "Sys.init".into(), 0),
+            (2, "Main.main".into(), 1),
+            (4, "Main.spin".into(), 0),
+        ]);
+        assert_eq!(
+            lines(&cpu, &map, &[], &functions),
+            [
+                "#0 Main.spin(7, -1) at ROM[4], Prog.asm:9 in Main.spin, locals [], THIS 3000, \
+                 THAT 4000",
+                "#1 Main.main() at ROM[3], Prog.asm:6 in Main.main, locals [42], THIS 3000, \
+                 THAT 0",
+                "#2 Sys.init() at ROM[1], Prog.asm:3 in Sys.init, locals [], THIS 0, THAT 0",
+            ]
+        );
+        assert_eq!(
+            lines(&cpu, &map, &[], &Functions::default())[0],
+            "#0 (7, -1) at ROM[4], Prog.asm:9 in Main.spin, THIS 3000, THAT 4000"
+        );
+    }
+}
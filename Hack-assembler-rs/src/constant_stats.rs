@@ -0,0 +1,155 @@
+//! How often each constant and symbol is loaded by an A-instruction, and how many of
+//! those loads find the value already in A: the words a cached register would save.
+
+use std::collections::HashMap;
+
+use crate::parser::{Instruction, Node, Parser};
+use crate::preprocessor::expand_macros;
+use crate::scanner::{Scanner, TokenType};
+
+/// Values listed by `--constant-stats`.
+pub const TOP: usize = 10;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Loads {
+    /// `@value` instructions.
+    pub count: usize,
+    /// Of them, the ones where A already holds `value`: no label in between, and no
+    /// C-instruction writing A.
+    pub reloads: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ConstantStats {
+    /// A-instructions of every value, as written: a number or a symbol.
+    pub loads: HashMap<String, Loads>,
+    /// Instructions of the text sections.
+    pub instructions: usize,
+}
+
+impl ConstantStats {
+    pub fn of(source: &str) -> anyhow::Result<Self> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let tokens = expand_macros(tokens?)?;
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
+
+        Ok(Self::of_nodes(&nodes?))
+    }
+
+    fn of_nodes(nodes: &[Node<'_>]) -> Self {
+        let mut stats = Self::default();
+        let mut in_a = None;
+        for node in nodes.iter() {
+            match node {
+                Node::Instruction(Instruction::A { token, terms, .. }) => {
+                    stats.instructions += 1;
+                    let text = terms
+                        .iter()
+                        .fold(token.lexeme.to_string(), |text, (sign, operand)| {
+                            text + &sign.lexeme + &operand.lexeme
+                        });
+                    let loads = stats.loads.entry(text.clone()).or_default();
+                    loads.count += 1;
+                    if in_a.as_ref() == Some(&text) {
+                        loads.reloads += 1;
+                    }
+                    in_a = Some(text);
+                }
+                Node::Instruction(Instruction::C { dest, .. }) => {
+                    stats.instructions += 1;
+                    if dest.as_ref().is_some_and(|dest| {
+                        matches!(
+                            dest.token_type,
+                            TokenType::A | TokenType::AM | TokenType::AD | TokenType::AMD
+                        )
+                    }) {
+                        in_a = None;
+                    }
+                }
+                // Reached by a jump, with anything in A
+                Node::Label { .. } | Node::Section { .. } | Node::Org { .. } => in_a = None,
+                Node::Data { .. } => {}
+            }
+        }
+
+        stats
+    }
+
+    /// The `n` most loaded values, ties by name.
+    pub fn most_loaded(&self, n: usize) -> Vec<(&str, &Loads)> {
+        let mut loads: Vec<_> = self
+            .loads
+            .iter()
+            .map(|(value, loads)| (value.as_str(), loads))
+            .collect();
+        loads.sort_by(|(a, a_loads), (b, b_loads)| {
+            b_loads.count.cmp(&a_loads.count).then_with(|| a.cmp(b))
+        });
+        loads.truncate(n);
+
+        loads
+    }
+
+    pub fn a_instructions(&self) -> usize {
+        self.loads.values().map(|loads| loads.count).sum()
+    }
+
+    pub fn reloads(&self) -> usize {
+        self.loads.values().map(|loads| loads.reloads).sum()
+    }
+
+    pub fn print(&self) {
+        println!(
+            "[~] A-instructions: {} of {} instructions, loading {} distinct values",
+            self.a_instructions(),
+            self.instructions,
+            self.loads.len()
+        );
+        for (value, loads) in self.most_loaded(TOP) {
+            println!(
+                "[~]   @{value:<16} {:>6} loads {:>6} reloads",
+                loads.count, loads.reloads
+            );
+        }
+        println!(
+            "[~] {} loads find the value already in A, and could be dropped",
+            self.reloads()
+        );
+    }
+}
+
+#[cfg(test)]
+mod constant_stats_tests {
+    use super::*;
+
+    #[test]
+    fn counts_loads_and_reloads() {
+        let stats = ConstantStats::of(
+            "@SP\nAM=M-1\nD=M\n@SP\nA=M-1\nM=D+M\n@5\nD=A\n@5\nD=D+A\n(LOOP)\n@5\n0;JMP",
+        )
+        .unwrap();
+
+        assert_eq!(stats.instructions, 12);
+        assert_eq!(stats.a_instructions(), 5);
+        assert_eq!(
+            stats.most_loaded(TOP),
+            [
+                (
+                    "5",
+                    &Loads {
+                        count: 3,
+                        reloads: 1
+                    }
+                ),
+                (
+                    "SP",
+                    &Loads {
+                        count: 2,
+                        reloads: 0
+                    }
+                ),
+            ]
+        );
+        assert_eq!(stats.reloads(), 1);
+    }
+}
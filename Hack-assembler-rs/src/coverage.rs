@@ -0,0 +1,142 @@
+//! `--coverage`: how often every line of a program ran, as an lcov tracefile that
+//! `genhtml` and editor plugins read. The `.asm` lines are counted from the ROM
+//! addresses of the run through the source map; the line maps of the VM translator and
+//! the Jack compiler carry the counts on to `.vm` and `.jack` lines.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use n2t_core::line_map::LineMap;
+
+use crate::source_map::SourceMap;
+
+/// Hit counts of the lines of every file counted, by file and line.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    files: BTreeMap<String, BTreeMap<usize, u64>>,
+}
+
+impl Coverage {
+    /// The `.asm` lines of `map`, from the `hits` of the ROM addresses: a line ran as
+    /// often as the instruction of it that ran the most.
+    pub fn of(map: &SourceMap, hits: &[u64]) -> Self {
+        let mut files: BTreeMap<String, BTreeMap<usize, u64>> = BTreeMap::new();
+        for entry in map.entries.iter() {
+            let file = entry.file.as_ref().unwrap_or(&map.file);
+            let count = hits.get(entry.address as usize).copied().unwrap_or(0);
+            let line = files
+                .entry(file.clone())
+                .or_default()
+                .entry(entry.line)
+                .or_default();
+            *line = (*line).max(count);
+        }
+
+        Self { files }
+    }
+
+    /// Adds the sources of the files counted that `maps` map, and theirs in turn, so
+    /// the maps can be given in any order.
+    pub fn carry(&mut self, maps: &[LineMap]) {
+        let mut pending: Vec<String> = self.files.keys().cloned().collect();
+        while let Some(file) = pending.pop() {
+            for mapped in maps.iter().filter_map(|map| map.get(&file)) {
+                for (source, counts) in mapped.carry(&self.files[&file]) {
+                    if !self.files.contains_key(&source) {
+                        pending.push(source.clone());
+                    }
+                    let lines = self.files.entry(source).or_default();
+                    for (line, count) in counts {
+                        let hits = lines.entry(line).or_default();
+                        *hits = (*hits).max(count);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The lcov tracefile: a record per file, with a `DA:line,count` line per line.
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (file, lines) in self.files.iter() {
+            let _ = writeln!(lcov, "SF:{file}");
+            for (line, count) in lines {
+                let _ = writeln!(lcov, "DA:{line},{count}");
+            }
+            let _ = writeln!(lcov, "LF:{}", lines.len());
+            let _ = writeln!(lcov, "LH:{}", run(lines));
+            lcov.push_str("end_of_record\n");
+        }
+
+        lcov
+    }
+
+    /// A line per file: the lines that ran out of those with code.
+    pub fn summary(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .map(|(file, lines)| {
+                let run = run(lines);
+                format!(
+                    "{file}: {run}/{} lines run ({:.1}%)",
+                    lines.len(),
+                    100.0 * run as f64 / lines.len() as f64
+                )
+            })
+            .collect()
+    }
+}
+
+fn run(lines: &BTreeMap<usize, u64>) -> usize {
+    lines.values().filter(|&&count| count > 0).count()
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use super::*;
+    use crate::{
+        assembler::Assembler, emulator::Cpu, parser::Parser, preprocessor::Preprocessor,
+        scanner::Scanner,
+    };
+
+    #[test]
+    fn carries_the_hits_to_vm_and_jack_lines() {
+        // `push constant 2`, `if-goto SKIP`, `push constant 7`, `label SKIP`
+        let source = "@2\nD=A\n@SKIP\nD;JNE\n@7\nD=A\n(SKIP)\n@SKIP\n0;JMP";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let map = SourceMap::of("out/Main.asm", &nodes);
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+        let mut cpu = Cpu::new(Assembler::new(nodes).assemble().unwrap());
+        cpu.count_hits();
+        cpu.run(100);
+
+        let mut vm_map = LineMap::default();
+        vm_map.push("Main.asm", 1, "Main.vm", 1);
+        vm_map.push("Main.asm", 3, "Main.vm", 2);
+        vm_map.push("Main.asm", 5, "Main.vm", 3);
+        vm_map.push("Main.asm", 7, "Main.vm", 4);
+        let mut jack_map = LineMap::default();
+        jack_map.push("src/Main.vm", 1, "Main.jack", 5);
+        jack_map.push("src/Main.vm", 3, "Main.jack", 6);
+
+        let mut coverage = Coverage::of(&map, cpu.hits());
+        coverage.carry(&[jack_map, vm_map]);
+        assert_eq!(
+            coverage.lcov(),
+            "SF:Main.jack\nDA:5,1\nDA:6,0\nLF:2\nLH:1\nend_of_record\n\
+             SF:Main.vm\nDA:1,1\nDA:2,1\nDA:3,0\nDA:4,1\nLF:4\nLH:3\nend_of_record\n\
+             SF:out/Main.asm\nDA:1,1\nDA:2,1\nDA:3,1\nDA:4,1\nDA:5,0\nDA:6,0\nDA:8,1\nDA:9,1\n\
+             LF:8\nLH:6\nend_of_record\n"
+        );
+        assert_eq!(
+            coverage.summary(),
+            [
+                "Main.jack: 1/2 lines run (50.0%)",
+                "Main.vm: 3/4 lines run (75.0%)",
+                "out/Main.asm: 6/8 lines run (75.0%)",
+            ]
+        );
+    }
+}
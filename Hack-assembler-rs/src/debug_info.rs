@@ -0,0 +1,173 @@
+//! The VM translator's `--debug-info` file: the function of every generated label, the
+//! call site of every return address and the `static` word of every variable, so that
+//! a run shows them by their VM names. Locations are named after the function they are
+//! in, or after the call a return address comes back from, instead of the last label.
+
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::parser::Address;
+use crate::source_map::SourceMap;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DebugInfo {
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    returns: BTreeMap<String, CallSite>,
+    #[serde(default)]
+    statics: BTreeMap<String, Static>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallSite {
+    function: Option<String>,
+    callee: String,
+    file: String,
+    line: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Static {
+    file: String,
+    index: u16,
+}
+
+/// The RAM addresses of the `static` words of a program, by `.vm` file.
+#[derive(Debug, Default)]
+pub struct Statics(Vec<(String, Vec<(u16, Address)>)>);
+
+impl DebugInfo {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        serde_json::from_str(&read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    /// Names the label scopes of `map` after their function, and those of return
+    /// addresses after their call as well.
+    pub fn name_scopes(&self, map: &mut SourceMap) {
+        for scope in map
+            .entries
+            .iter_mut()
+            .filter_map(|entry| entry.scope.as_mut())
+        {
+            if let Some(call) = self.returns.get(scope) {
+                let back = format!("back from {} at {}:{}", call.callee, call.file, call.line);
+                *scope = match &call.function {
+                    Some(function) => format!("{function}, {back}"),
+                    None => back,
+                };
+            } else if let Some(function) = self.labels.get(scope) {
+                *scope = function.clone();
+            }
+        }
+    }
+
+    /// The statics the program keeps, placed by its `variables`.
+    pub fn statics(&self, variables: &BTreeMap<String, Address>) -> Statics {
+        let mut files: BTreeMap<&str, Vec<(u16, Address)>> = BTreeMap::new();
+        for (name, word) in self.statics.iter() {
+            if let Some(&address) = variables.get(name) {
+                files
+                    .entry(&word.file)
+                    .or_default()
+                    .push((word.index, address));
+            }
+        }
+
+        Statics(
+            files
+                .into_iter()
+                .map(|(file, mut words)| {
+                    words.sort();
+                    (file.to_string(), words)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Statics {
+    /// A line per file: the values of its statics in `ram`, as signed numbers.
+    pub fn lines(&self, ram: &[Address]) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|(file, words)| {
+                let words: Vec<_> = words
+                    .iter()
+                    .map(|&(index, address)| {
+                        format!("static {index} = {}", ram[address as usize] as i16)
+                    })
+                    .collect();
+                format!("Statics of {file}: {}", words.join(", "))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod debug_info_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn names_scopes_and_statics() {
+        let info: DebugInfo = serde_json::from_value(serde_json::json!({
+            "labels": {
+                "Main.END": "Main.main",
+                "Main.Main.double.return.0": "Main.main",
+            },
+            "returns": {
+                "Main.Main.double.return.0": {
+                    "function": "Main.main",
+                    "callee": "Main.double",
+                    "file": "Main.vm",
+                    "line": 3,
+                },
+            },
+            "statics": {
+                "Main.2": {"file": "Main.vm", "index": 2},
+                "Main.0": {"file": "Main.vm", "index": 0},
+                "Main.5": {"file": "Main.vm", "index": 5},
+            },
+        }))
+        .unwrap();
+
+        let source = "(Main.main)\n@Main.double\n0;JMP\n(Main.Main.double.return.0)\n\
+                      @Main.2\nM=D\n(Main.END)\n@Main.END\n0;JMP\n(Main.double)\n0;JMP";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut map = SourceMap::of("Prog.asm", &nodes.unwrap());
+        info.name_scopes(&mut map);
+        let scopes: Vec<_> = map
+            .entries
+            .iter()
+            .map(|entry| entry.scope.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            scopes,
+            [
+                "Main.main",
+                "Main.main",
+                "Main.main, back from Main.double at Main.vm:3",
+                "Main.main, back from Main.double at Main.vm:3",
+                "Main.main",
+                "Main.main",
+                "Main.double",
+            ]
+        );
+
+        // `Main.5` is never used, so it takes no RAM
+        let variables = BTreeMap::from([("Main.2".to_string(), 17), ("Main.0".to_string(), 16)]);
+        let mut ram = vec![0; 18];
+        ram[16] = 7;
+        ram[17] = 0xFFFF;
+        assert_eq!(
+            info.statics(&variables).lines(&ram),
+            ["Statics of Main.vm: static 0 = 7, static 2 = -1"]
+        );
+    }
+}
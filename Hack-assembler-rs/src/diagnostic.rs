@@ -0,0 +1,140 @@
+//! Errors pointing at a span of the source.
+
+pub use n2t_core::diagnostic::SyntaxError;
+
+use crate::preprocessor::LabelConflicts;
+
+/// Shows the source line of a [`SyntaxError`], or of each of the [`LabelConflicts`];
+/// other errors are left as they are.
+pub fn with_source(error: anyhow::Error, source: &str) -> anyhow::Error {
+    match error.downcast_ref::<LabelConflicts>() {
+        Some(conflicts) => anyhow::anyhow!(conflicts.render(source)),
+        None => n2t_core::diagnostic::with_source(error, source),
+    }
+}
+
+/// A-instruction value that does not fit in its 15 bits.
+pub const A_INSTRUCTION_RANGE: &str = "E001";
+
+#[cfg(test)]
+mod diagnostic_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn first_error(source: &str) -> SyntaxError {
+        let result: anyhow::Result<Vec<_>> = Scanner::new(source)
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|tokens| Parser::new(tokens.into_iter()).collect());
+
+        result.unwrap_err().downcast().expect("A positioned error")
+    }
+
+    #[test]
+    fn points_at_an_unexpected_character() {
+        let source = "@1\nD=M#1\n";
+
+        assert_eq!(
+            first_error(source).render(source),
+            "[line 2:4] Error: Unexpected character `#`\n 2 | D=M#1\n   |    ^"
+        );
+    }
+
+    #[test]
+    fn lists_the_computations_of_the_cpu() {
+        let error = first_error("D=M+A");
+        assert_eq!((error.line, error.column, error.width), (1, 3, 3));
+        assert!(error.message.starts_with(
+            "`M+A` is not a computation of the Hack CPU; expected `0`, `1`, `-1`, `D`, `A`,"
+        ));
+        assert!(error.message.ends_with("`D&M` or `D|M`"));
+    }
+
+    #[test]
+    fn points_at_an_unexpected_token() {
+        let error = first_error("  @LOOP\n\t(END\n@END");
+        assert_eq!((error.line, error.column, error.width), (3, 1, 1));
+        assert_eq!(error.message, "Expected `)` but got `@`");
+
+        let error = first_error("(LOOP)\n  @;");
+        assert_eq!((error.line, error.column), (2, 4));
+        assert_eq!(error.message, "Expected a symbol or a number but got `;`");
+
+        let error = first_error("@LOOP\n(LOOP");
+        assert_eq!((error.line, error.column), (2, 6));
+        assert_eq!(error.message, "Expected `)` but got the end of the file");
+    }
+
+    #[test]
+    fn explains_malformed_instructions() {
+        let message = |source| {
+            let error = first_error(source);
+            (error.line, error.column, error.width, error.message)
+        };
+
+        assert_eq!(
+            message("@\nD=M"),
+            (1, 1, 1, "Expected a symbol or a number after `@`".into())
+        );
+        assert_eq!(
+            message("D=;JMP"),
+            (1, 2, 1, "Expected a computation after `=`".into())
+        );
+        assert_eq!(
+            message("@1\nD;"),
+            (
+                2,
+                2,
+                1,
+                "Expected a jump after `;`: `JGT`, `JEQ`, `JGE`, `JLT`, `JNE`, `JLE` or `JMP`"
+                    .into()
+            )
+        );
+        assert_eq!(
+            message("D=M JMP"),
+            (1, 5, 3, "Expected `;` before the jump `JMP`".into())
+        );
+        assert_eq!(
+            message("AM=M-1\nD=A+D"),
+            (
+                2,
+                3,
+                3,
+                "`A+D` is not a computation of the Hack CPU (write `D+A`)".into()
+            )
+        );
+        assert_eq!(
+            message("DM=1"),
+            (1, 1, 2, "`DM` is not a destination; expected `M`, `D`, `MD`, `A`, `AM`, `AD` or `AMD` (write `MD`)".into())
+        );
+        assert_eq!(
+            message("0;JXX"),
+            (
+                1,
+                3,
+                3,
+                "`JXX` is not a jump; expected `JGT`, `JEQ`, `JGE`, `JLT`, `JNE`, `JLE` or `JMP`"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_numbers_out_of_range() {
+        let error = first_error("@1\n@65536");
+        assert_eq!((error.line, error.column, error.width), (2, 2, 5));
+        assert_eq!(error.message, "Number `65536` is out of range 0..=65535");
+
+        let error = first_error("@1\n@2abc\nD=M");
+        assert_eq!((error.line, error.column, error.width), (2, 2, 4));
+        assert_eq!(
+            error.message,
+            "`2abc` is not a number, and symbols cannot start with a digit"
+        );
+
+        let source = "@32767\n@32768";
+        assert_eq!(
+            first_error(source).render(source),
+            "[line 2:2] Error[E001]: A-instruction value `32768` is out of range 0..=32767\n 2 | @32768\n   |  ^^^^^"
+        );
+    }
+}
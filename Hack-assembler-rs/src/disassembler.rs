@@ -0,0 +1,169 @@
+//! The `disassemble` subcommand: `.hack` machine code back to assembly text, to read
+//! the binaries of other tools or round-trip this one's.
+//!
+//! Every word becomes one instruction, so the text assembles to the same words. The
+//! targets of jumps, an `@n` right before a jumping C-instruction, get labels named
+//! after their ROM address, such as `(ROM12)`; other values stay numbers, as nothing
+//! tells a variable from a constant.
+
+use std::collections::BTreeSet;
+use std::fs::{read, read_to_string};
+use std::path::Path;
+
+use crate::parser::{Address, Comp};
+
+const DEST: [&str; 8] = ["", "M", "D", "MD", "A", "AM", "AD", "AMD"];
+
+const JUMP: [&str; 8] = ["", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
+/// The words of a `.hack` file, or of a big-endian `.bin` one.
+pub fn read_words(path: &Path) -> anyhow::Result<Vec<Address>> {
+    let words = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bin") => {
+            let bytes = read(path)?;
+            if bytes.len() % 2 != 0 {
+                anyhow::bail!("{}: odd number of bytes", path.display());
+            }
+            bytes
+                .chunks(2)
+                .map(|word| Address::from_be_bytes([word[0], word[1]]))
+                .collect()
+        }
+        _ => parse_hack(&read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?,
+    };
+
+    Ok(words)
+}
+
+/// One word of 16 binary digits per line; blank lines are skipped.
+pub fn parse_hack(text: &str) -> anyhow::Result<Vec<Address>> {
+    let mut words = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != 16 {
+            anyhow::bail!("line {}: expected 16 binary digits but got `{line}`", i + 1);
+        }
+        let word = Address::from_str_radix(line, 2).map_err(|_| {
+            anyhow::anyhow!("line {}: expected binary digits but got `{line}`", i + 1)
+        })?;
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+/// `dest=comp;jump` of a C-instruction, or why the word is none.
+fn c_instruction(word: Address) -> Result<String, &'static str> {
+    if word >> 13 != 0b111 {
+        return Err("neither an A- nor a C-instruction");
+    }
+    let Some(comp) = Comp::from_bits((word >> 6) & 0x7f) else {
+        return Err("a comp the assembly language has no mnemonic for");
+    };
+
+    let mut text = String::new();
+    let dest = DEST[((word >> 3) & 0b111) as usize];
+    if !dest.is_empty() {
+        text.push_str(dest);
+        text.push('=');
+    }
+    text.push_str(comp.mnemonic());
+    let jump = JUMP[(word & 0b111) as usize];
+    if !jump.is_empty() {
+        text.push(';');
+        text.push_str(jump);
+    }
+
+    Ok(text)
+}
+
+/// Assembly text of `words`, one instruction per line.
+pub fn disassemble(words: &[Address]) -> anyhow::Result<String> {
+    let jumps = |i: usize| {
+        words
+            .get(i)
+            .is_some_and(|&next| next >> 15 == 1 && next & 0b111 != 0)
+    };
+    let targets: BTreeSet<usize> = (0..words.len())
+        .filter(|&i| words[i] >> 15 == 0 && jumps(i + 1))
+        .map(|i| words[i] as usize)
+        .filter(|&target| target <= words.len())
+        .collect();
+
+    let mut text = String::new();
+    for (i, &word) in words.iter().enumerate() {
+        if targets.contains(&i) {
+            text += &format!("(ROM{i})\n");
+        }
+        if word >> 15 == 0 {
+            match targets.contains(&(word as usize)) && jumps(i + 1) {
+                true => text += &format!("@ROM{word}\n"),
+                false => text += &format!("@{word}\n"),
+            }
+        } else {
+            let instruction = c_instruction(word)
+                .map_err(|reason| anyhow::anyhow!("ROM[{i}]: {word:016b} is {reason}"))?;
+            text += &instruction;
+            text.push('\n');
+        }
+    }
+    if targets.contains(&words.len()) {
+        text += &format!("(ROM{})\n", words.len());
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod disassembler_tests {
+    use super::*;
+    use crate::{
+        assembler::{Assembler, to_hack_text},
+        parser::Parser,
+        preprocessor::Preprocessor,
+        scanner::Scanner,
+    };
+
+    fn assemble(source: &str) -> Vec<Address> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+
+        Assembler::new(nodes).assemble().unwrap()
+    }
+
+    #[test]
+    fn decodes_every_field() {
+        let words = assemble("@R0\nD=M\n@i\nAMD=D|M;JLE\n(END)\n@END\n0;JMP");
+
+        assert_eq!(
+            disassemble(&words).unwrap(),
+            "@0\nD=M\n@16\nAMD=D|M;JLE\n(ROM4)\n@ROM4\n0;JMP\n"
+        );
+    }
+
+    #[test]
+    fn pong_round_trips() {
+        let words = assemble(include_str!("../input/Pong.asm"));
+        let hack = to_hack_text(&words);
+
+        let text = disassemble(&parse_hack(&hack).unwrap()).unwrap();
+        assert_eq!(assemble(&text), words);
+    }
+
+    #[test]
+    fn rejects_words_without_mnemonics() {
+        let error = disassemble(&[0b1111_1111_1100_0000]).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "ROM[0]: 1111111111000000 is a comp the assembly language has no mnemonic for"
+        );
+        assert!(parse_hack("0000000000000010\n01\n").is_err());
+    }
+}
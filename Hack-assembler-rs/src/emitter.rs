@@ -0,0 +1,100 @@
+//! Output backends for an assembled program, selected from the CLI flags.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::assembler::{to_hack_bin, to_hack_text};
+use crate::parser::Address;
+use crate::provenance::{Artifact, Provenance};
+
+pub trait Emitter {
+    /// Assembled program, one word per instruction.
+    fn emit_binary(&mut self, words: &[Address]) -> anyhow::Result<()>;
+
+    /// RAM image of the `.data` sections, from RAM[0]; empty without any.
+    fn emit_ram(&mut self, _image: &[Address]) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// `.hack` text file.
+pub struct HackWriter {
+    pub path: String,
+}
+
+impl Emitter for HackWriter {
+    fn emit_binary(&mut self, words: &[Address]) -> anyhow::Result<()> {
+        let mut output_file = File::create(&self.path)?;
+        output_file.write_all(to_hack_text(words).as_bytes())?;
+
+        Ok(())
+    }
+
+    /// `.ram` file next to the `.hack` one, in the same text format.
+    fn emit_ram(&mut self, image: &[Address]) -> anyhow::Result<()> {
+        if image.is_empty() {
+            return Ok(());
+        }
+
+        let path = Path::new(&self.path).with_extension("ram");
+        println!("[<-] RAM image: {}", path.display());
+        let mut output_file = File::create(path)?;
+        output_file.write_all(to_hack_text(image).as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Raw big-endian `.hack.bin` file.
+pub struct BinWriter {
+    pub path: String,
+}
+
+impl Emitter for BinWriter {
+    fn emit_binary(&mut self, words: &[Address]) -> anyhow::Result<()> {
+        let mut output_file = File::create(&self.path)?;
+        output_file.write_all(&to_hack_bin(words))?;
+
+        Ok(())
+    }
+}
+
+/// `.hack.json` sidecar with the hashes of the outputs the other backends write.
+pub struct ProvenanceWriter {
+    /// Path of the `.hack` file.
+    pub output: String,
+    pub bin: bool,
+    pub input: String,
+    pub source: String,
+    /// The files the input includes, recorded as inputs after it.
+    pub includes: Vec<Artifact>,
+}
+
+impl Emitter for ProvenanceWriter {
+    fn emit_binary(&mut self, words: &[Address]) -> anyhow::Result<()> {
+        let mut flags = vec![];
+        if self.bin {
+            flags.push("--bin".to_string());
+        }
+
+        let input = Artifact::new(&self.input, self.source.as_bytes());
+        let mut provenance = Provenance::new(flags, input, &self.source);
+        provenance.inputs.append(&mut self.includes);
+
+        provenance
+            .outputs
+            .push(Artifact::new(&self.output, to_hack_text(words).as_bytes()));
+        if self.bin {
+            provenance.outputs.push(Artifact::new(
+                format!("{}.bin", self.output),
+                &to_hack_bin(words),
+            ));
+        }
+
+        let provenance_file = File::create(format!("{}.json", self.output))?;
+        serde_json::to_writer_pretty(provenance_file, &provenance)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,560 @@
+//! Headless Hack CPU for `--run`, executing the assembled words as the hardware does.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::Instant;
+
+use crate::parser::Address;
+
+/// Words the 15-bit A register can address, the screen and keyboard included.
+pub const RAM_SIZE: usize = 32 * 1024;
+pub const SCREEN: usize = 16384;
+pub const KBD: usize = 24576;
+
+/// Taken jumps [`Cpu::branches`] keeps, the oldest dropped first.
+pub const RECENT_BRANCHES: usize = 16;
+
+/// Instructions run between two looks at the clock, when there is a deadline.
+const CLOCK_INTERVAL: u64 = 4096;
+
+/// Why [`Cpu::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Reached the `(END) @END 0;JMP` loop programs end with.
+    Halted,
+    /// Ran the given number of instructions without halting.
+    StepLimit,
+    /// Still running at the [`Cpu::set_deadline`].
+    Timeout,
+    /// Ran past the last instruction.
+    EndOfRom,
+    /// Reached one of the [`Cpu::set_breakpoints`], before running it.
+    Breakpoint,
+    /// Reached an instruction accessing memory the platform does not have, before
+    /// running it.
+    Trap(Trap),
+}
+
+/// An access to M that the hardware would not make as the program means it: memory
+/// ends at KBD, and only the keyboard sets KBD. A program gets there through a wrong
+/// pointer, most often one that a translator or compiler bug left in SP, LCL, ARG,
+/// THIS or THAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// Read of a word past KBD.
+    Read(Address),
+    /// Write of a word past KBD.
+    Write(Address),
+    /// Write of KBD.
+    KeyboardWrite,
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trap::Read(address) => write!(f, "read of RAM[{address}], past KBD at RAM[{KBD}]"),
+            Trap::Write(address) => write!(f, "write of RAM[{address}], past KBD at RAM[{KBD}]"),
+            Trap::KeyboardWrite => write!(
+                f,
+                "write of KBD at RAM[{KBD}], which only the keyboard sets"
+            ),
+        }
+    }
+}
+
+/// A read or write of M, as [`Cpu::record_accesses`] keeps them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    /// The instruction making it, counted from 1.
+    pub step: u64,
+    pub pc: Address,
+    pub write: bool,
+    pub address: Address,
+    /// The word read, or written.
+    pub value: Address,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verb = if self.write { "writes" } else { "reads" };
+        write!(
+            f,
+            "{}: ROM[{}] {verb} RAM[{}] = {}",
+            self.step, self.pc, self.address, self.value as i16
+        )
+    }
+}
+
+pub struct Cpu {
+    rom: Vec<Address>,
+    pub ram: Vec<Address>,
+    pub pc: Address,
+    pub a: Address,
+    pub d: Address,
+    /// Instructions executed so far.
+    pub steps: u64,
+    /// `(step, key)` presses not made yet, by step.
+    keys: Vec<(u64, Address)>,
+    /// `(from, to)` ROM addresses of the last jumps taken, the latest last.
+    branches: VecDeque<(Address, Address)>,
+    breakpoints: Vec<Address>,
+    /// Times each ROM address ran, when counted.
+    hits: Vec<u64>,
+    /// When [`Cpu::run`] gives up, whatever the steps left.
+    deadline: Option<Instant>,
+    /// Every access to M so far, when recorded.
+    accesses: Option<Vec<Access>>,
+}
+
+impl Cpu {
+    pub fn new(rom: Vec<Address>) -> Self {
+        Self {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            a: 0,
+            d: 0,
+            steps: 0,
+            keys: vec![],
+            branches: VecDeque::with_capacity(RECENT_BRANCHES),
+            breakpoints: vec![],
+            hits: vec![],
+            deadline: None,
+            accesses: None,
+        }
+    }
+
+    /// A copy to run `code` on, placed after the ROM and started there, with the RAM and
+    /// registers as they are. It has no breakpoints, keys, deadline or records of its
+    /// own, and whatever it does leaves this CPU as it was.
+    pub fn fork(&self, code: &[Address]) -> Cpu {
+        let mut rom = self.rom.clone();
+        rom.extend_from_slice(code);
+
+        Cpu {
+            ram: self.ram.clone(),
+            pc: self.rom.len() as Address,
+            a: self.a,
+            d: self.d,
+            ..Cpu::new(rom)
+        }
+    }
+
+    /// Words of the ROM, where the code of a [`Cpu::fork`] goes.
+    pub fn rom_len(&self) -> usize {
+        self.rom.len()
+    }
+
+    /// Makes [`Cpu::run`] stop with [`Stop::Timeout`] once `deadline` is past, so that a
+    /// program that does not halt takes no longer than that whatever its step limit.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Keeps every read and write of M from now on, for an audit of what a program
+    /// touched.
+    pub fn record_accesses(&mut self) {
+        self.accesses = Some(vec![]);
+    }
+
+    /// The accesses to M since [`Cpu::record_accesses`], in order.
+    pub fn accesses(&self) -> &[Access] {
+        self.accesses.as_deref().unwrap_or_default()
+    }
+
+    /// Counts the times each ROM address runs from now on.
+    pub fn count_hits(&mut self) {
+        self.hits = vec![0; self.rom.len()];
+    }
+
+    /// Times each ROM address ran, by address, once [`Cpu::count_hits`] is called. The
+    /// `@pc` and jump a program halts at count once.
+    pub fn hits(&self) -> &[u64] {
+        &self.hits
+    }
+
+    /// ROM addresses [`Cpu::run`] stops at.
+    pub fn set_breakpoints(&mut self, breakpoints: &[Address]) {
+        self.breakpoints = breakpoints.to_vec();
+    }
+
+    /// `(from, to)` ROM addresses of up to [`RECENT_BRANCHES`] last jumps taken, the
+    /// latest last: where a program that does not halt keeps going round.
+    pub fn branches(&self) -> impl Iterator<Item = &(Address, Address)> {
+        self.branches.iter()
+    }
+
+    /// Sets KBD to each `key` once `step` instructions have run, as if typed then.
+    pub fn script_keys(&mut self, keys: &[(u64, Address)]) {
+        self.keys = keys.to_vec();
+        self.keys.sort_by_key(|&(step, _)| std::cmp::Reverse(step));
+    }
+
+    /// CRC-32 of the screen memory, each word as two bytes, high one first: a short
+    /// way to compare what a program drew.
+    pub fn screen_crc(&self) -> u32 {
+        let mut crc = !0u32;
+        for word in self.ram[SCREEN..KBD].iter() {
+            for byte in word.to_be_bytes() {
+                crc ^= byte as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 == 1 {
+                        (crc >> 1) ^ 0xEDB8_8320
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        }
+
+        !crc
+    }
+
+    /// Sets RAM words before running, later pairs winning over earlier ones.
+    pub fn preload(&mut self, words: &[(Address, Address)]) {
+        for &(address, value) in words {
+            self.ram[address as usize % RAM_SIZE] = value;
+        }
+    }
+
+    /// Runs until the program halts, `max_steps` instructions are executed, the
+    /// program counter leaves the ROM, reaches a breakpoint or an instruction that
+    /// traps, or the deadline passes. The instruction the run starts at is never a
+    /// breakpoint, so that running again goes on past it.
+    pub fn run(&mut self, max_steps: u64) -> Stop {
+        for i in 0..max_steps {
+            if i % CLOCK_INTERVAL == 0 && self.deadline.is_some_and(|end| Instant::now() >= end) {
+                return Stop::Timeout;
+            }
+            if self.pc as usize >= self.rom.len() {
+                return Stop::EndOfRom;
+            }
+            if i > 0 && self.breakpoints.contains(&self.pc) {
+                return Stop::Breakpoint;
+            }
+            if self.is_halted() {
+                for hit in self.hits.iter_mut().skip(self.pc as usize).take(2) {
+                    *hit += 1;
+                }
+                return Stop::Halted;
+            }
+            while let Some(&(step, key)) = self.keys.last()
+                && step <= self.steps
+            {
+                self.ram[KBD] = key;
+                self.keys.pop();
+            }
+            if let Some(trap) = self.trap() {
+                return Stop::Trap(trap);
+            }
+            self.step();
+        }
+
+        Stop::StepLimit
+    }
+
+    /// At `@pc` followed by an unconditional jump, which loops forever.
+    fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+
+        self.rom[pc] == self.pc
+            && self
+                .rom
+                .get(pc + 1)
+                .is_some_and(|&next| next == 0b1110_1010_1000_0111)
+    }
+
+    /// The trap of the instruction at the PC, if it accesses M out of bounds.
+    fn trap(&self) -> Option<Trap> {
+        let instruction = self.rom[self.pc as usize];
+        let address = self.a as usize;
+        if instruction & 0x8000 == 0 || address < KBD {
+            return None;
+        }
+
+        let (reads, writes) = (instruction & 1 << 12 != 0, instruction & 1 << 3 != 0);
+        match address {
+            KBD if writes => Some(Trap::KeyboardWrite),
+            KBD => None,
+            _ if writes => Some(Trap::Write(self.a)),
+            _ if reads => Some(Trap::Read(self.a)),
+            _ => None,
+        }
+    }
+
+    /// Runs the instruction at the PC, M wrapping to the 15 bits of the address bus
+    /// rather than trapping.
+    pub fn step(&mut self) {
+        let instruction = self.rom[self.pc as usize];
+        self.steps += 1;
+        if let Some(hit) = self.hits.get_mut(self.pc as usize) {
+            *hit += 1;
+        }
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc += 1;
+            return;
+        }
+
+        let bit = |n: u16| instruction & (1 << n) != 0;
+        let address = self.a as usize % RAM_SIZE;
+
+        // ALU: zx nx zy ny f no, on D and on A or M
+        let mut x = self.d;
+        let mut y = if bit(12) { self.ram[address] } else { self.a };
+        if bit(12) {
+            self.record(false, address, y);
+        }
+        if bit(11) {
+            x = 0;
+        }
+        if bit(10) {
+            x = !x;
+        }
+        if bit(9) {
+            y = 0;
+        }
+        if bit(8) {
+            y = !y;
+        }
+        let mut out = if bit(7) { x.wrapping_add(y) } else { x & y };
+        if bit(6) {
+            out = !out;
+        }
+
+        // The jump goes to A as it was before this instruction
+        let target = self.a;
+        if bit(3) {
+            self.ram[address] = out;
+            self.record(true, address, out);
+        }
+        if bit(5) {
+            self.a = out;
+        }
+        if bit(4) {
+            self.d = out;
+        }
+
+        let out = out as i16;
+        let jumps = (bit(2) && out < 0) || (bit(1) && out == 0) || (bit(0) && out > 0);
+        if jumps {
+            if self.branches.len() == RECENT_BRANCHES {
+                self.branches.pop_front();
+            }
+            self.branches.push_back((self.pc, target));
+            self.pc = target;
+        } else {
+            self.pc += 1;
+        }
+    }
+
+    fn record(&mut self, write: bool, address: usize, value: Address) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(Access {
+                step: self.steps,
+                pc: self.pc,
+                write,
+                address: address as Address,
+                value,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod emulator_tests {
+    use super::*;
+    use crate::{
+        assembler::Assembler, parser::Parser, preprocessor::Preprocessor, scanner::Scanner,
+    };
+
+    fn load(source: &str) -> (Cpu, Vec<Address>) {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        (
+            Cpu::new(Assembler::new(nodes).assemble().unwrap()),
+            preprocessor.ram_image(),
+        )
+    }
+
+    #[test]
+    fn runs_until_the_end_loop() {
+        // R2 = max(R0, R1) - 1
+        let (mut cpu, _) = load(
+            "
+            @R0
+            D=M
+            @R1
+            D=D-M
+            @FIRST
+            D;JGT
+            @R1
+            D=M
+            @STORE
+            0;JMP
+            (FIRST)
+            @R0
+            D=M
+            (STORE)
+            @R2
+            M=D-1
+            (END)
+            @END
+            0;JMP
+        ",
+        );
+        cpu.preload(&[(0, 3), (1, 0xFFF9)]);
+
+        assert_eq!(cpu.run(1000), Stop::Halted);
+        assert_eq!(cpu.ram[2], 2);
+        assert_eq!(cpu.steps, 10);
+    }
+
+    #[test]
+    fn reads_the_data_of_its_ram_image() {
+        let (mut cpu, image) = load(
+            "
+            .data
+            (SQUARES)
+            .word 0 1 4 9 16
+            .text
+            @SQUARES
+            D=A
+            @R1
+            A=D+M
+            D=M
+            @R0
+            M=D
+        ",
+        );
+        let image: Vec<_> = (0..).zip(image).collect();
+        cpu.preload(&image);
+        cpu.preload(&[(1, 3)]);
+
+        assert_eq!(cpu.run(1000), Stop::EndOfRom);
+        assert_eq!(cpu.ram[0], 9);
+
+        let (mut cpu, _) = load("(LOOP)\nD=D+1\n@LOOP\n0;JMP");
+        assert_eq!(cpu.run(30), Stop::StepLimit);
+        assert_eq!(cpu.d, 10);
+        assert_eq!(cpu.branches().count(), 10);
+        assert!(cpu.branches().all(|&branch| branch == (2, 0)));
+
+        cpu.set_breakpoints(&[1]);
+        assert_eq!(cpu.run(30), Stop::Breakpoint);
+        assert_eq!((cpu.pc, cpu.steps), (1, 31));
+        assert_eq!(cpu.run(30), Stop::Breakpoint);
+        assert_eq!((cpu.pc, cpu.steps), (1, 34));
+    }
+
+    #[test]
+    fn reads_scripted_keys() {
+        // Copies KBD to the first screen word, forever
+        let (mut cpu, _) = load("(LOOP)\n@KBD\nD=M\n@SCREEN\nM=D\n@LOOP\n0;JMP");
+        cpu.script_keys(&[(100, 0), (10, 132)]);
+
+        cpu.run(50);
+        assert_eq!(cpu.ram[SCREEN], 132);
+        let pressed = cpu.screen_crc();
+        cpu.run(100);
+        assert_eq!(cpu.ram[SCREEN], 0);
+        // zlib.crc32(bytes(16384))
+        assert_eq!(cpu.screen_crc(), 0xAB54_D286);
+        assert_ne!(cpu.screen_crc(), pressed);
+    }
+
+    #[test]
+    fn counts_the_hits_of_every_address() {
+        let (mut cpu, _) = load("@3\nD=A\n(LOOP)\nD=D-1\n@LOOP\nD;JGT\n(END)\n@END\n0;JMP\n@0");
+        cpu.count_hits();
+        assert_eq!(cpu.run(100), Stop::Halted);
+
+        assert_eq!(cpu.hits(), [1, 1, 3, 3, 3, 1, 1, 0]);
+    }
+
+    #[test]
+    fn stops_at_the_deadline() {
+        let (mut cpu, _) = load("(LOOP)\n@LOOP\n0;JMP\n@LOOP");
+        cpu.set_deadline(Instant::now());
+
+        assert_eq!(cpu.run(u64::MAX), Stop::Timeout);
+        assert_eq!(cpu.steps, 0);
+    }
+
+    #[test]
+    fn records_the_accesses_to_memory() {
+        let (mut cpu, _) = load("@R0\nD=M\n@R1\nM=D+1\nD=A");
+        cpu.preload(&[(0, 0xFFFF)]);
+        cpu.record_accesses();
+
+        assert_eq!(cpu.run(100), Stop::EndOfRom);
+        assert_eq!(
+            cpu.accesses(),
+            [
+                Access {
+                    step: 2,
+                    pc: 1,
+                    write: false,
+                    address: 0,
+                    value: 0xFFFF
+                },
+                Access {
+                    step: 4,
+                    pc: 3,
+                    write: true,
+                    address: 1,
+                    value: 0
+                },
+            ]
+        );
+        assert_eq!(cpu.accesses()[0].to_string(), "2: ROM[1] reads RAM[0] = -1");
+    }
+
+    #[test]
+    fn forks_run_code_after_the_rom() {
+        let (mut cpu, _) = load("@R0\nM=M+1\n(END)\n@END\n0;JMP");
+        cpu.set_breakpoints(&[1]);
+        assert_eq!(cpu.run(100), Stop::Breakpoint);
+
+        // R1 = R0 + 41, assembled after the program's ROM, with an end loop of its own
+        let end = cpu.rom_len();
+        let (code, _) = load(&format!(
+            ".org {end}\n@R0\nD=M\n@41\nD=D+A\n@R1\nM=D\n(DONE)\n@DONE\n0;JMP"
+        ));
+        let mut fork = cpu.fork(&code.rom[end..]);
+        assert_eq!(fork.run(100), Stop::Halted);
+        assert_eq!((fork.ram[1], fork.steps), (41, 6));
+
+        assert_eq!((cpu.rom_len(), cpu.ram[1]), (4, 0));
+        assert_eq!(cpu.run(100), Stop::Halted);
+        assert_eq!(cpu.ram[0], 1);
+    }
+
+    #[test]
+    fn traps_on_memory_past_the_keyboard() {
+        let (mut cpu, _) = load("@KBD\nD=M\n@SCREEN\nM=D\n@KBD\nM=1\nD=0");
+        assert_eq!(cpu.run(100), Stop::Trap(Trap::KeyboardWrite));
+        assert_eq!((cpu.pc, cpu.steps), (5, 5));
+
+        // A pointer gone past the end of memory, as a broken SP would be
+        let (mut cpu, _) = load("@SP\nA=M\nD=M");
+        cpu.preload(&[(0, 24577)]);
+        assert_eq!(cpu.run(100), Stop::Trap(Trap::Read(24577)));
+        let (mut cpu, _) = load("@SP\nA=M\nM=D");
+        cpu.preload(&[(0, 0x8000)]);
+        assert_eq!(cpu.run(100), Stop::Trap(Trap::Write(0x8000)));
+        assert_eq!(
+            Trap::Write(0x8000).to_string(),
+            "write of RAM[32768], past KBD at RAM[24576]"
+        );
+
+        // A alone may hold any value
+        let (mut cpu, _) = load("@32767\nD=A\nA=D+1\nD=A");
+        assert_eq!(cpu.run(100), Stop::EndOfRom);
+    }
+}
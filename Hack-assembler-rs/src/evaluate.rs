@@ -0,0 +1,225 @@
+//! `--eval`: Jack expressions evaluated at every breakpoint, with the variables of the
+//! function the program stopped in. The Jack compiler's `snippet` compiles an
+//! expression against the function's locals, arguments and fields, the VM translator
+//! translates it, and it is assembled after the program, with the program's symbols.
+//! The code then runs on a [`Cpu::fork`], so the run it stopped goes on as it was.
+//!
+//! The function is that of the VM translator's `--functions` table around the PC, and
+//! an expression is compiled once for each function it is evaluated in.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::assembler::Assembler;
+use crate::call_stack::Functions;
+use crate::emulator::{Cpu, Stop};
+use crate::grade::Tools;
+use crate::parser::{Address, Parser};
+use crate::preprocessor::{Preprocessor, expand_macros};
+use crate::scanner::Scanner;
+use crate::symbol_file::SymbolFile;
+
+/// Prefix of the labels of the evaluated code, which may not redefine the program's.
+const PREFIX: &str = "$eval.";
+
+/// Instructions an evaluation runs before it is given up, calls included.
+const MAX_STEPS: u64 = 1_000_000;
+
+pub struct Evaluator {
+    expressions: Vec<String>,
+    /// The Jack sources of the program.
+    jack: PathBuf,
+    tools: Tools,
+    /// Labels and variables of the program, shared by the evaluated code.
+    symbols: SymbolFile,
+    work: PathBuf,
+    /// The code of each expression, or why it does not compile, by function.
+    compiled: BTreeMap<(String, String), Result<Vec<Address>, String>>,
+}
+
+impl Evaluator {
+    pub fn new(expressions: &[String], jack: &Path, tools: Tools, symbols: SymbolFile) -> Self {
+        Self {
+            expressions: expressions.to_vec(),
+            jack: jack.to_path_buf(),
+            tools,
+            symbols,
+            work: std::env::temp_dir().join(format!("hack-eval-{}", std::process::id())),
+            compiled: BTreeMap::new(),
+        }
+    }
+
+    /// A line per expression, `expression = value` or why it has none, in the
+    /// function `cpu` stopped in. Its stack starts past the locals, which the function
+    /// has not pushed yet at its first instruction, so these are 0.
+    pub fn evaluate(&mut self, cpu: &Cpu, functions: &Functions) -> Vec<String> {
+        let Some((function, locals)) = functions.containing(cpu.pc) else {
+            return vec![format!(
+                "ROM[{}] is in no function of the --functions table",
+                cpu.pc
+            )];
+        };
+
+        let mut lines = vec![];
+        for expression in self.expressions.clone() {
+            let key = (function.to_string(), expression.clone());
+            if !self.compiled.contains_key(&key) {
+                let code = self
+                    .compile(function, &expression, cpu.rom_len())
+                    .map_err(|e| format!("{e:#}"));
+                self.compiled.insert(key.clone(), code);
+            }
+            let code = match &self.compiled[&key] {
+                Ok(code) => code,
+                Err(error) => {
+                    lines.push(format!("{expression}: {error}"));
+                    continue;
+                }
+            };
+
+            let mut fork = cpu.fork(code);
+            // The locals not pushed yet start as the function would push them, at 0
+            let (sp, end) = (fork.ram[0], fork.ram[1].saturating_add(locals));
+            let size = fork.ram.len();
+            for address in sp..end {
+                fork.ram[address as usize % size] = 0;
+            }
+            fork.ram[0] = sp.max(end);
+            let line = match fork.run(MAX_STEPS) {
+                Stop::Halted => {
+                    let top = fork.ram[0].wrapping_sub(1) as usize % fork.ram.len();
+                    format!("{expression} = {}", fork.ram[top] as i16)
+                }
+                Stop::Trap(trap) => format!("{expression}: {trap}"),
+                Stop::StepLimit => format!("{expression}: does not end in {MAX_STEPS} steps"),
+                Stop::EndOfRom => format!("{expression}: runs past the end of the ROM"),
+                Stop::Breakpoint | Stop::Timeout => {
+                    unreachable!("forks have no breakpoints or deadline")
+                }
+            };
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// The words of `expression` in `function`, to be placed from ROM[`rom_len`] on and
+    /// ending in a loop of their own.
+    fn compile(
+        &self,
+        function: &str,
+        expression: &str,
+        rom_len: usize,
+    ) -> anyhow::Result<Vec<Address>> {
+        let class = function.split('.').next().unwrap_or(function);
+        fs::create_dir_all(&self.work)?;
+        // Named after the class, so that its statics are the program's
+        let vm_path = self.work.join(format!("{class}.vm"));
+        let asm_path = vm_path.with_extension("asm");
+
+        let vm = tool(
+            Command::new(&self.tools.compiler)
+                .arg("snippet")
+                .arg(&self.jack)
+                .arg("--in")
+                .arg(function)
+                .arg(expression),
+        )?;
+        // A function the program lacks would be a variable to the assembler, not a label
+        for line in vm.lines() {
+            if let Some(callee) = line
+                .strip_prefix("call ")
+                .and_then(|call| call.split(' ').next())
+                && !self.symbols.labels.contains_key(callee)
+            {
+                anyhow::bail!("`{callee}` is not a function of the program");
+            }
+        }
+        fs::write(&vm_path, vm)?;
+        tool(
+            Command::new(&self.tools.translator)
+                .arg(&vm_path)
+                .arg("-o")
+                .arg(&asm_path),
+        )?;
+
+        let asm = relabel(&fs::read_to_string(&asm_path)?);
+        let source = format!(".org {rom_len}\n{asm}({PREFIX}END)\n@{PREFIX}END\n0;JMP\n");
+        let tokens = Scanner::new(&source)
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(expand_macros)?;
+        let mut nodes = Parser::new(tokens.into_iter()).collect::<Result<Vec<_>, _>>()?;
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.import(&self.symbols)?;
+        preprocessor.preprocess(&mut nodes)?;
+        let words = Assembler::new(nodes).assemble()?;
+
+        Ok(words[rom_len..].to_vec())
+    }
+}
+
+impl Drop for Evaluator {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.work);
+    }
+}
+
+/// Runs `command`, failing with the error it printed, or returns its output.
+fn tool(command: &mut Command) -> anyhow::Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .env("RUST_LIB_BACKTRACE", "0")
+        .output()
+        .map_err(|e| anyhow::anyhow!("Cannot run `{program}`: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}", stderr.trim().trim_start_matches("Error: "));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `asm` with the labels it defines prefixed, where defined and where loaded.
+fn relabel(asm: &str) -> String {
+    let labels: BTreeSet<_> = asm
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('(')?.strip_suffix(')'))
+        .collect();
+
+    let mut relabeled = String::new();
+    for line in asm.lines() {
+        let line = line.trim();
+        let label = |name: &str| labels.contains(name).then(|| format!("{PREFIX}{name}"));
+        let line = match (line.strip_prefix('('), line.strip_prefix('@')) {
+            (Some(rest), _) => rest
+                .strip_suffix(')')
+                .and_then(label)
+                .map_or(line.to_string(), |label| format!("({label})")),
+            (_, Some(name)) => label(name).map_or(line.to_string(), |label| format!("@{label}")),
+            _ => line.to_string(),
+        };
+        relabeled.push_str(&line);
+        relabeled.push('\n');
+    }
+
+    relabeled
+}
+
+#[cfg(test)]
+mod evaluate_tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_the_labels_it_defines() {
+        let asm = "@Main.label_yes.0\nD;JLT\n(Main.label_yes.0)\n@Math.multiply\n0;JMP\n\
+                   (Main.Math.multiply.return.1)\n@Main.0\nM=D\n";
+
+        assert_eq!(
+            relabel(asm),
+            "@$eval.Main.label_yes.0\nD;JLT\n($eval.Main.label_yes.0)\n@Math.multiply\n0;JMP\n\
+             ($eval.Main.Math.multiply.return.1)\n@Main.0\nM=D\n"
+        );
+    }
+}
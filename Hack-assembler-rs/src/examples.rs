@@ -0,0 +1,86 @@
+//! Typical runs of the assembler, at the end of the Jack toolchain. The `examples`
+//! subcommand, the long `--help` and the README's examples are all written from
+//! [`EXAMPLES`], so they cannot drift apart.
+
+use std::fmt::Write as _;
+
+pub struct Example {
+    pub title: &'static str,
+    /// Shell commands, run in order.
+    pub commands: &'static [&'static str],
+    pub note: &'static str,
+}
+
+/// What `--help` says before the flags.
+pub const LONG_ABOUT: &str = "Hack language assembler
+
+Assembles a Hack .asm file into the .hack machine code of the CPU emulator, or a directory \
+of them concurrently. The program can then be run on a headless Hack CPU, preloading RAM, \
+typing keys and checking the result against the course's comparison files.";
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        title: "Assemble a program",
+        commands: &["hack-assembler-rs Max.asm -o Max.hack"],
+        note: "Writes one 16-bit word per line, as the CPU emulator loads it.",
+    },
+    Example {
+        title: "Assemble with a listing of the addresses",
+        commands: &["hack-assembler-rs Pong.asm -o Pong.hack --resolve-symbols Pong.list.asm"],
+        note: "Also writes the assembly with its labels removed and every symbol replaced by its address.",
+    },
+    Example {
+        title: "Assemble and run a translated program",
+        commands: &[
+            "VMTranslator Sys.vm Main.vm -o Game.asm",
+            "hack-assembler-rs Game.asm -o Game.hack --run --ram boot.ram --keys keys.txt",
+        ],
+        note: "Runs on the headless CPU with the RAM of `boot.ram` (such as SP, LCL and ARG at 256) and the key presses of `keys.txt`, then prints the screen's CRC-32.",
+    },
+    Example {
+        title: "Assemble every program of a directory",
+        commands: &["hack-assembler-rs input/ -o output/"],
+        note: "Assembles each `.asm` file on its own thread into `output/<name>.hack`, with a summary table.",
+    },
+];
+
+/// The examples for the end of `--help`.
+pub fn long_help() -> String {
+    let mut help = "Examples:\n".to_string();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(help, "\n  {}:", example.title);
+        for command in example.commands.iter() {
+            let _ = writeln!(help, "    $ {command}");
+        }
+        let _ = writeln!(help, "  {}", example.note);
+    }
+
+    help
+}
+
+/// The examples as the README has them.
+pub fn markdown() -> String {
+    let mut markdown = String::new();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(markdown, "**{}**", example.title);
+        let _ = writeln!(markdown, "```bash\n{}\n```", example.commands.join("\n"));
+        let _ = writeln!(markdown, "{}\n", example.note);
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod examples_tests {
+    use super::*;
+
+    #[test]
+    fn readme_has_the_examples() {
+        let readme = include_str!("../README.md");
+
+        assert!(
+            readme.contains(&markdown()),
+            "Update the README's examples with `hack-assembler-rs examples --markdown`"
+        );
+    }
+}
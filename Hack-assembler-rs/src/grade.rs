@@ -0,0 +1,601 @@
+//! `grade`: every submission of a directory through the toolchain and the tests of a
+//! rubric, with a report of what passed for each student.
+//!
+//! A submission is a subdirectory, named after its student, of `.jack`, `.vm` or `.asm`
+//! files, or a `<student>.zip` archive with them in one of its directories. They are
+//! copied to a work directory and go through the stages from their own on: the Jack compiler, the VM translator, then this assembler. The first stage
+//! that fails ends the submission, with what the tool printed as its diagnostics.
+//! Every test of the rubric then runs the program on the headless CPU of `--run`.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use n2t_core::files::has_extension;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::assembler::Assembler;
+use crate::emulator::{Cpu, Stop};
+use crate::include::Sources;
+use crate::parser::{Address, Parser};
+use crate::preprocessor::{Preprocessor, expand_macros};
+use crate::ram_file;
+use crate::scanner::Scanner;
+
+/// Name of the program the translator writes into the work directory.
+const PROGRAM: &str = "Program.asm";
+
+/// Extensions of the files a submission is made of.
+const SOURCE_EXTS: [&str; 3] = ["jack", "vm", "asm"];
+
+/// The rubric file, JSON.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RubricFile {
+    #[serde(default)]
+    compiler_args: Vec<String>,
+    #[serde(default)]
+    translator_args: Vec<String>,
+    #[serde(default = "default_max_steps")]
+    max_steps: u64,
+    /// Seconds of wall-clock time a test may run.
+    timeout: Option<f64>,
+    tests: Vec<TestFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestFile {
+    name: String,
+    /// RAM set before running, as a `--ram` JSON file.
+    #[serde(default)]
+    ram: Option<Value>,
+    /// RAM words the program must leave, as a `--ram` JSON file.
+    #[serde(default)]
+    expect: Option<Value>,
+    /// CRC-32 of the screen, as `--run` prints it.
+    screen_crc: Option<String>,
+    #[serde(default = "default_halt")]
+    halt: bool,
+}
+
+fn default_max_steps() -> u64 {
+    1_000_000
+}
+
+fn default_halt() -> bool {
+    true
+}
+
+/// What to run every submission through, and the tests of its program.
+#[derive(Debug)]
+pub struct Rubric {
+    pub compiler_args: Vec<String>,
+    pub translator_args: Vec<String>,
+    pub max_steps: u64,
+    pub timeout: Option<Duration>,
+    pub tests: Vec<Test>,
+}
+
+#[derive(Debug)]
+pub struct Test {
+    pub name: String,
+    pub ram: Vec<(Address, Address)>,
+    pub expect: Vec<(Address, Address)>,
+    pub screen_crc: Option<u32>,
+    /// Whether the program must reach its `(END)` loop. Without, it runs its steps
+    /// and is checked then, for a program that waits in a loop of its own.
+    pub halt: bool,
+}
+
+impl Rubric {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e:#}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let file: RubricFile = serde_json::from_str(contents)?;
+        let timeout = file
+            .timeout
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| anyhow::anyhow!("Timeout `{seconds}` is not a number of seconds"))
+            })
+            .transpose()?;
+        let tests = file
+            .tests
+            .into_iter()
+            .map(|test| {
+                let context = format!("Test `{}`", test.name);
+                Test::parse(test).map_err(|e| e.context(context))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            compiler_args: file.compiler_args,
+            translator_args: file.translator_args,
+            max_steps: file.max_steps,
+            timeout,
+            tests,
+        })
+    }
+}
+
+impl Test {
+    fn parse(test: TestFile) -> anyhow::Result<Self> {
+        let words = |json: Option<Value>| match json {
+            Some(json) => ram_file::from_json(&json),
+            None => Ok(vec![]),
+        };
+        let screen_crc = test
+            .screen_crc
+            .map(|crc| {
+                u32::from_str_radix(&crc, 16)
+                    .map_err(|_| anyhow::anyhow!("Screen CRC `{crc}` is not 8 hexadecimal digits"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            ram: words(test.ram)?,
+            expect: words(test.expect)?,
+            screen_crc,
+            halt: test.halt,
+            name: test.name,
+        })
+    }
+}
+
+/// The other tools of the chain, as commands.
+#[derive(Debug)]
+pub struct Tools {
+    pub compiler: String,
+    pub translator: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub student: String,
+    /// The stage that failed, before any test ran.
+    pub failed_stage: Option<&'static str>,
+    /// What the failed stage printed.
+    pub diagnostics: String,
+    pub tests: Vec<Outcome>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Outcome {
+    pub name: String,
+    pub passed: bool,
+    /// Why it failed, empty when it passed.
+    pub message: String,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.tests.iter().filter(|test| test.passed).count()
+    }
+}
+
+/// Grades every subdirectory and `.zip` file of `submissions`, printing a line for each student and
+/// writing every report as JSON to `report_path`.
+pub fn run(
+    submissions: &Path,
+    rubric: &Rubric,
+    tools: &Tools,
+    report_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let work = std::env::temp_dir().join(format!("hack-grade-{}", std::process::id()));
+    let reports = grade_all(submissions, rubric, tools, &work);
+    let _ = fs::remove_dir_all(&work);
+    let reports = reports?;
+
+    for report in reports.iter() {
+        match report.failed_stage {
+            Some(stage) => {
+                println!("[~] {}: {stage} failed", report.student);
+                for line in report.diagnostics.lines() {
+                    println!("[~]   {line}");
+                }
+            }
+            None => {
+                println!(
+                    "[~] {}: {}/{} tests passed",
+                    report.student,
+                    report.passed(),
+                    rubric.tests.len()
+                );
+                for test in report.tests.iter().filter(|test| !test.passed) {
+                    println!("[~]   {}: {}", test.name, test.message);
+                }
+            }
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        println!("[<-] Report: {}", report_path.display());
+        serde_json::to_writer_pretty(File::create(report_path)?, &reports)?;
+    }
+
+    Ok(())
+}
+
+fn grade_all(
+    submissions: &Path,
+    rubric: &Rubric,
+    tools: &Tools,
+    work: &Path,
+) -> anyhow::Result<Vec<Report>> {
+    let mut students: Vec<_> = fs::read_dir(submissions)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<_>>()?;
+    students.retain(|path| path.is_dir() || is_archive(path));
+    students.sort();
+
+    students
+        .iter()
+        .map(|submission| grade(submission, rubric, tools, &work.join(student(submission))))
+        .collect()
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.is_file() && has_extension(path, &["zip".to_string()])
+}
+
+/// The name of the student of `submission`, without the extension of an archive.
+fn student(submission: &Path) -> String {
+    let name = match is_archive(submission) {
+        true => submission.file_stem(),
+        false => submission.file_name(),
+    };
+
+    name.unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// Builds the program of `submission` in `work`, then runs the tests on it.
+pub fn grade(
+    submission: &Path,
+    rubric: &Rubric,
+    tools: &Tools,
+    work: &Path,
+) -> anyhow::Result<Report> {
+    let mut report = Report {
+        student: student(submission),
+        failed_stage: None,
+        diagnostics: String::new(),
+        tests: vec![],
+    };
+
+    fs::create_dir_all(work)?;
+    let mut submission = submission.to_path_buf();
+    if is_archive(&submission) {
+        match extract(&submission, &work.with_extension("zip")) {
+            Ok(sources) => submission = sources,
+            Err(error) => {
+                report.failed_stage = Some("extract");
+                report.diagnostics = format!("{error:#}");
+                return Ok(report);
+            }
+        }
+    }
+    for entry in fs::read_dir(&submission)? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::copy(&path, work.join(path.file_name().unwrap_or_default()))?;
+        }
+    }
+
+    let (words, image) = match build(work, rubric, tools)? {
+        Ok(program) => program,
+        Err((stage, diagnostics)) => {
+            report.failed_stage = Some(stage);
+            report.diagnostics = diagnostics;
+            return Ok(report);
+        }
+    };
+    report.tests = rubric
+        .tests
+        .iter()
+        .map(|test| run_test(&words, &image, test, rubric))
+        .collect();
+
+    Ok(report)
+}
+
+/// Extracts the archive at `path` to `dir`, returning the one directory in it with
+/// sources; the `__MACOSX` metadata archivers add is skipped.
+fn extract(path: &Path, dir: &Path) -> anyhow::Result<PathBuf> {
+    // Entries escaping the directory, such as `../x`, are rejected
+    ZipArchive::new(File::open(path)?)
+        .and_then(|mut archive| archive.extract(dir))
+        .map_err(|e| anyhow::anyhow!("Failed to extract {}: {e}", path.display()))?;
+
+    let exts = SOURCE_EXTS.map(String::from);
+    let mut pending = vec![dir.to_path_buf()];
+    let mut found = vec![];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() && !path.ends_with("__MACOSX") {
+                pending.push(path);
+            } else if path.is_file() && has_extension(&path, &exts) && !found.contains(&dir) {
+                found.push(dir.clone());
+            }
+        }
+    }
+
+    match &found[..] {
+        [sources] => Ok(sources.clone()),
+        [] => anyhow::bail!("{} holds no .jack, .vm or .asm files", path.display()),
+        _ => anyhow::bail!(
+            "{} holds sources in {} directories",
+            path.display(),
+            found.len()
+        ),
+    }
+}
+
+/// The words and RAM image of the program in `work`, or the stage that failed with
+/// its diagnostics.
+#[allow(clippy::type_complexity)]
+fn build(
+    work: &Path,
+    rubric: &Rubric,
+    tools: &Tools,
+) -> anyhow::Result<Result<(Vec<Address>, Vec<Address>), (&'static str, String)>> {
+    if !files(work, "jack")?.is_empty() {
+        let mut compile = Command::new(&tools.compiler);
+        compile.arg(work).args(&rubric.compiler_args);
+        if let Err(diagnostics) = tool(&mut compile) {
+            return Ok(Err(("compile", diagnostics)));
+        }
+    }
+
+    let mut asm = files(work, "asm")?;
+    if !files(work, "vm")?.is_empty() {
+        let program = work.join(PROGRAM);
+        let mut translate = Command::new(&tools.translator);
+        translate
+            .arg(work)
+            .arg("-o")
+            .arg(&program)
+            .args(&rubric.translator_args);
+        if let Err(diagnostics) = tool(&mut translate) {
+            return Ok(Err(("translate", diagnostics)));
+        }
+        asm = vec![program];
+    }
+    if asm.is_empty() {
+        return Ok(Err(("assemble", "No .jack, .vm or .asm files".to_string())));
+    }
+
+    Ok(assemble(&asm).map_err(|e| ("assemble", e.to_string())))
+}
+
+/// Runs `command`, failing with what it printed, without the backtrace of its error.
+fn tool(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .env("RUST_LIB_BACKTRACE", "0")
+        .output()
+        .map_err(|e| format!("Cannot run `{program}`: {e}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+/// The files of `dir` with extension `ext`, sorted.
+fn files(dir: &Path, ext: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<_>>()?;
+    files.retain(|path| path.extension().is_some_and(|e| e == ext));
+    files.sort();
+
+    Ok(files)
+}
+
+/// The words and RAM image of the `.asm` files at `paths`, joined into one program.
+fn assemble(paths: &[PathBuf]) -> anyhow::Result<(Vec<Address>, Vec<Address>)> {
+    let sources = Sources::read(paths)?;
+    let program = Scanner::new(sources.text())
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(expand_macros)
+        .and_then(|tokens| Parser::new(tokens.into_iter()).collect::<Result<Vec<_>, _>>())
+        .and_then(|mut nodes| {
+            let mut preprocessor = Preprocessor::new();
+            preprocessor.preprocess(&mut nodes)?;
+            let image = preprocessor.ram_image();
+
+            Ok((Assembler::new(nodes).assemble()?, image))
+        });
+
+    program.map_err(|error| sources.diagnose(error))
+}
+
+fn run_test(words: &[Address], image: &[Address], test: &Test, rubric: &Rubric) -> Outcome {
+    let mut cpu = Cpu::new(words.to_vec());
+    let preload: Vec<_> = (0..).zip(image.iter().copied()).collect();
+    cpu.preload(&preload);
+    cpu.preload(&test.ram);
+    // A deadline too far ahead to represent is never reached
+    if let Some(deadline) = rubric
+        .timeout
+        .and_then(|timeout| Instant::now().checked_add(timeout))
+    {
+        cpu.set_deadline(deadline);
+    }
+
+    let stop = cpu.run(rubric.max_steps);
+    let mut failures = vec![];
+    match stop {
+        Stop::Halted => {}
+        Stop::StepLimit if !test.halt => {}
+        Stop::StepLimit => failures.push(format!("does not halt in {} steps", cpu.steps)),
+        Stop::Timeout => failures.push(format!(
+            "does not halt in {:?}, after {} steps",
+            rubric.timeout.unwrap_or_default(),
+            cpu.steps
+        )),
+        Stop::EndOfRom => failures.push(format!(
+            "runs past the end of the ROM after {} steps",
+            cpu.steps
+        )),
+        Stop::Trap(trap) => failures.push(format!(
+            "traps at ROM[{}] after {} steps: {trap}",
+            cpu.pc, cpu.steps
+        )),
+        Stop::Breakpoint => unreachable!("tests set no breakpoints"),
+    }
+    if failures.is_empty() {
+        for &(address, expected) in test.expect.iter() {
+            let actual = cpu.ram[address as usize];
+            if actual != expected {
+                failures.push(format!(
+                    "RAM[{address}] is {}, expected {}",
+                    actual as i16, expected as i16
+                ));
+            }
+        }
+        if let Some(crc) = test.screen_crc
+            && cpu.screen_crc() != crc
+        {
+            failures.push(format!(
+                "screen CRC-32 is {:08x}, expected {crc:08x}",
+                cpu.screen_crc()
+            ));
+        }
+    }
+
+    Outcome {
+        name: test.name.clone(),
+        passed: failures.is_empty(),
+        message: failures.join("; "),
+    }
+}
+
+#[cfg(test)]
+mod grade_tests {
+    use std::io::Write;
+
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    const RUBRIC: &str = r#"{
+        "max_steps": 1000,
+        "tests": [
+            { "name": "adds", "ram": { "0": 3, "1": 4 }, "expect": { "2": 7 } },
+            { "name": "adds negatives", "ram": { "0": -3, "1": 1 }, "expect": { "2": -2 } }
+        ]
+    }"#;
+
+    const ADD: &str = "@R0\nD=M\n@R1\nD=D+M\n@R2\nM=D\n(END)\n@END\n0;JMP\n";
+
+    fn submissions(name: &str, students: &[(&str, &str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-grade-{name}-{}", std::process::id()));
+        for (student, file, text) in students {
+            fs::create_dir_all(dir.join(student)).unwrap();
+            fs::write(dir.join(student).join(file), text).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn reads_a_rubric() {
+        let rubric = Rubric::parse(RUBRIC).unwrap();
+
+        assert_eq!(rubric.max_steps, 1000);
+        assert_eq!(rubric.timeout, None);
+        assert_eq!(rubric.tests[1].ram, [(0, 0xFFFD), (1, 1)]);
+        assert_eq!(rubric.tests[1].expect, [(2, 0xFFFE)]);
+        assert!(rubric.tests[1].halt);
+
+        let error = Rubric::parse(r#"{ "tests": [{ "name": "x", "screen_crc": "xyz" }] }"#);
+        assert!(format!("{:#}", error.unwrap_err()).contains("Test `x`: Screen CRC `xyz`"));
+    }
+
+    #[test]
+    fn a_timeout_too_far_ahead_is_no_deadline() {
+        let rubric = RUBRIC.replace(r#""max_steps""#, r#""timeout": 1e19, "max_steps""#);
+        let rubric = Rubric::parse(&rubric).unwrap();
+        let dir = submissions("timeout", &[("alice", "Add.asm", ADD)]);
+
+        let (words, image) = assemble(&[dir.join("alice/Add.asm")]).unwrap();
+        assert!(run_test(&words, &image, &rubric.tests[0], &rubric).passed);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn grades_every_student() {
+        let dir = submissions(
+            "students",
+            &[
+                ("alice", "Add.asm", ADD),
+                ("bob", "Add.asm", &ADD.replace("D=D+M", "D=D-M")),
+                ("carol", "Add.asm", "@R0\nD=M#\n"),
+                ("dave", "Main.jack", "class Main {}"),
+                ("erin", "Add.asm", "(LOOP)\n@LOOP\nD;JEQ\n"),
+            ],
+        );
+        let mut writer = ZipWriter::new(File::create(dir.join("frank.zip")).unwrap());
+        for (name, text) in [
+            ("frank/Add/Add.asm", ADD),
+            ("__MACOSX/frank/Add/._Add.asm", ""),
+        ] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        fs::write(dir.join("gina.zip"), "not an archive").unwrap();
+        let tools = Tools {
+            compiler: "no-such-jack-compiler".to_string(),
+            translator: "no-such-vm-translator".to_string(),
+        };
+        let rubric = Rubric::parse(RUBRIC).unwrap();
+
+        let reports = grade_all(&dir, &rubric, &tools, &dir.join("work")).unwrap();
+        let students: Vec<_> = reports.iter().map(|r| r.student.as_str()).collect();
+        assert_eq!(
+            students,
+            ["alice", "bob", "carol", "dave", "erin", "frank", "gina"]
+        );
+
+        assert_eq!(reports[0].passed(), 2);
+        assert_eq!(
+            reports[1].tests[0],
+            Outcome {
+                name: "adds".into(),
+                passed: false,
+                message: "RAM[2] is -1, expected 7".into()
+            }
+        );
+        assert_eq!(reports[2].failed_stage, Some("assemble"));
+        assert!(reports[2].diagnostics.contains("Unexpected character `#`"));
+        assert_eq!(reports[3].failed_stage, Some("compile"));
+        assert!(
+            reports[3]
+                .diagnostics
+                .starts_with("Cannot run `no-such-jack-compiler`")
+        );
+        assert_eq!(reports[4].tests[0].message, "does not halt in 1000 steps");
+        assert_eq!(reports[5].passed(), 2);
+        assert_eq!(reports[6].failed_stage, Some("extract"));
+        assert!(reports[6].diagnostics.starts_with("Failed to extract"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! TextMate and Tree-sitter grammar stubs built from the scanner's keyword table,
+//! so editor highlighting follows the registers and jumps the assembler knows.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::parser::DIRECTIVES;
+use crate::preprocessor::MACRO_DIRECTIVES;
+use crate::scanner::KEYWORDS;
+
+/// Single-character tokens of the scanner.
+const SYMBOLS: &str = "()-+=!&|@;";
+
+/// Writes `hack-asm.tmLanguage.json` and `tree-sitter-hack-asm/` into `dir`.
+pub fn emit(dir: &Path) -> anyhow::Result<()> {
+    let tree_sitter_dir = dir.join("tree-sitter-hack-asm");
+    fs::create_dir_all(tree_sitter_dir.join("queries"))?;
+
+    for (path, contents) in [
+        (
+            dir.join("hack-asm.tmLanguage.json"),
+            serde_json::to_string_pretty(&text_mate())? + "\n",
+        ),
+        (tree_sitter_dir.join("grammar.js"), tree_sitter()),
+        (
+            tree_sitter_dir.join("queries").join("highlights.scm"),
+            HIGHLIGHTS.to_string(),
+        ),
+    ] {
+        println!("[<-] Grammar: {}", path.display());
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+/// Keywords of the given [`kind`](crate::scanner::TokenType::kind), longest first
+/// so that `AMD` is not highlighted as `A` followed by `MD`.
+fn keywords(kind: &str) -> Vec<&'static str> {
+    let mut keywords: Vec<_> = KEYWORDS
+        .iter()
+        .filter(|(_, token_type)| token_type.kind() == kind)
+        .map(|(keyword, _)| *keyword)
+        .collect();
+    keywords.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    keywords
+}
+
+fn text_mate() -> Value {
+    let words = |kind| format!("(?<![\\w.$:])(?:{})(?![\\w.$:])", keywords(kind).join("|"));
+    let symbols: String = SYMBOLS.chars().map(|c| format!("\\{c}")).collect();
+    let directives: Vec<_> = DIRECTIVES
+        .iter()
+        .chain(MACRO_DIRECTIVES.iter())
+        .map(|d| format!("\\{d}"))
+        .collect();
+
+    json!({
+        "name": "Hack Assembly",
+        "scopeName": "source.hack-asm",
+        "fileTypes": ["asm"],
+        "patterns": [
+            { "name": "comment.line.double-slash.hack-asm", "match": "//.*$" },
+            { "name": "entity.name.label.hack-asm", "match": "(?<=\\()[^)\\s]+(?=\\))" },
+            {
+                "name": "keyword.other.directive.hack-asm",
+                "match": format!("(?<![\\w.$:])(?:{})(?![\\w.$:])", directives.join("|"))
+            },
+            { "name": "constant.numeric.integer.hack-asm", "match": "\\b[0-9]+\\b" },
+            { "name": "variable.language.hack-asm", "match": words("register") },
+            { "name": "keyword.control.hack-asm", "match": words("jump") },
+            { "name": "keyword.operator.hack-asm", "match": format!("[{symbols}]") },
+        ]
+    })
+}
+
+fn tree_sitter() -> String {
+    let quote = |s: &str| serde_json::to_string(s).expect("Strings serialize");
+    let words = |kind| {
+        keywords(kind)
+            .into_iter()
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let symbols: Vec<_> = SYMBOLS.chars().map(|c| quote(&c.to_string())).collect();
+
+    format!(
+        r#"// Generated by `Hack-assembler-rs --emit-grammar`: tokens only, a stub to grow the grammar from.
+module.exports = grammar({{
+  name: 'hack_asm',
+  extras: $ => [/\s/, $.comment],
+  word: $ => $.identifier,
+  rules: {{
+    source_file: $ => repeat($._token),
+    _token: $ => choice($.register, $.jump, $.symbol, $.number, $.identifier),
+    register: $ => choice({}),
+    jump: $ => choice({}),
+    symbol: $ => choice({}),
+    number: $ => /[0-9]+/,
+    identifier: $ => /[A-Za-z_.$:][A-Za-z0-9_.$:]*/,
+    comment: $ => token(seq('//', /.*/)),
+  }}
+}});
+"#,
+        words("register"),
+        words("jump"),
+        symbols.join(", ")
+    )
+}
+
+const HIGHLIGHTS: &str = "(register) @variable.builtin
+(jump) @keyword
+(symbol) @operator
+(number) @number
+(comment) @comment
+";
+
+#[cfg(test)]
+mod grammar_tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn highlights_every_keyword_and_symbol_as_the_scanner_reads_them() {
+        let (registers, jumps) = (keywords("register"), keywords("jump"));
+        assert_eq!(registers.len() + jumps.len(), KEYWORDS.len());
+
+        let source = registers.join(" ") + " " + &jumps.join(" ") + " " + SYMBOLS;
+        let tokens: Result<Vec<_>, _> = Scanner::new(&source).collect();
+        let kinds: Vec<_> = tokens
+            .unwrap()
+            .iter()
+            .map(|token| token.token_type.kind())
+            .collect();
+
+        let expected: Vec<_> = [
+            ("register", registers.len()),
+            ("jump", jumps.len()),
+            ("symbol", SYMBOLS.len()),
+            ("eof", 1),
+        ]
+        .into_iter()
+        .flat_map(|(kind, n)| std::iter::repeat_n(kind, n))
+        .collect();
+        assert_eq!(kinds, expected);
+
+        assert_eq!(registers[0], "AMD");
+        assert!(tree_sitter().contains(r#"jump: $ => choice("JEQ", "JGE""#));
+    }
+}
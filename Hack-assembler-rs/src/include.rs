@@ -0,0 +1,383 @@
+//! Programs of several `.asm` files: the inputs given together and the files they
+//! pull in with `.include "file.asm"`, alone on its line. The files are joined into
+//! one source, each `.include` line replaced by the file it names, so the labels and
+//! variables of every file share one symbol table and the ROM continues from file to
+//! file.
+//!
+//! A path is relative to the file including it. Every file goes into the program once,
+//! where it is first given or included: a later `.include` of it is skipped, which
+//! also ends include cycles.
+//!
+//! Errors keep the line numbers of the files: [`Sources::diagnose`] turns a line of the
+//! joined source back into a line of its own file.
+
+use std::fs::{canonicalize, read_to_string};
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::SyntaxError;
+use crate::preprocessor::LabelConflicts;
+
+const DIRECTIVE: &str = ".include";
+
+#[derive(Debug)]
+pub struct File {
+    pub path: PathBuf,
+    /// Whether an `.include` brought it in, rather than the inputs.
+    pub included: bool,
+    pub text: String,
+}
+
+/// The joined source of a program and the file of each of its lines.
+#[derive(Debug, Default)]
+pub struct Sources {
+    text: String,
+    lines: usize,
+    files: Vec<File>,
+    /// Canonical paths of `files`, for the files read already.
+    canonical: Vec<PathBuf>,
+    /// `(first line in the joined source, file, first line in the file)`, in order.
+    segments: Vec<(usize, usize, usize)>,
+}
+
+impl Sources {
+    /// Reads the `inputs`, in order, and the files they include.
+    pub fn read(inputs: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut sources = Self::default();
+        for input in inputs {
+            let text = read_to_string(input)
+                .map_err(|e| anyhow::anyhow!("Cannot read {}: {e}", input.display()))?;
+            sources.add(input, false, text)?;
+        }
+
+        Ok(sources)
+    }
+
+    /// The source of the whole program.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Every file of the program, in the order they were read.
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
+    /// The first input, which names the program.
+    pub fn main(&self) -> &Path {
+        &self.files[0].path
+    }
+
+    /// Whether the program is a single file, whose lines are those of the joined source.
+    pub fn is_single(&self) -> bool {
+        self.files.len() == 1
+    }
+
+    /// The file and line of `line` in the joined source.
+    pub fn locate(&self, line: usize) -> (&Path, usize) {
+        let (file, line) = self.file_line(line);
+
+        (&self.files[file].path, line)
+    }
+
+    fn file_line(&self, line: usize) -> (usize, usize) {
+        let i = self
+            .segments
+            .partition_point(|&(start, ..)| start <= line)
+            .max(1);
+        let (start, file, first) = self.segments[i - 1];
+
+        (file, first + line - start)
+    }
+
+    /// `error` of the joined source with the lines of the files: a [`SyntaxError`]
+    /// shows its file and line, and `line N` in other messages becomes `file:N`.
+    pub fn diagnose(&self, error: anyhow::Error) -> anyhow::Error {
+        if self.is_single() {
+            return crate::diagnostic::with_source(error, &self.text);
+        }
+
+        if let Some(conflicts) = error.downcast_ref::<LabelConflicts>() {
+            let conflicts: Vec<_> = conflicts
+                .0
+                .iter()
+                .map(|conflict| self.render(&conflict.error()))
+                .collect();
+            return anyhow::anyhow!(conflicts.join("\n"));
+        }
+        match error.downcast_ref::<SyntaxError>() {
+            Some(syntax_error) => anyhow::anyhow!(self.render(syntax_error)),
+            None => anyhow::anyhow!(self.relabel(&error.to_string())),
+        }
+    }
+
+    /// `error` of the joined source rendered with the line of its file, the lines its
+    /// message names as `file:N`.
+    fn render(&self, error: &SyntaxError) -> String {
+        let (file, line) = self.file_line(error.line);
+        let file = &self.files[file];
+        let local = SyntaxError {
+            line,
+            message: self.relabel(&error.message),
+            ..*error
+        };
+
+        in_file(&file.path, local.render(&file.text))
+    }
+
+    /// `message` with every `line N` of the joined source as `file:N`.
+    pub fn relabel(&self, message: &str) -> String {
+        if self.is_single() {
+            return message.to_string();
+        }
+
+        let mut relabeled = String::new();
+        let mut rest = message;
+        while let Some(at) = rest.find("line ") {
+            let digits = rest[at + 5..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - at - 5);
+            relabeled.push_str(&rest[..at]);
+            match rest[at + 5..at + 5 + digits].parse() {
+                Ok(line) => {
+                    let (path, line) = self.locate(line);
+                    relabeled.push_str(&format!("{}:{line}", path.display()));
+                }
+                Err(_) => relabeled.push_str("line "),
+            }
+            rest = &rest[at + 5 + digits..];
+        }
+        relabeled.push_str(rest);
+
+        relabeled
+    }
+
+    /// Appends the lines of `text`, read from `path`, with the files it includes in
+    /// place of their `.include` lines.
+    fn add(&mut self, path: &Path, included: bool, text: String) -> anyhow::Result<()> {
+        let canonical = canonicalize(path)?;
+        if self.canonical.contains(&canonical) {
+            return Ok(());
+        }
+        self.canonical.push(canonical);
+        self.end_line();
+        let file = self.files.len();
+        self.files.push(File {
+            path: path.to_path_buf(),
+            included,
+            text: String::new(),
+        });
+        self.segments.push((self.lines + 1, file, 1));
+
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let Some(name) = include_path(line) else {
+                self.text.push_str(line);
+                self.lines += 1;
+                continue;
+            };
+
+            let error = |message: String| {
+                let column = line.find(DIRECTIVE).unwrap_or(0) + 1;
+                let error = SyntaxError::new(i + 1, column, DIRECTIVE.len(), message);
+                anyhow::anyhow!(in_file(path, error.render(&text)))
+            };
+            let name = name.map_err(error)?;
+            let included_path = path.parent().unwrap_or(Path::new("")).join(name);
+            let included_text = read_to_string(&included_path)
+                .map_err(|e| error(format!("Cannot read {}: {e}", included_path.display())))?;
+            self.add(&included_path, true, included_text)?;
+            self.end_line();
+            self.segments.push((self.lines + 1, file, i + 2));
+        }
+        self.files[file].text = text;
+
+        Ok(())
+    }
+
+    /// Ends the last line of the joined source, when its file has no final newline,
+    /// before the lines of another one.
+    fn end_line(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/// A rendered error of the file at `path`, `[path:line:column]` instead of `[line ..]`.
+fn in_file(path: &Path, rendered: String) -> String {
+    rendered.replacen("[line ", &format!("[{}:", path.display()), 1)
+}
+
+/// The file name of an `.include` line, `None` for other lines.
+fn include_path(line: &str) -> Option<Result<&str, String>> {
+    let rest = line.trim_start().strip_prefix(DIRECTIVE)?;
+    if rest.starts_with(|c: char| !c.is_whitespace() && c != '"') {
+        return None;
+    }
+
+    let name = rest
+        .trim_start()
+        .strip_prefix('"')
+        .and_then(|rest| rest.split_once('"'))
+        .filter(|(_, after)| {
+            let after = after.trim();
+            after.is_empty() || after.starts_with("//")
+        })
+        .map(|(name, _)| name);
+
+    Some(name.ok_or_else(|| format!("Expected a quoted file name after `{DIRECTIVE}`")))
+}
+
+/// `text` with its `.include` lines blanked, for scanning a file on its own: lines,
+/// columns and byte offsets stay those of the file.
+pub fn without_includes(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| match include_path(line) {
+            Some(_) => {
+                let content = line.trim_end_matches(['\n', '\r']);
+                format!("{}{}", " ".repeat(content.len()), &line[content.len()..])
+            }
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod include_tests {
+    use super::*;
+    use crate::{parser::Parser, preprocessor::Preprocessor, scanner::Scanner};
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-include-{}-{name}", std::process::id()));
+        create_dir_all(dir.join("lib")).unwrap();
+        for (path, text) in files {
+            write(dir.join(path), text).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn joins_the_inputs_and_their_includes() {
+        let dir = project(
+            "join",
+            &[
+                (
+                    "Main.asm",
+                    "@Math.double\n0;JMP\n.include \"lib/Math.asm\"\n(END)",
+                ),
+                (
+                    "lib/Math.asm",
+                    ".include \"Util.asm\" // helpers\n(Math.double)\nD=D+A",
+                ),
+                (
+                    "lib/Util.asm",
+                    "(Util.halt)\n@END\n0;JMP\n.include \"../lib/Util.asm\"\n",
+                ),
+                ("Extra.asm", ".include \"lib/Math.asm\"\n@Util.halt\n"),
+            ],
+        );
+        let sources = Sources::read(&[dir.join("Main.asm"), dir.join("Extra.asm")]).unwrap();
+
+        assert_eq!(
+            sources.text(),
+            "@Math.double\n0;JMP\n(Util.halt)\n@END\n0;JMP\n(Math.double)\nD=D+A\n(END)\n@Util.halt\n"
+        );
+        let files: Vec<_> = sources
+            .files()
+            .iter()
+            .map(|file| (file.path.strip_prefix(&dir).unwrap(), file.included))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (Path::new("Main.asm"), false),
+                (Path::new("lib/Math.asm"), true),
+                (Path::new("lib/Util.asm"), true),
+                (Path::new("Extra.asm"), false),
+            ]
+        );
+        let lines: Vec<_> = (1..=9)
+            .map(|line| {
+                let (path, line) = sources.locate(line);
+                (path.file_name().unwrap().to_str().unwrap(), line)
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("Main.asm", 1),
+                ("Main.asm", 2),
+                ("Util.asm", 1),
+                ("Util.asm", 2),
+                ("Util.asm", 3),
+                ("Math.asm", 2),
+                ("Math.asm", 3),
+                ("Main.asm", 4),
+                ("Extra.asm", 2),
+            ]
+        );
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_errors_at_the_lines_of_their_files() {
+        let dir = project(
+            "errors",
+            &[
+                ("Main.asm", "(LOOP)\n.include \"lib/Loop.asm\"\n@LOOP"),
+                ("lib/Loop.asm", "// again\n(LOOP)\nD=M#"),
+                ("Bad.asm", "@1\n.include lib.asm"),
+                ("Missing.asm", "\n\n  .include \"none.asm\""),
+            ],
+        );
+        let main = dir.join("Main.asm").display().to_string();
+        let lib = dir.join("lib/Loop.asm").display().to_string();
+
+        let sources = Sources::read(&[dir.join("Main.asm")]).unwrap();
+        let tokens: Result<Vec<_>, _> = Scanner::new(sources.text()).collect();
+        assert_eq!(
+            sources.diagnose(tokens.unwrap_err()).to_string(),
+            format!("[{lib}:3:4] Error: Unexpected character `#`\n 3 | D=M#\n   |    ^")
+        );
+
+        let tokens: Result<Vec<_>, _> = Scanner::new("(LOOP)\n@LOOP\n(LOOP)").collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let error = Preprocessor::new()
+            .preprocess(&mut nodes.unwrap())
+            .unwrap_err();
+        assert_eq!(
+            sources.diagnose(error).to_string(),
+            format!(
+                "[{lib}:2:2] Error: Label (LOOP) is already defined at {main}:1\n 2 | (LOOP)\n   |  ^^^^"
+            )
+        );
+
+        let error = Sources::read(&[dir.join("Bad.asm")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "[{}:2:1] Error: Expected a quoted file name after `.include`\n \
+                 2 | .include lib.asm\n   | ^^^^^^^^",
+                dir.join("Bad.asm").display()
+            )
+        );
+        let error = Sources::read(&[dir.join("Missing.asm")]).unwrap_err();
+        assert!(error.to_string().starts_with(&format!(
+            "[{}:3:3] Error: Cannot read {}: ",
+            dir.join("Missing.asm").display(),
+            dir.join("none.asm").display()
+        )));
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blanks_includes_for_scanning_a_file_alone() {
+        assert_eq!(
+            without_includes("@1\r\n  .include \"a.asm\"\r\n.includes\n"),
+            format!("@1\r\n{}\r\n.includes\n", " ".repeat(18))
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Keyboard scripts of `--keys`, typing on the headless CPU at given steps.
+//!
+//! One press per line: `step: key`, setting KBD to the key once `step` instructions
+//! have run; it stays pressed until the next line. A key is a code (so the digit
+//! keys are `48` to `57`), a character (letters give their upper case code, as on the
+//! Hack keyboard), a name such as `left`, `esc` or `f1`, or `none` to release it.
+//! `//` starts a comment.
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::parser::Address;
+
+/// Names of the keys of the Hack keyboard that are not characters.
+const KEY_NAMES: [(&str, Address); 15] = [
+    ("none", 0),
+    ("space", 32),
+    ("newline", 128),
+    ("backspace", 129),
+    ("left", 130),
+    ("up", 131),
+    ("right", 132),
+    ("down", 133),
+    ("home", 134),
+    ("end", 135),
+    ("pageup", 136),
+    ("pagedown", 137),
+    ("insert", 138),
+    ("delete", 139),
+    ("esc", 140),
+];
+
+/// `(step, key)` presses of the file at `path`.
+pub fn read(path: &Path) -> anyhow::Result<Vec<(u64, Address)>> {
+    parse(&read_to_string(path)?).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+}
+
+pub fn parse(contents: &str) -> anyhow::Result<Vec<(u64, Address)>> {
+    let mut keys = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let key = match line.split_once(':') {
+            Some((step, key)) => step
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Expected a step but got `{}`", step.trim()))
+                .and_then(|step| Ok((step, parse_key(key.trim())?))),
+            None => Err(anyhow::anyhow!("Expected `step: key` but got `{line}`")),
+        };
+        keys.push(key.map_err(|e| anyhow::anyhow!("line {}: {e}", i + 1))?);
+    }
+
+    Ok(keys)
+}
+
+fn parse_key(key: &str) -> anyhow::Result<Address> {
+    if let Ok(code) = key.parse::<Address>() {
+        return match code {
+            0 | 32..=126 | 128..=152 => Ok(code),
+            _ => anyhow::bail!("No key has the code {code}"),
+        };
+    }
+    let lowercase = key.to_ascii_lowercase();
+    if let Some(&(_, code)) = KEY_NAMES.iter().find(|(name, _)| *name == lowercase) {
+        return Ok(code);
+    }
+    if let Some(function) = lowercase.strip_prefix('f')
+        && let Ok(n @ 1..=12) = function.parse::<Address>()
+    {
+        return Ok(140 + n);
+    }
+
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if (' '..='~').contains(&c) => Ok(c.to_ascii_uppercase() as Address),
+        _ => anyhow::bail!("Expected a key but got `{key}`"),
+    }
+}
+
+#[cfg(test)]
+mod key_script_tests {
+    use super::*;
+
+    #[test]
+    fn reads_codes_characters_and_names() {
+        assert_eq!(
+            parse("// Move, then quit\n0: right\n5000: none\n9000: q\n9100: 130").unwrap(),
+            [(0, 132), (5000, 0), (9000, 81), (9100, 130)]
+        );
+        assert_eq!(
+            parse("1: f12\n2: ESC\n3: ;").unwrap(),
+            [(1, 152), (2, 140), (3, 59)]
+        );
+
+        assert_eq!(
+            parse("1: right\n2 right").unwrap_err().to_string(),
+            "line 2: Expected `step: key` but got `2 right`"
+        );
+        assert_eq!(
+            parse("1: sideways").unwrap_err().to_string(),
+            "line 1: Expected a key but got `sideways`"
+        );
+        assert_eq!(
+            parse("1: 7").unwrap_err().to_string(),
+            "line 1: No key has the code 7"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! The Hack assembler as a library, for programs that assemble without running the
+//! `hack-assembler-rs` binary, such as an emulator loading `.asm` files.
+//!
+//! [`assemble`] runs the whole pipeline. Its stages are public too: [`Scanner`] turns
+//! the source into tokens, [`expand_macros`] expands `.define` and `.macro`,
+//! [`Parser`] reads the tokens into nodes, [`Preprocessor`] resolves the symbols in
+//! place and [`Assembler`] encodes the instructions. [`Cpu`] runs the words.
+//! [`Sources`] joins the files of a program with `.include` into one source first.
+//!
+//! [`Scanner`]: scanner::Scanner
+//! [`expand_macros`]: preprocessor::expand_macros
+//! [`Parser`]: parser::Parser
+//! [`Preprocessor`]: preprocessor::Preprocessor
+//! [`Assembler`]: assembler::Assembler
+//! [`Cpu`]: emulator::Cpu
+//! [`Sources`]: include::Sources
+
+pub mod assembler;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod emulator;
+pub mod include;
+pub mod parser;
+pub mod peephole;
+pub mod preprocessor;
+pub mod scanner;
+pub mod source_map;
+pub mod stream;
+pub mod symbol_file;
+
+use crate::assembler::Assembler;
+use crate::parser::Parser;
+use crate::preprocessor::{Preprocessor, expand_macros};
+use crate::scanner::Scanner;
+
+/// The words of the `.hack` file of the assembly `source`.
+///
+/// A program with `.data` sections also needs their RAM image, which only the stages
+/// give: [`Preprocessor::ram_image`]. Scan and parse errors show the source line, as
+/// the binary reports them.
+///
+/// ```
+/// let words = hack_assembler_rs::assemble("@2\nD=A\n@i\nM=D")?;
+/// assert_eq!(words, [2, 0b1110_1100_0001_0000, 16, 0b1110_0011_0000_1000]);
+///
+/// let error = hack_assembler_rs::assemble("D=M#1").unwrap_err();
+/// assert!(error.to_string().starts_with("[line 1:4] Error: Unexpected character `#`"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn assemble(source: &str) -> anyhow::Result<Vec<u16>> {
+    let result = Scanner::new(source)
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(expand_macros)
+        .and_then(|tokens| Parser::new(tokens.into_iter()).collect::<Result<Vec<_>, _>>());
+    let mut nodes = result.map_err(|error| diagnostic::with_source(error, source))?;
+    Preprocessor::new()
+        .preprocess(&mut nodes)
+        .map_err(|error| diagnostic::with_source(error, source))?;
+
+    Assembler::new(nodes)
+        .assemble()
+        .map_err(|error| diagnostic::with_source(error, source))
+}
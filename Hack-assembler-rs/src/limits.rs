@@ -0,0 +1,144 @@
+//! Hack platform limits an assembled program has to stay within.
+
+use std::fmt::Display;
+
+/// Upper bounds checked after assembling. [`Limits::default`] is the Hack platform.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// ROM words.
+    pub rom: usize,
+    /// Data words and variables, allocated from RAM[16] up to the screen at RAM[16384].
+    pub variables: usize,
+    /// Bytes of the `.hack` file, unchecked unless set.
+    pub output_bytes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            rom: 32 * 1024,
+            variables: 16 * 1024 - 16,
+            output_bytes: None,
+        }
+    }
+}
+
+/// What a program uses of each limited resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Usage {
+    pub rom: usize,
+    /// Data words included.
+    pub variables: usize,
+    /// Bytes of the `.hack` file.
+    pub output_bytes: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LimitExceeded {
+    pub what: &'static str,
+    pub used: usize,
+    pub max: usize,
+    pub hint: &'static str,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many {}: {} of at most {}. Hint: {}",
+            self.what, self.used, self.max, self.hint
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl Limits {
+    /// Every limit `usage` goes over.
+    pub fn check(&self, usage: &Usage) -> Vec<LimitExceeded> {
+        [
+            (
+                "ROM instructions",
+                usage.rom,
+                self.rom,
+                "enable -O1 in the VM translator to reduce code size",
+            ),
+            (
+                "variables and data words",
+                usage.variables,
+                self.variables,
+                "reuse variables, or keep large data on the heap",
+            ),
+            (
+                "bytes of output",
+                usage.output_bytes,
+                self.output_bytes.unwrap_or(usize::MAX),
+                "enable -O1 in the VM translator to reduce code size",
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, used, max, _)| used > max)
+        .map(|(what, used, max, hint)| LimitExceeded {
+            what,
+            used,
+            max,
+            hint,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+
+    #[test]
+    fn program_filling_the_platform_fits() {
+        let limits = Limits::default();
+        let usage = Usage {
+            rom: 32768,
+            variables: 16368,
+            output_bytes: 557055,
+        };
+
+        assert!(limits.check(&usage).is_empty());
+    }
+
+    #[test]
+    fn reports_exceeded_limits_only() {
+        let limits = Limits::default();
+        let usage = Usage {
+            rom: 32769,
+            variables: 3,
+            output_bytes: 557072,
+        };
+
+        let exceeded = limits.check(&usage);
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(
+            exceeded[0].to_string(),
+            "Too many ROM instructions: 32769 of at most 32768. Hint: enable -O1 in the VM translator to reduce code size"
+        );
+    }
+
+    #[test]
+    fn output_bytes_are_checked_when_capped() {
+        let limits = Limits {
+            rom: 100,
+            output_bytes: Some(1000),
+            ..Default::default()
+        };
+        let usage = Usage {
+            rom: 100,
+            variables: 0,
+            output_bytes: 1699,
+        };
+
+        let exceeded = limits.check(&usage);
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(
+            (exceeded[0].what, exceeded[0].used, exceeded[0].max),
+            ("bytes of output", 1699, 1000)
+        );
+    }
+}
@@ -1,65 +1,697 @@
-use std::env;
-use std::fs::{File, create_dir_all, read_to_string};
-use std::io::Write;
-use std::path::Path;
+use std::fs::{File, read_to_string, remove_file};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use clap::Parser as _;
+use hack_assembler_rs::{
+    assembler, disassembler, emulator, include, parser, peephole, preprocessor, scanner, source_map,
+    stream, symbol_file,
+};
+use n2t_core::build_dir::BuildDir;
+use n2t_core::line_map::LineMap;
+use n2t_core::profiling;
+use n2t_core::version::Version;
+use once_cell::sync::Lazy;
 
-use crate::assembler::Assembler;
-use crate::parser::Parser;
-use crate::preprocessor::Preprocessor;
+use crate::assembler::{Assembler, to_hack_text};
+use crate::call_stack::Functions;
+use crate::constant_stats::ConstantStats;
+use crate::coverage::Coverage;
+use crate::debug_info::{DebugInfo, Statics};
+use crate::emitter::{BinWriter, Emitter, HackWriter, ProvenanceWriter};
+use crate::emulator::{Cpu, Stop};
+use crate::evaluate::Evaluator;
+use crate::include::{Sources, without_includes};
+use crate::limits::{Limits, Usage};
+use crate::parser::{Address, Node, Parser};
+use crate::preprocessor::{Preprocessor, expand_macros};
+use crate::provenance::Artifact;
 use crate::scanner::Scanner;
+use crate::session::{Options, Session};
+use crate::source_map::SourceMap;
+use crate::symbol_file::SymbolFile;
+use crate::token_dump::TokenDump;
 
-mod assembler;
-mod parser;
-mod preprocessor;
-mod scanner;
+mod batch;
+mod call_stack;
+mod constant_stats;
+mod coverage;
+mod debug_info;
+mod emitter;
+mod evaluate;
+mod examples;
+mod grade;
+mod grammar;
+mod key_script;
+mod limits;
+mod output_list;
+mod provenance;
+mod ram_file;
+mod resolve;
+mod session;
+mod symbol_usage;
+mod token_dump;
+mod triage;
+mod verify;
 
-const DEBUG_ALL: &str = "DEBUG_ALL";
-const DEBUG_TOKENS: &str = "DEBUG_TOKENS";
-const DEBUG_AST: &str = "DEBUG_AST";
-const DEBUG_SYMBOL_TABLE: &str = "DEBUG_SYMBOL_TABLE";
-const DEBUG_AST_L: &str = "DEBUG_AST_L";
+static VERSION: Lazy<Version> = Lazy::new(|| n2t_core::version!("profiling"));
 
 #[derive(clap::Parser)]
-#[command(about = "Hack language assembler", long_about = None)]
+#[command(
+    version = VERSION.version,
+    long_version = VERSION.long(),
+    about = "Hack language assembler",
+    long_about = examples::LONG_ABOUT,
+    after_long_help = examples::long_help()
+)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Input .asm file
-    input: String,
+    /// Input .asm files, joined into one program, or a directory or `*`/`?` file-name
+    /// pattern of .asm files to assemble concurrently into the output directory
+    #[arg(required_unless_present = "emit_grammar")]
+    inputs: Vec<String>,
 
     /// Output .hack file
-    #[arg(short = 'o', long, help = ".hack output")]
-    output: String,
+    #[arg(
+        short = 'o',
+        long,
+        help = ".hack output, or the directory of the .hack outputs in batch mode",
+        required_unless_present_any = ["verify", "emit_grammar"]
+    )]
+    output: Option<String>,
 
     /// Additionally: Output to binary .hack.bin
     #[clap(long)]
     bin: bool,
+
+    /// Additionally: Record tool version, flags and hashes in .hack.json
+    #[clap(long)]
+    provenance: bool,
+
+    /// Additionally: Map every ROM address to its .asm line and label in a .map file
+    #[clap(long, conflicts_with = "verify")]
+    map: bool,
+
+    /// Write the words as they are assembled, in two passes over the source, instead of
+    /// holding every token and node: for large generated files, without macros
+    #[clap(long, conflicts_with_all = [
+        "verify", "bin", "provenance", "map", "dump", "resolve_symbols", "constant_stats", "run"
+    ])]
+    stream: bool,
+
+    /// Re-run the pipeline recorded in the input .hack.json and report the stage that diverged
+    #[clap(long, conflicts_with_all = ["output", "bin", "provenance"])]
+    verify: bool,
+
+    /// VM translator used by --verify and --eval
+    #[clap(long, default_value = "VMTranslator")]
+    translator: String,
+
+    /// Only warn when the program exceeds a Hack platform limit (ROM, variables)
+    #[clap(long)]
+    ignore_limits: bool,
+
+    /// Directory of the DEBUG_* dumps, instead of $N2T_BUILD_DIR or target-n2t
+    #[clap(long, value_name = "DIR")]
+    build_dir: Option<String>,
+
+    /// Fail when the program takes more ROM words than this, instead of the platform's 32768
+    #[clap(long, value_name = "N")]
+    max_rom: Option<usize>,
+
+    /// Fail when the .hack file takes more bytes than this
+    #[clap(long, value_name = "N")]
+    max_output_bytes: Option<usize>,
+
+    /// Additionally: Output the tokens with their kind and position as JSON
+    #[clap(long, value_name = "FILE", conflicts_with = "verify")]
+    dump: Option<String>,
+
+    /// Additionally: Output the assembly with labels removed and symbols replaced by addresses
+    #[clap(long, value_name = "FILE", conflicts_with = "verify")]
+    resolve_symbols: Option<String>,
+
+    /// Drop the instructions that change nothing: reloads of the value in A, loads
+    /// overwritten before use, jumps to the next instruction and unreachable code
+    #[clap(long, conflicts_with_all = ["verify", "provenance", "stream"])]
+    peephole: bool,
+
+    /// Define the labels and variables of another program's --emit-symbols before
+    /// assembling, allocating the variables after its own
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["verify", "provenance"])]
+    symbols: Vec<String>,
+
+    /// Additionally: Output the labels and variables of the program as JSON, for the
+    /// --symbols of other programs
+    #[clap(long, value_name = "FILE", conflicts_with = "verify")]
+    emit_symbols: Option<String>,
+
+    /// Additionally: List the most loaded constants and symbols, and the loads that find
+    /// their value already in A
+    #[clap(long, conflicts_with = "verify")]
+    constant_stats: bool,
+
+    /// Run the assembled program on a headless Hack CPU, with the RAM image of its `.data` sections
+    #[clap(long, conflicts_with = "verify")]
+    run: bool,
+
+    /// Preload RAM from the file before running: `address: value` lines, one word per line
+    /// from RAM[0] (such as a `.ram` file), or JSON
+    #[clap(long, value_name = "FILE", requires = "run")]
+    ram: Vec<String>,
+
+    /// Type on the keyboard while running: `step: key` lines, such as `1000: left`
+    #[clap(long, value_name = "FILE", requires = "run")]
+    keys: Option<String>,
+
+    /// Instructions to run before stopping a program that does not halt
+    #[clap(long, default_value_t = 1_000_000, requires = "run")]
+    max_steps: u64,
+
+    /// Seconds to run before stopping a program that does not halt, whatever --max-steps
+    /// is left
+    #[clap(long, value_name = "SECONDS", requires = "run")]
+    timeout: Option<f64>,
+
+    /// Write every read and write of memory to the file once the program stops, as
+    /// `step: ROM[pc] writes RAM[address] = value` lines
+    #[clap(long, value_name = "FILE", requires = "run")]
+    audit: Option<String>,
+
+    /// Print the call stack whenever the run reaches this label or ROM address
+    #[clap(long = "break", value_name = "LABEL", requires = "run")]
+    breakpoints: Vec<String>,
+
+    /// The VM translator's --functions table, naming the frames of call stacks
+    #[clap(long, value_name = "FILE", requires = "run")]
+    functions: Option<String>,
+
+    /// The VM translator's --debug-info file, naming locations after their function or
+    /// call site and showing the statics with call stacks
+    #[clap(long, value_name = "FILE", requires = "run")]
+    debug_info: Option<String>,
+
+    /// Evaluate this Jack expression at every breakpoint, in the function stopped in,
+    /// with the Jack compiler and the VM translator
+    #[clap(
+        long = "eval",
+        value_name = "EXPRESSION",
+        requires_all = ["breakpoints", "functions", "jack"]
+    )]
+    expressions: Vec<String>,
+
+    /// Directory of the Jack sources of the program, for --eval
+    #[clap(long, value_name = "DIR", requires = "expressions")]
+    jack: Option<String>,
+
+    /// Jack compiler used by --eval
+    #[clap(long, default_value = "Jack-compiler-rs")]
+    compiler: String,
+
+    /// Write how often every line ran to an lcov file once the program stops
+    #[clap(long, value_name = "FILE", requires = "run")]
+    coverage: Option<String>,
+
+    /// A --line-map of the VM translator or the Jack compiler, carrying --coverage and
+    /// the locations of call stacks on to the lines of their sources
+    #[clap(long, value_name = "FILE", requires = "run")]
+    line_map: Vec<String>,
+
+    /// Write the non-zero RAM words once the program stops, as `address: value` lines
+    #[clap(long, value_name = "FILE", requires = "run")]
+    dump_ram: Option<String>,
+
+    /// Write the columns, such as `"RAM[0]%D2.6.2 D%D1.6.1"`, to a .out file once the
+    /// program stops, formatted as the output-list of a test script
+    #[clap(long, value_name = "COLUMNS", requires = "run")]
+    output_list: Option<String>,
+
+    /// The .out file of --output-list [default: the output with extension .out]
+    #[clap(long, value_name = "FILE", requires = "output_list")]
+    out: Option<String>,
+
+    /// Compare the .out file with a .cmp file, failing at the first differing line
+    #[clap(long, value_name = "FILE", requires = "output_list")]
+    compare_to: Option<String>,
+
+    /// Write TextMate and Tree-sitter grammar stubs for Hack assembly into the directory
+    #[clap(long, value_name = "DIR", exclusive = true)]
+    emit_grammar: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Show typical runs of the assembler and the tools before it
+    Examples {
+        /// Write them as the README's Markdown
+        #[arg(long)]
+        markdown: bool,
+    },
+    /// Turn a .hack file, or a big-endian .bin one, back into assembly
+    Disassemble {
+        /// .hack or .hack.bin input
+        input: String,
+
+        /// .asm output [default: the standard output]
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+    },
+    /// Build every submission of a directory and run a rubric's tests on it
+    Grade {
+        /// Directory of one subdirectory of .jack, .vm or .asm files, or one `<student>.zip`, per student
+        submissions: String,
+
+        /// JSON rubric: the tests, with the RAM they start from and must leave
+        #[arg(long)]
+        rubric: String,
+
+        /// Jack compiler to run on .jack submissions
+        #[arg(long, default_value = "Jack-compiler-rs")]
+        compiler: String,
+
+        /// VM translator to run on .vm files
+        #[arg(long, default_value = "VMTranslator")]
+        translator: String,
+
+        /// Write the results of every student as JSON
+        #[arg(long)]
+        report: Option<String>,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let input_path = Path::new(&cli.input);
-    let output_path = Path::new(&cli.output);
-    println!("[->] Input file: {}", input_path.display());
+    profiling::start()?;
+    let result = execute(cli);
+    profiling::finish()?;
+
+    result
+}
+
+fn execute(cli: Cli) -> anyhow::Result<()> {
+    if let Some(Command::Examples { markdown }) = cli.command {
+        match markdown {
+            true => print!("{}", examples::markdown()),
+            false => print!("{}", examples::long_help()),
+        }
+        return Ok(());
+    }
+    if let Some(Command::Disassemble { input, output }) = &cli.command {
+        return disassemble(Path::new(input), output.as_deref());
+    }
+    if let Some(Command::Grade {
+        submissions,
+        rubric,
+        compiler,
+        translator,
+        report,
+    }) = &cli.command
+    {
+        let tools = grade::Tools {
+            compiler: compiler.clone(),
+            translator: translator.clone(),
+        };
+        let rubric = grade::Rubric::read(Path::new(rubric))?;
+        return grade::run(
+            Path::new(submissions),
+            &rubric,
+            &tools,
+            report.as_deref().map(Path::new),
+        );
+    }
+    if let Some(grammar_dir) = &cli.emit_grammar {
+        return grammar::emit(Path::new(grammar_dir));
+    }
+
+    let input = cli.inputs[0].clone();
+    let input_path = Path::new(&input);
+    if cli.verify {
+        if cli.inputs.len() > 1 {
+            anyhow::bail!("--verify takes a single .hack.json file");
+        }
+        println!("[->] Provenance file: {}", input_path.display());
+
+        return verify::run(input_path, &cli.translator);
+    }
+
+    let mut options = Options::from_env();
+    if let Some(build_dir) = &cli.build_dir {
+        options.build_dir = BuildDir::new(build_dir);
+    }
+    let session = Session::new(Options {
+        limits: Limits {
+            rom: cli.max_rom.unwrap_or(Limits::default().rom),
+            output_bytes: cli.max_output_bytes,
+            ..Default::default()
+        },
+        ignore_limits: cli.ignore_limits,
+        ..options
+    });
+    let output = cli.output.as_deref().expect("Required unless verifying");
+    let output_path = Path::new(output);
+    let mut imports = vec![];
+    for symbols_path in cli.symbols.iter() {
+        println!("[->] Symbols: {symbols_path}");
+        imports.push(SymbolFile::read(Path::new(symbols_path))?);
+    }
+
+    let batch_inputs = match cli.inputs.len() {
+        1 => batch::inputs(&input)?,
+        _ => None,
+    };
+    if let Some(inputs) = batch_inputs {
+        if cli.dump.is_some()
+            || cli.resolve_symbols.is_some()
+            || cli.emit_symbols.is_some()
+            || cli.provenance
+            || cli.constant_stats
+            || cli.run
+            || cli.stream
+        {
+            anyhow::bail!(
+                "--dump, --resolve-symbols, --emit-symbols, --provenance, --constant-stats, --run \
+                 and --stream take a single input file"
+            );
+        }
+        println!("[->] Input files: {} matching {input}", inputs.len());
+        println!("[<-] Output directory: {}", output_path.display());
+
+        return batch::assemble_all(&inputs, output_path, |input_path, output_path| {
+            let sources = Sources::read(&[input_path.to_path_buf()])?;
+            let program = assemble(&session, &sources, &imports, cli.peephole, None)?;
+
+            let output = output_path.display().to_string();
+            let mut emitters: Vec<Box<dyn Emitter>> = vec![Box::new(HackWriter {
+                path: output.clone(),
+            })];
+            if cli.bin {
+                emitters.push(Box::new(BinWriter {
+                    path: format!("{output}.bin"),
+                }));
+            }
+            profiling::stage("emit", || {
+                for emitter in emitters.iter_mut() {
+                    emitter.emit_binary(&program.binary)?;
+                    emitter.emit_ram(&program.ram_image)?;
+                }
+
+                anyhow::Ok(())
+            })?;
+            if cli.map {
+                program
+                    .source_map
+                    .write(&output_path.with_extension("map"))?;
+            }
+
+            Ok(program.usage)
+        });
+    }
+
+    if cli.provenance && cli.inputs.len() > 1 {
+        anyhow::bail!("--provenance takes a single input file, with the files it includes");
+    }
+    let input_paths: Vec<_> = cli.inputs.iter().map(PathBuf::from).collect();
+    for input_path in input_paths.iter() {
+        println!("[->] Input file: {}", input_path.display());
+    }
     println!("[<-] Output file: {}", output_path.display());
 
+    let sources = Sources::read(&input_paths)?;
+    for file in sources.files().iter().filter(|file| file.included) {
+        println!("[->] Included file: {}", file.path.display());
+    }
+    let source = sources.text();
+    if cli.stream {
+        let symbols = assemble_streaming(&session, &sources, &imports, output_path)?;
+        if let Some(symbols_path) = &cli.emit_symbols {
+            println!("[<-] Symbols: {symbols_path}");
+            symbols.write(Path::new(symbols_path))?;
+        }
+
+        return Ok(());
+    }
+    let Program {
+        binary,
+        ram_image,
+        source_map,
+        symbols,
+        ..
+    } = assemble(
+        &session,
+        &sources,
+        &imports,
+        cli.peephole,
+        cli.dump.as_deref(),
+    )?;
+
+    let mut emitters: Vec<Box<dyn Emitter>> = vec![Box::new(HackWriter {
+        path: output.to_string(),
+    })];
+    if cli.bin {
+        emitters.push(Box::new(BinWriter {
+            path: format!("{}.bin", output),
+        }));
+    }
+    if cli.provenance {
+        emitters.push(Box::new(ProvenanceWriter {
+            output: output.to_string(),
+            bin: cli.bin,
+            input: input.clone(),
+            source: sources.files()[0].text.clone(),
+            includes: sources.files()[1..]
+                .iter()
+                .map(|file| Artifact::new(file.path.display().to_string(), file.text.as_bytes()))
+                .collect(),
+        }));
+    }
+
+    profiling::stage("emit", || {
+        for emitter in emitters.iter_mut() {
+            emitter.emit_binary(&binary)?;
+            emitter.emit_ram(&ram_image)?;
+        }
+
+        anyhow::Ok(())
+    })?;
+
+    if cli.map {
+        let map_path = output_path.with_extension("map");
+        println!("[<-] Source map: {}", map_path.display());
+        source_map.write(&map_path)?;
+    }
+
+    if let Some(symbols_path) = &cli.emit_symbols {
+        println!("[<-] Symbols: {symbols_path}");
+        symbols.write(Path::new(symbols_path))?;
+    }
+
+    if let Some(resolved_path) = &cli.resolve_symbols {
+        println!("[<-] Resolved assembly: {resolved_path}");
+        let (resolved, _) = resolve::resolve_symbols(source)?;
+        File::create(resolved_path)?.write_all(resolved.as_bytes())?;
+    }
+
+    if cli.constant_stats {
+        ConstantStats::of(source)?.print();
+    }
+
+    if cli.run {
+        let mut source_map = source_map;
+        let statics = match &cli.debug_info {
+            Some(debug_info_path) => {
+                println!("[->] Debug info: {debug_info_path}");
+                let debug_info = DebugInfo::read(Path::new(debug_info_path))?;
+                debug_info.name_scopes(&mut source_map);
+                debug_info.statics(&symbols.variables)
+            }
+            None => Statics::default(),
+        };
+        let mut preload: Vec<_> = (0..).zip(ram_image).collect();
+        for ram_path in cli.ram.iter() {
+            println!("[->] RAM: {ram_path}");
+            preload.extend(ram_file::read(Path::new(ram_path))?);
+        }
+
+        let keys = match &cli.keys {
+            Some(keys_path) => {
+                println!("[->] Keys: {keys_path}");
+                key_script::read(Path::new(keys_path))?
+            }
+            None => vec![],
+        };
+
+        let functions = match &cli.functions {
+            Some(functions_path) => {
+                println!("[->] Functions: {functions_path}");
+                Functions::read(Path::new(functions_path), &source_map)?
+            }
+            None => Functions::default(),
+        };
+        let mut line_maps = vec![];
+        for map_path in cli.line_map.iter() {
+            println!("[->] Line map: {map_path}");
+            line_maps.push(LineMap::read(Path::new(map_path))?);
+        }
+        let breakpoints = cli
+            .breakpoints
+            .iter()
+            .map(|at| breakpoint(&source_map, at))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut evaluator = cli.jack.as_ref().map(|jack| {
+            let tools = grade::Tools {
+                compiler: cli.compiler.clone(),
+                translator: cli.translator.clone(),
+            };
+            Evaluator::new(&cli.expressions, Path::new(jack), tools, symbols.clone())
+        });
+
+        let mut cpu = Cpu::new(binary);
+        cpu.preload(&preload);
+        cpu.script_keys(&keys);
+        cpu.set_breakpoints(&breakpoints);
+        if cli.coverage.is_some() {
+            cpu.count_hits();
+        }
+        if cli.audit.is_some() {
+            cpu.record_accesses();
+        }
+        if let Some(seconds) = cli.timeout {
+            let timeout = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| anyhow::anyhow!("--timeout {seconds} is not a number of seconds"))?;
+            // A deadline too far ahead to represent is never reached
+            if let Some(deadline) = Instant::now().checked_add(timeout) {
+                cpu.set_deadline(deadline);
+            }
+        }
+        let cpu = run(
+            cpu,
+            &source_map,
+            &line_maps,
+            &functions,
+            &statics,
+            evaluator.as_mut(),
+            cli.max_steps,
+        )?;
+
+        if let Some(dump_path) = &cli.dump_ram {
+            println!("[<-] RAM: {dump_path}");
+            let mut dump_file = File::create(dump_path)?;
+            for (address, value) in cpu.ram.iter().enumerate() {
+                if *value != 0 {
+                    writeln!(&mut dump_file, "{address}: {}", *value as i16)?;
+                }
+            }
+        }
+
+        if let Some(audit_path) = &cli.audit {
+            println!("[<-] Memory accesses: {audit_path}");
+            let mut audit_file = BufWriter::new(File::create(audit_path)?);
+            for access in cpu.accesses() {
+                writeln!(&mut audit_file, "{access}")?;
+            }
+            audit_file.flush()?;
+        }
+
+        if let Some(coverage_path) = &cli.coverage {
+            let mut coverage = Coverage::of(&source_map, cpu.hits());
+            coverage.carry(&line_maps);
+            for line in coverage.summary() {
+                println!("[~] {line}");
+            }
+            println!("[<-] Coverage: {coverage_path}");
+            File::create(coverage_path)?.write_all(coverage.lcov().as_bytes())?;
+        }
+
+        if let Some(columns) = &cli.output_list {
+            let columns = output_list::parse(columns)?;
+            let out = format!(
+                "{}\n{}\n",
+                output_list::header(&columns),
+                output_list::row(&columns, &cpu)
+            );
+            let out_path = cli.out.unwrap_or_else(|| {
+                Path::new(output)
+                    .with_extension("out")
+                    .display()
+                    .to_string()
+            });
+            println!("[<-] Output list: {out_path}");
+            File::create(&out_path)?.write_all(out.as_bytes())?;
+
+            if let Some(cmp_path) = &cli.compare_to {
+                println!("[->] Comparison file: {cmp_path}");
+                output_list::compare(&out, &read_to_string(cmp_path)?)?;
+                println!("[~] End of script - Comparison ended successfully");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What the pipeline makes of one program.
+struct Program {
+    binary: Vec<Address>,
+    /// RAM image of the `.data` sections.
+    ram_image: Vec<Address>,
+    /// Source line and label of every ROM word.
+    source_map: SourceMap,
+    /// Labels and variables of the program, for `--emit-symbols`.
+    symbols: SymbolFile,
+    usage: Usage,
+}
+
+/// Scans, parses, preprocesses and assembles the program of `sources`, with the
+/// symbols of `imports` defined and the `peephole` pass if asked for, writing the
+/// debug files the session asks for to the build directory.
+fn assemble(
+    session: &Session,
+    sources: &Sources,
+    imports: &[SymbolFile],
+    peephole: bool,
+    dump_path: Option<&str>,
+) -> anyhow::Result<Program> {
+    let input_path = sources.main();
+    let source = sources.text();
+
     // 1. Scanning ..
-    let source = read_to_string(&input_path)?;
-    let tokens: Result<Vec<_>, _> = Scanner::new(&source).into_iter().collect();
-    let tokens = tokens?;
-    if test_debug(DEBUG_TOKENS) {
-        let mut debug_output_file = create_debug_file(&output_path, "tokens")?;
+    let tokens: Result<Vec<_>, _> = profiling::stage("scan", || Scanner::new(source).collect());
+    let tokens = tokens.map_err(|error| sources.diagnose(error))?;
+    if session.options.debug_tokens {
+        let mut debug_output_file = create_debug_file(session, input_path, "tokens")?;
 
         for token in tokens.iter() {
             let _ = writeln!(&mut debug_output_file, "{token:#?}");
         }
     }
+    if let Some(dump_path) = dump_path {
+        let mut dump = TokenDump::new();
+        for file in sources.files() {
+            let text = without_includes(&file.text);
+            let tokens: Result<Vec<_>, _> = Scanner::new(&text).collect();
+            dump.add(&file.path, &tokens?);
+        }
+        dump.write(Path::new(dump_path))?;
+    }
+    let tokens = profiling::stage("expand", || expand_macros(tokens))
+        .map_err(|error| sources.diagnose(error))?;
 
     // 2. Parsing ..
-    let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
-    let nodes = nodes?;
-    if test_debug(DEBUG_AST) {
-        let mut debug_output_file = create_debug_file(&output_path, "ast")?;
+    let nodes: Result<Vec<_>, _> =
+        profiling::stage("parse", || Parser::new(tokens.into_iter()).collect());
+    let mut nodes = nodes.map_err(|error| sources.diagnose(error))?;
+    if peephole {
+        optimize(&mut nodes, &input_path.display().to_string());
+    }
+    let mut source_map = SourceMap::of(&input_path.display().to_string(), &nodes);
+    source_map.locate(sources);
+    if session.options.debug_ast {
+        let mut debug_output_file = create_debug_file(session, input_path, "ast")?;
 
         for node in nodes.iter() {
             writeln!(&mut debug_output_file, "{node:#?}")?;
@@ -67,17 +699,25 @@ fn main() -> anyhow::Result<()> {
     }
 
     // 3. Preprocessing ..
-    let preprocessor = Preprocessor::init_static_symbols(nodes).extract_source_symbols();
-    if test_debug(DEBUG_SYMBOL_TABLE) {
-        let mut debug_output_file = create_debug_file(&output_path, "symbol_table")?;
+    let mut preprocessor = Preprocessor::new();
+    for symbols in imports {
+        preprocessor.import(symbols)?;
+    }
+    profiling::stage("preprocess", || preprocessor.preprocess(&mut nodes))
+        .map_err(|error| sources.diagnose(error))?;
+    symbol_usage::print(sources, &symbol_usage::warnings(&preprocessor));
+    if session.options.debug_symbol_table {
+        let mut debug_output_file = create_debug_file(session, input_path, "symbol_table")?;
         let symbol_table = preprocessor.symbol_table();
 
         writeln!(&mut debug_output_file, "{symbol_table:#?}")?;
     }
+    let variables = preprocessor.variable_count() + preprocessor.data_len();
+    let ram_image = preprocessor.ram_image();
+    let symbols = preprocessor.export();
 
-    let nodes: Vec<_> = preprocessor.replace_source_symbols();
-    if test_debug(DEBUG_AST_L) {
-        let mut debug_output_file = create_debug_file(&output_path, "ast_L")?;
+    if session.options.debug_ast_l {
+        let mut debug_output_file = create_debug_file(session, input_path, "ast_L")?;
 
         for node in nodes.iter() {
             writeln!(&mut debug_output_file, "{node:#?}")?;
@@ -85,52 +725,231 @@ fn main() -> anyhow::Result<()> {
     }
 
     // 4. Assembling ..
-    let assembler = Assembler::new(nodes).assemble();
-    let mut output_file = File::create(&output_path)?;
-    for (i, x) in assembler.iter().enumerate() {
-        write!(&mut output_file, "{:016b}", x)?;
+    let binary = profiling::stage("assemble", || Assembler::new(nodes).assemble())
+        .map_err(|error| sources.diagnose(error))?;
+    let usage = Usage {
+        rom: binary.len(),
+        variables,
+        output_bytes: to_hack_text(&binary).len(),
+    };
+    check_limits(session, &usage)?;
 
-        if i != assembler.len() - 1 {
-            write!(&mut output_file, "\n")?;
-        }
+    Ok(Program {
+        binary,
+        ram_image,
+        source_map,
+        symbols,
+        usage,
+    })
+}
+
+/// `--peephole`: drops the instructions that change nothing from `nodes`, unless an
+/// expression over a label depends on the layout of the ROM.
+fn optimize(nodes: &mut Vec<Node<'_>>, file: &str) {
+    if let Some(expression) = peephole::layout_dependency(nodes) {
+        println!("[~] {file}: no peephole pass, as `@{expression}` depends on the ROM layout");
+        return;
     }
 
-    if cli.bin {
-        let mut output_file_binary = File::create(format!("{}.bin", output_path.display()))?;
+    let instructions = nodes
+        .iter()
+        .filter(|node| matches!(node, Node::Instruction(_)))
+        .count();
+    let removed = profiling::stage("peephole", || peephole::optimize(nodes));
+    println!(
+        "[~] {file}: peephole pass removed {} of {instructions} instructions: {} reloads, {} \
+         overwritten loads, {} jumps to the next instruction, {} unreachable",
+        removed.removed(),
+        removed.reloads,
+        removed.overwritten,
+        removed.jumps_to_next,
+        removed.unreachable
+    );
+}
+
+/// `--stream`: writes the words of the program of `sources` to `output_path` as they
+/// are assembled, removing the file again when it turns out to be wrong or too large.
+/// Returns the labels and variables of the program.
+fn assemble_streaming(
+    session: &Session,
+    sources: &Sources,
+    imports: &[SymbolFile],
+    output_path: &Path,
+) -> anyhow::Result<SymbolFile> {
+    let source = sources.text();
+    let mut preprocessor = Preprocessor::new();
+    for symbols in imports {
+        preprocessor.import(symbols)?;
+    }
+    let mut output = BufWriter::new(File::create(output_path)?);
+    let result = profiling::stage("stream", || {
+        stream::assemble(source, &mut preprocessor, &mut output)
+            .map_err(|error| sources.diagnose(error))
+    })
+    .and_then(|rom| {
+        output.flush()?;
+        let usage = Usage {
+            rom,
+            variables: preprocessor.variable_count() + preprocessor.data_len(),
+            output_bytes: (17 * rom).saturating_sub(1),
+        };
+        check_limits(session, &usage)
+    });
+    if let Err(error) = result {
+        drop(output);
+        let _ = remove_file(output_path);
+        return Err(error);
+    }
+
+    symbol_usage::print(sources, &symbol_usage::warnings(&preprocessor));
+    HackWriter {
+        path: output_path.display().to_string(),
+    }
+    .emit_ram(&preprocessor.ram_image())?;
+
+    Ok(preprocessor.export())
+}
 
-        for x in assembler.iter() {
-            output_file_binary.write_all(&x.to_be_bytes())?;
+/// Writes the assembly of the machine code at `input_path` to `output`, or prints it.
+fn disassemble(input_path: &Path, output: Option<&str>) -> anyhow::Result<()> {
+    let words = disassembler::read_words(input_path)?;
+    let text = disassembler::disassemble(&words)?;
+
+    match output {
+        Some(output) => {
+            println!("[->] Input file: {}", input_path.display());
+            println!("[<-] Output file: {output}");
+            File::create(output)?.write_all(text.as_bytes())?;
         }
+        None => print!("{text}"),
     }
 
     Ok(())
 }
 
-fn test_debug<S>(s: S) -> bool
-where
-    S: AsRef<str>,
-{
-    env::var(s.as_ref()).is_ok() || env::var(DEBUG_ALL).is_ok()
+/// The ROM address of the `--break` label or number `at`.
+fn breakpoint(map: &SourceMap, at: &str) -> anyhow::Result<Address> {
+    if let Some(&address) = map.labels.get(at) {
+        return Ok(address);
+    }
+
+    at.parse().map_err(|_| {
+        anyhow::anyhow!("Breakpoint `{at}` is neither a label of the program nor a ROM address")
+    })
 }
 
-fn create_debug_file<P, S>(output_path: P, suffix: S) -> anyhow::Result<File>
-where
-    P: AsRef<Path>,
-    S: AsRef<str>,
-{
-    let parent_output_path = output_path
-        .as_ref()
-        .parent()
-        .unwrap_or_else(|| Path::new("."));
+/// Runs `cpu` until it stops, printing the call stack at every breakpoint, then how
+/// it stopped and what is on the screen, and where it is stuck when it does not halt.
+fn run(
+    mut cpu: Cpu,
+    map: &SourceMap,
+    line_maps: &[LineMap],
+    functions: &Functions,
+    statics: &Statics,
+    mut evaluator: Option<&mut Evaluator>,
+    max_steps: u64,
+) -> anyhow::Result<Cpu> {
+    let print_call_stack = |cpu: &Cpu| {
+        println!("[~]   Call stack, innermost first:");
+        for line in call_stack::lines(cpu, map, line_maps, functions) {
+            println!("[~]     {line}");
+        }
+        for line in statics.lines(&cpu.ram) {
+            println!("[~]   {line}");
+        }
+    };
+
+    let stop = profiling::stage("run", || {
+        loop {
+            match cpu.run(max_steps - cpu.steps) {
+                Stop::Breakpoint => {
+                    let at = call_stack::describe(map, line_maps, cpu.pc);
+                    println!("[~] Breakpoint at {at} after {} steps", cpu.steps);
+                    print_call_stack(&cpu);
+                    if let Some(evaluator) = evaluator.as_mut() {
+                        for line in evaluator.evaluate(&cpu, functions) {
+                            println!("[~]   {line}");
+                        }
+                    }
+                }
+                stop => break stop,
+            }
+        }
+    });
+    match stop {
+        Stop::Halted => println!("[~] Halted after {} steps", cpu.steps),
+        Stop::EndOfRom => {
+            match cpu.branches().last() {
+                Some(&(from, to)) if to == cpu.pc => println!(
+                    "[~] Ran past the end of the ROM after {} steps, jumping to ROM[{to}] from {}",
+                    cpu.steps,
+                    call_stack::describe(map, line_maps, from)
+                ),
+                _ => println!("[~] Ran past the end of the ROM after {} steps", cpu.steps),
+            }
+            print_call_stack(&cpu);
+        }
+        Stop::Trap(trap) => {
+            let at = call_stack::describe(map, line_maps, cpu.pc);
+            println!("[~] Trapped after {} steps at {at}: {trap}", cpu.steps);
+            println!(
+                "[~]   A {}, D {}, SP {}, LCL {}, ARG {}, THIS {}, THAT {}",
+                cpu.a, cpu.d as i16, cpu.ram[0], cpu.ram[1], cpu.ram[2], cpu.ram[3], cpu.ram[4]
+            );
+            print_call_stack(&cpu);
+        }
+        Stop::StepLimit | Stop::Timeout => {
+            let limit = match stop {
+                Stop::Timeout => " at the timeout",
+                _ => "",
+            };
+            println!(
+                "[~] Stopped{limit} after {} steps at PC {}, without halting",
+                cpu.steps, cpu.pc
+            );
+            for line in triage::report(&cpu, map, line_maps, functions) {
+                println!("[~]   {line}");
+            }
+            for line in statics.lines(&cpu.ram) {
+                println!("[~]   {line}");
+            }
+        }
+        Stop::Breakpoint => unreachable!("breakpoints are printed while running"),
+    }
+    println!("[~] Screen CRC-32: {:08x}", cpu.screen_crc());
+
+    Ok(cpu)
+}
 
-    let file_name = output_path.as_ref().file_name().expect("").display();
+/// Fails listing every exceeded limit and the size of the program, unless only
+/// warnings are asked for.
+fn check_limits(session: &Session, usage: &Usage) -> anyhow::Result<()> {
+    let exceeded = session.options.limits.check(usage);
+    if exceeded.is_empty() {
+        return Ok(());
+    }
 
-    let debug_dir = parent_output_path.join(format!("{file_name}_debug",));
+    let size = format!(
+        "Size: {} ROM words, {} variables, {} bytes of .hack",
+        usage.rom, usage.variables, usage.output_bytes
+    );
+    if session.options.ignore_limits {
+        for limit in exceeded.iter() {
+            println!("[~] {limit}");
+        }
+        println!("[~] {size}");
+    } else {
+        let mut report: Vec<_> = exceeded.iter().map(ToString::to_string).collect();
+        report.push(size);
+        anyhow::bail!("{}", report.join("\n"));
+    }
 
-    create_dir_all(&debug_dir)?;
+    Ok(())
+}
 
-    let path = debug_dir.join(format!("{}.{}", file_name, suffix.as_ref()));
-    let debug_output_file = File::create(path)?;
+/// The `suffix` dump of `input_path`, in the build directory.
+fn create_debug_file(session: &Session, input_path: &Path, suffix: &str) -> anyhow::Result<File> {
+    let build_dir = &session.options.build_dir;
 
-    Ok(debug_output_file)
+    build_dir.create(env!("CARGO_PKG_NAME"), input_path, suffix)
 }
@@ -0,0 +1,217 @@
+//! The `output-list` of the course's test scripts, for `--run`: `.out` rows of CPU
+//! registers and RAM words in the same columns as the official `.cmp` files, and
+//! the comparison with them.
+//!
+//! A column is `name%<format><left>.<width>.<right>`, such as `RAM[0]%D2.6.2`: the value
+//! in `width` characters (`B`inary, `D`ecimal, he`X` or `S`tring, left-aligned), between
+//! `left` and `right` spaces. The header centers the name in the whole column.
+
+use crate::emulator::Cpu;
+use crate::parser::Address;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Variable {
+    Ram(Address),
+    A,
+    D,
+    Pc,
+    /// Instructions executed.
+    Time,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    name: String,
+    variable: Variable,
+    format: char,
+    left: usize,
+    width: usize,
+    right: usize,
+}
+
+/// Columns of a whitespace-separated list; a column without format is `%B1.16.1`.
+pub fn parse(list: &str) -> anyhow::Result<Vec<Column>> {
+    list.split_whitespace().map(parse_column).collect()
+}
+
+fn parse_column(column: &str) -> anyhow::Result<Column> {
+    let (name, format) = column.split_once('%').unwrap_or((column, "B1.16.1"));
+
+    let variable = match name {
+        "A" => Variable::A,
+        "D" => Variable::D,
+        "PC" => Variable::Pc,
+        "time" => Variable::Time,
+        _ => match name
+            .strip_prefix("RAM[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|address| address.parse().ok())
+        {
+            Some(address @ 0..0x8000) => Variable::Ram(address),
+            _ => {
+                anyhow::bail!("Unknown output variable `{name}`, expected RAM[n], A, D, PC or time")
+            }
+        },
+    };
+
+    let bad_format =
+        || anyhow::anyhow!("Bad format `%{format}` of `{name}`, expected e.g. `%D2.6.2`");
+    let mut chars = format.chars();
+    let letter = chars
+        .next()
+        .filter(|c| "BDXS".contains(*c))
+        .ok_or_else(bad_format)?;
+    let sizes: Vec<usize> = chars
+        .as_str()
+        .split('.')
+        .map(|size| size.parse().map_err(|_| bad_format()))
+        .collect::<Result<_, _>>()?;
+    let [left, width, right] = sizes[..] else {
+        return Err(bad_format());
+    };
+
+    Ok(Column {
+        name: name.to_string(),
+        variable,
+        format: letter,
+        left,
+        width,
+        right,
+    })
+}
+
+/// `|  RAM[0]  |`
+pub fn header(columns: &[Column]) -> String {
+    let cells = columns.iter().map(|column| {
+        let total = column.left + column.width + column.right;
+        let name: String = column.name.chars().take(total).collect();
+        let left = (total - name.chars().count()) / 2;
+
+        format!("{}{name:<rest$}", " ".repeat(left), rest = total - left)
+    });
+
+    line(cells)
+}
+
+/// `|       5  |`
+pub fn row(columns: &[Column], cpu: &Cpu) -> String {
+    let cells = columns.iter().map(|column| {
+        let word = match column.variable {
+            Variable::Ram(address) => cpu.ram[address as usize],
+            Variable::A => cpu.a,
+            Variable::D => cpu.d,
+            Variable::Pc => cpu.pc,
+            Variable::Time => cpu.steps as Address,
+        };
+        let value = match column.format {
+            'B' => format!("{word:016b}"),
+            'X' => format!("{word:04X}"),
+            _ => (word as i16).to_string(),
+        };
+        // Too long a value keeps its lowest digits
+        let value = &value[value.len().saturating_sub(column.width)..];
+        let value = match column.format {
+            'B' | 'X' => format!("{value:0>width$}", width = column.width),
+            'S' => format!("{value:<width$}", width = column.width),
+            _ => format!("{value:>width$}", width = column.width),
+        };
+
+        format!(
+            "{}{value}{}",
+            " ".repeat(column.left),
+            " ".repeat(column.right)
+        )
+    });
+
+    line(cells)
+}
+
+fn line(cells: impl Iterator<Item = String>) -> String {
+    let mut line = String::from("|");
+    for cell in cells {
+        line.push_str(&cell);
+        line.push('|');
+    }
+
+    line
+}
+
+/// Checks `out` against the expected `cmp`, line by line, where `*` in `cmp` matches
+/// any character. Line endings do not matter.
+pub fn compare(out: &str, cmp: &str) -> anyhow::Result<()> {
+    let mut out_lines = out.lines();
+    for (i, expected) in cmp.lines().enumerate() {
+        let expected = expected.trim_end_matches('\r');
+        let actual = out_lines.next().unwrap_or_default().trim_end_matches('\r');
+
+        let matches = actual.len() == expected.len()
+            && actual
+                .chars()
+                .zip(expected.chars())
+                .all(|(a, e)| e == '*' || a == e);
+        if !matches {
+            anyhow::bail!(
+                "Comparison failure at line {}:\n  expected {expected}\n  got      {actual}",
+                i + 1
+            );
+        }
+    }
+    if let Some(extra) = out_lines.next() {
+        anyhow::bail!("Comparison failure: `{extra}` goes past the end of the comparison file");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod output_list_tests {
+    use super::*;
+
+    #[test]
+    fn formats_columns_like_the_course_files() {
+        let columns = parse("RAM[0]%D2.6.2 RAM[1]%B1.16.1 D%X1.4.1 PC%D1.1.1 time%S1.4.1").unwrap();
+        let mut cpu = Cpu::new(vec![]);
+        cpu.preload(&[(0, 5), (1, 0xFFFE)]);
+        cpu.d = 0xBEEF;
+        cpu.pc = 12;
+        cpu.steps = 7;
+
+        assert_eq!(
+            header(&columns),
+            "|  RAM[0]  |      RAM[1]      |  D   |PC | time |"
+        );
+        assert_eq!(
+            row(&columns, &cpu),
+            "|       5  | 1111111111111110 | BEEF | 2 | 7    |"
+        );
+        assert_eq!(row(&parse("RAM[1]%D1.3.1").unwrap(), &cpu), "|  -2 |");
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_formats() {
+        assert_eq!(
+            parse("RAM[32768]").unwrap_err().to_string(),
+            "Unknown output variable `RAM[32768]`, expected RAM[n], A, D, PC or time"
+        );
+        assert_eq!(
+            parse("A%D2.6").unwrap_err().to_string(),
+            "Bad format `%D2.6` of `A`, expected e.g. `%D2.6.2`"
+        );
+        assert!(parse("A%Q1.1.1").is_err());
+    }
+
+    #[test]
+    fn compares_with_wildcards() {
+        let out = "|  RAM[0]  |\n|       5  |";
+
+        assert!(compare(out, "|  RAM[0]  |\r\n|       *  |\r\n").is_ok());
+        assert_eq!(
+            compare(out, "|  RAM[0]  |\n|       6  |")
+                .unwrap_err()
+                .to_string(),
+            "Comparison failure at line 2:\n  expected |       6  |\n  got      |       5  |"
+        );
+        assert!(compare(out, "|  RAM[0]  |").is_err());
+        assert!(compare("", "|  RAM[0]  |").is_err());
+    }
+}
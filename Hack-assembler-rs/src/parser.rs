@@ -1,5 +1,7 @@
+use std::fmt::Display;
 use std::iter::Peekable;
 
+use crate::diagnostic::{A_INSTRUCTION_RANGE, SyntaxError};
 use crate::scanner::{Token, TokenType};
 
 macro_rules! consume {
@@ -10,45 +12,30 @@ macro_rules! consume {
     };
 }
 
+/// `$expected` describes the patterns in the error pointing at any other token.
 macro_rules! consume_and_ensure_matches {
-    ($tokens:expr, $( $pattern:pat ),* $(,)?) => {
+    ($tokens:expr, $expected:expr; $( $pattern:pat ),* $(,)?) => {
         match $tokens.next() {
             $(Some(token @ Token {
                 token_type: $pattern,
                 ..
             }) => anyhow::Result::<Token>::Ok(token), )*
-            token => {
-                let expected_patterns = vec![$(stringify!($pattern)),*];
-                anyhow::bail!(
-                    "Unexpected token. Expected one of: {} but got {:?}",
-                    expected_patterns.join(", "),
-                    token
-                )
-            },
+            Some(token) => return Err(unexpected(&token, $expected).into()),
+            None => anyhow::bail!("Unexpected end of input, expected {}", $expected),
         }
     };
 }
 
 macro_rules! peek_matches {
     ($tokens:expr, $( $pattern:pat ),* $(,)?) => {
-        if let Some(value) = $tokens.peek() {
-            match value {
-                $(Token {
-                    token_type: $pattern,
-                    ..
-                } => true, )*
-                _ => false,
-            }
-        } else {
-            false
-        }
+        matches!($tokens.peek(), $( Some(Token { token_type: $pattern, .. }) )|*)
     };
 }
 
 macro_rules! consume_if_matches {
     ($tokens:expr, $( $pattern:pat ),* $(,)?) => {
         if peek_matches!($tokens, $( $pattern ),*) {
-            Some(consume_and_ensure_matches!($tokens, $( $pattern ),*)?)
+            Some(consume_and_ensure_matches!($tokens, stringify!($( $pattern )|*); $( $pattern ),*)?)
         } else {
             None
         }
@@ -57,6 +44,139 @@ macro_rules! consume_if_matches {
 
 pub type Address = u16;
 
+/// Directives of data sections and `.org`, scanned as identifiers.
+pub const DIRECTIVES: [&str; 5] = [".data", ".text", ".word", ".fill", ".org"];
+
+/// A computation of the Hack ALU, the `comp` of a C-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comp {
+    Zero,
+    One,
+    NegOne,
+    D,
+    A,
+    NotD,
+    NotA,
+    NegD,
+    NegA,
+    DPlusOne,
+    APlusOne,
+    DMinusOne,
+    AMinusOne,
+    DPlusA,
+    DMinusA,
+    AMinusD,
+    DAndA,
+    DOrA,
+    M,
+    NotM,
+    NegM,
+    MPlusOne,
+    MMinusOne,
+    DPlusM,
+    DMinusM,
+    MMinusD,
+    DAndM,
+    DOrM,
+}
+
+impl Comp {
+    #[rustfmt::skip]
+    pub const ALL: [Comp; 28] = [
+        Comp::Zero, Comp::One, Comp::NegOne, Comp::D, Comp::A, Comp::NotD, Comp::NotA,
+        Comp::NegD, Comp::NegA, Comp::DPlusOne, Comp::APlusOne, Comp::DMinusOne,
+        Comp::AMinusOne, Comp::DPlusA, Comp::DMinusA, Comp::AMinusD, Comp::DAndA, Comp::DOrA,
+        Comp::M, Comp::NotM, Comp::NegM, Comp::MPlusOne, Comp::MMinusOne, Comp::DPlusM,
+        Comp::DMinusM, Comp::MMinusD, Comp::DAndM, Comp::DOrM,
+    ];
+
+    /// As written in a C-instruction, such as `D+1`.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Comp::Zero => "0",
+            Comp::One => "1",
+            Comp::NegOne => "-1",
+            Comp::D => "D",
+            Comp::A => "A",
+            Comp::NotD => "!D",
+            Comp::NotA => "!A",
+            Comp::NegD => "-D",
+            Comp::NegA => "-A",
+            Comp::DPlusOne => "D+1",
+            Comp::APlusOne => "A+1",
+            Comp::DMinusOne => "D-1",
+            Comp::AMinusOne => "A-1",
+            Comp::DPlusA => "D+A",
+            Comp::DMinusA => "D-A",
+            Comp::AMinusD => "A-D",
+            Comp::DAndA => "D&A",
+            Comp::DOrA => "D|A",
+            Comp::M => "M",
+            Comp::NotM => "!M",
+            Comp::NegM => "-M",
+            Comp::MPlusOne => "M+1",
+            Comp::MMinusOne => "M-1",
+            Comp::DPlusM => "D+M",
+            Comp::DMinusM => "D-M",
+            Comp::MMinusD => "M-D",
+            Comp::DAndM => "D&M",
+            Comp::DOrM => "D|M",
+        }
+    }
+
+    /// The `a c1 c2 c3 c4 c5 c6` bits of the instruction.
+    pub fn bits(self) -> u16 {
+        match self {
+            Comp::Zero => 42,
+            Comp::One => 63,
+            Comp::NegOne => 58,
+            Comp::D => 12,
+            Comp::A => 48,
+            Comp::NotD => 13,
+            Comp::NotA => 49,
+            Comp::NegD => 15,
+            Comp::NegA => 51,
+            Comp::DPlusOne => 31,
+            Comp::APlusOne => 55,
+            Comp::DMinusOne => 14,
+            Comp::AMinusOne => 50,
+            Comp::DPlusA => 2,
+            Comp::DMinusA => 19,
+            Comp::AMinusD => 7,
+            Comp::DAndA => 0,
+            Comp::DOrA => 21,
+            Comp::M => 112,
+            Comp::NotM => 113,
+            Comp::NegM => 115,
+            Comp::MPlusOne => 119,
+            Comp::MMinusOne => 114,
+            Comp::DPlusM => 66,
+            Comp::DMinusM => 83,
+            Comp::MMinusD => 71,
+            Comp::DAndM => 64,
+            Comp::DOrM => 85,
+        }
+    }
+
+    pub fn from_mnemonic(text: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|comp| comp.mnemonic() == text)
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|comp| comp.bits() == bits)
+    }
+}
+
+impl Display for Comp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+pub(crate) const DESTINATIONS: &str = "`M`, `D`, `MD`, `A`, `AM`, `AD` or `AMD`";
+
+pub(crate) const JUMPS: &str = "`JGT`, `JEQ`, `JGE`, `JLT`, `JNE`, `JLE` or `JMP`";
+
 #[derive(Debug)]
 pub enum Instruction<'de> {
     /// A-Instruction
@@ -67,6 +187,9 @@ pub enum Instruction<'de> {
         _at: Token<'de>,
         /// Either a symbol (label or variable) or a numeric identifier.
         token: Token<'de>,
+        /// `+` or `-` and its operand, for every term after `token`, as `+ 32` of
+        /// `@SCREEN+32`. Preprocessing adds them up into `token`.
+        terms: Vec<(Token<'de>, Token<'de>)>,
     },
     /// C-Instruction
     /// Format: dest=comp;jump
@@ -76,7 +199,9 @@ pub enum Instruction<'de> {
     C {
         dest: Option<Token<'de>>,
         _eq: Option<Token<'de>>,
-        comp: Vec<Token<'de>>,
+        comp: Comp,
+        /// The tokens `comp` was read from.
+        _comp_tokens: Vec<Token<'de>>,
         _sem: Option<Token<'de>>,
         jump: Option<Token<'de>>,
     },
@@ -90,10 +215,30 @@ pub enum Node<'de> {
         _right_paren: Token<'de>,
     },
     Instruction(Instruction<'de>),
+    /// `.data` or `.text`: the labels and words that follow go to RAM, or the
+    /// labels and instructions to ROM.
+    Section {
+        directive: Token<'de>,
+        data: bool,
+    },
+    /// `.word` or `.fill` of a `.data` section, with the words it lays out.
+    Data {
+        directive: Token<'de>,
+        words: Vec<Address>,
+    },
+    /// `.org <address>`: the instructions that follow go to ROM from `address` on.
+    Org {
+        _directive: Token<'de>,
+        address: Token<'de>,
+        /// Zero words laid out before `address`, counted by preprocessing.
+        padding: Address,
+    },
 }
 
 pub struct Parser<'de, I: Iterator<Item = Token<'de>>> {
     tokens: Peekable<I>,
+    /// In a `.data` section.
+    in_data: bool,
 }
 
 impl<'de, I> Parser<'de, I>
@@ -103,6 +248,7 @@ where
     pub fn new(tokens: I) -> Parser<'de, I> {
         Parser {
             tokens: tokens.peekable(),
+            in_data: false,
         }
     }
 
@@ -112,10 +258,21 @@ where
                 return None;
             }
 
+            if matches!(token.token_type, TokenType::IDENTIFIER)
+                && DIRECTIVES.contains(&token.lexeme.as_ref())
+            {
+                return Some(self.parse_directive());
+            }
+
             if matches!(token.token_type, TokenType::LEFT_PAREN) {
                 return Some(self.parse_label());
             }
 
+            if self.in_data {
+                let message = "Expected `.word`, `.fill`, a label or `.text` in a `.data` section";
+                return Some(Err(token.error(message).into()));
+            }
+
             if matches!(token.token_type, TokenType::AT) {
                 return Some(self.parse_a_instruction());
             }
@@ -123,13 +280,14 @@ where
             return Some(self.parse_c_instruction());
         }
 
-        unreachable!()
+        // Out of tokens before `EOF`: the scanner stopped at an error
+        None
     }
 
     fn parse_label(&mut self) -> anyhow::Result<Node<'de>> {
-        let _left_paren = consume_and_ensure_matches!(self.tokens, TokenType::LEFT_PAREN)?;
-        let name = consume_and_ensure_matches!(self.tokens, TokenType::IDENTIFIER)?;
-        let _right_paren = consume_and_ensure_matches!(self.tokens, TokenType::RIGHT_PAREN)?;
+        let _left_paren = consume_and_ensure_matches!(self.tokens, "`(`"; TokenType::LEFT_PAREN)?;
+        let name = consume_and_ensure_matches!(self.tokens, "a label name"; TokenType::IDENTIFIER)?;
+        let _right_paren = consume_and_ensure_matches!(self.tokens, "`)`"; TokenType::RIGHT_PAREN)?;
 
         Ok(Node::Label {
             _left_paren,
@@ -138,12 +296,138 @@ where
         })
     }
 
+    fn parse_directive(&mut self) -> anyhow::Result<Node<'de>> {
+        let directive = consume!(self.tokens)?;
+
+        match directive.lexeme.as_ref() {
+            ".data" | ".text" => {
+                self.in_data = directive.lexeme == ".data";
+
+                Ok(Node::Section {
+                    directive,
+                    data: self.in_data,
+                })
+            }
+            ".org" if self.in_data => {
+                let message = "`.org` is only allowed outside of a `.data` section";
+                Err(directive.error(message).into())
+            }
+            ".org" => {
+                let address = consume_and_ensure_matches!(self.tokens, "a ROM address"; TokenType::NUMBER(_))?;
+                if let TokenType::NUMBER(value @ 0x8000..) = address.token_type {
+                    let message = format!("ROM address `{value}` is out of range 0..=32767");
+                    return Err(address.error(message).into());
+                }
+
+                Ok(Node::Org {
+                    _directive: directive,
+                    address,
+                    padding: 0,
+                })
+            }
+            lexeme if !self.in_data => {
+                let message = format!("`{lexeme}` is only allowed in a `.data` section");
+                Err(directive.error(message).into())
+            }
+            ".word" => {
+                let mut words = vec![self.parse_word()?];
+                while peek_matches!(self.tokens, TokenType::NUMBER(_) | TokenType::MINUS) {
+                    words.push(self.parse_word()?);
+                }
+
+                Ok(Node::Data {
+                    directive,
+                    words,
+                })
+            }
+            ".fill" => {
+                let count =
+                    consume_and_ensure_matches!(self.tokens, "a word count"; TokenType::NUMBER(_))?;
+                let TokenType::NUMBER(count) = count.token_type else {
+                    unreachable!()
+                };
+                let value = if peek_matches!(self.tokens, TokenType::NUMBER(_) | TokenType::MINUS) {
+                    self.parse_word()?
+                } else {
+                    0
+                };
+
+                Ok(Node::Data {
+                    directive,
+                    words: vec![value; count as usize],
+                })
+            }
+            _ => unreachable!("Every directive is handled"),
+        }
+    }
+
+    /// A data word: a number up to 65535, or down to -32768 in two's complement.
+    fn parse_word(&mut self) -> anyhow::Result<Address> {
+        let minus = consume_if_matches!(self.tokens, TokenType::MINUS);
+        let token = consume_and_ensure_matches!(self.tokens, "a number"; TokenType::NUMBER(_))?;
+        let TokenType::NUMBER(value) = token.token_type else {
+            unreachable!()
+        };
+
+        match minus {
+            None => Ok(value),
+            Some(_) if value <= 0x8000 => Ok(value.wrapping_neg()),
+            Some(_) => {
+                let message = format!("Word `-{value}` is out of range -32768..=65535");
+                Err(token.error(message).into())
+            }
+        }
+    }
+
     fn parse_a_instruction(&mut self) -> anyhow::Result<Node<'de>> {
-        let _at = consume_and_ensure_matches!(self.tokens, TokenType::AT)?;
-        let token =
-            consume_and_ensure_matches!(self.tokens, TokenType::IDENTIFIER | TokenType::NUMBER(_))?;
+        let _at = consume_and_ensure_matches!(self.tokens, "`@`"; TokenType::AT)?;
+        match self.tokens.peek() {
+            Some(next) if matches!(next.token_type, TokenType::EOF) || next.line != _at.line => {
+                return Err(_at.error("Expected a symbol or a number after `@`").into());
+            }
+            Some(
+                minus @ Token {
+                    token_type: TokenType::MINUS,
+                    ..
+                },
+            ) => {
+                let message = "A-instruction values cannot be negative; load the value and negate it with `-A` or `-D`";
+                return Err(minus.error(message).into());
+            }
+            _ => {}
+        }
+        let token = consume_and_ensure_matches!(
+            self.tokens,
+            "a symbol or a number";
+            TokenType::IDENTIFIER | TokenType::NUMBER(_)
+        )?;
+        let mut terms = vec![];
+        while let Some(sign) = self.tokens.next_if(|next| {
+            next.line == _at.line && matches!(next.token_type, TokenType::PLUS | TokenType::MINUS)
+        }) {
+            let operand = match self.tokens.next_if(|next| next.line == _at.line) {
+                Some(next) => next,
+                None => {
+                    let message = format!("Expected a symbol or a number after `{}`", sign.lexeme);
+                    return Err(sign.error(message).into());
+                }
+            };
+            if !matches!(
+                operand.token_type,
+                TokenType::IDENTIFIER | TokenType::NUMBER(_)
+            ) {
+                return Err(unexpected(&operand, "a symbol or a number").into());
+            }
+            terms.push((sign, operand));
+        }
+        if let TokenType::NUMBER(value @ 0x8000..) = token.token_type
+            && terms.is_empty()
+        {
+            let message = format!("A-instruction value `{value}` is out of range 0..=32767");
+            return Err(token.error(message).with_code(A_INSTRUCTION_RANGE).into());
+        }
 
-        Ok(Node::Instruction(Instruction::A { _at, token }))
+        Ok(Node::Instruction(Instruction::A { _at, token, terms }))
     }
 
     fn parse_c_instruction(&mut self) -> anyhow::Result<Node<'de>> {
@@ -156,6 +440,7 @@ where
                 if matches!(prev.token_type, TokenType::BANG) && consumed_tokens.len() == 1 {
                     let next = consume_and_ensure_matches!(
                         tokens,
+                        "`A`, `D` or `M`";
                         TokenType::A | TokenType::D | TokenType::M
                     )?;
 
@@ -167,6 +452,7 @@ where
                 if matches!(prev.token_type, TokenType::MINUS) && consumed_tokens.len() == 1 {
                     let next = consume_and_ensure_matches!(
                         tokens,
+                        "`A`, `D`, `M` or `1`";
                         TokenType::A | TokenType::D | TokenType::M | TokenType::NUMBER(1)
                     )?;
 
@@ -180,6 +466,7 @@ where
                 {
                     let next = consume_and_ensure_matches!(
                         tokens,
+                        "`A`, `D`, `M` or `1`";
                         TokenType::A | TokenType::D | TokenType::M | TokenType::NUMBER(1)
                     )?;
 
@@ -191,7 +478,11 @@ where
                 if matches!(prev.token_type, TokenType::AMPERSAND | TokenType::BAR)
                     && consumed_tokens.len() == 2
                 {
-                    let next = consume_and_ensure_matches!(tokens, TokenType::A | TokenType::M)?;
+                    let next = consume_and_ensure_matches!(
+                        tokens,
+                        "`A` or `M`";
+                        TokenType::A | TokenType::M
+                    )?;
 
                     consumed_tokens.push(next);
 
@@ -242,15 +533,19 @@ where
 
         'parsing_loop: loop {
             if peek_matches!(self.tokens, TokenType::EOF) {
-                // TODO: verify `comp` is valid
-                comp = consumed_tokens;
+                // After `;`, the computation is already taken
+                if !matches!(state, ParsingState::ConsumingJump) {
+                    comp = consumed_tokens;
+                }
 
                 break 'parsing_loop;
             }
 
             if let Some(eq) = consume_if_matches!(self.tokens, TokenType::EQUAL) {
                 // TODO: verify `dest` is valid;
-                assert!(consumed_tokens.len() == 1);
+                if consumed_tokens.len() != 1 {
+                    return Err(eq.error("Expected a single destination before `=`").into());
+                }
                 dest = consumed_tokens.pop();
                 consumed_tokens = vec![];
 
@@ -289,16 +584,133 @@ where
             }
         }
 
+        let _comp_tokens = comp;
+        let comp = check_c_instruction(&dest, &_eq, &_comp_tokens, &_sem, &jump)?;
+
         Ok(Node::Instruction(Instruction::C {
             dest,
             _eq,
             comp,
+            _comp_tokens,
             _sem,
             jump,
         }))
     }
 }
 
+/// Spelling of a computation token, by its type.
+fn comp_text(token: &Token<'_>) -> String {
+    match token.token_type {
+        TokenType::NUMBER(value) => value.to_string(),
+        TokenType::A => "A".to_string(),
+        TokenType::D => "D".to_string(),
+        TokenType::M => "M".to_string(),
+        TokenType::MINUS => "-".to_string(),
+        TokenType::PLUS => "+".to_string(),
+        TokenType::BANG => "!".to_string(),
+        TokenType::AMPERSAND => "&".to_string(),
+        TokenType::BAR => "|".to_string(),
+        _ => token.lexeme.to_string(),
+    }
+}
+
+/// The computation of a C-instruction, or an error about its first part that the CPU
+/// has no encoding for.
+fn check_c_instruction(
+    dest: &Option<Token<'_>>,
+    eq: &Option<Token<'_>>,
+    comp: &[Token<'_>],
+    sem: &Option<Token<'_>>,
+    jump: &Option<Token<'_>>,
+) -> Result<Comp, SyntaxError> {
+    if let Some(dest) = dest
+        && !matches!(
+            dest.token_type,
+            TokenType::M
+                | TokenType::D
+                | TokenType::MD
+                | TokenType::A
+                | TokenType::AM
+                | TokenType::AD
+                | TokenType::AMD
+                | TokenType::NUMBER(0)
+        )
+    {
+        let mut message = format!(
+            "`{}` is not a destination; expected {DESTINATIONS}",
+            dest.lexeme
+        );
+        let mut letters: Vec<_> = dest.lexeme.chars().collect();
+        letters.sort_by_key(|c| "AMD".find(*c));
+        let reordered: String = letters.into_iter().collect();
+        if ["MD", "AM", "AD", "AMD"].contains(&reordered.as_str()) {
+            message.push_str(&format!(" (write `{reordered}`)"));
+        }
+        return Err(dest.error(message));
+    }
+
+    let computation = match (comp.first(), comp.last()) {
+        (Some(first), _) if first.token_type.kind() == "jump" => {
+            let message = format!("Expected `;` before the jump `{}`", first.lexeme);
+            return Err(first.error(message));
+        }
+        (Some(first), Some(last)) => {
+            let text: String = comp.iter().map(comp_text).collect();
+            match Comp::from_mnemonic(&text) {
+                Some(computation) => computation,
+                None => {
+                    let mut message = format!("`{text}` is not a computation of the Hack CPU");
+                    let swapped = match text.chars().collect::<Vec<_>>()[..] {
+                        [a, op @ ('+' | '&' | '|'), b] => Some(format!("{b}{op}{a}"))
+                            .filter(|swapped| Comp::from_mnemonic(swapped).is_some()),
+                        _ => None,
+                    };
+                    match swapped {
+                        Some(swapped) => message.push_str(&format!(" (write `{swapped}`)")),
+                        None => {
+                            let comps: Vec<_> =
+                                Comp::ALL.iter().map(|comp| format!("`{comp}`")).collect();
+                            let (last, rest) = comps.split_last().unwrap();
+                            message.push_str(&format!("; expected {} or {last}", rest.join(", ")));
+                        }
+                    }
+                    let width = if first.line == last.line {
+                        last.column + last.lexeme.chars().count() - first.column
+                    } else {
+                        first.lexeme.chars().count()
+                    };
+                    return Err(SyntaxError::new(first.line, first.column, width, message));
+                }
+            }
+        }
+        _ => match (eq, sem) {
+            (Some(eq), _) => return Err(eq.error("Expected a computation after `=`")),
+            (None, Some(sem)) => return Err(sem.error("Expected a computation before `;`")),
+            (None, None) => unreachable!("A C-instruction starts with a token"),
+        },
+    };
+
+    if let Some(sem) = sem {
+        match jump {
+            Some(jump) if jump.token_type.kind() == "jump" => {}
+            Some(jump) if jump.line == sem.line && !matches!(jump.token_type, TokenType::EOF) => {
+                let message = format!("`{}` is not a jump; expected {JUMPS}", jump.lexeme);
+                return Err(jump.error(message));
+            }
+            _ => return Err(sem.error(format!("Expected a jump after `;`: {JUMPS}"))),
+        }
+    }
+
+    Ok(computation)
+}
+
+fn unexpected(token: &Token<'_>, expected: &str) -> SyntaxError {
+    match token.token_type {
+        TokenType::EOF => token.error(format!("Expected {expected} but got the end of the file")),
+        _ => token.error(format!("Expected {expected} but got `{}`", token.lexeme)),
+    }
+}
+
 impl<'de, I> Iterator for Parser<'de, I>
 where
     I: Iterator<Item = Token<'de>>,
@@ -334,7 +746,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -359,7 +774,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -385,7 +803,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -417,7 +838,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -442,7 +866,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -468,7 +895,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -501,7 +931,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -534,7 +967,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -567,7 +1003,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -601,7 +1040,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -642,7 +1084,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -683,7 +1128,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -724,7 +1172,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -765,7 +1216,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -806,7 +1260,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -847,7 +1304,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -888,7 +1348,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -929,7 +1392,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -968,7 +1434,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -994,7 +1463,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1027,7 +1499,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1061,7 +1536,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1102,7 +1580,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1143,7 +1624,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1184,7 +1668,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1225,7 +1712,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1266,7 +1756,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1307,7 +1800,10 @@ mod single_comp_tests {
         let nodes = parse_nodes(tokens);
         assert_eq!(nodes.len(), 1);
 
-        let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+        let Node::Instruction(Instruction::C {
+            _comp_tokens: comp, ..
+        }) = &nodes[0]
+        else {
             return assert!(false);
         };
 
@@ -1334,6 +1830,21 @@ mod single_comp_tests {
             }
         ));
     }
+
+    #[test]
+    fn reads_every_computation_into_its_comp() {
+        for expected in Comp::ALL {
+            let source = format!("AM={expected};JMP");
+            let tokens: Result<Vec<_>, _> = crate::scanner::Scanner::new(&source).collect();
+            let nodes = parse_nodes(tokens.unwrap());
+
+            let Node::Instruction(Instruction::C { comp, .. }) = &nodes[0] else {
+                panic!("`{source}` should be a C-instruction");
+            };
+            assert_eq!(*comp, expected);
+            assert_eq!(Comp::from_bits(comp.bits()), Some(expected));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1370,7 +1881,100 @@ mod a_tests {
                     token_type: TokenType::NUMBER(10),
                     ..
                 },
+                ..
             }),
         ));
     }
+
+    #[test]
+    fn reads_the_terms_of_an_expression() {
+        let source = "@SCREEN+32-i\n@KBD\n-1";
+        let tokens: Result<Vec<_>, _> = crate::scanner::Scanner::new(source).collect();
+        let nodes = parse_nodes(tokens.unwrap());
+
+        let Node::Instruction(Instruction::A { token, terms, .. }) = &nodes[0] else {
+            panic!("Expected an A-instruction");
+        };
+        assert_eq!(token.lexeme, "SCREEN");
+        let terms: Vec<_> = terms
+            .iter()
+            .map(|(sign, operand)| (sign.lexeme.as_ref(), operand.lexeme.as_ref()))
+            .collect();
+        assert_eq!(terms, [("+", "32"), ("-", "i")]);
+        // A term does not run on to the next line
+        assert!(
+            matches!(&nodes[1], Node::Instruction(Instruction::A { terms, .. }) if terms.is_empty())
+        );
+
+        let tokens: Result<Vec<_>, _> = crate::scanner::Scanner::new("@LOOP+\nD=M").collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        assert_eq!(
+            nodes.unwrap_err().to_string(),
+            "[line 1:6] Error: Expected a symbol or a number after `+`"
+        );
+    }
+}
+
+#[cfg(test)]
+mod data_tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> anyhow::Result<Vec<Node<'_>>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+
+        Parser::new(tokens?.into_iter()).collect()
+    }
+
+    #[test]
+    fn lays_out_words_and_fills() {
+        let nodes =
+            parse(".data\n(TABLE)\n.word 1 65535 -1\n.fill 2 -32768\n.fill 1\n.text\nD=M").unwrap();
+
+        let words: Vec<_> = nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Data { words, .. } => Some(words.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(words, [vec![1, 65535, 65535], vec![32768, 32768], vec![0]]);
+        assert!(matches!(nodes[0], Node::Section { data: true, .. }));
+        assert!(matches!(nodes[5], Node::Section { data: false, .. }));
+        assert!(matches!(nodes[6], Node::Instruction(Instruction::C { .. })));
+    }
+
+    #[test]
+    fn rejects_misplaced_data() {
+        let message = |source| parse(source).unwrap_err().to_string();
+
+        assert_eq!(
+            message(".word 1"),
+            "[line 1:1] Error: `.word` is only allowed in a `.data` section"
+        );
+        assert_eq!(
+            message(".data\nD=M"),
+            "[line 2:1] Error: Expected `.word`, `.fill`, a label or `.text` in a `.data` section"
+        );
+        assert_eq!(
+            message(".data\n.word -32769"),
+            "[line 2:8] Error: Word `-32769` is out of range -32768..=65535"
+        );
+        assert_eq!(
+            message(".data\n.fill D"),
+            "[line 2:7] Error: Expected a word count but got `D`"
+        );
+        assert_eq!(
+            message(".data\n.org 4"),
+            "[line 2:1] Error: `.org` is only allowed outside of a `.data` section"
+        );
+        assert_eq!(
+            message(".org 32768"),
+            "[line 1:6] Error: ROM address `32768` is out of range 0..=32767"
+        );
+        assert_eq!(
+            message(".org LOOP"),
+            "[line 1:6] Error: Expected a ROM address but got `LOOP`"
+        );
+    }
 }
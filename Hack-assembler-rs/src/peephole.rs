@@ -0,0 +1,243 @@
+//! `--peephole`: drops the instructions of a parsed program that change nothing, before
+//! its symbols are resolved. Generated code, such as the VM translator's, is full of
+//! them:
+//!
+//! * an `@value` whose value A already holds: no label in between, and no
+//!   C-instruction writing A;
+//! * an `@value` overwritten by the next `@value` before anything uses it;
+//! * a jump without a destination to the instruction right after it;
+//! * the instructions after an unconditional jump, up to the next label, which
+//!   nothing reaches.
+//!
+//! Values are compared as written, so `@SP` and `@0` are different values. The ROM
+//! shrinks, so a program that reaches its code by numeric addresses instead of labels
+//! breaks; one with expressions over labels, such as `@LOOP+2`, is left as it is.
+
+use crate::parser::{Instruction, Node};
+use crate::scanner::TokenType;
+
+/// Instructions removed, by reason.
+#[derive(Debug, Default, PartialEq)]
+pub struct Peephole {
+    pub reloads: usize,
+    pub overwritten: usize,
+    pub jumps_to_next: usize,
+    pub unreachable: usize,
+}
+
+impl Peephole {
+    pub fn removed(&self) -> usize {
+        self.reloads + self.overwritten + self.jumps_to_next + self.unreachable
+    }
+}
+
+/// The first expression over a label, as written, whose value depends on the layout
+/// of the ROM the pass changes.
+pub fn layout_dependency(nodes: &[Node<'_>]) -> Option<String> {
+    let labels: Vec<_> = nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Label { name, .. } => Some(name.lexeme.as_ref()),
+            _ => None,
+        })
+        .collect();
+
+    nodes.iter().find_map(|node| match node {
+        Node::Instruction(Instruction::A { token, terms, .. })
+            if !terms.is_empty()
+                && std::iter::once(token)
+                    .chain(terms.iter().map(|(_, operand)| operand))
+                    .any(|operand| labels.contains(&operand.lexeme.as_ref())) =>
+        {
+            Some(value(node).expect("An A-instruction"))
+        }
+        _ => None,
+    })
+}
+
+/// Drops the instructions that change nothing from `nodes`, until none is left.
+pub fn optimize(nodes: &mut Vec<Node<'_>>) -> Peephole {
+    let mut peephole = Peephole::default();
+    loop {
+        let keep = pass(nodes, &mut peephole);
+        if keep.iter().all(|&keep| keep) {
+            return peephole;
+        }
+
+        let mut keep = keep.into_iter();
+        nodes.retain(|_| keep.next().expect("A flag per node"));
+    }
+}
+
+/// Which of `nodes` one pass keeps.
+fn pass(nodes: &[Node<'_>], peephole: &mut Peephole) -> Vec<bool> {
+    let mut keep = vec![true; nodes.len()];
+    // The value A holds, if known
+    let mut in_a: Option<String> = None;
+    // The A-instruction run last, if no C-instruction ran since
+    let mut last_load: Option<usize> = None;
+    // A jump without a destination, run last, with its target
+    let mut last_jump: Option<(usize, String)> = None;
+    let mut labels: Vec<&str> = vec![];
+    let mut unreachable = false;
+
+    for (i, node) in nodes.iter().enumerate() {
+        let instruction = match node {
+            Node::Label { name, .. } => {
+                labels.push(name.lexeme.as_ref());
+                in_a = None;
+                unreachable = false;
+                continue;
+            }
+            Node::Section { .. } | Node::Data { .. } | Node::Org { .. } => {
+                in_a = None;
+                last_load = None;
+                last_jump = None;
+                labels.clear();
+                unreachable = false;
+                continue;
+            }
+            Node::Instruction(instruction) => instruction,
+        };
+        if unreachable {
+            keep[i] = false;
+            peephole.unreachable += 1;
+            continue;
+        }
+        if let Some((jump, target)) = last_jump.take()
+            && labels.contains(&target.as_str())
+        {
+            keep[jump] = false;
+            peephole.jumps_to_next += 1;
+        }
+        labels.clear();
+
+        match instruction {
+            Instruction::A { .. } => {
+                let value = value(node).expect("An A-instruction");
+                if in_a.as_ref() == Some(&value) {
+                    keep[i] = false;
+                    peephole.reloads += 1;
+                    continue;
+                }
+                if let Some(load) = last_load {
+                    keep[load] = false;
+                    peephole.overwritten += 1;
+                }
+                in_a = Some(value);
+                last_load = Some(i);
+            }
+            Instruction::C { dest, jump, .. } => {
+                last_load = None;
+                let writes_a = dest.as_ref().is_some_and(|dest| {
+                    matches!(
+                        dest.token_type,
+                        TokenType::A | TokenType::AM | TokenType::AD | TokenType::AMD
+                    )
+                });
+                if let Some(jump) = jump {
+                    unreachable = matches!(jump.token_type, TokenType::JMP);
+                    if dest.is_none() {
+                        last_jump = in_a.clone().map(|target| (i, target));
+                    }
+                }
+                if writes_a {
+                    in_a = None;
+                }
+            }
+        }
+    }
+
+    keep
+}
+
+/// The value of an A-instruction, as written.
+fn value(node: &Node<'_>) -> Option<String> {
+    let Node::Instruction(Instruction::A { token, terms, .. }) = node else {
+        return None;
+    };
+
+    Some(
+        terms
+            .iter()
+            .fold(token.lexeme.to_string(), |text, (sign, operand)| {
+                text + &sign.lexeme + &operand.lexeme
+            }),
+    )
+}
+
+#[cfg(test)]
+mod peephole_tests {
+    use super::*;
+    use crate::{
+        assembler::Assembler, emulator::Cpu, parser::Parser, preprocessor::Preprocessor,
+        scanner::Scanner,
+    };
+
+    fn parse(source: &str) -> Vec<Node<'_>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        nodes.unwrap()
+    }
+
+    /// The labels and A-instructions of `nodes`.
+    fn loads(nodes: &[Node<'_>]) -> Vec<String> {
+        nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Label { name, .. } => Some(format!("({})", name.lexeme)),
+                _ => value(node).map(|value| format!("@{value}")),
+            })
+            .collect()
+    }
+
+    /// RAM[0..3] once the program of `nodes` halts, with SP at 256.
+    fn run(mut nodes: Vec<Node<'_>>) -> Vec<u16> {
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+        let mut cpu = Cpu::new(Assembler::new(nodes).assemble().unwrap());
+        cpu.preload(&[(0, 256)]);
+        cpu.run(1000);
+
+        cpu.ram[..3].to_vec()
+    }
+
+    #[test]
+    fn drops_reloads_overwritten_loads_and_jumps_to_the_next_instruction() {
+        // A push of 7 and a pop to R1 as the VM translator writes them, a jump over
+        // nothing, then a halt loop with a stray word after it
+        let source = "@7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\n@R1\nM=D\n\
+                      @NEXT\n0;JMP\n(NEXT)\n@3\n@2\nD=A\n@R2\nM=D\n@R2\nM=M+1\n\
+                      (END)\n@END\n0;JMP\nD=0";
+        let mut nodes = parse(source);
+        let before = run(parse(source));
+
+        let peephole = optimize(&mut nodes);
+        assert_eq!(
+            peephole,
+            Peephole {
+                reloads: 2,
+                overwritten: 2,
+                jumps_to_next: 1,
+                unreachable: 1,
+            }
+        );
+        assert_eq!(peephole.removed(), 6);
+        // `@NEXT` goes once its jump does, as `@3` overwrites it
+        assert_eq!(
+            loads(&nodes),
+            [
+                "@7", "@SP", "@SP", "@R1", "(NEXT)", "@2", "@R2", "(END)", "@END"
+            ]
+        );
+        assert_eq!(before, [256, 7, 3]);
+        assert_eq!(run(nodes), before);
+    }
+
+    #[test]
+    fn finds_expressions_over_labels() {
+        let nodes = parse("@SCREEN+32\n(LOOP)\n@LOOP+2\n0;JMP");
+        assert_eq!(layout_dependency(&nodes), Some("LOOP+2".to_string()));
+        assert_eq!(layout_dependency(&parse("@SCREEN+32\n(LOOP)")), None);
+    }
+}
@@ -1,187 +1,1242 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    iter::Peekable,
+    vec::IntoIter,
+};
 
 use crate::{
-    parser::{Address, Node},
+    diagnostic::{A_INSTRUCTION_RANGE, SyntaxError},
+    parser::{Address, Instruction, Node},
     scanner::{Token, TokenType},
+    symbol_file::SymbolFile,
 };
 
-#[allow(unused)]
-#[derive(Debug)]
-pub(crate) struct InitialState;
+/// Keys borrow the source, like the lexemes they come from, but for the labels a
+/// macro expansion renames.
+pub type SymbolTable<'de> = HashMap<Cow<'de, str>, Address>;
 
-#[derive(Debug)]
-pub(crate) struct StaticSymbolInited;
+const FIRST_VARIABLE_ADDRESS: Address = 16;
+/// The largest value an A-instruction holds, in 15 bits.
+const MAX_A_VALUE: Address = 0x7FFF;
 
-#[derive(Debug)]
-pub(crate) struct SymbolExtractedState;
+/// A node, and an operand of its A-instruction: 0 for the first one, then those of
+/// its terms.
+type OperandIndex = (usize, usize);
 
-#[allow(unused)]
-#[derive(Debug)]
-pub(crate) struct SymbolReplacedState;
+/// A label that cannot name its address, as another symbol has the name already. The
+/// `line` and `column` are those of its name.
+#[derive(Debug, PartialEq)]
+pub enum LabelConflict {
+    Duplicate {
+        name: String,
+        first_line: usize,
+        line: usize,
+        column: usize,
+    },
+    Predefined {
+        name: String,
+        line: usize,
+        column: usize,
+        address: Address,
+    },
+    Imported {
+        name: String,
+        line: usize,
+        column: usize,
+        address: Address,
+    },
+}
+
+impl LabelConflict {
+    /// The conflict as a diagnostic pointing at the name of the label.
+    pub fn error(&self) -> SyntaxError {
+        let (name, line, column, message) = match self {
+            LabelConflict::Duplicate {
+                name,
+                first_line,
+                line,
+                column,
+            } => (
+                name,
+                line,
+                column,
+                format!("Label ({name}) is already defined at line {first_line}"),
+            ),
+            LabelConflict::Predefined {
+                name,
+                line,
+                column,
+                address,
+            } => (
+                name,
+                line,
+                column,
+                format!(
+                    "Label ({name}) would redefine the predefined symbol {name}, RAM[{address}]"
+                ),
+            ),
+            LabelConflict::Imported {
+                name,
+                line,
+                column,
+                address,
+            } => (
+                name,
+                line,
+                column,
+                format!("Label ({name}) would redefine the imported symbol {name} = {address}"),
+            ),
+        };
+
+        SyntaxError::new(*line, *column, name.chars().count(), message)
+    }
+}
+
+impl Display for LabelConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error())
+    }
+}
+
+/// Every label conflict of a program, in source order.
+#[derive(Debug, PartialEq)]
+pub struct LabelConflicts(pub Vec<LabelConflict>);
+
+impl LabelConflicts {
+    /// Each conflict with the line it is on, as [`SyntaxError::render`] shows it.
+    pub fn render(&self, source: &str) -> String {
+        let conflicts: Vec<_> = self.0.iter().map(|c| c.error().render(source)).collect();
+        conflicts.join("\n")
+    }
+}
+
+impl Display for LabelConflicts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let conflicts: Vec<_> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", conflicts.join("\n"))
+    }
+}
 
-type SymbolTable<'a> = HashMap<Cow<'a, str>, Address>;
+impl std::error::Error for LabelConflicts {}
 
 #[derive(Debug)]
-pub(crate) struct Preprocessor<'de, I, State> {
-    nodes: I,
+pub struct Preprocessor<'de> {
     symbol_table: SymbolTable<'de>,
     next_free_memory_address: Address,
-    _marker: std::marker::PhantomData<State>,
+    /// Words of the `.data` sections, laid out from RAM[16] on.
+    data: Vec<Address>,
+    /// Labels with the line defining them, in source order.
+    labels: Vec<(Cow<'de, str>, usize)>,
+    /// Variables with the line of their first use, in order of allocation.
+    variables: Vec<(Cow<'de, str>, usize)>,
+    /// A-instructions naming each symbol.
+    references: HashMap<Cow<'de, str>, usize>,
+    /// ROM words [`Self::resolve`] has laid out.
+    rom: usize,
+    /// Symbols defined by [`Self::import`].
+    imported: HashSet<Cow<'de, str>>,
+    /// First RAM address past the imported variables.
+    imported_variables_end: Address,
 }
 
-impl<'de, I, S> Preprocessor<'de, I, S> {
-    pub fn symbol_table(&self) -> &SymbolTable<'de> {
-        return &self.symbol_table;
+impl Default for Preprocessor<'_> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<'de, I> Preprocessor<'de, I, InitialState>
-where
-    I: IntoIterator<Item = Node<'de>>,
-{
-    pub fn init_static_symbols(nodes: I) -> Preprocessor<'de, I, StaticSymbolInited> {
-        let virtual_registers = (0..=15).map(|r| (Cow::Owned(format!("R{r}")), r));
+impl<'de> Preprocessor<'de> {
+    /// A symbol table with the predefined symbols only.
+    pub fn new() -> Self {
+        const VIRTUAL_REGISTERS: [&str; 16] = [
+            "R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12", "R13",
+            "R14", "R15",
+        ];
 
-        let predefined_pointers = [
-            (Cow::Borrowed("SP"), 0),
-            (Cow::Borrowed("LCL"), 1),
-            (Cow::Borrowed("ARG"), 2),
-            (Cow::Borrowed("THIS"), 3),
-            (Cow::Borrowed("THAT"), 4),
-        ]
-        .into_iter();
+        let virtual_registers = VIRTUAL_REGISTERS.into_iter().zip(0..);
 
-        let i_o_pointers = [
-            (Cow::Borrowed("SCREEN"), 16384),
-            (Cow::Borrowed("KBD"), 24576),
-        ]
-        .into_iter();
+        let predefined_pointers =
+            [("SP", 0), ("LCL", 1), ("ARG", 2), ("THIS", 3), ("THAT", 4)].into_iter();
+
+        let i_o_pointers = [("SCREEN", 16384), ("KBD", 24576)].into_iter();
 
         let symbol_table = virtual_registers
             .chain(predefined_pointers)
             .chain(i_o_pointers)
+            .map(|(symbol, address)| (Cow::Borrowed(symbol), address))
             .collect();
 
         Preprocessor {
-            nodes,
             symbol_table,
-            next_free_memory_address: 16,
-            _marker: std::marker::PhantomData,
+            next_free_memory_address: FIRST_VARIABLE_ADDRESS,
+            data: vec![],
+            labels: vec![],
+            variables: vec![],
+            references: HashMap::new(),
+            rom: 0,
+            imported: HashSet::new(),
+            imported_variables_end: FIRST_VARIABLE_ADDRESS,
+        }
+    }
+
+    /// Defines the symbols of another program before this one is preprocessed. Its
+    /// variables then come after the imported ones. A symbol already defined, such as
+    /// a predefined one, has to keep its address.
+    pub fn import(&mut self, symbols: &SymbolFile) -> anyhow::Result<()> {
+        let imported = symbols.labels.iter().chain(symbols.variables.iter());
+        for (name, &address) in imported {
+            match self.symbol_table.get(name.as_str()) {
+                Some(&defined) if defined != address => anyhow::bail!(
+                    "Imported symbol {name} = {address} is already defined as {defined}"
+                ),
+                Some(_) => {}
+                None => {
+                    self.symbol_table.insert(Cow::Owned(name.clone()), address);
+                    self.imported.insert(Cow::Owned(name.clone()));
+                }
+            }
+        }
+        for &address in symbols.variables.values() {
+            self.imported_variables_end =
+                self.imported_variables_end.max(address.saturating_add(1));
         }
+
+        Ok(())
+    }
+
+    /// The labels and variables the program defines, without the imported ones.
+    pub fn export(&self) -> SymbolFile {
+        let addresses = |symbols: &[(Cow<'de, str>, usize)]| {
+            symbols
+                .iter()
+                .map(|(name, _)| (name.to_string(), self.symbol_table[name]))
+                .collect()
+        };
+
+        SymbolFile {
+            labels: addresses(&self.labels),
+            variables: addresses(&self.variables),
+        }
+    }
+
+    pub fn symbol_table(&self) -> &SymbolTable<'de> {
+        return &self.symbol_table;
+    }
+
+    /// Variables allocated so far, after the data words.
+    pub fn variable_count(&self) -> usize {
+        ((self.next_free_memory_address - FIRST_VARIABLE_ADDRESS) as usize)
+            .saturating_sub(self.data.len())
+    }
+
+    /// Words of the `.data` sections.
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Words of the `.data` sections, from RAM[16] on.
+    pub fn data(&self) -> &[Address] {
+        &self.data
+    }
+
+    pub fn labels(&self) -> &[(Cow<'de, str>, usize)] {
+        &self.labels
+    }
+
+    pub fn variables(&self) -> &[(Cow<'de, str>, usize)] {
+        &self.variables
+    }
+
+    /// A-instructions naming `symbol`.
+    pub fn references(&self, symbol: &str) -> usize {
+        self.references.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// RAM contents the program expects at start, from RAM[0] to its last data word;
+    /// empty without `.data` sections.
+    pub fn ram_image(&self) -> Vec<Address> {
+        if self.data.is_empty() {
+            return vec![];
+        }
+
+        let mut image = vec![0; FIRST_VARIABLE_ADDRESS as usize];
+        image.extend(self.data.iter());
+
+        image
+    }
+
+    /// Drops the labels and turns every symbolic A-instruction into a numeric one,
+    /// in place and in a single pass over `nodes`.
+    ///
+    /// A symbol not known yet when first seen may still be a label defined further
+    /// down, so its uses are resolved once the pass is over: to that label, or else
+    /// to a fresh variable, in order of first use.
+    ///
+    /// Data sections are dropped too, their words collected for [`Self::ram_image`]:
+    /// a label in one names the RAM address of the word that follows it, and the
+    /// variables come after the last data word.
+    ///
+    /// A label defined twice, or named like a predefined symbol, is an error: all of
+    /// them are reported together, as [`LabelConflicts`].
+    ///
+    /// `.org` nodes stay, with the zero words padding the ROM up to their address; an
+    /// address below the instructions already laid out is an error.
+    ///
+    /// Expressions such as `@SCREEN+32` are added up last, once every symbol has its
+    /// address; a sum out of 0..=32767 is an error, as is a symbol alone whose label or
+    /// variable is past 32767.
+    pub fn preprocess(&mut self, nodes: &mut Vec<Node<'de>>) -> anyhow::Result<()> {
+        let mut unresolved: Vec<(Cow<'de, str>, Vec<OperandIndex>)> = vec![];
+        let mut unresolved_index: HashMap<Cow<'de, str>, usize> = HashMap::new();
+        let mut expressions = vec![];
+        let mut conflicts = vec![];
+
+        let mut len = 0;
+        let mut rom = 0;
+        let mut in_data = false;
+        for i in 0..nodes.len() {
+            match &mut nodes[i] {
+                Node::Label { name, .. } => {
+                    self.define_label(name, rom, in_data, &mut conflicts);
+
+                    continue;
+                }
+                Node::Section { data, .. } => {
+                    in_data = *data;
+
+                    continue;
+                }
+                Node::Data { words, .. } => {
+                    self.data.append(words);
+
+                    continue;
+                }
+                Node::Org {
+                    address, padding, ..
+                } => {
+                    *padding = org_padding(address, rom)?;
+                    rom += *padding as usize;
+                }
+                Node::Instruction(Instruction::A { token, terms, .. }) => {
+                    rom += 1;
+                    let alone = terms.is_empty();
+                    if !alone {
+                        expressions.push(len);
+                    }
+                    let operands =
+                        std::iter::once(token).chain(terms.iter_mut().map(|(_, operand)| operand));
+                    for (k, operand) in operands.enumerate() {
+                        if !matches!(operand.token_type, TokenType::IDENTIFIER) {
+                            continue;
+                        }
+                        let symbol = &operand.lexeme;
+                        *self.references.entry(symbol.clone()).or_default() += 1;
+
+                        match self.symbol_table.get(symbol.as_ref()) {
+                            Some(&address) => resolve_operand(operand, address, alone)?,
+                            None => {
+                                let index =
+                                    *unresolved_index.entry(symbol.clone()).or_insert_with(|| {
+                                        unresolved.push((symbol.clone(), vec![]));
+                                        unresolved.len() - 1
+                                    });
+                                unresolved[index].1.push((len, k));
+                            }
+                        }
+                    }
+                }
+                Node::Instruction(_) => rom += 1,
+            }
+
+            nodes.swap(len, i);
+            len += 1;
+        }
+        nodes.truncate(len);
+        if !conflicts.is_empty() {
+            return Err(LabelConflicts(conflicts).into());
+        }
+        self.place_variables_after_data();
+
+        for (symbol, uses) in unresolved {
+            let address = match self.symbol_table.get(symbol.as_ref()) {
+                Some(&address) => address,
+                None => {
+                    let address = self.next_free_memory_address;
+                    self.symbol_table.insert(symbol.clone(), address);
+                    let (i, k) = uses[0];
+                    if let Some(operand) = operand(&mut nodes[i], k) {
+                        self.variables.push((symbol, operand.line));
+                    }
+                    self.next_free_memory_address = address.saturating_add(1);
+
+                    address
+                }
+            };
+
+            for (i, k) in uses {
+                let alone = k == 0
+                    && matches!(&nodes[i], Node::Instruction(Instruction::A { terms, .. }) if terms.is_empty());
+                if let Some(operand) = operand(&mut nodes[i], k) {
+                    resolve_operand(operand, address, alone)?;
+                }
+            }
+        }
+
+        for i in expressions {
+            if let Node::Instruction(Instruction::A { token, terms, .. }) = &mut nodes[i] {
+                add_up(token, terms)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// First pass of a streamed assembly, over nodes parsed one at a time: defines the
+    /// labels and collects the `.data` words, keeping nothing else of the nodes.
+    /// [`Self::resolve`] then takes them again, from a second parse.
+    pub fn define_labels(
+        &mut self,
+        nodes: impl Iterator<Item = anyhow::Result<Node<'de>>>,
+    ) -> anyhow::Result<()> {
+        let mut conflicts = vec![];
+        let mut rom = 0;
+        let mut in_data = false;
+        for node in nodes {
+            match node? {
+                Node::Label { name, .. } => self.define_label(&name, rom, in_data, &mut conflicts),
+                Node::Section { data, .. } => in_data = data,
+                Node::Data { mut words, .. } => self.data.append(&mut words),
+                Node::Org { address, .. } => rom += org_padding(&address, rom)? as usize,
+                Node::Instruction(_) => rom += 1,
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(LabelConflicts(conflicts).into());
+        }
+        self.place_variables_after_data();
+
+        Ok(())
+    }
+
+    /// Second pass of a streamed assembly: `node` with its symbols turned into numbers,
+    /// once [`Self::define_labels`] has defined every label. A symbol that names no
+    /// label becomes a variable at its first use, the order [`Self::preprocess`] gives
+    /// them too. Labels, sections and data words give `None`.
+    pub fn resolve(&mut self, mut node: Node<'de>) -> anyhow::Result<Option<Node<'de>>> {
+        match &mut node {
+            Node::Label { .. } | Node::Section { .. } | Node::Data { .. } => return Ok(None),
+            Node::Org {
+                address, padding, ..
+            } => {
+                *padding = org_padding(address, self.rom)?;
+                self.rom += *padding as usize;
+            }
+            Node::Instruction(Instruction::A { token, terms, .. }) => {
+                self.rom += 1;
+                let alone = terms.is_empty();
+                let operands = std::iter::once(&mut *token)
+                    .chain(terms.iter_mut().map(|(_, operand)| operand));
+                for operand in operands {
+                    if !matches!(operand.token_type, TokenType::IDENTIFIER) {
+                        continue;
+                    }
+                    let symbol = &operand.lexeme;
+                    *self.references.entry(symbol.clone()).or_default() += 1;
+
+                    let address = match self.symbol_table.get(symbol.as_ref()) {
+                        Some(&address) => address,
+                        None => {
+                            let address = self.next_free_memory_address;
+                            self.symbol_table.insert(symbol.clone(), address);
+                            self.variables.push((symbol.clone(), operand.line));
+                            self.next_free_memory_address = address.saturating_add(1);
+
+                            address
+                        }
+                    };
+                    resolve_operand(operand, address, alone)?;
+                }
+                if !terms.is_empty() {
+                    add_up(token, terms)?;
+                }
+            }
+            Node::Instruction(_) => self.rom += 1,
+        }
+
+        Ok(Some(node))
+    }
+
+    /// Defines the label `name` at the next ROM address, or at the next data word in a
+    /// `.data` section, unless it conflicts with a symbol defined before.
+    fn define_label(
+        &mut self,
+        name: &Token<'de>,
+        rom: usize,
+        in_data: bool,
+        conflicts: &mut Vec<LabelConflict>,
+    ) {
+        let address = if in_data {
+            FIRST_VARIABLE_ADDRESS as usize + self.data.len()
+        } else {
+            rom
+        };
+        let first = self.labels.iter().find(|(label, _)| *label == name.lexeme);
+        match (first, self.symbol_table.get(name.lexeme.as_ref())) {
+            (Some(&(_, first_line)), _) => conflicts.push(LabelConflict::Duplicate {
+                name: name.lexeme.to_string(),
+                first_line,
+                line: name.line,
+                column: name.column,
+            }),
+            (None, Some(&address)) if self.imported.contains(&name.lexeme) => {
+                conflicts.push(LabelConflict::Imported {
+                    name: name.lexeme.to_string(),
+                    line: name.line,
+                    column: name.column,
+                    address,
+                })
+            }
+            (None, Some(&address)) => conflicts.push(LabelConflict::Predefined {
+                name: name.lexeme.to_string(),
+                line: name.line,
+                column: name.column,
+                address,
+            }),
+            (None, None) => {
+                let address = Address::try_from(address).unwrap_or(Address::MAX);
+                self.symbol_table.insert(name.lexeme.clone(), address);
+                self.labels.push((name.lexeme.clone(), name.line));
+            }
+        }
+    }
+
+    /// Variables are allocated after the last data word, and after the imported
+    /// variables.
+    fn place_variables_after_data(&mut self) {
+        self.next_free_memory_address = FIRST_VARIABLE_ADDRESS
+            .saturating_add(self.data.len().try_into().unwrap_or(Address::MAX))
+            .max(self.imported_variables_end);
     }
 }
 
-impl<'de, I> Preprocessor<'de, I, StaticSymbolInited>
-where
-    I: IntoIterator<Item = Node<'de>> + FromIterator<Node<'de>>,
-{
-    pub fn extract_source_symbols(self) -> Preprocessor<'de, I, SymbolExtractedState> {
-        let nodes = self.nodes;
-        let mut symbol_table = self.symbol_table;
-        let mut next_free_memory_address = self.next_free_memory_address;
+/// Zero words from `rom` up to the address of `.org <address>`; an address below
+/// `rom` is an error.
+fn org_padding(address: &Token<'_>, rom: usize) -> anyhow::Result<Address> {
+    let TokenType::NUMBER(target) = address.token_type else {
+        unreachable!("`.org` takes a number")
+    };
+    if (target as usize) < rom {
+        let message =
+            format!("`.org {target}` would overlap the {rom} ROM words laid out before it");
+        return Err(address.error(message).into());
+    }
+
+    Ok(target - rom as Address)
+}
 
-        let nodes = Preprocessor::extract_label_symbols(nodes, &mut symbol_table);
-        let nodes = Preprocessor::extract_variable_symbols(
-            nodes,
-            &mut symbol_table,
-            &mut next_free_memory_address,
+/// Turns the symbol `operand` into its `address`. A symbol `alone` in its A-instruction
+/// has to fit in 15 bits, as a label past the ROM or a variable past 32767 does not;
+/// one in an expression only has to add up to a value that does.
+fn resolve_operand(operand: &mut Token<'_>, address: Address, alone: bool) -> anyhow::Result<()> {
+    if alone && address > MAX_A_VALUE {
+        let message = format!(
+            "A-instruction value `{}` = {address} is out of range 0..={MAX_A_VALUE}",
+            operand.lexeme
         );
+        return Err(operand.error(message).with_code(A_INSTRUCTION_RANGE).into());
+    }
+    operand.token_type = TokenType::NUMBER(address);
 
-        Preprocessor {
-            nodes,
-            symbol_table,
-            next_free_memory_address,
-            _marker: std::marker::PhantomData,
+    Ok(())
+}
+
+/// Adds the numbers of `token` and `terms` up into `token`, clearing `terms`; a sum
+/// out of 0..=32767 is an error.
+fn add_up(token: &mut Token<'_>, terms: &mut Vec<(Token<'_>, Token<'_>)>) -> anyhow::Result<()> {
+    let value = |operand: &Token<'_>| match operand.token_type {
+        TokenType::NUMBER(value) => i32::from(value),
+        _ => unreachable!("every symbol is resolved by now"),
+    };
+    let mut sum = value(token);
+    for (sign, operand) in terms.iter() {
+        match sign.token_type {
+            TokenType::MINUS => sum -= value(operand),
+            _ => sum += value(operand),
         }
     }
 
-    fn extract_label_symbols(nodes: I, symbol_table: &mut SymbolTable<'de>) -> I {
-        nodes
-            .into_iter()
-            .fold(vec![], |mut nodes, node| {
-                match node {
-                    Node::Label { name, .. } => {
-                        let len = nodes.len();
+    if !(0..0x8000).contains(&sum) {
+        let last = &terms[terms.len() - 1].1;
+        let width = last.column + last.lexeme.chars().count() - token.column;
+        let text = terms
+            .iter()
+            .fold(token.lexeme.to_string(), |text, (sign, operand)| {
+                text + &sign.lexeme + &operand.lexeme
+            });
+        let message = format!("A-instruction value `{text}` = {sum} is out of range 0..=32767");
+        let error = SyntaxError::new(token.line, token.column, width, message);
+        return Err(error.with_code(A_INSTRUCTION_RANGE).into());
+    }
+    token.token_type = TokenType::NUMBER(sum as Address);
+    terms.clear();
+
+    Ok(())
+}
 
-                        symbol_table.insert(name.lexeme.clone(), len as Address);
+/// Operand `k` of the A-instruction `node`, as in [`OperandIndex`].
+fn operand<'a, 'de>(node: &'a mut Node<'de>, k: usize) -> Option<&'a mut Token<'de>> {
+    match node {
+        Node::Instruction(Instruction::A { token, .. }) if k == 0 => Some(token),
+        Node::Instruction(Instruction::A { terms, .. }) => {
+            terms.get_mut(k - 1).map(|(_, operand)| operand)
+        }
+        _ => None,
+    }
+}
+
+/// Directives of the macro pass, scanned as identifiers and gone before parsing.
+pub const MACRO_DIRECTIVES: [&str; 3] = [".define", ".macro", ".endmacro"];
+
+/// Expansions within expansions before a macro is taken to expand itself forever.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug)]
+struct Macro<'de> {
+    params: Vec<Cow<'de, str>>,
+    body: Vec<Token<'de>>,
+    /// Labels the body defines, renamed in every expansion.
+    labels: Vec<Cow<'de, str>>,
+}
+
+#[derive(Debug, Default)]
+struct Expander<'de> {
+    defines: HashMap<Cow<'de, str>, Token<'de>>,
+    macros: HashMap<Cow<'de, str>, Macro<'de>>,
+    expansions: usize,
+}
+
+/// Replaces the constants of `.define NAME value` lines and expands the macros of
+/// `.macro NAME params ... .endmacro` blocks, before `tokens` are parsed.
+///
+/// A macro is used by its name, followed by its arguments on the same line. Each
+/// expansion renames the labels its body defines to `LABEL$NAME.n`, so that a macro
+/// with a loop can be used more than once. Expanded tokens keep the position of the
+/// body they come from.
+pub fn expand_macros<'de>(tokens: Vec<Token<'de>>) -> anyhow::Result<Vec<Token<'de>>> {
+    let has_directives = tokens.iter().any(|token| {
+        matches!(token.token_type, TokenType::IDENTIFIER)
+            && MACRO_DIRECTIVES.contains(&token.lexeme.as_ref())
+    });
+    if !has_directives {
+        return Ok(tokens);
+    }
+
+    Expander::default().expand(tokens, 0)
+}
+
+/// The next token when it is on `line`.
+fn next_on_line<'de>(
+    tokens: &mut Peekable<IntoIter<Token<'de>>>,
+    line: usize,
+) -> Option<Token<'de>> {
+    tokens.next_if(|token| token.line == line && !matches!(token.token_type, TokenType::EOF))
+}
+
+impl<'de> Expander<'de> {
+    fn expand(&mut self, tokens: Vec<Token<'de>>, depth: usize) -> anyhow::Result<Vec<Token<'de>>> {
+        let mut output: Vec<Token<'de>> = Vec::with_capacity(tokens.len());
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            if !matches!(token.token_type, TokenType::IDENTIFIER) {
+                output.push(token);
+                continue;
+            }
+
+            match token.lexeme.as_ref() {
+                ".define" => self.define(token, &mut tokens)?,
+                ".macro" => self.define_macro(token, &mut tokens)?,
+                ".endmacro" => return Err(token.error("`.endmacro` without a `.macro`").into()),
+                name if self.defines.contains_key(name) => {
+                    let value = &self.defines[name];
+                    output.push(Token {
+                        token_type: value.token_type.clone(),
+                        lexeme: value.lexeme.clone(),
+                        ..token
+                    });
+                }
+                name if self.macros.contains_key(name) => {
+                    let after_symbol_syntax = output.last().is_some_and(|prev| {
+                        prev.line == token.line
+                            && matches!(prev.token_type, TokenType::AT | TokenType::LEFT_PAREN)
+                    });
+                    if after_symbol_syntax {
+                        let message = format!("`{name}` is a macro, not a symbol");
+                        return Err(token.error(message).into());
                     }
-                    Node::Instruction(_) => {
-                        nodes.push(node);
+                    if depth == MAX_EXPANSION_DEPTH {
+                        let message = format!(
+                            "Macro `{name}` is expanded more than {MAX_EXPANSION_DEPTH} levels deep; does it use itself?"
+                        );
+                        return Err(token.error(message).into());
                     }
-                };
 
-                nodes
-            })
-            .into_iter()
-            .collect()
+                    let mut args = vec![];
+                    while let Some(arg) = next_on_line(&mut tokens, token.line) {
+                        args.push(arg);
+                    }
+                    let expansion = self.instantiate(&token, &args)?;
+                    output.extend(self.expand(expansion, depth + 1)?);
+                }
+                _ => output.push(token),
+            }
+        }
+
+        Ok(output)
     }
 
-    fn extract_variable_symbols(
-        nodes: I,
-        symbol_table: &mut SymbolTable<'de>,
-        next_free_memory_address: &mut Address,
-    ) -> I {
-        nodes
-            .into_iter()
-            .map(|node| {
-                match &node {
-                    Node::Instruction(instruction) => match instruction {
-                        crate::parser::Instruction::A { token, .. }
-                            if matches!(token.token_type, TokenType::IDENTIFIER) =>
-                        {
-                            if !symbol_table.contains_key(token.lexeme.as_ref()) {
-                                symbol_table
-                                    .insert(token.lexeme.clone(), *next_free_memory_address);
-                                *next_free_memory_address += 1;
-                            }
-                        }
-                        _ => {}
+    /// `.define NAME value`, the value being a single token.
+    fn define(
+        &mut self,
+        directive: Token<'de>,
+        tokens: &mut Peekable<IntoIter<Token<'de>>>,
+    ) -> anyhow::Result<()> {
+        let name = self.new_name(&directive, tokens)?;
+        let Some(mut value) = next_on_line(tokens, directive.line) else {
+            let message = format!("Expected a value after `.define {}`", name.lexeme);
+            return Err(name.error(message).into());
+        };
+        if let Some(extra) = next_on_line(tokens, directive.line) {
+            return Err(extra
+                .error("Expected a single value after `.define NAME`")
+                .into());
+        }
+        if let Some(defined) = self.defines.get(value.lexeme.as_ref()) {
+            value = defined.clone();
+        }
+        self.defines.insert(name.lexeme, value);
+
+        Ok(())
+    }
+
+    /// `.macro NAME params` up to the next `.endmacro`.
+    fn define_macro(
+        &mut self,
+        directive: Token<'de>,
+        tokens: &mut Peekable<IntoIter<Token<'de>>>,
+    ) -> anyhow::Result<()> {
+        let name = self.new_name(&directive, tokens)?;
+        let mut params = vec![];
+        while let Some(param) = next_on_line(tokens, directive.line) {
+            if !matches!(param.token_type, TokenType::IDENTIFIER) {
+                return Err(param.error("Expected a parameter name").into());
+            }
+            params.push(param.lexeme);
+        }
+
+        let mut body = vec![];
+        loop {
+            let token = match tokens.next() {
+                Some(token) if !matches!(token.token_type, TokenType::EOF) => token,
+                _ => {
+                    let message = format!("`.macro {}` has no `.endmacro`", name.lexeme);
+                    return Err(directive.error(message).into());
+                }
+            };
+            match token.lexeme.as_ref() {
+                ".endmacro" => break,
+                ".macro" | ".define" => {
+                    let message = format!("`{}` cannot be used inside a macro", token.lexeme);
+                    return Err(token.error(message).into());
+                }
+                _ => body.push(token),
+            }
+        }
+
+        let labels = body
+            .windows(3)
+            .filter_map(|window| match window {
+                [
+                    Token {
+                        token_type: TokenType::LEFT_PAREN,
+                        ..
+                    },
+                    label @ Token {
+                        token_type: TokenType::IDENTIFIER,
+                        ..
                     },
-                    Node::Label { .. } => unreachable!(),
+                    Token {
+                        token_type: TokenType::RIGHT_PAREN,
+                        ..
+                    },
+                ] => Some(label.lexeme.clone()),
+                _ => None,
+            })
+            .collect();
+        self.macros.insert(
+            name.lexeme,
+            Macro {
+                params,
+                body,
+                labels,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// The name a directive introduces, on its line and not taken yet.
+    fn new_name(
+        &self,
+        directive: &Token<'de>,
+        tokens: &mut Peekable<IntoIter<Token<'de>>>,
+    ) -> anyhow::Result<Token<'de>> {
+        let name = match next_on_line(tokens, directive.line) {
+            Some(name)
+                if matches!(name.token_type, TokenType::IDENTIFIER)
+                    && !MACRO_DIRECTIVES.contains(&name.lexeme.as_ref()) =>
+            {
+                name
+            }
+            _ => {
+                let message = format!("Expected a name after `{}`", directive.lexeme);
+                return Err(directive.error(message).into());
+            }
+        };
+        let name_str = name.lexeme.as_ref();
+        if self.defines.contains_key(name_str) || self.macros.contains_key(name_str) {
+            let message = format!("`{name_str}` is already defined");
+            return Err(name.error(message).into());
+        }
+
+        Ok(name)
+    }
+
+    /// The body of the macro `name` with `args` for its parameters and its labels
+    /// renamed.
+    fn instantiate(
+        &mut self,
+        name: &Token<'de>,
+        args: &[Token<'de>],
+    ) -> anyhow::Result<Vec<Token<'de>>> {
+        let r#macro = &self.macros[name.lexeme.as_ref()];
+        if args.len() != r#macro.params.len() {
+            let count = r#macro.params.len();
+            let message = format!(
+                "Macro `{}` takes {count} argument{} but got {}",
+                name.lexeme,
+                if count == 1 { "" } else { "s" },
+                args.len()
+            );
+            return Err(name.error(message).into());
+        }
+        self.expansions += 1;
+
+        let expansion = r#macro
+            .body
+            .iter()
+            .map(|token| {
+                if !matches!(token.token_type, TokenType::IDENTIFIER) {
+                    return token.clone();
+                }
+                let lexeme = &token.lexeme;
+                if let Some(i) = r#macro.params.iter().position(|param| param == lexeme) {
+                    return Token {
+                        token_type: args[i].token_type.clone(),
+                        lexeme: args[i].lexeme.clone(),
+                        ..token.clone()
+                    };
+                }
+                if r#macro.labels.contains(lexeme) {
+                    return Token {
+                        lexeme: Cow::Owned(format!("{lexeme}${}.{}", name.lexeme, self.expansions)),
+                        ..token.clone()
+                    };
                 }
 
-                node
+                token.clone()
             })
-            .collect()
+            .collect();
+
+        Ok(expansion)
     }
 }
 
-impl<'de, I> Preprocessor<'de, I, SymbolExtractedState>
-where
-    I: IntoIterator<Item = Node<'de>> + FromIterator<Node<'de>>,
-{
-    pub fn replace_source_symbols<U>(self) -> U
-    where
-        U: IntoIterator<Item = Node<'de>> + FromIterator<Node<'de>>,
-    {
-        let nodes = self.nodes;
-        let symbol_table = self.symbol_table;
+#[cfg(test)]
+mod preprocessor_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+    use std::time::Instant;
 
-        nodes
-            .into_iter()
-            .map(|mut node| match &mut node {
-                Node::Instruction(instruction) => match instruction {
-                    crate::parser::Instruction::A { token, .. }
-                        if matches!(token.token_type, TokenType::IDENTIFIER) =>
-                    {
-                        assert!(symbol_table.contains_key(&token.lexeme));
-
-                        let &symbol_table_value = symbol_table
-                            .get(&token.lexeme)
-                            .expect("Symbols should have been extracted in a previous step");
-
-                        *token = Token::new(
-                            TokenType::NUMBER(symbol_table_value),
-                            Cow::Owned(format!("{symbol_table_value}")),
-                            token.line,
-                        );
+    fn parse(source: &str) -> Vec<Node<'_>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
 
-                        node
-                    }
-                    _ => node,
+        nodes.unwrap()
+    }
+
+    fn addresses(nodes: &[Node<'_>]) -> Vec<Option<Address>> {
+        nodes
+            .iter()
+            .map(|node| match node {
+                Node::Instruction(Instruction::A { token, .. }) => match token.token_type {
+                    TokenType::NUMBER(address) => Some(address),
+                    _ => unreachable!(),
                 },
-                Node::Label { .. } => unreachable!(),
+                _ => None,
             })
             .collect()
     }
+
+    #[test]
+    fn resolves_labels_before_variables() {
+        let source = "
+            @i
+            @END
+            (LOOP)
+            D=M
+            @LOOP
+            @sum
+            @i
+            (END)
+            @END
+            @R15
+            @SCREEN
+            @42
+        ";
+        let mut nodes = parse(source);
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        assert_eq!(
+            addresses(&nodes),
+            [
+                Some(16),
+                Some(6),
+                None,
+                Some(2),
+                Some(17),
+                Some(16),
+                Some(6),
+                Some(15),
+                Some(16384),
+                Some(42)
+            ]
+        );
+        assert_eq!(preprocessor.variable_count(), 2);
+        assert_eq!(preprocessor.symbol_table()["END"], 6);
+    }
+
+    #[test]
+    fn data_labels_name_ram_addresses_before_the_variables() {
+        let source = "
+            @i
+            @ROW
+            .data
+            (SQUARES)
+            .word 0 1 4
+            (ROW)
+            .fill 2 7
+            .text
+            (LOOP)
+            @SQUARES
+            @LOOP
+        ";
+        let mut nodes = parse(source);
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        assert_eq!(addresses(&nodes), [Some(21), Some(19), Some(16), Some(2)]);
+        assert_eq!(preprocessor.variable_count(), 1);
+        assert_eq!(preprocessor.data_len(), 5);
+
+        let image = preprocessor.ram_image();
+        assert_eq!(image.len(), 21);
+        assert_eq!(image[16..], [0, 1, 4, 7, 7]);
+    }
+
+    #[test]
+    fn rejects_duplicate_and_predefined_labels() {
+        let mut nodes = parse("(LOOP)\n@LOOP\n(SCREEN)\n0;JMP\n(LOOP)\n(END)\n(LOOP)");
+        let error = Preprocessor::new().preprocess(&mut nodes).unwrap_err();
+
+        let conflicts = error.downcast_ref::<LabelConflicts>().unwrap();
+        assert_eq!(
+            conflicts.0[0],
+            LabelConflict::Predefined {
+                name: "SCREEN".into(),
+                line: 3,
+                column: 2,
+                address: 16384
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "[line 3:2] Error: Label (SCREEN) would redefine the predefined symbol SCREEN, RAM[16384]\n\
+             [line 5:2] Error: Label (LOOP) is already defined at line 1\n\
+             [line 7:2] Error: Label (LOOP) is already defined at line 1"
+        );
+        assert_eq!(
+            conflicts.render("(LOOP)\n@LOOP\n(SCREEN)"),
+            "[line 3:2] Error: Label (SCREEN) would redefine the predefined symbol SCREEN, RAM[16384]\n \
+             3 | (SCREEN)\n   |  ^^^^^^\n\
+             [line 5:2] Error: Label (LOOP) is already defined at line 1\n\
+             [line 7:2] Error: Label (LOOP) is already defined at line 1"
+        );
+    }
+
+    #[test]
+    fn rejects_symbols_past_32767() {
+        // A label right after the last ROM word, and variables after 32751 data words
+        let far_label = "@END\n@END-1\n.org 32767\n0;JMP\n(END)";
+        assert_eq!(
+            assemble(far_label).unwrap_err().to_string(),
+            "[line 1:2] Error[E001]: A-instruction value `END` = 32768 is out of range 0..=32767"
+        );
+        let far_variable = ".data\n.fill 32751\n.text\n@x\n@y-1\n@y";
+        assert_eq!(
+            assemble(far_variable).unwrap_err().to_string(),
+            "[line 6:2] Error[E001]: A-instruction value `y` = 32768 is out of range 0..=32767"
+        );
+
+        let mut preprocessor = Preprocessor::new();
+        let nodes = parse(far_variable);
+        preprocessor
+            .define_labels(parse(far_variable).into_iter().map(Ok))
+            .unwrap();
+        let error = nodes
+            .into_iter()
+            .map(|node| preprocessor.resolve(node))
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[line 6:2] Error[E001]: A-instruction value `y` = 32768 is out of range 0..=32767"
+        );
+    }
+
+    #[test]
+    fn symbols_may_contain_colons() {
+        let mut nodes = parse("(Main.main$loop:1)\n@Main.main$loop:1\n0;JMP");
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+
+        assert_eq!(addresses(&nodes), [Some(0), None]);
+    }
+
+    fn assemble(source: &str) -> anyhow::Result<(Vec<Address>, Vec<String>)> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let tokens = expand_macros(tokens?)?;
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
+        let mut nodes = nodes?;
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes)?;
+        let labels = preprocessor
+            .labels()
+            .iter()
+            .map(|(label, _)| label.to_string())
+            .collect();
+
+        Ok((crate::assembler::Assembler::new(nodes).assemble()?, labels))
+    }
+
+    #[test]
+    fn expands_defines_and_macros_before_symbols() {
+        let source = "
+            .define LIMIT 10
+            .define COUNTER i
+            .macro DEC_JGT var target
+            @var
+            MD=M-1
+            @target
+            D;JGT
+            .endmacro
+            .macro HALT
+            (SPIN)
+            @SPIN
+            0;JMP
+            .endmacro
+            @LIMIT
+            D=A
+            @COUNTER
+            M=D
+            (LOOP)
+            DEC_JGT COUNTER LOOP
+            HALT
+            HALT
+        ";
+        let expanded = "
+            @10
+            D=A
+            @i
+            M=D
+            (LOOP)
+            @i
+            MD=M-1
+            @LOOP
+            D;JGT
+            (SPIN1)
+            @SPIN1
+            0;JMP
+            (SPIN2)
+            @SPIN2
+            0;JMP
+        ";
+
+        let (words, labels) = assemble(source).unwrap();
+        assert_eq!(words, assemble(expanded).unwrap().0);
+        assert_eq!(labels, ["LOOP", "SPIN$HALT.2", "SPIN$HALT.3"]);
+    }
+
+    #[test]
+    fn rejects_malformed_macros() {
+        let message = |source| assemble(source).unwrap_err().to_string();
+
+        assert_eq!(
+            message(".macro INC x\n@x\nM=M+1"),
+            "[line 1:1] Error: `.macro INC` has no `.endmacro`"
+        );
+        assert_eq!(
+            message("D=M\n.endmacro"),
+            "[line 2:1] Error: `.endmacro` without a `.macro`"
+        );
+        assert_eq!(
+            message(".macro INC x\n@x\nM=M+1\n.endmacro\nINC"),
+            "[line 5:1] Error: Macro `INC` takes 1 argument but got 0"
+        );
+        assert_eq!(
+            message(".macro INC x\n.define Y 1\n.endmacro"),
+            "[line 2:1] Error: `.define` cannot be used inside a macro"
+        );
+        assert_eq!(
+            message(".define X 1\n.define X 2"),
+            "[line 2:9] Error: `X` is already defined"
+        );
+        assert_eq!(
+            message(".macro LOOP\nLOOP\n.endmacro\nLOOP"),
+            "[line 2:1] Error: Macro `LOOP` is expanded more than 64 levels deep; does it use itself?"
+        );
+        assert_eq!(
+            message(".macro HALT\n0;JMP\n.endmacro\n@HALT"),
+            "[line 4:2] Error: `HALT` is a macro, not a symbol"
+        );
+    }
+
+    #[test]
+    fn adds_up_a_instruction_expressions() {
+        let source = "
+            @SCREEN+32
+            @TABLE+2
+            @i-1+i
+            @10-4+1
+            (TABLE)
+            @TABLE-TABLE
+        ";
+        let (words, _) = assemble(source).unwrap();
+        assert_eq!(words, [16416, 6, 31, 7, 0]);
+
+        assert_eq!(
+            assemble("@1\n@SCREEN+KBD").unwrap_err().to_string(),
+            "[line 2:2] Error[E001]: A-instruction value `SCREEN+KBD` = 40960 is out of range 0..=32767"
+        );
+        assert_eq!(
+            assemble("@R0-1").unwrap_err().to_string(),
+            "[line 1:2] Error[E001]: A-instruction value `R0-1` = -1 is out of range 0..=32767"
+        );
+    }
+
+    #[test]
+    fn org_pads_the_rom_up_to_its_address() {
+        let source = "
+            @MAIN
+            0;JMP
+            .org 4
+            (HANDLER)
+            @HANDLER
+            0;JMP
+            .org 6
+            (MAIN)
+            D=0
+        ";
+        let (words, _) = assemble(source).unwrap();
+        assert_eq!(
+            words,
+            [
+                6,
+                0b1110_1010_1000_0111,
+                0,
+                0,
+                4,
+                0b1110_1010_1000_0111,
+                0b1110_1010_1001_0000
+            ]
+        );
+
+        assert_eq!(
+            assemble("@1\nD=A\n@2\n.org 2").unwrap_err().to_string(),
+            "[line 4:6] Error: `.org 2` would overlap the 3 ROM words laid out before it"
+        );
+    }
+
+    #[test]
+    fn imports_the_symbols_of_another_program() {
+        let library = SymbolFile {
+            labels: [("Lib.draw".to_string(), 1000), ("SP".to_string(), 0)].into(),
+            variables: [("Lib.x".to_string(), 16), ("Lib.y".to_string(), 17)].into(),
+        };
+        let mut nodes = parse("@Lib.draw\n@Lib.y\n@z\n(LOOP)\n@LOOP");
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.import(&library).unwrap();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        // `z` comes after the imported variables
+        assert_eq!(addresses(&nodes), [Some(1000), Some(17), Some(18), Some(3)]);
+        assert_eq!(
+            preprocessor.export(),
+            SymbolFile {
+                labels: [("LOOP".to_string(), 3)].into(),
+                variables: [("z".to_string(), 18)].into(),
+            }
+        );
+
+        let mut nodes = parse("(Lib.draw)\n0;JMP");
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.import(&library).unwrap();
+        assert_eq!(
+            preprocessor.preprocess(&mut nodes).unwrap_err().to_string(),
+            "[line 1:2] Error: Label (Lib.draw) would redefine the imported symbol Lib.draw = 1000"
+        );
+
+        let screen = SymbolFile {
+            labels: [("SCREEN".to_string(), 12)].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            Preprocessor::new().import(&screen).unwrap_err().to_string(),
+            "Imported symbol SCREEN = 12 is already defined as 16384"
+        );
+    }
+
+    /// `cargo test --release -- --ignored --nocapture preprocess_100k_lines`
+    #[test]
+    #[ignore]
+    fn preprocess_100k_lines() {
+        let mut source = String::new();
+        for i in 0..20_000 {
+            source.push_str(&format!(
+                "(LOOP{i})\n@var{}\nD=M\n@LOOP{}\n0;JMP\n",
+                i % 500,
+                (i + 7) % 20_000
+            ));
+        }
+        let mut nodes = parse(&source);
+
+        let start = Instant::now();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+        println!("Preprocessed 100000 lines in {:?}", start.elapsed());
+
+        assert_eq!(nodes.len(), 80_000);
+        assert_eq!(preprocessor.variable_count(), 500);
+    }
 }
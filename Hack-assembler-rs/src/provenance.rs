@@ -0,0 +1,82 @@
+//! Sidecar JSON recording how a `.hack` file was produced.
+
+use serde::{Deserialize, Serialize};
+
+/// Header lines the VM translator puts at the top of an `.asm` file.
+pub use n2t_core::provenance::PREFIX as SOURCE_PREFIX;
+pub use n2t_core::provenance::sha256_hex;
+use n2t_core::version::GIT_HASH;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Provenance {
+    pub tool: String,
+    pub version: String,
+    /// Commit the tool was built from; absent in sidecars of older builds.
+    #[serde(default)]
+    pub commit: String,
+    /// Cargo features the tool was built with.
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub flags: Vec<String>,
+    pub inputs: Vec<Artifact>,
+    pub outputs: Vec<Artifact>,
+    /// Provenance header of the input, without the comment prefix.
+    pub source: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Artifact {
+    pub path: String,
+    pub sha256: String,
+}
+
+impl Artifact {
+    pub fn new<S: Into<String>>(path: S, bytes: &[u8]) -> Self {
+        Self {
+            path: path.into(),
+            sha256: sha256_hex(bytes),
+        }
+    }
+}
+
+impl Provenance {
+    pub fn new(flags: Vec<String>, input: Artifact, source: &str) -> Self {
+        Self {
+            tool: crate::VERSION.tool.to_string(),
+            version: crate::VERSION.version.to_string(),
+            commit: GIT_HASH.to_string(),
+            features: crate::VERSION
+                .features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+            flags,
+            inputs: vec![input],
+            outputs: vec![],
+            source: source
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix(SOURCE_PREFIX))
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod provenance_tests {
+    use super::*;
+
+    #[test]
+    fn picks_up_translator_header() {
+        let source = "// provenance: tool=VMTranslator version=0.1.0\n// provenance: flags=-O 0\n// push constant 7\n@7\nD=A\n";
+        let provenance =
+            Provenance::new(vec![], Artifact::new("Prog.asm", source.as_bytes()), source);
+
+        assert_eq!(
+            provenance.source,
+            ["tool=VMTranslator version=0.1.0", "flags=-O 0"]
+        );
+        assert_eq!(provenance.commit, GIT_HASH);
+        assert_eq!(provenance.inputs[0].sha256, sha256_hex(source.as_bytes()));
+    }
+}
@@ -0,0 +1,153 @@
+//! RAM preload files of `--ram`, setting RAM words before the program runs like the
+//! `set RAM[x] v` commands of a test script.
+//!
+//! A text file has one word per line: `address: value`, or a bare value stored at the
+//! address after the previous line's, from RAM[0] on, so that a `.ram` image or any
+//! other contiguous dump loads as it is. Values are decimal, from -32768 to 65535, or
+//! 16 binary digits as in a `.hack` file; `//` starts a comment. A `.json` file is an
+//! object of addresses to values, or an array of values from RAM[0].
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::parser::Address;
+
+/// `(address, value)` pairs of the file at `path`.
+pub fn read(path: &Path) -> anyhow::Result<Vec<(Address, Address)>> {
+    let contents = read_to_string(path)?;
+    let words = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json(&contents),
+        _ => parse_text(&contents),
+    };
+
+    words.map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+}
+
+pub fn parse_text(contents: &str) -> anyhow::Result<Vec<(Address, Address)>> {
+    let mut words = vec![];
+    let mut next = 0;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let word = match line.split_once(':') {
+            Some((address, value)) => parse_address(address.trim())
+                .and_then(|address| Ok((address, parse_value(value.trim())?))),
+            None => parse_value(line).map(|value| (next, value)),
+        };
+        let word = word.map_err(|e| anyhow::anyhow!("line {}: {e}", i + 1))?;
+
+        next = word.0.wrapping_add(1);
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+pub fn parse_json(contents: &str) -> anyhow::Result<Vec<(Address, Address)>> {
+    from_json(&serde_json::from_str(contents)?)
+}
+
+/// `(address, value)` pairs of an object of addresses to values, or of an array of
+/// values from RAM[0], as in a `.json` file.
+pub fn from_json(json: &Value) -> anyhow::Result<Vec<(Address, Address)>> {
+    let value = |value: &Value| match value {
+        Value::Number(number) => parse_value(&number.to_string()),
+        _ => anyhow::bail!("Expected a number but got `{value}`"),
+    };
+
+    match json {
+        Value::Object(words) => words
+            .iter()
+            .map(|(address, word)| Ok((parse_address(address)?, value(word)?)))
+            .collect(),
+        Value::Array(words) => words
+            .iter()
+            .enumerate()
+            .map(|(address, word)| Ok((check_address(address)?, value(word)?)))
+            .collect(),
+        _ => anyhow::bail!("Expected an object of addresses to values, or an array of values"),
+    }
+}
+
+fn parse_address(address: &str) -> anyhow::Result<Address> {
+    let address = address
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Expected an address but got `{address}`"))?;
+
+    check_address(address)
+}
+
+/// Addresses the 15 bits of the A register reach.
+fn check_address(address: usize) -> anyhow::Result<Address> {
+    if address > 0x7FFF {
+        anyhow::bail!("Address `{address}` is out of range 0..=32767");
+    }
+
+    Ok(address as Address)
+}
+
+fn parse_value(value: &str) -> anyhow::Result<Address> {
+    if value.len() == 16 && value.chars().all(|c| c == '0' || c == '1') {
+        return Ok(Address::from_str_radix(value, 2)?);
+    }
+
+    match value.parse::<i32>() {
+        Ok(number @ -32768..=65535) => Ok(number as Address),
+        Ok(_) => anyhow::bail!("Value `{value}` is out of range -32768..=65535"),
+        Err(_) => anyhow::bail!("Expected a value but got `{value}`"),
+    }
+}
+
+#[cfg(test)]
+mod ram_file_tests {
+    use super::*;
+
+    #[test]
+    fn reads_pairs_and_contiguous_dumps() {
+        let text = "
+            // A .ram image
+            0000000000000101
+            -1
+            16: 42
+            65535 // follows RAM[16]
+            256: 0000000000000011
+        ";
+
+        assert_eq!(
+            parse_text(text).unwrap(),
+            [(0, 5), (1, 65535), (16, 42), (17, 65535), (256, 3)]
+        );
+        assert_eq!(
+            parse_json(r#"{"0": 256, "3": -2}"#).unwrap(),
+            [(0, 256), (3, 65534)]
+        );
+        assert_eq!(parse_json("[7, 8]").unwrap(), [(0, 7), (1, 8)]);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_word() {
+        let error = |text| parse_text(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("1\n32768: 0"),
+            "line 2: Address `32768` is out of range 0..=32767"
+        );
+        assert_eq!(
+            error("0: 65536"),
+            "line 1: Value `65536` is out of range -32768..=65535"
+        );
+        assert_eq!(
+            error("RAM[0]: 1"),
+            "line 1: Expected an address but got `RAM[0]`"
+        );
+        assert_eq!(
+            parse_json(r#"{"0": "x"}"#).unwrap_err().to_string(),
+            r#"Expected a number but got `"x"`"#
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! The symbol pre-pass on its own: assembly text without labels or symbolic
+//! A-instructions, for teaching and for tools that do not know about symbols.
+
+use std::fmt::Write as _;
+
+use crate::parser::{Instruction, Node, Parser};
+use crate::preprocessor::{Preprocessor, SymbolTable, expand_macros};
+use crate::scanner::{Scanner, TokenType};
+
+/// `source` with its labels removed and every symbol replaced by its address, one
+/// instruction per line, and the symbol table that did it: predefined symbols,
+/// labels and variables. Data sections come first, as one `.word` line.
+///
+/// Resolving the result again gives it back unchanged, and it assembles to the same
+/// words as `source`.
+pub fn resolve_symbols(source: &str) -> anyhow::Result<(String, SymbolTable<'_>)> {
+    let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+    let tokens = expand_macros(tokens?)?;
+    let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
+    let mut nodes = nodes?;
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.preprocess(&mut nodes)?;
+
+    let mut text = String::new();
+    if !preprocessor.data().is_empty() {
+        let words: Vec<_> = preprocessor.data().iter().map(u16::to_string).collect();
+        writeln!(text, ".data\n.word {}\n.text", words.join(" "))?;
+    }
+    for node in nodes.iter() {
+        match node {
+            Node::Instruction(Instruction::A { token, .. }) => match token.token_type {
+                TokenType::NUMBER(address) => writeln!(text, "@{address}")?,
+                _ => unreachable!("Preprocessing resolves every symbol"),
+            },
+            Node::Instruction(Instruction::C {
+                dest, comp, jump, ..
+            }) => {
+                if let Some(dest) = dest {
+                    write!(text, "{}=", dest.lexeme)?;
+                }
+                write!(text, "{comp}")?;
+                if let Some(jump) = jump {
+                    write!(text, ";{}", jump.lexeme)?;
+                }
+                text.push('\n');
+            }
+            Node::Org { address, .. } => writeln!(text, ".org {}", address.lexeme)?,
+            _ => unreachable!("Preprocessing drops labels and data sections"),
+        }
+    }
+
+    Ok((text, preprocessor.symbol_table().clone()))
+}
+
+#[cfg(test)]
+mod resolve_tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    fn assemble(source: &str) -> Vec<u16> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+
+        Assembler::new(nodes).assemble().unwrap()
+    }
+
+    #[test]
+    fn removes_labels_and_numbers_variables() {
+        let source = "
+            // Counts down from R0
+            @R0
+            D=M
+            @i
+            M=D
+            (LOOP)
+            @i
+            MD=M-1
+            @LOOP
+            D;JGT
+            .data
+            (TABLE)
+            .word 3 -1
+            .text
+            @TABLE
+            0;JMP
+        ";
+        let (text, symbols) = resolve_symbols(source).unwrap();
+
+        assert_eq!(
+            text,
+            ".data\n.word 3 65535\n.text\n@0\nD=M\n@18\nM=D\n@18\nMD=M-1\n@4\nD;JGT\n@16\n0;JMP\n"
+        );
+        assert_eq!(symbols["LOOP"], 4);
+        assert_eq!(symbols["TABLE"], 16);
+        assert_eq!(symbols["i"], 18);
+        assert_eq!(assemble(&text), assemble(source));
+
+        let (again, symbols) = resolve_symbols(&text).unwrap();
+        assert_eq!(again, text);
+        assert!(!symbols.contains_key("i"));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(resolve_symbols("(LOOP\n@1").is_err());
+    }
+}
@@ -1,9 +1,11 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, ops::Range};
 
 use once_cell::sync::Lazy;
 
+use crate::diagnostic::SyntaxError;
+
 #[rustfmt::skip] 
-static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
+pub static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     [
         ("M",   TokenType::M),
         ("D",   TokenType::D),
@@ -44,27 +46,96 @@ pub enum TokenType {
     EOF
 }
 
+impl TokenType {
+    /// Category of the token for editors: `symbol`, `number`, `identifier`,
+    /// `register` (a destination or operand) or `jump`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenType::LEFT_PAREN
+            | TokenType::RIGHT_PAREN
+            | TokenType::MINUS
+            | TokenType::PLUS
+            | TokenType::EQUAL
+            | TokenType::BANG
+            | TokenType::AT
+            | TokenType::BAR
+            | TokenType::AMPERSAND
+            | TokenType::SEMICOLON => "symbol",
+            TokenType::IDENTIFIER => "identifier",
+            TokenType::NUMBER(_) => "number",
+            TokenType::M
+            | TokenType::D
+            | TokenType::MD
+            | TokenType::A
+            | TokenType::AM
+            | TokenType::AD
+            | TokenType::AMD => "register",
+            TokenType::JGT
+            | TokenType::JEQ
+            | TokenType::JGE
+            | TokenType::JLT
+            | TokenType::JNE
+            | TokenType::JLE
+            | TokenType::JMP => "jump",
+            TokenType::EOF => "eof",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token<'de> {
     pub token_type: TokenType,
     pub lexeme: Cow<'de, str>,
     pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+    /// Byte offsets in the source.
+    pub span: Range<usize>,
 }
 
 impl<'de> Token<'de> {
+    /// A token at the start of `line`.
+    #[cfg(test)]
     pub fn new(token_type: TokenType, lexeme: impl Into<Cow<'de, str>>, line: usize) -> Self {
+        Self::at(token_type, lexeme, (line, 1, 0))
+    }
+
+    /// A token whose first character is at `(line, column, byte offset)`.
+    pub fn at(
+        token_type: TokenType,
+        lexeme: impl Into<Cow<'de, str>>,
+        (line, column, offset): (usize, usize, usize),
+    ) -> Self {
+        let lexeme = lexeme.into();
+
         Token {
             token_type,
-            lexeme: lexeme.into(),
+            span: offset..offset + lexeme.len(),
+            lexeme,
             line,
+            column,
         }
     }
+
+    /// An error pointing at this token.
+    pub fn error(&self, message: impl Into<String>) -> SyntaxError {
+        SyntaxError::new(
+            self.line,
+            self.column,
+            self.lexeme.chars().count(),
+            message,
+        )
+    }
 }
 
 pub struct Scanner<'de> {
     rest: &'de str,
     current: usize,
     line: usize,
+    /// Column of the next character.
+    column: usize,
+    /// Bytes consumed so far.
+    offset: usize,
     eof: bool,
 }
 
@@ -74,6 +145,8 @@ impl<'de> Scanner<'de> {
             rest: source,
             current: 0,
             line: 1,
+            column: 1,
+            offset: 0,
             eof: false,
         }
     }
@@ -95,6 +168,8 @@ impl<'de> Scanner<'de> {
         let lexeme = &self.rest[0..bytes_n];
         self.rest = &self.rest[bytes_n..];
         self.current += n;
+        self.column += n;
+        self.offset += bytes_n;
 
         lexeme
     }
@@ -111,9 +186,9 @@ impl<'de> Scanner<'de> {
         fn token<'de>(
             token_type: TokenType,
             lexeme: &'de str,
-            line: usize,
+            position: (usize, usize, usize),
         ) -> Option<anyhow::Result<Token<'de>>> {
-            Some(Ok(Token::<'de>::new(token_type, lexeme, line)))
+            Some(Ok(Token::<'de>::at(token_type, lexeme, position)))
         }
 
         'scan_loop: loop {
@@ -123,6 +198,7 @@ impl<'de> Scanner<'de> {
                 return None;
             };
 
+            let position = (self.line, self.column, self.offset);
             match cur {
                 // Meaningless characters.
                 ' ' | '\r' | '\t' => {
@@ -131,18 +207,19 @@ impl<'de> Scanner<'de> {
                 '\n' => {
                     self.line += 1;
                     let _ = self.advance_n(1);
+                    self.column = 1;
                 },
                 // Single-character tokens.
-                '(' => return token(TokenType::LEFT_PAREN,  self.advance_n(1), self.line),
-                ')' => return token(TokenType::RIGHT_PAREN, self.advance_n(1), self.line),
-                '-' => return token(TokenType::MINUS,       self.advance_n(1), self.line),
-                '+' => return token(TokenType::PLUS,        self.advance_n(1), self.line),
-                '=' => return token(TokenType::EQUAL,       self.advance_n(1), self.line),
-                '!' => return token(TokenType::BANG,        self.advance_n(1), self.line),
-                '&' => return token(TokenType::AMPERSAND,   self.advance_n(1), self.line),
-                '|' => return token(TokenType::BAR,         self.advance_n(1), self.line),
-                '@' => return token(TokenType::AT,          self.advance_n(1), self.line),
-                ';' => return token(TokenType::SEMICOLON,   self.advance_n(1), self.line),
+                '(' => return token(TokenType::LEFT_PAREN,  self.advance_n(1), position),
+                ')' => return token(TokenType::RIGHT_PAREN, self.advance_n(1), position),
+                '-' => return token(TokenType::MINUS,       self.advance_n(1), position),
+                '+' => return token(TokenType::PLUS,        self.advance_n(1), position),
+                '=' => return token(TokenType::EQUAL,       self.advance_n(1), position),
+                '!' => return token(TokenType::BANG,        self.advance_n(1), position),
+                '&' => return token(TokenType::AMPERSAND,   self.advance_n(1), position),
+                '|' => return token(TokenType::BAR,         self.advance_n(1), position),
+                '@' => return token(TokenType::AT,          self.advance_n(1), position),
+                ';' => return token(TokenType::SEMICOLON,   self.advance_n(1), position),
                 // Comments
                 '/' if self.peek_rest_at(1) == Some('/') => {
                     loop {
@@ -164,13 +241,16 @@ impl<'de> Scanner<'de> {
 
                     fn token_number<'de>(
                         lexeme: &'de str,
-                        line: usize,
+                        position: (usize, usize, usize),
                     ) -> Option<anyhow::Result<Token<'de>>> {
                         if let Ok(number) = lexeme.parse::<u16>() {
-                            token(TokenType::NUMBER(number), lexeme, line)
+                            token(TokenType::NUMBER(number), lexeme, position)
                         } else {
-                            Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Could not parse a number: {lexeme}"))))
-                        }                        
+                            let (line, column, _) = position;
+                            let error = SyntaxError::new(line, column, lexeme.len(), format!("Number `{lexeme}` is out of range 0..={}", u16::MAX));
+
+                            Some(Err(error.into()))
+                        }
                     }
 
                     loop {
@@ -178,31 +258,43 @@ impl<'de> Scanner<'de> {
                             Some(c) if c.is_digit(10) => {
                                 cur_len += 1;
                             }
-                            _ => return token_number(self.advance_n(cur_len), self.line),
+                            // `123abc`: a number running into a symbol
+                            Some(c) if c.is_alphabetic() || c == '_' || c == '.' || c == '$' || c == ':' => {
+                                while self.peek_rest_at(cur_len).is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '$' || c == ':') {
+                                    cur_len += 1;
+                                }
+                                let lexeme = self.advance_n(cur_len);
+                                let (line, column, _) = position;
+                                let error = SyntaxError::new(line, column, cur_len, format!("`{lexeme}` is not a number, and symbols cannot start with a digit"));
+
+                                return Some(Err(error.into()));
+                            }
+                            _ => return token_number(self.advance_n(cur_len), position),
                         }
                     }
                 },
-                'a'..='z' | 'A'..='Z' | '_' | '.' | '$' => {
+                'a'..='z' | 'A'..='Z' | '_' | '.' | '$' | ':' => {
                     let mut cur_len = 0;
 
                     loop {
                         match self.peek_rest_at(cur_len) {
-                            Some(c) if c.is_alphanumeric() || c == '_' || c == '.' || c == '$' => {
+                            Some(c) if c.is_alphanumeric() || c == '_' || c == '.' || c == '$' || c == ':' => {
                                 cur_len += 1;
                             }
                             _ => {
                                 let lexeme = self.advance_n(cur_len);
 
-                                return token(self.get_keyword_or_identifier(lexeme), lexeme, self.line);
+                                return token(self.get_keyword_or_identifier(lexeme), lexeme, position);
                             }
                         }
                     }
                 },
                 lexeme => {
                     let _ = self.advance_n(1);
-                    let line = self.line;
+                    let (line, column, _) = position;
+                    let error = SyntaxError::new(line, column, 1, format!("Unexpected character `{lexeme}`"));
 
-                    return Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Unexpected character: {lexeme}"))));
+                    return Some(Err(error.into()));
                 }
             }
         }
@@ -220,7 +312,7 @@ impl<'de> Iterator for Scanner<'de> {
             if !self.eof {
                 self.eof = true;
 
-                Some(Ok(Token::new(TokenType::EOF, "eof", self.line)))
+                Some(Ok(Token::at(TokenType::EOF, "eof", (self.line, self.column, self.offset))))
             } else {
                 None
             }
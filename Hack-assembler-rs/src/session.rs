@@ -0,0 +1,49 @@
+//! State shared by the stages of a run, read once from the environment.
+
+use n2t_core::build_dir::{BuildDir, debug_enabled};
+
+use crate::limits::Limits;
+
+const DEBUG_TOKENS: &str = "DEBUG_TOKENS";
+const DEBUG_AST: &str = "DEBUG_AST";
+const DEBUG_SYMBOL_TABLE: &str = "DEBUG_SYMBOL_TABLE";
+const DEBUG_AST_L: &str = "DEBUG_AST_L";
+
+/// Which debug dumps to write to the build directory, and the limits to check.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub debug_tokens: bool,
+    pub debug_ast: bool,
+    pub debug_symbol_table: bool,
+    /// Nodes once labels and variables are replaced by addresses.
+    pub debug_ast_l: bool,
+    /// Where the debug dumps go.
+    pub build_dir: BuildDir,
+    pub limits: Limits,
+    /// Only warn about exceeded limits.
+    pub ignore_limits: bool,
+}
+
+impl Options {
+    pub fn from_env() -> Self {
+        Self {
+            debug_tokens: debug_enabled(DEBUG_TOKENS),
+            debug_ast: debug_enabled(DEBUG_AST),
+            debug_symbol_table: debug_enabled(DEBUG_SYMBOL_TABLE),
+            debug_ast_l: debug_enabled(DEBUG_AST_L),
+            build_dir: BuildDir::from_env(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Session {
+    pub options: Options,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Self { options }
+    }
+}
@@ -0,0 +1,178 @@
+//! `.map` files: where in the `.asm` source each ROM word comes from, so that an
+//! emulator or a debugger can show the line of the current PC.
+//!
+//! The map is read off the parsed nodes, before preprocessing drops the labels. The
+//! scope of a word is the last label before it without a `$`: labels with one are
+//! local to a function of the VM translator, such as `Main.main$LOOP`, or renamed
+//! by a macro expansion.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::include::Sources;
+use crate::parser::{Address, Instruction, Node};
+use crate::scanner::{Token, TokenType};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceMap {
+    /// The `.asm` file every entry is a line of.
+    pub file: String,
+    /// One entry per instruction, by ROM address. The zero words of `.org` padding
+    /// have none.
+    pub entries: Vec<Entry>,
+    /// ROM address of every label outside `.data` sections.
+    #[serde(default)]
+    pub labels: BTreeMap<String, Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub address: Address,
+    pub line: usize,
+    /// The file of the line, when it is not the map's: one joined to it or included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Last label before the instruction; none before the first one.
+    pub scope: Option<String>,
+}
+
+impl SourceMap {
+    /// The map of `nodes`, parsed from `file` and not preprocessed yet.
+    pub fn of(file: &str, nodes: &[Node<'_>]) -> Self {
+        let mut entries = vec![];
+        let mut labels = BTreeMap::new();
+        let mut scope = None;
+        let mut rom: usize = 0;
+        let mut in_data = false;
+        for node in nodes {
+            match node {
+                Node::Label { name, .. } if !in_data => {
+                    labels.insert(name.lexeme.to_string(), rom as Address);
+                    if !name.lexeme.contains('$') {
+                        scope = Some(name.lexeme.to_string());
+                    }
+                }
+                Node::Label { .. } | Node::Data { .. } => {}
+                Node::Section { data, .. } => in_data = *data,
+                Node::Org { address, .. } => {
+                    if let TokenType::NUMBER(address) = address.token_type {
+                        rom = address as usize;
+                    }
+                }
+                Node::Instruction(instruction) => {
+                    entries.push(Entry {
+                        address: rom as Address,
+                        line: first_token(instruction).line,
+                        file: None,
+                        scope: scope.clone(),
+                    });
+                    rom += 1;
+                }
+            }
+        }
+
+        Self {
+            file: file.to_string(),
+            entries,
+            labels,
+        }
+    }
+
+    /// Turns the lines of the joined source of `sources` into lines of their files.
+    pub fn locate(&mut self, sources: &Sources) {
+        if sources.is_single() {
+            return;
+        }
+
+        for entry in self.entries.iter_mut() {
+            let (path, line) = sources.locate(entry.line);
+            let path = path.display().to_string();
+            entry.line = line;
+            entry.file = (path != self.file).then_some(path);
+        }
+    }
+
+    /// The entry of the instruction at `address`, if one is there.
+    pub fn at(&self, address: Address) -> Option<&Entry> {
+        self.entries
+            .binary_search_by_key(&address, |entry| entry.address)
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+
+        Ok(())
+    }
+}
+
+/// The token an instruction starts with, on its line.
+fn first_token<'a>(instruction: &'a Instruction<'_>) -> &'a Token<'a> {
+    match instruction {
+        Instruction::A { _at, .. } => _at,
+        Instruction::C {
+            dest, _comp_tokens, ..
+        } => dest.as_ref().unwrap_or(&_comp_tokens[0]),
+    }
+}
+
+#[cfg(test)]
+mod source_map_tests {
+    use super::*;
+    use crate::{parser::Parser, preprocessor::expand_macros, scanner::Scanner};
+
+    #[test]
+    fn maps_every_instruction_to_its_line_and_scope() {
+        let source = "@i
+M=0
+(Main.main)
+@i
+.macro STOP
+(HALT)
+@HALT
+0;JMP
+.endmacro
+(Main.main$LOOP)
+D=M
+.data
+(TABLE)
+.word 1
+.text
+.org 8
+STOP";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let tokens = expand_macros(tokens.unwrap()).unwrap();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
+        let map = SourceMap::of("Prog.asm", &nodes.unwrap());
+
+        let entries: Vec<_> = map
+            .entries
+            .iter()
+            .map(|entry| (entry.address, entry.line, entry.scope.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0, 1, None),
+                (1, 2, None),
+                (2, 4, Some("Main.main")),
+                (3, 11, Some("Main.main")),
+                (8, 7, Some("Main.main")),
+                (9, 8, Some("Main.main")),
+            ]
+        );
+        assert_eq!(map.at(9).map(|entry| entry.line), Some(8));
+        assert_eq!(
+            map.labels
+                .iter()
+                .map(|(name, &address)| (name.as_str(), address))
+                .collect::<Vec<_>>(),
+            [("HALT$STOP.1", 8), ("Main.main", 2), ("Main.main$LOOP", 3)]
+        );
+        assert_eq!(map.at(5), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! `--stream`: assembly without holding the tokens and nodes of the whole program, for
+//! the large `.asm` files the VM translator writes. Only the source, the symbols and
+//! the data words stay in memory.
+//!
+//! The source is scanned and parsed twice, each time as a chain of iterators: the
+//! first pass defines the labels ([`Preprocessor::define_labels`]), the second
+//! resolves every node ([`Preprocessor::resolve`]) and encodes it right away, each
+//! word written as it comes. `.define` and `.macro` need the whole token list to
+//! expand, so they are rejected.
+
+use std::io::Write;
+
+use crate::assembler::Assembler;
+use crate::parser::Parser;
+use crate::preprocessor::{MACRO_DIRECTIVES, Preprocessor};
+use crate::scanner::{Scanner, Token, TokenType};
+
+/// The items of `results` up to the first error, which is left in `error`.
+fn until_error<'a, T: 'a>(
+    results: impl Iterator<Item = anyhow::Result<T>> + 'a,
+    error: &'a mut Option<anyhow::Error>,
+) -> impl Iterator<Item = T> + 'a {
+    results.map_while(move |result| result.map_err(|e| *error = Some(e)).ok())
+}
+
+/// Tokens of `source`, failing at a macro directive.
+fn tokens<'de>(source: &'de str) -> impl Iterator<Item = anyhow::Result<Token<'de>>> {
+    Scanner::new(source).map(|token| {
+        let token = token?;
+        if matches!(token.token_type, TokenType::IDENTIFIER)
+            && MACRO_DIRECTIVES.contains(&token.lexeme.as_ref())
+        {
+            let message = format!(
+                "`{}` is not expanded by --stream; assemble without it",
+                token.lexeme
+            );
+            return Err(token.error(message).into());
+        }
+
+        Ok(token)
+    })
+}
+
+/// Writes the `.hack` text of `source` to `output`, returning the number of words.
+/// `preprocessor` is left with the symbols, for the variables and the RAM image.
+///
+/// Words already written stay when an error comes up later in the source.
+pub fn assemble<'de>(
+    source: &'de str,
+    preprocessor: &mut Preprocessor<'de>,
+    output: &mut impl Write,
+) -> anyhow::Result<usize> {
+    // 1. Labels ..
+    let mut scan_error = None;
+    let nodes = Parser::new(until_error(tokens(source), &mut scan_error));
+    let result = preprocessor.define_labels(nodes);
+    if let Some(error) = scan_error {
+        return Err(error);
+    }
+    result?;
+
+    // 2. Words ..
+    let mut scan_error = None;
+    let mut parse_error = None;
+    let mut resolve_error = None;
+    let nodes = until_error(
+        Parser::new(until_error(tokens(source), &mut scan_error)),
+        &mut parse_error,
+    );
+    let resolved = until_error(
+        nodes.map(|node| preprocessor.resolve(node)),
+        &mut resolve_error,
+    )
+    .flatten();
+
+    let mut count = 0;
+    for word in Assembler::new(resolved).words() {
+        let word = word?;
+        if count > 0 {
+            writeln!(output)?;
+        }
+        write!(output, "{word:016b}")?;
+        count += 1;
+    }
+    if let Some(error) = scan_error.or(parse_error).or(resolve_error) {
+        return Err(error);
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use super::*;
+    use crate::assembler::to_hack_text;
+
+    fn stream(source: &str) -> anyhow::Result<String> {
+        let mut output = vec![];
+        assemble(source, &mut Preprocessor::new(), &mut output)?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn writes_the_words_of_a_whole_assembly() {
+        let source = include_str!("../input/Pong.asm");
+        let words = crate::assemble(source).unwrap();
+        assert_eq!(stream(source).unwrap(), to_hack_text(&words));
+
+        let source = "@END\n0;JMP\n.data\n(TABLE)\n.word 7\n.text\n@TABLE+1\nD=A\n@x\n\
+                      .org 8\n(END)\n@END\n0;JMP";
+        let mut preprocessor = Preprocessor::new();
+        let mut output = vec![];
+        assemble(source, &mut preprocessor, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            to_hack_text(&crate::assemble(source).unwrap())
+        );
+        assert_eq!(preprocessor.variable_count(), 1);
+        assert_eq!(preprocessor.ram_image()[16], 7);
+    }
+
+    #[test]
+    fn reports_the_first_error() {
+        let error = stream("@1\nD=M#\n@2").unwrap_err();
+        assert!(error.to_string().contains("Unexpected character `#`"));
+
+        let error = stream("@1\n(LOOP)\n(LOOP)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 3:2] Error: Label (LOOP) is already defined at line 2"
+        );
+
+        let error = stream(".define N 2\n@N").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("`.define` is not expanded by --stream")
+        );
+
+        let error = stream("@SCREEN+KBD").unwrap_err();
+        assert!(error.to_string().contains("is out of range 0..=32767"));
+    }
+}
@@ -0,0 +1,37 @@
+//! Symbol files of `--emit-symbols` and `--symbols`: the labels and variables a
+//! program defines, so that a program assembled on its own can use those of another,
+//! as a primitive link step between modules.
+//!
+//! The predefined symbols and those a program imported are not written again.
+
+use std::collections::BTreeMap;
+use std::fs::{File, read_to_string};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parser::Address;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolFile {
+    /// ROM address of every label, or RAM address of those of `.data` sections.
+    #[serde(default)]
+    pub labels: BTreeMap<String, Address>,
+    /// RAM address of every variable. A program importing them allocates its own after
+    /// the last one.
+    #[serde(default)]
+    pub variables: BTreeMap<String, Address>,
+}
+
+impl SymbolFile {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        serde_json::from_str(&read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Symbols a program most likely got wrong, checked once it is preprocessed: labels
+//! nothing jumps to, variables named like labels, as a misspelled `@LOOOP` or a
+//! forgotten `(END)` silently become, and variables that spill past the RAM for them
+//! into the screen memory.
+
+use std::fmt::Display;
+use std::mem::discriminant;
+
+use crate::emulator::SCREEN;
+use crate::include::Sources;
+use crate::parser::Address;
+use crate::preprocessor::Preprocessor;
+
+/// Warnings of each kind listed before the rest are only counted.
+const MAX_LISTED: usize = 10;
+
+/// Called from outside the program, as the VM translator's output starts with it.
+const ENTRY_FUNCTION: &str = "Sys.init";
+
+#[derive(Debug, PartialEq)]
+pub enum SymbolWarning {
+    UnusedLabel {
+        name: String,
+        line: usize,
+    },
+    LabelLikeVariable {
+        name: String,
+        line: usize,
+        address: Address,
+    },
+    SpilledVariable {
+        name: String,
+        line: usize,
+        address: Address,
+    },
+}
+
+impl Display for SymbolWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolWarning::UnusedLabel { name, line } => {
+                write!(f, "[line {line}] Label ({name}) is never referenced")
+            }
+            SymbolWarning::LabelLikeVariable {
+                name,
+                line,
+                address,
+            } => write!(
+                f,
+                "[line {line}] @{name} became the variable RAM[{address}], but looks like a \
+                 label: no ({name}) is defined"
+            ),
+            SymbolWarning::SpilledVariable {
+                name,
+                line,
+                address,
+            } => write!(
+                f,
+                "[line {line}] @{name} became the variable RAM[{address}], past RAM[{}] into \
+                 the screen memory: the data words and variables before it fill the RAM for them",
+                SCREEN - 1
+            ),
+        }
+    }
+}
+
+/// Upper-case letters, digits and punctuation only, as labels are written by convention
+/// and variables are not.
+fn looks_like_label(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_uppercase()) && !name.chars().any(|c| c.is_lowercase())
+}
+
+/// Unused labels first, then label-like variables, then spilled ones, each in source
+/// order. A label at ROM[0], or of the entry function, is where execution starts, so it
+/// needs no reference.
+pub fn warnings(preprocessor: &Preprocessor<'_>) -> Vec<SymbolWarning> {
+    let unused = preprocessor
+        .labels()
+        .iter()
+        .filter(|(name, _)| preprocessor.references(name) == 0)
+        .filter(|(name, _)| preprocessor.symbol_table()[name] != 0 && name != ENTRY_FUNCTION)
+        .map(|(name, line)| SymbolWarning::UnusedLabel {
+            name: name.to_string(),
+            line: *line,
+        });
+    let label_like = preprocessor
+        .variables()
+        .iter()
+        .filter(|(name, _)| looks_like_label(name))
+        .map(|(name, line)| SymbolWarning::LabelLikeVariable {
+            name: name.to_string(),
+            line: *line,
+            address: preprocessor.symbol_table()[name],
+        });
+    let spilled = preprocessor
+        .variables()
+        .iter()
+        .map(|(name, line)| (name, line, preprocessor.symbol_table()[name]))
+        .filter(|&(_, _, address)| address as usize >= SCREEN)
+        .map(|(name, line, address)| SymbolWarning::SpilledVariable {
+            name: name.to_string(),
+            line: *line,
+            address,
+        });
+
+    unused.chain(label_like).chain(spilled).collect()
+}
+
+/// Prints up to [`MAX_LISTED`] warnings of each kind, grouped as [`warnings`] gives
+/// them, after the file they are about. The lines of a program of several files are given as `file:line`.
+pub fn print(sources: &Sources, warnings: &[SymbolWarning]) {
+    let path = sources.main();
+    let same_kind = |a: &SymbolWarning, b: &SymbolWarning| discriminant(a) == discriminant(b);
+
+    for kind in warnings.chunk_by(same_kind) {
+        for warning in kind.iter().take(MAX_LISTED) {
+            match sources.is_single() {
+                true => println!("[~] {}: {warning}", path.display()),
+                false => println!("[~] {}", sources.relabel(&warning.to_string())),
+            }
+        }
+        if kind.len() > MAX_LISTED {
+            let more = kind.len() - MAX_LISTED;
+            println!("[~] {}: ... and {more} more like it", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod symbol_usage_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn flags_unused_labels_and_label_like_variables() {
+        let source = "@i
+(LOOP)
+@i
+M=M+1
+@LOOOP
+0;JMP
+(SKIPPED)
+@sum
+(END)
+@END
+0;JMP
+@SCREEN
+@Main.0";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        assert_eq!(
+            warnings(&preprocessor),
+            [
+                SymbolWarning::UnusedLabel {
+                    name: "LOOP".into(),
+                    line: 2
+                },
+                SymbolWarning::UnusedLabel {
+                    name: "SKIPPED".into(),
+                    line: 7
+                },
+                SymbolWarning::LabelLikeVariable {
+                    name: "LOOOP".into(),
+                    line: 5,
+                    address: 17
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_where_execution_starts() {
+        let source = "(START)
+@Main.main
+0;JMP
+(Main.main)
+@Main.main
+0;JMP
+(Sys.init)
+@Main.main
+0;JMP";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+        assert_eq!(warnings(&preprocessor), []);
+
+        let source = "@Main.main\n0;JMP\n(Sys.init)\n(Main.main)\n@Main.main\n0;JMP";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+        assert_eq!(warnings(&preprocessor), []);
+    }
+
+    #[test]
+    fn flags_variables_spilled_into_the_screen() {
+        let source = ".data\n.fill 16367\n.text\n@i\n@j\n@k\n@j";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.preprocess(&mut nodes).unwrap();
+
+        let warnings = warnings(&preprocessor);
+        assert_eq!(
+            warnings,
+            [
+                SymbolWarning::SpilledVariable {
+                    name: "j".into(),
+                    line: 5,
+                    address: 16384
+                },
+                SymbolWarning::SpilledVariable {
+                    name: "k".into(),
+                    line: 6,
+                    address: 16385
+                },
+            ]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "[line 5] @j became the variable RAM[16384], past RAM[16383] into the screen \
+             memory: the data words and variables before it fill the RAM for them"
+        );
+    }
+}
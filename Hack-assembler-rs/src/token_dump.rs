@@ -0,0 +1,98 @@
+//! Tokens as JSON, for syntax highlighters and editor plugins that want to reuse
+//! the scanner rather than re-implement it.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::scanner::{Token, TokenType};
+
+#[derive(Debug, Serialize)]
+pub struct TokenDump {
+    files: Vec<FileTokens>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileTokens {
+    file: String,
+    tokens: Vec<TokenRecord>,
+}
+
+/// `start` and `end` are byte offsets, `column` counts characters from 1.
+#[derive(Debug, Serialize)]
+struct TokenRecord {
+    kind: &'static str,
+    lexeme: String,
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+}
+
+impl TokenDump {
+    pub fn new() -> Self {
+        Self { files: vec![] }
+    }
+
+    /// Every token of the file at `path` but the end of file.
+    pub fn add(&mut self, path: &Path, tokens: &[Token<'_>]) {
+        let tokens = tokens
+            .iter()
+            .filter(|token| !matches!(token.token_type, TokenType::EOF))
+            .map(|token| TokenRecord {
+                kind: token.token_type.kind(),
+                lexeme: token.lexeme.to_string(),
+                line: token.line,
+                column: token.column,
+                start: token.span.start,
+                end: token.span.end,
+            })
+            .collect();
+
+        self.files.push(FileTokens {
+            file: path.display().to_string(),
+            tokens,
+        });
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        println!("[<-] Tokens: {}", path.display());
+
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(&mut file)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod token_dump_tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn records_positions_and_byte_offsets() {
+        let source = "// é\n@i\n  D=M;JGT";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let tokens = tokens.unwrap();
+
+        let mut dump = TokenDump::new();
+        dump.add(Path::new("Max.asm"), &tokens);
+        let json = serde_json::to_value(&dump).unwrap();
+        let tokens = json["files"][0]["tokens"].as_array().unwrap();
+
+        assert_eq!(tokens.len(), 7);
+        assert_eq!(
+            tokens[1],
+            serde_json::json!({
+                "kind": "identifier", "lexeme": "i", "line": 2, "column": 2, "start": 7, "end": 8
+            })
+        );
+        assert_eq!(tokens[6]["kind"], "jump");
+        assert_eq!(tokens[6]["column"], 7);
+        assert_eq!(&source[tokens[6]["start"].as_u64().unwrap() as usize..], "JGT");
+    }
+}
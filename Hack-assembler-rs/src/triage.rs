@@ -0,0 +1,125 @@
+//! What `--run` reports about a program that used up its `--max-steps` without
+//! halting, so that an infinite loop can be found without stepping through it: where
+//! the PC is, the calls that led there and the last jumps taken, each as a line of
+//! the `.asm` source and the function around it, and the `.vm` and `.jack` lines of
+//! the line maps given.
+//!
+//! The call stack is that of [`crate::call_stack`].
+
+use n2t_core::line_map::LineMap;
+
+use crate::call_stack::{self, Functions, describe};
+use crate::emulator::Cpu;
+use crate::parser::Address;
+use crate::source_map::SourceMap;
+
+/// Lines of the report, without the `[~]` prefix. A jump taken several times in a
+/// row is listed once, with its count.
+pub fn report(
+    cpu: &Cpu,
+    map: &SourceMap,
+    line_maps: &[LineMap],
+    functions: &Functions,
+) -> Vec<String> {
+    let mut lines = vec!["Call stack, innermost first:".to_string()];
+    for line in call_stack::lines(cpu, map, line_maps, functions) {
+        lines.push(format!("  {line}"));
+    }
+
+    let mut runs: Vec<((Address, Address), usize)> = vec![];
+    for &branch in cpu.branches() {
+        match runs.last_mut() {
+            Some((last, count)) if *last == branch => *count += 1,
+            _ => runs.push((branch, 1)),
+        }
+    }
+    let taken: usize = runs.iter().map(|(_, count)| count).sum();
+    lines.push(format!("Last jumps taken ({taken}), latest last:"));
+    for ((from, to), count) in runs {
+        let times = if count > 1 {
+            format!(" ({count} times)")
+        } else {
+            String::new()
+        };
+        lines.push(format!(
+            "  {} -> {}{times}",
+            describe(map, line_maps, from),
+            describe(map, line_maps, to)
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod triage_tests {
+    use super::*;
+    use crate::{
+        assembler::Assembler, parser::Parser, preprocessor::Preprocessor, scanner::Scanner,
+    };
+
+    fn load(source: &str) -> (Cpu, SourceMap) {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let mut nodes = nodes.unwrap();
+        let map = SourceMap::of("Prog.asm", &nodes);
+        Preprocessor::new().preprocess(&mut nodes).unwrap();
+
+        (Cpu::new(Assembler::new(nodes).assemble().unwrap()), map)
+    }
+
+    #[test]
+    fn reports_where_the_loop_is() {
+        let (mut cpu, map) = load(
+            "(Sys.init)
+@Main.main
+0;JMP
+(Main.main)
+@Main.spin
+0;JMP
+(Main.spin)
+(Main.spin$LOOP)
+D=D+1
+@Main.spin$LOOP
+0;JMP",
+        );
+        cpu.run(100);
+
+        assert_eq!(
+            report(&cpu, &map, &[], &Functions::default()),
+            [
+                "Call stack, innermost first:",
+                "  #0 (?) at ROM[4], Prog.asm:9 in Main.spin, THIS 0, THAT 0",
+                "Last jumps taken (16), latest last:",
+                "  ROM[6], Prog.asm:11 in Main.spin -> ROM[4], Prog.asm:9 in Main.spin (16 times)",
+            ]
+        );
+
+        let (mut cpu, map) = load("@1\nD=A\n(LOOP)\n@LOOP\nD;JGT");
+        cpu.run(4);
+        assert_eq!(
+            report(&cpu, &map, &[], &Functions::default()),
+            [
+                "Call stack, innermost first:",
+                "  #0 (?) at ROM[2], Prog.asm:4 in LOOP, THIS 0, THAT 0",
+                "Last jumps taken (1), latest last:",
+                "  ROM[3], Prog.asm:5 in LOOP -> ROM[2], Prog.asm:4 in LOOP",
+            ]
+        );
+    }
+
+    #[test]
+    fn traces_the_lines_back_to_the_sources() {
+        let (mut cpu, map) = load("(Main.spin)\nD=D+1\n@Main.spin\n0;JMP");
+        cpu.run(9);
+        let mut vm_map = LineMap::default();
+        vm_map.push("Prog.asm", 1, "Main.vm", 4);
+        let mut jack_map = LineMap::default();
+        jack_map.push("Main.vm", 1, "Main.jack", 7);
+
+        assert_eq!(
+            report(&cpu, &map, &[vm_map, jack_map], &Functions::default())[1],
+            "  #0 (?) at ROM[0], Prog.asm:2, Main.vm:4, Main.jack:7 in Main.spin, THIS 0, THAT 0",
+        );
+    }
+}
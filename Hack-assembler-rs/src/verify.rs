@@ -0,0 +1,310 @@
+//! Re-runs the pipeline recorded in a `.hack.json` sidecar and compares every stage
+//! against the recorded hashes.
+
+use std::fs::{read, read_to_string};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, bail};
+
+use crate::assembler::{Assembler, to_hack_bin, to_hack_text};
+use crate::include::Sources;
+use crate::parser::{Address, Parser};
+use crate::preprocessor::Preprocessor;
+use crate::provenance::{Provenance, SOURCE_PREFIX, sha256_hex};
+use crate::scanner::Scanner;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Same,
+    Diverged(String),
+    Skipped(String),
+}
+
+#[derive(Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// Prints every stage and fails if any of them diverged.
+pub fn run(provenance_path: &Path, translator: &str) -> anyhow::Result<()> {
+    let stages = verify(provenance_path, translator)?;
+
+    for stage in stages.iter() {
+        match &stage.outcome {
+            Outcome::Same => println!("[~] {}: same", stage.name),
+            Outcome::Diverged(reason) => println!("[~] {}: DIVERGED, {}", stage.name, reason),
+            Outcome::Skipped(reason) => println!("[~] {}: skipped, {}", stage.name, reason),
+        }
+    }
+
+    if let Some(stage) = stages
+        .iter()
+        .find(|stage| matches!(stage.outcome, Outcome::Diverged(_)))
+    {
+        bail!("Stage `{}` diverged", stage.name);
+    }
+
+    Ok(())
+}
+
+/// Stages, in pipeline order:
+/// * `sources` - the `.vm` files still have the hashes recorded by the translator,
+/// * `translate` - translating them again gives the same `.asm` (header aside),
+/// * `asm` - the `.asm` file and the files it includes still have the recorded hashes,
+/// * `assemble` - assembling it again gives the recorded outputs,
+/// * `artifacts` - the outputs on disk still have the recorded hashes.
+pub fn verify(provenance_path: &Path, translator: &str) -> anyhow::Result<Vec<Stage>> {
+    let provenance: Provenance = serde_json::from_str(
+        &read_to_string(provenance_path)
+            .with_context(|| format!("Failed to read {}", provenance_path.display()))?,
+    )?;
+    let Some(asm) = provenance.inputs.first() else {
+        bail!("No input recorded in {}", provenance_path.display());
+    };
+
+    let sources: Vec<(&str, &str)> = provenance
+        .source
+        .iter()
+        .filter_map(|line| {
+            let (path, hash) = line.strip_prefix("input=")?.split_once(" sha256=")?;
+            Some((path, hash))
+        })
+        .collect();
+    let translator_flags = provenance
+        .source
+        .iter()
+        .find_map(|line| line.strip_prefix("flags="))
+        .unwrap_or_default();
+
+    let mut stages = vec![];
+
+    let sources_outcome = if sources.is_empty() {
+        Outcome::Skipped("no translator provenance in the .asm".to_string())
+    } else {
+        sources
+            .iter()
+            .find_map(|(path, hash)| compare_file(path, hash))
+            .unwrap_or(Outcome::Same)
+    };
+    stages.push(Stage {
+        name: "sources",
+        outcome: sources_outcome,
+    });
+
+    stages.push(Stage {
+        name: "translate",
+        outcome: if sources.is_empty() {
+            Outcome::Skipped("no translator provenance in the .asm".to_string())
+        } else {
+            compare_translation(&asm.path, &sources, translator, translator_flags)
+        },
+    });
+
+    stages.push(Stage {
+        name: "asm",
+        outcome: provenance
+            .inputs
+            .iter()
+            .find_map(|input| compare_file(&input.path, &input.sha256))
+            .unwrap_or(Outcome::Same),
+    });
+
+    let assemble_outcome = match Sources::read(&[PathBuf::from(&asm.path)])
+        .and_then(|sources| assemble(sources.text()))
+    {
+        Err(e) => Outcome::Diverged(format!("{} does not assemble: {e}", asm.path)),
+        Ok(words) => {
+            let fresh = [
+                sha256_hex(to_hack_text(&words).as_bytes()),
+                sha256_hex(&to_hack_bin(&words)),
+            ];
+            provenance
+                .outputs
+                .iter()
+                .zip(fresh)
+                .find(|(output, fresh)| output.sha256 != *fresh)
+                .map(|(output, _)| Outcome::Diverged(format!("{} would change", output.path)))
+                .unwrap_or(Outcome::Same)
+        }
+    };
+    stages.push(Stage {
+        name: "assemble",
+        outcome: assemble_outcome,
+    });
+
+    stages.push(Stage {
+        name: "artifacts",
+        outcome: provenance
+            .outputs
+            .iter()
+            .find_map(|output| compare_file(&output.path, &output.sha256))
+            .unwrap_or(Outcome::Same),
+    });
+
+    Ok(stages)
+}
+
+/// `None` when the file still has the recorded hash.
+fn compare_file(path: &str, sha256: &str) -> Option<Outcome> {
+    match read(path) {
+        Err(e) => Some(Outcome::Diverged(format!("{path}: {e}"))),
+        Ok(bytes) if sha256_hex(&bytes) != sha256 => {
+            Some(Outcome::Diverged(format!("{path} has changed")))
+        }
+        Ok(_) => None,
+    }
+}
+
+/// Translates the sources one by one, in the recorded order, with the recorded flags.
+fn compare_translation(
+    asm_path: &str,
+    sources: &[(&str, &str)],
+    translator: &str,
+    flags: &str,
+) -> Outcome {
+    let output = std::env::temp_dir().join(format!("hack-verify-{}.asm", std::process::id()));
+    let _ = std::fs::remove_file(&output);
+
+    for (path, _) in sources {
+        let status = Command::new(translator)
+            .arg(path)
+            .arg("-o")
+            .arg(&output)
+            .args(flags.split_whitespace())
+            .output();
+        match status {
+            Err(e) => return Outcome::Skipped(format!("cannot run `{translator}`: {e}")),
+            Ok(status) if !status.status.success() => {
+                let _ = std::fs::remove_file(&output);
+                return Outcome::Diverged(format!("`{translator}` failed on {path}"));
+            }
+            Ok(_) => {}
+        }
+    }
+
+    let fresh = read_to_string(&output).unwrap_or_default();
+    let _ = std::fs::remove_file(&output);
+    let Ok(recorded) = read_to_string(asm_path) else {
+        return Outcome::Diverged(format!("cannot read {asm_path}"));
+    };
+
+    let recorded: Vec<&str> = recorded
+        .lines()
+        .filter(|line| !line.trim().starts_with(SOURCE_PREFIX))
+        .collect();
+    let fresh: Vec<&str> = fresh.lines().collect();
+    if recorded == fresh {
+        return Outcome::Same;
+    }
+
+    let line = recorded
+        .iter()
+        .zip(fresh.iter())
+        .take_while(|(recorded, fresh)| recorded == fresh)
+        .count()
+        + 1;
+    Outcome::Diverged(format!(
+        "{asm_path} differs from a fresh translation at line {line}"
+    ))
+}
+
+fn assemble(source: &str) -> anyhow::Result<Vec<Address>> {
+    let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+    let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+    let mut nodes = nodes?;
+    Preprocessor::new().preprocess(&mut nodes)?;
+
+    Assembler::new(nodes).assemble()
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+    use crate::provenance::Artifact;
+
+    const MISSING_TRANSLATOR: &str = "/nonexistent/VMTranslator";
+
+    /// Writes `Prog.vm`, `Prog.asm` (with a translator header), `Prog.hack` and its sidecar.
+    fn project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-verify-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| dir.join(file).display().to_string();
+
+        let vm = "push constant 7\n";
+        std::fs::write(path("Prog.vm"), vm).unwrap();
+
+        let asm = format!(
+            "{SOURCE_PREFIX}flags=-O 0\n{SOURCE_PREFIX}input={} sha256={}\n@7\nD=A\n",
+            path("Prog.vm"),
+            sha256_hex(vm.as_bytes())
+        );
+        std::fs::write(path("Prog.asm"), &asm).unwrap();
+
+        let hack = to_hack_text(&assemble(&asm).unwrap());
+        std::fs::write(path("Prog.hack"), &hack).unwrap();
+
+        let mut provenance = Provenance::new(
+            vec![],
+            Artifact::new(path("Prog.asm"), asm.as_bytes()),
+            &asm,
+        );
+        provenance
+            .outputs
+            .push(Artifact::new(path("Prog.hack"), hack.as_bytes()));
+        std::fs::write(
+            path("Prog.hack.json"),
+            serde_json::to_string(&provenance).unwrap(),
+        )
+        .unwrap();
+
+        dir
+    }
+
+    fn outcomes(dir: &Path) -> Vec<(&'static str, Outcome)> {
+        verify(&dir.join("Prog.hack.json"), MISSING_TRANSLATOR)
+            .unwrap()
+            .into_iter()
+            .map(|stage| (stage.name, stage.outcome))
+            .collect()
+    }
+
+    fn diverged(dir: &Path) -> Vec<&'static str> {
+        outcomes(dir)
+            .into_iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Diverged(_)))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_project() {
+        let dir = project("unchanged");
+        let outcomes = outcomes(&dir);
+
+        let names: Vec<_> = outcomes.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            ["sources", "translate", "asm", "assemble", "artifacts"]
+        );
+        assert!(matches!(outcomes[1].1, Outcome::Skipped(_)));
+        assert!(diverged(&dir).is_empty());
+    }
+
+    #[test]
+    fn reports_changed_stage() {
+        let dir = project("changed");
+
+        std::fs::write(dir.join("Prog.vm"), "push constant 8\n").unwrap();
+        assert_eq!(diverged(&dir), ["sources"]);
+
+        std::fs::write(dir.join("Prog.hack"), "0000000000000000").unwrap();
+        assert_eq!(diverged(&dir), ["sources", "artifacts"]);
+
+        // A different program: both the recorded hash and the re-assembled output disagree.
+        let asm = read_to_string(dir.join("Prog.asm")).unwrap();
+        std::fs::write(dir.join("Prog.asm"), asm.replace("@7", "@8")).unwrap();
+        assert_eq!(diverged(&dir), ["sources", "asm", "assemble", "artifacts"]);
+    }
+}
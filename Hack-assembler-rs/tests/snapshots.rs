@@ -0,0 +1,57 @@
+//! Golden outputs: every `.asm` file of `input/` is assembled and compared with its
+//! `.hack` file in `output/`.
+//!
+//! `cargo test --test snapshots -- --bless` writes the outputs that changed instead,
+//! so changes of the machine code show up in the diff for review; the harness is
+//! [`n2t_core::snapshot`].
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitCode};
+
+use anyhow::Context;
+use n2t_core::snapshot;
+
+const INPUT_DIR: &str = "input";
+const SNAPSHOT_DIR: &str = "output";
+
+fn main() -> ExitCode {
+    snapshot::main("hack", |args, temp_dir| {
+        let fixtures = snapshot::fixtures(INPUT_DIR, |path| {
+            path.extension().is_some_and(|ext| ext == "asm")
+        })?;
+
+        let mut failed = 0;
+        for fixture in fixtures.iter() {
+            let name = fixture.file_stem().unwrap_or_default().to_string_lossy();
+            if !args.selects(&name) {
+                continue;
+            }
+
+            let golden = Path::new(SNAPSHOT_DIR).join(format!("{name}.hack"));
+            let actual = assemble(fixture, &temp_dir.join(format!("{name}.hack")))?;
+            if !snapshot::check(&name, &golden, &actual, args.bless)? {
+                failed += 1;
+            }
+        }
+
+        Ok(failed)
+    })
+}
+
+fn assemble(input: &Path, output: &Path) -> anyhow::Result<String> {
+    let run = Command::new(env!("CARGO_BIN_EXE_hack-assembler-rs"))
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .output()?;
+    if !run.status.success() {
+        anyhow::bail!(
+            "Could not assemble {}:\n{}",
+            input.display(),
+            String::from_utf8_lossy(&run.stderr)
+        );
+    }
+
+    fs::read_to_string(output).with_context(|| format!("No output for {}", input.display()))
+}
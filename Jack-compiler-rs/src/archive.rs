@@ -0,0 +1,167 @@
+//! `.zip` inputs, as submissions usually arrive: extracted to a fresh directory under
+//! the system's temp directory, where the project is compiled or run.
+
+use std::fs::{File, create_dir_all, read_dir, remove_dir_all};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use n2t_core::files::has_extension;
+use zip::ZipArchive;
+
+/// Directory an archive was extracted to, removed on drop unless [`Sandbox::keep`]
+/// is called.
+pub struct Sandbox {
+    dir: PathBuf,
+    /// Directory of the archive holding the sources.
+    pub project: PathBuf,
+    keep: bool,
+}
+
+impl Sandbox {
+    /// Extracts `input` when it is a `.zip` file, and finds the one directory of the
+    /// archive with files of one of the `source_exts`; `None` for any other input.
+    pub fn open(input: &Path, source_exts: &[String]) -> anyhow::Result<Option<Sandbox>> {
+        if !has_extension(input, &["zip".to_string()]) || !input.is_file() {
+            return Ok(None);
+        }
+
+        let name = crate::filename(input).to_string_lossy().into_owned();
+        let dir = std::env::temp_dir().join(format!("jack-{name}-{}", std::process::id()));
+        if dir.exists() {
+            remove_dir_all(&dir)?;
+        }
+        create_dir_all(&dir)?;
+        let mut sandbox = Sandbox {
+            project: dir.clone(),
+            dir,
+            keep: false,
+        };
+
+        // Entries escaping the directory, such as `../x`, are rejected
+        ZipArchive::new(File::open(input)?)
+            .and_then(|mut archive| archive.extract(&sandbox.dir))
+            .with_context(|| format!("Failed to extract {}", input.display()))?;
+        println!(
+            "[->] Extracted {} to {}",
+            input.display(),
+            sandbox.dir.display()
+        );
+
+        let mut projects = vec![];
+        source_dirs(&sandbox.dir, source_exts, &mut projects)?;
+        sandbox.project = match &projects[..] {
+            [project] => project.clone(),
+            [] => anyhow::bail!("{} holds no sources", input.display()),
+            _ => {
+                let projects: Vec<_> = projects
+                    .iter()
+                    .map(|project| {
+                        let relative = project.strip_prefix(&sandbox.dir).unwrap_or(project);
+                        relative.display().to_string()
+                    })
+                    .collect();
+                anyhow::bail!(
+                    "{} holds sources in several directories: {}",
+                    input.display(),
+                    projects.join(", ")
+                );
+            }
+        };
+
+        Ok(Some(sandbox))
+    }
+
+    /// Leaves the directory in place once dropped, for the outputs written there.
+    pub fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Directories under `dir`, itself included, with files of one of the `exts`; the
+/// `__MACOSX` metadata archivers add is skipped.
+fn source_dirs(dir: &Path, exts: &[String], found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut entries: Vec<_> = read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<_>>()?;
+    entries.sort();
+
+    if entries
+        .iter()
+        .any(|path| path.is_file() && has_extension(path, exts))
+    {
+        found.push(dir.to_path_buf());
+    }
+    for path in entries {
+        if path.is_dir() && !path.ends_with("__MACOSX") {
+            source_dirs(&path, exts, found)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use std::io::Write;
+
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn zip(path: &Path, files: &[(&str, &str)]) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn finds_the_project_in_an_archive() {
+        let dir = std::env::temp_dir().join(format!("jack-archive-test-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let exts = ["jack".to_string()];
+
+        let path = dir.join("Submission.zip");
+        zip(
+            &path,
+            &[
+                ("Submission/Square/Main.jack", "class Main {}"),
+                ("Submission/Square/Square.JACK", "class Square {}"),
+                ("Submission/README.txt", ""),
+                ("__MACOSX/Submission/Square/._Main.jack", ""),
+            ],
+        );
+        let sandbox = Sandbox::open(&path, &exts).unwrap().unwrap();
+        assert!(sandbox.project.ends_with("Submission/Square"));
+        assert!(sandbox.project.join("Main.jack").is_file());
+        let extracted = sandbox.dir.clone();
+        drop(sandbox);
+        assert!(!extracted.exists());
+
+        zip(&path, &[("A/Main.jack", ""), ("B/Main.jack", "")]);
+        let error = Sandbox::open(&path, &exts).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{} holds sources in several directories: A, B",
+                path.display()
+            )
+        );
+
+        assert!(Sandbox::open(&dir, &exts).unwrap().is_none());
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
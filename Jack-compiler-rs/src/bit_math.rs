@@ -0,0 +1,128 @@
+//! `--arithmetic bits`: `*` and `/` compiled to calls of a `BitMath` class written
+//! with `+`, `&` and comparisons only, compiled along with the program, so that they
+//! need no OS.
+
+pub const CLASS_NAME: &str = "BitMath";
+
+/// Dividing by zero gives 32767 or -32767, as there is no `Sys.error` to call.
+pub const SOURCE: &str = "\
+/** Multiplication and division without the OS, generated by the compiler. */
+class BitMath {
+
+    /** x * y, adding x shifted left for every bit set in y. */
+    function int multiply(int x, int y) {
+        var int sum, bit;
+        let bit = 1;
+        while (~(bit = 0)) {
+            if (~((y & bit) = 0)) {
+                let sum = sum + x;
+            }
+            let x = x + x;
+            let bit = bit + bit;
+        }
+        return sum;
+    }
+
+    /** x / y rounded toward zero, by long division over the 15 bits of |x|. */
+    function int divide(int x, int y) {
+        var int quotient, remainder, i;
+        var boolean negative;
+        let negative = ~((x < 0) = (y < 0));
+        if (x < 0) {
+            let x = -x;
+        }
+        if (y < 0) {
+            let y = -y;
+        }
+        if (y > 16383) {
+            // Doubling the remainder would overflow, and the quotient is 0 or 1
+            if (~(x < y)) {
+                let quotient = 1;
+            }
+        } else {
+            while (i < 15) {
+                let remainder = remainder + remainder;
+                if (~((x & 16384) = 0)) {
+                    let remainder = remainder + 1;
+                }
+                let x = x + x;
+                let quotient = quotient + quotient;
+                if (~(remainder < y)) {
+                    let remainder = remainder - y;
+                    let quotient = quotient + 1;
+                }
+                let i = i + 1;
+            }
+        }
+        if (negative) {
+            return -quotient;
+        }
+        return quotient;
+    }
+}
+";
+
+#[cfg(test)]
+mod bit_math_tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, InterpreterConfig};
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    const VALUES: [i16; 12] = [
+        0, 1, -1, 3, -7, 100, -255, 181, 16384, -20000, 32767, -32767,
+    ];
+
+    /// What `BitMath.<function>` returns for every pair of `VALUES`, space separated.
+    fn run(function: &str, pairs: &[(i16, i16)]) -> String {
+        let calls: Vec<_> = pairs
+            .iter()
+            .map(|(x, y)| {
+                format!("do Output.printInt({CLASS_NAME}.{function}({x}, {y})); do Output.printChar(32);")
+            })
+            .collect();
+        let main = format!(
+            "class Main {{ function void main() {{ {} return; }} }}",
+            calls.join(" ")
+        );
+
+        let sources = [main.as_str(), SOURCE];
+        let mut classes = vec![];
+        for source in sources {
+            let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+            let parsed: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+            classes.extend(parsed.unwrap());
+        }
+        let mut interpreter = Interpreter::new(&classes, InterpreterConfig::default()).unwrap();
+        interpreter.run().unwrap();
+
+        interpreter.output().to_string()
+    }
+
+    fn pairs() -> Vec<(i16, i16)> {
+        VALUES
+            .iter()
+            .flat_map(|&x| VALUES.iter().map(move |&y| (x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn multiplies_like_the_os() {
+        let pairs = pairs();
+        let expected: String = pairs
+            .iter()
+            .map(|(x, y)| format!("{} ", x.wrapping_mul(*y)))
+            .collect();
+
+        assert_eq!(run("multiply", &pairs), expected);
+    }
+
+    #[test]
+    fn divides_like_the_os() {
+        let pairs: Vec<_> = pairs().into_iter().filter(|&(_, y)| y != 0).collect();
+        let expected: String = pairs.iter().map(|(x, y)| format!("{} ", x / y)).collect();
+
+        assert_eq!(run("divide", &pairs), expected);
+        assert_eq!(run("divide", &[(5, 0), (-5, 0)]), "32767 -32767 ");
+    }
+}
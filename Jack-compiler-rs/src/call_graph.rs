@@ -0,0 +1,233 @@
+//! Words of the RAM[256..2047] stack a program may take: the operands of each
+//! subroutine, found by walking its VM code, added up along the calls it makes.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::metrics::ClassMetrics;
+
+/// Return address, `LCL`, `ARG`, `THIS` and `THAT`, saved by every `call`.
+pub const FRAME_WORDS: usize = 5;
+
+/// A `call` of a subroutine's code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub callee: String,
+    /// Operands on the stack when the call is made, its arguments included.
+    pub operands: usize,
+}
+
+/// What the code of a subroutine keeps on the stack.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operands {
+    pub locals: usize,
+    /// Most operands on the stack at once.
+    pub max: usize,
+    pub calls: Vec<Call>,
+}
+
+/// The operands of `instructions`, the code of one subroutine. Statements leave
+/// the stack as they found it, so the code can be walked in order despite jumps.
+pub fn operands(instructions: &[String]) -> Operands {
+    let mut operands = Operands::default();
+    let mut depth = 0usize;
+
+    for instruction in instructions.iter() {
+        let mut parts = instruction.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("function"), _, Some(locals)) => {
+                operands.locals = locals.parse().unwrap_or(0);
+                depth = 0;
+            }
+            (Some("push"), ..) => depth += 1,
+            (Some("pop" | "add" | "sub" | "and" | "or" | "eq" | "gt" | "lt" | "if-goto"), ..)
+            | (Some("return"), ..) => depth = depth.saturating_sub(1),
+            (Some("call"), Some(callee), Some(args)) => {
+                operands.calls.push(Call {
+                    callee: callee.to_string(),
+                    operands: depth,
+                });
+                depth = depth.saturating_sub(args.parse().unwrap_or(0)) + 1;
+            }
+            _ => {}
+        }
+        operands.max = operands.max.max(depth);
+    }
+
+    operands
+}
+
+/// The subroutines compiled so far, by full name, and the calls between them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallGraph {
+    subroutines: BTreeMap<String, Operands>,
+}
+
+impl CallGraph {
+    pub fn add(&mut self, metrics: &ClassMetrics) {
+        for subroutine in metrics.subroutine_metrics.iter() {
+            self.subroutines.insert(
+                format!("{}.{}", metrics.class, subroutine.name),
+                Operands {
+                    locals: subroutine.locals,
+                    max: subroutine.max_operand_depth,
+                    calls: subroutine.calls.clone(),
+                },
+            );
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.subroutines.contains_key(name)
+    }
+
+    /// Every subroutine called, compiled with the program or not.
+    pub fn callees(&self) -> BTreeSet<&str> {
+        self.subroutines
+            .values()
+            .flat_map(|operands| operands.calls.iter())
+            .map(|call| call.callee.as_str())
+            .collect()
+    }
+
+    /// Words a call of `name` takes, the calls it makes included: its frame, locals
+    /// and operands. `None` if it may recurse. Subroutines not compiled with the
+    /// program, such as the OS's, only count their frame.
+    pub fn stack_words(&self, name: &str) -> Option<usize> {
+        self.deepest(name).map(|(words, _)| words)
+    }
+
+    /// [`CallGraph::stack_words`] of `name`, and the calls taking that many words,
+    /// starting with `name`.
+    pub fn deepest(&self, name: &str) -> Option<(usize, Vec<String>)> {
+        self.walk(name, &mut BTreeSet::new())
+    }
+
+    fn walk<'a>(
+        &'a self,
+        name: &'a str,
+        active: &mut BTreeSet<&'a str>,
+    ) -> Option<(usize, Vec<String>)> {
+        let Some(operands) = self.subroutines.get(name) else {
+            return Some((FRAME_WORDS, vec![name.to_string()]));
+        };
+        if !active.insert(name) {
+            return None;
+        }
+
+        let mut deepest = (operands.max, vec![]);
+        for call in operands.calls.iter() {
+            let (words, path) = self.walk(&call.callee, active)?;
+            if call.operands + words > deepest.0 {
+                deepest = (call.operands + words, path);
+            }
+        }
+        active.remove(name);
+
+        let (words, mut path) = deepest;
+        path.insert(0, name.to_string());
+
+        Some((FRAME_WORDS + operands.locals + words, path))
+    }
+}
+
+#[cfg(test)]
+mod call_graph_tests {
+    use super::*;
+    use crate::{
+        compiler::Compiler, metrics::class_metrics, parser::Parser, session::Session,
+        tokenizer::Tokenizer,
+    };
+
+    fn graph(source: &str) -> CallGraph {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+        let instructions = Compiler::new(classes.iter()).compile(&mut Session::default());
+
+        let mut graph = CallGraph::default();
+        graph.add(&class_metrics(&classes[0], &instructions));
+
+        graph
+    }
+
+    #[test]
+    fn operands_of_a_subroutine() {
+        let instructions = [
+            "function Main.f 2",
+            "push constant 1",
+            "push local 0",
+            "push local 1",
+            "call Main.g 2",
+            "add",
+            "pop local 0",
+            "push constant 0",
+            "return",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            operands(&instructions),
+            Operands {
+                locals: 2,
+                max: 3,
+                calls: vec![Call {
+                    callee: "Main.g".to_string(),
+                    operands: 3
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn words_add_up_along_the_deepest_calls() {
+        let graph = graph(
+            "class Main {
+                function void main() {
+                    do Main.f(1, 2);
+                    do Output.printInt(Main.g());
+                    return;
+                }
+                function int f(int a, int b) {
+                    var int x;
+                    let x = a + (b * Main.g());
+                    return x;
+                }
+                function int g() {
+                    return 1 + 2;
+                }
+            }",
+        );
+
+        // g: frame, 2 operands
+        assert_eq!(graph.stack_words("Main.g"), Some(FRAME_WORDS + 2));
+        // f: frame, 1 local, `a` and `b` under the call of g
+        assert_eq!(graph.stack_words("Main.f"), Some(FRAME_WORDS + 1 + 2 + 7));
+        assert_eq!(
+            graph.deepest("Main.main"),
+            Some((
+                FRAME_WORDS + 2 + FRAME_WORDS + 1 + 2 + 7,
+                ["Main.main", "Main.f", "Main.g"].map(String::from).to_vec()
+            ))
+        );
+    }
+
+    #[test]
+    fn recursion_has_no_bound() {
+        let graph = graph(
+            "class Main {
+                function void main() {
+                    do Main.fact(5);
+                    return;
+                }
+                function int fact(int n) {
+                    if (n < 2) {
+                        return 1;
+                    }
+                    return n * Main.fact(n - 1);
+                }
+            }",
+        );
+
+        assert_eq!(graph.stack_words("Main.main"), None);
+    }
+}
@@ -1,26 +1,48 @@
 use crate::{
     compiler::{
+        Codegen, LineStarts,
         subroutine_compiler::SubroutineCompiler,
         symbol_table::{ClassSymbolTableState, SymbolTable},
     },
     parser::{Class, ClassVarDec, ClassVarDecKind, Type},
+    session::Session,
     tokenizer::Identifier,
 };
 
 pub(super) struct ClassCompiler<'de> {
     class: &'de Class<'de>,
-    label_index: usize,
+    codegen: Codegen,
     symbol_table: SymbolTable<'de, ClassSymbolTableState>,
     output: Vec<String>,
+    /// Where the code of every subroutine and statement starts, with its line
+    lines: LineStarts,
+    /// Debug diagnostics, handed to the session once the class is compiled
+    notes: Vec<String>,
 }
 
 impl<'de> ClassCompiler<'de> {
-    pub fn compile(class: &'de Class<'de>) -> anyhow::Result<Vec<String>> {
+    pub fn compile(
+        class: &'de Class<'de>,
+        codegen: Codegen,
+        session: &mut Session,
+    ) -> anyhow::Result<Vec<String>> {
+        Self::compile_with_lines(class, codegen, session).map(|(instructions, _)| instructions)
+    }
+
+    /// The instructions, and where the code of every subroutine and statement starts
+    /// in them, with its line in the source.
+    pub fn compile_with_lines(
+        class: &'de Class<'de>,
+        codegen: Codegen,
+        session: &mut Session,
+    ) -> anyhow::Result<(Vec<String>, LineStarts)> {
         let mut compiler = Self {
             class,
-            label_index: 0,
+            codegen,
             symbol_table: SymbolTable::new_class_symbol_table(),
             output: vec![],
+            lines: vec![],
+            notes: vec![],
         };
 
         for class_var_dec in class.class_var_decs.iter() {
@@ -28,12 +50,22 @@ impl<'de> ClassCompiler<'de> {
         }
 
         for subroutine_dec in class.subroutine_decs.iter() {
-            let subroutine_instructions =
+            let (subroutine_instructions, lines) =
                 SubroutineCompiler::compile(&mut compiler, subroutine_dec)?;
+            let offset = compiler.output.len();
+            compiler.lines.extend(
+                lines
+                    .into_iter()
+                    .map(|(start, line)| (offset + start, line)),
+            );
             compiler.output.extend(subroutine_instructions);
         }
 
-        Ok(compiler.output)
+        for note in compiler.notes {
+            session.debug(note);
+        }
+
+        Ok((compiler.output, compiler.lines))
     }
 
     pub(super) fn get_field(&self, key: &'de Identifier<'de>) -> Option<&(&'de Type<'de>, usize)> {
@@ -52,11 +84,12 @@ impl<'de> ClassCompiler<'de> {
         self.class
     }
 
-    pub(super) fn create_new_label(&mut self) -> String {
-        let label = format!("{}_{}", self.class.class_name.0, self.label_index);
-        self.label_index += 1;
+    pub(super) fn debug(&mut self, note: String) {
+        self.notes.push(note);
+    }
 
-        label
+    pub(super) fn codegen(&self) -> Codegen {
+        self.codegen
     }
 
     fn compile_class_var_dec(&mut self, class_var_dec: &'de ClassVarDec<'_>) -> anyhow::Result<()> {
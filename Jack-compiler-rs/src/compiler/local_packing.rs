@@ -0,0 +1,245 @@
+//! `--pack-locals`: the locals of a subroutine whose values are never needed at the
+//! same time share a slot, so its frame takes fewer words.
+//!
+//! Liveness is found on the subroutine's VM code, over the jumps of its `label`,
+//! `goto` and `if-goto` commands. Two locals interfere when one is written while the
+//! other's value is still to be read; the `function` command writes them all with 0,
+//! which the locals read before they are written rely on. The slots are then given
+//! greedily, in the order of the locals.
+
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command<'a> {
+    Label(&'a str),
+    Goto(&'a str),
+    IfGoto(&'a str),
+    Return,
+    Push(usize),
+    Pop(usize),
+    Other,
+}
+
+impl<'a> Command<'a> {
+    fn parse(instruction: &'a str) -> Self {
+        let words: Vec<_> = instruction.split_whitespace().collect();
+        match words[..] {
+            ["label", label] => Self::Label(label),
+            ["goto", label] => Self::Goto(label),
+            ["if-goto", label] => Self::IfGoto(label),
+            ["return"] => Self::Return,
+            ["push", "local", index] => index.parse().map_or(Self::Other, Self::Push),
+            ["pop", "local", index] => index.parse().map_or(Self::Other, Self::Pop),
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Packs the locals of the subroutine whose code, from its `function` command, is
+/// `code`, rewriting the commands in place. Returns the number of locals left, or
+/// `None` when the code is left as it is.
+pub(super) fn pack_locals(code: &mut [String]) -> Option<usize> {
+    let (header, locals) = code.first()?.trim_start().rsplit_once(' ')?;
+    let header = header.to_string();
+    let locals: usize = locals.parse().ok()?;
+    if locals == 0 {
+        return None;
+    }
+
+    let commands: Vec<_> = code.iter().map(|line| Command::parse(line)).collect();
+    let successors = successors(&commands)?;
+
+    // Backwards to a fixpoint: the locals whose value may still be read after each command
+    let mut live_in = vec![BTreeSet::new(); commands.len()];
+    let mut live_out = vec![BTreeSet::new(); commands.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..commands.len()).rev() {
+            let out: BTreeSet<usize> = successors[i]
+                .iter()
+                .flat_map(|&next| live_in[next].iter().copied())
+                .collect();
+            let mut r#in = out.clone();
+            match commands[i] {
+                Command::Push(local) => {
+                    r#in.insert(local);
+                }
+                Command::Pop(local) => {
+                    r#in.remove(&local);
+                }
+                _ => {}
+            }
+            if r#in != live_in[i] {
+                live_in[i] = r#in;
+                changed = true;
+            }
+            live_out[i] = out;
+        }
+    }
+
+    let mut interferences = vec![BTreeSet::new(); locals];
+    let mut interfere = |a: usize, b: usize| {
+        if a != b && a < locals && b < locals {
+            interferences[a].insert(b);
+            interferences[b].insert(a);
+        }
+    };
+    for entry in live_in[0].iter() {
+        for other in live_in[0].iter() {
+            interfere(*entry, *other);
+        }
+    }
+    for (command, out) in commands.iter().zip(&live_out) {
+        if let Command::Pop(local) = *command {
+            for other in out.iter() {
+                interfere(local, *other);
+            }
+        }
+    }
+
+    let used: BTreeSet<usize> = commands
+        .iter()
+        .filter_map(|command| match *command {
+            Command::Push(local) | Command::Pop(local) => Some(local),
+            _ => None,
+        })
+        .collect();
+    if used.iter().any(|&local| local >= locals) {
+        return None;
+    }
+    let mut slots: HashMap<usize, usize> = HashMap::new();
+    for &local in used.iter() {
+        let taken: BTreeSet<usize> = interferences[local]
+            .iter()
+            .filter_map(|other| slots.get(other).copied())
+            .collect();
+        let slot = (0..)
+            .find(|slot| !taken.contains(slot))
+            .expect("A free slot");
+        slots.insert(local, slot);
+    }
+    let packed = slots.values().map(|slot| slot + 1).max().unwrap_or(0);
+
+    let accesses: Vec<_> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match *command {
+            Command::Push(local) => Some((i, "push", local)),
+            Command::Pop(local) => Some((i, "pop", local)),
+            _ => None,
+        })
+        .collect();
+    for (i, access, local) in accesses {
+        let line = &code[i];
+        let pad = &line[..line.len() - line.trim_start().len()];
+        code[i] = format!("{pad}{access} local {}", slots[&local]);
+    }
+    let pad = &code[0][..code[0].len() - code[0].trim_start().len()];
+    code[0] = format!("{pad}{header} {packed}");
+
+    Some(packed)
+}
+
+/// The commands each command may be followed by, or `None` if a jump leaves the code.
+fn successors(commands: &[Command<'_>]) -> Option<Vec<Vec<usize>>> {
+    let labels: HashMap<&str, usize> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match *command {
+            Command::Label(label) => Some((label, i)),
+            _ => None,
+        })
+        .collect();
+    let next = |i: usize| (i + 1 < commands.len()).then_some(i + 1);
+
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| match *command {
+            Command::Goto(label) => Some(vec![*labels.get(label)?]),
+            Command::IfGoto(label) => {
+                Some(next(i).into_iter().chain([*labels.get(label)?]).collect())
+            }
+            Command::Return => Some(vec![]),
+            _ => Some(next(i).into_iter().collect()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod local_packing_tests {
+    use super::*;
+
+    fn pack(code: &[&str]) -> (Option<usize>, Vec<String>) {
+        let mut code: Vec<_> = code.iter().map(|line| line.to_string()).collect();
+        let packed = pack_locals(&mut code);
+
+        (packed, code)
+    }
+
+    #[test]
+    fn locals_used_one_after_the_other_share_a_slot() {
+        let (packed, code) = pack(&[
+            "function Main.main 3",
+            "    push constant 1",
+            "    pop local 0",
+            "    push local 0",
+            "    pop local 1",
+            "    push local 1",
+            "    pop local 2",
+            "    push local 2",
+            "    return",
+        ]);
+
+        assert_eq!(packed, Some(1));
+        assert_eq!(
+            code,
+            [
+                "function Main.main 1",
+                "    push constant 1",
+                "    pop local 0",
+                "    push local 0",
+                "    pop local 0",
+                "    push local 0",
+                "    pop local 0",
+                "    push local 0",
+                "    return",
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_live_across_a_loop_keep_their_slots() {
+        // `local 1` only carries `local 0` to its next value, within a turn of the loop
+        let (packed, code) = pack(&[
+            "function Main.loop 3",
+            "label WHILE",
+            "    push local 0",
+            "    if-goto END",
+            "    push local 0",
+            "    pop local 1",
+            "    push local 1",
+            "    pop local 0",
+            "    goto WHILE",
+            "label END",
+            "    push local 2",
+            "    return",
+        ]);
+
+        // `local 2` is read before it is written, so it keeps the 0 of the entry
+        assert_eq!(packed, Some(2));
+        assert_eq!(code[0], "function Main.loop 2");
+        assert_eq!(code[5], "    pop local 0");
+        assert_eq!(code[10], "    push local 1");
+    }
+
+    #[test]
+    fn unused_locals_take_no_slot() {
+        let (packed, code) = pack(&["function Main.f 4", "    push constant 0", "    return"]);
+
+        assert_eq!(packed, Some(0));
+        assert_eq!(code[0], "function Main.f 0");
+        assert_eq!(pack(&["function Main.f 0", "    return"]).0, None);
+    }
+}
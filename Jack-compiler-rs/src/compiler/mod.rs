@@ -1,14 +1,52 @@
 use std::fmt::Display;
 
-use crate::{compiler::class_compiler::ClassCompiler, parser::Class};
+use crate::{
+    compiler::{class_compiler::ClassCompiler, target::Target},
+    parser::Class,
+    session::{Arithmetic, Booleans, LabelStyle, Session, ThisHandling},
+};
 
 mod class_compiler;
+mod local_packing;
+pub mod snippet;
 mod subroutine_compiler;
 pub(super) mod symbol_table;
+pub mod target;
+
+/// Choices of the generated VM code. The default is the compiler's own; [`Codegen::reference`]
+/// matches the course's reference compiler, so outputs can be compared line by line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Codegen {
+    pub booleans: Booleans,
+    pub label_style: LabelStyle,
+    pub this: ThisHandling,
+    pub arithmetic: Arithmetic,
+    pub target: Target,
+    /// Share the slots of locals whose values are never needed at the same time
+    pub pack_locals: bool,
+}
+
+impl Codegen {
+    pub fn reference() -> Self {
+        Self {
+            booleans: Booleans::Reference,
+            label_style: LabelStyle::Reference,
+            this: ThisHandling::Always,
+            arithmetic: Arithmetic::Os,
+            target: Target::default(),
+            pack_locals: false,
+        }
+    }
+}
+
+/// Where the code of each subroutine and statement starts in the output, with its line.
+type LineStarts = Vec<(usize, usize)>;
 
 pub struct Compiler<'de, I: Iterator<Item = &'de Class<'de>>> {
     nodes: I,
+    codegen: Option<Codegen>,
     output: Vec<String>,
+    lines: LineStarts,
 }
 
 impl<'de, I> Compiler<'de, I>
@@ -18,23 +56,50 @@ where
     pub fn new(nodes: I) -> Self {
         Self {
             nodes,
+            codegen: None,
             output: vec![],
+            lines: vec![],
         }
     }
 
-    pub fn compile(&mut self) -> Vec<String> {
+    /// Compiles with `codegen` instead of the one of the session's options.
+    pub fn with_codegen(mut self, codegen: Codegen) -> Self {
+        self.codegen = Some(codegen);
+        self
+    }
+
+    /// Classes that fail to compile are reported to `session` and left out.
+    pub fn compile(&mut self, session: &mut Session) -> Vec<String> {
         let mut nodes = self.nodes.clone();
+        let codegen = self.codegen.unwrap_or(session.options.codegen);
 
         while let Some(class) = nodes.next() {
-            self.compile_class(class).unwrap();
+            if let Err(e) = self.compile_class(class, codegen, session) {
+                session.error(format!("{}: {e}", class.class_name.0));
+            }
         }
 
         return self.output.clone();
     }
 
-    fn compile_class(&mut self, class: &Class<'_>) -> anyhow::Result<()> {
-        let compiled_class_instructions = ClassCompiler::compile(class)?;
+    /// Where the code of every subroutine and statement compiled starts in the output,
+    /// with its line in the source, in order.
+    pub fn lines(&self) -> &[(usize, usize)] {
+        &self.lines
+    }
+
+    fn compile_class(
+        &mut self,
+        class: &Class<'_>,
+        codegen: Codegen,
+        session: &mut Session,
+    ) -> anyhow::Result<()> {
+        let (compiled_class_instructions, lines) =
+            ClassCompiler::compile_with_lines(class, codegen, session)?;
 
+        let offset = self.output.len();
+        self.lines
+            .extend(lines.into_iter().map(|(start, line)| (offset + start, line)));
         self.output.extend(compiled_class_instructions);
 
         Ok(())
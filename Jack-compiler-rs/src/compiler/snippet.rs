@@ -0,0 +1,376 @@
+//! Compiling a lone expression or statement list, for code that runs inside a
+//! subroutine compiled elsewhere: the REPL, a debugger, unit tests.
+//!
+//! The snippet is wrapped into a class declaring the caller's variables at their
+//! indexes, compiled as usual, and cut out of the compiled subroutine.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    compiler::{ClassCompiler, Codegen},
+    index::SymbolKind,
+    parser::{Class, ClassVarDecKind, Parser, SubroutineDecType},
+    session::{Session, ThisHandling},
+    tokenizer::Tokenizer,
+};
+
+const SNIPPET_NAME: &str = "$snippet";
+
+/// A variable in scope of a snippet, at its index in the VM segment of its kind.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// `int`, `char`, `boolean` or a class name.
+    pub type_name: String,
+    pub index: usize,
+}
+
+/// The variables a snippet may use, as in the subroutine it runs in.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub class_name: String,
+    /// The snippet runs in a method or a constructor, with `this` set. In a method
+    /// argument 0 is the object, so the other arguments start at 1.
+    pub is_method: bool,
+    pub variables: Vec<Variable>,
+    pub codegen: Codegen,
+}
+
+impl Environment {
+    pub fn new(class_name: impl Into<String>) -> Self {
+        Self {
+            class_name: class_name.into(),
+            is_method: false,
+            variables: vec![],
+            codegen: Codegen::default(),
+        }
+    }
+
+    /// The variables in scope in `subroutine`, `Class.name`, of the `classes` of a
+    /// project, at the indexes the compiler gives them without `--pack-locals`. Fields
+    /// are only in scope of methods and constructors.
+    pub fn of_subroutine(classes: &[Class<'_>], subroutine: &str) -> anyhow::Result<Self> {
+        let Some((class_name, name)) = subroutine.split_once('.') else {
+            anyhow::bail!("`{subroutine}` is not of the form `Class.subroutine`");
+        };
+        let Some(class) = classes
+            .iter()
+            .find(|class| class.class_name.0 == class_name)
+        else {
+            anyhow::bail!("Could not find class `{class_name}`");
+        };
+        let Some(subroutine_dec) = class
+            .subroutine_decs
+            .iter()
+            .find(|subroutine_dec| subroutine_dec.subroutine_name.0 == name)
+        else {
+            anyhow::bail!("Could not find `{subroutine}`");
+        };
+
+        let is_method = matches!(
+            subroutine_dec.subroutine_dec_type,
+            SubroutineDecType::Method
+        );
+        let has_this = !matches!(
+            subroutine_dec.subroutine_dec_type,
+            SubroutineDecType::Function
+        );
+        let mut environment = Environment {
+            is_method,
+            ..Environment::new(class_name)
+        };
+        let (mut statics, mut fields) = (0, 0);
+        for class_var_dec in class.class_var_decs.iter() {
+            let (kind, index) = match class_var_dec.class_var_dec_kind {
+                ClassVarDecKind::Static => (SymbolKind::Static, &mut statics),
+                ClassVarDecKind::Field => (SymbolKind::Field, &mut fields),
+            };
+            for var_name in class_var_dec.var_names.iter() {
+                if kind == SymbolKind::Static || has_this {
+                    let type_name = class_var_dec.class_var_dec_type.name();
+                    environment = environment.with_variable(kind, var_name.0, type_name, *index);
+                }
+                *index += 1;
+            }
+        }
+        let first_argument = if is_method { 1 } else { 0 };
+        for (index, (r#type, name)) in subroutine_dec.parameter_list.parameters.iter().enumerate() {
+            environment = environment.with_variable(
+                SymbolKind::Argument,
+                name.0,
+                r#type.name(),
+                first_argument + index,
+            );
+        }
+        let locals = subroutine_dec
+            .subroutine_body
+            .var_decs
+            .iter()
+            .flat_map(|var_dec| var_dec.var_names.iter().map(move |name| (var_dec, name)));
+        for (index, (var_dec, name)) in locals.enumerate() {
+            environment = environment.with_variable(
+                SymbolKind::Local,
+                name.0,
+                var_dec.var_type.name(),
+                index,
+            );
+        }
+
+        Ok(environment)
+    }
+
+    pub fn with_variable(
+        mut self,
+        kind: SymbolKind,
+        name: impl Into<String>,
+        type_name: impl Into<String>,
+        index: usize,
+    ) -> Self {
+        self.variables.push(Variable {
+            name: name.into(),
+            kind,
+            type_name: type_name.into(),
+            index,
+        });
+
+        self
+    }
+}
+
+/// VM code leaving the value of `expression` on the stack.
+pub fn compile_expression(
+    expression: &str,
+    environment: &Environment,
+) -> anyhow::Result<Vec<String>> {
+    let instructions = compile(&format!("return {expression};"), environment)?;
+
+    Ok(strip_return(instructions, 0))
+}
+
+/// VM code of `statements`.
+pub fn compile_statements(
+    statements: &str,
+    environment: &Environment,
+) -> anyhow::Result<Vec<String>> {
+    let instructions = compile(&format!("{statements} return;"), environment)?;
+
+    Ok(strip_return(instructions, 1))
+}
+
+/// The compiled snippet subroutine, without its `function` line and method prologue.
+fn compile(body: &str, environment: &Environment) -> anyhow::Result<Vec<String>> {
+    let mut declarations: BTreeMap<(SymbolKind, usize), &Variable> = BTreeMap::new();
+    for variable in environment.variables.iter() {
+        if !matches!(
+            variable.kind,
+            SymbolKind::Static | SymbolKind::Field | SymbolKind::Argument | SymbolKind::Local
+        ) {
+            anyhow::bail!(
+                "`{}` is a {:?}, not a variable",
+                variable.name,
+                variable.kind
+            );
+        }
+        if variable.kind == SymbolKind::Argument && variable.index == 0 && environment.is_method {
+            anyhow::bail!("Argument 0 of a method is `this`, not `{}`", variable.name);
+        }
+        if let Some(other) = declarations.insert((variable.kind, variable.index), variable) {
+            anyhow::bail!(
+                "`{}` and `{}` are both {:?} {}",
+                other.name,
+                variable.name,
+                variable.kind,
+                variable.index
+            );
+        }
+    }
+
+    let first_argument = if environment.is_method { 1 } else { 0 };
+    let declare = |kind: SymbolKind, first: usize, declare: &dyn Fn(&str, &str) -> String| {
+        let variables: Vec<_> = declarations
+            .iter()
+            .filter(|((other, _), _)| *other == kind)
+            .map(|(_, variable)| *variable)
+            .collect();
+        let count = variables.last().map_or(0, |variable| variable.index + 1);
+
+        // Unused indexes are taken by placeholders, so every variable keeps its own
+        (first..count)
+            .map(
+                |index| match variables.iter().find(|variable| variable.index == index) {
+                    Some(variable) => declare(&variable.type_name, &variable.name),
+                    None => declare("int", &format!("$unused{index}")),
+                },
+            )
+            .collect::<Vec<_>>()
+    };
+
+    let statics = declare(SymbolKind::Static, 0, &|r#type, name| {
+        format!("static {type} {name};")
+    });
+    let fields = declare(SymbolKind::Field, 0, &|r#type, name| {
+        format!("field {type} {name};")
+    });
+    let arguments = declare(SymbolKind::Argument, first_argument, &|r#type, name| {
+        format!("{type} {name}")
+    });
+    let locals = declare(SymbolKind::Local, 0, &|r#type, name| {
+        format!("var {type} {name};")
+    });
+
+    // The declarations stay on the first line, so errors point at the lines of the snippet
+    let source = format!(
+        "class {} {{ {} {} {} int {SNIPPET_NAME}({}) {{ {} {body} }} }}",
+        environment.class_name,
+        statics.join(" "),
+        fields.join(" "),
+        if environment.is_method {
+            "method"
+        } else {
+            "function"
+        },
+        arguments.join(", "),
+        locals.join(" ")
+    );
+
+    let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).collect();
+    let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+    let classes = classes?;
+
+    // The prologue cut off below is always there, and the locals keep the environment's slots
+    let codegen = Codegen {
+        this: ThisHandling::Always,
+        pack_locals: false,
+        ..environment.codegen
+    };
+    let instructions = ClassCompiler::compile(&classes[0], codegen, &mut Session::default())?;
+    let prologue = if environment.is_method { 3 } else { 1 };
+
+    Ok(instructions
+        .into_iter()
+        .skip(prologue)
+        .map(|instruction| instruction.trim().to_string())
+        .collect())
+}
+
+/// Drops the `return` ending the snippet, and the `push constant 0` of a `return;`.
+fn strip_return(mut instructions: Vec<String>, pushed: usize) -> Vec<String> {
+    instructions.truncate(instructions.len().saturating_sub(1 + pushed));
+
+    instructions
+}
+
+#[cfg(test)]
+mod snippet_tests {
+    use super::*;
+
+    #[test]
+    fn variables_are_found_at_the_given_indexes() {
+        let environment = Environment::new("Point")
+            .with_variable(SymbolKind::Field, "y", "int", 1)
+            .with_variable(SymbolKind::Argument, "dx", "int", 2)
+            .with_variable(SymbolKind::Local, "other", "Point", 0)
+            .with_variable(SymbolKind::Static, "count", "int", 3);
+        let environment = Environment {
+            is_method: true,
+            ..environment
+        };
+
+        assert_eq!(
+            compile_expression("(y + dx) + count", &environment).unwrap(),
+            [
+                "push this 1",
+                "push argument 2",
+                "add",
+                "push static 3",
+                "add"
+            ]
+        );
+        assert_eq!(
+            compile_statements("let y = other.getY(); do Output.printInt(y);", &environment)
+                .unwrap(),
+            [
+                "push local 0",
+                "call Point.getY 1",
+                "pop this 1",
+                "push this 1",
+                "call Output.printInt 1",
+                "pop temp 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn takes_the_variables_of_a_subroutine() {
+        let source = "class P {
+            static int count;
+            field int x, y;
+            constructor P new(int ax) { let x = ax; return this; }
+            method int sum(int d) { var int t; var P other, next; return d; }
+            function int twice(int n) { return n; }
+        }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+
+        let environment = Environment::of_subroutine(&classes, "P.sum").unwrap();
+        assert!(environment.is_method);
+        assert_eq!(
+            compile_expression("((y + d) + next.sum(count)) + x", &environment).unwrap(),
+            [
+                "push this 1",
+                "push argument 1",
+                "add",
+                "push local 2",
+                "push static 0",
+                "call P.sum 2",
+                "add",
+                "push this 0",
+                "add"
+            ]
+        );
+
+        let environment = Environment::of_subroutine(&classes, "P.new").unwrap();
+        assert!(!environment.is_method);
+        assert_eq!(
+            compile_expression("y + ax", &environment).unwrap(),
+            ["push this 1", "push argument 0", "add"]
+        );
+
+        let environment = Environment::of_subroutine(&classes, "P.twice").unwrap();
+        assert_eq!(
+            compile_expression("x", &environment)
+                .unwrap_err()
+                .to_string(),
+            "Could not find x in any symbol table"
+        );
+        assert_eq!(
+            Environment::of_subroutine(&classes, "Q.twice")
+                .unwrap_err()
+                .to_string(),
+            "Could not find class `Q`"
+        );
+    }
+
+    #[test]
+    fn reports_errors_of_the_snippet() {
+        let environment = Environment::new("Main").with_variable(SymbolKind::Local, "x", "int", 0);
+
+        assert_eq!(
+            compile_expression("x + y", &environment)
+                .unwrap_err()
+                .to_string(),
+            "Could not find y in any symbol table"
+        );
+        assert!(compile_statements("let x = ;", &environment).is_err());
+
+        let environment = environment.with_variable(SymbolKind::Local, "y", "int", 0);
+        assert_eq!(
+            compile_expression("x", &environment)
+                .unwrap_err()
+                .to_string(),
+            "`x` and `y` are both Local 0"
+        );
+    }
+}
@@ -1,13 +1,15 @@
 use crate::{
     compiler::{
-        ClassCompiler, Pad,
+        ClassCompiler, LineStarts, Pad, local_packing,
         symbol_table::{SubroutineSymbolTableState, SymbolTable},
+        target::Target,
     },
     parser::{
         DoStatement, Expression, ExpressionList, IfStatement, KeywordConstant, LetStatement, Op,
         ParameterList, ReturnStatement, Statement, Statements, SubroutineCall, SubroutineDec,
         SubroutineDecType, Term, Type, UnaryOp, VarDec, WhileStatement,
     },
+    session::{Arithmetic, Booleans, LabelStyle, ThisHandling},
     tokenizer::{Constant, Identifier},
 };
 use std::fmt::Write;
@@ -22,8 +24,31 @@ pub(super) struct SubroutineCompiler<'de, 'a> {
     class_compiler: &'a mut ClassCompiler<'de>,
     symbol_table: SymbolTable<'de, SubroutineSymbolTableState>,
     output: Vec<String>,
+    /// Where the code of the subroutine and of each statement starts, with its line
+    lines: LineStarts,
 
     pad: Pad,
+    tail_call: Option<TailCall>,
+    subroutine_name: &'de str,
+    /// Number of the next `subroutine_N` label, so editing one subroutine leaves
+    /// the labels of the others as they were
+    label_index: usize,
+    /// Numbers of the next `if` and `while` labels with [`LabelStyle::Reference`]
+    if_index: usize,
+    while_index: usize,
+}
+
+/// Self tail calls rebind the arguments and jump back to the start of the body
+/// instead of pushing a new call frame.
+struct TailCall {
+    subroutine_name: String,
+    is_method: bool,
+    args_cnt: usize,
+    locals_cnt: usize,
+    /// Where the body starts in the output
+    label_at: usize,
+    /// Created on the first self tail call
+    label: Option<String>,
 }
 
 impl<'de, 'a> SubroutineCompiler<'de, 'a> {
@@ -38,18 +63,24 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
     pub fn compile(
         class_compiler: &'a mut ClassCompiler<'de>,
         subroutine_dec: &'de SubroutineDec<'_>,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> anyhow::Result<(Vec<String>, LineStarts)> {
         let mut compiler = Self {
             class_compiler,
             symbol_table: SymbolTable::new_subroutine_symbol_table(),
             output: vec![],
+            lines: vec![],
             pad: Pad::None,
+            tail_call: None,
+            subroutine_name: subroutine_dec.subroutine_name.0,
+            label_index: 0,
+            if_index: 0,
+            while_index: 0,
         };
 
         let class_name = compiler.class_compiler.get_class().class_name.0;
         compiler.compile_subroutine_dec(class_name, subroutine_dec)?;
 
-        Ok(compiler.output)
+        Ok((compiler.output, compiler.lines))
     }
 
     fn compile_subroutine_dec(
@@ -57,6 +88,7 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         class_name: &str,
         subroutine_dec: &'de SubroutineDec<'_>,
     ) -> anyhow::Result<()> {
+        self.lines.push((self.output.len(), subroutine_dec.line));
         {
             let subroutine_name = subroutine_dec.subroutine_name.0;
             let local_args_cnt = subroutine_dec
@@ -77,10 +109,11 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                         self.pad = Pad::One;
                         // calculate the size of an instance
                         let cnt = self.class_compiler.get_fields_cnt();
+                        let target = self.target();
                         write_pad!(self, "push constant {cnt}")?;
-                        write_pad!(self, "call Memory.alloc 1")?;
+                        write_pad!(self, "call {} 1", target.alloc)?;
                         // pop the allocated memory into `this`
-                        write_pad!(self, "pop pointer 0")?;
+                        write_pad!(self, "pop {}", target.this_pointer)?;
                         self.pad = Pad::None;
                     }
                 }
@@ -99,7 +132,7 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                         .insert_argument(&Identifier("this"), &Type::Boolean);
                     write_pad!(self, "push argument 0")?;
                     // pop the allocated memory into `this`
-                    write_pad!(self, "pop pointer 0")?;
+                    write_pad!(self, "pop {}", self.target().this_pointer)?;
                     self.pad = Pad::None;
                 }
             };
@@ -115,6 +148,28 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
             self.pad = Pad::None;
         }
 
+        if !matches!(
+            subroutine_dec.subroutine_dec_type,
+            SubroutineDecType::Constructor
+        ) {
+            self.tail_call = Some(TailCall {
+                subroutine_name: subroutine_dec.subroutine_name.0.to_string(),
+                is_method: matches!(
+                    subroutine_dec.subroutine_dec_type,
+                    SubroutineDecType::Method
+                ),
+                args_cnt: subroutine_dec.parameter_list.parameters.len(),
+                locals_cnt: subroutine_dec
+                    .subroutine_body
+                    .var_decs
+                    .iter()
+                    .map(|var_dec| var_dec.var_names.len())
+                    .sum(),
+                label_at: self.output.len(),
+                label: None,
+            });
+        }
+
         {
             self.pad = Pad::One;
             let statements = &subroutine_dec.subroutine_body.statements;
@@ -122,6 +177,42 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
             self.pad = Pad::None;
         }
 
+        if let Some(TailCall {
+            label_at,
+            label: Some(label),
+            ..
+        }) = self.tail_call.take()
+        {
+            self.output
+                .insert(label_at, format!("{}label {label}", Pad::None));
+            for (start, _) in self
+                .lines
+                .iter_mut()
+                .filter(|(start, _)| *start >= label_at)
+            {
+                *start += 1;
+            }
+        }
+
+        if matches!(
+            subroutine_dec.subroutine_dec_type,
+            SubroutineDecType::Method
+        ) && self.class_compiler.codegen().this == ThisHandling::WhenUsed
+            && !self.output[3..]
+                .iter()
+                .any(|instruction| uses_this(instruction, &self.target()))
+        {
+            // `push argument 0` and `pop pointer 0` after the `function` line
+            self.output.drain(1..3);
+            for (start, _) in self.lines.iter_mut().filter(|(start, _)| *start >= 3) {
+                *start -= 2;
+            }
+        }
+
+        if self.class_compiler.codegen().pack_locals {
+            local_packing::pack_locals(&mut self.output);
+        }
+
         Ok(())
     }
 
@@ -147,7 +238,8 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
     }
 
     fn compile_statements(&mut self, statements: &'de Statements<'_>) -> anyhow::Result<()> {
-        for statement in statements.statements.iter() {
+        for (statement, &line) in statements.statements.iter().zip(&statements.lines) {
+            self.lines.push((self.output.len(), line));
             self.compile_statement(statement)?;
         }
 
@@ -168,26 +260,29 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         }
     }
 
+    fn is_var(&self, var_name: &'de Identifier<'_>) -> bool {
+        self.class_compiler.get_field(var_name).is_some()
+            || self.symbol_table.get_var(var_name).is_some()
+            || self.symbol_table.get_argument(var_name).is_some()
+            || self.class_compiler.get_static(var_name).is_some()
+    }
+
     fn search_var(
-        &self,
+        &mut self,
         var_name: &'de Identifier<'_>,
     ) -> anyhow::Result<(&'static str, usize, Option<&'de str>)> {
         let (var_segment_name, var_segment_index, r#type) = 
         // searching in the class's `fields` symbol table
         if let Some(&(r#type, field_index)) = self.class_compiler.get_field(var_name) {
-            println!(
-                "[debug] Found {:?} in the class's `fields` table",
-                var_name
-            );
+            self.class_compiler
+                .debug(format!("Found {:?} in the class's `fields` table", var_name));
 
             ("this", field_index, r#type)
         } else {
             // Searching in the coroutine's `vars` symbol table
             if let Some(&(r#type, var_index)) = self.symbol_table.get_var(var_name) {
-                println!(
-                    "[debug] Found {:?} in the subroutine's `vars` table",
-                    var_name
-                );
+                self.class_compiler
+                .debug(format!("Found {:?} in the subroutine's `vars` table", var_name));
 
                 ("local", var_index, r#type)
             } else {
@@ -195,10 +290,8 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                 if let Some(&(r#type, arg_index)) =
                     self.symbol_table.get_argument(var_name)
                 {
-                    println!(
-                        "[debug] Found {:?} in the subroutine's `args` table",
-                        var_name
-                    );
+                    self.class_compiler
+                .debug(format!("Found {:?} in the subroutine's `args` table", var_name));
 
                     ("argument", arg_index, r#type)
                 } else {
@@ -206,14 +299,12 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                     if let Some(&(r#type, static_index)) =
                         self.class_compiler.get_static(var_name)
                     {
-                        println!(
-                            "[debug] Found {:?} in the subroutine's `statics` table",
-                            var_name
-                        );
+                        self.class_compiler
+                .debug(format!("Found {:?} in the subroutine's `statics` table", var_name));
 
                         ("static", static_index, r#type)
                     } else {
-                        println!("[debug] Could not complete assignment for the let statement: {:?}. Ok. It's either a class constructor or a class function call", var_name);
+                        self.class_compiler.debug(format!("Could not complete assignment for the let statement: {:?}. Ok. It's either a class constructor or a class function call", var_name));
 
                         anyhow::bail!(
                             "Could not find {} in any symbol table",
@@ -244,10 +335,11 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
             write_pad!(self, "add")?;
 
             self.compile_expression(&let_statement.expression_2)?;
-            write_pad!(self, "pop temp 0")?;
+            let target = self.target();
+            write_pad!(self, "pop {}", target.scratch)?;
 
-            write_pad!(self, "pop pointer 1")?;
-            write_pad!(self, "push temp 0")?;
+            write_pad!(self, "pop {}", target.that_pointer)?;
+            write_pad!(self, "push {}", target.scratch)?;
             write_pad!(self, "pop that 0")
         } else {
             self.compile_expression(&let_statement.expression_2)?;
@@ -256,12 +348,23 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         }
     }
 
+    fn create_new_label(&mut self) -> String {
+        let label = format!("{}_{}", self.subroutine_name, self.label_index);
+        self.label_index += 1;
+
+        label
+    }
+
     fn compile_if_statement(&mut self, if_statement: &'de IfStatement<'_>) -> anyhow::Result<()> {
+        if self.class_compiler.codegen().label_style == LabelStyle::Reference {
+            return self.compile_reference_if_statement(if_statement);
+        }
+
         self.compile_expression(&if_statement.condition)?;
         write_pad!(self, "not")?;
 
-        let label_then = self.class_compiler.create_new_label();
-        let label_else = self.class_compiler.create_new_label();
+        let label_then = self.create_new_label();
+        let label_else = self.create_new_label();
 
         write_pad!(self, "if-goto {label_else}")?;
         self.compile_statements(&if_statement.then_branch)?;
@@ -283,12 +386,58 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         Ok(())
     }
 
+    /// The layout and labels of the course's reference compiler.
+    fn compile_reference_if_statement(
+        &mut self,
+        if_statement: &'de IfStatement<'_>,
+    ) -> anyhow::Result<()> {
+        let index = self.if_index;
+        self.if_index += 1;
+
+        self.compile_expression(&if_statement.condition)?;
+        write_pad!(self, "if-goto IF_TRUE{index}")?;
+        write_pad!(self, "goto IF_FALSE{index}")?;
+        {
+            self.pad = Pad::None;
+            write_pad!(self, "label IF_TRUE{index}")?;
+            self.pad = Pad::One;
+        }
+        self.compile_statements(&if_statement.then_branch)?;
+        if let Some(else_branch) = &if_statement.else_branch {
+            write_pad!(self, "goto IF_END{index}")?;
+            {
+                self.pad = Pad::None;
+                write_pad!(self, "label IF_FALSE{index}")?;
+                self.pad = Pad::One;
+            }
+            self.compile_statements(else_branch)?;
+            {
+                self.pad = Pad::None;
+                write_pad!(self, "label IF_END{index}")?;
+                self.pad = Pad::One;
+            }
+        } else {
+            self.pad = Pad::None;
+            write_pad!(self, "label IF_FALSE{index}")?;
+            self.pad = Pad::One;
+        }
+
+        Ok(())
+    }
+
     fn compile_while_statement(
         &mut self,
         while_statement: &'de WhileStatement<'_>,
     ) -> anyhow::Result<()> {
-        let label_yes = self.class_compiler.create_new_label();
-        let label_no = self.class_compiler.create_new_label();
+        let (label_yes, label_no) = match self.class_compiler.codegen().label_style {
+            LabelStyle::Unique => (self.create_new_label(), self.create_new_label()),
+            LabelStyle::Reference => {
+                let index = self.while_index;
+                self.while_index += 1;
+
+                (format!("WHILE_EXP{index}"), format!("WHILE_END{index}"))
+            }
+        };
 
         {
             self.pad = Pad::None;
@@ -312,7 +461,7 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
     fn compile_do_statement(&mut self, do_statement: &'de DoStatement<'_>) -> anyhow::Result<()> {
         self.compile_subroutine_call(&do_statement.subroutine_call)?;
 
-        write_pad!(self, "pop temp 0")?;
+        write_pad!(self, "pop {}", self.target().scratch)?;
 
         Ok(())
     }
@@ -322,6 +471,10 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         return_statement: &'de ReturnStatement<'_>,
     ) -> anyhow::Result<()> {
         if let Some(expression) = &return_statement.expression {
+            if let Some(expression_list) = self.get_self_tail_call(expression) {
+                return self.compile_self_tail_call(expression_list);
+            }
+
             self.compile_expression(expression)?;
         } else {
             // Add a constant 0 as return value
@@ -333,6 +486,83 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         Ok(())
     }
 
+    /// `return f(..);` calling the subroutine being compiled, with the same `this`.
+    fn get_self_tail_call(
+        &self,
+        expression: &'de Expression<'_>,
+    ) -> Option<&'de ExpressionList<'de>> {
+        let tail_call = self.tail_call.as_ref()?;
+        if !expression.terms.is_empty() {
+            return None;
+        }
+        let Term::SubroutineCall(subroutine_call) = &expression.term else {
+            return None;
+        };
+
+        let (is_self_call, expression_list) = match subroutine_call {
+            SubroutineCall::Call {
+                subroutine_name,
+                expression_list,
+            } => (
+                subroutine_name.0 == tail_call.subroutine_name,
+                expression_list,
+            ),
+            SubroutineCall::ClassCall {
+                class_or_var_name,
+                subroutine_name,
+                expression_list,
+            } => {
+                let class_name = self.class_compiler.get_class().class_name.0;
+                let is_self_call = !tail_call.is_method
+                    && class_or_var_name.0 == class_name
+                    && subroutine_name.0 == tail_call.subroutine_name
+                    && !self.is_var(class_or_var_name);
+
+                (is_self_call, expression_list)
+            }
+        };
+
+        (is_self_call && expression_list.expressions.len() == tail_call.args_cnt)
+            .then_some(expression_list)
+    }
+
+    fn compile_self_tail_call(
+        &mut self,
+        expression_list: &'de ExpressionList<'_>,
+    ) -> anyhow::Result<()> {
+        // All the arguments are evaluated before any of them is rebound
+        self.compile_expression_list(expression_list)?;
+
+        if self.tail_call.as_ref().is_some_and(|t| t.label.is_none()) {
+            let label = self.create_new_label();
+            self.tail_call.as_mut().expect("Self tail call").label = Some(label);
+        }
+        let tail_call = self.tail_call.as_ref().expect("Self tail call");
+        let label = tail_call.label.clone().expect("Created above");
+        let &TailCall {
+            is_method,
+            args_cnt,
+            locals_cnt,
+            ..
+        } = tail_call;
+
+        let first_arg = if is_method {
+            1 /* arg0 - is `this` */
+        } else {
+            0
+        };
+        for i in (0..args_cnt).rev() {
+            write_pad!(self, "pop argument {}", first_arg + i)?;
+        }
+        // As on a fresh call, the locals start at 0
+        for i in 0..locals_cnt {
+            write_pad!(self, "push constant 0")?;
+            write_pad!(self, "pop local {i}")?;
+        }
+
+        write_pad!(self, "goto {label}")
+    }
+
     fn compile_expression_list(
         &mut self,
         expression_list: &'de ExpressionList<'_>,
@@ -361,11 +591,12 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
             Term::Constant(constant) => match constant {
                 Constant::String(cow) => {
                     let len = cow.len();
+                    let target = self.target();
                     write_pad!(self, "push constant {len}")?;
-                    write_pad!(self, "call String.new 1")?;
+                    write_pad!(self, "call {} 1", target.string_new)?;
                     for b in cow.as_bytes() {
                         write_pad!(self, "push constant {}", b)?;
-                        write_pad!(self, "call String.appendChar 2")?;
+                        write_pad!(self, "call {} 2", target.append_char)?;
                     }
 
                     Ok(())
@@ -373,13 +604,19 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                 Constant::Integer(i) => write_pad!(self, "push constant {}", i),
             },
             Term::KeywordConstant(keyword_constant) => match keyword_constant {
-                KeywordConstant::True => {
-                    write_pad!(self, "push constant 1")?;
-                    write_pad!(self, "neg")
-                }
+                KeywordConstant::True => match self.class_compiler.codegen().booleans {
+                    Booleans::Neg => {
+                        write_pad!(self, "push constant 1")?;
+                        write_pad!(self, "neg")
+                    }
+                    Booleans::Reference => {
+                        write_pad!(self, "push constant 0")?;
+                        write_pad!(self, "not")
+                    }
+                },
                 KeywordConstant::False => write_pad!(self, "push constant 0"),
                 KeywordConstant::Null => write_pad!(self, "push constant 0"),
-                KeywordConstant::This => write_pad!(self, "push pointer 0"),
+                KeywordConstant::This => write_pad!(self, "push {}", self.target().this_pointer),
             },
             Term::VarName(identifier) => {
                 let (var_segment_name, var_segment_index, _) = self.search_var(&identifier)?;
@@ -394,7 +631,7 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                 self.compile_expression(expression)?;
                 write_pad!(self, "push {} {}", var_segment_name, var_segment_index)?;
                 write_pad!(self, "add")?;
-                write_pad!(self, "pop pointer 1")?;
+                write_pad!(self, "pop {}", self.target().that_pointer)?;
                 write_pad!(self, "push that 0")
             }
             Term::Expression(expression) => self.compile_expression(expression),
@@ -422,7 +659,7 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
                 subroutine_name,
                 expression_list,
             } => {
-                write_pad!(self, "push pointer 0")?;
+                write_pad!(self, "push {}", self.target().this_pointer)?;
                 self.compile_expression_list(expression_list)?;
 
                 let class_name = self.class_compiler.get_class().class_name.0;
@@ -474,12 +711,25 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         Ok(())
     }
 
+    fn target(&self) -> Target {
+        self.class_compiler.codegen().target
+    }
+
+    /// Class whose `multiply` and `divide` implement `*` and `/`.
+    fn math_class(&self) -> &'static str {
+        match self.class_compiler.codegen().arithmetic {
+            Arithmetic::Os => self.target().math,
+            Arithmetic::Bits => crate::bit_math::CLASS_NAME,
+        }
+    }
+
     fn compile_op(&mut self, op: &Op) -> anyhow::Result<()> {
+        let math_class = self.math_class();
         match op {
             Op::Plus => write_pad!(self, "add"),
             Op::Minus => write_pad!(self, "sub"),
-            Op::Asterisk => write_pad!(self, "call Math.multiply 2"),
-            Op::Slash => write_pad!(self, "call Math.divide 2"),
+            Op::Asterisk => write_pad!(self, "call {math_class}.multiply 2"),
+            Op::Slash => write_pad!(self, "call {math_class}.divide 2"),
             Op::Ampersand => write_pad!(self, "and"),
             Op::Pipe => write_pad!(self, "or"),
             Op::LessThan => write_pad!(self, "lt"),
@@ -488,3 +738,395 @@ impl<'de, 'a> SubroutineCompiler<'de, 'a> {
         }
     }
 }
+
+/// The instruction reads or writes a field, or reads or sets `this`.
+fn uses_this(instruction: &str, target: &Target) -> bool {
+    let mut parts = instruction.split_whitespace().skip(1);
+    match (parts.next(), parts.next()) {
+        (Some("this"), _) => true,
+        (Some(segment), Some(index)) => {
+            segment == target.this_pointer.segment && index == target.this_pointer.index.to_string()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tail_call_tests {
+    use crate::{compiler::Compiler, parser::Parser, session::Session, tokenizer::Tokenizer};
+
+    fn compile(source: &str) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+
+        let mut session = Session::default();
+        let instructions = Compiler::new(classes.iter()).compile(&mut session);
+        assert!(!session.has_errors());
+
+        instructions
+            .into_iter()
+            .map(|instruction| instruction.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn function() {
+        let instructions = compile(
+            "class Main {
+                function int sum(int n, int acc) {
+                    var int t;
+                    if (n = 0) {
+                        return acc;
+                    }
+                    let t = n;
+                    return Main.sum(n - 1, acc + t);
+                }
+            }",
+        );
+
+        assert!(!instructions.contains(&"call Main.sum 2".to_string()));
+        assert_eq!(instructions[1], "label sum_2");
+        assert_eq!(
+            instructions[instructions.len() - 5..],
+            [
+                "pop argument 1",
+                "pop argument 0",
+                "push constant 0",
+                "pop local 0",
+                "goto sum_2",
+            ]
+            .map(String::from)[..]
+        );
+    }
+
+    #[test]
+    fn method() {
+        let instructions = compile(
+            "class Counter {
+                field int count;
+
+                method int countDown(int n) {
+                    if (n = 0) {
+                        return count;
+                    }
+                    let count = count + 1;
+                    return countDown(n - 1);
+                }
+            }",
+        );
+
+        assert!(!instructions.contains(&"call Counter.countDown 2".to_string()));
+        assert_eq!(
+            instructions[instructions.len() - 2..],
+            ["pop argument 1", "goto countDown_2"].map(String::from)[..]
+        );
+    }
+
+    #[test]
+    fn not_a_tail_call() {
+        let instructions = compile(
+            "class Main {
+                function int fact(int n) {
+                    if (n < 2) {
+                        return 1;
+                    }
+                    return n * Main.fact(n - 1);
+                }
+            }",
+        );
+
+        assert!(instructions.contains(&"call Main.fact 1".to_string()));
+        assert!(
+            !instructions
+                .iter()
+                .any(|i| i.starts_with("goto") && i != "goto fact_0")
+        );
+    }
+}
+
+#[cfg(test)]
+mod label_style_tests {
+    use crate::{
+        compiler::{Codegen, Compiler},
+        parser::Parser,
+        session::{LabelStyle, Options, Session},
+        tokenizer::Tokenizer,
+    };
+
+    fn compile(source: &str, label_style: LabelStyle) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+
+        let mut session = Session::new(Options {
+            codegen: Codegen {
+                label_style,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let instructions = Compiler::new(classes.iter()).compile(&mut session);
+        assert!(!session.has_errors());
+
+        instructions
+            .into_iter()
+            .map(|instruction| instruction.trim().to_string())
+            .filter(|instruction| instruction.starts_with("label") || instruction.contains("goto"))
+            .collect()
+    }
+
+    const SOURCE: &str = "class Main {
+        function void a(int n) {
+            while (n > 0) {
+                if (n = 1) {
+                    let n = 0;
+                } else {
+                    let n = n - 1;
+                }
+            }
+            if (n = 0) {
+                return;
+            }
+            return;
+        }
+
+        function void b(int n) {
+            while (n > 0) {
+                let n = n - 1;
+            }
+            return;
+        }
+    }";
+
+    #[test]
+    fn reference_labels_are_numbered_per_subroutine() {
+        assert_eq!(
+            compile(SOURCE, LabelStyle::Reference),
+            [
+                "label WHILE_EXP0",
+                "if-goto WHILE_END0",
+                "if-goto IF_TRUE0",
+                "goto IF_FALSE0",
+                "label IF_TRUE0",
+                "goto IF_END0",
+                "label IF_FALSE0",
+                "label IF_END0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+                "if-goto IF_TRUE1",
+                "goto IF_FALSE1",
+                "label IF_TRUE1",
+                "label IF_FALSE1",
+                "label WHILE_EXP0",
+                "if-goto WHILE_END0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn unique_labels_are_numbered_per_subroutine() {
+        let labels = compile(SOURCE, LabelStyle::default());
+
+        assert_eq!(labels[0], "label a_0");
+        assert_eq!(labels[labels.len() - 1], "label b_1");
+        assert!(labels.contains(&"goto b_0".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod codegen_tests {
+    use crate::{
+        compiler::{
+            Codegen, Compiler,
+            target::{Slot, Target},
+        },
+        parser::Parser,
+        session::{Booleans, Options, Session, ThisHandling},
+        tokenizer::Tokenizer,
+    };
+
+    fn compile(source: &str, session: &mut Session, codegen: Option<Codegen>) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+
+        let mut compiler = Compiler::new(classes.iter());
+        if let Some(codegen) = codegen {
+            compiler = compiler.with_codegen(codegen);
+        }
+        let instructions = compiler.compile(session);
+        assert!(!session.has_errors());
+
+        instructions
+            .into_iter()
+            .map(|instruction| instruction.trim().to_string())
+            .collect()
+    }
+
+    const SOURCE: &str = "class Flag {
+        field boolean on;
+
+        method boolean get() {
+            return on;
+        }
+
+        method boolean yes() {
+            return true;
+        }
+    }";
+
+    #[test]
+    fn booleans() {
+        let codegen = Codegen {
+            booleans: Booleans::Reference,
+            ..Default::default()
+        };
+        let reference = compile(SOURCE, &mut Session::default(), Some(codegen));
+        assert!(reference.ends_with(&["push constant 0", "not", "return"].map(String::from)));
+
+        let neg = compile(SOURCE, &mut Session::default(), None);
+        assert!(neg.ends_with(&["push constant 1", "neg", "return"].map(String::from)));
+    }
+
+    #[test]
+    fn this_is_set_only_when_used() {
+        let codegen = Codegen {
+            this: ThisHandling::WhenUsed,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            compile(SOURCE, &mut Session::default(), Some(codegen)),
+            [
+                "function Flag.get 0",
+                "push argument 0",
+                "pop pointer 0",
+                "push this 0",
+                "return",
+                "function Flag.yes 0",
+                "push constant 1",
+                "neg",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_compiler_codegen_overrides_the_session() {
+        let mut session = Session::new(Options {
+            codegen: Codegen {
+                booleans: Booleans::Reference,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert!(compile(SOURCE, &mut session, None).contains(&"not".to_string()));
+        assert!(
+            !compile(SOURCE, &mut session, Some(Codegen::default())).contains(&"not".to_string())
+        );
+    }
+
+    #[test]
+    fn the_target_names_the_borrowed_words_and_the_os() {
+        let codegen = Codegen {
+            target: Target {
+                scratch: Slot::new("temp", 7),
+                alloc: "Heap.alloc",
+                string_new: "Str.new",
+                append_char: "Str.push",
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let source = "class Box {
+            field int a;
+
+            constructor Box new() {
+                var Array xs;
+                let xs[1] = a;
+                do Output.printString(\"ok\");
+                return this;
+            }
+        }";
+
+        assert_eq!(
+            compile(source, &mut Session::default(), Some(codegen)),
+            [
+                "function Box.new 1",
+                "push constant 1",
+                "call Heap.alloc 1",
+                "pop pointer 0",
+                "push constant 1",
+                "push local 0",
+                "add",
+                "push this 0",
+                "pop temp 7",
+                "pop pointer 1",
+                "push temp 7",
+                "pop that 0",
+                "push constant 2",
+                "call Str.new 1",
+                "push constant 111",
+                "call Str.push 2",
+                "push constant 107",
+                "call Str.push 2",
+                "call Output.printString 1",
+                "pop temp 7",
+                "push pointer 0",
+                "return",
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod line_tests {
+    use crate::{compiler::Compiler, parser::Parser, session::Session, tokenizer::Tokenizer};
+
+    #[test]
+    fn statements_start_where_their_code_does() {
+        let source = "class Main {
+    function int count(int n) {
+        if (n = 0) {
+            return 0;
+        }
+        return Main.count(n - 1);
+    }
+
+    method int half(int n) {
+        return n / 2;
+    }
+}";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+
+        let mut session = Session::default();
+        let mut compiler = Compiler::new(classes.iter());
+        let instructions = compiler.compile(&mut session);
+        assert!(!session.has_errors());
+
+        // The tail call's label goes before the first statement, and the unused
+        // `this` of `half` is dropped
+        let starts: Vec<_> = compiler
+            .lines()
+            .iter()
+            .map(|&(start, line)| (instructions[start].trim(), line))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                ("function Main.count 0", 2),
+                ("push argument 0", 3),
+                ("push constant 0", 4),
+                ("push argument 0", 6),
+                ("function Main.half 0", 9),
+                ("push argument 1", 10),
+            ]
+        );
+    }
+}
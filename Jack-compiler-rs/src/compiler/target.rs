@@ -0,0 +1,60 @@
+//! What the generated VM code assumes beyond the Jack language: the VM words it
+//! borrows, and the OS subroutines it calls. [`Target::default`] is the Hack VM with
+//! the course's OS; another OS ABI or an instrumented runtime changes it here instead
+//! of in the compiler.
+
+use std::fmt::Display;
+
+/// A word of a VM segment, such as `temp 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub segment: &'static str,
+    pub index: u16,
+}
+
+impl Slot {
+    pub const fn new(segment: &'static str, index: u16) -> Self {
+        Self { segment, index }
+    }
+}
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.segment, self.index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    /// Takes the value a `do` discards, and keeps the value of `let a[i] = ...` while
+    /// `that` is set.
+    pub scratch: Slot,
+    /// Base address of the `this` segment.
+    pub this_pointer: Slot,
+    /// Base address of the `that` segment, set for every array access.
+    pub that_pointer: Slot,
+    /// Called with the words of an object, returning its address.
+    pub alloc: &'static str,
+    /// Called with the length of a string constant, returning an empty string.
+    pub string_new: &'static str,
+    /// Method called with each character of a string constant.
+    pub append_char: &'static str,
+    /// Class whose `multiply` and `divide` compute `*` and `/` with [`Arithmetic::Os`].
+    ///
+    /// [`Arithmetic::Os`]: crate::session::Arithmetic::Os
+    pub math: &'static str,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self {
+            scratch: Slot::new("temp", 0),
+            this_pointer: Slot::new("pointer", 0),
+            that_pointer: Slot::new("pointer", 1),
+            alloc: "Memory.alloc",
+            string_new: "String.new",
+            append_char: "String.appendChar",
+            math: "Math",
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Output backends. The driver hands every selected backend the result of each
+//! stage, and each backend writes the ones it knows about.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use n2t_core::line_map::LineMap;
+use serde::Serialize;
+
+use crate::Tokens;
+use crate::call_graph::CallGraph;
+use crate::metrics::ClassMetrics;
+use crate::parser::{Class, SubroutineDecReturn, SubroutineDecType};
+use crate::tokenizer::TokenType;
+
+/// Optional outputs selected with `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    /// `<file>T.xml` tokens and `<file>.xml` parse tree, in the course's format.
+    Xml,
+}
+
+pub trait Emitter {
+    /// Compiled VM code of the `classes` of the `.jack` file at `input`.
+    fn emit_vm(
+        &mut self,
+        _input: &Path,
+        _classes: &[Class],
+        _instructions: &[String],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// VM code of the stub of the OS `class`, written to `vm` without a source.
+    fn emit_stub(
+        &mut self,
+        _vm: &Path,
+        _class: &str,
+        _instructions: &[String],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Where the code of every subroutine and statement of the `.jack` file at `input`
+    /// starts in its VM code, with its line.
+    fn emit_lines(&mut self, _input: &Path, _lines: &[(usize, usize)]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Tokens and parse tree of the `.jack` file at `input`.
+    fn emit_xml(&mut self, _input: &Path, _tokens: &Tokens, _class: &Class) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Tokens of the `.jack` file at `input`, before parsing.
+    fn emit_tokens(&mut self, _input: &Path, _tokens: &Tokens) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Metrics of a compiled class.
+    fn emit_json(&mut self, _metrics: &ClassMetrics) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called once every file has been handled.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// `<file>.vm`, or another extension, next to each input.
+pub struct VmWriter {
+    ext: String,
+}
+
+impl VmWriter {
+    pub fn new(ext: impl Into<String>) -> Self {
+        Self { ext: ext.into() }
+    }
+}
+
+impl Emitter for VmWriter {
+    fn emit_vm(
+        &mut self,
+        input: &Path,
+        _classes: &[Class],
+        instructions: &[String],
+    ) -> anyhow::Result<()> {
+        write_vm(&crate::default_output(input, "", &self.ext), instructions)
+    }
+
+    fn emit_stub(
+        &mut self,
+        vm: &Path,
+        _class: &str,
+        instructions: &[String],
+    ) -> anyhow::Result<()> {
+        write_vm(vm, instructions)
+    }
+}
+
+fn write_vm(path: &Path, instructions: &[String]) -> anyhow::Result<()> {
+    let mut output_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        if i + 1 != instructions.len() {
+            writeln!(&mut output_file, "{instruction}")?;
+        } else {
+            write!(&mut output_file, "{instruction}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `<file>T.xml` tokens and `<file>.xml` parse tree next to each input.
+#[cfg(feature = "xml")]
+pub struct XmlWriter;
+
+#[cfg(feature = "xml")]
+impl Emitter for XmlWriter {
+    fn emit_xml(&mut self, input: &Path, tokens: &Tokens, class: &Class) -> anyhow::Result<()> {
+        let mut f = File::create(crate::default_output(input, "T", "xml"))?;
+        write!(&mut f, "{}", tokens.to_xml())?;
+
+        let mut f = File::create(crate::default_output(input, "", "xml"))?;
+        write!(&mut f, "{}", class.to_xml()?)?;
+
+        Ok(())
+    }
+}
+
+/// Metrics of every class, sorted by class name, as a single JSON file.
+pub struct MetricsWriter {
+    path: PathBuf,
+    metrics: Vec<ClassMetrics>,
+}
+
+impl MetricsWriter {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            metrics: vec![],
+        }
+    }
+}
+
+impl Emitter for MetricsWriter {
+    fn emit_json(&mut self, metrics: &ClassMetrics) -> anyhow::Result<()> {
+        self.metrics.push(metrics.clone());
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        println!("[<-] Metrics: {}", self.path.display());
+
+        self.metrics.sort_by(|a, b| a.class.cmp(&b.class));
+        let mut call_graph = CallGraph::default();
+        for class_metrics in self.metrics.iter() {
+            call_graph.add(class_metrics);
+        }
+        for class_metrics in self.metrics.iter_mut() {
+            for subroutine in class_metrics.subroutine_metrics.iter_mut() {
+                subroutine.stack_words =
+                    call_graph.stack_words(&format!("{}.{}", class_metrics.class, subroutine.name));
+            }
+        }
+        let mut metrics_file = File::create(&self.path)?;
+        serde_json::to_writer_pretty(&mut metrics_file, &self.metrics)?;
+        writeln!(&mut metrics_file)?;
+
+        Ok(())
+    }
+}
+
+/// Tokens of every file as JSON, for syntax highlighters and editor plugins that
+/// want to reuse the tokenizer rather than re-implement it.
+pub struct TokenDumpWriter {
+    path: PathBuf,
+    files: Vec<FileTokens>,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenDump<'a> {
+    files: &'a [FileTokens],
+}
+
+#[derive(Debug, Serialize)]
+struct FileTokens {
+    file: String,
+    tokens: Vec<TokenRecord>,
+}
+
+/// `start` and `end` are byte offsets, `column` counts characters from 1.
+#[derive(Debug, Serialize)]
+struct TokenRecord {
+    kind: &'static str,
+    lexeme: String,
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+}
+
+impl TokenDumpWriter {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            files: vec![],
+        }
+    }
+}
+
+impl Emitter for TokenDumpWriter {
+    fn emit_tokens(&mut self, input: &Path, tokens: &Tokens) -> anyhow::Result<()> {
+        let tokens = tokens
+            .tokens
+            .iter()
+            .filter(|token| !matches!(token.token_type, TokenType::Eof))
+            .map(|token| TokenRecord {
+                kind: token.token_type.kind(),
+                lexeme: token.lexeme.to_string(),
+                line: token.line,
+                column: token.column,
+                start: token.span.start,
+                end: token.span.end,
+            })
+            .collect();
+
+        self.files.push(FileTokens {
+            file: crate::portable_path(input),
+            tokens,
+        });
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        println!("[<-] Tokens: {}", self.path.display());
+
+        let mut dump_file = File::create(&self.path)?;
+        serde_json::to_writer_pretty(&mut dump_file, &TokenDump { files: &self.files })?;
+        writeln!(&mut dump_file)?;
+
+        Ok(())
+    }
+}
+
+/// The `.jack` line of every stretch of the VM code, as a [`LineMap`].
+pub struct LineMapWriter {
+    path: PathBuf,
+    /// Extension of the VM files.
+    ext: String,
+    map: LineMap,
+}
+
+impl LineMapWriter {
+    pub fn new(path: PathBuf, ext: impl Into<String>) -> Self {
+        Self {
+            path,
+            ext: ext.into(),
+            map: LineMap::default(),
+        }
+    }
+}
+
+impl Emitter for LineMapWriter {
+    fn emit_lines(&mut self, input: &Path, lines: &[(usize, usize)]) -> anyhow::Result<()> {
+        let vm = crate::portable_path(&crate::default_output(input, "", &self.ext));
+        let source = crate::portable_path(input);
+        for &(start, line) in lines {
+            self.map.push(&vm, start + 1, &source, line);
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        println!("[<-] Line map: {}", self.path.display());
+
+        self.map.write(&self.path)
+    }
+}
+
+/// `manifest.json` in the input directory, listing the `.vm` files written by this
+/// run so that tools reading the directory can tell them from stale ones.
+pub struct ManifestWriter {
+    dir: PathBuf,
+    /// Extension of the VM files.
+    ext: String,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    files: &'a [ManifestEntry],
+}
+
+/// Paths are relative to the manifest's directory.
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    vm: String,
+    /// Left out for the stubs of `--gen-stubs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    classes: Vec<String>,
+}
+
+impl ManifestWriter {
+    pub const FILE_NAME: &str = "manifest.json";
+
+    pub fn new(dir: PathBuf, ext: impl Into<String>) -> Self {
+        Self {
+            dir,
+            ext: ext.into(),
+            files: vec![],
+        }
+    }
+}
+
+impl Emitter for ManifestWriter {
+    fn emit_vm(
+        &mut self,
+        input: &Path,
+        classes: &[Class],
+        _instructions: &[String],
+    ) -> anyhow::Result<()> {
+        let relative =
+            |path: &Path| crate::portable_path(path.strip_prefix(&self.dir).unwrap_or(path));
+
+        self.files.push(ManifestEntry {
+            vm: relative(&crate::default_output(input, "", &self.ext)),
+            source: Some(relative(input)),
+            classes: classes
+                .iter()
+                .map(|class| class.class_name.0.to_string())
+                .collect(),
+        });
+
+        Ok(())
+    }
+
+    fn emit_stub(
+        &mut self,
+        vm: &Path,
+        class: &str,
+        _instructions: &[String],
+    ) -> anyhow::Result<()> {
+        self.files.push(ManifestEntry {
+            vm: crate::portable_path(vm.strip_prefix(&self.dir).unwrap_or(vm)),
+            source: None,
+            classes: vec![class.to_string()],
+        });
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let path = self.dir.join(Self::FILE_NAME);
+        println!("[<-] Manifest: {}", path.display());
+
+        self.files.sort_by(|a, b| a.vm.cmp(&b.vm));
+        let mut manifest_file = File::create(&path)?;
+        serde_json::to_writer_pretty(&mut manifest_file, &Manifest { files: &self.files })?;
+        writeln!(&mut manifest_file)?;
+
+        Ok(())
+    }
+}
+
+/// `exports.json` next to the VM files of a `--lib` compile: the signature of every
+/// subroutine of the library, for the call check of the programs linking it.
+pub struct ExportsWriter {
+    path: PathBuf,
+    subroutines: Vec<Export>,
+}
+
+#[derive(Debug, Serialize)]
+struct Exports<'a> {
+    subroutines: &'a [Export],
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Export {
+    /// `Class.subroutine`, as called in VM code.
+    name: String,
+    kind: &'static str,
+    return_type: String,
+    parameters: Vec<Parameter>,
+    /// Arguments of its `call` command, the object of a method included.
+    vm_arguments: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Parameter {
+    name: String,
+    #[serde(rename = "type")]
+    parameter_type: String,
+}
+
+impl ExportsWriter {
+    pub const FILE_NAME: &str = "exports.json";
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            subroutines: vec![],
+        }
+    }
+}
+
+fn exports(class: &Class<'_>) -> impl Iterator<Item = Export> {
+    let class_name = class.class_name.0;
+    class.subroutine_decs.iter().map(move |subroutine_dec| {
+        let kind = &subroutine_dec.subroutine_dec_type;
+        let parameters: Vec<_> = subroutine_dec
+            .parameter_list
+            .parameters
+            .iter()
+            .map(|(parameter_type, name)| Parameter {
+                name: name.0.to_string(),
+                parameter_type: parameter_type.name().to_string(),
+            })
+            .collect();
+        let this = matches!(kind, SubroutineDecType::Method);
+
+        Export {
+            name: format!("{class_name}.{}", subroutine_dec.subroutine_name.0),
+            kind: kind.keyword(),
+            return_type: match &subroutine_dec.subroutine_dec_return_type {
+                SubroutineDecReturn::Void => "void".to_string(),
+                SubroutineDecReturn::Type(return_type) => return_type.name().to_string(),
+            },
+            vm_arguments: parameters.len() + usize::from(this),
+            parameters,
+        }
+    })
+}
+
+impl Emitter for ExportsWriter {
+    fn emit_vm(
+        &mut self,
+        _input: &Path,
+        classes: &[Class],
+        _instructions: &[String],
+    ) -> anyhow::Result<()> {
+        self.subroutines.extend(classes.iter().flat_map(exports));
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        println!("[<-] Exports: {}", self.path.display());
+
+        self.subroutines.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut exports_file = File::create(&self.path)?;
+        let exports = Exports {
+            subroutines: &self.subroutines,
+        };
+        serde_json::to_writer_pretty(&mut exports_file, &exports)?;
+        writeln!(&mut exports_file)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod emitter_tests {
+    use super::*;
+    use crate::{parser::Parser, tokenizer::Tokenizer};
+
+    #[test]
+    fn exports_every_signature() {
+        let source = "class Point {
+            field int x, y;
+            constructor Point new(int ax, int ay) { let x = ax; let y = ay; return this; }
+            method int dot(Point other) { return 0; }
+            function void reset() { return; }
+        }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let exports: Vec<_> = exports(&classes.unwrap()[0]).collect();
+
+        let signature = |export: &Export| {
+            let parameters: Vec<_> = export
+                .parameters
+                .iter()
+                .map(|parameter| format!("{} {}", parameter.parameter_type, parameter.name))
+                .collect();
+            format!(
+                "{} {} {}({}) / {}",
+                export.kind,
+                export.return_type,
+                export.name,
+                parameters.join(", "),
+                export.vm_arguments
+            )
+        };
+        assert_eq!(
+            exports.iter().map(signature).collect::<Vec<_>>(),
+            [
+                "constructor Point Point.new(int ax, int ay) / 2",
+                "method int Point.dot(Point other) / 2",
+                "function void Point.reset() / 0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Typical runs of the compiler, and of the pipeline it starts. The `examples`
+//! subcommand, the long `--help` and the README's examples are all written from
+//! [`EXAMPLES`], so they cannot drift apart.
+
+use std::fmt::Write as _;
+
+pub struct Example {
+    pub title: &'static str,
+    /// Shell commands, run in order.
+    pub commands: &'static [&'static str],
+    pub note: &'static str,
+}
+
+/// What `--help` says before the flags.
+pub const LONG_ABOUT: &str = "Jack language compiler
+
+Compiles a .jack file, or every .jack file of a directory or zipped project, into a .vm \
+file next to each source, for the VM translator. The subcommands refactor a project, run \
+it on the built-in interpreter and OS model, or test an OS of project 12.";
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        title: "Compile a project",
+        commands: &["Jack-compiler-rs Pong/"],
+        note: "Writes `Pong/<Class>.vm` next to every `.jack` file, and `Pong/manifest.json` listing them.",
+    },
+    Example {
+        title: "Build a program for the CPU emulator",
+        commands: &[
+            "Jack-compiler-rs Pong/",
+            "VMTranslator Pong/ --with-os tools/OS -O 1 -o Pong.asm",
+            "hack-assembler-rs Pong.asm -o Pong.hack",
+        ],
+        note: "The VM translator adds the OS classes the project does not provide, and the assembler writes the machine code.",
+    },
+    Example {
+        title: "Run a program without the VM",
+        commands: &["Jack-compiler-rs run Pong/ --keyboard keys.txt --heap-report"],
+        note: "Runs on the interpreter and its OS model, typing the keys of `keys.txt` and reporting the heap blocks left at exit.",
+    },
+    Example {
+        title: "Measure a project",
+        commands: &["Jack-compiler-rs Pong/ --metrics pong.json"],
+        note: "Writes per-class metrics, with the stack each subroutine may take, as JSON.",
+    },
+];
+
+/// The examples for the end of `--help`.
+pub fn long_help() -> String {
+    let mut help = "Examples:\n".to_string();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(help, "\n  {}:", example.title);
+        for command in example.commands.iter() {
+            let _ = writeln!(help, "    $ {command}");
+        }
+        let _ = writeln!(help, "  {}", example.note);
+    }
+
+    help
+}
+
+/// The examples as the README has them.
+pub fn markdown() -> String {
+    let mut markdown = String::new();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(markdown, "**{}**", example.title);
+        let _ = writeln!(markdown, "```bash\n{}\n```", example.commands.join("\n"));
+        let _ = writeln!(markdown, "{}\n", example.note);
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod examples_tests {
+    use super::*;
+
+    #[test]
+    fn readme_has_the_examples() {
+        let readme = include_str!("../README.md");
+
+        assert!(
+            readme.contains(&markdown()),
+            "Update the README's examples with `Jack-compiler-rs examples --markdown`"
+        );
+    }
+}
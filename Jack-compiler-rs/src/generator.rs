@@ -0,0 +1,335 @@
+use std::fmt::Write;
+
+const OPS: [&str; 9] = ["+", "-", "*", "/", "&", "|", "<", ">", "="];
+
+/// Bounds of the generated programs.
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Maximum nesting depth of expressions and statements.
+    pub max_depth: usize,
+    /// Number of statements in each block at the top level.
+    pub statements: usize,
+    /// Number of helper functions next to `Main.main`.
+    pub functions: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            statements: 8,
+            functions: 2,
+        }
+    }
+}
+
+/// Small xorshift64* generator, so that a seed always reproduces the same program.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Scope of the subroutine being generated.
+struct Scope {
+    /// Variables which may be read and assigned.
+    vars: Vec<String>,
+    /// Loop counters, which are only read by the loop body.
+    counters: Vec<String>,
+    /// Number of helper functions that may be called.
+    callable: usize,
+}
+
+/// Generates random but valid Jack programs: always terminating (loops are bounded by
+/// dedicated counters), free of division by zero and without recursion. Results are
+/// made observable with `Output.printInt`.
+pub struct Generator {
+    rng: Rng,
+    config: GeneratorConfig,
+    output: String,
+    indent: usize,
+}
+
+impl Generator {
+    pub fn new(seed: u64, config: GeneratorConfig) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            config,
+            output: String::new(),
+            indent: 0,
+        }
+    }
+
+    /// Generates the source of a single `Main` class.
+    pub fn generate(mut self) -> String {
+        self.line("class Main {");
+        self.indent += 1;
+
+        for index in 0..self.config.functions {
+            self.function(index);
+            self.line("");
+        }
+        self.main();
+
+        self.indent -= 1;
+        self.line("}");
+
+        self.output
+    }
+
+    fn line(&mut self, line: &str) {
+        if !line.is_empty() {
+            for _ in 0..self.indent {
+                self.output.push_str("   ");
+            }
+        }
+        let _ = writeln!(&mut self.output, "{line}");
+    }
+
+    fn function(&mut self, index: usize) {
+        self.line(&format!("function int f{index}(int a, int b) {{"));
+        self.indent += 1;
+        self.line("var int x, y;");
+
+        let mut scope = Scope {
+            vars: vec!["a".into(), "b".into(), "x".into(), "y".into()],
+            counters: vec![],
+            callable: index,
+        };
+        self.line("let x = 0;");
+        self.line("let y = 0;");
+        for _ in 0..self.config.statements / 2 {
+            self.assignment(&mut scope, self.config.max_depth);
+        }
+        let expression = self.expression(&scope, self.config.max_depth);
+        self.line(&format!("return {expression};"));
+
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn main(&mut self) {
+        let vars: Vec<String> = (0..4).map(|i| format!("v{i}")).collect();
+        let counters: Vec<String> = (0..self.config.max_depth)
+            .map(|i| format!("i{i}"))
+            .collect();
+
+        self.line("function void main() {");
+        self.indent += 1;
+        self.line(&format!("var int {};", vars.join(", ")));
+        if !counters.is_empty() {
+            self.line(&format!("var int {};", counters.join(", ")));
+        }
+
+        let mut scope = Scope {
+            vars,
+            counters: vec![],
+            callable: self.config.functions,
+        };
+        for var in scope.vars.clone() {
+            let value = self.rng.below(100);
+            self.line(&format!("let {var} = {value};"));
+        }
+
+        let mut available_counters = counters;
+        for _ in 0..self.config.statements {
+            self.statement(&mut scope, &mut available_counters, self.config.max_depth);
+        }
+        for var in scope.vars.clone() {
+            self.print(&var);
+        }
+
+        self.line("return;");
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn print(&mut self, expression: &str) {
+        self.line(&format!("do Output.printInt({expression});"));
+        self.line("do Output.println();");
+    }
+
+    fn statement(&mut self, scope: &mut Scope, counters: &mut Vec<String>, depth: usize) {
+        let choice = if depth == 0 { 0 } else { self.rng.below(5) };
+
+        match choice {
+            0 | 1 => self.assignment(scope, depth),
+            2 => {
+                let expression = self.expression(scope, depth);
+                self.print(&expression);
+            }
+            3 => {
+                let condition = self.condition(scope, depth);
+                self.line(&format!("if ({condition}) {{"));
+                self.block(scope, counters, depth - 1);
+                if self.rng.chance(50) {
+                    self.line("} else {");
+                    self.block(scope, counters, depth - 1);
+                }
+                self.line("}");
+            }
+            _ => match counters.pop() {
+                Some(counter) => {
+                    let bound = 1 + self.rng.below(4);
+                    self.line(&format!("let {counter} = 0;"));
+                    self.line(&format!("while ({counter} < {bound}) {{"));
+
+                    scope.counters.push(counter.clone());
+                    self.block(scope, counters, depth - 1);
+                    self.indent += 1;
+                    self.line(&format!("let {counter} = {counter} + 1;"));
+                    self.indent -= 1;
+                    scope.counters.pop();
+
+                    self.line("}");
+                    counters.push(counter);
+                }
+                None => self.assignment(scope, depth),
+            },
+        }
+    }
+
+    fn block(&mut self, scope: &mut Scope, counters: &mut Vec<String>, depth: usize) {
+        self.indent += 1;
+        for _ in 0..1 + self.rng.below(3) {
+            self.statement(scope, counters, depth);
+        }
+        self.indent -= 1;
+    }
+
+    fn assignment(&mut self, scope: &mut Scope, depth: usize) {
+        let var = self.rng.pick(&scope.vars).clone();
+        let expression = self.expression(scope, depth);
+
+        self.line(&format!("let {var} = {expression};"));
+    }
+
+    fn condition(&mut self, scope: &Scope, depth: usize) -> String {
+        let op = self.rng.pick(&["<", ">", "="]);
+        let left = self.term(scope, depth - 1);
+        let right = self.term(scope, depth - 1);
+
+        format!("{left} {op} {right}")
+    }
+
+    /// `term (op term)?` - the right hand side of a binary operation is always a term,
+    /// so nested operations are parenthesized.
+    fn expression(&mut self, scope: &Scope, depth: usize) -> String {
+        let left = self.term(scope, depth);
+        if depth == 0 || self.rng.chance(30) {
+            return left;
+        }
+
+        match *self.rng.pick(&OPS) {
+            // Never divide by zero
+            "/" => format!("{left} / {}", 1 + self.rng.below(9)),
+            op => {
+                let right = self.term(scope, depth - 1);
+
+                format!("{left} {op} {right}")
+            }
+        }
+    }
+
+    fn term(&mut self, scope: &Scope, depth: usize) -> String {
+        let choice = if depth == 0 {
+            self.rng.below(2)
+        } else {
+            self.rng.below(6)
+        };
+
+        match choice {
+            0 => self.rng.below(200).to_string(),
+            1 => {
+                if !scope.counters.is_empty() && self.rng.chance(30) {
+                    self.rng.pick(&scope.counters).clone()
+                } else {
+                    self.rng.pick(&scope.vars).clone()
+                }
+            }
+            2 => format!("({})", self.expression(scope, depth - 1)),
+            3 => {
+                let op = self.rng.pick(&["-", "~"]);
+
+                format!("{op}{}", self.term(scope, depth - 1))
+            }
+            _ if scope.callable > 0 => {
+                let function = self.rng.below(scope.callable);
+                let a = self.expression(scope, depth - 1);
+                let b = self.expression(scope, depth - 1);
+
+                format!("Main.f{function}({a}, {b})")
+            }
+            _ => format!("({})", self.expression(scope, depth - 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod generator_tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::Parser, session::Session, tokenizer::Tokenizer};
+
+    #[test]
+    fn deterministic() {
+        let config = GeneratorConfig::default();
+
+        assert_eq!(
+            Generator::new(42, config.clone()).generate(),
+            Generator::new(42, config.clone()).generate()
+        );
+        assert_ne!(
+            Generator::new(42, config.clone()).generate(),
+            Generator::new(43, config).generate()
+        );
+    }
+
+    #[test]
+    fn compiles() {
+        for max_depth in 0..5 {
+            let config = GeneratorConfig {
+                max_depth,
+                ..Default::default()
+            };
+
+            for seed in 0..50 {
+                let source = Generator::new(seed, config.clone()).generate();
+
+                let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).collect();
+                let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+                let classes = classes.unwrap();
+                assert_eq!(classes.len(), 1, "{source}");
+
+                let mut session = Session::default();
+                let instructions = Compiler::new(classes.iter()).compile(&mut session);
+                assert!(!session.has_errors(), "{source}");
+                assert!(
+                    instructions
+                        .iter()
+                        .any(|i| i.starts_with("function Main.main "))
+                );
+            }
+        }
+    }
+}
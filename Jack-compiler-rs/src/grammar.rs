@@ -0,0 +1,136 @@
+//! TextMate and Tree-sitter grammar stubs built from the tokenizer's tables, so
+//! editor highlighting follows the keywords and symbols the compiler knows.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::tokenizer::{KEYWORDS, SYMBOLS};
+
+/// Writes `jack.tmLanguage.json` and `tree-sitter-jack/` into `dir`.
+pub fn emit(dir: &Path) -> anyhow::Result<()> {
+    let tree_sitter_dir = dir.join("tree-sitter-jack");
+    fs::create_dir_all(tree_sitter_dir.join("queries"))?;
+
+    for (path, contents) in [
+        (
+            dir.join("jack.tmLanguage.json"),
+            serde_json::to_string_pretty(&text_mate())? + "\n",
+        ),
+        (tree_sitter_dir.join("grammar.js"), tree_sitter()),
+        (
+            tree_sitter_dir.join("queries").join("highlights.scm"),
+            HIGHLIGHTS.to_string(),
+        ),
+    ] {
+        println!("[<-] Grammar: {}", path.display());
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<_> = KEYWORDS.keys().copied().collect();
+    keywords.sort();
+
+    keywords
+}
+
+fn symbols() -> Vec<char> {
+    let mut symbols: Vec<_> = SYMBOLS.keys().copied().collect();
+    symbols.sort();
+
+    symbols
+}
+
+fn text_mate() -> Value {
+    let symbols: String = symbols().iter().map(|c| format!("\\{c}")).collect();
+
+    json!({
+        "name": "Jack",
+        "scopeName": "source.jack",
+        "fileTypes": ["jack"],
+        "patterns": [
+            { "name": "comment.line.double-slash.jack", "match": "//.*$" },
+            { "name": "comment.block.jack", "begin": "/\\*", "end": "\\*/" },
+            { "name": "string.quoted.double.jack", "match": "\"[^\"\\n]*\"" },
+            { "name": "constant.numeric.integer.jack", "match": "\\b[0-9]+\\b" },
+            {
+                "name": "keyword.other.jack",
+                "match": format!("(?<![\\w$-])(?:{})(?![\\w$-])", keywords().join("|"))
+            },
+            { "name": "keyword.operator.jack", "match": format!("[{symbols}]") },
+        ]
+    })
+}
+
+fn tree_sitter() -> String {
+    let quote = |s: &str| serde_json::to_string(s).expect("Strings serialize");
+    let keywords: Vec<_> = keywords().into_iter().map(quote).collect();
+    let symbols: Vec<_> = symbols().iter().map(|c| quote(&c.to_string())).collect();
+
+    format!(
+        r#"// Generated by `Jack-compiler-rs --emit-grammar`: tokens only, a stub to grow the grammar from.
+module.exports = grammar({{
+  name: 'jack',
+  extras: $ => [/\s/, $.comment],
+  word: $ => $.identifier,
+  rules: {{
+    source_file: $ => repeat($._token),
+    _token: $ => choice($.keyword, $.symbol, $.integer_constant, $.string_constant, $.identifier),
+    keyword: $ => choice({}),
+    symbol: $ => choice({}),
+    integer_constant: $ => /[0-9]+/,
+    string_constant: $ => /"[^"\n]*"/,
+    identifier: $ => /[A-Za-z_$][A-Za-z0-9_$-]*/,
+    comment: $ => token(choice(seq('//', /.*/), seq('/*', /[^*]*\*+([^/*][^*]*\*+)*/, '/'))),
+  }}
+}});
+"#,
+        keywords.join(", "),
+        symbols.join(", ")
+    )
+}
+
+const HIGHLIGHTS: &str = "(keyword) @keyword
+(symbol) @operator
+(integer_constant) @number
+(string_constant) @string
+(comment) @comment
+";
+
+#[cfg(test)]
+mod grammar_tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn highlights_every_keyword_and_symbol_as_the_tokenizer_reads_them() {
+        let source = keywords().join(" ") + &symbols().iter().collect::<String>();
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).collect();
+        let kinds: Vec<_> = tokens
+            .unwrap()
+            .iter()
+            .map(|token| token.token_type.kind())
+            .collect();
+
+        let expected: Vec<_> = [
+            ("keyword", KEYWORDS.len()),
+            ("symbol", SYMBOLS.len()),
+            ("eof", 1),
+        ]
+        .into_iter()
+        .flat_map(|(kind, n)| std::iter::repeat_n(kind, n))
+        .collect();
+        assert_eq!(kinds, expected);
+
+        let grammar = tree_sitter();
+        assert!(grammar.contains(r#""while""#) && grammar.contains(r#""~""#));
+        assert_eq!(
+            text_mate()["patterns"][5]["match"],
+            r"[\&\(\)\*\+\,\-\.\/\;\<\=\>\[\]\{\|\}\~]"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Exports of the heap usage of an interpreter run, to tell leaks from fragmentation:
+//! a heap that fills up leaks, one whose largest free block shrinks while enough
+//! words are free is fragmented.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::interpreter::{HeapSample, HeapUsage};
+
+const CHART_WIDTH: usize = 800;
+const CHART_HEIGHT: usize = 240;
+/// Sites listed in the HTML report.
+const TOP_SITES: usize = 20;
+
+/// Writes `usage` as HTML, or as CSV next to `<stem>-sites.csv`, after the extension of `path`.
+pub fn write(path: &Path, usage: &HeapUsage) -> anyhow::Result<()> {
+    let files = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => vec![
+            (path.to_path_buf(), samples_csv(&usage.samples)),
+            (sites_path(path), sites_csv(usage)),
+        ],
+        Some("html" | "htm") => vec![(path.to_path_buf(), html(usage))],
+        _ => anyhow::bail!(
+            "Unknown heap usage format of {}, expected .csv or .html",
+            path.display()
+        ),
+    };
+
+    for (path, contents) in files {
+        println!("[<-] Heap usage: {}", path.display());
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+fn sites_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!("{stem}-sites.csv"))
+}
+
+fn samples_csv(samples: &[HeapSample]) -> String {
+    let mut csv = String::from("step,blocks,used_words,free_words,largest_free_block\n");
+    for sample in samples.iter() {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{}",
+            sample.step,
+            sample.blocks,
+            sample.used_words,
+            sample.free_words,
+            sample.largest_free_block
+        );
+    }
+
+    csv
+}
+
+/// Sites are identifiers and words, so they need no quoting.
+fn sites_csv(usage: &HeapUsage) -> String {
+    let mut csv = String::from("site,blocks,words\n");
+    for site in usage.sites.iter() {
+        let _ = writeln!(csv, "{},{},{}", site.site, site.blocks, site.words);
+    }
+
+    csv
+}
+
+fn html(usage: &HeapUsage) -> String {
+    let heap_words = usage.samples.first().map_or(1, |sample| sample.free_words);
+    let peak = usage.samples.iter().map(|sample| sample.used_words).max();
+    let top_words = usage.sites.first().map_or(1, |site| site.words);
+
+    let mut rows = String::new();
+    for site in usage.sites.iter().take(TOP_SITES) {
+        let _ = writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td><div class="bar" style="width: {}px"></div></td></tr>"#,
+            site.site,
+            site.blocks,
+            site.words,
+            site.words * 300 / top_words
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Heap usage</title>
+<style>
+body {{ font-family: sans-serif; }}
+td {{ padding: 2px 8px; }}
+.bar {{ height: 12px; background: steelblue; }}
+</style>
+</head>
+<body>
+<h1>Heap usage</h1>
+<p>{} allocation(s) and free(s), peak of {} of {heap_words} words in use.</p>
+<svg width="{CHART_WIDTH}" height="{CHART_HEIGHT}" style="border: 1px solid #ccc">
+<polyline fill="none" stroke="steelblue" points="{}"/>
+<polyline fill="none" stroke="darkorange" points="{}"/>
+</svg>
+<p><span style="color: steelblue">Words in use</span> and <span style="color: darkorange">largest free block</span> over the executed statements.
+The largest free block falling short of the free words is fragmentation.</p>
+<h2>Largest allocation sites</h2>
+<table>
+<tr><th>Site</th><th>Blocks</th><th>Words</th><th></th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+        usage.samples.len().saturating_sub(1),
+        peak.unwrap_or(0),
+        points(
+            &usage.samples,
+            heap_words,
+            |sample| sample.used_words,
+            usize::max
+        ),
+        points(
+            &usage.samples,
+            heap_words,
+            |sample| sample.largest_free_block,
+            usize::min
+        ),
+    )
+}
+
+/// SVG polyline points of `value` over the steps, with one point per pixel column
+/// merged by `merge` so that peaks survive long runs.
+fn points(
+    samples: &[HeapSample],
+    heap_words: usize,
+    value: fn(&HeapSample) -> usize,
+    merge: fn(usize, usize) -> usize,
+) -> String {
+    let last_step = samples.last().map_or(0, |sample| sample.step).max(1);
+
+    let mut columns: Vec<(usize, usize)> = vec![];
+    for sample in samples.iter() {
+        let x = (sample.step * (CHART_WIDTH - 1) as u64 / last_step) as usize;
+        match columns.last_mut() {
+            Some((column, merged)) if *column == x => *merged = merge(*merged, value(sample)),
+            _ => columns.push((x, value(sample))),
+        }
+    }
+
+    columns
+        .iter()
+        .map(|&(x, words)| {
+            let y = CHART_HEIGHT - 1 - words * (CHART_HEIGHT - 1) / heap_words.max(1);
+            format!("{x},{y}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod heap_usage_tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, InterpreterConfig};
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn exports_samples_and_sites() {
+        let source = "class Main {
+                function void main() {
+                    var Array a, b;
+                    let a = Array.new(10);
+                    let b = Array.new(5);
+                    do a.dispose();
+                    let a = Array.new(3);
+                    return;
+                }
+            }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+        let mut interpreter = Interpreter::new(&classes, InterpreterConfig::default()).unwrap();
+        interpreter.run().unwrap();
+        let usage = interpreter.heap_usage();
+
+        assert_eq!(
+            samples_csv(&usage.samples),
+            "step,blocks,used_words,free_words,largest_free_block
+0,0,0,14336,14336
+1,1,11,14325,14325
+2,2,17,14319,14319
+3,1,6,14330,14319
+4,2,10,14326,14319
+"
+        );
+        assert_eq!(
+            sites_csv(&usage),
+            "site,blocks,words\nArray.new in Main.main,3,18\n"
+        );
+        assert_eq!(
+            sites_path(Path::new("out/heap.csv")),
+            Path::new("out/heap-sites.csv")
+        );
+
+        let html = html(&usage);
+        assert!(html.contains("4 allocation(s) and free(s), peak of 17 of 14336 words in use"));
+        assert!(html.contains(r#"points="0,239 199,239 399,239 599,239 799,239""#));
+    }
+}
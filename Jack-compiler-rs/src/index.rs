@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    parser::{
+        Class, ClassVarDecKind, Expression, ExpressionList, Statement, Statements, SubroutineCall,
+        SubroutineDec, SubroutineDecReturn, Term, Type,
+    },
+    tokenizer::Identifier,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolKind {
+    Class,
+    Subroutine,
+    Field,
+    Static,
+    Argument,
+    Local,
+}
+
+impl SymbolKind {
+    pub fn is_class_var(&self) -> bool {
+        matches!(self, SymbolKind::Field | SymbolKind::Static)
+    }
+}
+
+/// Fully qualified symbol name:
+/// `Class`, `Class.subroutine`, `Class.field` or `Class.subroutine.variable`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolKey {
+    pub name: String,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub file: usize,
+    /// Byte offset into the source file.
+    pub offset: usize,
+    /// Byte length.
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub span: Span,
+    pub is_definition: bool,
+    /// Qualified name of the enclosing subroutine, if any.
+    pub scope: Option<String>,
+}
+
+/// Project-wide table of every declaration and use of classes, subroutines and variables.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    files: Vec<PathBuf>,
+    symbols: BTreeMap<SymbolKey, Vec<Occurrence>>,
+}
+
+impl SymbolIndex {
+    pub fn add_file<'de>(&mut self, path: &Path, source: &'de str, classes: &[Class<'de>]) {
+        let file = self.files.len();
+        self.files.push(path.to_path_buf());
+
+        let mut indexer = Indexer::new(self, file, source);
+        for class in classes.iter() {
+            indexer.index_class(class);
+        }
+    }
+
+    pub fn file(&self, file: usize) -> &Path {
+        &self.files[file]
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&SymbolKey, &[Occurrence])> {
+        self.symbols
+            .iter()
+            .map(|(key, occurrences)| (key, occurrences.as_slice()))
+    }
+
+    pub fn lookup<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a SymbolKey, &'a [Occurrence])> {
+        self.symbols().filter(move |(key, _)| key.name == name)
+    }
+
+    pub fn is_defined(&self, name: &str, kind: SymbolKind) -> bool {
+        self.symbols
+            .get(&SymbolKey {
+                name: name.to_string(),
+                kind,
+            })
+            .is_some_and(|occurrences| occurrences.iter().any(|o| o.is_definition))
+    }
+
+    fn insert(&mut self, key: SymbolKey, occurrence: Occurrence) {
+        self.symbols.entry(key).or_default().push(occurrence);
+    }
+}
+
+type Scope<'de> = HashMap<&'de str, (SymbolKind, Option<&'de str>)>;
+
+struct Indexer<'a, 'de> {
+    index: &'a mut SymbolIndex,
+    file: usize,
+    source: &'de str,
+    line_starts: Vec<usize>,
+
+    class_name: &'de str,
+    subroutine_name: Option<&'de str>,
+    class_scope: Scope<'de>,
+    subroutine_scope: Scope<'de>,
+}
+
+impl<'a, 'de> Indexer<'a, 'de> {
+    fn new(index: &'a mut SymbolIndex, file: usize, source: &'de str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            index,
+            file,
+            source,
+            line_starts,
+            class_name: "",
+            subroutine_name: None,
+            class_scope: HashMap::new(),
+            subroutine_scope: HashMap::new(),
+        }
+    }
+
+    /// Identifiers borrow straight from the source, so their position is the pointer offset.
+    fn span(&self, identifier: &Identifier<'de>) -> Option<Span> {
+        let start = self.source.as_ptr() as usize;
+        let at = identifier.0.as_ptr() as usize;
+        if at < start || at + identifier.0.len() > start + self.source.len() {
+            return None;
+        }
+
+        let offset = at - start;
+        let line = self.line_starts.partition_point(|&s| s <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source[line_start..offset].chars().count() + 1;
+
+        Some(Span {
+            file: self.file,
+            offset,
+            len: identifier.0.len(),
+            line,
+            column,
+        })
+    }
+
+    fn scope(&self) -> Option<String> {
+        self.subroutine_name
+            .map(|subroutine_name| format!("{}.{}", self.class_name, subroutine_name))
+    }
+
+    fn record(
+        &mut self,
+        name: String,
+        kind: SymbolKind,
+        identifier: &Identifier<'de>,
+        is_definition: bool,
+    ) {
+        let Some(span) = self.span(identifier) else {
+            return;
+        };
+        let scope = self.scope();
+
+        self.index.insert(
+            SymbolKey { name, kind },
+            Occurrence {
+                span,
+                is_definition,
+                scope,
+            },
+        );
+    }
+
+    fn index_class(&mut self, class: &Class<'de>) {
+        self.class_name = class.class_name.0;
+        self.subroutine_name = None;
+        self.class_scope.clear();
+        self.record(
+            class.class_name.0.to_string(),
+            SymbolKind::Class,
+            &class.class_name,
+            true,
+        );
+
+        for class_var_dec in class.class_var_decs.iter() {
+            let kind = match class_var_dec.class_var_dec_kind {
+                ClassVarDecKind::Static => SymbolKind::Static,
+                ClassVarDecKind::Field => SymbolKind::Field,
+            };
+            self.index_type(&class_var_dec.class_var_dec_type);
+
+            for var_name in class_var_dec.var_names.iter() {
+                let name = format!("{}.{}", self.class_name, var_name.0);
+                self.record(name, kind, var_name, true);
+                self.class_scope.insert(
+                    var_name.0,
+                    (kind, type_name(&class_var_dec.class_var_dec_type)),
+                );
+            }
+        }
+
+        for subroutine_dec in class.subroutine_decs.iter() {
+            self.index_subroutine_dec(subroutine_dec);
+        }
+    }
+
+    fn index_subroutine_dec(&mut self, subroutine_dec: &SubroutineDec<'de>) {
+        let name = format!("{}.{}", self.class_name, subroutine_dec.subroutine_name.0);
+        self.record(
+            name,
+            SymbolKind::Subroutine,
+            &subroutine_dec.subroutine_name,
+            true,
+        );
+
+        self.subroutine_name = Some(subroutine_dec.subroutine_name.0);
+        self.subroutine_scope.clear();
+
+        if let SubroutineDecReturn::Type(r#type) = &subroutine_dec.subroutine_dec_return_type {
+            self.index_type(r#type);
+        }
+
+        for (r#type, identifier) in subroutine_dec.parameter_list.parameters.iter() {
+            self.index_type(r#type);
+            self.declare_subroutine_var(SymbolKind::Argument, r#type, identifier);
+        }
+
+        for var_dec in subroutine_dec.subroutine_body.var_decs.iter() {
+            self.index_type(&var_dec.var_type);
+            for var_name in var_dec.var_names.iter() {
+                self.declare_subroutine_var(SymbolKind::Local, &var_dec.var_type, var_name);
+            }
+        }
+
+        self.index_statements(&subroutine_dec.subroutine_body.statements);
+
+        self.subroutine_name = None;
+    }
+
+    fn declare_subroutine_var(
+        &mut self,
+        kind: SymbolKind,
+        r#type: &Type<'de>,
+        identifier: &Identifier<'de>,
+    ) {
+        let name = format!(
+            "{}.{}",
+            self.scope().expect("Inside a subroutine"),
+            identifier.0
+        );
+        self.record(name, kind, identifier, true);
+        self.subroutine_scope
+            .insert(identifier.0, (kind, type_name(r#type)));
+    }
+
+    fn index_type(&mut self, r#type: &Type<'de>) {
+        if let Type::Class { name } = r#type {
+            self.record(name.0.to_string(), SymbolKind::Class, name, false);
+        }
+    }
+
+    /// Records a variable use. Returns the variable's class type, if it is a known variable.
+    fn index_var(&mut self, identifier: &Identifier<'de>) -> Option<Option<&'de str>> {
+        if let Some(&(kind, r#type)) = self.subroutine_scope.get(identifier.0) {
+            let name = format!(
+                "{}.{}",
+                self.scope().expect("Inside a subroutine"),
+                identifier.0
+            );
+            self.record(name, kind, identifier, false);
+
+            return Some(r#type);
+        }
+
+        if let Some(&(kind, r#type)) = self.class_scope.get(identifier.0) {
+            let name = format!("{}.{}", self.class_name, identifier.0);
+            self.record(name, kind, identifier, false);
+
+            return Some(r#type);
+        }
+
+        None
+    }
+
+    fn index_statements(&mut self, statements: &Statements<'de>) {
+        for statement in statements.statements.iter() {
+            self.index_statement(statement);
+        }
+    }
+
+    fn index_statement(&mut self, statement: &Statement<'de>) {
+        match statement {
+            Statement::LetStatement(let_statement) => {
+                self.index_var(&let_statement.var_name);
+                if let Some(expression_1) = &let_statement.expression_1 {
+                    self.index_expression(expression_1);
+                }
+                self.index_expression(&let_statement.expression_2);
+            }
+            Statement::IfStatement(if_statement) => {
+                self.index_expression(&if_statement.condition);
+                self.index_statements(&if_statement.then_branch);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    self.index_statements(else_branch);
+                }
+            }
+            Statement::WhileStatement(while_statement) => {
+                self.index_expression(&while_statement.condition);
+                self.index_statements(&while_statement.body);
+            }
+            Statement::DoStatement(do_statement) => {
+                self.index_subroutine_call(&do_statement.subroutine_call);
+            }
+            Statement::ReturnStatement(return_statement) => {
+                if let Some(expression) = &return_statement.expression {
+                    self.index_expression(expression);
+                }
+            }
+        }
+    }
+
+    fn index_expression(&mut self, expression: &Expression<'de>) {
+        self.index_term(&expression.term);
+        for (_, term) in expression.terms.iter() {
+            self.index_term(term);
+        }
+    }
+
+    fn index_expression_list(&mut self, expression_list: &ExpressionList<'de>) {
+        for expression in expression_list.expressions.iter() {
+            self.index_expression(expression);
+        }
+    }
+
+    fn index_term(&mut self, term: &Term<'de>) {
+        match term {
+            Term::Constant(_) | Term::KeywordConstant(_) => {}
+            Term::VarName(identifier) => {
+                self.index_var(identifier);
+            }
+            Term::VarNameExpression {
+                var_name,
+                expression,
+            } => {
+                self.index_var(var_name);
+                self.index_expression(expression);
+            }
+            Term::Expression(expression) => self.index_expression(expression),
+            Term::UnaryOpTerm { term, .. } => self.index_term(term),
+            Term::SubroutineCall(subroutine_call) => self.index_subroutine_call(subroutine_call),
+        }
+    }
+
+    fn index_subroutine_call(&mut self, subroutine_call: &SubroutineCall<'de>) {
+        match subroutine_call {
+            SubroutineCall::Call {
+                subroutine_name,
+                expression_list,
+            } => {
+                let name = format!("{}.{}", self.class_name, subroutine_name.0);
+                self.record(name, SymbolKind::Subroutine, subroutine_name, false);
+                self.index_expression_list(expression_list);
+            }
+            SubroutineCall::ClassCall {
+                class_or_var_name,
+                subroutine_name,
+                expression_list,
+            } => {
+                let target_class = match self.index_var(class_or_var_name) {
+                    // A method call on a variable of a class type
+                    Some(r#type) => r#type,
+                    // Ok. It's either a class constructor or a class function call
+                    None => {
+                        let name = class_or_var_name.0.to_string();
+                        self.record(name, SymbolKind::Class, class_or_var_name, false);
+
+                        Some(class_or_var_name.0)
+                    }
+                };
+
+                if let Some(target_class) = target_class {
+                    let name = format!("{}.{}", target_class, subroutine_name.0);
+                    self.record(name, SymbolKind::Subroutine, subroutine_name, false);
+                }
+                self.index_expression_list(expression_list);
+            }
+        }
+    }
+}
+
+fn type_name<'de>(r#type: &Type<'de>) -> Option<&'de str> {
+    match r#type {
+        Type::Class { name } => Some(name.0),
+        _ => None,
+    }
+}
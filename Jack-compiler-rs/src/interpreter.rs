@@ -0,0 +1,1360 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{
+    parser::{
+        Class, ClassVarDecKind, Expression, ExpressionList, KeywordConstant, Op, Statement,
+        Statements, SubroutineCall, SubroutineDec, SubroutineDecType, Term, Type, UnaryOp,
+    },
+    tokenizer::Constant,
+};
+
+const MEMORY_SIZE: usize = 32768;
+const HEAP_BASE: usize = 2048;
+const HEAP_END: usize = 16384;
+const SCREEN: usize = 16384;
+const KEYBOARD: usize = 24576;
+
+const SCREEN_WIDTH: i16 = 512;
+const SCREEN_HEIGHT: i16 = 256;
+
+const NEWLINE: i16 = 128;
+const BACKSPACE: i16 = 129;
+const DOUBLE_QUOTE: i16 = 34;
+
+#[derive(Debug, Clone)]
+pub struct InterpreterConfig {
+    /// Maximum number of executed statements before giving up.
+    pub max_steps: u64,
+    /// Maximum depth of nested subroutine calls.
+    pub max_call_depth: usize,
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> Self {
+        Self {
+            max_steps: 10_000_000,
+            max_call_depth: 1024,
+        }
+    }
+}
+
+/// Raised by `Sys.halt` to unwind the interpreter.
+#[derive(Debug)]
+struct Halted;
+
+impl std::fmt::Display for Halted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Halted")
+    }
+}
+
+impl std::error::Error for Halted {}
+
+struct ClassInfo<'a, 'de> {
+    /// Field names and their class types, in declaration order.
+    fields: Vec<(&'de str, Option<&'de str>)>,
+    /// Static names to their index in `Interpreter::statics` and their class types.
+    statics: HashMap<&'de str, (usize, Option<&'de str>)>,
+    subroutines: HashMap<&'de str, &'a SubroutineDec<'de>>,
+}
+
+struct Frame<'de> {
+    class_name: &'de str,
+    this: Option<i16>,
+    /// Arguments and locals, with their class types.
+    vars: HashMap<&'de str, (i16, Option<&'de str>)>,
+}
+
+enum Place<'de> {
+    Var(&'de str),
+    Field(usize),
+    Static(usize),
+}
+
+/// A live heap block.
+struct Allocation {
+    size: usize,
+    /// The constructor, or the OS function and its caller, that allocated it.
+    site: String,
+}
+
+/// Heap blocks allocated from the same site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteUsage {
+    pub site: String,
+    pub blocks: usize,
+    pub words: usize,
+}
+
+/// The state of the heap, typically once the program is over.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeapReport {
+    /// Blocks never freed, largest first.
+    pub leaks: Vec<SiteUsage>,
+    /// `deAlloc`s of anything but a live block, such as a block freed twice.
+    pub invalid_frees: Vec<String>,
+}
+
+/// The heap right after an allocation or a free.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeapSample {
+    /// Statements executed so far.
+    pub step: u64,
+    pub blocks: usize,
+    /// Words of the live blocks, with their size headers.
+    pub used_words: usize,
+    pub free_words: usize,
+    /// The largest allocation that can still succeed is one word less.
+    pub largest_free_block: usize,
+}
+
+/// How the heap was used over a whole run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeapUsage {
+    /// Starting with the empty heap.
+    pub samples: Vec<HeapSample>,
+    /// Every block allocated, freed or not, by site, largest first.
+    pub sites: Vec<SiteUsage>,
+}
+
+enum Flow {
+    Next,
+    Return(i16),
+}
+
+/// Tree-walking interpreter of Jack classes, with a built-in model of the Jack OS.
+///
+/// Everything the program prints through `Output` is collected as text (`Output.moveCursor`
+/// is ignored), `Screen` draws into the memory-mapped screen, and `Keyboard` reads from the
+/// given input. `Keyboard.keyPressed` consumes one key of the input per call.
+pub struct Interpreter<'a, 'de> {
+    classes: HashMap<&'de str, ClassInfo<'a, 'de>>,
+    config: InterpreterConfig,
+
+    memory: Vec<i16>,
+    /// Free heap blocks `(start, len)`, sorted by start.
+    free: Vec<(usize, usize)>,
+    statics: Vec<i16>,
+    color: bool,
+
+    input: VecDeque<i16>,
+    output: String,
+
+    steps: u64,
+    /// Subroutines being run, innermost last.
+    calls: Vec<(&'de str, &'de str)>,
+
+    /// Live heap blocks by address.
+    allocations: BTreeMap<usize, Allocation>,
+    /// Sites of the blocks freed since, by address, to tell double frees apart.
+    freed: HashMap<usize, String>,
+    invalid_frees: Vec<String>,
+    /// Blocks and words allocated so far by site.
+    allocated: HashMap<String, (usize, usize)>,
+    heap_samples: Vec<HeapSample>,
+}
+
+impl<'a, 'de> Interpreter<'a, 'de> {
+    pub fn new(classes: &'a [Class<'de>], config: InterpreterConfig) -> anyhow::Result<Self> {
+        let mut infos = HashMap::new();
+        let mut statics_cnt = 0;
+
+        for class in classes.iter() {
+            let mut info = ClassInfo {
+                fields: vec![],
+                statics: HashMap::new(),
+                subroutines: HashMap::new(),
+            };
+
+            for class_var_dec in class.class_var_decs.iter() {
+                let r#type = class_type(&class_var_dec.class_var_dec_type);
+                for var_name in class_var_dec.var_names.iter() {
+                    match class_var_dec.class_var_dec_kind {
+                        ClassVarDecKind::Field => info.fields.push((var_name.0, r#type)),
+                        ClassVarDecKind::Static => {
+                            info.statics.insert(var_name.0, (statics_cnt, r#type));
+                            statics_cnt += 1;
+                        }
+                    }
+                }
+            }
+
+            for subroutine_dec in class.subroutine_decs.iter() {
+                info.subroutines
+                    .insert(subroutine_dec.subroutine_name.0, subroutine_dec);
+            }
+
+            if infos.insert(class.class_name.0, info).is_some() {
+                anyhow::bail!("Class `{}` is declared more than once", class.class_name.0);
+            }
+        }
+
+        Ok(Self {
+            classes: infos,
+            config,
+            memory: vec![0; MEMORY_SIZE],
+            free: vec![(HEAP_BASE, HEAP_END - HEAP_BASE)],
+            statics: vec![0; statics_cnt],
+            color: true,
+            input: VecDeque::new(),
+            output: String::new(),
+            steps: 0,
+            calls: vec![],
+            allocations: BTreeMap::new(),
+            freed: HashMap::new(),
+            invalid_frees: vec![],
+            allocated: HashMap::new(),
+            heap_samples: vec![HeapSample {
+                step: 0,
+                blocks: 0,
+                used_words: 0,
+                free_words: HEAP_END - HEAP_BASE,
+                largest_free_block: HEAP_END - HEAP_BASE,
+            }],
+        })
+    }
+
+    /// Queues keyboard input; `\n` is sent as the Jack newline key.
+    pub fn with_input(mut self, input: &str) -> Self {
+        self.input.extend(input.chars().map(|c| match c {
+            '\n' => NEWLINE,
+            c => c as i16,
+        }));
+
+        self
+    }
+
+    /// Runs `Main.main`, as `Sys.init` does.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        match self.call("Main", "main", None, vec![]) {
+            Ok(_) => Ok(()),
+            Err(e) if e.is::<Halted>() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Calls the function `class_name.subroutine_name` without arguments and returns
+    /// its value.
+    pub fn call_function(
+        &mut self,
+        class_name: &str,
+        subroutine_name: &str,
+    ) -> anyhow::Result<i16> {
+        self.call(class_name, subroutine_name, None, vec![])
+    }
+
+    /// Blocks still allocated through the built-in `Memory`, by the site that
+    /// allocated them, and the invalid `deAlloc`s seen so far.
+    pub fn heap_report(&self) -> HeapReport {
+        let mut sites: HashMap<&str, (usize, usize)> = HashMap::new();
+        for allocation in self.allocations.values() {
+            let (blocks, words) = sites.entry(&allocation.site).or_default();
+            *blocks += 1;
+            *words += allocation.size;
+        }
+
+        HeapReport {
+            leaks: site_usages(sites),
+            invalid_frees: self.invalid_frees.clone(),
+        }
+    }
+
+    /// The heap after every allocation and free through the built-in `Memory`, and
+    /// the sites that allocated the most.
+    pub fn heap_usage(&self) -> HeapUsage {
+        let sites = self
+            .allocated
+            .iter()
+            .map(|(site, &usage)| (site.as_str(), usage))
+            .collect();
+
+        HeapUsage {
+            samples: self.heap_samples.clone(),
+            sites: site_usages(sites),
+        }
+    }
+
+    /// Text printed so far through `Output`.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// The memory-mapped screen, 32 words per row of 512 pixels.
+    pub fn screen(&self) -> &[i16] {
+        &self.memory[SCREEN..KEYBOARD]
+    }
+
+    fn call(
+        &mut self,
+        class_name: &str,
+        subroutine_name: &str,
+        this: Option<i16>,
+        args: Vec<i16>,
+    ) -> anyhow::Result<i16> {
+        let Some((&class_name, info)) = self.classes.get_key_value(class_name) else {
+            return self.call_os(class_name, subroutine_name, this, args);
+        };
+        let Some(&subroutine_dec) = info.subroutines.get(subroutine_name) else {
+            anyhow::bail!("Could not find subroutine `{class_name}.{subroutine_name}`");
+        };
+        let fields_cnt = info.fields.len();
+        let subroutine_name = subroutine_dec.subroutine_name.0;
+
+        let this = match (&subroutine_dec.subroutine_dec_type, this) {
+            (SubroutineDecType::Method, Some(this)) => Some(this),
+            (SubroutineDecType::Method, None) => {
+                anyhow::bail!("`{class_name}.{subroutine_name}` is a method and needs an object")
+            }
+            (_, Some(_)) => {
+                anyhow::bail!("`{class_name}.{subroutine_name}` is not a method")
+            }
+            (SubroutineDecType::Constructor, None) => {
+                let site = format!("{class_name}.{subroutine_name}");
+                Some(self.alloc(fields_cnt.max(1) as i16, site)?)
+            }
+            (SubroutineDecType::Function, None) => None,
+        };
+
+        let parameters = &subroutine_dec.parameter_list.parameters;
+        if parameters.len() != args.len() {
+            anyhow::bail!(
+                "`{class_name}.{subroutine_name}` expects {} argument(s), got {}",
+                parameters.len(),
+                args.len()
+            );
+        }
+
+        let mut frame = Frame {
+            class_name,
+            this,
+            vars: HashMap::new(),
+        };
+        for ((r#type, identifier), arg) in parameters.iter().zip(args) {
+            frame.vars.insert(identifier.0, (arg, class_type(r#type)));
+        }
+        for var_dec in subroutine_dec.subroutine_body.var_decs.iter() {
+            for var_name in var_dec.var_names.iter() {
+                frame
+                    .vars
+                    .insert(var_name.0, (0, class_type(&var_dec.var_type)));
+            }
+        }
+
+        if self.calls.len() >= self.config.max_call_depth {
+            anyhow::bail!("Stack overflow calling `{class_name}.{subroutine_name}`");
+        }
+        self.calls.push((class_name, subroutine_name));
+        let flow = self.exec_statements(&mut frame, &subroutine_dec.subroutine_body.statements);
+        self.calls.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(0),
+        }
+    }
+
+    fn exec_statements(
+        &mut self,
+        frame: &mut Frame<'de>,
+        statements: &'a Statements<'de>,
+    ) -> anyhow::Result<Flow> {
+        for statement in statements.statements.iter() {
+            if let Flow::Return(value) = self.exec_statement(frame, statement)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn exec_statement(
+        &mut self,
+        frame: &mut Frame<'de>,
+        statement: &'a Statement<'de>,
+    ) -> anyhow::Result<Flow> {
+        self.step()?;
+
+        match statement {
+            Statement::LetStatement(let_statement) => {
+                let var_name = let_statement.var_name.0;
+                match &let_statement.expression_1 {
+                    Some(index) => {
+                        let base = self.read(frame, var_name)?;
+                        let index = self.eval(frame, index)?;
+                        let value = self.eval(frame, &let_statement.expression_2)?;
+                        let address = self.address(base.wrapping_add(index))?;
+                        self.memory[address] = value;
+                    }
+                    None => {
+                        let value = self.eval(frame, &let_statement.expression_2)?;
+                        self.write(frame, var_name, value)?;
+                    }
+                }
+            }
+            Statement::IfStatement(if_statement) => {
+                if self.eval(frame, &if_statement.condition)? != 0 {
+                    return self.exec_statements(frame, &if_statement.then_branch);
+                } else if let Some(else_branch) = &if_statement.else_branch {
+                    return self.exec_statements(frame, else_branch);
+                }
+            }
+            Statement::WhileStatement(while_statement) => {
+                while self.eval(frame, &while_statement.condition)? != 0 {
+                    self.step()?;
+                    if let Flow::Return(value) =
+                        self.exec_statements(frame, &while_statement.body)?
+                    {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Statement::DoStatement(do_statement) => {
+                self.eval_subroutine_call(frame, &do_statement.subroutine_call)?;
+            }
+            Statement::ReturnStatement(return_statement) => {
+                let value = match &return_statement.expression {
+                    Some(expression) => self.eval(frame, expression)?,
+                    None => 0,
+                };
+
+                return Ok(Flow::Return(value));
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn step(&mut self) -> anyhow::Result<()> {
+        self.steps += 1;
+        if self.steps > self.config.max_steps {
+            anyhow::bail!("Exceeded {} steps", self.config.max_steps);
+        }
+
+        Ok(())
+    }
+
+    fn eval(
+        &mut self,
+        frame: &mut Frame<'de>,
+        expression: &'a Expression<'de>,
+    ) -> anyhow::Result<i16> {
+        let mut value = self.eval_term(frame, &expression.term)?;
+
+        for (op, term) in expression.terms.iter() {
+            let other = self.eval_term(frame, term)?;
+            value = match op {
+                Op::Plus => value.wrapping_add(other),
+                Op::Minus => value.wrapping_sub(other),
+                Op::Asterisk => value.wrapping_mul(other),
+                Op::Slash => self.divide(value, other)?,
+                Op::Ampersand => value & other,
+                Op::Pipe => value | other,
+                Op::LessThan => boolean(value < other),
+                Op::GreaterThan => boolean(value > other),
+                Op::Equal => boolean(value == other),
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn eval_term(&mut self, frame: &mut Frame<'de>, term: &'a Term<'de>) -> anyhow::Result<i16> {
+        match term {
+            Term::Constant(Constant::Integer(i)) => Ok(*i as i16),
+            // Built with the program's own `String` class, when it has one
+            Term::Constant(Constant::String(s)) if self.classes.contains_key("String") => {
+                let mut string = self.call("String", "new", None, vec![s.len() as i16])?;
+                for b in s.as_bytes() {
+                    string = self.call("String", "appendChar", Some(string), vec![*b as i16])?;
+                }
+
+                Ok(string)
+            }
+            Term::Constant(Constant::String(s)) => {
+                let site = self.site("a string constant");
+                let string = self.string_new(s.len() as i16, site)?;
+                for b in s.as_bytes() {
+                    self.string_append_char(string, *b as i16)?;
+                }
+
+                Ok(string)
+            }
+            Term::KeywordConstant(keyword_constant) => Ok(match keyword_constant {
+                KeywordConstant::True => -1,
+                KeywordConstant::False | KeywordConstant::Null => 0,
+                KeywordConstant::This => frame.this.unwrap_or(0),
+            }),
+            Term::VarName(identifier) => self.read(frame, identifier.0),
+            Term::VarNameExpression {
+                var_name,
+                expression,
+            } => {
+                let base = self.read(frame, var_name.0)?;
+                let index = self.eval(frame, expression)?;
+                let address = self.address(base.wrapping_add(index))?;
+
+                Ok(self.memory[address])
+            }
+            Term::Expression(expression) => self.eval(frame, expression),
+            Term::UnaryOpTerm { unary_op, term } => {
+                let value = self.eval_term(frame, term)?;
+
+                Ok(match unary_op {
+                    UnaryOp::Minus => value.wrapping_neg(),
+                    UnaryOp::Tilde => !value,
+                })
+            }
+            Term::SubroutineCall(subroutine_call) => {
+                self.eval_subroutine_call(frame, subroutine_call)
+            }
+        }
+    }
+
+    fn eval_subroutine_call(
+        &mut self,
+        frame: &mut Frame<'de>,
+        subroutine_call: &'a SubroutineCall<'de>,
+    ) -> anyhow::Result<i16> {
+        match subroutine_call {
+            SubroutineCall::Call {
+                subroutine_name,
+                expression_list,
+            } => {
+                let args = self.eval_expression_list(frame, expression_list)?;
+                let is_method = self.classes[frame.class_name]
+                    .subroutines
+                    .get(subroutine_name.0)
+                    .is_some_and(|s| matches!(s.subroutine_dec_type, SubroutineDecType::Method));
+                let this = if is_method {
+                    let Some(this) = frame.this else {
+                        anyhow::bail!(
+                            "Method `{}.{}` called from a function",
+                            frame.class_name,
+                            subroutine_name.0
+                        );
+                    };
+                    Some(this)
+                } else {
+                    None
+                };
+
+                self.call(frame.class_name, subroutine_name.0, this, args)
+            }
+            SubroutineCall::ClassCall {
+                class_or_var_name,
+                subroutine_name,
+                expression_list,
+            } => match self.resolve(frame, class_or_var_name.0) {
+                // A method call on a variable of a class type
+                Some((_, r#type)) => {
+                    let Some(class_name) = r#type else {
+                        anyhow::bail!(
+                            "`{}` is not an object, can not call `{}` on it",
+                            class_or_var_name.0,
+                            subroutine_name.0
+                        );
+                    };
+                    let this = self.read(frame, class_or_var_name.0)?;
+                    let args = self.eval_expression_list(frame, expression_list)?;
+
+                    self.call(class_name, subroutine_name.0, Some(this), args)
+                }
+                // Ok. It's either a class constructor or a class function call
+                None => {
+                    let args = self.eval_expression_list(frame, expression_list)?;
+
+                    self.call(class_or_var_name.0, subroutine_name.0, None, args)
+                }
+            },
+        }
+    }
+
+    fn eval_expression_list(
+        &mut self,
+        frame: &mut Frame<'de>,
+        expression_list: &'a ExpressionList<'de>,
+    ) -> anyhow::Result<Vec<i16>> {
+        expression_list
+            .expressions
+            .iter()
+            .map(|expression| self.eval(frame, expression))
+            .collect()
+    }
+
+    /// Finds a variable in the subroutine scope first, then in the class scope.
+    fn resolve(
+        &self,
+        frame: &Frame<'de>,
+        name: &'de str,
+    ) -> Option<(Place<'de>, Option<&'de str>)> {
+        if let Some(&(_, r#type)) = frame.vars.get(name) {
+            return Some((Place::Var(name), r#type));
+        }
+
+        let info = &self.classes[frame.class_name];
+        let field = info.fields.iter().position(|(field, _)| *field == name);
+        if let (Some(_), Some(index)) = (frame.this, field) {
+            return Some((Place::Field(index), info.fields[index].1));
+        }
+
+        info.statics
+            .get(name)
+            .map(|&(index, r#type)| (Place::Static(index), r#type))
+    }
+
+    fn read(&self, frame: &Frame<'de>, name: &'de str) -> anyhow::Result<i16> {
+        match self.resolve(frame, name) {
+            Some((Place::Var(name), _)) => Ok(frame.vars[name].0),
+            Some((Place::Field(index), _)) => {
+                let address = self.address(frame.this.unwrap_or(0).wrapping_add(index as i16))?;
+
+                Ok(self.memory[address])
+            }
+            Some((Place::Static(index), _)) => Ok(self.statics[index]),
+            None => anyhow::bail!("Could not find `{name}` in `{}`", frame.class_name),
+        }
+    }
+
+    fn write(&mut self, frame: &mut Frame<'de>, name: &'de str, value: i16) -> anyhow::Result<()> {
+        match self.resolve(frame, name) {
+            Some((Place::Var(name), _)) => {
+                frame.vars.get_mut(name).expect("Resolved variable").0 = value;
+            }
+            Some((Place::Field(index), _)) => {
+                let address = self.address(frame.this.unwrap_or(0).wrapping_add(index as i16))?;
+                self.memory[address] = value;
+            }
+            Some((Place::Static(index), _)) => self.statics[index] = value,
+            None => anyhow::bail!("Could not find `{name}` in `{}`", frame.class_name),
+        }
+
+        Ok(())
+    }
+
+    fn address(&self, address: i16) -> anyhow::Result<usize> {
+        if address < 0 {
+            anyhow::bail!("Invalid memory access at {address}");
+        }
+
+        Ok(address as usize)
+    }
+
+    /// Built-in OS classes, used unless the program declares a class of the same name.
+    fn call_os(
+        &mut self,
+        class_name: &str,
+        subroutine_name: &str,
+        this: Option<i16>,
+        args: Vec<i16>,
+    ) -> anyhow::Result<i16> {
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        let this_or_arg = this.unwrap_or_else(|| arg(0));
+        // Methods get their object as `this`, but may also be called as `String.length(s)`
+        let method_arg = |i: usize| {
+            if this.is_some() { arg(i) } else { arg(i + 1) }
+        };
+
+        let value = match (class_name, subroutine_name) {
+            ("Math", "multiply") => arg(0).wrapping_mul(arg(1)),
+            ("Math", "divide") => self.divide(arg(0), arg(1))?,
+            ("Math", "min") => arg(0).min(arg(1)),
+            ("Math", "max") => arg(0).max(arg(1)),
+            ("Math", "abs") => arg(0).wrapping_abs(),
+            ("Math", "sqrt") => {
+                if arg(0) < 0 {
+                    return Err(sys_error(4));
+                }
+                (arg(0) as f64).sqrt() as i16
+            }
+
+            ("Memory", "peek") => self.memory[self.address(arg(0))?],
+            ("Memory", "poke") => {
+                let address = self.address(arg(0))?;
+                self.memory[address] = arg(1);
+                0
+            }
+            ("Memory", "alloc") => self.alloc(arg(0), self.site("Memory.alloc"))?,
+            ("Memory", "deAlloc") => {
+                self.dealloc(arg(0), "Memory.deAlloc");
+                0
+            }
+
+            ("Array", "new") => {
+                if arg(0) <= 0 {
+                    return Err(sys_error(2));
+                }
+                self.alloc(arg(0), self.site("Array.new"))?
+            }
+            ("Array", "dispose") => {
+                self.dealloc(this_or_arg, "Array.dispose");
+                0
+            }
+
+            ("String", "new") => self.string_new(arg(0), self.site("String.new"))?,
+            ("String", "dispose") => {
+                self.dealloc(this_or_arg, "String.dispose");
+                0
+            }
+            ("String", "length") => self.memory[self.address(this_or_arg)? + 1],
+            ("String", "charAt") => self.string_char_at(this_or_arg, method_arg(0))?,
+            ("String", "setCharAt") => {
+                let (length, chars) = self.string_parts(this_or_arg)?;
+                let index = method_arg(0);
+                if index < 0 || index >= length {
+                    return Err(sys_error(16));
+                }
+                self.memory[chars + index as usize] = method_arg(1);
+                0
+            }
+            ("String", "appendChar") => self.string_append_char(this_or_arg, method_arg(0))?,
+            ("String", "eraseLastChar") => {
+                let (length, _) = self.string_parts(this_or_arg)?;
+                if length == 0 {
+                    return Err(sys_error(18));
+                }
+                let address = self.address(this_or_arg)?;
+                self.memory[address + 1] = length - 1;
+                0
+            }
+            ("String", "intValue") => {
+                let digits = self.string_to_rust(this_or_arg)?;
+                let (negative, digits) = match digits.strip_prefix('-') {
+                    Some(digits) => (true, digits),
+                    None => (false, digits.as_str()),
+                };
+                let value = digits
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .fold(0i16, |value, c| {
+                        value.wrapping_mul(10).wrapping_add(c as i16 - '0' as i16)
+                    });
+
+                if negative {
+                    value.wrapping_neg()
+                } else {
+                    value
+                }
+            }
+            ("String", "setInt") => {
+                let string = this_or_arg;
+                let digits = method_arg(0).to_string();
+                let max_length = self.memory[self.address(string)?];
+                if digits.len() as i16 > max_length {
+                    return Err(sys_error(19));
+                }
+                self.memory[string as usize + 1] = 0;
+                for b in digits.bytes() {
+                    self.string_append_char(string, b as i16)?;
+                }
+                0
+            }
+            ("String", "backSpace") => BACKSPACE,
+            ("String", "doubleQuote") => DOUBLE_QUOTE,
+            ("String", "newLine") => NEWLINE,
+
+            ("Output", "init") | ("Output", "moveCursor") => 0,
+            ("Output", "printChar") => {
+                self.print_char(arg(0));
+                0
+            }
+            ("Output", "printString") => {
+                let string = self.string_to_rust(arg(0))?;
+                self.output.push_str(&string);
+                0
+            }
+            ("Output", "printInt") => {
+                self.output.push_str(&arg(0).to_string());
+                0
+            }
+            ("Output", "println") => {
+                self.output.push('\n');
+                0
+            }
+            ("Output", "backSpace") => {
+                self.print_char(BACKSPACE);
+                0
+            }
+
+            ("Screen", "init") => 0,
+            ("Screen", "clearScreen") => {
+                self.memory[SCREEN..KEYBOARD].fill(0);
+                0
+            }
+            ("Screen", "setColor") => {
+                self.color = arg(0) != 0;
+                0
+            }
+            ("Screen", "drawPixel") => {
+                if !on_screen(arg(0), arg(1)) {
+                    return Err(sys_error(7));
+                }
+                self.draw_pixel(arg(0), arg(1));
+                0
+            }
+            ("Screen", "drawLine") => {
+                if !on_screen(arg(0), arg(1)) || !on_screen(arg(2), arg(3)) {
+                    return Err(sys_error(8));
+                }
+                self.draw_line(arg(0), arg(1), arg(2), arg(3));
+                0
+            }
+            ("Screen", "drawRectangle") => {
+                let (x1, y1, x2, y2) = (arg(0), arg(1), arg(2), arg(3));
+                if !on_screen(x1, y1) || !on_screen(x2, y2) || x1 > x2 || y1 > y2 {
+                    return Err(sys_error(9));
+                }
+                for y in y1..=y2 {
+                    for x in x1..=x2 {
+                        self.draw_pixel(x, y);
+                    }
+                }
+                0
+            }
+            ("Screen", "drawCircle") => {
+                let (x, y, r) = (arg(0), arg(1), arg(2));
+                if !on_screen(x, y) {
+                    return Err(sys_error(12));
+                }
+                if !(0..=181).contains(&r) {
+                    return Err(sys_error(13));
+                }
+                for dy in -r..=r {
+                    let dx = ((r as i32 * r as i32 - dy as i32 * dy as i32) as f64).sqrt() as i16;
+                    for px in x - dx..=x + dx {
+                        if on_screen(px, y + dy) {
+                            self.draw_pixel(px, y + dy);
+                        }
+                    }
+                }
+                0
+            }
+
+            ("Keyboard", "init") => 0,
+            ("Keyboard", "keyPressed") => {
+                let key = self.input.pop_front().unwrap_or(0);
+                self.memory[KEYBOARD] = key;
+                key
+            }
+            ("Keyboard", "readChar") => self.read_char()?,
+            ("Keyboard", "readLine") => {
+                let message = self.string_to_rust(arg(0))?;
+                self.output.push_str(&message);
+                self.read_line()?
+            }
+            ("Keyboard", "readInt") => {
+                let message = self.string_to_rust(arg(0))?;
+                self.output.push_str(&message);
+                let line = self.read_line()?;
+                self.call_os("String", "intValue", Some(line), vec![])?
+            }
+
+            ("Sys", "init") => {
+                self.call("Main", "main", None, vec![])?;
+                return Err(Halted.into());
+            }
+            ("Sys", "halt") => return Err(Halted.into()),
+            ("Sys", "error") => return Err(sys_error(arg(0))),
+            ("Sys", "wait") => 0,
+
+            _ => anyhow::bail!("Could not find subroutine `{class_name}.{subroutine_name}`"),
+        };
+
+        Ok(value)
+    }
+
+    fn divide(&self, x: i16, y: i16) -> anyhow::Result<i16> {
+        if y == 0 {
+            return Err(sys_error(3));
+        }
+
+        Ok(x.wrapping_div(y))
+    }
+
+    /// `os_function` called by the subroutine being run, as an allocation site.
+    fn site(&self, os_function: &str) -> String {
+        match self.calls.last() {
+            Some((class_name, subroutine_name)) => {
+                format!("{os_function} in {class_name}.{subroutine_name}")
+            }
+            None => os_function.to_string(),
+        }
+    }
+
+    /// First-fit allocation; the block size is kept right before the returned address.
+    fn alloc(&mut self, size: i16, site: String) -> anyhow::Result<i16> {
+        if size <= 0 {
+            return Err(sys_error(5));
+        }
+        let needed = size as usize + 1;
+
+        let Some(i) = self.free.iter().position(|&(_, len)| len >= needed) else {
+            return Err(sys_error(6));
+        };
+        let (start, len) = self.free[i];
+        if len == needed {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (start + needed, len - needed);
+        }
+
+        self.memory[start] = size;
+        let address = start + 1;
+        self.freed.remove(&address);
+        let (blocks, words) = self.allocated.entry(site.clone()).or_default();
+        *blocks += 1;
+        *words += size as usize;
+        self.allocations.insert(
+            address,
+            Allocation {
+                size: size as usize,
+                site,
+            },
+        );
+        self.sample_heap();
+
+        Ok(address as i16)
+    }
+
+    /// Frees a live block; anything else is reported, leaving the heap as it is.
+    fn dealloc(&mut self, address: i16, os_function: &str) {
+        let Some(allocation) = self.allocations.remove(&(address as usize)) else {
+            let freer = self.site(os_function);
+            self.invalid_frees
+                .push(match self.freed.get(&(address as usize)) {
+                    Some(site) => format!("{freer} frees {address} twice (allocated by {site})"),
+                    None => format!("{freer} frees {address}, which was never allocated"),
+                });
+
+            return;
+        };
+        let start = address as usize - 1;
+        let len = allocation.size + 1;
+        self.freed.insert(address as usize, allocation.site);
+
+        let i = self.free.partition_point(|&(s, _)| s < start);
+        self.free.insert(i, (start, len));
+
+        // Merge with the neighbours
+        if i + 1 < self.free.len() && self.free[i].0 + self.free[i].1 == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == self.free[i].0 {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+        self.sample_heap();
+    }
+
+    fn sample_heap(&mut self) {
+        let free_words = self.free.iter().map(|&(_, len)| len).sum();
+        self.heap_samples.push(HeapSample {
+            step: self.steps,
+            blocks: self.allocations.len(),
+            used_words: HEAP_END - HEAP_BASE - free_words,
+            free_words,
+            largest_free_block: self.free.iter().map(|&(_, len)| len).max().unwrap_or(0),
+        });
+    }
+
+    /// A string is `[max length, length, chars..]`.
+    fn string_new(&mut self, max_length: i16, site: String) -> anyhow::Result<i16> {
+        if max_length < 0 {
+            return Err(sys_error(14));
+        }
+        let string = self.alloc(max_length + 2, site)?;
+        self.memory[string as usize] = max_length;
+        self.memory[string as usize + 1] = 0;
+
+        Ok(string)
+    }
+
+    fn string_parts(&self, string: i16) -> anyhow::Result<(i16, usize)> {
+        let address = self.address(string)?;
+
+        Ok((self.memory[address + 1], address + 2))
+    }
+
+    fn string_char_at(&self, string: i16, index: i16) -> anyhow::Result<i16> {
+        let (length, chars) = self.string_parts(string)?;
+        if index < 0 || index >= length {
+            return Err(sys_error(15));
+        }
+
+        Ok(self.memory[chars + index as usize])
+    }
+
+    fn string_append_char(&mut self, string: i16, c: i16) -> anyhow::Result<i16> {
+        let address = self.address(string)?;
+        let (max_length, length) = (self.memory[address], self.memory[address + 1]);
+        if length >= max_length {
+            return Err(sys_error(17));
+        }
+        self.memory[address + 2 + length as usize] = c;
+        self.memory[address + 1] = length + 1;
+
+        Ok(string)
+    }
+
+    fn string_to_rust(&self, string: i16) -> anyhow::Result<String> {
+        let (length, chars) = self.string_parts(string)?;
+
+        Ok(self.memory[chars..chars + length.max(0) as usize]
+            .iter()
+            .map(|&c| jack_char(c))
+            .collect())
+    }
+
+    fn print_char(&mut self, c: i16) {
+        match c {
+            NEWLINE => self.output.push('\n'),
+            BACKSPACE => {
+                if !self.output.ends_with('\n') {
+                    self.output.pop();
+                }
+            }
+            c => self.output.push(jack_char(c)),
+        }
+    }
+
+    fn read_char(&mut self) -> anyhow::Result<i16> {
+        let Some(c) = self.input.pop_front() else {
+            anyhow::bail!("Keyboard input is exhausted");
+        };
+        self.print_char(c);
+
+        Ok(c)
+    }
+
+    fn read_line(&mut self) -> anyhow::Result<i16> {
+        let mut line = vec![];
+        loop {
+            match self.read_char()? {
+                NEWLINE => break,
+                BACKSPACE => {
+                    line.pop();
+                }
+                c => line.push(c),
+            }
+        }
+
+        let site = self.site("Keyboard.readLine");
+        let string = self.string_new(line.len() as i16, site)?;
+        for c in line {
+            self.string_append_char(string, c)?;
+        }
+
+        Ok(string)
+    }
+
+    fn draw_pixel(&mut self, x: i16, y: i16) {
+        let address = SCREEN + (y as usize) * 32 + (x as usize) / 16;
+        let mask = 1i16 << (x % 16);
+
+        if self.color {
+            self.memory[address] |= mask;
+        } else {
+            self.memory[address] &= !mask;
+        }
+    }
+
+    fn draw_line(&mut self, x1: i16, y1: i16, x2: i16, y2: i16) {
+        let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+        let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+
+        loop {
+            self.draw_pixel(x, y);
+            if x == x2 && y == y2 {
+                break;
+            }
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+/// Largest first, then by site.
+fn site_usages(sites: HashMap<&str, (usize, usize)>) -> Vec<SiteUsage> {
+    let mut usages: Vec<_> = sites
+        .into_iter()
+        .map(|(site, (blocks, words))| SiteUsage {
+            site: site.to_string(),
+            blocks,
+            words,
+        })
+        .collect();
+    usages.sort_by(|a, b| b.words.cmp(&a.words).then_with(|| a.site.cmp(&b.site)));
+
+    usages
+}
+
+fn class_type<'de>(r#type: &Type<'de>) -> Option<&'de str> {
+    match r#type {
+        Type::Class { name } => Some(name.0),
+        _ => None,
+    }
+}
+
+fn boolean(value: bool) -> i16 {
+    if value { -1 } else { 0 }
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..SCREEN_WIDTH).contains(&x) && (0..SCREEN_HEIGHT).contains(&y)
+}
+
+fn jack_char(c: i16) -> char {
+    match c {
+        NEWLINE => '\n',
+        c => u8::try_from(c).map(char::from).unwrap_or('?'),
+    }
+}
+
+/// The OS error codes of `Sys.error`.
+fn sys_error(code: i16) -> anyhow::Error {
+    let reason = match code {
+        2 => "Array size must be positive",
+        3 => "Division by zero",
+        4 => "Cannot compute square root of a negative number",
+        5 => "Allocated memory size must be positive",
+        6 => "Heap overflow",
+        7 => "Illegal pixel coordinates",
+        8 => "Illegal line coordinates",
+        9 => "Illegal rectangle coordinates",
+        12 => "Illegal center coordinates",
+        13 => "Illegal radius",
+        14 => "Maximum length must be non-negative",
+        15 => "String index out of bounds",
+        16 => "String index out of bounds",
+        17 => "String is full",
+        18 => "String is empty",
+        19 => "Insufficient string capacity",
+        _ => "Program error",
+    };
+
+    anyhow::anyhow!("Sys.error({code}): {reason}")
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use super::*;
+    use crate::{parser::Parser, tokenizer::Tokenizer};
+
+    fn run(source: &str, input: &str) -> anyhow::Result<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+        let classes = classes?;
+
+        let mut interpreter =
+            Interpreter::new(&classes, InterpreterConfig::default())?.with_input(input);
+        interpreter.run()?;
+
+        Ok(interpreter.output().to_string())
+    }
+
+    #[test]
+    fn arithmetic() {
+        let output = run(
+            "class Main {
+                function void main() {
+                    var int i, sum;
+                    let i = 0;
+                    while (i < 10) {
+                        let sum = sum + (i * i);
+                        let i = i + 1;
+                    }
+                    do Output.printInt(sum);
+                    do Output.println();
+                    do Output.printInt(-7 / 2);
+                    do Output.println();
+                    do Output.printInt(~(3 = 3));
+                    do Output.println();
+                    do Output.printInt(32767 + 1);
+                    return;
+                }
+            }",
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(output, "285\n-3\n0\n-32768");
+    }
+
+    #[test]
+    fn objects_and_strings() {
+        let output = run(
+            "class Counter {
+                field int count;
+                static int instances;
+
+                constructor Counter new(int start) {
+                    let count = start;
+                    let instances = instances + 1;
+                    return this;
+                }
+
+                method int inc() {
+                    let count = count + 1;
+                    return count;
+                }
+
+                function int instances() {
+                    return instances;
+                }
+            }
+
+            class Main {
+                function void main() {
+                    var Counter a, b;
+                    var String s;
+                    var Array xs;
+                    let a = Counter.new(10);
+                    let b = Counter.new(20);
+                    do a.inc();
+                    do Output.printInt(a.inc() + b.inc());
+                    do Output.printChar(32);
+                    do Output.printInt(Counter.instances());
+                    let s = \"Hi\";
+                    do Output.printString(s);
+                    do Output.printInt(s.length());
+                    let xs = Array.new(3);
+                    let xs[2] = 5;
+                    do Output.printInt(xs[2]);
+                    return;
+                }
+            }",
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(output, "33 2Hi25");
+    }
+
+    #[test]
+    fn keyboard() {
+        let output = run(
+            "class Main {
+                function void main() {
+                    var int n;
+                    let n = Keyboard.readInt(\"n? \");
+                    do Output.printInt(n * 2);
+                    return;
+                }
+            }",
+            "21\n",
+        )
+        .unwrap();
+
+        assert_eq!(output, "n? 21\n42");
+    }
+
+    #[test]
+    fn generated_programs() {
+        use crate::generator::{Generator, GeneratorConfig};
+
+        for seed in 0..20 {
+            let source = Generator::new(seed, GeneratorConfig::default()).generate();
+            let output = run(&source, "").unwrap();
+
+            assert!(output.lines().count() >= 4, "{source}");
+        }
+    }
+
+    #[test]
+    fn heap_report_attributes_leaks_and_invalid_frees() {
+        let source = "class Point {
+                field int x, y;
+                constructor Point new() {
+                    var Array cache;
+                    let cache = Array.new(4);
+                    return this;
+                }
+                method void dispose() {
+                    do Memory.deAlloc(this);
+                    return;
+                }
+            }
+            class Main {
+                function void main() {
+                    var Point a, b;
+                    let a = Point.new();
+                    let b = Point.new();
+                    do b.dispose();
+                    do b.dispose();
+                    do Memory.deAlloc(1234);
+                    return;
+                }
+            }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+        let mut interpreter = Interpreter::new(&classes, InterpreterConfig::default()).unwrap();
+        interpreter.run().unwrap();
+
+        let report = interpreter.heap_report();
+        let leaks: Vec<_> = report
+            .leaks
+            .iter()
+            .map(|leak| (leak.site.as_str(), leak.blocks, leak.words))
+            .collect();
+        assert_eq!(
+            leaks,
+            [("Array.new in Point.new", 2, 8), ("Point.new", 1, 2)]
+        );
+        assert_eq!(
+            report.invalid_frees,
+            [
+                "Memory.deAlloc in Point.dispose frees 2057 twice (allocated by Point.new)",
+                "Memory.deAlloc in Main.main frees 1234, which was never allocated"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let division = run(
+            "class Main {
+                function void main() {
+                    var int zero;
+                    do Output.printInt(1 / zero);
+                    return;
+                }
+            }",
+            "",
+        );
+        assert!(division.unwrap_err().to_string().contains("Sys.error(3)"));
+
+        let halt = run(
+            "class Main {
+                function void main() {
+                    do Output.printInt(1);
+                    do Sys.halt();
+                    do Output.printInt(2);
+                    return;
+                }
+            }",
+            "",
+        );
+        assert_eq!(halt.unwrap(), "1");
+
+        let forever = run(
+            "class Main {
+                function void main() {
+                    while (true) {
+                    }
+                    return;
+                }
+            }",
+            "",
+        );
+        assert!(forever.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Hack platform limits a compiled program has to stay within.
+
+use std::fmt::Display;
+
+use crate::{
+    call_graph::{CallGraph, FRAME_WORDS},
+    metrics::ClassMetrics,
+};
+
+/// Upper bounds checked once every class is compiled. [`Limits::default`] is the
+/// Hack platform. Code size is checked by the VM translator and the assembler.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Static variables of all classes together, RAM[16..255].
+    pub statics: usize,
+    /// Expression nesting; every level keeps at least one word on the
+    /// RAM[256..2047] stack.
+    pub expression_depth: usize,
+    /// Characters of a string constant. `String.new` allocates them and the string's
+    /// 3 fields as two blocks of the RAM[2048..16383] heap, each with a header word.
+    pub string_length: usize,
+    /// Words of the RAM[256..2047] stack; more run into the heap.
+    pub stack: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            statics: 240,
+            expression_depth: 2048 - 256,
+            string_length: 16384 - 2048 - 3 - 2,
+            stack: 2048 - 256,
+        }
+    }
+}
+
+/// What the classes compiled so far use of each limited resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Usage {
+    pub statics: usize,
+    pub expression_depth: usize,
+    pub call_graph: CallGraph,
+}
+
+impl Usage {
+    pub fn add(&mut self, metrics: &ClassMetrics) {
+        self.statics += metrics.statics;
+        self.expression_depth = self.expression_depth.max(metrics.max_expression_depth);
+        self.call_graph.add(metrics);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LimitExceeded {
+    pub what: &'static str,
+    pub used: usize,
+    pub max: usize,
+    pub hint: &'static str,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many {}: {} of at most {}. Hint: {}",
+            self.what, self.used, self.max, self.hint
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Calls from the start of the program that may take more of the stack than there is.
+#[derive(Debug, PartialEq)]
+pub struct StackRisk {
+    pub words: usize,
+    pub max: usize,
+    pub calls: Vec<String>,
+}
+
+impl Display for StackRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The stack may take {} of at most {} words, calling {}, and run into the heap. \
+             Hint: keep fewer values on the stack across calls, or call less deeply",
+            self.words,
+            self.max,
+            self.calls.join(" -> ")
+        )
+    }
+}
+
+impl Limits {
+    /// The string length limit, if a string constant of `length` characters goes over it.
+    pub fn check_string(&self, length: usize) -> Option<LimitExceeded> {
+        (length > self.string_length).then_some(LimitExceeded {
+            what: "characters in a string constant",
+            used: length,
+            max: self.string_length,
+            hint: "split the text into several strings, or read it from a file",
+        })
+    }
+
+    /// The deepest calls from `Sys.init`, or from `Main.main` called by the OS's, if
+    /// they may take more than the stack. Recursion is left out, as its depth is unknown.
+    pub fn check_stack(&self, usage: &Usage) -> Option<StackRisk> {
+        let (words, calls) = if usage.call_graph.contains("Sys.init") {
+            usage.call_graph.deepest("Sys.init")?
+        } else {
+            let (words, mut calls) = usage.call_graph.deepest("Main.main")?;
+            calls.insert(0, "Sys.init".to_string());
+            (FRAME_WORDS + words, calls)
+        };
+
+        (words > self.stack).then_some(StackRisk {
+            words,
+            max: self.stack,
+            calls,
+        })
+    }
+
+    /// Every limit `usage` goes over.
+    pub fn check(&self, usage: &Usage) -> Vec<LimitExceeded> {
+        [
+            (
+                "static variables",
+                usage.statics,
+                self.statics,
+                "turn statics into fields, or group them in an Array",
+            ),
+            (
+                "nested expression levels",
+                usage.expression_depth,
+                self.expression_depth,
+                "split the expression with local variables",
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, used, max, _)| used > max)
+        .map(|(what, used, max, hint)| LimitExceeded {
+            what,
+            used,
+            max,
+            hint,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+    use crate::{
+        compiler::Compiler, metrics::class_metrics, parser::Parser, session::Session,
+        tokenizer::Tokenizer,
+    };
+
+    fn usage(sources: &[&str]) -> Usage {
+        let mut usage = Usage::default();
+        for source in sources {
+            let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+            let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+            usage.add(&class_metrics(&classes.unwrap()[0], &[]));
+        }
+
+        usage
+    }
+
+    #[test]
+    fn statics_add_up_across_classes() {
+        let usage = usage(&[
+            "class A { static int a, b; function void f() { return; } }",
+            "class B { static int c; field int d; function int g() { return (1 + (2 * 3)); } }",
+        ]);
+
+        assert_eq!(usage.statics, 3);
+        assert_eq!(usage.expression_depth, 3);
+    }
+
+    #[test]
+    fn reports_exceeded_limits_only() {
+        let limits = Limits {
+            statics: 2,
+            ..Default::default()
+        };
+        let usage = usage(&["class A { static int a, b, c; function void f() { return; } }"]);
+
+        let exceeded = limits.check(&usage);
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(
+            exceeded[0].to_string(),
+            "Too many static variables: 3 of at most 2. Hint: turn statics into fields, or group them in an Array"
+        );
+    }
+
+    #[test]
+    fn string_constants_fit_in_the_heap() {
+        let limits = Limits::default();
+
+        assert!(limits.check_string(14331).is_none());
+        assert_eq!(
+            limits.check_string(14332).unwrap().to_string(),
+            "Too many characters in a string constant: 14332 of at most 14331. Hint: split the text into several strings, or read it from a file"
+        );
+    }
+
+    #[test]
+    fn deep_calls_may_run_into_the_heap() {
+        let source = "class Main {
+            function void main() {
+                do Main.f(1);
+                return;
+            }
+            function int f(int a) {
+                var int x, y;
+                return a + (a * a);
+            }
+        }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+        let instructions = Compiler::new(classes.iter()).compile(&mut Session::default());
+        let mut usage = Usage::default();
+        usage.add(&class_metrics(&classes[0], &instructions));
+
+        // Frames of Sys.init, Main.main, Main.f and Math.multiply, `1`, 2 locals, 3 operands
+        assert!(Limits::default().check_stack(&usage).is_none());
+        let limits = Limits {
+            stack: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.check_stack(&usage).unwrap().to_string(),
+            "The stack may take 26 of at most 20 words, calling Sys.init -> Main.main -> Main.f -> Math.multiply, and run into the heap. Hint: keep fewer values on the stack across calls, or call less deeply"
+        );
+    }
+}
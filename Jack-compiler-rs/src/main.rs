@@ -1,4 +1,3 @@
-use std::fs::OpenOptions;
 use std::io::Write;
 use std::{
     ffi::OsString,
@@ -7,26 +6,244 @@ use std::{
 };
 
 use clap::Parser as _;
+use n2t_core::files::has_extension;
+use n2t_core::profiling;
+use n2t_core::version::Version;
+use once_cell::sync::Lazy;
 
-use crate::compiler::Compiler;
+use crate::archive::Sandbox;
+use crate::compiler::snippet::{self, Environment};
+use crate::compiler::{Codegen, Compiler};
+use crate::emitter::{
+    Emit, Emitter, ExportsWriter, LineMapWriter, ManifestWriter, MetricsWriter, TokenDumpWriter,
+    VmWriter,
+};
+use crate::generator::{Generator, GeneratorConfig};
+use crate::index::SymbolIndex;
+use crate::interpreter::{Interpreter, InterpreterConfig};
 use crate::parser::Parser;
-use crate::tokenizer::{Token, Tokenizer};
+use crate::repl::Repl;
+use crate::session::{Arithmetic, Booleans, LabelStyle, Options, Session, Severity, ThisHandling};
+use crate::tokenizer::{Constant, Token, TokenType, Tokenizer};
 
+mod archive;
+mod bit_math;
+mod call_graph;
 mod compiler;
+mod emitter;
+mod examples;
+mod generator;
+mod grammar;
+mod heap_usage;
+mod index;
+mod interpreter;
+mod limits;
+mod metrics;
+mod no_os;
+mod os_suite;
 mod parser;
 #[cfg(feature = "xml")]
 mod parser_xml;
+mod recursion;
+mod rename;
+mod repl;
+mod session;
+mod stubs;
+mod token_cursor;
 mod tokenizer;
 #[cfg(feature = "xml")]
 mod tokenizer_xml;
 
 const JACK_EXT: &str = "jack";
+const VM_EXT: &str = "vm";
+
+static VERSION: Lazy<Version> = Lazy::new(|| n2t_core::version!("xml", "profiling"));
 
 #[derive(clap::Parser)]
-#[command(about = "Jack language compiler", long_about = None)]
+#[command(
+    version = VERSION.version,
+    long_version = VERSION.long(),
+    about = "Jack language compiler",
+    long_about = examples::LONG_ABOUT,
+    after_long_help = examples::long_help()
+)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
     /// Input .jack file or directory
-    input: PathBuf,
+    #[arg(required_unless_present = "emit_grammar")]
+    input: Option<PathBuf>,
+
+    /// Additionally: Output per-class metrics as JSON
+    #[arg(long, value_name = "FILE")]
+    metrics: Option<PathBuf>,
+
+    /// Additionally: Output the tokens of every file, with kind and position, as JSON
+    #[arg(long, value_name = "FILE")]
+    dump: Option<PathBuf>,
+
+    /// Additionally: Output the .jack line every stretch of the VM code comes from as
+    /// JSON, for the assembler's --coverage to count hits of Jack lines
+    #[arg(long, value_name = "FILE")]
+    line_map: Option<PathBuf>,
+
+    /// Additionally: Output `xml` token and parse tree files next to each source
+    #[arg(long, value_enum, value_name = "KIND")]
+    emit: Vec<Emit>,
+
+    /// Only warn when the program exceeds a Hack platform limit (statics, expression depth)
+    #[arg(long)]
+    ignore_limits: bool,
+
+    /// Reject `*`, `/`, strings, constructors and OS calls, for programs run without an OS
+    #[arg(long)]
+    no_os: bool,
+
+    /// Write a stub `.vm` file for every OS class called but not in the project, whose
+    /// functions stop the program in a loop named after them
+    #[arg(long, conflicts_with = "no_os")]
+    gen_stubs: bool,
+
+    /// Compile a library for other projects: no `Main.main` is expected, and the
+    /// signatures of its subroutines are written to `exports.json` next to the VM files
+    #[arg(long, conflicts_with = "gen_stubs")]
+    lib: bool,
+
+    /// Compiling of `*` and `/`: calls of the OS's Math, or of a BitMath class written
+    /// next to the program
+    #[arg(long, value_enum, default_value_t = Arithmetic::Os)]
+    arithmetic: Arithmetic,
+
+    /// Naming of the `if` and `while` labels
+    #[arg(long, value_enum, default_value_t = LabelStyle::Unique)]
+    labels: LabelStyle,
+
+    /// Pushing of `true`
+    #[arg(long, value_enum, default_value_t = Booleans::Neg)]
+    booleans: Booleans,
+
+    /// Setting of `this` on entry to a method
+    #[arg(long, value_enum, default_value_t = ThisHandling::Always)]
+    this: ThisHandling,
+
+    /// Experimental: share the slots of locals whose values are never needed at the
+    /// same time, so subroutines take fewer locals
+    #[arg(long)]
+    pack_locals: bool,
+
+    /// Extensions of the sources taken from a directory, matched case-insensitively
+    #[arg(
+        long = "source-ext",
+        value_name = "EXT",
+        value_delimiter = ',',
+        default_value = JACK_EXT,
+        global = true
+    )]
+    source_exts: Vec<String>,
+
+    /// Extension of the VM files written next to the sources
+    #[arg(long, value_name = "EXT", default_value = VM_EXT)]
+    output_ext: String,
+
+    /// Write TextMate and Tree-sitter grammar stubs for Jack into the directory
+    #[arg(long, value_name = "DIR", exclusive = true)]
+    emit_grammar: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Rename a class, subroutine, field or variable across a project
+    Rename {
+        /// Input .jack file or directory
+        input: PathBuf,
+
+        /// `Class`, `Class.subroutine`, `Class.field` or `Class.subroutine.variable`
+        symbol: String,
+
+        /// New name
+        new_name: String,
+
+        /// Only print the edits without touching any file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List every use of a class, subroutine, field or variable across a project
+    References {
+        /// Input .jack file or directory
+        input: PathBuf,
+
+        /// `Class`, `Class.subroutine`, `Class.field` or `Class.subroutine.variable`
+        symbol: String,
+    },
+    /// Run a program with the built-in interpreter and OS model, printing its output
+    Run {
+        /// Input .jack file or directory
+        input: PathBuf,
+
+        /// File with the keyboard input
+        #[arg(long, value_name = "FILE")]
+        keyboard: Option<PathBuf>,
+
+        /// Maximum number of executed statements
+        #[arg(long, default_value_t = InterpreterConfig::default().max_steps)]
+        max_steps: u64,
+
+        /// Report the heap blocks still allocated at exit, by allocation site, and invalid frees
+        #[arg(long)]
+        heap_report: bool,
+
+        /// Output the heap occupancy over time and the largest allocation sites, as .csv or .html
+        #[arg(long, value_name = "FILE")]
+        heap_usage: Option<PathBuf>,
+    },
+    /// Test the OS classes of a project against the built-in OS model
+    OsTest {
+        /// .jack file or directory with the OS classes to test
+        input: PathBuf,
+    },
+    /// Evaluate statements and expressions interactively
+    Repl {
+        /// .jack file or directory whose classes are in scope
+        input: Option<PathBuf>,
+    },
+    /// Print the VM code leaving the value of an expression on the stack, with the
+    /// variables of a subroutine in scope, for a debugger stopped in it
+    Snippet {
+        /// .jack file or directory of the project
+        input: PathBuf,
+
+        /// The subroutine, as `Class.subroutine`
+        #[arg(long = "in", value_name = "SUBROUTINE")]
+        subroutine: String,
+
+        /// Jack expression
+        expression: String,
+    },
+    /// Generate random but valid Jack programs for stress testing
+    Generate {
+        /// Output directory, one `<seed>/Main.jack` project per program
+        output: PathBuf,
+
+        /// Seed of the first program
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Number of programs, with consecutive seeds
+        #[arg(long, default_value_t = 1)]
+        count: u64,
+
+        /// Maximum nesting depth of expressions and statements
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
+    },
+    /// Show typical runs of the compiler and the tools after it
+    Examples {
+        /// Write them as the README's Markdown
+        #[arg(long)]
+        markdown: bool,
+    },
 }
 
 struct Tokens<'de> {
@@ -35,111 +252,647 @@ struct Tokens<'de> {
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let input_path = &cli.input;
+    profiling::start()?;
+    let result = execute(cli);
+    profiling::finish()?;
+
+    result
+}
+
+fn execute(cli: Cli) -> anyhow::Result<()> {
+    match cli.command {
+        Some(Command::Rename {
+            input,
+            symbol,
+            new_name,
+            dry_run,
+        }) => return rename(&input, &cli.source_exts, &symbol, &new_name, dry_run),
+        Some(Command::References { input, symbol }) => {
+            return references(&input, &cli.source_exts, &symbol);
+        }
+        Some(Command::Run {
+            input,
+            keyboard,
+            max_steps,
+            heap_report,
+            heap_usage,
+        }) => {
+            return run(
+                &input,
+                &cli.source_exts,
+                keyboard.as_deref(),
+                max_steps,
+                heap_report,
+                heap_usage.as_deref(),
+            );
+        }
+        Some(Command::OsTest { input }) => return os_test(&input, &cli.source_exts),
+        Some(Command::Repl { input }) => return repl(input.as_deref(), &cli.source_exts),
+        Some(Command::Snippet {
+            input,
+            subroutine,
+            expression,
+        }) => return snippet(&input, &cli.source_exts, &subroutine, &expression),
+        Some(Command::Generate {
+            output,
+            seed,
+            count,
+            depth,
+        }) => return generate(&output, seed, count, depth),
+        Some(Command::Examples { markdown }) => {
+            match markdown {
+                true => print!("{}", examples::markdown()),
+                false => print!("{}", examples::long_help()),
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+    if let Some(grammar_dir) = cli.emit_grammar {
+        return grammar::emit(&grammar_dir);
+    }
+
+    let input_path = &cli
+        .input
+        .expect("Required unless a subcommand or --emit-grammar is given");
     println!("[->] Input: {}", input_path.display());
+    let (project, mut sandbox) = project_input(input_path, &cli.source_exts)?;
+    let input_path = &project;
+    if let Some(sandbox) = sandbox.as_mut() {
+        println!("[~] Outputs are written next to the extracted sources");
+        sandbox.keep();
+    }
+
+    let mut session = Session::new(Options {
+        ignore_limits: cli.ignore_limits,
+        no_os: cli.no_os,
+        library: cli.lib,
+        codegen: Codegen {
+            booleans: cli.booleans,
+            label_style: cli.labels,
+            this: cli.this,
+            arithmetic: cli.arithmetic,
+            pack_locals: cli.pack_locals,
+            ..Default::default()
+        },
+        ..Options::from_env()
+    });
+    let output_ext = cli.output_ext.trim_start_matches('.');
+    let mut emitters: Vec<Box<dyn Emitter>> = vec![Box::new(VmWriter::new(output_ext))];
+    if cli.emit.contains(&Emit::Xml) {
+        #[cfg(feature = "xml")]
+        emitters.push(Box::new(emitter::XmlWriter));
+        #[cfg(not(feature = "xml"))]
+        anyhow::bail!("This build has no XML support: rebuild it with `--features xml`");
+    }
+    if let Some(metrics_path) = cli.metrics {
+        emitters.push(Box::new(MetricsWriter::new(metrics_path)));
+    }
+    if let Some(dump_path) = cli.dump {
+        emitters.push(Box::new(TokenDumpWriter::new(dump_path)));
+    }
+    if let Some(map_path) = cli.line_map {
+        emitters.push(Box::new(LineMapWriter::new(map_path, output_ext)));
+    }
+    if cli.lib {
+        let exports_path =
+            default_output(input_path, "", JACK_EXT).with_file_name(ExportsWriter::FILE_NAME);
+        emitters.push(Box::new(ExportsWriter::new(exports_path)));
+    }
+    if input_path.is_dir() {
+        emitters.push(Box::new(ManifestWriter::new(
+            input_path.clone(),
+            output_ext,
+        )));
+    }
 
+    let mut paths = vec![];
     if input_path.is_dir() {
         for entry in std::fs::read_dir(input_path)? {
             let path = entry?.path();
-            if path.is_file() {
-                if let Some(e) = path.extension().and_then(|s| s.to_str()) {
-                    if e.eq_ignore_ascii_case(JACK_EXT) {
-                        let source = read_to_string(&path)?;
-                        let output_path_t = default_output(&path, "T", "xml");
-                        let output_path = default_output(&path, "", "xml");
-                        let o = default_output(&path, "", "vm");
-
-                        let _ = handle_file(source, &path, &output_path_t, &output_path, &o)?;
-                    }
-                }
+            if path.is_file() && has_extension(&path, &cli.source_exts) {
+                paths.push(path);
             }
         }
-
-        return Ok(());
+        // Directories list in any order, depending on the file system
+        paths.sort();
     } else {
-        let source = read_to_string(&input_path)?;
-        let output_path_t = default_output(&cli.input, "T", "xml");
-        let output_path = default_output(&cli.input, "", "xml");
-        let o = default_output(&cli.input, "", "vm");
+        paths.push(input_path.clone());
+    }
+    // `BitMath.jack` is written next to the program, so the VM translator finds the
+    // source of `BitMath.vm`. The one left by an earlier compile is written again.
+    let bit_math_path = (session.options.codegen.arithmetic == Arithmetic::Bits).then(|| {
+        default_output(input_path, "", JACK_EXT)
+            .with_file_name(format!("{}.{JACK_EXT}", bit_math::CLASS_NAME))
+    });
+    if let Some(bit_math_path) = bit_math_path.as_ref()
+        && paths.contains(bit_math_path)
+    {
+        if read_to_string(bit_math_path)? != bit_math::SOURCE {
+            anyhow::bail!(
+                "{} is a class of the program, so --arithmetic bits cannot generate it",
+                bit_math::CLASS_NAME
+            );
+        }
+        paths.retain(|path| path != bit_math_path);
+    }
 
-        return handle_file(source, input_path, &output_path_t, &output_path, &o);
+    // Named after their files, as Jack requires
+    let project_classes: Vec<_> = paths
+        .iter()
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+
+    for path in paths.iter() {
+        let source = read_to_string(path)?;
+
+        handle_file(source, path, &project_classes, &mut session, &mut emitters)?;
     }
+    if let Some(bit_math_path) = bit_math_path.as_ref() {
+        println!("[<-] Generated: {}", bit_math_path.display());
+        std::fs::write(bit_math_path, bit_math::SOURCE)?;
+
+        handle_file(
+            bit_math::SOURCE.to_string(),
+            bit_math_path,
+            &project_classes,
+            &mut session,
+            &mut emitters,
+        )?;
+    }
+
+    if input_path.is_dir() {
+        session.check_entry_point();
+    }
+    session.check_limits();
+    session.flush();
+    if session.has_errors() {
+        anyhow::bail!("{} exceeds the Hack platform limits", input_path.display());
+    }
+
+    let missing = stubs::missing(&session.usage.call_graph, &project_classes);
+    if cli.gen_stubs {
+        for (class, subroutines) in missing.iter() {
+            let stub_path = default_output(input_path, "", JACK_EXT)
+                .with_file_name(format!("{class}.{output_ext}"));
+            if !stubs::may_write(&stub_path)? {
+                println!("[~] Keeping {}: not a stub", stub_path.display());
+                continue;
+            }
+
+            println!("[<-] Stub: {}", stub_path.display());
+            let instructions = stubs::stub(class, subroutines);
+            for emitter in emitters.iter_mut() {
+                emitter.emit_stub(&stub_path, class, &instructions)?;
+            }
+        }
+    } else if !missing.is_empty() && !session.options.no_os && !session.options.library {
+        let classes: Vec<_> = missing.keys().copied().collect();
+        println!(
+            "[~] Calls OS classes the project does not provide: {}. Translate it with an OS, \
+             or stub them with --gen-stubs",
+            classes.join(", ")
+        );
+    }
+
+    profiling::stage("emit", || {
+        for emitter in emitters.iter_mut() {
+            emitter.finish()?;
+        }
+
+        anyhow::Ok(())
+    })?;
+
+    Ok(())
 }
 
-fn handle_file<P>(
+fn handle_file(
     source: String,
-    input_file_path: P,
-    output_path_t: P,
-    output_path: P,
-    o: P,
-) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
-    println!(
-        "[->] Input file path: {}",
-        input_file_path.as_ref().display()
-    );
+    input_file_path: &Path,
+    project_classes: &[String],
+    session: &mut Session,
+    emitters: &mut [Box<dyn Emitter>],
+) -> anyhow::Result<()> {
+    println!("[->] Input file path: {}", input_file_path.display());
+    session.add_file(input_file_path);
 
     // 1. Scanning ..
-    let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).into_iter().collect();
+    let tokens: Result<Vec<_>, _> = profiling::stage("scan", || Tokenizer::new(&source).collect());
     let tokens = tokens?;
     let tokens = Tokens { tokens };
-
-    #[cfg(feature = "xml")]
-    {
-        use quick_xml::se::to_string;
-        use std::fs::File;
-
-        let xml = to_string(&tokens)?;
-        let mut f = File::create(output_path_t)?;
-        writeln!(&mut f, "{}\n", xml)?;
+    for emitter in emitters.iter_mut() {
+        emitter.emit_tokens(input_file_path, &tokens)?;
+    }
+    for token in tokens.tokens.iter() {
+        if let TokenType::Constant(Constant::String(string)) = &token.token_type {
+            session.check_string(token.line, token.column, string.len());
+        }
     }
 
     // 2. Parsing ..
-    let nodes: Result<Vec<_>, _> = Parser::new(tokens.tokens.into_iter()).collect();
+    let nodes: Result<Vec<_>, _> = profiling::stage("parse", || {
+        Parser::new(tokens.tokens.iter().cloned()).collect()
+    });
     let nodes = nodes?;
 
-    assert!(nodes.len() == 1);
-    #[cfg(feature = "xml")]
-    {
-        use quick_xml::se::Serializer;
-        use serde::Serialize;
-        use std::fs::File;
-
+    match nodes.len() {
+        1 => {}
+        0 => anyhow::bail!("{} holds no class", input_file_path.display()),
+        n => anyhow::bail!(
+            "{} holds {n} classes, but a .jack file holds one",
+            input_file_path.display()
+        ),
+    }
+    for emitter in emitters.iter_mut() {
         for node in nodes.iter() {
-            let mut output = String::new();
-            let mut ser = Serializer::new(&mut output);
-            ser.indent(' ', 4);
-
-            node.serialize(ser)?;
-
-            let mut f = File::create(&output_path)?;
-            writeln!(&mut f, "{}", output)?;
+            emitter.emit_xml(input_file_path, &tokens, node)?;
         }
     }
 
     // 3. Compiling ..
     let mut compiler = Compiler::new(nodes.iter());
-    let instructions = compiler.compile();
+    let instructions = profiling::stage("compile", || compiler.compile(session));
+    for class in nodes.iter() {
+        for problem in recursion::check(class) {
+            session.report(Severity::Warning, problem);
+        }
+    }
+    if session.options.no_os {
+        for class in nodes.iter() {
+            for problem in no_os::check(class, project_classes, session.options.codegen.arithmetic)
+            {
+                session.error(problem);
+            }
+        }
+    }
+    session.flush();
+    if session.has_errors() {
+        anyhow::bail!("Could not compile {}", input_file_path.display());
+    }
 
-    let mut output_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(o)?;
+    let metrics: Vec<_> = profiling::stage("metrics", || {
+        nodes
+            .iter()
+            .map(|class| metrics::class_metrics(class, &instructions))
+            .collect()
+    });
+    for class_metrics in metrics.iter() {
+        session.usage.add(class_metrics);
+    }
 
-    for (i, instruction) in instructions.iter().enumerate() {
-        if i + 1 != instructions.len() {
-            writeln!(&mut output_file, "{instruction}")?;
-        } else {
-            write!(&mut output_file, "{instruction}")?;
+    profiling::stage("emit", || {
+        for emitter in emitters.iter_mut() {
+            emitter.emit_vm(input_file_path, &nodes, &instructions)?;
+            emitter.emit_lines(input_file_path, compiler.lines())?;
+            for class_metrics in metrics.iter() {
+                emitter.emit_json(class_metrics)?;
+            }
         }
+
+        anyhow::Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn rename(
+    input: &Path,
+    source_exts: &[String],
+    symbol: &str,
+    new_name: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    println!("[->] Input: {}", input.display());
+
+    let (input, _sandbox) = project_input(input, source_exts)?;
+    let sources = read_project(&input, source_exts)?;
+    let index = build_index(&sources)?;
+    let plan = rename::plan(&index, symbol, new_name)?;
+
+    for span in plan.edits.iter() {
+        println!(
+            "[~] {}:{}:{}: {:?} {} -> {}",
+            index.file(span.file).display(),
+            span.line,
+            span.column,
+            plan.symbol.kind,
+            plan.symbol.name,
+            plan.new_name
+        );
+    }
+    for (from, to) in plan.file_renames.iter() {
+        if to.exists() {
+            anyhow::bail!(
+                "Could not rename {}: {} already exists",
+                from.display(),
+                to.display()
+            );
+        }
+        println!("[~] {} -> {}", from.display(), to.display());
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    for (file, (path, source)) in sources.iter().enumerate() {
+        let edits = plan.edits.iter().filter(|span| span.file == file);
+        if edits.clone().next().is_none() {
+            continue;
+        }
+
+        std::fs::write(path, rename::rewrite(source, edits, &plan.new_name))?;
+    }
+    for (from, to) in plan.file_renames.iter() {
+        std::fs::rename(from, to)?;
     }
 
     Ok(())
 }
 
+fn references(input: &Path, source_exts: &[String], symbol: &str) -> anyhow::Result<()> {
+    println!("[->] Input: {}", input.display());
+
+    let (project, _sandbox) = project_input(input, source_exts)?;
+    let sources = read_project(&project, source_exts)?;
+    let index = build_index(&sources)?;
+
+    let mut found = false;
+    for (key, occurrences) in index.lookup(symbol) {
+        found = true;
+
+        let mut uses: Vec<_> = occurrences.iter().filter(|o| !o.is_definition).collect();
+        uses.sort_by(|a, b| a.span.cmp(&b.span));
+        println!("[~] {:?} {}: {} use(s)", key.kind, key.name, uses.len());
+
+        for occurrence in uses {
+            let span = &occurrence.span;
+            let line = sources[span.file]
+                .1
+                .lines()
+                .nth(span.line - 1)
+                .unwrap_or_default();
+
+            println!(
+                "{}:{}:{}: {}",
+                index.file(span.file).display(),
+                span.line,
+                span.column,
+                line.trim()
+            );
+        }
+    }
+
+    if !found {
+        anyhow::bail!("Could not find `{symbol}` in {}", input.display());
+    }
+
+    Ok(())
+}
+
+fn run(
+    input: &Path,
+    source_exts: &[String],
+    keyboard: Option<&Path>,
+    max_steps: u64,
+    heap_report: bool,
+    heap_usage: Option<&Path>,
+) -> anyhow::Result<()> {
+    let (input, _sandbox) = project_input(input, source_exts)?;
+    let sources = read_project(&input, source_exts)?;
+    let keyboard = match keyboard {
+        Some(keyboard) => read_to_string(keyboard)?,
+        None => String::new(),
+    };
+
+    let mut classes = vec![];
+    for (_, source) in sources.iter() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+        classes.extend(nodes?);
+    }
+
+    let config = InterpreterConfig {
+        max_steps,
+        ..Default::default()
+    };
+    let mut interpreter = Interpreter::new(&classes, config)?.with_input(&keyboard);
+    let result = interpreter.run();
+
+    print!("{}", interpreter.output());
+    std::io::stdout().flush()?;
+
+    if heap_report {
+        let report = interpreter.heap_report();
+        if !interpreter.output().is_empty() && !interpreter.output().ends_with('\n') {
+            println!();
+        }
+
+        let (blocks, words) = report.leaks.iter().fold((0, 0), |(blocks, words), leak| {
+            (blocks + leak.blocks, words + leak.words)
+        });
+        println!("[~] Heap: {blocks} block(s) of {words} word(s) still allocated");
+        for leak in report.leaks.iter() {
+            println!(
+                "[~]   {}: {} block(s), {} word(s)",
+                leak.site, leak.blocks, leak.words
+            );
+        }
+        for invalid_free in report.invalid_frees.iter() {
+            println!("[~] Invalid free: {invalid_free}");
+        }
+    }
+    if let Some(path) = heap_usage {
+        heap_usage::write(path, &interpreter.heap_usage())?;
+    }
+
+    result
+}
+
+fn os_test(input: &Path, source_exts: &[String]) -> anyhow::Result<()> {
+    println!("[->] Input: {}", input.display());
+    let (input, _sandbox) = project_input(input, source_exts)?;
+    let sources: Vec<_> = read_project(&input, source_exts)?
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect();
+
+    let report = os_suite::run(&sources)?;
+    for class_name in report.not_covered.iter() {
+        println!("[~] {class_name}: not covered, the built-in OS model cannot check it");
+    }
+    for (class_name, cases) in report.tested.iter() {
+        let failures: Vec<_> = report
+            .failures
+            .iter()
+            .filter(|failure| failure.class_name == *class_name)
+            .collect();
+        println!(
+            "[~] {class_name}: {}/{cases} case(s) passed",
+            cases - failures.len()
+        );
+        for failure in failures {
+            println!("[~]   {}: {}", failure.case, failure.reason);
+        }
+    }
+
+    if !report.failures.is_empty() {
+        anyhow::bail!(
+            "{} of {} case(s) failed",
+            report.failures.len(),
+            report.failures.len() + report.passed
+        );
+    }
+    println!("[~] All {} case(s) passed", report.passed);
+
+    Ok(())
+}
+
+fn repl(input: Option<&Path>, source_exts: &[String]) -> anyhow::Result<()> {
+    let classes = match input {
+        Some(input) => {
+            println!("[->] Input: {}", input.display());
+
+            let (input, _sandbox) = project_input(input, source_exts)?;
+            read_project(&input, source_exts)?
+                .into_iter()
+                .map(|(_, source)| source)
+                .collect()
+        }
+        None => vec![],
+    };
+    println!("[~] Type :help for the commands");
+
+    Repl::new(classes).run(std::io::stdin().lock(), std::io::stdout())
+}
+
+fn snippet(
+    input: &Path,
+    source_exts: &[String],
+    subroutine: &str,
+    expression: &str,
+) -> anyhow::Result<()> {
+    let (input, _sandbox) = project_input(input, source_exts)?;
+    let sources = read_project(&input, source_exts)?;
+    let mut classes = vec![];
+    for (_, source) in sources.iter() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+        classes.extend(nodes?);
+    }
+
+    let environment = Environment::of_subroutine(&classes, subroutine)?;
+    // Stopped at the start of a method, THIS is still the caller's
+    if environment.is_method {
+        println!("push argument 0");
+        println!("pop pointer 0");
+    }
+    for instruction in snippet::compile_expression(expression, &environment)? {
+        println!("{instruction}");
+    }
+
+    Ok(())
+}
+
+fn generate(output: &Path, seed: u64, count: u64, depth: usize) -> anyhow::Result<()> {
+    let config = GeneratorConfig {
+        max_depth: depth,
+        ..Default::default()
+    };
+
+    for seed in seed..seed + count {
+        let source = Generator::new(seed, config.clone()).generate();
+
+        // Every generated program has to make it through the front end
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+        let classes = classes?;
+        if classes.len() != 1 {
+            anyhow::bail!("Generated program with seed {seed} could not be parsed");
+        }
+        let mut session = Session::default();
+        Compiler::new(classes.iter()).compile(&mut session);
+        if session.has_errors() {
+            anyhow::bail!("Generated program with seed {seed} could not be compiled");
+        }
+
+        // The interpreter is the oracle for the expected output
+        let mut interpreter = Interpreter::new(&classes, InterpreterConfig::default())?;
+        interpreter.run()?;
+
+        let project = output.join(seed.to_string());
+        std::fs::create_dir_all(&project)?;
+        let path = project.join("Main").with_extension(JACK_EXT);
+        std::fs::write(&path, &source)?;
+        std::fs::write(project.join("expected.txt"), interpreter.output())?;
+
+        println!("[<-] Output: {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// `input`, or the project directory extracted from it when it is a `.zip` file,
+/// along with the extracted files, removed once dropped.
+fn project_input(
+    input: &Path,
+    source_exts: &[String],
+) -> anyhow::Result<(PathBuf, Option<Sandbox>)> {
+    Ok(match Sandbox::open(input, source_exts)? {
+        Some(sandbox) => (sandbox.project.clone(), Some(sandbox)),
+        None => (input.to_path_buf(), None),
+    })
+}
+
+/// Reads every source of a project with one of `source_exts`, sorted by path.
+fn read_project(input: &Path, source_exts: &[String]) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let mut paths = vec![];
+    if input.is_dir() {
+        for entry in std::fs::read_dir(input)? {
+            let path = entry?.path();
+            let is_source = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|e| source_exts.iter().any(|ext| e.eq_ignore_ascii_case(ext)));
+            if path.is_file() && is_source {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+    } else {
+        paths.push(input.to_path_buf());
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = read_to_string(&path)?;
+
+            Ok((path, source))
+        })
+        .collect()
+}
+
+fn build_index(sources: &[(PathBuf, String)]) -> anyhow::Result<SymbolIndex> {
+    let mut index = SymbolIndex::default();
+
+    for (path, source) in sources.iter() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+
+        index.add_file(path, source, &classes?);
+    }
+
+    Ok(index)
+}
+
+/// `path` with `/` between its components whatever the platform, for the files
+/// that other tools and graders compare byte for byte.
+fn portable_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
 fn filename(input: &Path) -> OsString {
     input
         .file_stem()
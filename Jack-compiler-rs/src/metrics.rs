@@ -0,0 +1,195 @@
+use serde::Serialize;
+
+use crate::{
+    call_graph::{self, Call},
+    parser::{
+        Class, ClassVarDecKind, Expression, Statement, Statements, SubroutineCall, SubroutineDec,
+        Term,
+    },
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassMetrics {
+    pub class: String,
+    pub fields: usize,
+    pub statics: usize,
+    pub subroutines: usize,
+    pub vm_instructions: usize,
+    pub max_expression_depth: usize,
+    pub subroutine_metrics: Vec<SubroutineMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubroutineMetrics {
+    pub name: String,
+    pub kind: &'static str,
+    pub vm_instructions: usize,
+    pub max_expression_depth: usize,
+    pub cyclomatic_complexity: usize,
+    pub locals: usize,
+    /// Most words on the stack at once in the subroutine's own code.
+    pub max_operand_depth: usize,
+    /// Words of the stack a call of the subroutine takes, the calls it makes included;
+    /// known once the whole program is compiled, and `null` if it may recurse.
+    pub stack_words: Option<usize>,
+    #[serde(skip)]
+    pub calls: Vec<Call>,
+}
+
+/// Collects the metrics of a class from its AST and the VM code compiled from it.
+pub fn class_metrics(class: &Class<'_>, instructions: &[String]) -> ClassMetrics {
+    let count_vars = |kind: fn(&ClassVarDecKind) -> bool| {
+        class
+            .class_var_decs
+            .iter()
+            .filter(|class_var_dec| kind(&class_var_dec.class_var_dec_kind))
+            .map(|class_var_dec| class_var_dec.var_names.len())
+            .sum()
+    };
+
+    let subroutine_metrics: Vec<_> = class
+        .subroutine_decs
+        .iter()
+        .map(|subroutine_dec| subroutine_metrics(class, subroutine_dec, instructions))
+        .collect();
+
+    ClassMetrics {
+        class: class.class_name.0.to_string(),
+        fields: count_vars(|kind| matches!(kind, ClassVarDecKind::Field)),
+        statics: count_vars(|kind| matches!(kind, ClassVarDecKind::Static)),
+        subroutines: class.subroutine_decs.len(),
+        vm_instructions: instructions.len(),
+        max_expression_depth: subroutine_metrics
+            .iter()
+            .map(|metrics| metrics.max_expression_depth)
+            .max()
+            .unwrap_or(0),
+        subroutine_metrics,
+    }
+}
+
+fn subroutine_metrics(
+    class: &Class<'_>,
+    subroutine_dec: &SubroutineDec<'_>,
+    instructions: &[String],
+) -> SubroutineMetrics {
+    let name = subroutine_dec.subroutine_name.0;
+    let kind = subroutine_dec.subroutine_dec_type.keyword();
+
+    // The subroutine's code spans from its `function` command up to the next one
+    let header = format!("function {}.{} ", class.class_name.0, name);
+    let code: Vec<_> = instructions
+        .iter()
+        .skip_while(|instruction| !instruction.trim_start().starts_with(&header))
+        .enumerate()
+        .take_while(|(i, instruction)| {
+            *i == 0 || !instruction.trim_start().starts_with("function ")
+        })
+        .map(|(_, instruction)| instruction.clone())
+        .collect();
+    let operands = call_graph::operands(&code);
+
+    let statements = &subroutine_dec.subroutine_body.statements;
+
+    SubroutineMetrics {
+        name: name.to_string(),
+        kind,
+        vm_instructions: code.len(),
+        max_expression_depth: statements_expression_depth(statements),
+        cyclomatic_complexity: 1 + statements_decisions(statements),
+        locals: operands.locals,
+        max_operand_depth: operands.max,
+        stack_words: None,
+        calls: operands.calls,
+    }
+}
+
+/// Number of decision points (`if` and `while`).
+fn statements_decisions(statements: &Statements<'_>) -> usize {
+    statements
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::IfStatement(if_statement) => {
+                1 + statements_decisions(&if_statement.then_branch)
+                    + if_statement
+                        .else_branch
+                        .as_ref()
+                        .map_or(0, statements_decisions)
+            }
+            Statement::WhileStatement(while_statement) => {
+                1 + statements_decisions(&while_statement.body)
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+fn statements_expression_depth(statements: &Statements<'_>) -> usize {
+    statements
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::LetStatement(let_statement) => let_statement
+                .expression_1
+                .as_ref()
+                .map_or(0, expression_depth)
+                .max(expression_depth(&let_statement.expression_2)),
+            Statement::IfStatement(if_statement) => expression_depth(&if_statement.condition)
+                .max(statements_expression_depth(&if_statement.then_branch))
+                .max(
+                    if_statement
+                        .else_branch
+                        .as_ref()
+                        .map_or(0, statements_expression_depth),
+                ),
+            Statement::WhileStatement(while_statement) => {
+                expression_depth(&while_statement.condition)
+                    .max(statements_expression_depth(&while_statement.body))
+            }
+            Statement::DoStatement(do_statement) => {
+                subroutine_call_depth(&do_statement.subroutine_call)
+            }
+            Statement::ReturnStatement(return_statement) => return_statement
+                .expression
+                .as_ref()
+                .map_or(0, expression_depth),
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn expression_depth(expression: &Expression<'_>) -> usize {
+    let terms = std::iter::once(&expression.term).chain(expression.terms.iter().map(|(_, t)| t));
+
+    1 + terms.map(term_depth).max().unwrap_or(0)
+}
+
+fn term_depth(term: &Term<'_>) -> usize {
+    match term {
+        Term::Constant(_) | Term::KeywordConstant(_) | Term::VarName(_) => 0,
+        Term::VarNameExpression { expression, .. } | Term::Expression(expression) => {
+            expression_depth(expression)
+        }
+        Term::UnaryOpTerm { term, .. } => term_depth(term),
+        Term::SubroutineCall(subroutine_call) => subroutine_call_depth(subroutine_call),
+    }
+}
+
+fn subroutine_call_depth(subroutine_call: &SubroutineCall<'_>) -> usize {
+    let expression_list = match subroutine_call {
+        SubroutineCall::Call {
+            expression_list, ..
+        }
+        | SubroutineCall::ClassCall {
+            expression_list, ..
+        } => expression_list,
+    };
+
+    expression_list
+        .expressions
+        .iter()
+        .map(expression_depth)
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,330 @@
+//! `--no-os`: the constructs compiled to OS calls, rejected for programs meant for
+//! the bare VM translator and emulator, where no OS is linked.
+
+use std::collections::HashMap;
+
+use crate::parser::{
+    Class, Expression, KeywordConstant, Op, Statement, Statements, SubroutineCall,
+    SubroutineDecType, Term, Type, UnaryOp,
+};
+use crate::session::Arithmetic;
+use crate::tokenizer::Constant;
+
+pub const OS_CLASSES: [&str; 8] = [
+    "Math", "String", "Array", "Output", "Screen", "Keyboard", "Memory", "Sys",
+];
+
+/// Every construct of `class` that needs an OS class, one message each. OS classes
+/// among `project_classes` are the program's own and may be used, as are `*` and `/`
+/// with [`Arithmetic::Bits`].
+pub fn check(class: &Class<'_>, project_classes: &[String], arithmetic: Arithmetic) -> Vec<String> {
+    let os_classes: Vec<_> = OS_CLASSES
+        .into_iter()
+        .filter(|os_class| !project_classes.iter().any(|name| name == os_class))
+        .collect();
+    let mut checker = Checker {
+        os_classes,
+        arithmetic,
+        location: String::new(),
+        types: HashMap::new(),
+        problems: vec![],
+    };
+
+    let class_name = class.class_name.0;
+    let mut class_types = HashMap::new();
+    for class_var_dec in class.class_var_decs.iter() {
+        for var_name in class_var_dec.var_names.iter() {
+            class_types.insert(var_name.0, class_type(&class_var_dec.class_var_dec_type));
+        }
+    }
+
+    for subroutine_dec in class.subroutine_decs.iter() {
+        let subroutine_name = subroutine_dec.subroutine_name.0;
+        checker.location = format!("{class_name}.{subroutine_name}");
+        checker.types = class_types.clone();
+        for (r#type, identifier) in subroutine_dec.parameter_list.parameters.iter() {
+            checker.types.insert(identifier.0, class_type(r#type));
+        }
+        for var_dec in subroutine_dec.subroutine_body.var_decs.iter() {
+            for var_name in var_dec.var_names.iter() {
+                checker
+                    .types
+                    .insert(var_name.0, class_type(&var_dec.var_type));
+            }
+        }
+
+        if matches!(
+            subroutine_dec.subroutine_dec_type,
+            SubroutineDecType::Constructor
+        ) {
+            checker.report(
+                "Memory",
+                "is a constructor, which allocates the object with Memory.alloc".to_string(),
+            );
+        }
+        checker.statements(&subroutine_dec.subroutine_body.statements);
+    }
+
+    checker.problems
+}
+
+struct Checker<'de> {
+    /// OS classes the program does not provide itself.
+    os_classes: Vec<&'static str>,
+    arithmetic: Arithmetic,
+    /// `Class.subroutine` being checked.
+    location: String,
+    /// Class types of the variables in scope; `None` for `int`, `char` and `boolean`.
+    types: HashMap<&'de str, Option<&'de str>>,
+    problems: Vec<String>,
+}
+
+impl<'de> Checker<'de> {
+    fn report(&mut self, os_class: &str, problem: String) {
+        if self.os_classes.contains(&os_class) {
+            self.problems.push(format!("{}: {problem}", self.location));
+        }
+    }
+
+    fn statements(&mut self, statements: &Statements<'de>) {
+        for statement in statements.statements.iter() {
+            match statement {
+                Statement::LetStatement(let_statement) => {
+                    if let Some(index) = let_statement.expression_1.as_ref() {
+                        self.expression(index);
+                    }
+                    self.expression(&let_statement.expression_2);
+                }
+                Statement::IfStatement(if_statement) => {
+                    self.expression(&if_statement.condition);
+                    self.statements(&if_statement.then_branch);
+                    if let Some(else_branch) = if_statement.else_branch.as_ref() {
+                        self.statements(else_branch);
+                    }
+                }
+                Statement::WhileStatement(while_statement) => {
+                    self.expression(&while_statement.condition);
+                    self.statements(&while_statement.body);
+                }
+                Statement::DoStatement(do_statement) => {
+                    self.subroutine_call(&do_statement.subroutine_call)
+                }
+                Statement::ReturnStatement(return_statement) => {
+                    if let Some(expression) = return_statement.expression.as_ref() {
+                        self.expression(expression);
+                    }
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression<'de>) {
+        self.term(&expression.term);
+        for (op, term) in expression.terms.iter() {
+            match op {
+                Op::Asterisk | Op::Slash if self.arithmetic == Arithmetic::Bits => {}
+                Op::Asterisk => self.report(
+                    "Math",
+                    format!("`{}` calls Math.multiply", render(expression)),
+                ),
+                Op::Slash => self.report(
+                    "Math",
+                    format!("`{}` calls Math.divide", render(expression)),
+                ),
+                _ => {}
+            }
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &Term<'de>) {
+        match term {
+            Term::Constant(Constant::String(_)) => self.report(
+                "String",
+                format!(
+                    "`{}` builds a String with String.new and String.appendChar",
+                    render_term(term)
+                ),
+            ),
+            Term::VarNameExpression { expression, .. } | Term::Expression(expression) => {
+                self.expression(expression)
+            }
+            Term::UnaryOpTerm { term, .. } => self.term(term),
+            Term::SubroutineCall(subroutine_call) => self.subroutine_call(subroutine_call),
+            Term::Constant(Constant::Integer(_)) | Term::KeywordConstant(_) | Term::VarName(_) => {}
+        }
+    }
+
+    fn subroutine_call(&mut self, subroutine_call: &SubroutineCall<'de>) {
+        let expression_list = match subroutine_call {
+            SubroutineCall::Call {
+                expression_list, ..
+            } => expression_list,
+            SubroutineCall::ClassCall {
+                class_or_var_name,
+                subroutine_name,
+                expression_list,
+            } => {
+                let name = class_or_var_name.0;
+                match self.types.get(name) {
+                    Some(Some(class_name)) => self.report(
+                        class_name,
+                        format!(
+                            "`{name}.{}` calls the OS method {class_name}.{}",
+                            subroutine_name.0, subroutine_name.0
+                        ),
+                    ),
+                    Some(None) => {}
+                    None => self.report(
+                        name,
+                        format!("`{name}.{}` is an OS subroutine", subroutine_name.0),
+                    ),
+                }
+                expression_list
+            }
+        };
+
+        for expression in expression_list.expressions.iter() {
+            self.expression(expression);
+        }
+    }
+}
+
+fn class_type<'de>(r#type: &Type<'de>) -> Option<&'de str> {
+    match r#type {
+        Type::Class { name } => Some(name.0),
+        Type::Int | Type::Char | Type::Boolean => None,
+    }
+}
+
+/// Jack source of `expression`, for messages.
+fn render(expression: &Expression<'_>) -> String {
+    let mut text = render_term(&expression.term);
+    for (op, term) in expression.terms.iter() {
+        let op = match op {
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Asterisk => "*",
+            Op::Slash => "/",
+            Op::Ampersand => "&",
+            Op::Pipe => "|",
+            Op::LessThan => "<",
+            Op::GreaterThan => ">",
+            Op::Equal => "=",
+        };
+        text = format!("{text} {op} {}", render_term(term));
+    }
+
+    text
+}
+
+fn render_term(term: &Term<'_>) -> String {
+    match term {
+        Term::Constant(Constant::Integer(i)) => i.to_string(),
+        Term::Constant(Constant::String(s)) => format!("\"{s}\""),
+        Term::KeywordConstant(keyword_constant) => match keyword_constant {
+            KeywordConstant::True => "true",
+            KeywordConstant::False => "false",
+            KeywordConstant::Null => "null",
+            KeywordConstant::This => "this",
+        }
+        .to_string(),
+        Term::VarName(identifier) => identifier.0.to_string(),
+        Term::VarNameExpression {
+            var_name,
+            expression,
+        } => format!("{}[{}]", var_name.0, render(expression)),
+        Term::Expression(expression) => format!("({})", render(expression)),
+        Term::UnaryOpTerm { unary_op, term } => match unary_op {
+            UnaryOp::Minus => format!("-{}", render_term(term)),
+            UnaryOp::Tilde => format!("~{}", render_term(term)),
+        },
+        Term::SubroutineCall(subroutine_call) => {
+            let (name, expression_list) = match subroutine_call {
+                SubroutineCall::Call {
+                    subroutine_name,
+                    expression_list,
+                } => (subroutine_name.0.to_string(), expression_list),
+                SubroutineCall::ClassCall {
+                    class_or_var_name,
+                    subroutine_name,
+                    expression_list,
+                } => (
+                    format!("{}.{}", class_or_var_name.0, subroutine_name.0),
+                    expression_list,
+                ),
+            };
+            let arguments: Vec<_> = expression_list.expressions.iter().map(render).collect();
+
+            format!("{name}({})", arguments.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod no_os_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn check_source(source: &str, project_classes: &[&str], arithmetic: Arithmetic) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let project_classes: Vec<_> = project_classes.iter().map(|s| s.to_string()).collect();
+
+        check(&classes.unwrap()[0], &project_classes, arithmetic)
+    }
+
+    #[test]
+    fn reports_every_construct_needing_the_os() {
+        let source = r#"class Point {
+                field int x;
+                field Array cache;
+                constructor Point new(int ax) { let x = ax; return this; }
+                method int scaled(int k) {
+                    var String name;
+                    let cache[k] = x + k;
+                    let name = "p";
+                    do Output.printInt(-(x * k));
+                    if (k > 0) { return (x / Math.abs(k)); }
+                    return name.length();
+                }
+            }"#;
+
+        assert_eq!(
+            check_source(source, &["Point"], Arithmetic::Os),
+            [
+                "Point.new: is a constructor, which allocates the object with Memory.alloc",
+                r#"Point.scaled: `"p"` builds a String with String.new and String.appendChar"#,
+                "Point.scaled: `Output.printInt` is an OS subroutine",
+                "Point.scaled: `x * k` calls Math.multiply",
+                "Point.scaled: `x / Math.abs(k)` calls Math.divide",
+                "Point.scaled: `Math.abs` is an OS subroutine",
+                "Point.scaled: `name.length` calls the OS method String.length",
+            ]
+        );
+
+        // A program providing its own Math and Memory needs no OS for them
+        assert_eq!(
+            check_source(source, &["Point", "Math", "Memory"], Arithmetic::Os).len(),
+            3
+        );
+        // Nor for `*` and `/` lowered to BitMath
+        assert_eq!(check_source(source, &["Point"], Arithmetic::Bits).len(), 5);
+    }
+
+    #[test]
+    fn plain_arithmetic_and_arrays_need_no_os() {
+        let source = "class Main {
+                static Array ram;
+                function int sum(int n) {
+                    var int i, total;
+                    while (i < n) { let total = total + ram[i]; let i = i + 1; }
+                    return Main.twice(total);
+                }
+                function int twice(int x) { return x + x; }
+            }";
+
+        assert!(check_source(source, &["Main"], Arithmetic::Os).is_empty());
+    }
+}
@@ -0,0 +1,482 @@
+//! Conformance tests for OS classes written by the user, in place of the project 12
+//! test programs. Each case is a `Main.main` exercising one OS class; it is run with
+//! the user's class and with the built-in OS model, which is the specification: both
+//! runs must print the same, draw the same screen and fail with the same `Sys.error`.
+//!
+//! Only the class under test is the user's, every other OS class is built in.
+
+use crate::interpreter::{Interpreter, InterpreterConfig};
+use crate::parser::{Class, Parser};
+use crate::tokenizer::Tokenizer;
+
+/// OS classes with test cases.
+pub const COVERED: [&str; 5] = ["Math", "String", "Array", "Memory", "Screen"];
+/// OS classes the built-in model cannot check: it prints text instead of drawing
+/// characters, and reads a queue instead of the keyboard.
+pub const NOT_COVERED: [&str; 3] = ["Output", "Keyboard", "Sys"];
+
+/// Statements the user's classes may run; enough for the cases, stops infinite loops.
+const MAX_STEPS: u64 = 2_000_000;
+
+struct Case {
+    class_name: &'static str,
+    name: &'static str,
+    /// Statements of `Main.main`, with `int i, x`, `String s` and `Array a, b` declared.
+    /// `Main.show(int)` and `Main.text(String)` print their argument and a space.
+    body: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        class_name: "Math",
+        name: "multiply",
+        body: "do Main.show(Math.multiply(7, -6)); do Main.show(Math.multiply(-181, 181));
+               do Main.show(Math.multiply(0, -5)); do Main.show(Math.multiply(-1, -32767));",
+    },
+    Case {
+        class_name: "Math",
+        name: "multiply wraps around on overflow",
+        body: "do Main.show(Math.multiply(300, 300)); do Main.show(Math.multiply(-256, 255));",
+    },
+    Case {
+        class_name: "Math",
+        name: "divide rounds towards zero",
+        body: "do Main.show(Math.divide(42, 5)); do Main.show(Math.divide(-42, 5));
+               do Main.show(Math.divide(42, -5)); do Main.show(Math.divide(32767, 1));
+               do Main.show(Math.divide(0, 7)); do Main.show(Math.divide(5, 42));",
+    },
+    Case {
+        class_name: "Math",
+        name: "divide by zero",
+        body: "do Math.divide(1, 0);",
+    },
+    Case {
+        class_name: "Math",
+        name: "sqrt rounds down",
+        body: "do Main.show(Math.sqrt(0)); do Main.show(Math.sqrt(1)); do Main.show(Math.sqrt(2));
+               do Main.show(Math.sqrt(15)); do Main.show(Math.sqrt(16)); do Main.show(Math.sqrt(17));
+               do Main.show(Math.sqrt(32767));",
+    },
+    Case {
+        class_name: "Math",
+        name: "sqrt of a negative number",
+        body: "do Math.sqrt(-1);",
+    },
+    Case {
+        class_name: "Math",
+        name: "min, max and abs",
+        body: "do Main.show(Math.min(3, -4)); do Main.show(Math.max(3, -4));
+               do Main.show(Math.abs(-7)); do Main.show(Math.abs(32767));",
+    },
+    Case {
+        class_name: "String",
+        name: "appendChar, length and charAt",
+        body: "let s = String.new(3); do s.appendChar(74); do s.appendChar(97);
+               do Main.show(s.length()); do Main.show(s.charAt(1)); do Main.text(s);",
+    },
+    Case {
+        class_name: "String",
+        name: "string constants",
+        body: r#"do Main.text("Hello, world"); do Main.text("a");"#,
+    },
+    Case {
+        class_name: "String",
+        name: "setCharAt and eraseLastChar",
+        body: r#"let s = "abc"; do s.setCharAt(0, 65); do s.eraseLastChar();
+               do Main.text(s); do Main.show(s.length());"#,
+    },
+    Case {
+        class_name: "String",
+        name: "intValue",
+        body: r#"let s = "123"; do Main.show(s.intValue()); let s = "-45"; do Main.show(s.intValue());
+               let s = "12a3"; do Main.show(s.intValue()); let s = "0"; do Main.show(s.intValue());"#,
+    },
+    Case {
+        class_name: "String",
+        name: "setInt",
+        body: "let s = String.new(6); do s.setInt(-1234); do Main.text(s);
+               do s.setInt(0); do Main.text(s); do s.setInt(32767); do Main.text(s);",
+    },
+    Case {
+        class_name: "String",
+        name: "empty strings and dispose",
+        body: "let s = String.new(0); do Main.show(s.length()); do s.dispose();
+               let s = String.new(4); do Main.show(s.length());",
+    },
+    Case {
+        class_name: "String",
+        name: "character constants",
+        body: "do Main.show(String.newLine()); do Main.show(String.backSpace());
+               do Main.show(String.doubleQuote());",
+    },
+    Case {
+        class_name: "String",
+        name: "appendChar to a full string",
+        body: "let s = String.new(1); do s.appendChar(65); do s.appendChar(66);",
+    },
+    Case {
+        class_name: "String",
+        name: "charAt out of bounds",
+        body: r#"let s = "ab"; do s.charAt(2);"#,
+    },
+    Case {
+        class_name: "String",
+        name: "negative maximum length",
+        body: "let s = String.new(-1);",
+    },
+    Case {
+        class_name: "Array",
+        name: "new and dispose",
+        body: "let a = Array.new(3); let a[0] = 5; let a[2] = 7; do Main.show(a[0] + a[2]);
+               do a.dispose(); let a = Array.new(1000); let a[999] = 1; do Main.show(a[999]);",
+    },
+    Case {
+        class_name: "Array",
+        name: "non-positive size",
+        body: "let a = Array.new(0);",
+    },
+    Case {
+        class_name: "Memory",
+        name: "peek and poke",
+        body: "do Memory.poke(300, 1234); do Main.show(Memory.peek(300));
+               do Memory.poke(300, -1); do Main.show(Memory.peek(300));",
+    },
+    Case {
+        class_name: "Memory",
+        name: "blocks lie apart in the heap",
+        body: "let a = Memory.alloc(10); let b = Memory.alloc(20);
+               do Main.show(~(a < 2048) & ((a + 10) < 16385));
+               do Main.show(~(b < 2048) & ((b + 20) < 16385));
+               do Main.show(((a + 10) < (b + 1)) | ((b + 20) < (a + 1)));",
+    },
+    Case {
+        class_name: "Memory",
+        name: "blocks keep their contents",
+        body: "let a = Memory.alloc(10); let b = Memory.alloc(10);
+               while (i < 10) { let a[i] = i; let b[i] = -i; let i = i + 1; }
+               let i = 0;
+               while (i < 10) { let x = x + (a[i] - b[i]); let i = i + 1; }
+               do Main.show(x);",
+    },
+    Case {
+        class_name: "Memory",
+        name: "freed blocks are reused",
+        body: "while (i < 100) { let a = Memory.alloc(1000); do Memory.deAlloc(a); let i = i + 1; }
+               do Main.show(i);",
+    },
+    Case {
+        class_name: "Memory",
+        name: "freed neighbours are merged",
+        body: "let b = Memory.alloc(13);
+               while (i < 13) { let b[i] = Memory.alloc(1000); let i = i + 1; }
+               let i = 0;
+               while (i < 13) { do Memory.deAlloc(b[i]); let i = i + 1; }
+               let a = Memory.alloc(13000); do Main.show(~(a = 0));",
+    },
+    Case {
+        class_name: "Memory",
+        name: "non-positive size",
+        body: "let a = Memory.alloc(0);",
+    },
+    Case {
+        class_name: "Memory",
+        name: "heap overflow",
+        body: "let a = Memory.alloc(20000);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "drawPixel and setColor",
+        body: "do Screen.drawPixel(0, 0); do Screen.drawPixel(511, 255); do Screen.drawPixel(17, 3);
+               do Screen.drawPixel(18, 3); do Screen.setColor(false); do Screen.drawPixel(17, 3);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "straight lines",
+        body: "do Screen.drawLine(3, 10, 200, 10); do Screen.drawLine(200, 12, 3, 12);
+               do Screen.drawLine(40, 0, 40, 255); do Screen.drawLine(41, 255, 41, 0);
+               do Screen.drawLine(5, 5, 5, 5);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "diagonal lines",
+        body: "do Screen.drawLine(0, 0, 100, 100); do Screen.drawLine(100, 0, 0, 100);
+               do Screen.drawLine(300, 200, 250, 150); do Screen.drawLine(250, 50, 300, 0);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "drawRectangle",
+        body: "do Screen.drawRectangle(10, 20, 100, 30); do Screen.drawRectangle(0, 0, 511, 0);
+               do Screen.setColor(false); do Screen.drawRectangle(12, 22, 98, 28);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "drawCircle",
+        body: "do Screen.drawCircle(255, 127, 100); do Screen.drawCircle(20, 20, 0);
+               do Screen.setColor(false); do Screen.drawCircle(255, 127, 50);",
+    },
+    Case {
+        class_name: "Screen",
+        name: "clearScreen",
+        body: "do Screen.drawRectangle(0, 0, 511, 63); do Screen.clearScreen();",
+    },
+    Case {
+        class_name: "Screen",
+        name: "illegal coordinates",
+        body: "do Screen.drawPixel(512, 0);",
+    },
+];
+
+/// A case the user's class does not pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub class_name: &'static str,
+    pub case: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    /// Covered classes among the sources and their number of cases, in the order
+    /// of [`COVERED`].
+    pub tested: Vec<(&'static str, usize)>,
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+    /// OS classes among the sources without test cases.
+    pub not_covered: Vec<&'static str>,
+}
+
+/// What a run printed, drew and failed with.
+struct Outcome {
+    output: String,
+    screen: Vec<i16>,
+    error: Option<String>,
+}
+
+/// Runs the cases of every OS class declared in `sources`, other classes are ignored.
+pub fn run(sources: &[String]) -> anyhow::Result<Report> {
+    let mut user_classes = vec![];
+    for source in sources.iter() {
+        for class in parse(source)? {
+            user_classes.push((class.class_name.0, source.as_str()));
+        }
+    }
+    let user_source = |class_name: &str| {
+        user_classes
+            .iter()
+            .find(|(name, _)| *name == class_name)
+            .map(|(_, source)| *source)
+    };
+
+    let mut report = Report {
+        not_covered: NOT_COVERED
+            .into_iter()
+            .filter(|class_name| user_source(class_name).is_some())
+            .collect(),
+        ..Default::default()
+    };
+    for class_name in COVERED {
+        let Some(user_source) = user_source(class_name) else {
+            continue;
+        };
+        let cases: Vec<_> = CASES
+            .iter()
+            .filter(|case| case.class_name == class_name)
+            .collect();
+        report.tested.push((class_name, cases.len()));
+
+        for case in cases {
+            match check(case, user_source)? {
+                None => report.passed += 1,
+                Some(reason) => report.failures.push(Failure {
+                    class_name,
+                    case: case.name,
+                    reason,
+                }),
+            }
+        }
+    }
+
+    if report.tested.is_empty() {
+        anyhow::bail!(
+            "Found none of the OS classes with tests: {}",
+            COVERED.join(", ")
+        );
+    }
+
+    Ok(report)
+}
+
+/// Why the user's class fails `case`, if it does.
+fn check(case: &Case, user_source: &str) -> anyhow::Result<Option<String>> {
+    let main = format!(
+        "class Main {{
+            function void main() {{
+                var int i, x;
+                var String s;
+                var Array a, b;
+                {}
+                return;
+            }}
+            function void show(int x) {{
+                do Output.printInt(x);
+                do Output.printChar(32);
+                return;
+            }}
+            function void text(String s) {{
+                var int i;
+                while (i < s.length()) {{
+                    do Output.printChar(s.charAt(i));
+                    let i = i + 1;
+                }}
+                do Output.printChar(32);
+                return;
+            }}
+        }}",
+        case.body
+    );
+
+    let expected = execute(&parse(&main)?, None);
+    if let Some(error) = expected
+        .error
+        .as_ref()
+        .filter(|e| !e.starts_with("Sys.error"))
+    {
+        anyhow::bail!("Case `{}` fails with the built-in OS: {error}", case.name);
+    }
+
+    let mut classes = parse(&main)?;
+    classes.extend(parse(user_source)?);
+    let actual = execute(&classes, Some(case.class_name));
+
+    let reason = match (expected.error, actual.error) {
+        (None, Some(error)) => format!("failed with {error}"),
+        (Some(expected), None) => format!("expected {expected}"),
+        (Some(expected), Some(error)) if expected != error => {
+            format!("expected {expected}, got {error}")
+        }
+        _ if expected.output != actual.output => format!(
+            "printed `{}`, expected `{}`",
+            actual.output.trim_end(),
+            expected.output.trim_end()
+        ),
+        _ => {
+            let mut differences = expected
+                .screen
+                .iter()
+                .zip(actual.screen.iter())
+                .enumerate()
+                .filter(|(_, (expected, actual))| expected != actual);
+            let Some((first, _)) = differences.next() else {
+                return Ok(None);
+            };
+            format!(
+                "{} screen word(s) differ, the first at row {}, column {}",
+                differences.count() + 1,
+                first / 32,
+                first % 32 * 16
+            )
+        }
+    };
+
+    Ok(Some(reason))
+}
+
+/// Runs `Main.main`, after `<init_class>.init` when the class declares it.
+fn execute(classes: &[Class<'_>], init_class: Option<&str>) -> Outcome {
+    let config = InterpreterConfig {
+        max_steps: MAX_STEPS,
+        ..Default::default()
+    };
+    let mut interpreter = match Interpreter::new(classes, config) {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            return Outcome {
+                output: String::new(),
+                screen: vec![],
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let has_init = |class_name: &str| {
+        classes.iter().any(|class| {
+            class.class_name.0 == class_name
+                && class
+                    .subroutine_decs
+                    .iter()
+                    .any(|subroutine_dec| subroutine_dec.subroutine_name.0 == "init")
+        })
+    };
+    let result = match init_class.filter(|class_name| has_init(class_name)) {
+        Some(class_name) => interpreter
+            .call_function(class_name, "init")
+            .and_then(|_| interpreter.run()),
+        None => interpreter.run(),
+    };
+
+    Outcome {
+        output: interpreter.output().to_string(),
+        screen: interpreter.screen().to_vec(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+fn parse(source: &str) -> anyhow::Result<Vec<Class<'_>>> {
+    let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+    let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+
+    classes
+}
+
+#[cfg(test)]
+mod os_suite_tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_cases_a_user_class_fails() {
+        let math = "class Math {
+                function void init() { return; }
+                function int multiply(int x, int y) { return x * y; }
+                function int divide(int x, int y) { return x / y; }
+                function int sqrt(int x) { return x / 2; }
+                function int min(int x, int y) { if (x < y) { return x; } return y; }
+                function int max(int x, int y) { if (x > y) { return x; } return y; }
+                function int abs(int x) { if (x < 0) { return -x; } return x; }
+            }";
+        let output = "class Output { function void printInt(int i) { return; } }";
+
+        let report = run(&[math.to_string(), output.to_string()]).unwrap();
+        assert_eq!(report.tested, [("Math", 7)]);
+        assert_eq!(report.not_covered, ["Output"]);
+        assert_eq!(report.passed, 5);
+        assert_eq!(
+            report.failures,
+            [
+                Failure {
+                    class_name: "Math",
+                    case: "sqrt rounds down",
+                    reason: "printed `0 0 1 7 8 8 16383`, expected `0 1 1 3 4 4 181`".to_string(),
+                },
+                Failure {
+                    class_name: "Math",
+                    case: "sqrt of a negative number",
+                    reason:
+                        "expected Sys.error(4): Cannot compute square root of a negative number"
+                            .to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn every_case_passes_with_the_built_in_os() {
+        for case in CASES.iter() {
+            assert_eq!(
+                check(case, "class Unused { }").unwrap(),
+                None,
+                "{}",
+                case.name
+            );
+        }
+    }
+}
@@ -1,45 +1,33 @@
-use itertools::{Itertools, MultiPeek};
 use std::convert::TryFrom;
 
+use crate::token_cursor::TokenCursor;
 use crate::tokenizer::{Constant, Identifier, Keyword, Symbol, Token, TokenType};
 
 macro_rules! consume {
     ($tokens:expr) => {
-        $tokens.next().ok_or(anyhow::anyhow!(
-            "Could not consume a token. Token list is empty"
-        ))
+        $tokens
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Could not consume a token. Token list is empty"))
     };
 }
 
 macro_rules! peek {
     ($tokens:expr) => {
-        if let Some(res) = $tokens.peek().clone() {
-            Ok(res)
-        } else {
-            Err(anyhow::anyhow!(
-                "Could not peek a token. Token list is empty"
-            ))
-        }
+        $tokens
+            .peek()
+            .ok_or_else(|| anyhow::anyhow!("Could not peek a token. Token list is empty"))
     };
 }
 
 macro_rules! peek_matches {
     ($tokens:expr, $( $pattern:pat ),* $(,)?) => {
-        if let Some(value) = $tokens.peek() {
-            let res = match value {
-                $(Token {
-                    token_type: $pattern,
-                    ..
-                } => true, )*
-                _ => false,
-            };
-
-            $tokens.reset_peek();
-            res
-        } else {
-            $tokens.reset_peek();
-            false
-        }
+        matches!(
+            $tokens.peek(),
+            $(Some(Token {
+                token_type: $pattern,
+                ..
+            }))|*
+        )
     };
 }
 
@@ -50,12 +38,20 @@ macro_rules! consume_and_ensure_matches {
                 token_type: $pattern,
                 ..
             }) => anyhow::Result::<Token>::Ok(token), )*
-            token => {
+            Some(token) => {
                 let expected_patterns = vec![$(stringify!($pattern)),*];
                 Err(anyhow::anyhow!(
-                    "Unexpected token. Expected one of: {} but got {:?}",
+                    "[line {}] Error: Unexpected token. Expected one of: {} but got `{}`",
+                    token.line,
                     expected_patterns.join(", "),
-                    token
+                    token.lexeme
+                ))
+            },
+            None => {
+                let expected_patterns = vec![$(stringify!($pattern)),*];
+                Err(anyhow::anyhow!(
+                    "Error: Unexpected end of input. Expected one of: {}",
+                    expected_patterns.join(", ")
                 ))
             },
         }
@@ -82,6 +78,8 @@ pub struct SubroutineDec<'de> {
     pub(super) subroutine_name: Identifier<'de>,
     pub(super) parameter_list: ParameterList<'de>,
     pub(super) subroutine_body: SubroutineBody<'de>,
+    /// The line of its `constructor`, `function` or `method`.
+    pub(super) line: usize,
 }
 
 #[derive(Debug)]
@@ -91,6 +89,16 @@ pub enum SubroutineDecType {
     Method,
 }
 
+impl SubroutineDecType {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SubroutineDecType::Constructor => "constructor",
+            SubroutineDecType::Function => "function",
+            SubroutineDecType::Method => "method",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SubroutineDecReturn<'de> {
     Void,
@@ -118,6 +126,18 @@ pub enum Type<'de> {
     Class { name: Identifier<'de> },
 }
 
+impl<'de> Type<'de> {
+    /// `int`, `char`, `boolean` or the class name, as written in a declaration.
+    pub fn name(&self) -> &'de str {
+        match self {
+            Type::Int => "int",
+            Type::Char => "char",
+            Type::Boolean => "boolean",
+            Type::Class { name } => name.0,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParameterList<'de> {
     pub(super) parameters: Vec<(Type<'de>, Identifier<'de>)>,
@@ -132,6 +152,8 @@ pub struct SubroutineBody<'de> {
 #[derive(Debug)]
 pub struct Statements<'de> {
     pub(super) statements: Vec<Statement<'de>>,
+    /// The line each statement starts on.
+    pub(super) lines: Vec<usize>,
 }
 
 #[derive(Debug)]
@@ -242,7 +264,9 @@ pub enum KeywordConstant {
 }
 
 pub struct Parser<'de, I: Iterator<Item = Token<'de>>> {
-    tokens: MultiPeek<I>,
+    tokens: TokenCursor<'de, I>,
+    /// Name of the last class parsed, for errors about what follows it.
+    last_class: Option<&'de str>,
 }
 
 impl<'de, I> Parser<'de, I>
@@ -251,44 +275,80 @@ where
 {
     pub fn new(tokens: I) -> Parser<'de, I> {
         Parser {
-            tokens: tokens.multipeek(),
+            tokens: TokenCursor::new(tokens),
+            last_class: None,
         }
     }
 
     pub fn parse(&mut self) -> Option<anyhow::Result<Class<'de>>> {
-        while let Some(token) = self.tokens.peek() {
-            if matches!(token.token_type, TokenType::Eof) {
-                return None;
-            }
+        let token = self.tokens.peek()?;
+        if matches!(token.token_type, TokenType::Eof) {
+            return None;
+        }
 
-            if let Ok(class) = self.parse_class() {
-                return Some(Ok(class));
+        let class = match self.last_class {
+            Some(last_class) if !matches!(token.token_type, TokenType::Keyword(Keyword::Class)) => {
+                Err(self.trailing_tokens(last_class))
             }
-
-            return None;
+            _ => self.parse_class(),
+        };
+        match &class {
+            Ok(class) => self.last_class = Some(class.class_name.0),
+            // Nothing after a syntax error can be trusted
+            Err(_) => for _ in self.tokens.by_ref() {},
         }
 
-        return None;
+        Some(class)
+    }
+
+    /// Error about the tokens between the last class and the end of the file,
+    /// which are all consumed.
+    fn trailing_tokens(&mut self, last_class: &str) -> anyhow::Error {
+        let leftover: Vec<_> = self
+            .tokens
+            .by_ref()
+            .take_while(|token| !matches!(token.token_type, TokenType::Eof))
+            .collect();
+        let (Some(first), Some(last)) = (leftover.first(), leftover.last()) else {
+            return anyhow::anyhow!(
+                "Error: Expected the end of the file after class `{last_class}`"
+            );
+        };
+
+        let lines = if first.line == last.line {
+            format!("line {}", first.line)
+        } else {
+            format!("lines {}-{}", first.line, last.line)
+        };
+        anyhow::anyhow!(
+            "[line {}] Error: Expected the end of the file after class `{last_class}` but got `{}` ({} leftover token(s) on {lines})",
+            first.line,
+            first.lexeme,
+            leftover.len()
+        )
     }
 
     fn parse_class(&mut self) -> anyhow::Result<Class<'de>> {
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Class));
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Class))?;
 
-        let class_name = consume!(self.tokens)?.try_into()?;
+        let class_name = self.parse_identifier()?;
 
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace));
+        let _ =
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))?;
         let mut class_var_decs = vec![];
-        while let Some(class_var_dec) = self.parse_class_var_dec() {
+        while let Some(class_var_dec) = self.parse_class_var_dec()? {
             class_var_decs.push(class_var_dec);
         }
 
         let mut subroutine_decs = vec![];
-        while let Some(subroutine_dec) = self.parse_subroutine_dec() {
+        while let Some(subroutine_dec) = self.parse_subroutine_dec()? {
             subroutine_decs.push(subroutine_dec);
         }
 
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace));
+        if !peek_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace)) {
+            return Err(self.unexpected("a class variable, a subroutine or `}`"));
+        }
+        let _ = consume!(self.tokens)?;
 
         Ok(Class {
             class_name,
@@ -297,223 +357,229 @@ where
         })
     }
 
-    fn parse_let_statement(&mut self) -> Option<LetStatement<'de>> {
+    /// `Ok(None)` when the next token does not start a let statement.
+    fn parse_let_statement(&mut self) -> anyhow::Result<Option<LetStatement<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::Let)) {
-            return None;
+            return Ok(None);
         }
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Let)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Let))?;
 
-        let var_name = self.parse_identifier()?.try_into().ok()?;
+        let var_name = self.parse_identifier()?;
 
         let expression_1 =
             if peek_matches!(self.tokens, TokenType::Symbol(Symbol::LeftSquareBracket)) {
-                let _ = consume!(self.tokens).ok()?;
+                let _ = consume!(self.tokens)?;
 
-                let expression_1 = self.parse_expression().ok()?;
+                let expression_1 = self.parse_expression()?;
                 let _ = consume_and_ensure_matches!(
                     self.tokens,
                     TokenType::Symbol(Symbol::RightSquareBracket)
-                )
-                .ok()?;
+                )?;
 
                 Some(expression_1)
             } else {
                 None
             };
 
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Equal)).ok()?;
-        let expression_2 = self.parse_expression().ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Equal))?;
+        let expression_2 = self.parse_expression()?;
 
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon))?;
 
-        Some(LetStatement {
+        Ok(Some(LetStatement {
             var_name,
             expression_1,
             expression_2,
-        })
+        }))
     }
 
-    fn parse_if_statement(&mut self) -> Option<IfStatement<'de>> {
+    /// `Ok(None)` when the next token does not start an if statement.
+    fn parse_if_statement(&mut self) -> anyhow::Result<Option<IfStatement<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::If)) {
-            return None;
+            return Ok(None);
         }
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::If)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::If))?;
 
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))
-                .ok()?;
-        let condition = self.parse_expression().ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))?;
+        let condition = self.parse_expression()?;
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))
-                .ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))?;
 
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))
-            .ok()?;
-        let then_branch = self.parse_statements()?;
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace))
-                .ok()?;
+        let then_branch = self.parse_block()?;
 
         let else_branch = if peek_matches!(self.tokens, TokenType::Keyword(Keyword::Else)) {
-            let _ = consume!(self.tokens).ok()?;
-
-            let _ =
-                consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))
-                    .ok()?;
-            let else_branch = self.parse_statements()?;
-            let _ = consume_and_ensure_matches!(
-                self.tokens,
-                TokenType::Symbol(Symbol::RightCurlyBrace)
-            )
-            .ok()?;
-
-            Some(else_branch)
+            let _ = consume!(self.tokens)?;
+
+            Some(self.parse_block()?)
         } else {
             None
         };
 
-        Some(IfStatement {
+        Ok(Some(IfStatement {
             condition,
             then_branch,
             else_branch,
-        })
+        }))
     }
 
-    fn parse_while_statement(&mut self) -> Option<WhileStatement<'de>> {
+    /// `Ok(None)` when the next token does not start a while statement.
+    fn parse_while_statement(&mut self) -> anyhow::Result<Option<WhileStatement<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::While)) {
-            return None;
+            return Ok(None);
         }
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::While)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::While))?;
 
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))
-                .ok()?;
-        let condition = self.parse_expression().ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))?;
+        let condition = self.parse_expression()?;
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))
-                .ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))?;
 
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))
-            .ok()?;
-        let body = self.parse_statements()?;
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace))
-                .ok()?;
+        let body = self.parse_block()?;
 
-        Some(WhileStatement { condition, body })
+        Ok(Some(WhileStatement { condition, body }))
     }
 
-    fn parse_do_statement(&mut self) -> Option<DoStatement<'de>> {
+    /// `Ok(None)` when the next token does not start a do statement.
+    fn parse_do_statement(&mut self) -> anyhow::Result<Option<DoStatement<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::Do)) {
-            return None;
+            return Ok(None);
         }
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Do)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Do))?;
 
-        let subroutine_call = self.parse_subroutine_call().ok()?;
+        let subroutine_call = self.parse_subroutine_call()?;
 
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon))?;
 
-        Some(DoStatement { subroutine_call })
+        Ok(Some(DoStatement { subroutine_call }))
     }
 
-    fn parse_return_statement(&mut self) -> Option<ReturnStatement<'de>> {
+    /// `Ok(None)` when the next token does not start a return statement.
+    fn parse_return_statement(&mut self) -> anyhow::Result<Option<ReturnStatement<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::Return)) {
-            return None;
+            return Ok(None);
         }
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Return)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Return))?;
 
         let expression = if peek_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon)) {
-            let _ = consume!(self.tokens).ok()?;
+            let _ = consume!(self.tokens)?;
 
             None
         } else {
-            let expression = self.parse_expression().ok()?;
+            let expression = self.parse_expression()?;
 
-            let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon))
-                .ok()?;
+            let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon))?;
 
             Some(expression)
         };
 
-        Some(ReturnStatement { expression })
+        Ok(Some(ReturnStatement { expression }))
     }
 
-    fn parse_statement(&mut self) -> Option<Statement<'de>> {
-        if let Some(let_statement) = self.parse_let_statement() {
-            Some(Statement::LetStatement(let_statement))
-        } else if let Some(if_statement) = self.parse_if_statement() {
-            Some(Statement::IfStatement(if_statement))
-        } else if let Some(while_statement) = self.parse_while_statement() {
-            Some(Statement::WhileStatement(while_statement))
-        } else if let Some(do_statement) = self.parse_do_statement() {
-            Some(Statement::DoStatement(do_statement))
-        } else if let Some(return_statement) = self.parse_return_statement() {
-            Some(Statement::ReturnStatement(return_statement))
+    /// `Ok(None)` when the next token does not start a statement.
+    fn parse_statement(&mut self) -> anyhow::Result<Option<Statement<'de>>> {
+        let statement = if let Some(let_statement) = self.parse_let_statement()? {
+            Statement::LetStatement(let_statement)
+        } else if let Some(if_statement) = self.parse_if_statement()? {
+            Statement::IfStatement(if_statement)
+        } else if let Some(while_statement) = self.parse_while_statement()? {
+            Statement::WhileStatement(while_statement)
+        } else if let Some(do_statement) = self.parse_do_statement()? {
+            Statement::DoStatement(do_statement)
+        } else if let Some(return_statement) = self.parse_return_statement()? {
+            Statement::ReturnStatement(return_statement)
         } else {
-            None
-        }
+            return Ok(None);
+        };
+
+        Ok(Some(statement))
     }
 
-    fn parse_statements(&mut self) -> Option<Statements<'de>> {
+    /// Statements up to the closing `}`, which is left for the caller.
+    fn parse_statements(&mut self) -> anyhow::Result<Statements<'de>> {
         let mut statements = vec![];
+        let mut lines = vec![];
 
-        while let Some(statement) = self.parse_statement() {
+        loop {
+            let line = self.tokens.peek().map_or(0, |token| token.line);
+            let Some(statement) = self.parse_statement()? else {
+                break;
+            };
             statements.push(statement);
+            lines.push(line);
+        }
+
+        if !peek_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace)) {
+            return Err(self.unexpected("a statement or `}`"));
         }
 
-        Some(Statements { statements })
+        Ok(Statements { statements, lines })
+    }
+
+    /// `'{' statements '}'`
+    fn parse_block(&mut self) -> anyhow::Result<Statements<'de>> {
+        let _ =
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))?;
+        let statements = self.parse_statements()?;
+        let _ =
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace))?;
+
+        Ok(statements)
     }
 
-    fn parse_parameeter_list(&mut self) -> Option<ParameterList<'de>> {
+    fn parse_parameeter_list(&mut self) -> anyhow::Result<ParameterList<'de>> {
         let mut parameters = vec![];
 
         while let Some(r#type) = self.parse_type() {
-            let var_name = self.parse_identifier()?.try_into().ok()?;
+            let var_name = self.parse_identifier()?;
 
             parameters.push((r#type, var_name));
 
             if peek_matches!(self.tokens, TokenType::Symbol(Symbol::Comma)) {
-                let _ = consume!(self.tokens);
+                let _ = consume!(self.tokens)?;
             } else {
                 break;
             }
         }
 
-        Some(ParameterList { parameters })
+        Ok(ParameterList { parameters })
     }
 
-    fn parse_var_dec(&mut self) -> Option<VarDec<'de>> {
+    /// `Ok(None)` when the next token does not start a variable declaration.
+    fn parse_var_dec(&mut self) -> anyhow::Result<Option<VarDec<'de>>> {
         if !peek_matches!(self.tokens, TokenType::Keyword(Keyword::Var)) {
-            return None;
+            return Ok(None);
         }
 
-        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Var)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Keyword(Keyword::Var))?;
 
-        let var_type = self.parse_type()?;
+        let var_type = self.parse_type().ok_or_else(|| self.unexpected("a type"))?;
+        let var_names = self.parse_var_names()?;
+
+        Ok(Some(VarDec {
+            var_type,
+            var_names,
+        }))
+    }
 
+    /// `varName (',' varName)* ';'`
+    fn parse_var_names(&mut self) -> anyhow::Result<Vec<Identifier<'de>>> {
         let mut var_names = vec![];
         loop {
-            let var_name = self.parse_identifier()?.try_into().ok()?;
+            let var_name = self.parse_identifier()?;
             var_names.push(var_name);
 
             if peek_matches!(self.tokens, TokenType::Symbol(Symbol::Comma)) {
-                let _ = consume!(self.tokens);
+                let _ = consume!(self.tokens)?;
             } else {
                 break;
             }
         }
 
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon)).ok()?;
+        let _ = consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon))?;
 
-        Some(VarDec {
-            var_type,
-            var_names,
-        })
+        Ok(var_names)
     }
 
     fn parse_subroutine_body(&mut self) -> anyhow::Result<SubroutineBody<'de>> {
@@ -521,13 +587,11 @@ where
             consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftCurlyBrace))?;
 
         let mut var_decs = vec![];
-        while let Some(var_dec) = self.parse_var_dec() {
+        while let Some(var_dec) = self.parse_var_dec()? {
             var_decs.push(var_dec);
         }
 
-        let statements = self.parse_statements().ok_or(anyhow::anyhow!(
-            "Could not `parse_statements` at `parse_subroutine_body`"
-        ))?;
+        let statements = self.parse_statements()?;
 
         let _ =
             consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightCurlyBrace))?;
@@ -538,57 +602,56 @@ where
         })
     }
 
-    fn parse_subroutine_dec(&mut self) -> Option<SubroutineDec<'de>> {
-        if !matches!(
-            peek!(self.tokens).ok()?.token_type,
+    /// `Ok(None)` when the next token does not start a subroutine declaration.
+    fn parse_subroutine_dec(&mut self) -> anyhow::Result<Option<SubroutineDec<'de>>> {
+        if !peek_matches!(
+            self.tokens,
             TokenType::Keyword(Keyword::Constructor)
                 | TokenType::Keyword(Keyword::Function)
                 | TokenType::Keyword(Keyword::Method)
         ) {
-            self.tokens.reset_peek();
-
-            return None;
+            return Ok(None);
         }
 
-        let subroutine_dec_type = match consume!(self.tokens).ok()?.token_type {
+        let line = self.tokens.peek().map_or(0, |token| token.line);
+        let subroutine_dec_type = match consume!(self.tokens)?.token_type {
             TokenType::Keyword(Keyword::Constructor) => SubroutineDecType::Constructor,
             TokenType::Keyword(Keyword::Function) => SubroutineDecType::Function,
             TokenType::Keyword(Keyword::Method) => SubroutineDecType::Method,
             _ => unreachable!(),
         };
 
-        let subroutine_dec_return_type = match peek!(self.tokens).ok()?.token_type {
-            TokenType::Keyword(Keyword::Void) => {
-                let _ = consume!(self.tokens);
+        let subroutine_dec_return_type =
+            if peek_matches!(self.tokens, TokenType::Keyword(Keyword::Void)) {
+                let _ = consume!(self.tokens)?;
 
                 SubroutineDecReturn::Void
-            }
-            _ => {
-                let r#type = self.parse_type()?;
+            } else {
+                let r#type = self
+                    .parse_type()
+                    .ok_or_else(|| self.unexpected("a return type or `void`"))?;
 
                 SubroutineDecReturn::Type(r#type)
-            }
-        };
+            };
 
-        let subroutine_name = consume!(self.tokens).ok()?.try_into().ok()?;
+        let subroutine_name = self.parse_identifier()?;
 
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))
-                .ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::LeftParenthesis))?;
         let parameter_list = self.parse_parameeter_list()?;
         let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))
-                .ok()?;
+            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::RightParenthesis))?;
 
-        let subroutine_body = self.parse_subroutine_body().ok()?;
+        let subroutine_body = self.parse_subroutine_body()?;
 
-        Some(SubroutineDec {
+        Ok(Some(SubroutineDec {
             subroutine_dec_type,
             subroutine_dec_return_type,
             subroutine_name,
             parameter_list,
             subroutine_body,
-        })
+            line,
+        }))
     }
 
     fn parse_type(&mut self) -> Option<Type<'de>> {
@@ -599,8 +662,6 @@ where
                 | TokenType::Keyword(Keyword::Boolean)
                 | TokenType::Identifier(_)
         ) {
-            self.tokens.reset_peek();
-
             return None;
         }
 
@@ -613,53 +674,23 @@ where
         }
     }
 
-    fn parse_class_var_dec(&mut self) -> Option<ClassVarDec<'de>> {
-        let class_var_dec_kind = match peek!(self.tokens).ok()?.token_type {
-            TokenType::Keyword(Keyword::Static) => {
-                let _ = consume!(self.tokens);
-
-                ClassVarDecKind::Static
-            }
-            TokenType::Keyword(Keyword::Field) => {
-                let _ = consume!(self.tokens);
-
-                ClassVarDecKind::Field
-            }
-            _ => {
-                self.tokens.reset_peek();
-
-                return None;
-            }
+    /// `Ok(None)` when the next token does not start a class variable declaration.
+    fn parse_class_var_dec(&mut self) -> anyhow::Result<Option<ClassVarDec<'de>>> {
+        let class_var_dec_kind = match peek!(self.tokens)?.token_type {
+            TokenType::Keyword(Keyword::Static) => ClassVarDecKind::Static,
+            TokenType::Keyword(Keyword::Field) => ClassVarDecKind::Field,
+            _ => return Ok(None),
         };
+        let _ = consume!(self.tokens)?;
 
-        let class_var_dec_type = self.parse_type()?;
-
-        let mut var_names = vec![];
-
-        loop {
-            let var_name = self.parse_identifier()?;
-            var_names.push(var_name);
-
-            if matches!(
-                peek!(self.tokens).ok()?.token_type,
-                TokenType::Symbol(Symbol::Comma)
-            ) {
-                let _ = consume!(self.tokens).ok()?;
-            } else {
-                self.tokens.reset_peek();
-
-                break;
-            }
-        }
+        let class_var_dec_type = self.parse_type().ok_or_else(|| self.unexpected("a type"))?;
+        let var_names = self.parse_var_names()?;
 
-        let _ =
-            consume_and_ensure_matches!(self.tokens, TokenType::Symbol(Symbol::Semicolon)).ok()?;
-
-        Some(ClassVarDec {
+        Ok(Some(ClassVarDec {
             class_var_dec_kind,
             class_var_dec_type,
             var_names,
-        })
+        }))
     }
 
     fn parse_expression(&mut self) -> anyhow::Result<Expression<'de>> {
@@ -692,7 +723,7 @@ where
         let next_1 = self
             .tokens
             .peek()
-            .ok_or(anyhow::anyhow!("Could not peek a token at `parse_term`"))?;
+            .ok_or_else(|| anyhow::anyhow!("Could not peek a token at `parse_term`"))?;
         match &next_1.token_type {
             // integerConstant | stringConstant
             TokenType::Constant(_) => {
@@ -715,7 +746,7 @@ where
                 return Ok(Term::Expression(Box::new(expression)));
             }
             TokenType::Identifier(_) => {
-                if let Some(next_2) = self.tokens.peek() {
+                if let Some(next_2) = self.tokens.peek_second() {
                     // varName '[' expression ']'
                     match &next_2.token_type {
                         TokenType::Symbol(Symbol::LeftSquareBracket) => {
@@ -739,11 +770,15 @@ where
                     }
                 }
 
-                self.tokens.reset_peek();
-
                 // subroutineCall
-                if let Ok(subroutine_call) = self.parse_subroutine_call() {
-                    return Ok(Term::SubroutineCall(subroutine_call));
+                if matches!(
+                    self.tokens.peek_second(),
+                    Some(Token {
+                        token_type: TokenType::Symbol(Symbol::LeftParenthesis | Symbol::Dot),
+                        ..
+                    })
+                ) {
+                    return Ok(Term::SubroutineCall(self.parse_subroutine_call()?));
                 }
 
                 // varName
@@ -768,26 +803,26 @@ where
                     });
                 }
 
-                panic!()
+                return Err(self.unexpected("an expression"));
             }
         };
     }
 
     fn parse_subroutine_call(&mut self) -> anyhow::Result<SubroutineCall<'de>> {
-        let next_1 = self.tokens.peek().ok_or(anyhow::anyhow!(
-            "Could not peek a token at `parse_subroutine_call`"
-        ))?;
+        let next_1 = self
+            .tokens
+            .peek()
+            .ok_or_else(|| anyhow::anyhow!("Could not peek a token at `parse_subroutine_call`"))?;
         if !matches!(next_1.token_type, TokenType::Identifier(_)) {
-            self.tokens.reset_peek();
-
             anyhow::bail!(
                 "Got a wrong token type at `parse_subroutine_call`. Not an `Identifier` <thinking>"
             );
         }
 
-        let next_2 = self.tokens.peek().ok_or(anyhow::anyhow!(
-            "Could not peek a token at `parse_subroutine_call`"
-        ))?;
+        let next_2 = self
+            .tokens
+            .peek_second()
+            .ok_or_else(|| anyhow::anyhow!("Could not peek a token at `parse_subroutine_call`"))?;
         match next_2.token_type {
             TokenType::Symbol(Symbol::LeftParenthesis) => {
                 let subroutine_name = consume!(self.tokens)?.try_into()?;
@@ -858,8 +893,6 @@ where
                 })
             }
             _ => {
-                self.tokens.reset_peek();
-
                 anyhow::bail!(
                     "Got a wrong token type at `parse_subroutine_call`. Neither `Symbol::LeftParenthesis` nor `Symbol::Dot` <thinking>"
                 );
@@ -870,8 +903,8 @@ where
     fn parse_expression_list(&mut self) -> anyhow::Result<ExpressionList<'de>> {
         let mut expressions = vec![];
 
-        while let Ok(expression) = self.parse_expression() {
-            expressions.push(expression);
+        loop {
+            expressions.push(self.parse_expression()?);
 
             if peek_matches!(self.tokens, TokenType::Symbol(Symbol::Comma)) {
                 let _ = consume!(self.tokens);
@@ -883,19 +916,32 @@ where
         Ok(ExpressionList { expressions })
     }
 
-    fn parse_identifier(&mut self) -> Option<Identifier<'de>> {
-        match &peek!(self.tokens).ok()?.token_type {
-            TokenType::Identifier(_) => {
-                let token = consume!(self.tokens).ok()?;
-                let identifier = token.try_into().ok()?;
+    fn parse_identifier(&mut self) -> anyhow::Result<Identifier<'de>> {
+        if let Some(
+            token @ Token {
+                token_type: TokenType::Keyword(_),
+                ..
+            },
+        ) = self.tokens.peek()
+        {
+            return Err(keyword_as_identifier(token));
+        }
+        if !peek_matches!(self.tokens, TokenType::Identifier(_)) {
+            return Err(self.unexpected("an identifier"));
+        }
 
-                Some(identifier)
-            }
-            _ => {
-                self.tokens.reset_peek();
+        consume!(self.tokens)?.try_into()
+    }
 
-                None
-            }
+    /// Error about the next token, which is none of `expected`.
+    fn unexpected(&self, expected: &str) -> anyhow::Error {
+        match self.tokens.peek() {
+            Some(token) => anyhow::anyhow!(
+                "[line {}] Error: Expected {expected} but got `{}`",
+                token.line,
+                token.lexeme
+            ),
+            None => anyhow::anyhow!("Error: Expected {expected} but the input ended"),
         }
     }
 
@@ -911,17 +957,9 @@ where
                 Symbol::LessThan => Some(Op::LessThan),
                 Symbol::GreaterThan => Some(Op::GreaterThan),
                 Symbol::Equal => Some(Op::Equal),
-                _ => {
-                    self.tokens.reset_peek();
-
-                    None
-                }
+                _ => None,
             },
-            _ => {
-                self.tokens.reset_peek();
-
-                None
-            }
+            _ => None,
         };
         if result.is_some() {
             let _ = consume!(self.tokens);
@@ -935,15 +973,9 @@ where
             TokenType::Symbol(symbol) => match symbol {
                 Symbol::Minus => Some(UnaryOp::Minus),
                 Symbol::Tilde => Some(UnaryOp::Tilde),
-                _ => {
-                    self.tokens.reset_peek();
-                    None
-                }
+                _ => None,
             },
-            _ => {
-                self.tokens.reset_peek();
-                None
-            }
+            _ => None,
         };
         if result.is_some() {
             let _ = consume!(self.tokens);
@@ -959,16 +991,9 @@ where
                 Keyword::False => Some(KeywordConstant::False),
                 Keyword::Null => Some(KeywordConstant::Null),
                 Keyword::This => Some(KeywordConstant::This),
-                _ => {
-                    self.tokens.reset_peek();
-                    None
-                }
+                _ => None,
             },
-            _ => {
-                self.tokens.reset_peek();
-
-                None
-            }
+            _ => None,
         };
         if result.is_some() {
             let _ = consume!(self.tokens);
@@ -989,19 +1014,25 @@ where
     }
 }
 
+/// Error about a keyword, such as `class` or `this`, where a name belongs.
+fn keyword_as_identifier(token: &Token) -> anyhow::Error {
+    anyhow::anyhow!(
+        "[line {}:{}] Error: Expected an identifier, found keyword `{}`",
+        token.line,
+        token.column,
+        token.lexeme
+    )
+}
+
 impl<'de> TryFrom<Token<'de>> for Identifier<'de> {
     type Error = anyhow::Error;
 
     fn try_from(token: Token<'de>) -> Result<Self, Self::Error> {
-        let Token {
-            token_type: TokenType::Identifier(identifier),
-            ..
-        } = token
-        else {
-            anyhow::bail!("Error: Could not conver token `{token:?}` into an identifier")
-        };
-
-        Ok(identifier)
+        match token.token_type {
+            TokenType::Identifier(identifier) => Ok(identifier),
+            TokenType::Keyword(_) => Err(keyword_as_identifier(&token)),
+            _ => anyhow::bail!("Error: Could not conver token `{token:?}` into an identifier"),
+        }
     }
 }
 
@@ -1020,3 +1051,147 @@ impl<'de> TryFrom<Token<'de>> for Constant<'de> {
         Ok(constant)
     }
 }
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+    use std::time::Instant;
+
+    fn parse_error(source: &str) -> String {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        classes.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reports_syntax_errors_where_they_are() {
+        let misspelled_statement = "class Main {
+            function void main() {
+                lett x = 1;
+                return;
+            }
+        }";
+        assert_eq!(
+            parse_error(misspelled_statement),
+            "[line 3] Error: Expected a statement or `}` but got `lett`"
+        );
+
+        let missing_expression = "class Main {
+            function void main() {
+                let x = ;
+            }
+        }";
+        assert_eq!(
+            parse_error(missing_expression),
+            "[line 3] Error: Expected an expression but got `;`"
+        );
+
+        let bad_argument = "class Main {
+            function void main() {
+                do Output.printInt(Math.max(1, ));
+                return;
+            }
+        }";
+        assert_eq!(
+            parse_error(bad_argument),
+            "[line 3] Error: Expected an expression but got `)`"
+        );
+
+        let bad_var_dec = "class Main {
+            function void main() {
+                var int 1;
+            }
+        }";
+        assert_eq!(
+            parse_error(bad_var_dec),
+            "[line 3] Error: Expected an identifier but got `1`"
+        );
+
+        let bad_class_var_dec = "class Main {
+            field a;
+        }";
+        assert_eq!(
+            parse_error(bad_class_var_dec),
+            "[line 2] Error: Expected an identifier but got `;`"
+        );
+    }
+
+    #[test]
+    fn reports_keywords_used_as_identifiers() {
+        let this_variable = "class Main {
+            function void main() {
+                var int this;
+                return;
+            }
+        }";
+        assert_eq!(
+            parse_error(this_variable),
+            "[line 3:25] Error: Expected an identifier, found keyword `this`"
+        );
+
+        let class_assigned = "class Main {
+            function void main() {
+                let class = 1;
+            }
+        }";
+        assert_eq!(
+            parse_error(class_assigned),
+            "[line 3:21] Error: Expected an identifier, found keyword `class`"
+        );
+    }
+
+    #[test]
+    fn reports_tokens_after_the_last_class() {
+        let stray_brace = "class Main {
+            function void main() {
+                return;
+            }
+        }
+        }";
+        assert_eq!(
+            parse_error(stray_brace),
+            "[line 6] Error: Expected the end of the file after class `Main` but got `}` (1 leftover token(s) on line 6)"
+        );
+
+        let stray_code = "class A {}
+        class B {}
+        let x = 1;
+        do f();";
+        assert_eq!(
+            parse_error(stray_code),
+            "[line 3] Error: Expected the end of the file after class `B` but got `let` (10 leftover token(s) on lines 3-4)"
+        );
+    }
+
+    /// `cargo test --release -- --ignored --nocapture parse_throughput`
+    #[test]
+    #[ignore]
+    fn parse_throughput() {
+        let sources: Vec<_> = std::fs::read_dir("input/Pong")
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        let tokens: Vec<Vec<_>> = sources
+            .iter()
+            .map(|source| Tokenizer::new(source).collect::<Result<_, _>>().unwrap())
+            .collect();
+        let bytes: usize = sources.iter().map(String::len).sum();
+
+        const ROUNDS: usize = 1000;
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for tokens in tokens.iter() {
+                let classes: Result<Vec<_>, _> = Parser::new(tokens.iter().cloned()).collect();
+                assert_eq!(classes.unwrap().len(), 1);
+            }
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "Parsed Pong {ROUNDS} times in {elapsed:?}: {:.1} MB/s",
+            (bytes * ROUNDS) as f64 / elapsed.as_secs_f64() / 1e6
+        );
+    }
+}
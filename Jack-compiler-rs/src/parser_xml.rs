@@ -1,3 +1,4 @@
+use quick_xml::{Reader, events::Event};
 use serde::{
     Serialize, Serializer,
     ser::{SerializeMap, SerializeStruct},
@@ -11,8 +12,87 @@ use crate::{
         Term, Type, UnaryOp, VarDec, WhileStatement,
     },
     tokenizer::{Constant, Identifier},
+    tokenizer_xml::escape,
 };
 
+/// Elements of the tokens, the leaves of the parse tree.
+const TOKENS: [&str; 5] = [
+    "keyword",
+    "symbol",
+    "identifier",
+    "integerConstant",
+    "stringConstant",
+];
+
+impl<'de> Class<'de> {
+    /// Parse tree file in the course's `Xxx.xml` format: an element per line, indented
+    /// by two spaces per level, with the tokens padded and escaped as in `XxxT.xml`. An
+    /// empty `parameterList`, `expressionList` or `statements` still takes two lines.
+    pub fn to_xml(&self) -> anyhow::Result<String> {
+        let mut compact = String::new();
+        self.serialize(quick_xml::se::Serializer::new(&mut compact))?;
+
+        let mut xml = String::new();
+        let mut depth = 0;
+        // Escaped text of the token being read
+        let mut token: Option<String> = None;
+        let mut reader = Reader::from_str(&compact);
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let tag = start.local_name();
+                    let tag = std::str::from_utf8(tag.as_ref())?;
+                    if TOKENS.contains(&tag) {
+                        token = Some(String::new());
+                    } else {
+                        xml.push_str(&format!("{}<{tag}>\n", "  ".repeat(depth)));
+                        depth += 1;
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(token) = token.as_mut() {
+                        token.push_str(&text.decode()?);
+                    }
+                }
+                Event::GeneralRef(reference) => {
+                    if let Some(token) = token.as_mut() {
+                        token.push_str(&format!("&{};", reference.decode()?));
+                    }
+                }
+                Event::End(end) => {
+                    let tag = end.local_name();
+                    let tag = std::str::from_utf8(tag.as_ref())?;
+                    if let Some(token) = token.take() {
+                        let value = quick_xml::escape::unescape(&token)?;
+                        xml.push_str(&format!(
+                            "{}<{tag}> {} </{tag}>\n",
+                            "  ".repeat(depth),
+                            escape(&value)
+                        ));
+                    } else {
+                        depth -= 1;
+                        xml.push_str(&format!("{}</{tag}>\n", "  ".repeat(depth)));
+                    }
+                }
+                Event::Empty(empty) => {
+                    let tag = empty.local_name();
+                    let tag = std::str::from_utf8(tag.as_ref())?;
+                    let indent = "  ".repeat(depth);
+                    if TOKENS.contains(&tag) {
+                        xml.push_str(&format!("{indent}<{tag}>  </{tag}>\n"));
+                    } else {
+                        xml.push_str(&format!("{indent}<{tag}>\n{indent}</{tag}>\n"));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(xml)
+    }
+}
+
 impl<'de> Serialize for Class<'de> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -459,7 +539,7 @@ impl Serialize for UnaryOp {
     }
 }
 
-impl<'de> Serialize for KeywordConstant {
+impl Serialize for KeywordConstant {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -540,3 +620,74 @@ impl<'de> Serialize for ExpressionList<'de> {
         s.end()
     }
 }
+
+#[cfg(test)]
+mod parser_xml_tests {
+    use crate::{parser::Parser, tokenizer::Tokenizer};
+
+    #[test]
+    fn course_format() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(
+            "class Main {
+                function void main() {
+                    do Main.run(x < 3);
+                    return;
+                }
+            }",
+        )
+        .collect();
+        let class = Parser::new(tokens.unwrap().into_iter())
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let expected = "\
+<class>
+  <keyword> class </keyword>
+  <identifier> Main </identifier>
+  <symbol> { </symbol>
+  <subroutineDec>
+    <keyword> function </keyword>
+    <keyword> void </keyword>
+    <identifier> main </identifier>
+    <symbol> ( </symbol>
+    <parameterList>
+    </parameterList>
+    <symbol> ) </symbol>
+    <subroutineBody>
+      <symbol> { </symbol>
+      <statements>
+        <doStatement>
+          <keyword> do </keyword>
+          <identifier> Main </identifier>
+          <symbol> . </symbol>
+          <identifier> run </identifier>
+          <symbol> ( </symbol>
+          <expressionList>
+            <expression>
+              <term>
+                <identifier> x </identifier>
+              </term>
+              <symbol> &lt; </symbol>
+              <term>
+                <integerConstant> 3 </integerConstant>
+              </term>
+            </expression>
+          </expressionList>
+          <symbol> ) </symbol>
+          <symbol> ; </symbol>
+        </doStatement>
+        <returnStatement>
+          <keyword> return </keyword>
+          <symbol> ; </symbol>
+        </returnStatement>
+      </statements>
+      <symbol> } </symbol>
+    </subroutineBody>
+  </subroutineDec>
+  <symbol> } </symbol>
+</class>
+";
+        assert_eq!(class.to_xml().unwrap(), expected);
+    }
+}
@@ -0,0 +1,219 @@
+//! Subroutines that call themselves before any `if` or `while` could stop them, so
+//! every call recurses again: until the stack runs into the heap, or forever for a
+//! self tail call compiled into a jump.
+//!
+//! Only the statements before the first `if` or `while` are searched, and the
+//! conditions of those, as a base case needs one of them before its recursive call.
+//! Jack's `&` and `|` evaluate both sides, so a call anywhere in an expression is
+//! made. A self call is `f(..)` or `Class.f(..)`; calls on another object, such as
+//! `next.f(..)`, are left out.
+
+use crate::parser::{
+    Class, Expression, Statement, Statements, SubroutineCall, SubroutineDec, Term,
+};
+
+/// One warning for every subroutine of `class` that calls itself unconditionally.
+pub fn check(class: &Class<'_>) -> Vec<String> {
+    let class_name = class.class_name.0;
+    let mut problems = vec![];
+
+    for subroutine_dec in class.subroutine_decs.iter() {
+        let finder = Finder {
+            class_name,
+            subroutine_name: subroutine_dec.subroutine_name.0,
+            variables: variables(class, subroutine_dec),
+        };
+        if let Some(line) = finder.statements(&subroutine_dec.subroutine_body.statements) {
+            problems.push(format!(
+                "[line {line}] {class_name}.{} calls itself before any `if` or `while`, \
+                 so it never stops recursing. Hint: return in a base case before the call",
+                subroutine_dec.subroutine_name.0
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Names of the variables in scope of `subroutine_dec`, which a `Class.f(..)` call
+/// does not name the class by when one of them is called `Class`.
+fn variables<'de>(class: &Class<'de>, subroutine_dec: &SubroutineDec<'de>) -> Vec<&'de str> {
+    let fields = class
+        .class_var_decs
+        .iter()
+        .flat_map(|class_var_dec| class_var_dec.var_names.iter());
+    let parameters = subroutine_dec
+        .parameter_list
+        .parameters
+        .iter()
+        .map(|(_, identifier)| identifier);
+    let locals = subroutine_dec
+        .subroutine_body
+        .var_decs
+        .iter()
+        .flat_map(|var_dec| var_dec.var_names.iter());
+
+    fields
+        .chain(parameters)
+        .chain(locals)
+        .map(|identifier| identifier.0)
+        .collect()
+}
+
+struct Finder<'a> {
+    class_name: &'a str,
+    subroutine_name: &'a str,
+    variables: Vec<&'a str>,
+}
+
+impl Finder<'_> {
+    /// The line of the first statement of `statements` making the self call, before
+    /// any `if`, `while` or `return` stops the search.
+    fn statements(&self, statements: &Statements<'_>) -> Option<usize> {
+        for (statement, &line) in statements.statements.iter().zip(&statements.lines) {
+            let (calls, stop) = match statement {
+                Statement::LetStatement(let_statement) => (
+                    let_statement
+                        .expression_1
+                        .iter()
+                        .chain([&let_statement.expression_2])
+                        .any(|expression| self.expression(expression)),
+                    false,
+                ),
+                Statement::IfStatement(if_statement) => {
+                    (self.expression(&if_statement.condition), true)
+                }
+                Statement::WhileStatement(while_statement) => {
+                    (self.expression(&while_statement.condition), true)
+                }
+                Statement::DoStatement(do_statement) => {
+                    (self.subroutine_call(&do_statement.subroutine_call), false)
+                }
+                Statement::ReturnStatement(return_statement) => (
+                    return_statement
+                        .expression
+                        .as_ref()
+                        .is_some_and(|expression| self.expression(expression)),
+                    true,
+                ),
+            };
+            if calls {
+                return Some(line);
+            }
+            if stop {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn expression(&self, expression: &Expression<'_>) -> bool {
+        std::iter::once(&expression.term)
+            .chain(expression.terms.iter().map(|(_, term)| term))
+            .any(|term| self.term(term))
+    }
+
+    fn term(&self, term: &Term<'_>) -> bool {
+        match term {
+            Term::VarNameExpression { expression, .. } | Term::Expression(expression) => {
+                self.expression(expression)
+            }
+            Term::UnaryOpTerm { term, .. } => self.term(term),
+            Term::SubroutineCall(subroutine_call) => self.subroutine_call(subroutine_call),
+            Term::Constant(_) | Term::KeywordConstant(_) | Term::VarName(_) => false,
+        }
+    }
+
+    fn subroutine_call(&self, subroutine_call: &SubroutineCall<'_>) -> bool {
+        let (is_self, expression_list) = match subroutine_call {
+            SubroutineCall::Call {
+                subroutine_name,
+                expression_list,
+            } => (subroutine_name.0 == self.subroutine_name, expression_list),
+            SubroutineCall::ClassCall {
+                class_or_var_name,
+                subroutine_name,
+                expression_list,
+            } => (
+                class_or_var_name.0 == self.class_name
+                    && !self.variables.contains(&class_or_var_name.0)
+                    && subroutine_name.0 == self.subroutine_name,
+                expression_list,
+            ),
+        };
+
+        is_self
+            || expression_list
+                .expressions
+                .iter()
+                .any(|expression| self.expression(expression))
+    }
+}
+
+#[cfg(test)]
+mod recursion_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn check_source(source: &str) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        check(&classes.unwrap()[0])
+    }
+
+    #[test]
+    fn reports_calls_made_before_any_condition() {
+        let source = "class Main {
+                field int size;
+                function int sum(int n) {
+                    var int rest;
+                    let rest = 1 + Main.sum(n - 1);
+                    return n + rest;
+                }
+                method int count() {
+                    do Output.printInt(size);
+                    if (count() > 0) { return 1; }
+                    return 0;
+                }
+                function int fact(int n) {
+                    if (n < 2) { return 1; }
+                    return n * Main.fact(n - 1);
+                }
+                function void loop() {
+                    while (true) { do Main.loop(); }
+                    return;
+                }
+            }";
+
+        assert_eq!(
+            check_source(source),
+            [
+                "[line 5] Main.sum calls itself before any `if` or `while`, so it never \
+                 stops recursing. Hint: return in a base case before the call",
+                "[line 10] Main.count calls itself before any `if` or `while`, so it never \
+                 stops recursing. Hint: return in a base case before the call",
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_on_other_objects_are_not_self_calls() {
+        let source = "class Node {
+                field Node next;
+                method int length() {
+                    return 1 + next.length();
+                }
+                method int depth(Node Node) {
+                    return Node.depth(null);
+                }
+                function int f() {
+                    return Main.f();
+                }
+            }";
+
+        assert!(check_source(source).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+
+use crate::{
+    index::{Span, SymbolIndex, SymbolKey, SymbolKind},
+    tokenizer::KEYWORDS,
+};
+
+#[derive(Debug)]
+pub struct RenamePlan {
+    pub symbol: SymbolKey,
+    pub new_name: String,
+    /// Every identifier to be replaced with `new_name`.
+    pub edits: Vec<Span>,
+    /// `Old.jack` -> `New.jack` when a class is renamed.
+    pub file_renames: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Plans renaming of `symbol` (`Class`, `Class.member` or `Class.subroutine.variable`)
+/// to `new_name` across the indexed project, failing on conflicting declarations.
+pub fn plan(index: &SymbolIndex, symbol: &str, new_name: &str) -> anyhow::Result<RenamePlan> {
+    validate_identifier(new_name)?;
+
+    let mut candidates = index
+        .lookup(symbol)
+        .filter(|(_, occurrences)| occurrences.iter().any(|o| o.is_definition));
+    let Some((key, occurrences)) = candidates.next() else {
+        anyhow::bail!("Could not find a declaration of `{symbol}`");
+    };
+    if let Some((other, _)) = candidates.next() {
+        anyhow::bail!(
+            "`{symbol}` is ambiguous: it is declared both as {:?} and {:?}",
+            key.kind,
+            other.kind
+        );
+    }
+
+    let (parent, old_name) = match symbol.rsplit_once('.') {
+        Some((parent, old_name)) => (Some(parent), old_name),
+        None => (None, symbol),
+    };
+    if old_name == new_name {
+        anyhow::bail!("`{symbol}` is already named `{new_name}`");
+    }
+    let new_symbol = match parent {
+        Some(parent) => format!("{parent}.{new_name}"),
+        None => new_name.to_string(),
+    };
+
+    let location = |span: &Span| {
+        format!(
+            "{}:{}:{}",
+            index.file(span.file).display(),
+            span.line,
+            span.column
+        )
+    };
+
+    let mut conflicts = vec![];
+    match key.kind {
+        SymbolKind::Class => {
+            if index.is_defined(&new_symbol, SymbolKind::Class) {
+                conflicts.push(format!("class `{new_symbol}` already exists"));
+            }
+            // `Old.f()` would turn into a method call on a variable named `New`
+            for occurrence in occurrences.iter() {
+                let Some(scope) = &occurrence.scope else {
+                    continue;
+                };
+                let class_name = scope.split('.').next().expect("Qualified scope");
+                let shadowing = [
+                    (format!("{scope}.{new_name}"), SymbolKind::Local),
+                    (format!("{scope}.{new_name}"), SymbolKind::Argument),
+                    (format!("{class_name}.{new_name}"), SymbolKind::Field),
+                    (format!("{class_name}.{new_name}"), SymbolKind::Static),
+                ];
+                for (name, kind) in shadowing {
+                    if index.is_defined(&name, kind) {
+                        conflicts.push(format!(
+                            "the use at {} would refer to {kind:?} `{name}`",
+                            location(&occurrence.span)
+                        ));
+                    }
+                }
+            }
+        }
+        SymbolKind::Subroutine => {
+            if index.is_defined(&new_symbol, SymbolKind::Subroutine) {
+                conflicts.push(format!("subroutine `{new_symbol}` already exists"));
+            }
+        }
+        SymbolKind::Field | SymbolKind::Static => {
+            for kind in [SymbolKind::Field, SymbolKind::Static] {
+                if index.is_defined(&new_symbol, kind) {
+                    conflicts.push(format!("{kind:?} `{new_symbol}` already exists"));
+                }
+            }
+            // A local or an argument with the new name would capture the uses
+            for occurrence in occurrences.iter() {
+                let Some(scope) = &occurrence.scope else {
+                    continue;
+                };
+                let name = format!("{scope}.{new_name}");
+                for kind in [SymbolKind::Local, SymbolKind::Argument] {
+                    if index.is_defined(&name, kind) {
+                        conflicts.push(format!(
+                            "the use at {} would refer to {kind:?} `{name}`",
+                            location(&occurrence.span)
+                        ));
+                    }
+                }
+            }
+        }
+        SymbolKind::Argument | SymbolKind::Local => {
+            for kind in [SymbolKind::Local, SymbolKind::Argument] {
+                if index.is_defined(&new_symbol, kind) {
+                    conflicts.push(format!("{kind:?} `{new_symbol}` already exists"));
+                }
+            }
+            // The renamed variable would shadow a class variable used in the same subroutine
+            let class_name = symbol.split('.').next().expect("Qualified symbol");
+            let class_var = format!("{class_name}.{new_name}");
+            let scope = parent.map(str::to_string);
+            for (other, other_occurrences) in index.lookup(&class_var) {
+                if !other.kind.is_class_var() {
+                    continue;
+                }
+                for occurrence in other_occurrences.iter().filter(|o| o.scope == scope) {
+                    conflicts.push(format!(
+                        "the use of {:?} `{class_var}` at {} would be shadowed",
+                        other.kind,
+                        location(&occurrence.span)
+                    ));
+                }
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Could not rename `{symbol}` to `{new_name}`:\n  {}",
+            conflicts.join("\n  ")
+        );
+    }
+
+    let mut edits: Vec<_> = occurrences.iter().map(|o| o.span.clone()).collect();
+    edits.sort();
+    edits.dedup();
+
+    let file_renames = match key.kind {
+        SymbolKind::Class => occurrences
+            .iter()
+            .filter(|o| o.is_definition)
+            .map(|o| index.file(o.span.file))
+            .filter(|path| path.file_stem().is_some_and(|stem| stem == old_name))
+            .map(|path| {
+                let new_path = path.with_file_name(new_name);
+                let new_path = match path.extension() {
+                    Some(ext) => new_path.with_extension(ext),
+                    None => new_path,
+                };
+
+                (path.to_path_buf(), new_path)
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok(RenamePlan {
+        symbol: key.clone(),
+        new_name: new_name.to_string(),
+        edits,
+        file_renames,
+    })
+}
+
+/// Applies the edits of a single file to its source.
+pub fn rewrite<'a>(source: &str, edits: impl Iterator<Item = &'a Span>, new_name: &str) -> String {
+    let mut edits: Vec<_> = edits.collect();
+    edits.sort_by_key(|span| std::cmp::Reverse(span.offset));
+
+    let mut source = source.to_string();
+    for span in edits {
+        source.replace_range(span.offset..span.offset + span.len, new_name);
+    }
+
+    source
+}
+
+fn validate_identifier(name: &str) -> anyhow::Result<()> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+
+    if !valid {
+        anyhow::bail!("`{name}` is not a valid identifier");
+    }
+    if KEYWORDS.contains_key(name) {
+        anyhow::bail!("`{name}` is a keyword and can not be used as an identifier");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod rename_tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{parser::Parser, tokenizer::Tokenizer};
+
+    const SOURCE: &str = "class Counter {
+    field int count;
+
+    method void inc(int by) {
+        var int next;
+        let next = count + by;
+        let count = next;
+        return;
+    }
+
+    method int get() {
+        do inc(1);
+        return count;
+    }
+}
+";
+
+    fn index(source: &str) -> SymbolIndex {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        let mut index = SymbolIndex::default();
+        index.add_file(Path::new("Counter.jack"), source, &classes.unwrap());
+
+        index
+    }
+
+    #[test]
+    fn field() {
+        let index = index(SOURCE);
+        let plan = plan(&index, "Counter.count", "total").unwrap();
+
+        assert_eq!(plan.edits.len(), 4);
+        let renamed = rewrite(SOURCE, plan.edits.iter(), &plan.new_name);
+        assert!(renamed.contains("field int total;"));
+        assert!(renamed.contains("let next = total + by;"));
+        assert!(renamed.contains("return total;"));
+    }
+
+    #[test]
+    fn subroutine() {
+        let index = index(SOURCE);
+        let plan = plan(&index, "Counter.inc", "add").unwrap();
+
+        let renamed = rewrite(SOURCE, plan.edits.iter(), &plan.new_name);
+        assert!(renamed.contains("method void add(int by)"));
+        assert!(renamed.contains("do add(1);"));
+    }
+
+    #[test]
+    fn class() {
+        let index = index(SOURCE);
+        let plan = plan(&index, "Counter", "Tally").unwrap();
+
+        assert_eq!(plan.edits.len(), 1);
+        assert_eq!(
+            plan.file_renames,
+            vec![(PathBuf::from("Counter.jack"), PathBuf::from("Tally.jack"))]
+        );
+    }
+
+    #[test]
+    fn local_shadowing_field() {
+        let index = index(SOURCE);
+
+        assert!(plan(&index, "Counter.inc.next", "count").is_err());
+        assert!(plan(&index, "Counter.count", "by").is_err());
+        assert!(plan(&index, "Counter.inc.next", "by").is_err());
+        assert!(plan(&index, "Counter.inc.next", "following").is_ok());
+    }
+
+    #[test]
+    fn invalid_name() {
+        let index = index(SOURCE);
+
+        assert!(plan(&index, "Counter.count", "this").is_err());
+        assert!(plan(&index, "Counter.count", "1st").is_err());
+        assert!(plan(&index, "Counter.missing", "other").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Interactive evaluation of Jack statements and expressions.
+//!
+//! Every input is wrapped into a synthetic `Repl.eval` function, after the `var`
+//! declarations and statements accepted so far, then compiled and run by the
+//! interpreter. Earlier statements are replayed, so variables keep their values,
+//! and only the output of the new input is shown.
+
+use std::io::{BufRead, Write};
+
+use crate::compiler::snippet::{self, Environment};
+use crate::compiler::{Codegen, Compiler};
+use crate::index::SymbolKind;
+use crate::interpreter::{Interpreter, InterpreterConfig};
+use crate::parser::{Class, Parser};
+use crate::session::{Session, Severity};
+use crate::tokenizer::Tokenizer;
+
+const CLASS_NAME: &str = "Repl";
+const FUNCTION_NAME: &str = "eval";
+
+const HELP: &str = "\
+Type Jack statements (`let x = 1;`), expressions (`x * 2`) or `var` declarations.
+:vm         toggle printing the VM code of each input
+:reference  toggle VM code like the course's reference compiler's
+:reset      forget the variables and statements typed so far
+:quit       leave";
+
+#[derive(Default)]
+pub struct Repl {
+    /// Sources of the classes in scope, such as a project's.
+    classes: Vec<String>,
+    /// `var` declarations typed so far.
+    vars: Vec<String>,
+    /// Statements that ran without error, replayed before every input.
+    statements: Vec<String>,
+    /// Length of the output the replayed statements print.
+    printed: usize,
+    show_vm: bool,
+    codegen: Codegen,
+}
+
+/// What an input is wrapped as.
+enum Wrapped {
+    Statements,
+    Expression,
+}
+
+impl Repl {
+    pub fn new(classes: Vec<String>) -> Self {
+        Self {
+            classes,
+            ..Default::default()
+        }
+    }
+
+    /// Reads inputs from `input` until it ends or `:quit`, printing what each one does.
+    /// An input goes on until its braces are balanced.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "jack> ")?;
+            output.flush()?;
+
+            let Some(mut line) = lines.next().transpose()? else {
+                return Ok(());
+            };
+            while braces(&line) > 0 {
+                write!(output, "  ... ")?;
+                output.flush()?;
+                match lines.next().transpose()? {
+                    Some(more) => line = line + " " + &more,
+                    None => break,
+                }
+            }
+
+            match line.trim() {
+                "" => {}
+                ":quit" => return Ok(()),
+                ":help" => writeln!(output, "{HELP}")?,
+                ":reset" => {
+                    self.vars.clear();
+                    self.statements.clear();
+                    self.printed = 0;
+                }
+                ":vm" => {
+                    self.show_vm = !self.show_vm;
+                    writeln!(
+                        output,
+                        "VM code: {}",
+                        if self.show_vm { "on" } else { "off" }
+                    )?;
+                }
+                ":reference" => {
+                    let reference = self.codegen != Codegen::reference();
+                    self.codegen = if reference {
+                        Codegen::reference()
+                    } else {
+                        Codegen::default()
+                    };
+                    writeln!(
+                        output,
+                        "Reference VM code: {}",
+                        if reference { "on" } else { "off" }
+                    )?;
+                }
+                input => match self.eval(input) {
+                    Ok(text) => write!(output, "{text}")?,
+                    Err(e) => writeln!(output, "[error] {e}")?,
+                },
+            }
+        }
+    }
+
+    /// Evaluates one input and returns the text to print: the VM code when enabled,
+    /// what the input printed and, for an expression, its value.
+    pub fn eval(&mut self, input: &str) -> anyhow::Result<String> {
+        if input.starts_with("var ") {
+            self.vars.push(input.to_string());
+            let result = self.execute(String::new(), Wrapped::Statements);
+            if result.is_err() {
+                self.vars.pop();
+            }
+
+            return result;
+        }
+
+        // An input is an expression when it does not parse as statements
+        match self.execute(input.to_string(), Wrapped::Statements) {
+            Ok(text) => {
+                self.statements.push(input.to_string());
+
+                Ok(text)
+            }
+            Err(statement_error) => {
+                if input.ends_with(';') || input.ends_with('}') {
+                    return Err(statement_error);
+                }
+
+                self.execute(input.to_string(), Wrapped::Expression)
+            }
+        }
+    }
+
+    /// Compiles and runs the replayed statements followed by `input`.
+    fn execute(&mut self, input: String, wrapped: Wrapped) -> anyhow::Result<String> {
+        let tail = match wrapped {
+            Wrapped::Statements => format!("{input} return 0;"),
+            Wrapped::Expression => format!("return {input};"),
+        };
+        // On a single line, so errors point at line 1 of the input
+        let source = format!(
+            "class {CLASS_NAME} {{ function int {FUNCTION_NAME}() {{ {} {} {tail} }} }}",
+            self.vars.join(" "),
+            self.statements.join(" ")
+        );
+
+        let repl_class = parse(&source)?;
+        let mut session = Session::default();
+        session.add_file(CLASS_NAME);
+        Compiler::new(repl_class.iter())
+            .with_codegen(self.codegen)
+            .compile(&mut session);
+        let errors: Vec<_> = session
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        if !errors.is_empty() {
+            anyhow::bail!("{}", errors.join("\n"));
+        }
+
+        let mut classes = repl_class;
+        for source in self.classes.iter() {
+            classes.extend(parse(source)?);
+        }
+        let mut interpreter = Interpreter::new(&classes, InterpreterConfig::default())?;
+        let value = interpreter.call_function(CLASS_NAME, FUNCTION_NAME)?;
+
+        let mut text = String::new();
+        if self.show_vm {
+            let environment = Environment {
+                codegen: self.codegen,
+                ..environment(&classes[0])
+            };
+            let instructions = match wrapped {
+                Wrapped::Statements => snippet::compile_statements(&input, &environment)?,
+                Wrapped::Expression => snippet::compile_expression(&input, &environment)?,
+            };
+            for instruction in instructions.iter() {
+                text.push_str(instruction);
+                text.push('\n');
+            }
+        }
+        text.push_str(&interpreter.output()[self.printed..]);
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        match wrapped {
+            Wrapped::Statements => self.printed = interpreter.output().len(),
+            Wrapped::Expression => text.push_str(&format!("{value}\n")),
+        }
+
+        Ok(text)
+    }
+}
+
+fn parse(source: &str) -> anyhow::Result<Vec<Class<'_>>> {
+    let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+    let classes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+
+    classes
+}
+
+/// The locals of `Repl.eval`, the variables an input may use.
+fn environment(repl_class: &Class<'_>) -> Environment {
+    let var_decs = repl_class.subroutine_decs[0]
+        .subroutine_body
+        .var_decs
+        .iter();
+    let locals = var_decs.flat_map(|var_dec| {
+        let type_name = var_dec.var_type.name();
+        var_dec
+            .var_names
+            .iter()
+            .map(move |name| (name.0, type_name))
+    });
+
+    locals.enumerate().fold(
+        Environment::new(CLASS_NAME),
+        |environment, (index, (name, type_name))| {
+            environment.with_variable(SymbolKind::Local, name, type_name, index)
+        },
+    )
+}
+
+/// Opened braces not closed yet.
+fn braces(line: &str) -> i32 {
+    line.chars()
+        .map(|c| match c {
+            '{' => 1,
+            '}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::*;
+
+    #[test]
+    fn variables_keep_their_values_between_inputs() {
+        let mut repl = Repl::default();
+
+        assert_eq!(repl.eval("var int x, i;").unwrap(), "");
+        assert_eq!(repl.eval("let x = 6;").unwrap(), "");
+        assert_eq!(repl.eval("x * 7").unwrap(), "42\n");
+        assert_eq!(
+            repl.eval("while (i < 3) { do Output.printInt(i); let i = i + 1; }")
+                .unwrap(),
+            "012\n"
+        );
+        assert_eq!(repl.eval("do Output.printInt(x + i);").unwrap(), "9\n");
+        assert_eq!(repl.eval("Math.max(x, i) = 6").unwrap(), "-1\n");
+    }
+
+    #[test]
+    fn failed_inputs_are_forgotten() {
+        let mut repl = Repl::default();
+
+        assert!(repl.eval("let y = 1;").is_err());
+        assert!(repl.eval("var int x").is_err());
+        assert!(repl.eval("do Math.divide(1, 0);").is_err());
+        assert!(repl.eval("1 +").is_err());
+        assert_eq!(repl.eval("var int x;").unwrap(), "");
+        assert_eq!(repl.eval("x + 1").unwrap(), "1\n");
+    }
+
+    #[test]
+    fn reads_inputs_spanning_lines() {
+        let classes = vec!["class Twice { function int of(int n) { return n + n; } }".to_string()];
+        let mut repl = Repl::new(classes);
+        let input = "var int i;\nwhile (i < 2) {\n  let i = i + 1;\n}\n:vm\nTwice.of(i)\n:quit\n1";
+        let mut output = vec![];
+        repl.run(input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("jack>   ...   ... jack> VM code: on\n"));
+        assert!(output.contains("VM code: on\njack> push local 0\ncall Twice.of 1\n4\n"));
+        assert!(output.ends_with("4\njack> "));
+    }
+}
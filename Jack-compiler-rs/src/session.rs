@@ -0,0 +1,262 @@
+//! State shared by the stages of a run: options, the files seen so far and the
+//! diagnostics reported about them.
+
+use std::path::{Path, PathBuf};
+
+use n2t_core::build_dir::debug_enabled;
+
+use crate::compiler::Codegen;
+use crate::limits::{Limits, Usage};
+
+const DEBUG_SYMBOL_TABLE: &str = "DEBUG_SYMBOL_TABLE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Debug,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<FileId>,
+    pub message: String,
+}
+
+/// How the compiler names the labels of `if` and `while` statements.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LabelStyle {
+    /// `subroutine_N`, numbered per subroutine, so changing one leaves the others alone.
+    #[default]
+    Unique,
+    /// `IF_TRUE0`, `WHILE_EXP0`, ..., numbered per subroutine like the course's reference compiler.
+    Reference,
+}
+
+/// How the compiler lowers `*` and `/`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Arithmetic {
+    /// `Math.multiply` and `Math.divide` of the OS.
+    #[default]
+    Os,
+    /// Shift-add and shift-subtract loops of a `BitMath` class compiled with the program.
+    Bits,
+}
+
+/// How the compiler pushes `true`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Booleans {
+    /// `push constant 1` and `neg`.
+    #[default]
+    Neg,
+    /// `push constant 0` and `not`, like the course's reference compiler.
+    Reference,
+}
+
+/// When a method sets `pointer 0` to its object.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThisHandling {
+    /// On entry to every method, like the course's reference compiler.
+    #[default]
+    Always,
+    /// Only in methods that use a field, `this` or another method of the object.
+    WhenUsed,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Print debug diagnostics, such as symbol table lookups.
+    pub debug: bool,
+    /// Code generation of the compiles that do not choose their own.
+    pub codegen: Codegen,
+    pub limits: Limits,
+    /// Report exceeded limits as warnings.
+    pub ignore_limits: bool,
+    /// Reject the constructs compiled to OS calls.
+    pub no_os: bool,
+    /// Compile a library, linked into other programs: no entry point is expected.
+    pub library: bool,
+}
+
+impl Options {
+    pub fn from_env() -> Self {
+        Self {
+            debug: debug_enabled(DEBUG_SYMBOL_TABLE),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Session {
+    pub options: Options,
+    /// What the classes compiled so far use of the limited resources.
+    pub usage: Usage,
+    files: Vec<PathBuf>,
+    current: Option<FileId>,
+    diagnostics: Vec<Diagnostic>,
+    printed: usize,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Registers a file; diagnostics reported from now on are about it.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> FileId {
+        let id = FileId(self.files.len());
+        self.files.push(path.as_ref().to_path_buf());
+        self.current = Some(id);
+
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> &Path {
+        &self.files[id.0]
+    }
+
+    pub fn report<S: Into<String>>(&mut self, severity: Severity, message: S) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            file: self.current,
+            message: message.into(),
+        });
+    }
+
+    pub fn debug<S: Into<String>>(&mut self, message: S) {
+        self.report(Severity::Debug, message);
+    }
+
+    pub fn error<S: Into<String>>(&mut self, message: S) {
+        self.report(Severity::Error, message);
+    }
+
+    fn limit_severity(&self) -> Severity {
+        if self.options.ignore_limits {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+
+    /// Reports a string constant of the current file at `line`:`column` that is too
+    /// long for the heap.
+    pub fn check_string(&mut self, line: usize, column: usize, length: usize) {
+        if let Some(limit) = self.options.limits.check_string(length) {
+            self.report(
+                self.limit_severity(),
+                format!("[line {line}:{column}] {limit}"),
+            );
+        }
+    }
+
+    /// Reports every limit the classes compiled so far go over, about no file in particular.
+    pub fn check_limits(&mut self) {
+        let severity = self.limit_severity();
+
+        for limit in self.options.limits.check(&self.usage) {
+            self.diagnostics.push(Diagnostic {
+                severity,
+                file: None,
+                message: limit.to_string(),
+            });
+        }
+        // Only a warning: the OS's own use of the stack is left out
+        if self.options.library {
+            return;
+        }
+        if let Some(risk) = self.options.limits.check_stack(&self.usage) {
+            self.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                file: None,
+                message: risk.to_string(),
+            });
+        }
+    }
+
+    /// Warns about a program without `Main.main`, which the OS's `Sys.init` calls to
+    /// start it, unless it has a `Sys.init` of its own or is a library.
+    pub fn check_entry_point(&mut self) {
+        let call_graph = &self.usage.call_graph;
+        if self.options.library
+            || call_graph.contains("Main.main")
+            || call_graph.contains("Sys.init")
+        {
+            return;
+        }
+
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            file: None,
+            message: "No Main.main, which the OS's Sys.init calls to start the program; \
+                      compile a library with --lib"
+                .to_string(),
+        });
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Prints the diagnostics reported since the last call. Debug ones only with
+    /// [`Options::debug`].
+    pub fn flush(&mut self) {
+        for diagnostic in self.diagnostics()[self.printed..].iter() {
+            if diagnostic.severity > Severity::Debug || self.options.debug {
+                println!("{}", self.format(diagnostic));
+            }
+        }
+        self.printed = self.diagnostics.len();
+    }
+
+    fn format(&self, diagnostic: &Diagnostic) -> String {
+        let severity = match diagnostic.severity {
+            Severity::Debug => "debug",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        match diagnostic.file {
+            Some(file) => format!(
+                "[{severity}] {}: {}",
+                self.file(file).display(),
+                diagnostic.message
+            ),
+            None => format!("[{severity}] {}", diagnostic.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_belong_to_the_current_file() {
+        let mut session = Session::default();
+        session.debug("before any file");
+        let main = session.add_file("Main.jack");
+        session.error("bad");
+
+        let diagnostics = session.diagnostics();
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[1].file, Some(main));
+        assert_eq!(session.file(main), Path::new("Main.jack"));
+        assert!(session.has_errors());
+        assert_eq!(session.format(&diagnostics[1]), "[error] Main.jack: bad");
+    }
+}
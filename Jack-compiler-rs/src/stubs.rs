@@ -0,0 +1,109 @@
+//! `--gen-stubs`: VM files for the OS classes a project calls without providing them,
+//! so that a partly written program still runs on the VM translator and a CPU
+//! emulator until it calls one of them. Every stub function stops the program in a
+//! loop named after it, such as `Math.sqrt$NOT_IMPLEMENTED`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::call_graph::CallGraph;
+use crate::no_os::OS_CLASSES;
+
+/// First line of every stub, telling it from the `.vm` file of a real OS class.
+pub const HEADER: &str = "// Stub written by --gen-stubs: every function loops forever";
+
+/// The subroutines called of every OS class the program does not provide, by class.
+pub fn missing<'a>(
+    call_graph: &'a CallGraph,
+    project_classes: &[String],
+) -> BTreeMap<&'a str, BTreeSet<&'a str>> {
+    let mut missing: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for callee in call_graph.callees() {
+        let Some((class, subroutine)) = callee.split_once('.') else {
+            continue;
+        };
+        if OS_CLASSES.contains(&class) && !project_classes.iter().any(|name| name == class) {
+            missing.entry(class).or_default().insert(subroutine);
+        }
+    }
+
+    missing
+}
+
+/// VM code of `class` with a trap for each of `subroutines`. The trap never returns,
+/// so the number of arguments does not matter.
+pub fn stub(class: &str, subroutines: &BTreeSet<&str>) -> Vec<String> {
+    let mut instructions = vec![HEADER.to_string()];
+    for subroutine in subroutines.iter() {
+        let trap = format!("{class}.{subroutine}$NOT_IMPLEMENTED");
+        instructions.push(format!("function {class}.{subroutine} 0"));
+        instructions.push(format!("label {trap}"));
+        instructions.push(format!("goto {trap}"));
+    }
+
+    instructions
+}
+
+/// Whether a stub may be written to `path`: nothing is there, or a stub written before.
+pub fn may_write(path: &Path) -> anyhow::Result<bool> {
+    if !path.exists() {
+        return Ok(true);
+    }
+
+    Ok(read_to_string(path)?.starts_with(HEADER))
+}
+
+#[cfg(test)]
+mod stubs_tests {
+    use super::*;
+    use crate::{
+        compiler::Compiler, metrics::class_metrics, parser::Parser, session::Session,
+        tokenizer::Tokenizer,
+    };
+
+    #[test]
+    fn stubs_the_os_classes_called() {
+        let source = "class Main {
+            function void main() {
+                var String s;
+                let s = \"hi\";
+                do Output.printString(s);
+                do Output.println();
+                do Main.f(Math.sqrt(16));
+                do Screen.clearScreen();
+                return;
+            }
+            function void f(int x) {
+                return;
+            }
+        }";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let classes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let classes = classes.unwrap();
+        let instructions = Compiler::new(classes.iter()).compile(&mut Session::default());
+        let mut call_graph = CallGraph::default();
+        call_graph.add(&class_metrics(&classes[0], &instructions));
+
+        // The project's own Screen is not stubbed
+        let missing = missing(&call_graph, &["Main".to_string(), "Screen".to_string()]);
+        assert_eq!(
+            missing.keys().copied().collect::<Vec<_>>(),
+            ["Math", "Output", "String"]
+        );
+        assert_eq!(
+            missing["Output"].iter().copied().collect::<Vec<_>>(),
+            ["printString", "println"]
+        );
+
+        assert_eq!(
+            stub("Math", &missing["Math"]),
+            [
+                HEADER,
+                "function Math.sqrt 0",
+                "label Math.sqrt$NOT_IMPLEMENTED",
+                "goto Math.sqrt$NOT_IMPLEMENTED"
+            ]
+        );
+    }
+}
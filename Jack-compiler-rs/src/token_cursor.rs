@@ -0,0 +1,65 @@
+//! Token stream with a fixed two-token lookahead, all the Jack grammar needs.
+
+use crate::tokenizer::Token;
+
+pub struct TokenCursor<'de, I: Iterator<Item = Token<'de>>> {
+    tokens: I,
+    lookahead: [Option<Token<'de>>; 2],
+}
+
+impl<'de, I> TokenCursor<'de, I>
+where
+    I: Iterator<Item = Token<'de>>,
+{
+    pub fn new(mut tokens: I) -> Self {
+        let lookahead = [tokens.next(), tokens.next()];
+
+        Self { tokens, lookahead }
+    }
+
+    /// The next token, left in place.
+    pub fn peek(&self) -> Option<&Token<'de>> {
+        self.lookahead[0].as_ref()
+    }
+
+    /// The token after the next one, left in place.
+    pub fn peek_second(&self) -> Option<&Token<'de>> {
+        self.lookahead[1].as_ref()
+    }
+}
+
+impl<'de, I> Iterator for TokenCursor<'de, I>
+where
+    I: Iterator<Item = Token<'de>>,
+{
+    type Item = Token<'de>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.lookahead[0].take();
+        self.lookahead[0] = self.lookahead[1].take();
+        self.lookahead[1] = self.tokens.next();
+
+        token
+    }
+}
+
+#[cfg(test)]
+mod token_cursor_tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn peeks_two_tokens_ahead() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new("a . b").collect();
+        let mut cursor = TokenCursor::new(tokens.unwrap().into_iter());
+        let lexeme = |token: Option<&Token>| token.map(|token| format!("{:?}", token.token_type));
+
+        let (first, second) = (lexeme(cursor.peek()), lexeme(cursor.peek_second()));
+        assert_eq!(lexeme(cursor.next().as_ref()), first);
+        assert_eq!(lexeme(cursor.peek()), second);
+        assert_eq!(lexeme(cursor.next().as_ref()), second);
+
+        while cursor.next().is_some() {}
+        assert!(cursor.peek().is_none() && cursor.peek_second().is_none());
+    }
+}
@@ -1,5 +1,6 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, ops::{Range, RangeInclusive}};
 
+use n2t_core::diagnostic::SyntaxError;
 use once_cell::sync::Lazy;
 
 #[rustfmt::skip] 
@@ -60,6 +61,13 @@ pub(crate) static SYMBOLS: Lazy<HashMap<char, Symbol>> = Lazy::new(|| {
 
 static SYMBOL_LIST: Lazy<Vec<char>> = Lazy::new(|| SYMBOLS.keys().cloned().collect());
 
+/// Largest integer constant of the Jack language.
+const MAX_INTEGER: u16 = 32767;
+
+/// Characters a string constant may hold: the printable part of the Hack character
+/// set, which `Output` can draw.
+const HACK_CHARACTERS: RangeInclusive<char> = ' '..='~';
+
 #[derive(Debug, Clone)]
 pub enum Keyword {
     Class,
@@ -127,40 +135,81 @@ pub enum TokenType<'de> {
     Eof,
 }
 
+impl TokenType<'_> {
+    /// Category of the token, named like the course's XML elements.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenType::Keyword(_) => "keyword",
+            TokenType::Symbol(_) => "symbol",
+            TokenType::Constant(Constant::Integer(_)) => "integerConstant",
+            TokenType::Constant(Constant::String(_)) => "stringConstant",
+            TokenType::Identifier(_) => "identifier",
+            TokenType::Eof => "eof",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token<'de> {
     pub token_type: TokenType<'de>,
     pub lexeme: Cow<'de, str>,
-    pub _line: usize,
+    pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+    /// Byte offsets in the source, quotes of a string constant included.
+    pub span: Range<usize>,
 }
 
 impl<'de> Token<'de> {
-    pub fn new(token_type: TokenType<'de>, lexeme: impl Into<Cow<'de, str>>, line: usize) -> Self {
+    /// A token whose first character is at `(line, column, byte offset)`.
+    pub fn at(
+        token_type: TokenType<'de>,
+        lexeme: impl Into<Cow<'de, str>>,
+        (line, column, offset): (usize, usize, usize),
+    ) -> Self {
+        let lexeme = lexeme.into();
+
         Token {
             token_type,
-            lexeme: lexeme.into(),
-            _line: line,
+            span: offset..offset + lexeme.len(),
+            lexeme,
+            line,
+            column,
         }
     }
 }
 
 pub struct Tokenizer<'de> {
+    source: &'de str,
     rest: &'de str,
     current: usize,
     line: usize,
+    /// Column of the next character.
+    column: usize,
+    /// Bytes consumed so far.
+    offset: usize,
     eof: bool,
 }
 
 impl<'de> Tokenizer<'de> {
     pub fn new(source: &'de str) -> Self {
         Self {
+            source,
             rest: source,
             current: 0,
             line: 1,
+            column: 1,
+            offset: 0,
             eof: false,
         }
     }
 
+    /// Error at `line`:`column` of the source, followed by the line with carets
+    /// under the `width` characters from the column.
+    fn error_at(&self, line: usize, column: usize, width: usize, message: &str) -> anyhow::Error {
+        anyhow::anyhow!(SyntaxError::new(line, column, width, message).render(self.source))
+    }
+
     fn peek_rest_at(&self, pos: usize) -> Option<char> {
         self.rest.chars().nth(pos)
     }
@@ -173,7 +222,9 @@ impl<'de> Tokenizer<'de> {
         for _ in 0..n {
             let c = chars.next().unwrap();
             bytes_n += c.len_utf8();
+            self.column = if c == '\n' { 1 } else { self.column + 1 };
         }
+        self.offset += bytes_n;
 
         let lexeme = &self.rest[0..bytes_n];
         self.rest = &self.rest[bytes_n..];
@@ -203,9 +254,9 @@ impl<'de> Tokenizer<'de> {
         fn token<'de>(
             token_type: TokenType<'de>,
             lexeme: &'de str,
-            line: usize,
+            position: (usize, usize, usize),
         ) -> Option<anyhow::Result<Token<'de>>> {
-            Some(Ok(Token::<'de>::new(token_type, lexeme, line)))
+            Some(Ok(Token::<'de>::at(token_type, lexeme, position)))
         }
 
         'scan_loop: loop {
@@ -215,6 +266,7 @@ impl<'de> Tokenizer<'de> {
                 return None;
             };
 
+            let position = (self.line, self.column, self.offset);
             match cur {
                 // Meaningless characters.
                 ' ' | '\r' | '\t' => {
@@ -246,6 +298,9 @@ impl<'de> Tokenizer<'de> {
                             self.advance_n(2);
                             break;
                         } else {
+                            if self.peek_rest_at(0) == Some('\n') {
+                                self.line += 1;
+                            }
                             self.advance_n(1);
                         }
                     }
@@ -253,41 +308,70 @@ impl<'de> Tokenizer<'de> {
                 // Literals.
                 '0'..='9' => {
                     let mut cur_len = 0;
-
-                    fn token_number<'de>(
-                        lexeme: &'de str,
-                        line: usize,
-                    ) -> Option<anyhow::Result<Token<'de>>> {
-                        if let Ok(number) = lexeme.parse::<u16>() {
-                            token(TokenType::Constant(Constant::Integer(number)), lexeme, line)
-                        } else {
-                            Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Could not parse a number: {lexeme}"))))
-                        }                        
-                    }
+                    let (line, column, _) = position;
 
                     loop {
                         match self.peek_rest_at(cur_len) {
                             Some(c) if c.is_digit(10) => {
                                 cur_len += 1;
                             }
-                            _ => return token_number(self.advance_n(cur_len), self.line),
+                            // `123abc`: a number running into an identifier
+                            Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {
+                                while self.peek_rest_at(cur_len).is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$') {
+                                    cur_len += 1;
+                                }
+                                let lexeme = self.advance_n(cur_len);
+                                let message = format!("`{lexeme}` is not a number, and identifiers cannot start with a digit");
+
+                                return Some(Err(self.error_at(line, column, lexeme.chars().count(), &message)));
+                            }
+                            _ => {
+                                let lexeme = self.advance_n(cur_len);
+                                return match lexeme.parse::<u16>() {
+                                    Ok(number) if number <= MAX_INTEGER => token(TokenType::Constant(Constant::Integer(number)), lexeme, position),
+                                    _ => {
+                                        let message = format!("Integer constant `{lexeme}` is out of range 0..={MAX_INTEGER}");
+
+                                        Some(Err(self.error_at(line, column, lexeme.len(), &message)))
+                                    }
+                                };
+                            }
                         }
                     }
                 },
                 '"' => {
                     let _ = self.advance_n(1);
+                    let (line, column, _) = position;
 
                     let mut cur_len = 0;
                     loop {
                         match self.peek_rest_at(cur_len) {
                             Some('"') => {
-                                let lexeme = self.advance_n(cur_len);
+                                let lexeme = if cur_len > 0 { self.advance_n(cur_len) } else { "" };
                                 let _ = self.advance_n(1);
 
-                                return token(TokenType::Constant(Constant::String(Cow::Borrowed(lexeme))), lexeme, self.line);
-                                
+                                let mut string = Token::at(TokenType::Constant(Constant::String(Cow::Borrowed(lexeme))), lexeme, position);
+                                string.span.end = self.offset;
+
+                                return Some(Ok(string));
+                            }
+                            None | Some('\n') => {
+                                let message = "Unterminated string constant: it has no closing `\"` on its line";
+                                let error = self.error_at(line, column, 1, message);
+                                // The rest of the line would be read as code
+                                while !matches!(self.peek_rest_at(0), None | Some('\n')) {
+                                    let _ = self.advance_n(1);
+                                }
+
+                                return Some(Err(error));
+                            }
+                            Some(c) if !HACK_CHARACTERS.contains(&c) => {
+                                let message = format!("`{c}` is not in the Hack character set, the printable ASCII characters from ` ` to `~`");
+                                let error = self.error_at(line, column + 1 + cur_len, 1, &message);
+                                let _ = self.advance_n(cur_len + 1);
+
+                                return Some(Err(error));
                             }
-                            None => panic!(),
                             _ => {
                                 cur_len += 1;
                             }
@@ -298,7 +382,7 @@ impl<'de> Tokenizer<'de> {
                     let lexeme = self.advance_n(1);
                     let x: TokenType<'static> = self.get_symbol(&c);
 
-                    return token(x, lexeme, self.line);
+                    return token(x, lexeme, position);
                 },
                 'a'..='z' | 'A'..='Z' | '-' | '_' | '$' => {
                     let mut cur_len = 0;
@@ -312,16 +396,17 @@ impl<'de> Tokenizer<'de> {
                             _ => {
                                 let lexeme = self.advance_n(cur_len);
 
-                                return token(self.get_keyword_or_identifier(lexeme), lexeme, self.line);
+                                return token(self.get_keyword_or_identifier(lexeme), lexeme, position);
                             }
                         }
                     }
                 },
                 lexeme => {
                     let _ = self.advance_n(1);
-                    let line = self.line;
+                    let (line, column, _) = position;
+                    let message = format!("Unexpected character `{lexeme}`");
 
-                    return Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Unexpected character: {lexeme}"))));
+                    return Some(Err(self.error_at(line, column, 1, &message)));
                 }
             }
         }
@@ -339,10 +424,95 @@ impl<'de> Iterator for Tokenizer<'de> {
             if !self.eof {
                 self.eof = true;
 
-                Some(Ok(Token::new(TokenType::Eof, "eof", self.line)))
+                Some(Ok(Token::at(TokenType::Eof, "eof", (self.line, self.column, self.offset))))
             } else {
                 None
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::*;
+
+    #[test]
+    fn integer_constants_fit_in_15_bits() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new("let x = 32767;").collect();
+        assert!(tokens.is_ok());
+
+        for literal in ["32768", "99999999"] {
+            let source = format!("let x = 1;\nlet y = {literal};");
+            let tokens: Result<Vec<_>, _> = Tokenizer::new(&source).collect();
+
+            assert_eq!(
+                tokens.unwrap_err().to_string(),
+                format!(
+                    "[line 2:9] Error: Integer constant `{literal}` is out of range 0..=32767\n \
+                     2 | let y = {literal};\n   |         {}",
+                    "^".repeat(literal.len())
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn identifiers_cannot_start_with_a_digit() {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new("let x = 1;\nlet y = 123abc;").collect();
+
+        assert_eq!(
+            tokens.unwrap_err().to_string(),
+            "[line 2:9] Error: `123abc` is not a number, and identifiers cannot start with a digit\n \
+             2 | let y = 123abc;\n   |         ^^^^^^"
+        );
+    }
+
+    #[test]
+    fn string_constants_hold_printable_ascii_on_one_line() {
+        let error = |source| {
+            let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+            tokens.unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error("let s = \"\";\nlet s = \"caf\u{e9}\";"),
+            "[line 2:13] Error: `\u{e9}` is not in the Hack character set, the printable ASCII characters from ` ` to `~`\n \
+             2 | let s = \"caf\u{e9}\";\n   |             ^"
+        );
+        assert_eq!(
+            error("let s = \"a\tb\";").lines().next().unwrap(),
+            "[line 1:11] Error: `\t` is not in the Hack character set, the printable ASCII characters from ` ` to `~`"
+        );
+        assert_eq!(
+            error("let s = \"oops;\nreturn;"),
+            "[line 1:9] Error: Unterminated string constant: it has no closing `\"` on its line\n \
+             1 | let s = \"oops;\n   |         ^"
+        );
+    }
+
+    #[test]
+    fn tokens_know_their_position() {
+        let source = "/* a\n b */ do\n  Output.printString(/*é*/\"!\");";
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+        let positions: Vec<_> = tokens
+            .unwrap()
+            .iter()
+            .map(|token| (token.token_type.kind(), token.line, token.column, token.span.clone()))
+            .collect();
+
+        assert_eq!(
+            positions,
+            [
+                ("keyword", 2, 7, 11..13),
+                ("identifier", 3, 3, 16..22),
+                ("symbol", 3, 9, 22..23),
+                ("identifier", 3, 10, 23..34),
+                ("symbol", 3, 21, 34..35),
+                ("stringConstant", 3, 27, 41..44),
+                ("symbol", 3, 30, 44..45),
+                ("symbol", 3, 31, 45..46),
+                ("eof", 3, 32, 46..49),
+            ]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use once_cell::sync::Lazy;
-use serde::{Serialize, Serializer, ser::SerializeStruct};
+use serde::{Serialize, Serializer};
 use std::collections::HashMap;
 
 use crate::{
@@ -31,36 +31,120 @@ impl Serialize for Symbol {
     {
         serializer.serialize_char(
             SYMBOL_CHARS
-                .get(&self)
+                .get(self)
                 .expect("Always contains an enum key")
                 .to_owned(),
         )
     }
 }
 
-impl<'de> Serialize for Tokens<'de> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
+impl<'de> Tokens<'de> {
+    /// Token file in the course's `XxxT.xml` format: one padded element per line,
+    /// with `<`, `>`, `&` and `"` escaped.
+    pub fn to_xml(&self) -> String {
         use crate::tokenizer::Constant;
         use crate::tokenizer::TokenType;
 
-        let mut s = serializer.serialize_struct("tokens", self.tokens.len())?;
-
+        let mut xml = String::from("<tokens>\n");
         for token in &self.tokens {
-            match &token.token_type {
-                TokenType::Keyword(keyword_type) => s.serialize_field("keyword", keyword_type)?,
-                TokenType::Symbol(symbol_type) => s.serialize_field("symbol", symbol_type)?,
-                TokenType::Constant(constant_type) => match constant_type {
-                    Constant::String(c) => s.serialize_field("stringConstant", c)?,
-                    Constant::Integer(i) => s.serialize_field("integerConstant", i)?,
-                },
-                TokenType::Identifier(_) => s.serialize_field("identifier", &token.lexeme)?,
-                TokenType::Eof => {}
-            }
+            let (tag, value) = match &token.token_type {
+                TokenType::Keyword(keyword) => ("keyword", format!("{:?}", keyword).to_lowercase()),
+                TokenType::Symbol(symbol) => (
+                    "symbol",
+                    SYMBOL_CHARS
+                        .get(symbol)
+                        .expect("Always contains an enum key")
+                        .to_string(),
+                ),
+                TokenType::Constant(Constant::String(c)) => ("stringConstant", c.to_string()),
+                TokenType::Constant(Constant::Integer(i)) => ("integerConstant", i.to_string()),
+                TokenType::Identifier(_) => ("identifier", token.lexeme.to_string()),
+                TokenType::Eof => continue,
+            };
+            xml.push_str(&format!("<{tag}> {} </{tag}>\n", escape(&value)));
         }
+        xml.push_str("</tokens>\n");
+
+        xml
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tokenizer_xml_tests {
+    use crate::{Tokens, tokenizer::Tokenizer};
+
+    fn to_xml(source: &str) -> String {
+        let tokens: Result<Vec<_>, _> = Tokenizer::new(source).collect();
+
+        Tokens {
+            tokens: tokens.unwrap(),
+        }
+        .to_xml()
+    }
+
+    #[test]
+    fn course_format() {
+        let xml = to_xml(
+            "class Main {
+                function void main() {
+                    let a[i] = x < 3;
+                    do Output.printString(\"a & b\");
+                    return;
+                }
+            }",
+        );
+
+        let expected = "\
+<tokens>
+<keyword> class </keyword>
+<identifier> Main </identifier>
+<symbol> { </symbol>
+<keyword> function </keyword>
+<keyword> void </keyword>
+<identifier> main </identifier>
+<symbol> ( </symbol>
+<symbol> ) </symbol>
+<symbol> { </symbol>
+<keyword> let </keyword>
+<identifier> a </identifier>
+<symbol> [ </symbol>
+<identifier> i </identifier>
+<symbol> ] </symbol>
+<symbol> = </symbol>
+<identifier> x </identifier>
+<symbol> &lt; </symbol>
+<integerConstant> 3 </integerConstant>
+<symbol> ; </symbol>
+<keyword> do </keyword>
+<identifier> Output </identifier>
+<symbol> . </symbol>
+<identifier> printString </identifier>
+<symbol> ( </symbol>
+<stringConstant> a &amp; b </stringConstant>
+<symbol> ) </symbol>
+<symbol> ; </symbol>
+<keyword> return </keyword>
+<symbol> ; </symbol>
+<symbol> } </symbol>
+<symbol> } </symbol>
+</tokens>
+";
+        assert_eq!(xml, expected);
+    }
+
+    #[test]
+    fn escapes_every_special_symbol() {
+        let xml = to_xml("x < y > z & w");
 
-        s.end()
+        assert!(xml.contains("<symbol> &lt; </symbol>"));
+        assert!(xml.contains("<symbol> &gt; </symbol>"));
+        assert!(xml.contains("<symbol> &amp; </symbol>"));
     }
 }
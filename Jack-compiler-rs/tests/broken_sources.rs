@@ -0,0 +1,29 @@
+//! The compiler run on sources with syntax errors: it must fail, name the error and
+//! write no `.vm` file, rather than compile what it could parse.
+
+use std::fs::{copy, create_dir_all, remove_dir_all};
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn a_syntax_error_fails_the_build() {
+    let dir = std::env::temp_dir().join(format!("jack-broken-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    let source = dir.join("Main.jack");
+    copy(Path::new("tests/broken/Main.jack"), &source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+        .arg(&source)
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("[line 4] Error: Expected an expression but got `)`"),
+        "{stderr}"
+    );
+    assert!(!dir.join("Main.vm").exists());
+
+    remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+//! `--emit xml` against the course's compare files of project 10: every `.xml` file
+//! of `tests/course/<project>/` must be written the same for `input/<project>/`,
+//! line endings aside, as the course ships them with CRLF.
+#![cfg(feature = "xml")]
+
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, remove_dir_all};
+use std::path::Path;
+use std::process::Command;
+
+fn compare(project: &str) {
+    let dir = std::env::temp_dir().join(format!("jack-xml-{project}-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    for entry in read_dir(Path::new("input").join(project)).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "jack") {
+            copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+        .arg(&dir)
+        .arg("--emit")
+        .arg("xml")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for entry in read_dir(Path::new("tests/course").join(project)).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap();
+        let expected = read_to_string(&path).unwrap().replace("\r\n", "\n");
+        let actual = read_to_string(dir.join(name)).unwrap();
+        let difference = expected
+            .lines()
+            .zip(actual.lines())
+            .position(|(e, a)| e != a)
+            .map_or(String::new(), |i| format!(" from line {}", i + 1));
+        assert!(
+            actual == expected,
+            "{project}/{} differs{difference}",
+            name.display()
+        );
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn array_test_matches_the_course() {
+    compare("ArrayTest");
+}
+
+#[test]
+fn expression_less_square_matches_the_course() {
+    compare("ExpressionLessSquare");
+}
@@ -0,0 +1,96 @@
+//! The output must be the same bytes wherever a project is compiled, so that it can be
+//! graded by comparing files. Every project of `input/` is compiled as it is, then
+//! again in a new process (with new hash seeds), and from a copy with Windows line
+//! endings whose files were written in reverse order, as checkouts and file systems
+//! on other platforms leave them.
+
+use std::fs::{copy, create_dir_all, read, read_dir, read_to_string, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The `.jack` files of `project`, by name.
+fn sources(project: &Path) -> Vec<PathBuf> {
+    let mut sources: Vec<_> = read_dir(project)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    sources.sort();
+
+    sources
+}
+
+/// The `.vm` files and the manifest compiled from `dir`, by name.
+fn compile(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let run = Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert!(
+        run.status.success(),
+        "Could not compile {}:\n{}",
+        dir.display(),
+        String::from_utf8_lossy(&run.stderr)
+    );
+
+    let mut outputs: Vec<_> = read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "vm") || path.ends_with("manifest.json")
+        })
+        .map(|path| {
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (file_name, read(&path).unwrap())
+        })
+        .collect();
+    outputs.sort();
+
+    outputs
+}
+
+#[test]
+fn output_does_not_depend_on_the_platform() {
+    let temp_dir = std::env::temp_dir().join(format!("jack-determinism-{}", std::process::id()));
+
+    let mut projects: Vec<_> = read_dir("input")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    projects.sort();
+
+    for project in projects {
+        let name = project.file_name().unwrap().to_string_lossy().into_owned();
+        let (unix, windows) = (
+            temp_dir.join("unix").join(&name),
+            temp_dir.join("windows").join(&name),
+        );
+        create_dir_all(&unix).unwrap();
+        create_dir_all(&windows).unwrap();
+
+        for source in sources(&project).iter().rev() {
+            let file_name = source.file_name().unwrap();
+            copy(source, unix.join(file_name)).unwrap();
+            let crlf = read_to_string(source)
+                .unwrap()
+                .replace("\r\n", "\n")
+                .replace('\n', "\r\n");
+            write(windows.join(file_name), crlf).unwrap();
+        }
+
+        let expected = compile(&unix);
+        assert!(
+            expected
+                .iter()
+                .any(|(file_name, _)| file_name.ends_with(".vm"))
+        );
+        assert_eq!(compile(&unix), expected, "{name} compiled again");
+        assert_eq!(compile(&windows), expected, "{name} with CRLF line endings");
+
+        let manifest = read_to_string(unix.join("manifest.json")).unwrap();
+        assert!(!manifest.contains('\\'), "{manifest}");
+    }
+
+    remove_dir_all(&temp_dir).unwrap();
+}
@@ -0,0 +1,303 @@
+//! The whole toolchain on `tests/game`, a square moved by the arrow keys: compiled,
+//! translated by the VM translator, assembled and run by the assembler's headless
+//! CPU with the presses of `keys.txt`, then checked by what is left on the screen.
+//! The other two tools are built from their directories next to this one.
+
+use std::fs::{File, copy, create_dir_all, read_dir, read_to_string, remove_dir_all, write};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// The square after right, right, down: at column 2 and row 1, 16 black words from
+/// RAM[16898]. CRC-32 of the screen words, high byte first.
+const SCREEN_CRC: &str = "9c50b879";
+
+/// Builds the binary of the crate in `dir`, next to this one.
+fn tool(dir: &str, binary: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(dir);
+    let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .arg("build")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(dir.join("target"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "Could not build {}", dir.display());
+
+    dir.join("target/debug").join(binary)
+}
+
+fn succeed(command: &mut Command) -> String {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = command.output().unwrap();
+    let stdout = String::from_utf8_lossy(&stdout).into_owned();
+    assert!(
+        status.success(),
+        "{command:?} failed:\n{stdout}{}",
+        String::from_utf8_lossy(&stderr)
+    );
+
+    stdout
+}
+
+#[test]
+fn the_game_draws_the_moved_square() {
+    play("jack-game", &[]);
+}
+
+#[test]
+fn packing_the_locals_keeps_the_game_working() {
+    play("jack-game-packed", &["--pack-locals"]);
+}
+
+/// The reference compiler's labels repeat in every subroutine, `WHILE_EXP0` in `main`
+/// and `draw`, which the translator keeps apart.
+#[test]
+fn reference_labels_keep_the_game_working() {
+    play("jack-game-reference", &["--labels", "reference"]);
+}
+
+/// Runs the game compiled with `compiler_args` in a temp directory named after `name`.
+fn play(name: &str, compiler_args: &[&str]) {
+    let translator = tool("Jack-vm-translator-rs", "VMTranslator");
+    let assembler = tool("Hack-assembler-rs", "hack-assembler-rs");
+
+    let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    for entry in read_dir("tests/game").unwrap() {
+        let path = entry.unwrap().path();
+        copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+
+    succeed(
+        Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg(&dir)
+            .arg("--no-os")
+            .args(compiler_args),
+    );
+    // `Sys.init` first, as the program starts at ROM[0]
+    succeed(
+        Command::new(translator)
+            .arg(dir.join("Sys.vm"))
+            .arg(dir.join("Main.vm"))
+            .arg("-o")
+            .arg(dir.join("Game.asm")),
+    );
+    let run = succeed(
+        Command::new(assembler)
+            .arg(dir.join("Game.asm"))
+            .arg("-o")
+            .arg(dir.join("Game.hack"))
+            .arg("--run")
+            .arg("--ram")
+            .arg(dir.join("boot.ram"))
+            .arg("--keys")
+            .arg(dir.join("keys.txt"))
+            .arg("--max-steps")
+            .arg("200000"),
+    );
+
+    assert!(
+        run.contains(&format!("[~] Screen CRC-32: {SCREEN_CRC}")),
+        "{run}"
+    );
+
+    remove_dir_all(&dir).unwrap();
+}
+
+/// Every press stops at `Main.move`, where the expressions are evaluated with its
+/// argument and the statics, the call to `Main.draw` on a fork that leaves the screen
+/// of the run alone.
+#[test]
+fn evaluates_expressions_at_the_breakpoints() {
+    let translator = tool("Jack-vm-translator-rs", "VMTranslator");
+    let assembler = tool("Hack-assembler-rs", "hack-assembler-rs");
+
+    let dir = std::env::temp_dir().join(format!("jack-eval-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    for entry in read_dir("tests/game").unwrap() {
+        let path = entry.unwrap().path();
+        copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+
+    succeed(
+        Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg(&dir)
+            .arg("--no-os"),
+    );
+    succeed(
+        Command::new(&translator)
+            .arg(&dir)
+            .arg("-o")
+            .arg(dir.join("Game.asm"))
+            .arg("--functions")
+            .arg(dir.join("functions.json")),
+    );
+    let run = succeed(
+        Command::new(assembler)
+            .arg(dir.join("Game.asm"))
+            .arg("-o")
+            .arg(dir.join("Game.hack"))
+            .arg("--run")
+            .arg("--ram")
+            .arg(dir.join("boot.ram"))
+            .arg("--keys")
+            .arg(dir.join("keys.txt"))
+            .arg("--max-steps")
+            .arg("200000")
+            .arg("--functions")
+            .arg(dir.join("functions.json"))
+            .arg("--break")
+            .arg("Main.move")
+            .arg("--eval")
+            .arg("(key = 132) & (column < 31)")
+            .arg("--eval")
+            .arg("Main.draw(-1) + column")
+            .arg("--eval")
+            .arg("column * 2")
+            .arg("--jack")
+            .arg(&dir)
+            .arg("--compiler")
+            .arg(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg("--translator")
+            .arg(&translator),
+    );
+
+    let evaluated: Vec<_> = run
+        .lines()
+        .filter_map(|line| line.strip_prefix("[~]   "))
+        .filter(|line| !line.starts_with("Call stack") && !line.starts_with("  #"))
+        .take(3)
+        .collect();
+    assert_eq!(
+        evaluated,
+        [
+            "(key = 132) & (column < 31) = -1",
+            "Main.draw(-1) + column = 0",
+            "column * 2: `Math.multiply` is not a function of the program",
+        ],
+        "{run}"
+    );
+    assert!(run.contains("[~]   Main.draw(-1) + column = 2"), "{run}");
+    assert!(
+        run.contains(&format!("[~] Screen CRC-32: {SCREEN_CRC}")),
+        "{run}"
+    );
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn grades_the_submissions() {
+    tool("Jack-vm-translator-rs", "VMTranslator");
+    let assembler = tool("Hack-assembler-rs", "hack-assembler-rs");
+
+    let dir = std::env::temp_dir().join(format!("jack-grade-{}", std::process::id()));
+    for student in ["good", "broken"] {
+        create_dir_all(dir.join(student)).unwrap();
+        for name in ["Main.jack", "Sys.jack"] {
+            copy(
+                Path::new("tests/game").join(name),
+                dir.join(student).join(name),
+            )
+            .unwrap();
+        }
+    }
+    write(
+        dir.join("broken/Main.jack"),
+        "class Main {\n    function void main() {\n        let = 1;\n    }\n}\n",
+    )
+    .unwrap();
+    // Submitted as an archive, with the project in a directory of its own
+    let mut writer = ZipWriter::new(File::create(dir.join("zipped.zip")).unwrap());
+    for name in ["Main.jack", "Sys.jack"] {
+        writer
+            .start_file(format!("zipped/game/{name}"), SimpleFileOptions::default())
+            .unwrap();
+        let source = read_to_string(Path::new("tests/game").join(name)).unwrap();
+        writer.write_all(source.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+    // The square drawn at the corner while the game waits for a key
+    write(
+        dir.join("rubric.json"),
+        r#"{
+            "compiler_args": ["--no-os"],
+            "max_steps": 20000,
+            "tests": [{
+                "name": "draws the square",
+                "ram": { "0": 256, "1": 256, "2": 256 },
+                "expect": { "16384": -1, "16385": 0 },
+                "halt": false
+            }]
+        }"#,
+    )
+    .unwrap();
+
+    let translator_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Jack-vm-translator-rs");
+    let output = succeed(
+        Command::new(assembler)
+            .arg("grade")
+            .arg(&dir)
+            .arg("--rubric")
+            .arg(dir.join("rubric.json"))
+            .arg("--compiler")
+            .arg(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg("--translator")
+            .arg(translator_dir.join("target/debug/VMTranslator"))
+            .arg("--report")
+            .arg(dir.join("report.json")),
+    );
+
+    assert!(output.contains("[~] broken: compile failed"), "{output}");
+    assert!(output.contains("[~] good: 1/1 tests passed"), "{output}");
+    assert!(output.contains("[~] zipped: 1/1 tests passed"), "{output}");
+    let report = read_to_string(dir.join("report.json")).unwrap();
+    assert!(report.contains(r#""failed_stage": "compile""#), "{report}");
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn subcommands_take_the_source_extensions() {
+    let dir = std::env::temp_dir().join(format!("jack-source-ext-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    for name in ["Main", "Sys"] {
+        copy(
+            Path::new("tests/game").join(name).with_extension("jack"),
+            dir.join(name).with_extension("jk"),
+        )
+        .unwrap();
+    }
+
+    let output = succeed(
+        Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg("references")
+            .arg(&dir)
+            .arg("Main.main")
+            .arg("--source-ext")
+            .arg("jk"),
+    );
+    assert!(output.contains("Sys.jk:4:17: do Main.main();"), "{output}");
+
+    let output = succeed(
+        Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+            .arg("rename")
+            .arg(&dir)
+            .arg("Main.main")
+            .arg("start")
+            .arg("--dry-run")
+            .arg("--source-ext")
+            .arg("jk"),
+    );
+    assert!(output.contains("Main.main -> start"), "{output}");
+
+    remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,75 @@
+//! Golden outputs: every project of `input/` is compiled and its `.vm` files compared
+//! with the ones in `tests/snapshots/<project>/`.
+//!
+//! `cargo test --test snapshots -- --bless` writes the outputs that changed instead,
+//! so changes of the generated VM code show up in the diff for review; the harness is
+//! [`n2t_core::snapshot`].
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitCode};
+
+use n2t_core::snapshot;
+
+const INPUT_DIR: &str = "input";
+const SNAPSHOT_DIR: &str = "tests/snapshots";
+
+fn main() -> ExitCode {
+    snapshot::main("jack", |args, temp_dir| {
+        let fixtures = snapshot::fixtures(INPUT_DIR, Path::is_dir)?;
+
+        let mut failed = 0;
+        for fixture in fixtures.iter() {
+            let project = fixture.file_name().unwrap_or_default().to_string_lossy();
+            if !args.selects(&project) {
+                continue;
+            }
+
+            let snapshot_dir = Path::new(SNAPSHOT_DIR).join(project.as_ref());
+            fs::create_dir_all(&snapshot_dir)?;
+            for (file_name, actual) in compile(fixture, &temp_dir.join(project.as_ref()))? {
+                let golden = snapshot_dir.join(&file_name);
+                let name = format!("{project}/{file_name}");
+                if !snapshot::check(&name, &golden, &actual, args.bless)? {
+                    failed += 1;
+                }
+            }
+        }
+
+        Ok(failed)
+    })
+}
+
+/// The `.vm` files compiled from a copy of the `project` in `dir`, by name.
+fn compile(project: &Path, dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    fs::create_dir_all(dir)?;
+    for entry in fs::read_dir(project)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "jack") {
+            fs::copy(&path, dir.join(path.file_name().unwrap_or_default()))?;
+        }
+    }
+
+    let run = Command::new(env!("CARGO_BIN_EXE_Jack-compiler-rs"))
+        .arg(dir)
+        .output()?;
+    if !run.status.success() {
+        anyhow::bail!(
+            "Could not compile {}:\n{}",
+            project.display(),
+            String::from_utf8_lossy(&run.stderr)
+        );
+    }
+
+    let mut outputs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "vm") {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            outputs.push((file_name.into_owned(), fs::read_to_string(&path)?));
+        }
+    }
+    outputs.sort();
+
+    Ok(outputs)
+}
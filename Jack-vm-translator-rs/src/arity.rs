@@ -0,0 +1,250 @@
+//! Link-level check of a directory's calls before any assembly is written: every
+//! `call F n` has a `function F k` in some file (or is an OS subroutine, or exported
+//! by a library), all the calls of `F` pass the same `n`, and `n` covers the
+//! arguments `F` uses.
+
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::parser::{Node, Segment};
+
+/// Subroutines of the Jack OS with their argument counts, `this` included; called
+/// without being defined when the VM emulator provides the OS.
+const OS_SUBROUTINES: [(&str, u16); 48] = [
+    ("Math.init", 0),
+    ("Math.abs", 1),
+    ("Math.multiply", 2),
+    ("Math.divide", 2),
+    ("Math.min", 2),
+    ("Math.max", 2),
+    ("Math.sqrt", 1),
+    ("String.new", 1),
+    ("String.dispose", 1),
+    ("String.length", 1),
+    ("String.charAt", 2),
+    ("String.setCharAt", 3),
+    ("String.appendChar", 2),
+    ("String.eraseLastChar", 1),
+    ("String.intValue", 1),
+    ("String.setInt", 2),
+    ("String.backSpace", 0),
+    ("String.doubleQuote", 0),
+    ("String.newLine", 0),
+    ("Array.new", 1),
+    ("Array.dispose", 1),
+    ("Output.init", 0),
+    ("Output.moveCursor", 2),
+    ("Output.printChar", 1),
+    ("Output.printString", 1),
+    ("Output.printInt", 1),
+    ("Output.println", 0),
+    ("Output.backSpace", 0),
+    ("Screen.init", 0),
+    ("Screen.clearScreen", 0),
+    ("Screen.setColor", 1),
+    ("Screen.drawPixel", 2),
+    ("Screen.drawLine", 4),
+    ("Screen.drawRectangle", 4),
+    ("Screen.drawCircle", 3),
+    ("Keyboard.init", 0),
+    ("Keyboard.keyPressed", 0),
+    ("Keyboard.readChar", 0),
+    ("Keyboard.readLine", 1),
+    ("Keyboard.readInt", 1),
+    ("Memory.init", 0),
+    ("Memory.peek", 1),
+    ("Memory.poke", 2),
+    ("Memory.alloc", 1),
+    ("Memory.deAlloc", 1),
+    ("Sys.halt", 0),
+    ("Sys.error", 1),
+    ("Sys.wait", 1),
+];
+
+/// `exports.json` of a library compiled by the Jack compiler with `--lib`; only what
+/// the check needs of it.
+#[derive(Debug, Deserialize)]
+struct Exports {
+    subroutines: Vec<Export>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    name: String,
+    vm_arguments: u16,
+}
+
+/// Subroutines of a library with their argument counts, `this` included.
+pub fn read_exports(path: &Path) -> anyhow::Result<BTreeMap<String, u16>> {
+    let exports: Exports = serde_json::from_str(&read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+
+    Ok(exports
+        .subroutines
+        .into_iter()
+        .map(|export| (export.name, export.vm_arguments))
+        .collect())
+}
+
+/// Every unresolved or conflicting call among `files`, given as file names with
+/// their parsed nodes, and the subroutines of the libraries they link with; one
+/// message each.
+pub fn check(files: &[(String, Vec<Node<'_>>)], exports: &BTreeMap<String, u16>) -> Vec<String> {
+    // Function name -> arguments its body uses
+    let mut functions: BTreeMap<&str, u16> = BTreeMap::new();
+    // Function name -> (argument count, file) of every call
+    let mut calls: BTreeMap<&str, Vec<(u16, &str)>> = BTreeMap::new();
+
+    for (file, nodes) in files.iter() {
+        let mut current = None;
+        for node in nodes.iter() {
+            match node {
+                Node::Function { name, .. } => {
+                    functions.insert(name, 0);
+                    current = Some(&**name);
+                }
+                Node::Call { name, n_args } => {
+                    calls.entry(name).or_default().push((*n_args, file));
+                }
+                Node::Push {
+                    segment: Segment::Argument { offset },
+                }
+                | Node::Pop {
+                    segment: Segment::Argument { offset },
+                } => {
+                    if let Some(used) = current.and_then(|name| functions.get_mut(name)) {
+                        *used = (*used).max(offset + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut problems = vec![];
+    for (name, sites) in calls {
+        let (n_args, file) = sites[0];
+        if sites.iter().any(|(n, _)| *n != n_args) {
+            let counts: Vec<_> = sites
+                .iter()
+                .map(|(n, file)| format!("{n} in {file}"))
+                .collect();
+            problems.push(format!(
+                "{name} is called with different argument counts: {}",
+                dedup(counts).join(", ")
+            ));
+            continue;
+        }
+
+        match functions.get(name) {
+            Some(&used) if n_args < used => problems.push(format!(
+                "`call {name} {n_args}` in {file}: {name} uses argument {}",
+                used - 1
+            )),
+            Some(_) => {}
+            None => match OS_SUBROUTINES.iter().find(|(os_name, _)| *os_name == name) {
+                Some(&(_, expected)) if expected != n_args => problems.push(format!(
+                    "`call {name} {n_args}` in {file}: the OS subroutine takes {expected}"
+                )),
+                Some(_) => {}
+                None if exports.contains_key(name) => {
+                    let expected = exports[name];
+                    if expected != n_args {
+                        problems.push(format!(
+                            "`call {name} {n_args}` in {file}: the library subroutine takes {expected}"
+                        ));
+                    }
+                }
+                None => problems.push(format!(
+                    "`call {name} {n_args}` in {file}: no file defines function {name}"
+                )),
+            },
+        }
+    }
+
+    problems
+}
+
+/// `items` without repeats, in order.
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut unique = vec![];
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+
+    unique
+}
+
+#[cfg(test)]
+mod arity_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn check_sources(sources: &[(&str, &str)]) -> Vec<String> {
+        check_with_exports(sources, &BTreeMap::new())
+    }
+
+    fn check_with_exports(
+        sources: &[(&str, &str)],
+        exports: &BTreeMap<String, u16>,
+    ) -> Vec<String> {
+        let files: Vec<_> = sources
+            .iter()
+            .map(|(file, source)| {
+                let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+                let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+                (file.to_string(), nodes.unwrap())
+            })
+            .collect();
+
+        check(&files, exports)
+    }
+
+    #[test]
+    fn accepts_consistent_calls() {
+        let main = "function Main.main 0\npush constant 2\npush constant 3\ncall Main.add 2\n\
+                    call Output.printInt 1\nreturn";
+        let add = "function Main.add 0\npush argument 0\npush argument 1\nadd\nreturn";
+
+        assert!(check_sources(&[("Main.vm", main), ("Add.vm", add)]).is_empty());
+    }
+
+    #[test]
+    fn reports_unresolved_and_conflicting_calls() {
+        let main = "function Main.main 0\ncall Main.add 1\ncall Main.missing 0\n\
+                    call Math.multiply 1\ncall Main.twice 1\nreturn";
+        let other = "function Other.run 0\ncall Main.twice 2\nreturn\n\
+                     function Main.add 0\npush argument 1\nreturn\n\
+                     function Main.twice 0\nreturn";
+
+        assert_eq!(
+            check_sources(&[("Main.vm", main), ("Other.vm", other)]),
+            [
+                "`call Main.add 1` in Main.vm: Main.add uses argument 1",
+                "`call Main.missing 0` in Main.vm: no file defines function Main.missing",
+                "Main.twice is called with different argument counts: 1 in Main.vm, 2 in Other.vm",
+                "`call Math.multiply 1` in Main.vm: the OS subroutine takes 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_calls_of_library_exports() {
+        let main = "function Main.main 0\ncall Geo.area 2\ncall Geo.perimeter 1\nreturn";
+        let exports = BTreeMap::from([
+            ("Geo.area".to_string(), 2),
+            ("Geo.perimeter".to_string(), 2),
+        ]);
+
+        assert_eq!(
+            check_with_exports(&[("Main.vm", main)], &exports),
+            ["`call Geo.perimeter 1` in Main.vm: the library subroutine takes 2"]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! `--debug-info`: what the labels and variables of the translated program stand for,
+//! as a `.dbg` JSON file next to the `.asm` output, for a Hack emulator or debugger to
+//! show them by their VM names. It gives the function each generated label is in, the
+//! call site each return address follows, and the `static` word each variable is.
+//!
+//! Labels before the first function of a file have no function and are left out.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::parser::{Node, Segment};
+
+#[derive(Debug, Default, Serialize)]
+pub struct DebugInfo {
+    /// The function of every label of a `label` command, comparison or return address.
+    labels: BTreeMap<String, String>,
+    /// The call every return address label comes back to.
+    returns: BTreeMap<String, CallSite>,
+    /// The `static` word every variable such as `Main.3` is.
+    statics: BTreeMap<String, Static>,
+}
+
+#[derive(Debug, Serialize)]
+struct CallSite {
+    /// The calling function, if the call is in one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    callee: String,
+    file: String,
+    line: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Static {
+    file: String,
+    index: u16,
+}
+
+/// What [`DebugInfo::add`] needs of the nodes of a file, taken before translating
+/// them.
+#[derive(Debug, Default)]
+pub struct Outline {
+    /// Nodes of `function` commands, with the function.
+    functions: Vec<(usize, String)>,
+    /// Nodes of `call` commands, with the function called.
+    calls: Vec<(usize, String)>,
+    /// Indexes of the `static` segment used.
+    statics: Vec<u16>,
+}
+
+impl Outline {
+    pub fn of(nodes: &[Node<'_>]) -> Self {
+        let mut outline = Self::default();
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Function { name, .. } => outline.functions.push((i, name.to_string())),
+                Node::Call { name, .. } => outline.calls.push((i, name.to_string())),
+                Node::Push {
+                    segment: Segment::Static { offset },
+                }
+                | Node::Pop {
+                    segment: Segment::Static { offset },
+                } if !outline.statics.contains(offset) => outline.statics.push(*offset),
+                _ => {}
+            }
+        }
+
+        outline
+    }
+
+    /// The function node `i` is in.
+    fn function(&self, i: usize) -> Option<&str> {
+        let k = self.functions.partition_point(|(start, _)| *start <= i);
+
+        k.checked_sub(1).map(|k| self.functions[k].1.as_str())
+    }
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The labels and statics of the file at `path`, its variables named after `stem`,
+    /// from its `outline`, its `instructions`, where the code of each node `starts`
+    /// in them, and the `lines` of the nodes.
+    pub fn add(
+        &mut self,
+        path: &Path,
+        stem: &str,
+        outline: &Outline,
+        instructions: &[String],
+        starts: &[usize],
+        lines: &[usize],
+    ) {
+        let file = path.display().to_string();
+        let node_at = |k: usize| {
+            starts
+                .partition_point(|&start| start <= k)
+                .saturating_sub(1)
+        };
+        let label = |instruction: &str| {
+            instruction
+                .trim()
+                .strip_prefix('(')
+                .and_then(|label| label.strip_suffix(')'))
+                .map(str::to_string)
+        };
+
+        for (k, instruction) in instructions.iter().enumerate() {
+            let Some(label) = label(instruction) else {
+                continue;
+            };
+            let i = node_at(k);
+            let Some(function) = outline.function(i) else {
+                continue;
+            };
+            if label != function {
+                self.labels.insert(label, function.to_string());
+            }
+        }
+
+        for (i, callee) in outline.calls.iter() {
+            let end = starts.get(i + 1).copied().unwrap_or(instructions.len());
+            let Some(label) = instructions[starts[*i]..end]
+                .iter()
+                .find_map(|instruction| label(instruction))
+            else {
+                continue;
+            };
+            self.returns.insert(
+                label,
+                CallSite {
+                    function: outline.function(*i).map(str::to_string),
+                    callee: callee.clone(),
+                    file: file.clone(),
+                    line: lines[*i],
+                },
+            );
+        }
+
+        for &index in outline.statics.iter() {
+            self.statics.insert(
+                format!("{stem}.{index}"),
+                Static {
+                    file: file.clone(),
+                    index,
+                },
+            );
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        println!("[<-] Debug info: {}", path.display());
+
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(&mut file)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod debug_info_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner, translator::Translator};
+
+    #[test]
+    fn names_the_labels_return_addresses_and_statics() {
+        let source = "function Main.main 0\n\
+                      push constant 1\n\
+                      call Main.double 1\n\
+                      pop static 2\n\
+                      label END\n\
+                      goto END\n\
+                      function Main.double 0\n\
+                      push argument 0\n\
+                      push static 2\n\
+                      eq\n\
+                      return";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let mut parser = Parser::new(tokens.unwrap().into_iter());
+        let nodes: Result<Vec<_>, _> = parser.by_ref().collect();
+        let nodes = nodes.unwrap();
+        let lines = parser.lines().to_vec();
+        let outline = Outline::of(&nodes);
+        let (instructions, starts) = Translator::new("Main", nodes).translate_with_starts();
+
+        let mut info = DebugInfo::new();
+        info.add(
+            Path::new("src/Main.vm"),
+            "Main",
+            &outline,
+            &instructions,
+            &starts,
+            &lines,
+        );
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            serde_json::json!({
+                "labels": {
+                    "Main.main$END": "Main.main",
+                    "Main.Main.double.return.0": "Main.main",
+                    "Main.label_no.1": "Main.double",
+                    "Main.label_yes.1": "Main.double",
+                },
+                "returns": {
+                    "Main.Main.double.return.0": {
+                        "function": "Main.main",
+                        "callee": "Main.double",
+                        "file": "src/Main.vm",
+                        "line": 3,
+                    },
+                },
+                "statics": {
+                    "Main.2": {"file": "src/Main.vm", "index": 2},
+                },
+            })
+        );
+    }
+}
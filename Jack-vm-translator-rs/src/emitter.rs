@@ -0,0 +1,174 @@
+//! Output backends of a translation, selected from the CLI flags.
+
+use std::fs::{File, create_dir_all, remove_file, rename};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::linker::Fragment;
+
+pub trait Emitter {
+    /// Comment lines starting the output, before the code of any file.
+    fn emit_header(&mut self, _lines: &[String]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Translated code of the `.vm` file at `input`, or of a linked program.
+    fn emit_asm(&mut self, _input: &Path, _instructions: &[String]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Code of a function, or of the code before the first function of a file.
+    fn emit_fragment(&mut self, _fragment: &Fragment) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Lines written so far, where the code of the next file starts.
+    fn lines(&self) -> usize {
+        0
+    }
+
+    /// Puts the output in place, once every file is translated.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// A single `.asm` file. It is written under a temporary name next to it, and
+/// replaces an output left by an earlier run only once finished: a translation that
+/// fails half-way removes what it wrote and leaves the earlier output as it was.
+pub struct AsmWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    temp: PathBuf,
+    lines: usize,
+    finished: bool,
+}
+
+impl AsmWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        Ok(Self {
+            file: BufWriter::new(File::create(&temp)?),
+            path: path.to_path_buf(),
+            temp,
+            lines: 0,
+            finished: false,
+        })
+    }
+
+    fn write_lines(&mut self, lines: &[String]) -> anyhow::Result<()> {
+        for line in lines {
+            writeln!(&mut self.file, "{line}")?;
+        }
+        self.lines += lines.len();
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Emitter for AsmWriter {
+    fn emit_header(&mut self, lines: &[String]) -> anyhow::Result<()> {
+        self.write_lines(lines)
+    }
+
+    fn emit_asm(&mut self, _input: &Path, instructions: &[String]) -> anyhow::Result<()> {
+        self.write_lines(instructions)
+    }
+
+    fn lines(&self) -> usize {
+        self.lines
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.file.flush()?;
+        rename(&self.temp, &self.path)?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for AsmWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = remove_file(&self.temp);
+        }
+    }
+}
+
+/// A directory of `<name>.asm` fragments, one per function.
+pub struct FragmentWriter {
+    dir: PathBuf,
+}
+
+impl FragmentWriter {
+    pub fn create(dir: &Path) -> anyhow::Result<Self> {
+        create_dir_all(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+}
+
+impl Emitter for FragmentWriter {
+    fn emit_fragment(&mut self, fragment: &Fragment) -> anyhow::Result<()> {
+        fragment.write(&self.dir)
+    }
+}
+
+#[cfg(test)]
+mod emitter_tests {
+    use super::*;
+
+    #[test]
+    fn replaces_an_earlier_output() {
+        let path = std::env::temp_dir().join(format!("vm-emitter-{}.asm", std::process::id()));
+        std::fs::write(&path, "(Old)\n@Old\n0;JMP\n").unwrap();
+
+        let mut writer = AsmWriter::create(&path).unwrap();
+        writer.emit_header(&["// header".to_string()]).unwrap();
+        writer
+            .emit_asm(Path::new("Main.vm"), &["@1".to_string(), "D=A".to_string()])
+            .unwrap();
+
+        assert_eq!(writer.lines(), 3);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "(Old)\n@Old\n0;JMP\n"
+        );
+        writer.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "// header\n@1\nD=A\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_earlier_output_unless_finished() {
+        let path = std::env::temp_dir().join(format!("vm-unfinished-{}.asm", std::process::id()));
+        std::fs::write(&path, "(Old)\n@Old\n0;JMP\n").unwrap();
+
+        let mut writer = AsmWriter::create(&path).unwrap();
+        writer
+            .emit_asm(Path::new("Main.vm"), &["@1".to_string()])
+            .unwrap();
+        let temp = writer.temp.clone();
+        assert!(temp.exists());
+        drop(writer);
+
+        assert!(!temp.exists());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "(Old)\n@Old\n0;JMP\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
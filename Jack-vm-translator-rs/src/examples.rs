@@ -0,0 +1,86 @@
+//! Typical runs of the translator, between the Jack compiler and the assembler. The
+//! `examples` subcommand, the long `--help` and the README's examples are all written
+//! from [`EXAMPLES`], so they cannot drift apart.
+
+use std::fmt::Write as _;
+
+pub struct Example {
+    pub title: &'static str,
+    /// Shell commands, run in order.
+    pub commands: &'static [&'static str],
+    pub note: &'static str,
+}
+
+/// What `--help` says before the flags.
+pub const LONG_ABOUT: &str = "Jack language VM translator
+
+Translates .vm files, or the .vm files of directories, into one Hack assembly file for \
+the assembler, in the order given. The functions can also be written as fragments, and \
+linked into a program with the dead ones stripped.";
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        title: "Translate a single file",
+        commands: &["VMTranslator StackTest.vm"],
+        note: "Writes `StackTest.asm` next to it.",
+    },
+    Example {
+        title: "Translate a program, `Sys.init` first",
+        commands: &["VMTranslator Sys.vm Main.vm -o Game.asm"],
+        note: "No bootstrap code is written, so the first file starts at ROM[0]. Set SP, LCL and ARG to 256 before running, such as with the assembler's `--ram`.",
+    },
+    Example {
+        title: "Translate a project with the OS",
+        commands: &["VMTranslator Pong/ --with-os tools/OS -O 1 -o Pong.asm"],
+        note: "Adds the OS classes the project does not provide, and shortens the code with `-O 1`.",
+    },
+    Example {
+        title: "Link only the functions that are called",
+        commands: &[
+            "VMTranslator Pong/ --fragments -o Pong.fragments",
+            "VMTranslator Pong.fragments --link --strip-dead -o Pong.asm",
+        ],
+        note: "Writes every function as its own fragment, then links the ones reachable from `Sys.init`.",
+    },
+];
+
+/// The examples for the end of `--help`.
+pub fn long_help() -> String {
+    let mut help = "Examples:\n".to_string();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(help, "\n  {}:", example.title);
+        for command in example.commands.iter() {
+            let _ = writeln!(help, "    $ {command}");
+        }
+        let _ = writeln!(help, "  {}", example.note);
+    }
+
+    help
+}
+
+/// The examples as the README has them.
+pub fn markdown() -> String {
+    let mut markdown = String::new();
+    for example in EXAMPLES.iter() {
+        let _ = writeln!(markdown, "**{}**", example.title);
+        let _ = writeln!(markdown, "```bash\n{}\n```", example.commands.join("\n"));
+        let _ = writeln!(markdown, "{}\n", example.note);
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod examples_tests {
+    use super::*;
+
+    #[test]
+    fn readme_has_the_examples() {
+        let readme = include_str!("../README.md");
+
+        assert!(
+            readme.contains(&markdown()),
+            "Update the README's examples with `VMTranslator examples --markdown`"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! `--functions`: the functions of the translated program as JSON, for a Hack emulator
+//! to name the frames of a call stack and show their locals. A function's code starts
+//! at the label named after it, `(Main.main)`.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::parser::Node;
+
+#[derive(Debug, Default, Serialize)]
+pub struct FunctionTable {
+    functions: Vec<Function>,
+}
+
+#[derive(Debug, Serialize)]
+struct Function {
+    name: String,
+    /// Words of the `local` segment.
+    locals: u16,
+    /// The `.vm` file defining it.
+    file: String,
+}
+
+impl FunctionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every function of the file at `path`, in order.
+    pub fn add(&mut self, path: &Path, nodes: &[Node<'_>]) {
+        for node in nodes {
+            if let Node::Function { name, n_locals } = node {
+                self.functions.push(Function {
+                    name: name.to_string(),
+                    locals: *n_locals,
+                    file: path.display().to_string(),
+                });
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        println!("[<-] Functions: {}", path.display());
+
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(&mut file)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod function_table_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn lists_every_function() {
+        let mut table = FunctionTable::new();
+        let source = "function Main.main 2\ncall Main.f 0\nreturn\nfunction Main.f 0\nreturn";
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        table.add(Path::new("Main.vm"), &nodes.unwrap());
+
+        assert_eq!(
+            serde_json::to_value(&table).unwrap(),
+            serde_json::json!({"functions": [
+                {"name": "Main.main", "locals": 2, "file": "Main.vm"},
+                {"name": "Main.f", "locals": 0, "file": "Main.vm"},
+            ]})
+        );
+    }
+}
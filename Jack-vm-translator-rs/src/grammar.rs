@@ -0,0 +1,142 @@
+//! TextMate and Tree-sitter grammar stubs built from the scanner's keyword table,
+//! so editor highlighting follows the commands and segments the translator knows.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::scanner::KEYWORDS;
+
+/// Writes `jack-vm.tmLanguage.json` and `tree-sitter-jack-vm/` into `dir`.
+pub fn emit(dir: &Path) -> anyhow::Result<()> {
+    let tree_sitter_dir = dir.join("tree-sitter-jack-vm");
+    fs::create_dir_all(tree_sitter_dir.join("queries"))?;
+
+    for (path, contents) in [
+        (
+            dir.join("jack-vm.tmLanguage.json"),
+            serde_json::to_string_pretty(&text_mate())? + "\n",
+        ),
+        (tree_sitter_dir.join("grammar.js"), tree_sitter()),
+        (
+            tree_sitter_dir.join("queries").join("highlights.scm"),
+            HIGHLIGHTS.to_string(),
+        ),
+    ] {
+        println!("[<-] Grammar: {}", path.display());
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+/// Keywords of the given [`kind`](crate::scanner::TokenType::kind), sorted.
+fn keywords(kind: &str) -> Vec<&'static str> {
+    let mut keywords: Vec<_> = KEYWORDS
+        .iter()
+        .filter(|(_, token_type)| token_type.kind() == kind)
+        .map(|(keyword, _)| *keyword)
+        .collect();
+    keywords.sort();
+
+    keywords
+}
+
+/// Keywords are matched case-insensitively, like the scanner does.
+fn text_mate() -> Value {
+    let words = |kind| {
+        format!(
+            "(?i)(?<![\\w.$:-])(?:{})(?![\\w.$:-])",
+            keywords(kind).join("|")
+        )
+    };
+
+    json!({
+        "name": "Jack VM",
+        "scopeName": "source.jack-vm",
+        "fileTypes": ["vm"],
+        "patterns": [
+            { "name": "comment.line.double-slash.jack-vm", "match": "//.*$" },
+            { "name": "comment.block.jack-vm", "begin": "/\\*", "end": "\\*/" },
+            { "name": "constant.numeric.integer.jack-vm", "match": "\\b[0-9]+\\b" },
+            { "name": "keyword.control.jack-vm", "match": words("command") },
+            { "name": "storage.type.jack-vm", "match": words("segment") },
+        ]
+    })
+}
+
+fn tree_sitter() -> String {
+    let words = |kind| {
+        keywords(kind)
+            .iter()
+            .map(|keyword| format!("alias(/{keyword}/i, '{keyword}')"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!(
+        r#"// Generated by `VMTranslator --emit-grammar`: tokens only, a stub to grow the grammar from.
+module.exports = grammar({{
+  name: 'jack_vm',
+  extras: $ => [/\s/, $.comment],
+  word: $ => $.identifier,
+  rules: {{
+    source_file: $ => repeat($._token),
+    _token: $ => choice($.command, $.segment, $.number, $.identifier),
+    command: $ => choice({}),
+    segment: $ => choice({}),
+    number: $ => /[0-9]+/,
+    identifier: $ => /[A-Za-z_.$:-][A-Za-z0-9_.$:-]*/,
+    comment: $ => token(choice(seq('//', /.*/), seq('/*', /[^*]*\*+([^/*][^*]*\*+)*/, '/'))),
+  }}
+}});
+"#,
+        words("command"),
+        words("segment")
+    )
+}
+
+const HIGHLIGHTS: &str = "(command) @keyword
+(segment) @type.builtin
+(number) @number
+(comment) @comment
+";
+
+#[cfg(test)]
+mod grammar_tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn highlights_every_keyword_as_the_scanner_reads_it() {
+        let (commands, segments) = (keywords("command"), keywords("segment"));
+        assert_eq!(commands.len() + segments.len(), KEYWORDS.len());
+
+        let source = commands.join(" ").to_uppercase() + " " + &segments.join(" ");
+        let tokens: Result<Vec<_>, _> = Scanner::new(&source).collect();
+        let kinds: Vec<_> = tokens
+            .unwrap()
+            .iter()
+            .map(|token| token.token_type.kind())
+            .collect();
+
+        let expected: Vec<_> = [
+            ("command", commands.len()),
+            ("segment", segments.len()),
+            ("eof", 1),
+        ]
+        .into_iter()
+        .flat_map(|(kind, n)| std::iter::repeat_n(kind, n))
+        .collect();
+        assert_eq!(kinds, expected);
+
+        assert!(tree_sitter().contains("alias(/if-goto/i, 'if-goto')"));
+        assert!(
+            text_mate()["patterns"][4]["match"]
+                .as_str()
+                .unwrap()
+                .contains("|pointer|")
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Minimal Hack assembler and CPU, used to run translated code in tests.
+
+use std::collections::HashMap;
+
+enum Instruction {
+    A(u16),
+    C {
+        dest: String,
+        comp: String,
+        jump: String,
+    },
+}
+
+pub struct Emulator {
+    rom: Vec<Instruction>,
+    labels: HashMap<String, u16>,
+    pub ram: Vec<i16>,
+    pc: u16,
+    a: i16,
+    d: i16,
+}
+
+impl Emulator {
+    pub fn new(asm: &[String]) -> Self {
+        let mut symbols: HashMap<String, u16> = (0..=15).map(|r| (format!("R{r}"), r)).collect();
+        for (name, address) in [
+            ("SP", 0),
+            ("LCL", 1),
+            ("ARG", 2),
+            ("THIS", 3),
+            ("THAT", 4),
+            ("SCREEN", 16384),
+            ("KBD", 24576),
+        ] {
+            symbols.insert(name.to_string(), address);
+        }
+
+        let lines: Vec<&str> = asm
+            .iter()
+            .map(|line| line.split("//").next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let mut labels = HashMap::new();
+        let mut address = 0;
+        for line in lines.iter() {
+            match line.strip_prefix('(') {
+                Some(label) => {
+                    labels.insert(label.trim_end_matches(')').to_string(), address);
+                }
+                None => address += 1,
+            }
+        }
+        symbols.extend(labels.iter().map(|(k, v)| (k.clone(), *v)));
+
+        let mut next_variable = 16;
+        let mut rom = vec![];
+        for line in lines {
+            if line.starts_with('(') {
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix('@') {
+                let value = match value.parse() {
+                    Ok(value) => value,
+                    Err(_) => *symbols.entry(value.to_string()).or_insert_with(|| {
+                        next_variable += 1;
+                        next_variable - 1
+                    }),
+                };
+                rom.push(Instruction::A(value));
+            } else {
+                let (dest, rest) = line.split_once('=').unwrap_or(("", line));
+                let (comp, jump) = rest.split_once(';').unwrap_or((rest, ""));
+                rom.push(Instruction::C {
+                    dest: dest.to_string(),
+                    comp: comp.to_string(),
+                    jump: jump.to_string(),
+                });
+            }
+        }
+
+        Self {
+            rom,
+            labels,
+            ram: vec![0; 32768],
+            pc: 0,
+            a: 0,
+            d: 0,
+        }
+    }
+
+    /// Runs until `label` is reached, failing after `max_steps` instructions.
+    pub fn run_until(&mut self, label: &str, max_steps: usize) {
+        let end = self.labels[label];
+
+        for _ in 0..max_steps {
+            if self.pc == end {
+                return;
+            }
+            self.step();
+        }
+
+        panic!("Did not reach `{label}` in {max_steps} steps");
+    }
+
+    fn step(&mut self) {
+        match &self.rom[self.pc as usize] {
+            Instruction::A(value) => {
+                self.a = *value as i16;
+                self.pc += 1;
+            }
+            Instruction::C { dest, comp, jump } => {
+                let m = self.ram[self.a as u16 as usize];
+                let y = if comp.contains('M') { m } else { self.a };
+                let d = self.d;
+                let value = match comp.replace('M', "A").as_str() {
+                    "0" => 0,
+                    "1" => 1,
+                    "-1" => -1,
+                    "D" => d,
+                    "A" => y,
+                    "!D" => !d,
+                    "!A" => !y,
+                    "-D" => d.wrapping_neg(),
+                    "-A" => y.wrapping_neg(),
+                    "D+1" => d.wrapping_add(1),
+                    "A+1" => y.wrapping_add(1),
+                    "D-1" => d.wrapping_sub(1),
+                    "A-1" => y.wrapping_sub(1),
+                    "D+A" | "A+D" => d.wrapping_add(y),
+                    "D-A" => d.wrapping_sub(y),
+                    "A-D" => y.wrapping_sub(d),
+                    "D&A" | "A&D" => d & y,
+                    "D|A" | "A|D" => d | y,
+                    comp => panic!("Unknown comp `{comp}`"),
+                };
+
+                let address = self.a as u16 as usize;
+                if dest.contains('M') {
+                    self.ram[address] = value;
+                }
+                if dest.contains('D') {
+                    self.d = value;
+                }
+                let target = self.a as u16;
+                if dest.contains('A') {
+                    self.a = value;
+                }
+
+                let jumps = match jump.as_str() {
+                    "" => false,
+                    "JGT" => value > 0,
+                    "JEQ" => value == 0,
+                    "JGE" => value >= 0,
+                    "JLT" => value < 0,
+                    "JNE" => value != 0,
+                    "JLE" => value <= 0,
+                    "JMP" => true,
+                    jump => panic!("Unknown jump `{jump}`"),
+                };
+                self.pc = if jumps { target } else { self.pc + 1 };
+            }
+        }
+    }
+}
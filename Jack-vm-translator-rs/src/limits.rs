@@ -0,0 +1,256 @@
+//! Hack platform limits a translated program has to stay within.
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use crate::parser::{Node, Segment};
+
+/// Upper bounds checked after translating. [`Limits::default`] is the Hack platform.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// ROM words.
+    pub rom: usize,
+    /// Static variables of all files together, RAM[16..255].
+    pub statics: usize,
+    /// Temp registers, RAM[5..12].
+    pub temps: usize,
+    /// Stack words, RAM[256..2047].
+    pub stack: usize,
+    /// Bytes of the output files, unchecked unless set.
+    pub output_bytes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            rom: 32 * 1024,
+            statics: 240,
+            temps: 8,
+            stack: 2048 - 256,
+            output_bytes: None,
+        }
+    }
+}
+
+/// What a program uses of each limited resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Usage {
+    pub rom: usize,
+    pub statics: usize,
+    /// Highest temp register used, plus one.
+    pub temps: usize,
+    /// Deepest stack of a single function, its frame and call arguments included.
+    pub stack: usize,
+    /// Bytes of the output files, once written.
+    pub output_bytes: usize,
+}
+
+impl Usage {
+    /// Usage of the parsed nodes of one file, ROM aside.
+    pub fn of(nodes: &[Node<'_>]) -> Self {
+        let mut statics = BTreeSet::new();
+        let mut temps = 0;
+        let (mut depth, mut stack) = (0i64, 0i64);
+
+        for node in nodes {
+            match node {
+                Node::Push { segment } | Node::Pop { segment } => match segment {
+                    Segment::Static { offset } => {
+                        statics.insert(*offset);
+                    }
+                    Segment::Temp { offset } => temps = temps.max(*offset as usize + 1),
+                    _ => {}
+                },
+                _ => {}
+            }
+
+            depth += match node {
+                Node::Function { n_locals, .. } => {
+                    depth = 0;
+                    *n_locals as i64
+                }
+                Node::Push { .. } => 1,
+                // The return address and the 4 saved pointers are pushed on top of the arguments
+                Node::Call { n_args, .. } => {
+                    stack = stack.max(depth + 5);
+                    1 - *n_args as i64
+                }
+                Node::Pop { .. } | Node::IfGoto { .. } => -1,
+                Node::Add | Node::Sub | Node::Eq | Node::Gt | Node::Lt | Node::And | Node::Or => -1,
+                _ => 0,
+            };
+            stack = stack.max(depth);
+        }
+
+        Self {
+            rom: 0,
+            statics: statics.len(),
+            temps,
+            stack: stack as usize,
+            output_bytes: 0,
+        }
+    }
+
+    /// Usage of two files translated into one program.
+    pub fn add(&mut self, other: &Usage) {
+        self.rom += other.rom;
+        self.statics += other.statics;
+        self.temps = self.temps.max(other.temps);
+        self.stack = self.stack.max(other.stack);
+        self.output_bytes += other.output_bytes;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LimitExceeded {
+    pub what: &'static str,
+    pub used: usize,
+    pub max: usize,
+    pub hint: &'static str,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many {}: {} of at most {}. Hint: {}",
+            self.what, self.used, self.max, self.hint
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl Limits {
+    /// Every limit `usage` goes over. `opt_level` picks the hint for an oversized program.
+    pub fn check(&self, usage: &Usage, opt_level: u8) -> Vec<LimitExceeded> {
+        let rom_hint = if opt_level == 0 {
+            "enable -O1 to reduce code size"
+        } else {
+            "translate with --fragments and --link --strip-dead to drop unused functions"
+        };
+
+        [
+            ("ROM instructions", usage.rom, self.rom, rom_hint),
+            (
+                "static variables",
+                usage.statics,
+                self.statics,
+                "move statics into fields or locals",
+            ),
+            (
+                "temp registers",
+                usage.temps,
+                self.temps,
+                "use `temp 0` to `temp 7` only",
+            ),
+            (
+                "stack words",
+                usage.stack,
+                self.stack,
+                "split deep expressions and long argument lists with locals",
+            ),
+            (
+                "bytes of output",
+                usage.output_bytes,
+                self.output_bytes.unwrap_or(usize::MAX),
+                rom_hint,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, used, max, _)| used > max)
+        .map(|(what, used, max, hint)| LimitExceeded {
+            what,
+            used,
+            max,
+            hint,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn usage(source: &str) -> Usage {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        Usage::of(&nodes.unwrap())
+    }
+
+    #[test]
+    fn counts_statics_temps_and_stack() {
+        let usage = usage(
+            "
+            function Main.main 2
+            push static 0
+            push static 3
+            pop static 0
+            push constant 1
+            call Math.multiply 2
+            pop temp 6
+            return
+            ",
+        );
+
+        assert_eq!(usage.statics, 2);
+        assert_eq!(usage.temps, 7);
+        // 2 locals, 2 arguments and the 5 words of the call frame
+        assert_eq!(usage.stack, 9);
+    }
+
+    #[test]
+    fn reports_exceeded_limits_only() {
+        let limits = Limits::default();
+        let mut usage = Usage {
+            rom: 40_000,
+            statics: 100,
+            ..Default::default()
+        };
+        usage.add(&Usage {
+            statics: 141,
+            temps: 8,
+            ..Default::default()
+        });
+
+        let exceeded = limits.check(&usage, 0);
+        assert_eq!(exceeded.len(), 2);
+        assert_eq!(
+            exceeded[0].to_string(),
+            "Too many ROM instructions: 40000 of at most 32768. Hint: enable -O1 to reduce code size"
+        );
+        assert_eq!(exceeded[1].what, "static variables");
+        assert_eq!(exceeded[1].used, 241);
+    }
+
+    #[test]
+    fn output_bytes_are_checked_when_capped() {
+        let usage = Usage {
+            rom: 1000,
+            output_bytes: 9000,
+            ..Default::default()
+        };
+        assert!(Limits::default().check(&usage, 1).is_empty());
+
+        let limits = Limits {
+            rom: 999,
+            output_bytes: Some(8192),
+            ..Default::default()
+        };
+        let exceeded: Vec<_> = limits
+            .check(&usage, 1)
+            .iter()
+            .map(|limit| (limit.what, limit.used, limit.max))
+            .collect();
+        assert_eq!(
+            exceeded,
+            [
+                ("ROM instructions", 1000, 999),
+                ("bytes of output", 9000, 8192)
+            ]
+        );
+    }
+}
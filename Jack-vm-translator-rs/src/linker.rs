@@ -0,0 +1,438 @@
+//! Links per-function `.asm` fragments into a single program.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, read_dir, read_to_string};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, bail};
+
+const FRAGMENT_EXT: &str = ".asm";
+const DEFAULT_ENTRY: &str = "Sys.init";
+
+/// Translated code of a single VM function, or of the code before the first
+/// function of a file.
+pub struct Fragment {
+    pub name: String,
+    pub lines: Vec<String>,
+}
+
+impl Fragment {
+    /// Reads every `<name>.asm` fragment of `dir`, ordered by name.
+    pub fn read_dir<P: AsRef<Path>>(dir: P) -> anyhow::Result<Vec<Fragment>> {
+        let mut fragments = vec![];
+        for entry in read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            let Some(name) = path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.strip_suffix(FRAGMENT_EXT))
+            else {
+                continue;
+            };
+            let source = read_to_string(&path)
+                .with_context(|| format!("Failed to read fragment {}", path.display()))?;
+
+            fragments.push(Fragment {
+                name: name.to_string(),
+                lines: source.lines().map(String::from).collect(),
+            });
+        }
+        fragments.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(fragments)
+    }
+
+    /// Writes the fragment as `<dir>/<name>.asm`.
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<()> {
+        let path = dir.as_ref().join(format!("{}{}", self.name, FRAGMENT_EXT));
+        let mut file = File::create(path)?;
+        for line in self.lines.iter() {
+            writeln!(&mut file, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Function fragments start with their own label.
+    fn is_function(&self) -> bool {
+        self.lines
+            .first()
+            .is_some_and(|line| *line == format!("({})", self.name))
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &str> {
+        self.lines
+            .iter()
+            .filter_map(|line| line.strip_prefix('(')?.strip_suffix(')'))
+    }
+
+    /// Symbols of unconditional jumps (`@symbol`, `0;JMP`): gotos and calls.
+    fn jumps(&self) -> impl Iterator<Item = &str> {
+        self.lines
+            .windows(2)
+            .filter(|w| w[1] == "0;JMP")
+            .filter_map(|w| w[0].strip_prefix('@'))
+    }
+}
+
+/// Order of the functions that follow the top-level code and the entry function.
+pub enum Layout {
+    /// As the fragments were given.
+    Original,
+    /// Listed functions first, in the listed order, then the rest.
+    Ordered(Vec<String>),
+    /// Functions with the most call sites first.
+    HotFirst,
+}
+
+/// Result of [`Linker::link`].
+pub struct Program {
+    pub lines: Vec<String>,
+    /// Functions dropped by [`Linker::with_strip_dead`].
+    pub stripped: Vec<String>,
+    /// Instructions of all fragments.
+    pub size_before: usize,
+    /// Instructions of the linked program.
+    pub size_after: usize,
+}
+
+pub struct Linker {
+    fragments: Vec<Fragment>,
+    entry: Option<String>,
+    strip_dead: bool,
+    layout: Layout,
+}
+
+impl Linker {
+    pub fn new(fragments: Vec<Fragment>) -> Self {
+        Self {
+            fragments,
+            entry: None,
+            strip_dead: false,
+            layout: Layout::Original,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Function placed right after the top-level code. Defaults to `Sys.init` when present.
+    pub fn with_entry<S: Into<String>>(mut self, entry: S) -> Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    /// Drops functions not reachable from the top-level code or the entry function.
+    pub fn with_strip_dead(mut self, strip_dead: bool) -> Self {
+        self.strip_dead = strip_dead;
+        self
+    }
+
+    /// Lays out top-level code first, then the entry function, then the remaining
+    /// functions as [`Layout`] says.
+    pub fn link(self) -> anyhow::Result<Program> {
+        let fragments = self.fragments;
+
+        let mut owners: HashMap<&str, usize> = HashMap::new();
+        for (i, fragment) in fragments.iter().enumerate() {
+            if fragments[..i].iter().any(|f| f.name == fragment.name) {
+                bail!("Fragment `{}` is defined twice", fragment.name);
+            }
+            for label in fragment.labels() {
+                if let Some(other) = owners.insert(label, i) {
+                    bail!(
+                        "Label `{}` is defined in both `{}` and `{}`",
+                        label,
+                        fragments[other].name,
+                        fragment.name
+                    );
+                }
+            }
+        }
+
+        let mut callees = vec![HashSet::new(); fragments.len()];
+        let mut call_sites = vec![0; fragments.len()];
+        for (i, fragment) in fragments.iter().enumerate() {
+            for symbol in fragment.jumps() {
+                match owners.get(symbol) {
+                    Some(&owner) if owner != i => {
+                        callees[i].insert(owner);
+                        call_sites[owner] += 1;
+                    }
+                    Some(_) => {}
+                    None => bail!("Undefined symbol `{}` in `{}`", symbol, fragment.name),
+                }
+            }
+        }
+
+        let entry = match self.entry {
+            Some(entry) => match fragments.iter().position(|f| f.name == entry) {
+                Some(i) if fragments[i].is_function() => Some(i),
+                _ => bail!("Entry function `{}` is not defined", entry),
+            },
+            None => fragments
+                .iter()
+                .position(|f| f.name == DEFAULT_ENTRY && f.is_function()),
+        };
+
+        let mut order: Vec<usize> = (0..fragments.len())
+            .filter(|&i| !fragments[i].is_function())
+            .collect();
+        let roots = order.iter().copied().chain(entry).collect::<Vec<_>>();
+        order.extend(entry);
+
+        let mut rest: Vec<usize> = (0..fragments.len())
+            .filter(|&i| fragments[i].is_function() && Some(i) != entry)
+            .collect();
+        match self.layout {
+            Layout::Original => {}
+            Layout::Ordered(names) => {
+                let mut ranks = HashMap::new();
+                for (rank, name) in names.iter().enumerate() {
+                    if !fragments.iter().any(|f| f.name == *name && f.is_function()) {
+                        bail!("Ordered function `{}` is not defined", name);
+                    }
+                    ranks.entry(name.as_str()).or_insert(rank);
+                }
+                rest.sort_by_key(|&i| {
+                    ranks
+                        .get(fragments[i].name.as_str())
+                        .copied()
+                        .unwrap_or(usize::MAX)
+                });
+            }
+            Layout::HotFirst => rest.sort_by_key(|&i| std::cmp::Reverse(call_sites[i])),
+        }
+        order.extend(rest);
+
+        let mut keep = vec![true; fragments.len()];
+        if self.strip_dead {
+            if roots.is_empty() {
+                bail!("Nothing to link from: no top-level code and no entry function");
+            }
+
+            keep = vec![false; fragments.len()];
+            let mut queue: VecDeque<usize> = roots.into_iter().collect();
+            while let Some(i) = queue.pop_front() {
+                if !keep[i] {
+                    keep[i] = true;
+                    queue.extend(callees[i].iter().copied());
+                }
+            }
+        }
+
+        let mut program = Program {
+            lines: vec![],
+            stripped: vec![],
+            size_before: fragments.iter().map(|f| instruction_count(&f.lines)).sum(),
+            size_after: 0,
+        };
+        for (i, fragment) in order.into_iter().map(|i| (i, &fragments[i])) {
+            if keep[i] {
+                program.lines.extend(fragment.lines.iter().cloned());
+            } else {
+                program.stripped.push(fragment.name.clone());
+            }
+        }
+        program.size_after = instruction_count(&program.lines);
+
+        Ok(program)
+    }
+}
+
+/// Lines that take a ROM word: neither labels nor comments.
+pub(crate) fn instruction_count(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .map(|line| line.split("//").next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('('))
+        .count()
+}
+
+#[cfg(test)]
+mod linker_tests {
+    use super::*;
+    use crate::{
+        hack_emulator::Emulator, parser::Parser, scanner::Scanner, translator::Translator,
+    };
+
+    const BOOT: &str = "
+        call Main.main 0
+        label END
+        goto END
+    ";
+
+    const MAIN: &str = "
+        function Main.main 0
+            push constant 4
+            call Main.square 1
+            return
+        function Main.unused 0
+            call Main.square 0
+            return
+        function Main.square 0
+            push argument 0
+            push argument 0
+            call Main.add 2
+            push argument 0
+            push argument 0
+            call Main.add 2
+            add
+            return
+        function Main.add 0
+            push argument 0
+            push argument 1
+            add
+            return
+    ";
+
+    fn fragments(filename: &str, source: &str) -> Vec<Fragment> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        Translator::new(filename, nodes.unwrap()).translate_fragments()
+    }
+
+    fn run(lines: &[String]) -> i16 {
+        let mut asm = vec!["@256".to_string(), "D=A".into(), "@SP".into(), "M=D".into()];
+        asm.extend(lines.iter().cloned());
+
+        let mut emulator = Emulator::new(&asm);
+        emulator.run_until("Boot.END", 100_000);
+
+        emulator.ram[256]
+    }
+
+    fn program() -> Vec<Fragment> {
+        // Functions first, so the linker has to move the top-level code up.
+        let mut all = fragments("Main", MAIN);
+        all.extend(fragments("Boot", BOOT));
+        all
+    }
+
+    #[test]
+    fn one_fragment_per_function() {
+        let names: Vec<_> = program().into_iter().map(|f| f.name).collect();
+
+        assert_eq!(
+            names,
+            [
+                "Main.main",
+                "Main.unused",
+                "Main.square",
+                "Main.add",
+                "Boot"
+            ]
+        );
+    }
+
+    #[test]
+    fn links_into_runnable_program() {
+        let program = Linker::new(program()).link().unwrap();
+
+        assert!(program.stripped.is_empty());
+        assert_eq!(run(&program.lines), 16);
+    }
+
+    #[test]
+    fn strips_dead_functions() {
+        let full = Linker::new(program()).link().unwrap();
+        let stripped = Linker::new(program()).with_strip_dead(true).link().unwrap();
+
+        assert_eq!(stripped.stripped, ["Main.unused"]);
+        assert!(stripped.lines.len() < full.lines.len());
+        assert_eq!(run(&stripped.lines), 16);
+    }
+
+    #[test]
+    fn entry_goes_first() {
+        let program = Linker::new(fragments("Main", MAIN))
+            .with_entry("Main.square")
+            .with_strip_dead(true)
+            .link()
+            .unwrap();
+
+        assert_eq!(program.lines[0], "(Main.square)");
+        assert_eq!(program.stripped, ["Main.main", "Main.unused"]);
+    }
+
+    fn function_order(program: &Program) -> Vec<&str> {
+        program
+            .lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("(Main.")?.strip_suffix(')'))
+            .filter(|name| !name.contains('.'))
+            .collect()
+    }
+
+    #[test]
+    fn layouts() {
+        let original = Linker::new(program()).link().unwrap();
+        assert_eq!(
+            function_order(&original),
+            ["main", "unused", "square", "add"]
+        );
+
+        let ordered = Linker::new(program())
+            .with_layout(Layout::Ordered(vec![
+                "Main.add".into(),
+                "Main.unused".into(),
+            ]))
+            .link()
+            .unwrap();
+        assert_eq!(
+            function_order(&ordered),
+            ["add", "unused", "main", "square"]
+        );
+        assert_eq!(run(&ordered.lines), 16);
+
+        // `add` is called twice, `square` twice, `main` once, `unused` never.
+        let hot = Linker::new(program())
+            .with_layout(Layout::HotFirst)
+            .link()
+            .unwrap();
+        assert_eq!(function_order(&hot), ["square", "add", "main", "unused"]);
+        assert_eq!(run(&hot.lines), 16);
+        assert_eq!(hot.size_before, hot.size_after);
+
+        let unknown = Linker::new(program())
+            .with_layout(Layout::Ordered(vec!["Main.nope".into()]))
+            .link();
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        let program = Linker::new(program()).with_strip_dead(true).link().unwrap();
+        let unused = &fragments("Main", MAIN)[1];
+
+        assert_eq!(
+            program.size_before - program.size_after,
+            instruction_count(&unused.lines)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let undefined = Linker::new(fragments("Boot", BOOT)).link();
+        assert!(undefined.is_err_and(|e| e.to_string().contains("`Main.main`")));
+
+        let mut twice = program();
+        twice.extend(fragments(
+            "Main",
+            "function Main.add 0\npush constant 0\nreturn",
+        ));
+        assert!(Linker::new(twice).link().is_err());
+
+        let unknown_entry = Linker::new(program()).with_entry("Main.nope").link();
+        assert!(unknown_entry.is_err());
+
+        let no_roots = Linker::new(fragments("Main", MAIN))
+            .with_strip_dead(true)
+            .link();
+        assert!(no_roots.is_err());
+    }
+}
@@ -1,7 +1,15 @@
+use anyhow::Context as _;
 use clap::Parser as _;
-use std::env;
+use clap::ValueEnum as _;
+use n2t_core::build_dir::BuildDir;
+use n2t_core::files::has_extension;
+use n2t_core::line_map::LineMap;
+use n2t_core::profiling;
+use n2t_core::version::Version;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
 use std::ffi::OsString;
-use std::fs::{create_dir_all, File, OpenOptions};
+use std::fs::File;
 use std::io::Write;
 use std::str::FromStr;
 use std::{
@@ -9,60 +17,680 @@ use std::{
     path::{Path, PathBuf},
 };
 
+#[cfg(test)]
+mod hack_emulator;
+mod arity;
+mod debug_info;
+mod emitter;
+mod examples;
+mod function_table;
+mod grammar;
+mod limits;
+mod linker;
 mod parser;
+mod provenance;
+mod prune;
+mod response_file;
 mod scanner;
+mod session;
+mod token_dump;
 mod translator;
 
-use crate::parser::Parser;
+use crate::debug_info::{DebugInfo, Outline};
+use crate::emitter::{AsmWriter, Emitter, FragmentWriter};
+use crate::limits::{Limits, Usage};
+use crate::linker::{Fragment, Layout, Linker, instruction_count};
+use crate::parser::{Node, Parser};
 use crate::scanner::Scanner;
+use crate::session::{Comparison, Options, Session};
+use crate::function_table::FunctionTable;
+use crate::token_dump::TokenDump;
 use crate::translator::Translator;
 
-const DEBUG_ALL: &str = "DEBUG_ALL";
-const DEBUG_TOKENS: &str = "DEBUG_TOKENS";
-const DEBUG_AST: &str = "DEBUG_AST";
-
 const VM_EXT: &str = "vm";
+const ASM_EXT: &str = "asm";
+const JACK_EXT: &str = "jack";
+/// Class whose `Sys.init` the program starts with.
+const SYS_CLASS: &str = "Sys";
+/// Written by the Jack compiler next to the `.vm` files it produced.
+const MANIFEST: &str = "manifest.json";
+
+static VERSION: Lazy<Version> = Lazy::new(|| n2t_core::version!("profiling"));
 
 #[derive(clap::Parser)]
-#[command(about = "Jack language VM translator", long_about = None)]
+#[command(
+    version = VERSION.version,
+    long_version = VERSION.long(),
+    about = "Jack language VM translator",
+    long_about = examples::LONG_ABOUT,
+    after_long_help = examples::long_help()
+)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Input .vm file or directory
-    input: PathBuf,
+    /// Input .vm files or directories, translated in the given order; `@file` reads
+    /// more arguments from a response file, one per line
+    #[arg(required_unless_present = "emit_grammar")]
+    input: Vec<PathBuf>,
 
     /// Output .asm file
     #[arg(short = 'o', long, help = ".asm output")]
     output: Option<PathBuf>,
+
+    /// Optimization level: 1 - shorter `return` sequence, in-place stack-top arithmetic
+    #[arg(short = 'O', long, default_value_t = 0)]
+    opt_level: u8,
+
+    /// Comment every push/pop translation with the address it works out, such as
+    /// `// push argument 3: ARG=RAM[2], offset 3 -> RAM[ARG+3]`
+    #[arg(long)]
+    annotate_math: bool,
+
+    /// Code of `eq`, `gt` and `lt`
+    #[arg(long, value_enum, default_value_t = Comparison::Inline)]
+    comparison: Comparison,
+
+    /// Write one `.asm` fragment per function into the output directory
+    #[arg(long, conflicts_with = "link")]
+    fragments: bool,
+
+    /// Also translate the OS classes of this directory (such as the course's `tools/OS`)
+    /// that the input does not provide
+    #[arg(long, value_name = "DIR", conflicts_with = "link")]
+    with_os: Option<PathBuf>,
+
+    /// Translate every function of the `--with-os` classes, not only those the program calls
+    #[arg(long, requires = "with_os")]
+    keep_unused_os: bool,
+
+    /// Link the `.asm` fragments of the input directory into one program
+    #[arg(long)]
+    link: bool,
+
+    /// Function placed first when linking (default: `Sys.init` when present)
+    #[arg(long, requires = "link")]
+    entry: Option<String>,
+
+    /// Drop functions not reachable from the entry function or top-level code when linking
+    #[arg(long, requires = "link")]
+    strip_dead: bool,
+
+    /// File listing the functions to place first when linking, one per line
+    #[arg(long, requires = "link", conflicts_with = "hot_first")]
+    order: Option<PathBuf>,
+
+    /// Place the functions with the most call sites first when linking
+    #[arg(long, requires = "link")]
+    hot_first: bool,
+
+    /// `exports.json` of a library compiled with the Jack compiler's `--lib`: calls of
+    /// its subroutines are checked against it instead of being reported as unresolved
+    #[arg(long, value_name = "FILE", conflicts_with = "link")]
+    exports: Vec<PathBuf>,
+
+    /// Start the output with comments recording the tool version, flags and input hashes
+    #[arg(long, conflicts_with_all = ["fragments", "link"])]
+    provenance: bool,
+
+    /// Extensions of the VM files taken from a directory, matched case-insensitively
+    #[arg(long = "source-ext", value_name = "EXT", value_delimiter = ',', default_value = VM_EXT)]
+    source_exts: Vec<String>,
+
+    /// Extension of the default output file
+    #[arg(long, value_name = "EXT", default_value = ASM_EXT)]
+    output_ext: String,
+
+    /// Only warn when the program exceeds a Hack platform limit (ROM, statics, temps, stack)
+    #[arg(long)]
+    ignore_limits: bool,
+
+    /// Directory of the DEBUG_* dumps, instead of $N2T_BUILD_DIR or target-n2t
+    #[arg(long, value_name = "DIR")]
+    build_dir: Option<String>,
+
+    /// Fail when the program takes more ROM words than this, instead of the platform's 32768
+    #[arg(long, value_name = "N")]
+    max_rom: Option<usize>,
+
+    /// Fail when the output files take more bytes than this
+    #[arg(long, value_name = "N")]
+    max_output_bytes: Option<usize>,
+
+    /// Additionally: Output the tokens of every file with their kind and position as JSON
+    #[arg(long, value_name = "FILE", conflicts_with = "link")]
+    dump: Option<PathBuf>,
+
+    /// Additionally: Output the functions with their locals and files as JSON, for the
+    /// assembler's --run to name the frames of call stacks
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["link", "fragments"])]
+    functions: Option<PathBuf>,
+
+    /// Additionally: Output the .vm line every stretch of the output comes from as JSON,
+    /// for the assembler's --coverage to count hits of VM lines
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["link", "fragments"])]
+    line_map: Option<PathBuf>,
+
+    /// Additionally: Output what the generated labels and the static variables stand for
+    /// to a .dbg file next to the output, for a debugger to show them by their VM names
+    #[arg(long, conflicts_with_all = ["link", "fragments"])]
+    debug_info: bool,
+
+    /// Write TextMate and Tree-sitter grammar stubs for VM code into the directory
+    #[arg(long, value_name = "DIR", exclusive = true)]
+    emit_grammar: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Show typical runs of the translator and the tools around it
+    Examples {
+        /// Write them as the README's Markdown
+        #[arg(long)]
+        markdown: bool,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let input_path = &cli.input;
-    let output_path = &cli.output.unwrap_or_else(|| default_output(&cli.input));
-    println!("[->] Input: {}", input_path.display());
+    let cli = Cli::parse_from(response_file::expand(std::env::args_os())?);
+    profiling::start()?;
+    let result = execute(cli);
+    profiling::finish()?;
+
+    result
+}
+
+fn execute(cli: Cli) -> anyhow::Result<()> {
+    if let Some(Command::Examples { markdown }) = cli.command {
+        match markdown {
+            true => print!("{}", examples::markdown()),
+            false => print!("{}", examples::long_help()),
+        }
+        return Ok(());
+    }
+    if let Some(grammar_dir) = cli.emit_grammar {
+        return grammar::emit(&grammar_dir);
+    }
+
+    let inputs = &cli.input;
+    let input_path = &inputs[0];
+    if inputs.len() > 1 {
+        if cli.link {
+            anyhow::bail!("--link takes a single directory of fragments");
+        }
+        if cli.output.is_none() {
+            anyhow::bail!("Several inputs need an output given with -o");
+        }
+    }
+    let output_ext = cli.output_ext.trim_start_matches('.');
+    let output_path = &cli.output.unwrap_or_else(|| {
+        if cli.link {
+            input_path.with_extension(output_ext)
+        } else if cli.fragments {
+            default_output(input_path).with_extension("fragments")
+        } else {
+            default_output(input_path).with_extension(output_ext)
+        }
+    });
+    for input_path in inputs {
+        println!("[->] Input: {}", input_path.display());
+    }
     println!("[<-] Output: {}", output_path.display());
 
-    if input_path.is_dir() {
-        for entry in std::fs::read_dir(input_path)? {
-            let path = entry?.path();
-            if path.is_file() {
-                if let Some(e) = path.extension().and_then(|s| s.to_str()) {
-                    if e.eq_ignore_ascii_case(VM_EXT) {
-                        let source = read_to_string(&path)?;
-                        let _ = handle_file(source, &path, output_path)?;
-                    }
-                }
-            }
+    let mut session = Session::new(
+        Options {
+            opt_level: cli.opt_level,
+            comparison: cli.comparison,
+            annotate_math: cli.annotate_math,
+            fragments: cli.fragments,
+            limits: Limits {
+                rom: cli.max_rom.unwrap_or(Limits::default().rom),
+                output_bytes: cli.max_output_bytes,
+                ..Default::default()
+            },
+            ignore_limits: cli.ignore_limits,
+            ..Default::default()
+        }
+        .with_env(cli.build_dir.as_deref().map(BuildDir::new)),
+    );
+    if cli.dump.is_some() {
+        session.token_dump = Some(TokenDump::new());
+    }
+    if cli.functions.is_some() {
+        session.function_table = Some(FunctionTable::new());
+    }
+    if cli.line_map.is_some() {
+        session.line_map = Some(LineMap::default());
+    }
+    if cli.debug_info {
+        session.debug_info = Some(DebugInfo::new());
+    }
+
+    if cli.link {
+        let layout = match cli.order {
+            Some(order) => Layout::Ordered(
+                read_to_string(order)?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            None if cli.hot_first => Layout::HotFirst,
+            None => Layout::Original,
+        };
+
+        link(
+            input_path,
+            output_path,
+            cli.entry,
+            cli.strip_dead,
+            layout,
+            &mut session,
+        )?;
+
+        return check_limits(&mut session, output_path);
+    }
+
+    let mut files = input_files(inputs, &cli.source_exts)?;
+    let own_files = files.clone();
+    if let Some(os_dir) = &cli.with_os {
+        files = with_os(files, os_dir, &cli.source_exts)?;
+    }
+    if inputs.len() > 1 || input_path.is_dir() || !cli.exports.is_empty() {
+        let mut exports = BTreeMap::new();
+        for path in cli.exports.iter() {
+            exports.append(&mut arity::read_exports(path)?);
         }
+        check_calls(&files, &exports)?;
+    }
+
+    let mut emitter: Box<dyn Emitter> = match cli.fragments {
+        true => Box::new(FragmentWriter::create(output_path)?),
+        false => Box::new(AsmWriter::create(output_path)?),
+    };
+    if cli.provenance {
+        write_provenance(&files, emitter.as_mut(), cli.opt_level, cli.comparison)?;
+    }
+
+    let mut sources = files
+        .iter()
+        .map(read_to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    if cli.with_os.is_some() && !cli.keep_unused_os {
+        let units: Vec<_> = files
+            .iter()
+            .zip(sources.iter())
+            .map(|(path, source)| prune::File {
+                source,
+                os: !own_files.contains(path),
+            })
+            .collect();
+        let pruned = prune::prune(&units)?;
+        println!(
+            "[~] OS: {} functions kept, {} never called dropped",
+            pruned.kept,
+            pruned.dropped.len()
+        );
+        sources = pruned.sources;
+    }
+
+    for (path, source) in files.iter().zip(sources) {
+        handle_file(source, path, output_path, emitter.as_mut(), &mut session)?;
+    }
+    emitter.finish()?;
 
+    if let (Some(dump_path), Some(token_dump)) = (&cli.dump, &session.token_dump) {
+        token_dump.write(dump_path)?;
+    }
+    if let (Some(table_path), Some(function_table)) = (&cli.functions, &session.function_table)
+    {
+        function_table.write(table_path)?;
+    }
+    if let (Some(map_path), Some(line_map)) = (&cli.line_map, &session.line_map) {
+        line_map.write(map_path)?;
+        println!("[<-] Line map: {}", map_path.display());
+    }
+    if let Some(debug_info) = &session.debug_info {
+        debug_info.write(&output_path.with_extension("dbg"))?;
+    }
+
+    check_limits(&mut session, output_path)
+}
+
+/// Fails listing every call of `files` to a missing function, or with an argument
+/// count other calls or the function disagree with, before anything is written.
+fn check_calls(files: &[PathBuf], exports: &BTreeMap<String, u16>) -> anyhow::Result<()> {
+    let sources = files
+        .iter()
+        .map(read_to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut parsed = vec![];
+    for (path, source) in files.iter().zip(sources.iter()) {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+        let file = path.file_name().unwrap_or(path.as_os_str());
+        parsed.push((file.to_string_lossy().into_owned(), nodes?));
+    }
+
+    let problems = arity::check(&parsed, exports);
+    if !problems.is_empty() {
+        anyhow::bail!("{}", problems.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// Fails listing every exceeded limit and the size of the output at `output_path`,
+/// unless only warnings are asked for.
+fn check_limits(session: &mut Session, output_path: &Path) -> anyhow::Result<()> {
+    session.usage.output_bytes = output_size(output_path)?;
+    let options = &session.options;
+    let exceeded = options.limits.check(&session.usage, options.opt_level);
+    if exceeded.is_empty() {
         return Ok(());
+    }
+
+    let size = format!(
+        "Size: {} ROM words, {} bytes in {}",
+        session.usage.rom,
+        session.usage.output_bytes,
+        output_path.display()
+    );
+    if options.ignore_limits {
+        for limit in exceeded.iter() {
+            println!("[~] {limit}");
+        }
+        println!("[~] {size}");
     } else {
-        let source = read_to_string(&input_path)?;
+        let mut report: Vec<_> = exceeded.iter().map(ToString::to_string).collect();
+        report.push(size);
+        anyhow::bail!("{}", report.join("\n"));
+    }
 
-        return handle_file(source, input_path, output_path);
+    Ok(())
+}
+
+/// Bytes of the output file, or of the fragments in the output directory.
+fn output_size(output_path: &Path) -> anyhow::Result<usize> {
+    if !output_path.is_dir() {
+        return Ok(output_path
+            .metadata()
+            .map_or(0, |metadata| metadata.len() as usize));
     }
+
+    let mut size = 0;
+    for entry in std::fs::read_dir(output_path)? {
+        size += entry?.metadata()?.len() as usize;
+    }
+
+    Ok(size)
 }
 
-fn handle_file<P>(source: String, input_file_path: P, output_path: P) -> anyhow::Result<()>
+/// `.vm` files of `inputs` in the order given, a directory's files in place of it.
+fn input_files(inputs: &[PathBuf], exts: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = vec![];
+    for input_path in inputs {
+        let paths = if input_path.is_dir() {
+            let dir_files = vm_files(input_path, exts)?;
+            for warning in stale_warnings(input_path, &dir_files)? {
+                println!("[~] {warning}");
+            }
+
+            dir_files
+        } else {
+            vec![input_path.to_path_buf()]
+        };
+
+        for path in paths {
+            if files.contains(&path) {
+                anyhow::bail!("{} is given more than once", path.display());
+            }
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+#[derive(serde::Deserialize)]
+struct Manifest {
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct ManifestEntry {
+    /// Relative to the manifest's directory
+    vm: PathBuf,
+}
+
+/// VM files of `dir`, those with one of the `exts`, sorted so the output does not
+/// depend on the directory order, `Sys.vm` first. With a [`MANIFEST`] only the files
+/// it lists are taken, leaving stale ones out.
+fn vm_files(dir: &Path, exts: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let manifest_path = dir.join(MANIFEST);
+    if manifest_path.is_file() {
+        return manifest_vm_files(dir, &manifest_path, exts);
+    }
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && has_extension(&path, exts) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    sys_first(&mut files);
+
+    Ok(files)
+}
+
+/// Moves `Sys.vm` to the front of `files`. Nothing is written before the first file,
+/// so it sits at ROM[0] and `Sys.init` must be what the program starts with.
+fn sys_first(files: &mut [PathBuf]) {
+    if let Some(i) = files.iter().position(|path| is_sys(path)) {
+        files[..=i].rotate_right(1);
+    }
+}
+
+fn is_sys(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| stem == SYS_CLASS)
+}
+
+/// `.vm` files among `files` that look left over from an earlier compile: older than
+/// their `.jack` source, or without one in a directory of Jack sources.
+fn stale_warnings(dir: &Path, files: &[PathBuf]) -> anyhow::Result<Vec<String>> {
+    let jack_ext = [JACK_EXT.to_string()];
+    let mut sources = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if has_extension(&path, &jack_ext) {
+            sources.push(path);
+        }
+    }
+
+    let mut warnings = vec![];
+    for path in files {
+        // `Main.JACK` is the source of `Main.vm` too
+        let source = sources
+            .iter()
+            .find(|source| source.file_stem() == path.file_stem());
+        match source {
+            Some(source) if source.metadata()?.modified()? > path.metadata()?.modified()? => {
+                warnings.push(format!(
+                    "{} is older than {}, compile it again",
+                    path.display(),
+                    source.display()
+                ))
+            }
+            Some(_) => {}
+            None if !sources.is_empty() => warnings.push(format!(
+                "{} has no {} next to it and may be stale",
+                path.display(),
+                path.with_extension(JACK_EXT).display()
+            )),
+            None => {}
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn manifest_vm_files(
+    dir: &Path,
+    manifest_path: &Path,
+    exts: &[String],
+) -> anyhow::Result<Vec<PathBuf>> {
+    let manifest: Manifest = serde_json::from_str(&read_to_string(manifest_path)?)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+
+    let mut files = vec![];
+    for entry in manifest.files {
+        let path = dir.join(&entry.vm);
+        if !path.is_file() {
+            anyhow::bail!(
+                "{} lists {}, which does not exist",
+                manifest_path.display(),
+                path.display()
+            );
+        }
+        files.push(path);
+    }
+    files.sort();
+    sys_first(&mut files);
+
+    let mut stale: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| has_extension(path, exts) && !files.contains(path))
+        .collect();
+    stale.sort();
+    for path in stale {
+        println!("[~] Skipping {}: not listed in {MANIFEST}", path.display());
+    }
+
+    Ok(files)
+}
+
+/// `files` followed by the `.vm` files of `os_dir` for the classes they do not
+/// provide, so a user's `Math.vm` replaces the OS one. The OS `Sys.vm` goes first
+/// instead, for `Sys.init` to start the program. A class provided by two files
+/// of the input, or of `os_dir`, is an error.
+fn with_os(files: Vec<PathBuf>, os_dir: &Path, exts: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut providers: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in files.iter() {
+        for class_name in classes(path)? {
+            providers.entry(class_name).or_default().push(path.clone());
+        }
+    }
+
+    let mut os_classes = vec![];
+    let mut overridden = vec![];
+    let mut files = files;
+    for path in vm_files(os_dir, exts)? {
+        let class_names = classes(&path)?;
+        if class_names.iter().any(|name| providers.contains_key(name)) {
+            overridden.extend(class_names);
+            continue;
+        }
+        for class_name in class_names {
+            providers
+                .entry(class_name.clone())
+                .or_default()
+                .push(path.clone());
+            os_classes.push(class_name);
+        }
+        match is_sys(&path) {
+            true => files.insert(0, path),
+            false => files.push(path),
+        }
+    }
+
+    let conflicts: Vec<_> = providers
+        .iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(class_name, paths)| {
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            format!(
+                "Class {class_name} is provided twice: by {}",
+                paths.join(" and ")
+            )
+        })
+        .collect();
+    if !conflicts.is_empty() {
+        anyhow::bail!("{}", conflicts.join("\n"));
+    }
+
+    println!(
+        "[->] OS: {} from {}",
+        os_classes.join(", "),
+        os_dir.display()
+    );
+    if !overridden.is_empty() {
+        println!("[->] OS: {} from the input", overridden.join(", "));
+    }
+
+    Ok(files)
+}
+
+/// Classes whose functions `path` defines, or its name when it defines none.
+fn classes(path: &Path) -> anyhow::Result<Vec<String>> {
+    let source = read_to_string(path)?;
+    let tokens: Result<Vec<_>, _> = Scanner::new(&source).collect();
+    let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+
+    let mut class_names = vec![];
+    for node in nodes? {
+        if let Node::Function { name, .. } = node {
+            let class_name = name
+                .split_once('.')
+                .map_or(&*name, |(class_name, _)| class_name);
+            if !class_names.iter().any(|other| other == class_name) {
+                class_names.push(class_name.to_string());
+            }
+        }
+    }
+    if class_names.is_empty() {
+        class_names.push(filename(path).to_string_lossy().into_owned());
+    }
+
+    Ok(class_names)
+}
+
+/// Starts the output with comments recording the translator version, flags and input hashes.
+fn write_provenance(
+    files: &[PathBuf],
+    emitter: &mut dyn Emitter,
+    opt_level: u8,
+    comparison: Comparison,
+) -> anyhow::Result<()> {
+    let mut inputs = vec![];
+    for path in files {
+        let source = read_to_string(path)?;
+        inputs.push((path.display().to_string(), source));
+    }
+
+    let mut flags = format!("-O {opt_level}");
+    if comparison != Comparison::Inline {
+        let value = comparison
+            .to_possible_value()
+            .expect("no variant is skipped");
+        flags += &format!(" --comparison {}", value.get_name());
+    }
+    emitter.emit_header(&provenance::header(&flags, &inputs))
+}
+
+fn handle_file<P>(
+    source: String,
+    input_file_path: P,
+    output_path: P,
+    emitter: &mut dyn Emitter,
+    session: &mut Session,
+) -> anyhow::Result<()>
 where
     P: AsRef<Path>,
 {
@@ -72,42 +700,117 @@ where
     );
 
     // 1. Scanning ..
-    let tokens: Result<Vec<_>, _> = Scanner::new(&source).into_iter().collect();
+    let tokens: Result<Vec<_>, _> = profiling::stage("scan", || Scanner::new(&source).collect());
     let tokens = tokens?;
-    if test_debug(DEBUG_TOKENS) {
-        let mut debug_output_file = create_debug_file(&input_file_path, "tokens")?;
+    if session.options.debug_tokens {
+        let mut debug_output_file = create_debug_file(session, input_file_path.as_ref(), "tokens")?;
 
         for token in tokens.iter() {
             let _ = writeln!(&mut debug_output_file, "{token:#?}");
         }
     }
+    if let Some(token_dump) = session.token_dump.as_mut() {
+        token_dump.add(input_file_path.as_ref(), &tokens);
+    }
 
     // 2. Parsing ..
-    let nodes: Result<Vec<_>, _> = Parser::new(tokens.into_iter()).collect();
+    let mut parser = Parser::new(tokens.into_iter());
+    let nodes: Result<Vec<_>, _> = profiling::stage("parse", || parser.by_ref().collect());
     let nodes = nodes?;
-    if test_debug(DEBUG_AST) {
-        let mut debug_output_file = create_debug_file(&input_file_path, "ast")?;
+    let lines = parser.lines().to_vec();
+    if session.options.debug_ast {
+        let mut debug_output_file = create_debug_file(session, input_file_path.as_ref(), "ast")?;
 
         for node in nodes.iter() {
             writeln!(&mut debug_output_file, "{node:#?}")?;
         }
     }
 
+    if let Some(function_table) = session.function_table.as_mut() {
+        function_table.add(input_file_path.as_ref(), &nodes);
+    }
+
+    let mut usage = Usage::of(&nodes);
+    let outline = session.debug_info.is_some().then(|| Outline::of(&nodes));
+
     // 2. Translating ..
     let stem = filename(input_file_path.as_ref());
-    let translator = Translator::new(stem.display().to_string(), nodes);
-    let instructions = translator.translate();
+    let translator = Translator::new(stem.display().to_string(), nodes)
+        .with_opt_level(session.options.opt_level)
+        .with_comparison(session.options.comparison)
+        .with_annotate_math(session.options.annotate_math);
+    if session.options.fragments {
+        let fragments = profiling::stage("translate", || translator.translate_fragments());
+        for fragment in fragments {
+            usage.rom += instruction_count(&fragment.lines);
+            emitter.emit_fragment(&fragment)?;
+        }
+        session.usage.add(&usage);
 
-    let mut output_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(output_path)?;
+        return Ok(());
+    }
+    let with_starts = session.line_map.is_some() || session.debug_info.is_some();
+    let (instructions, starts) = profiling::stage("translate", || match with_starts {
+        true => translator.translate_with_starts(),
+        false => (translator.translate(), vec![]),
+    });
+    usage.rom = instruction_count(&instructions);
+    session.usage.add(&usage);
 
-    for instruction in instructions {
-        writeln!(&mut output_file, "{}", instruction)?;
+    if let Some(line_map) = session.line_map.as_mut() {
+        // After the provenance header and the files before
+        let written = emitter.lines();
+        let output = output_path.as_ref().display().to_string();
+        let input = input_file_path.as_ref().display().to_string();
+        for (start, line) in starts.iter().zip(&lines) {
+            line_map.push(&output, written + start + 1, &input, *line);
+        }
     }
 
-    Ok(())
+    if let (Some(debug_info), Some(outline)) = (session.debug_info.as_mut(), &outline) {
+        debug_info.add(
+            input_file_path.as_ref(),
+            &stem.display().to_string(),
+            outline,
+            &instructions,
+            &starts,
+            &lines,
+        );
+    }
+
+    profiling::stage("write", || {
+        emitter.emit_asm(input_file_path.as_ref(), &instructions)
+    })
+}
+
+fn link(
+    input_path: &Path,
+    output_path: &Path,
+    entry: Option<String>,
+    strip_dead: bool,
+    layout: Layout,
+    session: &mut Session,
+) -> anyhow::Result<()> {
+    let mut linker = Linker::new(Fragment::read_dir(input_path)?)
+        .with_strip_dead(strip_dead)
+        .with_layout(layout);
+    if let Some(entry) = entry {
+        linker = linker.with_entry(entry);
+    }
+    let program = profiling::stage("link", || linker.link())?;
+
+    for name in program.stripped.iter() {
+        println!("[~] Stripped dead function: {name}");
+    }
+    println!(
+        "[~] Size: {} instructions before, {} after",
+        program.size_before, program.size_after
+    );
+    session.usage.rom = program.size_after;
+
+    let mut writer = AsmWriter::create(output_path)?;
+    writer.emit_asm(input_path, &program.lines)?;
+    writer.finish()
 }
 
 fn filename(input: &Path) -> OsString {
@@ -128,26 +831,157 @@ fn default_output(input: &Path) -> PathBuf {
     }
 }
 
-fn create_debug_file<P, S>(path: P, suffix: S) -> anyhow::Result<File>
-where
-    P: AsRef<Path>,
-    S: AsRef<str>,
-{
-    let parent_path = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
-    let file_name = path.as_ref().file_name().expect("").display();
-    let debug_dir = parent_path.join(format!("{file_name}_debug",));
+/// The `suffix` dump of `path`, in the build directory.
+fn create_debug_file(session: &Session, path: &Path, suffix: &str) -> anyhow::Result<File> {
+    let build_dir = &session.options.build_dir;
 
-    create_dir_all(&debug_dir)?;
+    build_dir.create(env!("CARGO_PKG_NAME"), path, suffix)
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use super::*;
 
-    let path = debug_dir.join(format!("{}.{}", file_name, suffix.as_ref()));
-    let debug_output_file = File::create(path)?;
+    #[test]
+    fn translates_only_the_files_in_the_manifest() {
+        let dir = std::env::temp_dir().join(format!("vm-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exts = [VM_EXT.to_string()];
+        for name in ["Main.vm", "Stale.vm"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
 
-    Ok(debug_output_file)
+        assert_eq!(vm_files(&dir, &exts).unwrap().len(), 2);
+
+        std::fs::write(dir.join(MANIFEST), r#"{ "files": [{ "vm": "Main.vm" }] }"#).unwrap();
+        assert_eq!(vm_files(&dir, &exts).unwrap(), [dir.join("Main.vm")]);
+
+        std::fs::write(dir.join(MANIFEST), r#"{ "files": [{ "vm": "Gone.vm" }] }"#).unwrap();
+        assert!(vm_files(&dir, &exts).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
 
-fn test_debug<S>(s: S) -> bool
-where
-    S: AsRef<str>,
-{
-    env::var(s.as_ref()).is_ok() || env::var(DEBUG_ALL).is_ok()
+#[cfg(test)]
+mod vm_files_tests {
+    use super::*;
+
+    #[test]
+    fn puts_sys_first() {
+        let dir = std::env::temp_dir().join(format!("vm-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["Main.vm", "Sys.vm", "Array.vm"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let files = vm_files(&dir, &[VM_EXT.to_string()]).unwrap();
+        assert_eq!(
+            files,
+            ["Sys.vm", "Array.vm", "Main.vm"].map(|name| dir.join(name))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod with_os_tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_os_classes_the_input_does_not_provide() {
+        let dir = std::env::temp_dir().join(format!("vm-with-os-{}", std::process::id()));
+        let (input, os) = (dir.join("input"), dir.join("os"));
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::create_dir_all(&os).unwrap();
+        for (path, source) in [
+            (
+                input.join("Main.vm"),
+                "function Main.main 0\ncall Math.multiply 2",
+            ),
+            (input.join("Math.vm"), "function Math.multiply 0"),
+            (
+                os.join("Math.vm"),
+                "function Math.init 0\nfunction Math.multiply 0",
+            ),
+            (os.join("Screen.vm"), "function Screen.init 0"),
+            (os.join("Sys.vm"), "function Sys.init 0"),
+        ] {
+            std::fs::write(path, source).unwrap();
+        }
+
+        let exts = [VM_EXT.to_string()];
+        let files = with_os(vm_files(&input, &exts).unwrap(), &os, &exts).unwrap();
+        assert_eq!(
+            files,
+            [
+                os.join("Sys.vm"),
+                input.join("Main.vm"),
+                input.join("Math.vm"),
+                os.join("Screen.vm")
+            ]
+        );
+
+        // Screen functions in Main.vm replace the OS Screen.vm, but clash with Screen.vm
+        std::fs::write(
+            input.join("Main.vm"),
+            "function Main.main 0\nfunction Screen.init 0",
+        )
+        .unwrap();
+        let files = with_os(vm_files(&input, &exts).unwrap(), &os, &exts).unwrap();
+        assert_eq!(files.len(), 3);
+        std::fs::write(input.join("Screen.vm"), "function Screen.init 0").unwrap();
+        let error = with_os(vm_files(&input, &exts).unwrap(), &os, &exts).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Class Screen is provided twice: by {} and {}",
+                input.join("Main.vm").display(),
+                input.join("Screen.vm").display()
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod stale_tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn touch(path: &Path, time: SystemTime) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn warns_about_outdated_and_sourceless_vm_files() {
+        let dir = std::env::temp_dir().join(format!("vm-stale-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = ["Main.vm", "Old.vm", "Math.vm"].map(|name| dir.join(name));
+        for path in files.iter() {
+            std::fs::write(path, "").unwrap();
+        }
+
+        // Without any .jack, this is a plain VM project
+        assert!(stale_warnings(&dir, &files).unwrap().is_empty());
+
+        let old = SystemTime::now() - Duration::from_secs(60);
+        for name in ["Main", "Old"] {
+            std::fs::write(dir.join(name).with_extension(JACK_EXT), "").unwrap();
+        }
+        for path in files.iter() {
+            touch(path, old);
+        }
+        touch(&files[0], SystemTime::now());
+
+        let warnings = stale_warnings(&dir, &files).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("Old.vm is older than"));
+        assert!(warnings[1].contains("Math.vm has no"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
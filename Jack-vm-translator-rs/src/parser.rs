@@ -3,6 +3,8 @@ use std::iter::Peekable;
 
 use crate::scanner::{Token, TokenType};
 
+const MAX_CONSTANT: u16 = 32767;
+
 macro_rules! consume {
     ($tokens:expr) => {
         $tokens.next().ok_or(anyhow::anyhow!(
@@ -18,24 +20,20 @@ macro_rules! consume_number {
                 token_type: TokenType::NUMBER(value),
                 ..
             } => anyhow::Result::<u16>::Ok(value),
-            token => {
-                anyhow::bail!("Unexpected token. Expected NUMBER but got {:?}", token)
-            }
+            token => return Err(unexpected(&token, "a number")),
         }
     };
 }
 
+/// Names may be spelled like a keyword, e.g. `label not` from other compilers.
 macro_rules! consume_identifier {
     ($tokens:expr) => {
         match consume!($tokens)? {
-            Token {
-                token_type: TokenType::IDENTIFIER,
-                lexeme: lemexe,
+            token @ Token {
+                token_type: TokenType::NUMBER(_) | TokenType::EOF,
                 ..
-            } => anyhow::Result::<Cow<'_, str>>::Ok(lemexe),
-            token => {
-                anyhow::bail!("Unexpected token. Expected IDENTIFIER but got {:?}", token)
-            }
+            } => return Err(unexpected(&token, "a name")),
+            Token { lexeme: lemexe, .. } => anyhow::Result::<Cow<'_, str>>::Ok(lemexe),
         }
     };
 }
@@ -59,6 +57,28 @@ macro_rules! consume_and_ensure_matches {
     };
 }
 
+/// Every VM command, as listed in errors about a line starting with anything else.
+const COMMANDS: &str = "`push`, `pop`, `add`, `sub`, `neg`, `eq`, `gt`, `lt`, `and`, `or`, `not`, \
+                        `label`, `goto`, `if-goto`, `function`, `call` and `return`";
+
+const SEGMENTS: &str =
+    "`argument`, `local`, `static`, `constant`, `this`, `that`, `pointer` and `temp`";
+
+/// Error about `token`, which is not `expected`.
+fn unexpected(token: &Token<'_>, expected: &str) -> anyhow::Error {
+    match token.token_type {
+        TokenType::EOF => anyhow::anyhow!(
+            "[line {}] Error: Expected {expected} but got the end of the file",
+            token.line
+        ),
+        _ => anyhow::anyhow!(
+            "[line {}] Error: Expected {expected} but got `{}`",
+            token.line,
+            token.lexeme
+        ),
+    }
+}
+
 #[derive(Debug)]
 pub enum Segment {
     Argument { offset: u16 },
@@ -92,8 +112,13 @@ pub enum Node<'de> {
     Not,
 }
 
+/// Yields the nodes of a file in order. The first error is the last item: nothing past
+/// a command that could not be read is yielded, so a file is never cut short quietly.
 pub struct Parser<'de, I: Iterator<Item = Token<'de>>> {
     tokens: Peekable<I>,
+    failed: bool,
+    /// The line of every node yielded so far.
+    lines: Vec<usize>,
 }
 
 impl<'de, I> Parser<'de, I>
@@ -103,71 +128,110 @@ where
     pub fn new(tokens: I) -> Parser<'de, I> {
         Parser {
             tokens: tokens.peekable(),
+            failed: false,
+            lines: vec![],
         }
     }
 
+    /// The line of every node yielded so far, in order.
+    pub fn lines(&self) -> &[usize] {
+        &self.lines
+    }
+
+    /// The next node, or `None` at the end of the file. A command ends with its line:
+    /// a token after it on the same line is an error, not the start of the next one.
     pub fn parse(&mut self) -> Option<anyhow::Result<Node<'de>>> {
-        while let Some(token) = self.tokens.peek() {
-            if matches!(token.token_type, TokenType::EOF) {
-                return None;
-            }
+        let line = self.tokens.peek().map(|token| token.line);
+        let node = self.parse_command()?;
 
-            if matches!(token.token_type, TokenType::PUSH) {
-                return Some(self.parse_push());
-            }
-            if matches!(token.token_type, TokenType::POP) {
-                return Some(self.parse_pop());
-            }
-            if matches!(token.token_type, TokenType::LABEL) {
-                return Some(self.parse_label());
-            }
-            if matches!(token.token_type, TokenType::IF_GOTO) {
-                return Some(self.parse_if_goto());
-            }
-            if matches!(token.token_type, TokenType::GOTO) {
-                return Some(self.parse_goto());
-            }
-            if matches!(token.token_type, TokenType::FUNCTION) {
-                return Some(self.parse_function());
-            }
-            if matches!(token.token_type, TokenType::RETURN) {
-                return Some(self.parse_return());
-            }
-            if matches!(token.token_type, TokenType::CALL) {
-                return Some(self.parse_call());
+        Some(node.and_then(|node| {
+            if let Some(line) = line {
+                self.ensure_line_ends(line)?;
             }
-            if matches!(token.token_type, TokenType::ADD) {
-                return Some(self.parse_add());
-            }
-            if matches!(token.token_type, TokenType::SUB) {
-                return Some(self.parse_sub());
-            }
-            if matches!(token.token_type, TokenType::NEG) {
-                return Some(self.parse_neg());
-            }
-            if matches!(token.token_type, TokenType::EQ) {
-                return Some(self.parse_eq());
-            }
-            if matches!(token.token_type, TokenType::GT) {
-                return Some(self.parse_gt());
-            }
-            if matches!(token.token_type, TokenType::LT) {
-                return Some(self.parse_lt());
-            }
-            if matches!(token.token_type, TokenType::AND) {
-                return Some(self.parse_and());
-            }
-            if matches!(token.token_type, TokenType::OR) {
-                return Some(self.parse_or());
-            }
-            if matches!(token.token_type, TokenType::NOT) {
-                return Some(self.parse_not());
+            self.lines.push(line.unwrap_or(0));
+
+            Ok(node)
+        }))
+    }
+
+    fn ensure_line_ends(&mut self, line: usize) -> anyhow::Result<()> {
+        match self.tokens.peek() {
+            Some(token) if token.line == line && !matches!(token.token_type, TokenType::EOF) => {
+                anyhow::bail!(
+                    "[line {}:{}] Error: Unexpected `{}` after the command; each command takes a line of its own",
+                    token.line,
+                    token.column,
+                    token.lexeme
+                )
             }
+            _ => Ok(()),
+        }
+    }
 
+    fn parse_command(&mut self) -> Option<anyhow::Result<Node<'de>>> {
+        let Some(token) = self.tokens.peek() else {
+            return Some(Err(anyhow::anyhow!(
+                "Error: The tokens ended before the end of the file"
+            )));
+        };
+        if matches!(token.token_type, TokenType::EOF) {
             return None;
         }
 
-        unreachable!()
+        if matches!(token.token_type, TokenType::PUSH) {
+            return Some(self.parse_push());
+        }
+        if matches!(token.token_type, TokenType::POP) {
+            return Some(self.parse_pop());
+        }
+        if matches!(token.token_type, TokenType::LABEL) {
+            return Some(self.parse_label());
+        }
+        if matches!(token.token_type, TokenType::IF_GOTO) {
+            return Some(self.parse_if_goto());
+        }
+        if matches!(token.token_type, TokenType::GOTO) {
+            return Some(self.parse_goto());
+        }
+        if matches!(token.token_type, TokenType::FUNCTION) {
+            return Some(self.parse_function());
+        }
+        if matches!(token.token_type, TokenType::RETURN) {
+            return Some(self.parse_return());
+        }
+        if matches!(token.token_type, TokenType::CALL) {
+            return Some(self.parse_call());
+        }
+        if matches!(token.token_type, TokenType::ADD) {
+            return Some(self.parse_add());
+        }
+        if matches!(token.token_type, TokenType::SUB) {
+            return Some(self.parse_sub());
+        }
+        if matches!(token.token_type, TokenType::NEG) {
+            return Some(self.parse_neg());
+        }
+        if matches!(token.token_type, TokenType::EQ) {
+            return Some(self.parse_eq());
+        }
+        if matches!(token.token_type, TokenType::GT) {
+            return Some(self.parse_gt());
+        }
+        if matches!(token.token_type, TokenType::LT) {
+            return Some(self.parse_lt());
+        }
+        if matches!(token.token_type, TokenType::AND) {
+            return Some(self.parse_and());
+        }
+        if matches!(token.token_type, TokenType::OR) {
+            return Some(self.parse_or());
+        }
+        if matches!(token.token_type, TokenType::NOT) {
+            return Some(self.parse_not());
+        }
+
+        let error = unexpected(token, "a command");
+        Some(Err(anyhow::anyhow!("{error}; the commands are {COMMANDS}")))
     }
 
     fn parse_push(&mut self) -> anyhow::Result<Node<'de>> {
@@ -179,7 +243,15 @@ where
 
     fn parse_pop(&mut self) -> anyhow::Result<Node<'de>> {
         let _ = consume_and_ensure_matches!(self.tokens, TokenType::POP)?;
+        let (line, column) = self
+            .tokens
+            .peek()
+            .map_or((0, 0), |token| (token.line, token.column));
         let segment = self.parse_segment()?;
+        // Constants are only pushed, there is no RAM behind them
+        if matches!(segment, Segment::Constant { .. }) {
+            anyhow::bail!("[line {line}:{column}] Error: Cannot pop into the constant segment");
+        }
 
         Ok(Node::Pop { segment })
     }
@@ -310,7 +382,8 @@ where
             return self.parse_temp_segment();
         }
 
-        anyhow::bail!("Could not parse the segment")
+        let error = unexpected(token, "a segment");
+        anyhow::bail!("{error}; the segments are {SEGMENTS}")
     }
 
     fn parse_argument_segment(&mut self) -> anyhow::Result<Segment> {
@@ -336,7 +409,17 @@ where
 
     fn parse_constant_segment(&mut self) -> anyhow::Result<Segment> {
         let _ = consume_and_ensure_matches!(self.tokens, TokenType::CONSTANT)?;
+        let (line, column) = self
+            .tokens
+            .peek()
+            .map_or((0, 0), |token| (token.line, token.column));
         let value = consume_number!(self.tokens)?;
+        // Loaded by a single A-instruction, which holds 15 bits
+        if value > MAX_CONSTANT {
+            anyhow::bail!(
+                "[line {line}:{column}] Error: Constant `{value}` is out of range 0..={MAX_CONSTANT}"
+            );
+        }
 
         Ok(Segment::Constant { value })
     }
@@ -357,7 +440,15 @@ where
 
     fn parse_pointer_segment(&mut self) -> anyhow::Result<Segment> {
         let _ = consume_and_ensure_matches!(self.tokens, TokenType::POINTER)?;
+        let (line, column) = self
+            .tokens
+            .peek()
+            .map_or((0, 0), |token| (token.line, token.column));
         let offset = consume_number!(self.tokens)?;
+        // pointer 0 is THIS, pointer 1 is THAT
+        if offset > 1 {
+            anyhow::bail!("[line {line}:{column}] Error: Pointer `{offset}` is out of range 0..=1");
+        }
 
         Ok(Segment::Pointer { offset })
     }
@@ -377,6 +468,100 @@ where
     type Item = anyhow::Result<Node<'de>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.parse()
+        if self.failed {
+            return None;
+        }
+        let node = self.parse();
+        self.failed = matches!(node, Some(Err(_)));
+
+        node
+    }
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<anyhow::Result<Node<'_>>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+
+        Parser::new(tokens.unwrap().into_iter()).collect()
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let nodes = parse("push constant 1\nfoo\nadd\npush heap 2\nreturn");
+
+        assert_eq!(nodes.len(), 2);
+        assert!(matches!(nodes[0], Ok(Node::Push { .. })));
+        let error = nodes[1].as_ref().unwrap_err().to_string();
+        assert!(error.starts_with("[line 2] Error: Expected a command but got `foo`"));
+
+        let nodes = parse("push constant 1\npush local");
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes[1].is_err());
+    }
+
+    #[test]
+    fn reports_tokens_ending_before_the_end_of_file() {
+        let tokens: Result<Vec<_>, _> = Scanner::new("push constant 1\nadd").collect();
+        let mut tokens = tokens.unwrap();
+        tokens.pop();
+
+        let nodes: Vec<_> = Parser::new(tokens.into_iter()).collect();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(
+            nodes[2].as_ref().unwrap_err().to_string(),
+            "Error: The tokens ended before the end of the file"
+        );
+    }
+
+    #[test]
+    fn rejects_tokens_after_a_command_on_its_line() {
+        let nodes = parse("push constant 5 7\nadd");
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            nodes[0].as_ref().unwrap_err().to_string(),
+            "[line 1:17] Error: Unexpected `7` after the command; each command takes a line of its own"
+        );
+
+        let nodes = parse("function Main.main 0\n  return add // done\n");
+        assert_eq!(nodes.len(), 2);
+        assert!(
+            nodes[1]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .starts_with("[line 2:10] Error: Unexpected `add`")
+        );
+
+        let nodes = parse("push constant 5 // five\nadd");
+        assert!(nodes.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn rejects_pointers_past_that_and_popping_constants() {
+        let nodes = parse("push pointer 1\npush pointer 2");
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(
+            nodes[1].as_ref().unwrap_err().to_string(),
+            "[line 2:14] Error: Pointer `2` is out of range 0..=1"
+        );
+
+        let nodes = parse("push constant 1\n  pop constant 1");
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(
+            nodes[1].as_ref().unwrap_err().to_string(),
+            "[line 2:7] Error: Cannot pop into the constant segment"
+        );
+    }
+
+    #[test]
+    fn yields_every_node_of_a_whole_file() {
+        let nodes = parse("function Main.main 0\npush constant 1\nreturn\n");
+
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes.iter().all(Result::is_ok));
     }
 }
@@ -0,0 +1,36 @@
+//! Header comments recording how an `.asm` file was produced.
+
+pub use n2t_core::provenance::{PREFIX, sha256_hex};
+
+/// `inputs` are `(path, source)` pairs.
+pub fn header(flags: &str, inputs: &[(String, String)]) -> Vec<String> {
+    let mut lines = vec![
+        format!("{PREFIX}{}", crate::VERSION.provenance()),
+        format!("{PREFIX}flags={flags}"),
+    ];
+    for (path, source) in inputs {
+        lines.push(format!(
+            "{PREFIX}input={path} sha256={}",
+            sha256_hex(source.as_bytes())
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod provenance_tests {
+    use super::*;
+
+    #[test]
+    fn header_lines() {
+        let header = header("-O 1", &[("Main.vm".into(), "abc".into())]);
+
+        assert!(header[0].starts_with("// provenance: tool=VMTranslator version=0.1.0 commit="));
+        assert_eq!(header[1], "// provenance: flags=-O 1");
+        assert_eq!(
+            header[2],
+            "// provenance: input=Main.vm sha256=ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
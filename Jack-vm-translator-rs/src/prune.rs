@@ -0,0 +1,173 @@
+//! `--with-os` pruning: the OS functions a program never calls are dropped from the
+//! OS files before translation, as a small program uses a few of the OS's hundreds.
+//!
+//! A function is kept when `Sys.init` or the input reaches it through `call`s: every
+//! function and top-level code of the input files counts, called or not. Labels are
+//! local to their function, so dropping whole functions breaks no jump.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::scanner::{Scanner, TokenType};
+
+/// Run by the VM emulator, or placed first by the linker, without any call of it.
+const ENTRY: &str = "Sys.init";
+
+/// A file to translate, with whether it comes from the OS directory.
+pub struct File<'a> {
+    pub source: &'a str,
+    pub os: bool,
+}
+
+pub struct Pruned {
+    /// Source of each file, without the unreached functions of the OS ones.
+    pub sources: Vec<String>,
+    /// Names of the functions dropped, in file order.
+    pub dropped: Vec<String>,
+    /// Functions of the OS files that are kept.
+    pub kept: usize,
+}
+
+/// A function of a file: its name, first line, and the functions it calls.
+struct Function {
+    name: String,
+    line: usize,
+    calls: Vec<String>,
+}
+
+/// The code before the first function of `source`, as the functions it calls, and
+/// the functions after it.
+fn functions(source: &str) -> anyhow::Result<(Vec<String>, Vec<Function>)> {
+    let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+    let tokens = tokens?;
+
+    let mut top_level = vec![];
+    let mut functions: Vec<Function> = vec![];
+    for pair in tokens.windows(2) {
+        let name = pair[1].lexeme.to_string();
+        match pair[0].token_type {
+            TokenType::FUNCTION => functions.push(Function {
+                name,
+                line: pair[0].line,
+                calls: vec![],
+            }),
+            TokenType::CALL => match functions.last_mut() {
+                Some(function) => function.calls.push(name),
+                None => top_level.push(name),
+            },
+            _ => {}
+        }
+    }
+
+    Ok((top_level, functions))
+}
+
+pub fn prune(files: &[File<'_>]) -> anyhow::Result<Pruned> {
+    let parsed = files
+        .iter()
+        .map(|file| functions(file.source))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut callees: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut roots = vec![ENTRY];
+    for (file, (top_level, functions)) in files.iter().zip(parsed.iter()) {
+        roots.extend(top_level.iter().map(String::as_str));
+        for function in functions.iter() {
+            callees
+                .entry(&function.name)
+                .or_default()
+                .extend(function.calls.iter().map(String::as_str));
+            if !file.os {
+                roots.push(&function.name);
+            }
+        }
+    }
+
+    let mut reached = BTreeSet::new();
+    while let Some(name) = roots.pop() {
+        if reached.insert(name) {
+            roots.extend(callees.get(name).into_iter().flatten());
+        }
+    }
+
+    let mut pruned = Pruned {
+        sources: vec![],
+        dropped: vec![],
+        kept: 0,
+    };
+    for (file, (_, functions)) in files.iter().zip(parsed.iter()) {
+        if !file.os {
+            pruned.sources.push(file.source.to_string());
+            continue;
+        }
+
+        let lines: Vec<_> = file.source.lines().collect();
+        let first = functions
+            .first()
+            .map_or(lines.len(), |function| function.line - 1);
+        let mut kept_lines = lines[..first].to_vec();
+        for (i, function) in functions.iter().enumerate() {
+            let end = functions
+                .get(i + 1)
+                .map_or(lines.len(), |next| next.line - 1);
+            if reached.contains(function.name.as_str()) {
+                kept_lines.extend_from_slice(&lines[function.line - 1..end]);
+                pruned.kept += 1;
+            } else {
+                pruned.dropped.push(function.name.clone());
+            }
+        }
+        pruned.sources.push(kept_lines.join("\n"));
+    }
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod prune_tests {
+    use super::*;
+
+    #[test]
+    fn drops_the_os_functions_never_reached() {
+        let main = "function Main.main 0\ncall Output.printInt 1\nreturn\n\
+                    function Main.unused 0\ncall Math.abs 1\nreturn";
+        let output = "// Output\nfunction Output.printInt 0\ncall Output.printChar 1\nreturn\n\
+                      function Output.printChar 0\nreturn\n\
+                      function Output.println 0\nreturn";
+        let sys = "function Sys.init 0\ncall Main.main 0\nreturn\nfunction Sys.wait 0\nreturn";
+        let math = "function Math.abs 0\nreturn\nfunction Math.sqrt 0\nreturn";
+
+        let pruned = prune(&[
+            File {
+                source: main,
+                os: false,
+            },
+            File {
+                source: output,
+                os: true,
+            },
+            File {
+                source: sys,
+                os: true,
+            },
+            File {
+                source: math,
+                os: true,
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(pruned.sources[0], main);
+        assert_eq!(
+            pruned.sources[1],
+            "// Output\nfunction Output.printInt 0\ncall Output.printChar 1\nreturn\n\
+             function Output.printChar 0\nreturn"
+        );
+        assert_eq!(
+            pruned.sources[2],
+            "function Sys.init 0\ncall Main.main 0\nreturn"
+        );
+        assert_eq!(pruned.sources[3], "function Math.abs 0\nreturn");
+        assert_eq!(pruned.dropped, ["Output.println", "Sys.wait", "Math.sqrt"]);
+        assert_eq!(pruned.kept, 4);
+    }
+}
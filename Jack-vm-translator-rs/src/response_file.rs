@@ -0,0 +1,102 @@
+//! `@file` arguments: response files holding more arguments, one per line, so a long
+//! or carefully ordered list of inputs can be kept in a file.
+//!
+//! Blank lines and lines starting with `#` are skipped. A line may itself be an
+//! `@file`, read in turn; paths are taken as written, relative to the working
+//! directory.
+
+use std::ffi::OsString;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+/// `args` with every `@file` replaced by the arguments it holds, in place.
+pub fn expand<I>(args: I) -> anyhow::Result<Vec<OsString>>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut expanded = vec![];
+    for arg in args {
+        expand_arg(arg, &mut vec![], &mut expanded)?;
+    }
+
+    Ok(expanded)
+}
+
+/// `reading` holds the response files being read, to catch one including itself.
+fn expand_arg(
+    arg: OsString,
+    reading: &mut Vec<PathBuf>,
+    expanded: &mut Vec<OsString>,
+) -> anyhow::Result<()> {
+    let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix('@')) else {
+        expanded.push(arg);
+        return Ok(());
+    };
+
+    let path = Path::new(path).to_path_buf();
+    if reading.contains(&path) {
+        anyhow::bail!("Response file {} includes itself", path.display());
+    }
+    let contents = read_to_string(&path)
+        .with_context(|| format!("Failed to read response file {}", path.display()))?;
+
+    reading.push(path);
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        expand_arg(line.into(), reading, expanded)?;
+    }
+    reading.pop();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod response_file_tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn expands_response_files_in_place() {
+        let dir = std::env::temp_dir().join(format!("vm-response-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let inner = dir.join("inner.txt");
+        let outer = dir.join("outer.txt");
+        write(&inner, "Sys.vm\n\n# the rest\nMain.vm\n").unwrap();
+        write(&outer, format!("-O\n1\n@{}\nOther.vm", inner.display())).unwrap();
+
+        let args = [
+            "VMTranslator",
+            &format!("@{}", outer.display()),
+            "-o",
+            "Out.asm",
+        ];
+        let expanded = expand(args.map(OsString::from)).unwrap();
+        assert_eq!(
+            expanded,
+            [
+                "VMTranslator",
+                "-O",
+                "1",
+                "Sys.vm",
+                "Main.vm",
+                "Other.vm",
+                "-o",
+                "Out.asm"
+            ]
+        );
+
+        write(&inner, format!("@{}", outer.display())).unwrap();
+        let error = expand([format!("@{}", outer.display()).into()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Response file {} includes itself", outer.display())
+        );
+
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,9 +1,10 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, ops::Range};
 
+use n2t_core::diagnostic::SyntaxError;
 use once_cell::sync::Lazy;
 
 #[rustfmt::skip] 
-static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
+pub(crate) static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     [
         ("push",     TokenType::PUSH),
         ("pop",      TokenType::POP),
@@ -57,36 +58,78 @@ pub enum TokenType {
     EOF
 }
 
+impl TokenType {
+    /// Category of the token for editors: `command`, `segment`, `identifier` or `number`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenType::IDENTIFIER => "identifier",
+            TokenType::NUMBER(_) => "number",
+            TokenType::ARGUMENT
+            | TokenType::LOCAL
+            | TokenType::STATIC
+            | TokenType::CONSTANT
+            | TokenType::THIS
+            | TokenType::THAT
+            | TokenType::POINTER
+            | TokenType::TEMP => "segment",
+            TokenType::EOF => "eof",
+            _ => "command",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token<'de> {
     pub token_type: TokenType,
     pub lexeme: Cow<'de, str>,
     pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+    /// Byte offsets in the source.
+    pub span: Range<usize>,
 }
 
 impl<'de> Token<'de> {
-    pub fn new(token_type: TokenType, lexeme: impl Into<Cow<'de, str>>, line: usize) -> Self {
+    /// A token whose first character is at `(line, column, byte offset)`.
+    pub fn at(
+        token_type: TokenType,
+        lexeme: impl Into<Cow<'de, str>>,
+        (line, column, offset): (usize, usize, usize),
+    ) -> Self {
+        let lexeme = lexeme.into();
+
         Token {
             token_type,
-            lexeme: lexeme.into(),
+            span: offset..offset + lexeme.len(),
+            lexeme,
             line,
+            column,
         }
     }
 }
 
 pub struct Scanner<'de> {
+    /// The whole file, shown under errors.
+    source: &'de str,
     rest: &'de str,
     current: usize,
     line: usize,
+    /// Column of the next character.
+    column: usize,
+    /// Bytes consumed so far.
+    offset: usize,
     eof: bool,
 }
 
 impl<'de> Scanner<'de> {
     pub fn new(source: &'de str) -> Self {
         Self {
+            source,
             rest: source,
             current: 0,
             line: 1,
+            column: 1,
+            offset: 0,
             eof: false,
         }
     }
@@ -103,7 +146,9 @@ impl<'de> Scanner<'de> {
         for _ in 0..n {
             let c = chars.next().unwrap();
             bytes_n += c.len_utf8();
+            self.column = if c == '\n' { 1 } else { self.column + 1 };
         }
+        self.offset += bytes_n;
 
         let lexeme = &self.rest[0..bytes_n];
         self.rest = &self.rest[bytes_n..];
@@ -112,9 +157,15 @@ impl<'de> Scanner<'de> {
         lexeme
     }
 
+    fn error_at(&self, line: usize, column: usize, width: usize, message: &str) -> anyhow::Error {
+        anyhow::anyhow!(SyntaxError::new(line, column, width, message).render(self.source))
+    }
+
+    /// Keywords in any case, as some tools write `PUSH CONSTANT 7`.
     fn get_keyword_or_identifier(&self, lemexe: &'de str) -> TokenType {
         KEYWORDS
             .get(lemexe)
+            .or_else(|| KEYWORDS.get(lemexe.to_ascii_lowercase().as_str()))
             .cloned()
             .unwrap_or(TokenType::IDENTIFIER)
     }
@@ -124,9 +175,9 @@ impl<'de> Scanner<'de> {
         fn token<'de>(
             token_type: TokenType,
             lexeme: &'de str,
-            line: usize,
+            position: (usize, usize, usize),
         ) -> Option<anyhow::Result<Token<'de>>> {
-            Some(Ok(Token::<'de>::new(token_type, lexeme, line)))
+            Some(Ok(Token::<'de>::at(token_type, lexeme, position)))
         }
 
         'scan_loop: loop {
@@ -136,9 +187,10 @@ impl<'de> Scanner<'de> {
                 return None;
             };
 
+            let position = (self.line, self.column, self.offset);
             match cur {
-                // Meaningless characters.
-                ' ' | '\r' | '\t' => {
+                // Meaningless characters, a byte order mark included.
+                ' ' | '\r' | '\t' | '\u{feff}' => {
                     let _ = self.advance_n(1);
                 },
                 '\n' => {
@@ -160,43 +212,77 @@ impl<'de> Scanner<'de> {
                         }
                     }
                 },
+                '/' if self.peek_rest_at(1) == Some('*') => {
+                    let _ = self.advance_n(2);
+
+                    loop {
+                        match self.peek_rest_at(0) {
+                            Some('*') if self.peek_rest_at(1) == Some('/') => {
+                                let _ = self.advance_n(2);
+                                continue 'scan_loop;
+                            }
+                            Some(cur) => {
+                                if cur == '\n' {
+                                    self.line += 1;
+                                }
+                                let _ = self.advance_n(1);
+                            }
+                            None => {
+                                let line = self.line;
+
+                                return Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Unterminated comment"))));
+                            }
+                        }
+                    }
+                },
                 // Literals.
                 '0'..='9' => {
                     let mut cur_len = 0;
-
-                    fn token_number<'de>(
-                        lexeme: &'de str,
-                        line: usize,
-                    ) -> Option<anyhow::Result<Token<'de>>> {
-                        if let Ok(number) = lexeme.parse::<u16>() {
-                            token(TokenType::NUMBER(number), lexeme, line)
-                        } else {
-                            Some(Err(anyhow::anyhow!(format!("[line {line}] Error: Could not parse a number: {lexeme}"))))
-                        }                        
-                    }
+                    let (line, column, _) = position;
 
                     loop {
                         match self.peek_rest_at(cur_len) {
                             Some(c) if c.is_digit(10) => {
                                 cur_len += 1;
                             }
-                            _ => return token_number(self.advance_n(cur_len), self.line),
+                            // `123abc`: a number running into a name
+                            Some(c) if c.is_alphabetic() || c == '_' || c == '.' || c == '$' || c == ':' => {
+                                while self.peek_rest_at(cur_len).is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.' || c == '$' || c == ':') {
+                                    cur_len += 1;
+                                }
+                                let lexeme = self.advance_n(cur_len);
+                                let message = format!("`{lexeme}` is not a number, and names cannot start with a digit");
+
+                                return Some(Err(self.error_at(line, column, lexeme.chars().count(), &message)));
+                            }
+                            _ => {
+                                let lexeme = self.advance_n(cur_len);
+
+                                return match lexeme.parse::<u16>() {
+                                    Ok(number) => token(TokenType::NUMBER(number), lexeme, position),
+                                    Err(_) => {
+                                        let message = format!("Number `{lexeme}` is out of range 0..={}", u16::MAX);
+
+                                        Some(Err(self.error_at(line, column, lexeme.len(), &message)))
+                                    }
+                                };
+                            }
                         }
                     }
                 },
-                'a'..='z' | 'A'..='Z' | '-' | '_' | '.' | '$' => {
+                'a'..='z' | 'A'..='Z' | '-' | '_' | '.' | '$' | ':' => {
                     let mut cur_len = 0;
 
                     loop {
                         match self.peek_rest_at(cur_len) {
                             Some(c) if c.is_alphanumeric() || 
-                                c == '-' || c == '_' || c == '.' || c == '$' => {
+                                c == '-' || c == '_' || c == '.' || c == '$' || c == ':' => {
                                 cur_len += 1;
                             }
                             _ => {
                                 let lexeme = self.advance_n(cur_len);
 
-                                return token(self.get_keyword_or_identifier(lexeme), lexeme, self.line);
+                                return token(self.get_keyword_or_identifier(lexeme), lexeme, position);
                             }
                         }
                     }
@@ -223,10 +309,14 @@ impl<'de> Iterator for Scanner<'de> {
             if !self.eof {
                 self.eof = true;
 
-                Some(Ok(Token::new(TokenType::EOF, "eof", self.line)))
+                Some(Ok(Token::at(
+                    TokenType::EOF,
+                    "eof",
+                    (self.line, self.column, self.offset),
+                )))
             } else {
                 None
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,86 @@
+//! State shared by the stages of a run, read once from the CLI and the environment.
+
+use n2t_core::build_dir::{BuildDir, debug_enabled};
+use n2t_core::line_map::LineMap;
+
+use crate::debug_info::DebugInfo;
+use crate::function_table::FunctionTable;
+use crate::limits::{Limits, Usage};
+use crate::token_dump::TokenDump;
+
+const DEBUG_TOKENS: &str = "DEBUG_TOKENS";
+const DEBUG_AST: &str = "DEBUG_AST";
+
+/// Code of `eq`, `gt` and `lt`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Comparison {
+    /// Subtraction and a jump to labels of its own, at every comparison
+    #[default]
+    Inline,
+    /// A jump to one routine per kind of comparison and translated file (or fragment),
+    /// written at its first use
+    Shared,
+    /// Signs compared before subtracting, so that `gt` and `lt` are right when `x - y`
+    /// overflows, such as `32767 > -1`
+    Safe,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub opt_level: u8,
+    pub comparison: Comparison,
+    /// Comment the address math of every `push` and `pop`.
+    pub annotate_math: bool,
+    /// Write one `.asm` fragment per function instead of a single file.
+    pub fragments: bool,
+    /// Dump the tokens of every file to the build directory.
+    pub debug_tokens: bool,
+    /// Dump the parsed nodes of every file to the build directory.
+    pub debug_ast: bool,
+    /// Where the debug dumps go.
+    pub build_dir: BuildDir,
+    pub limits: Limits,
+    /// Only warn about exceeded limits.
+    pub ignore_limits: bool,
+}
+
+impl Options {
+    /// Debug dumps are enabled by the `DEBUG_*` environment variables, and go to
+    /// `N2T_BUILD_DIR` when no build directory was given.
+    pub fn with_env(self, build_dir: Option<BuildDir>) -> Self {
+        Self {
+            debug_tokens: debug_enabled(DEBUG_TOKENS),
+            debug_ast: debug_enabled(DEBUG_AST),
+            build_dir: build_dir.unwrap_or_else(BuildDir::from_env),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Session {
+    pub options: Options,
+    /// What the files translated so far use of the limited resources.
+    pub usage: Usage,
+    /// Tokens of the files translated so far, when asked for with `--dump`.
+    pub token_dump: Option<TokenDump>,
+    /// Functions of the files translated so far, when asked for with `--functions`.
+    pub function_table: Option<FunctionTable>,
+    /// The `.vm` line of every stretch of output, when asked for with `--line-map`.
+    pub line_map: Option<LineMap>,
+    /// What the labels and variables stand for, when asked for with `--debug-info`.
+    pub debug_info: Option<DebugInfo>,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            usage: Usage::default(),
+            token_dump: None,
+            function_table: None,
+            line_map: None,
+            debug_info: None,
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::linker::Fragment;
 use crate::parser::{Node, Segment};
+use crate::session::Comparison;
 
 macro_rules! c {
     ($vec:expr, $fmt:expr $(, $arg:expr)* $(,)?) => {
@@ -14,6 +16,9 @@ macro_rules! c {
 pub struct Translator<'de, I: IntoIterator<Item = Node<'de>>, S: AsRef<str>> {
     filename: S,
     nodes: I,
+    opt_level: u8,
+    comparison: Comparison,
+    annotate_math: bool,
 }
 
 impl<'de, I, S> Translator<'de, I, S>
@@ -22,25 +27,107 @@ where
     S: AsRef<str>,
 {
     pub fn new(filename: S, nodes: I) -> Self {
-        Self { filename, nodes }
+        Self {
+            filename,
+            nodes,
+            opt_level: 0,
+            comparison: Comparison::Inline,
+            annotate_math: false,
+        }
+    }
+
+    /// `1` - shorter `return` sequence, arithmetic in place on the stack top, and
+    /// segment bases reused from the command before instead of loaded again.
+    pub fn with_opt_level(mut self, opt_level: u8) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    pub fn with_comparison(mut self, comparison: Comparison) -> Self {
+        self.comparison = comparison;
+        self
+    }
+
+    /// Comments the address every `push` and `pop` works out, before its code.
+    pub fn with_annotate_math(mut self, annotate_math: bool) -> Self {
+        self.annotate_math = annotate_math;
+        self
     }
 
     pub fn translate(self) -> Vec<String> {
-        let filename = self.filename;
-        let nodes = self.nodes;
+        self.translate_from(0).0
+    }
+
+    /// The instructions, and where the code of every node starts in them. The end of a
+    /// push fused into the command after it at `-O 1` belongs to the command.
+    pub fn translate_with_starts(self) -> (Vec<String>, Vec<usize>) {
+        let (instructions, starts, _) = self.translate_from(0);
+
+        (instructions, starts)
+    }
+
+    /// Translates every function into its own [`Fragment`]. Code before the first
+    /// function becomes a fragment named after the file.
+    pub fn translate_fragments(self) -> Vec<Fragment> {
+        let filename = self.filename.as_ref();
+        let opt_level = self.opt_level;
+        let comparison = self.comparison;
+        let annotate_math = self.annotate_math;
+
+        let mut groups: Vec<Vec<Node<'de>>> = vec![];
+        for node in self.nodes {
+            if groups.is_empty() || matches!(node, Node::Function { .. }) {
+                groups.push(vec![]);
+            }
+            groups.last_mut().expect("Just pushed").push(node);
+        }
 
         let mut label_cnt = 0;
+        groups
+            .into_iter()
+            .map(|nodes| {
+                let name = match nodes.first() {
+                    Some(Node::Function { name, .. }) => name.to_string(),
+                    _ => filename.to_string(),
+                };
+                let translator = Translator {
+                    filename,
+                    nodes,
+                    opt_level,
+                    comparison,
+                    annotate_math,
+                };
+                let (lines, _, next_label_cnt) = translator.translate_from(label_cnt);
+                label_cnt = next_label_cnt;
+
+                Fragment { name, lines }
+            })
+            .collect()
+    }
 
-        nodes.into_iter().fold(vec![], |mut ans, node| match node {
+    /// Labels are numbered from `label_cnt`, which is returned past the last one used,
+    /// after the instructions and where the code of each node starts.
+    fn translate_from(self, mut label_cnt: u16) -> (Vec<String>, Vec<usize>, u16) {
+        let filename = self.filename;
+        let nodes = self.nodes;
+        let opt_level = self.opt_level;
+        let comparison = self.comparison;
+        let annotate_math = self.annotate_math;
+        // Label numbers of the shared comparison routines written so far, by jump
+        let mut routines: Vec<(&str, u16)> = vec![];
+        // Function the nodes are in, which their `label` names are local to
+        let mut function: Option<String> = None;
+
+        let mut translate = |mut ans: Vec<String>, node: Node<'de>, cached| match node {
             Node::Push { segment } => match segment {
                 Segment::Argument { offset } => {
-                    load_mem_with_offset_into_d(&mut ans, "ARG", offset);
+                    load_mem_with_offset_into_d(&mut ans, "ARG", offset, cached);
                     push_d_onto_stack(&mut ans);
 
                     ans
                 }
                 Segment::Local { offset } => {
-                    load_mem_with_offset_into_d(&mut ans, "LCL", offset);
+                    load_mem_with_offset_into_d(&mut ans, "LCL", offset, cached);
                     push_d_onto_stack(&mut ans);
 
                     ans
@@ -58,13 +145,13 @@ where
                     ans
                 }
                 Segment::This { offset } => {
-                    load_mem_with_offset_into_d(&mut ans, "THIS", offset);
+                    load_mem_with_offset_into_d(&mut ans, "THIS", offset, cached);
                     push_d_onto_stack(&mut ans);
 
                     ans
                 }
                 Segment::That { offset } => {
-                    load_mem_with_offset_into_d(&mut ans, "THAT", offset);
+                    load_mem_with_offset_into_d(&mut ans, "THAT", offset, cached);
                     push_d_onto_stack(&mut ans);
 
                     ans
@@ -82,7 +169,7 @@ where
 
                         ans
                     }
-                    _ => panic!(),
+                    _ => unreachable!("the parser only accepts pointer 0 and 1"),
                 },
                 Segment::Temp { offset } => {
                     c!(&mut ans, "@{}", 5 + offset; "D=M");
@@ -93,12 +180,12 @@ where
             },
             Node::Pop { segment } => match segment {
                 Segment::Argument { offset } => {
-                    load_sp_into_mem_with_offset(&mut ans, "ARG", offset);
+                    load_sp_into_mem_with_offset(&mut ans, "ARG", offset, cached);
 
                     ans
                 }
                 Segment::Local { offset } => {
-                    load_sp_into_mem_with_offset(&mut ans, "LCL", offset);
+                    load_sp_into_mem_with_offset(&mut ans, "LCL", offset, cached);
 
                     ans
                 }
@@ -109,14 +196,14 @@ where
 
                     ans
                 }
-                Segment::Constant { .. } => panic!("Not valid"),
+                Segment::Constant { .. } => unreachable!("the parser rejects pop constant"),
                 Segment::This { offset } => {
-                    load_sp_into_mem_with_offset(&mut ans, "THIS", offset);
+                    load_sp_into_mem_with_offset(&mut ans, "THIS", offset, cached);
 
                     ans
                 }
                 Segment::That { offset } => {
-                    load_sp_into_mem_with_offset(&mut ans, "THAT", offset);
+                    load_sp_into_mem_with_offset(&mut ans, "THAT", offset, cached);
 
                     ans
                 }
@@ -133,7 +220,7 @@ where
 
                         ans
                     }
-                    _ => panic!(),
+                    _ => unreachable!("the parser only accepts pointer 0 and 1"),
                 },
                 Segment::Temp { offset } => {
                     pop_stack_into_d(&mut ans);
@@ -143,22 +230,26 @@ where
                 }
             },
             Node::Label { name } => {
-                c!(&mut ans, "({}.{})", filename.as_ref(), name);
+                let label = local_label(filename.as_ref(), function.as_deref(), &name);
+                c!(&mut ans, "({})", label);
 
                 ans
             }
             Node::IfGoto { name } => {
+                let label = local_label(filename.as_ref(), function.as_deref(), &name);
                 pop_stack_into_d(&mut ans);
-                c!(&mut ans, "@{}.{}", filename.as_ref(), name; "D;JNE");
+                c!(&mut ans, "@{}", label; "D;JNE");
 
                 ans
             }
             Node::Goto { name } => {
-                c!(&mut ans, "@{}.{}", filename.as_ref(), name; "0;JMP");
+                let label = local_label(filename.as_ref(), function.as_deref(), &name);
+                c!(&mut ans, "@{}", label; "0;JMP");
 
                 ans
             }
             Node::Function { name, n_locals } => {
+                function = Some(name.to_string());
                 c!(&mut ans, "({})", name);
                 c!(&mut ans, "@0"; "D=A");
                 for _ in 0..n_locals {
@@ -167,6 +258,11 @@ where
 
                 ans
             }
+            Node::Return if opt_level >= 1 => {
+                build_return(&mut ans);
+
+                ans
+            }
             Node::Return => {
                 c!(&mut ans, "// endFrame - LCL");
                 c!(&mut ans, "@LCL"; "D=M"; "@endFrame"; "M=D");
@@ -256,6 +352,65 @@ where
 
                 ans
             }
+            Node::Add | Node::Sub | Node::Or | Node::And if opt_level >= 1 => {
+                let comp = match node {
+                    Node::Add => "D+M",
+                    Node::Sub => "M-D",
+                    Node::Or => "D|M",
+                    _ => "D&M",
+                };
+                load_binary_operands(&mut ans);
+                c!(&mut ans, "M={}", comp);
+
+                ans
+            }
+            Node::Neg | Node::Not if opt_level >= 1 => {
+                let comp = if matches!(node, Node::Neg) {
+                    "-M"
+                } else {
+                    "!M"
+                };
+                c!(&mut ans, "@SP"; "A=M-1"; "M={}", comp);
+
+                ans
+            }
+            Node::Eq | Node::Gt | Node::Lt if comparison != Comparison::Inline => {
+                let jmp = match node {
+                    Node::Eq => "JEQ",
+                    Node::Gt => "JGT",
+                    _ => "JLT",
+                };
+                load_comparison_operands(&mut ans, opt_level);
+                match comparison {
+                    // `x - y` is only 0 when they are equal, overflow or not
+                    Comparison::Safe if jmp != "JEQ" => {
+                        build_safe_comparison(&mut ans, jmp, filename.as_ref(), &mut label_cnt)
+                    }
+                    Comparison::Safe => {
+                        c!(&mut ans, "D=M-D");
+                        build_comparison_result(&mut ans, jmp, filename.as_ref(), &mut label_cnt)
+                    }
+                    _ => build_shared_comparison(
+                        &mut ans,
+                        jmp,
+                        filename.as_ref(),
+                        &mut label_cnt,
+                        &mut routines,
+                    ),
+                }
+
+                ans
+            }
+            Node::Eq | Node::Gt | Node::Lt if opt_level >= 1 => {
+                let jmp = match node {
+                    Node::Eq => "JEQ",
+                    Node::Gt => "JGT",
+                    _ => "JLT",
+                };
+                build_comparison_in_place(&mut ans, jmp, filename.as_ref(), &mut label_cnt);
+
+                ans
+            }
             Node::Add => {
                 pop_stack_into_d(&mut ans);
                 sp_dec(&mut ans);
@@ -317,10 +472,69 @@ where
 
                 ans
             }
-        })
+        };
+        let mut starts = vec![];
+        let push = PUSH_D_ONTO_STACK.map(String::from);
+        // What the code of the last node left in A and D
+        let mut cached = None;
+        let ans = nodes.into_iter().fold(vec![], |mut ans, node| {
+            let start = ans.len();
+            let fusable = ans.ends_with(&push);
+            // Before the code, so that `-O 1` still finds a push at the end
+            if annotate_math && let Some(math) = address_math(&node, filename.as_ref()) {
+                ans.push(math);
+            }
+            let next = if opt_level >= 1 {
+                Cached::after(&node)
+            } else {
+                None
+            };
+            let ans = translate(ans, node, cached);
+            cached = next;
+            let fused = fusable && ans.get(start - push.len()..start) != Some(&push[..]);
+            starts.push(if fused { start - push.len() } else { start });
+
+            ans
+        });
+
+        (ans, starts, label_cnt)
     }
 }
 
+/// `--annotate-math`: how a `push` or `pop` finds its address, such as
+/// `// push local 2: LCL=RAM[1], offset 2 -> RAM[LCL+2]`.
+fn address_math(node: &Node<'_>, filename: &str) -> Option<String> {
+    let (command, segment) = match node {
+        Node::Push { segment } => ("push", segment),
+        Node::Pop { segment } => ("pop", segment),
+        _ => return None,
+    };
+    let math = match *segment {
+        Segment::Argument { offset } => base_math("argument", "ARG", 2, offset),
+        Segment::Local { offset } => base_math("local", "LCL", 1, offset),
+        Segment::This { offset } => base_math("this", "THIS", 3, offset),
+        Segment::That { offset } => base_math("that", "THAT", 4, offset),
+        Segment::Static { offset } => {
+            format!("static {offset}: variable {filename}.{offset}, from RAM[16] on")
+        }
+        Segment::Constant { value } => format!("constant {value}: no address, the value itself"),
+        Segment::Pointer { offset } => {
+            let pointer = if offset == 0 { "THIS" } else { "THAT" };
+            format!(
+                "pointer {offset}: 3 + {offset} -> RAM[{}], {pointer}",
+                3 + offset
+            )
+        }
+        Segment::Temp { offset } => format!("temp {offset}: 5 + {offset} -> RAM[{}]", 5 + offset),
+    };
+
+    Some(format!("// {command} {math}"))
+}
+
+fn base_math(segment: &str, base: &str, pointer: u16, offset: u16) -> String {
+    format!("{segment} {offset}: {base}=RAM[{pointer}], offset {offset} -> RAM[{base}+{offset}]")
+}
+
 fn sp_inc(v: &mut Vec<String>) {
     c!(v, "@SP"; "M=M+1");
 }
@@ -337,9 +551,74 @@ fn load_sp_into_d(v: &mut Vec<String>) {
     c!(v, "@SP"; "A=M"; "D=M");
 }
 
-fn load_mem_with_offset_into_d(v: &mut Vec<String>, mem: &str, offset: u16) {
-    c!(v, "@{}", mem; "D=M");
-    c!(v, "@{}", offset; "A=D+A"; "D=M");
+/// What the code of a command leaves in A and D that the next one can use at `-O 1`,
+/// rather than loading a segment base again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cached {
+    /// D holds the base, as set by `pop pointer`.
+    Base(&'static str),
+    /// A holds the address `base + offset` and D the word there, as a `pop` to the
+    /// segment of `base` leaves them.
+    Address(&'static str, u16),
+}
+
+impl Cached {
+    /// What `node` leaves, once translated.
+    fn after(node: &Node<'_>) -> Option<Self> {
+        match node {
+            Node::Pop { segment } => match *segment {
+                Segment::Argument { offset } => Some(Self::Address("ARG", offset)),
+                Segment::Local { offset } => Some(Self::Address("LCL", offset)),
+                Segment::This { offset } => Some(Self::Address("THIS", offset)),
+                Segment::That { offset } => Some(Self::Address("THAT", offset)),
+                Segment::Pointer { offset: 0 } => Some(Self::Base("THIS")),
+                Segment::Pointer { offset: 1 } => Some(Self::Base("THAT")),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn load_mem_with_offset_into_d(
+    v: &mut Vec<String>,
+    mem: &'static str,
+    offset: u16,
+    cached: Option<Cached>,
+) {
+    match cached {
+        Some(Cached::Address(base, from)) if base == mem => match offset as i32 - from as i32 {
+            // The word just popped there
+            0 => {}
+            1 => {
+                c!(v, "A=A+1"; "D=M");
+            }
+            -1 => {
+                c!(v, "A=A-1"; "D=M");
+            }
+            d if d > 0 => {
+                c!(v, "D=A"; "@{}", d; "A=D+A"; "D=M");
+            }
+            d => {
+                c!(v, "D=A"; "@{}", -d; "A=D-A"; "D=M");
+            }
+        },
+        Some(Cached::Base(base)) if base == mem => match offset {
+            0 => {
+                c!(v, "A=D"; "D=M");
+            }
+            1 => {
+                c!(v, "A=D+1"; "D=M");
+            }
+            _ => {
+                c!(v, "@{}", offset; "A=D+A"; "D=M");
+            }
+        },
+        _ => {
+            c!(v, "@{}", mem; "D=M");
+            c!(v, "@{}", offset; "A=D+A"; "D=M");
+        }
+    }
 }
 
 fn pop_stack_into_d(v: &mut Vec<String>) {
@@ -347,20 +626,204 @@ fn pop_stack_into_d(v: &mut Vec<String>) {
     load_sp_into_d(v);
 }
 
+/// What `push_d_onto_stack` emits.
+const PUSH_D_ONTO_STACK: [&str; 5] = ["@SP", "A=M", "M=D", "@SP", "M=M+1"];
+
 fn push_d_onto_stack(v: &mut Vec<String>) {
     set_sp(v, "D");
     sp_inc(v);
 }
 
-fn load_sp_into_mem_with_offset(v: &mut Vec<String>, mem: &str, offset: u16) {
-    c!(v, "@{}", mem; "D=M");
-    c!(v, "@{}", offset; "D=D+A");
+fn load_sp_into_mem_with_offset(
+    v: &mut Vec<String>,
+    mem: &'static str,
+    offset: u16,
+    cached: Option<Cached>,
+) {
+    match cached {
+        Some(Cached::Address(base, from)) if base == mem && offset.abs_diff(from) <= 1 => {
+            match offset as i32 - from as i32 {
+                0 => {
+                    c!(v, "D=A");
+                }
+                1 => {
+                    c!(v, "D=A+1");
+                }
+                _ => {
+                    c!(v, "D=A-1");
+                }
+            }
+        }
+        Some(Cached::Base(base)) if base == mem => match offset {
+            0 => {}
+            1 => {
+                c!(v, "D=D+1");
+            }
+            _ => {
+                c!(v, "@{}", offset; "D=D+A");
+            }
+        },
+        _ => {
+            c!(v, "@{}", mem; "D=M");
+            c!(v, "@{}", offset; "D=D+A");
+        }
+    }
     c!(v, "@{}", "tmp"; "M=D");
     sp_dec(v);
     load_sp_into_d(v);
     c!(v, "@{}", "tmp"; "A=M"; "M=D");
 }
 
+/// `label` `name` as written in the code of `function`, `Function$name` as the VM
+/// specification scopes it, or `File.name` for code before the first function.
+fn local_label(filename: &str, function: Option<&str>, name: &str) -> String {
+    match function {
+        Some(function) => format!("{function}${name}"),
+        None => format!("{filename}.{name}"),
+    }
+}
+
+/// Keeps `endFrame` in R13 and walks it down to restore THAT, THIS, ARG and LCL,
+/// instead of recomputing `endFrame - i` for each of them.
+fn build_return(v: &mut Vec<String>) {
+    c!(v, "// R13 = endFrame = LCL");
+    c!(v, "@LCL"; "D=M"; "@R13"; "M=D");
+
+    c!(v, "// R14 = retAddr = *(endFrame - 5)");
+    c!(v, "@5"; "A=D-A"; "D=M"; "@R14"; "M=D");
+
+    c!(v, "// *ARG = pop()");
+    c!(v, "@SP"; "AM=M-1"; "D=M");
+    c!(v, "@ARG"; "A=M"; "M=D");
+
+    c!(v, "// SP = ARG + 1");
+    c!(v, "D=A+1"; "@SP"; "M=D");
+
+    c!(v, "// THAT, THIS, ARG, LCL = *(--endFrame)");
+    for pointer in ["THAT", "THIS", "ARG", "LCL"] {
+        c!(v, "@R13"; "AM=M-1"; "D=M"; "@{}", pointer; "M=D");
+    }
+
+    c!(v, "// goto retAddr");
+    c!(v, "@R14"; "A=M"; "0;JMP");
+}
+
+/// Leaves the second operand in D and points A at the first one, which is replaced by the
+/// result. Right after a push its value is still in D, so the push itself is dropped.
+fn load_binary_operands(v: &mut Vec<String>) {
+    if v.ends_with(&PUSH_D_ONTO_STACK.map(String::from)) {
+        v.truncate(v.len() - PUSH_D_ONTO_STACK.len());
+        c!(v, "@SP"; "A=M-1");
+    } else {
+        c!(v, "@SP"; "AM=M-1"; "D=M"; "A=A-1");
+    }
+}
+
+fn build_comparison_in_place(v: &mut Vec<String>, jmp: &str, filename: &str, label_cnt: &mut u16) {
+    load_binary_operands(v);
+    c!(v, "D=M-D");
+
+    // Cond
+    c!(v, "@{}.label_yes.{}", filename, label_cnt; "D;{}", jmp);
+    // NO
+    {
+        c!(v, "@SP"; "A=M-1"; "M=0");
+        c!(v, "@{}.label_no.{}", filename, label_cnt; "0;JMP");
+    }
+    // YES
+    {
+        c!(v, "({}.label_yes.{})", filename, label_cnt);
+        c!(v, "@SP"; "A=M-1"; "M=-1");
+    }
+
+    c!(v, "({}.label_no.{})", filename, label_cnt);
+
+    *label_cnt += 1;
+}
+
+/// `y` into D and A at `x`, which the result replaces; the push of `y` is fused at
+/// `-O 1`.
+fn load_comparison_operands(v: &mut Vec<String>, opt_level: u8) {
+    if opt_level >= 1 {
+        load_binary_operands(v);
+    } else {
+        c!(v, "@SP"; "AM=M-1"; "D=M"; "A=A-1");
+    }
+}
+
+/// Replaces `x` with `true` when `D;jmp` jumps, else with `false`.
+fn build_comparison_result(v: &mut Vec<String>, jmp: &str, filename: &str, label_cnt: &mut u16) {
+    c!(v, "@{}.label_yes.{}", filename, label_cnt; "D;{}", jmp);
+    c!(v, "@SP"; "A=M-1"; "M=0");
+    c!(v, "@{}.label_no.{}", filename, label_cnt; "0;JMP");
+    c!(v, "({}.label_yes.{})", filename, label_cnt);
+    c!(v, "@SP"; "A=M-1"; "M=-1");
+    c!(v, "({}.label_no.{})", filename, label_cnt);
+
+    *label_cnt += 1;
+}
+
+/// `x - y` into D, with `x` and `y` of the same sign so that it cannot overflow;
+/// otherwise D is `1` or `-1` from the signs alone.
+fn build_safe_comparison(v: &mut Vec<String>, jmp: &str, filename: &str, label_cnt: &mut u16) {
+    let n = *label_cnt;
+    c!(v, "@R13"; "M=D");
+    c!(v, "@SP"; "A=M-1"; "D=M");
+    c!(v, "@{}.compare_x_neg.{}", filename, n; "D;JLT");
+    c!(v, "@R13"; "D=M");
+    c!(v, "@{}.compare_diff.{}", filename, n; "D;JGE");
+    // x >= 0 > y
+    c!(v, "D=1");
+    c!(v, "@{}.compare_test.{}", filename, n; "0;JMP");
+    c!(v, "({}.compare_x_neg.{})", filename, n);
+    c!(v, "@R13"; "D=M");
+    c!(v, "@{}.compare_diff.{}", filename, n; "D;JLT");
+    // x < 0 <= y
+    c!(v, "D=-1");
+    c!(v, "@{}.compare_test.{}", filename, n; "0;JMP");
+    c!(v, "({}.compare_diff.{})", filename, n);
+    c!(v, "@SP"; "A=M-1"; "D=M"; "@R13"; "D=D-M");
+    c!(v, "({}.compare_test.{})", filename, n);
+
+    build_comparison_result(v, jmp, filename, label_cnt);
+}
+
+/// `x - y` into R13 and a jump to the routine of `jmp`, which writes the result and
+/// returns to the address it gets in D. The first comparison of each `jmp` falls
+/// into its routine, written right there.
+fn build_shared_comparison<'a>(
+    v: &mut Vec<String>,
+    jmp: &'a str,
+    filename: &str,
+    label_cnt: &mut u16,
+    routines: &mut Vec<(&'a str, u16)>,
+) {
+    let n = *label_cnt;
+    *label_cnt += 1;
+    c!(v, "D=M-D"; "@R13"; "M=D");
+    c!(v, "@{}.compare_return.{}", filename, n; "D=A");
+
+    match routines.iter().find(|(routine, _)| *routine == jmp) {
+        Some((_, routine)) => {
+            c!(v, "@{}.compare_{}.{}", filename, jmp, routine; "0;JMP");
+        }
+        None => {
+            routines.push((jmp, n));
+            c!(v, "({}.compare_{}.{})", filename, jmp, n);
+            c!(v, "@R14"; "M=D");
+            c!(v, "@R13"; "D=M");
+            c!(v, "@{}.compare_{}.{}.true", filename, jmp, n; "D;{}", jmp);
+            c!(v, "@SP"; "A=M-1"; "M=0");
+            c!(v, "@R14"; "A=M"; "0;JMP");
+            c!(v, "({}.compare_{}.{}.true)", filename, jmp, n);
+            c!(v, "@SP"; "A=M-1"; "M=-1");
+            c!(v, "@R14"; "A=M"; "0;JMP");
+        }
+    }
+
+    c!(v, "({}.compare_return.{})", filename, n);
+}
+
 fn build_comparison(v: &mut Vec<String>, jmp: &str, filename: &str, label_cnt: &mut u16) {
     pop_stack_into_d(v);
     sp_dec(v);
@@ -385,3 +848,602 @@ fn build_comparison(v: &mut Vec<String>, jmp: &str, filename: &str, label_cnt: &
 
     *label_cnt += 1;
 }
+
+#[cfg(test)]
+mod translator_tests {
+    use super::*;
+    use crate::{hack_emulator::Emulator, parser::Parser, scanner::Scanner};
+
+    const BOOT: &str = "
+        call Main.main 0
+        label END
+        goto END
+    ";
+
+    fn translate(filename: &str, source: &str, opt_level: u8) -> Vec<String> {
+        translate_with(filename, source, opt_level, Comparison::Inline)
+    }
+
+    fn translate_with(
+        filename: &str,
+        source: &str,
+        opt_level: u8,
+        comparison: Comparison,
+    ) -> Vec<String> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+
+        Translator::new(filename, nodes.unwrap())
+            .with_opt_level(opt_level)
+            .with_comparison(comparison)
+            .translate()
+    }
+
+    /// Runs `Main.main` and returns the RAM once it has returned.
+    fn run(source: &str, opt_level: u8) -> Vec<i16> {
+        run_with(source, opt_level, Comparison::Inline)
+    }
+
+    fn run_with(source: &str, opt_level: u8, comparison: Comparison) -> Vec<i16> {
+        let mut asm = vec!["@256".to_string(), "D=A".into(), "@SP".into(), "M=D".into()];
+        asm.extend(translate_with("Boot", BOOT, opt_level, comparison));
+        asm.extend(translate_with("Main", source, opt_level, comparison));
+
+        let mut emulator = Emulator::new(&asm);
+        emulator.run_until("Boot.END", 1_000_000);
+
+        emulator.ram
+    }
+
+    /// Both levels agree on the result, the stack pointer and the restored segments.
+    fn assert_same(source: &str, expected: i16) {
+        let plain = run(source, 0);
+        let optimized = run(source, 1);
+
+        assert_eq!(plain[256], expected);
+        assert_eq!(optimized[256], expected);
+        assert_eq!(plain[0..5], optimized[0..5]);
+        assert_eq!(optimized[0], 257);
+    }
+
+    #[test]
+    fn return_without_arguments() {
+        assert_same(
+            "
+            function Main.main 1
+                push constant 7
+                pop local 0
+                call Main.seven 0
+                push local 0
+                add
+                return
+            function Main.seven 0
+                push constant 7
+                return
+            ",
+            14,
+        );
+    }
+
+    #[test]
+    fn return_recursive() {
+        assert_same(
+            "
+            function Main.main 0
+                push constant 10
+                call Main.fib 1
+                return
+            function Main.fib 0
+                push argument 0
+                push constant 2
+                lt
+                if-goto BASE
+                push argument 0
+                push constant 1
+                sub
+                call Main.fib 1
+                push argument 0
+                push constant 2
+                sub
+                call Main.fib 1
+                add
+                return
+                label BASE
+                push argument 0
+                return
+            ",
+            55,
+        );
+    }
+
+    #[test]
+    fn return_restores_pointers() {
+        assert_same(
+            "
+            function Main.main 0
+                push constant 3000
+                pop pointer 0
+                push constant 4000
+                pop pointer 1
+                push constant 1
+                push constant 2
+                call Main.clobber 2
+                push pointer 0
+                add
+                push pointer 1
+                add
+                return
+            function Main.clobber 2
+                push constant 5000
+                pop pointer 0
+                push constant 6000
+                pop pointer 1
+                push argument 0
+                push argument 1
+                add
+                return
+            ",
+            7003,
+        );
+    }
+
+    #[test]
+    fn arithmetic_in_place() {
+        assert_same(
+            "
+            function Main.main 2
+                push constant 10
+                push constant 3
+                sub
+                pop local 0
+                push local 0
+                push local 0
+                add
+                neg
+                pop local 1
+                push constant 12
+                push constant 10
+                and
+                push constant 1
+                or
+                not
+                push local 1
+                add
+                push local 0
+                push constant 7
+                eq
+                sub
+                push local 0
+                push constant 3
+                gt
+                sub
+                push constant 3
+                push local 0
+                lt
+                add
+                return
+            ",
+            (!(12 & 10 | 1)) - 14 + 1 + 1 - 1,
+        );
+    }
+
+    #[test]
+    fn arithmetic_after_call() {
+        assert_same(
+            "
+            function Main.main 0
+                push constant 5
+                call Main.seven 0
+                sub
+                push constant 100
+                call Main.seven 0
+                lt
+                sub
+                return
+            function Main.seven 0
+                push constant 7
+                return
+            ",
+            -2,
+        );
+    }
+
+    /// Label names as written by the reference compiler and by other compilers.
+    #[test]
+    fn labels_with_the_full_character_set() {
+        assert_same(
+            "
+            function Main.main 1
+                push constant 5
+                pop local 0
+                push constant 0
+            label WHILE_EXP0
+                push local 0
+                push constant 0
+                eq
+                if-goto Main.main$while.end:0
+                push local 0
+                add
+                push local 0
+                push constant 1
+                sub
+                pop local 0
+                goto WHILE_EXP0
+            label Main.main$while.end:0
+                return
+            ",
+            15,
+        );
+    }
+
+    #[test]
+    fn return_is_shorter() {
+        let source = "
+            function Main.main 0
+                push constant 0
+                return
+        ";
+        let plain = translate("Main", source, 0);
+        let optimized = translate("Main", source, 1);
+
+        assert!(optimized.len() < plain.len());
+    }
+
+    #[test]
+    fn push_then_add_is_fused() {
+        let optimized = translate("Main", "push constant 1\npush constant 2\nadd", 1);
+
+        assert_eq!(
+            optimized,
+            [
+                "@1", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1", "@2", "D=A", "@SP", "A=M-1",
+                "M=D+M"
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn segment_bases_are_reused() {
+        assert_same(
+            "
+            function Main.main 4
+                push constant 1
+                push constant 2
+                push constant 3
+                push constant 4
+                pop local 3
+                pop local 2
+                pop local 0
+                pop local 0
+                push local 0
+                push constant 20
+                pop local 1
+                push local 3
+                push constant 30
+                pop local 3
+                push local 2
+                push constant 40
+                pop local 0
+                push local 1
+                push constant 50
+                pop local 3
+                push local 0
+                add
+                add
+                add
+                add
+                push local 3
+                add
+                push constant 11
+                push constant 3000
+                pop pointer 1
+                pop that 1
+                push constant 3000
+                pop pointer 1
+                push that 1
+                add
+                push constant 12
+                push constant 4000
+                pop pointer 0
+                pop this 5
+                push constant 4000
+                pop pointer 0
+                push this 5
+                add
+                push constant 3001
+                pop pointer 1
+                push that 0
+                add
+                push constant 100
+                push constant 200
+                call Main.second 2
+                add
+                return
+            function Main.second 0
+                push constant 1
+                pop argument 0
+                push argument 1
+                push argument 0
+                add
+                return
+            ",
+            1 + 4 + 3 + 20 + 40 + 50 + 11 + 12 + 11 + 201,
+        );
+    }
+
+    /// `pop pointer 1` leaves THAT in D for `push that 0`, and `pop local 2` leaves the
+    /// word and its address for `push local 2`.
+    #[test]
+    fn pointer_then_segment_uses_the_base_in_d() {
+        let source = "push constant 3000\npop pointer 1\npush that 0\npop local 2\npush local 2";
+        let optimized = translate("Main", source, 1);
+
+        assert_eq!(
+            optimized[12..],
+            [
+                "@THAT", "M=D", "A=D", "D=M", "@SP", "A=M", "M=D", "@SP", "M=M+1", "@LCL", "D=M",
+                "@2", "D=D+A", "@tmp", "M=D", "@SP", "M=M-1", "@SP", "A=M", "D=M", "@tmp", "A=M",
+                "M=D", "@SP", "A=M", "M=D", "@SP", "M=M+1"
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn math_is_annotated_before_the_code() {
+        let tokens: Result<Vec<_>, _> = Scanner::new(
+            "push argument 3
+push constant 2
+add
+pop temp 1",
+        )
+        .collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens.unwrap().into_iter()).collect();
+        let annotated = Translator::new("Main", nodes.unwrap())
+            .with_opt_level(1)
+            .with_annotate_math(true)
+            .translate();
+
+        let comments: Vec<_> = annotated
+            .iter()
+            .filter(|line| line.starts_with("//"))
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                "// push argument 3: ARG=RAM[2], offset 3 -> RAM[ARG+3]",
+                "// push constant 2: no address, the value itself",
+                "// pop temp 1: 5 + 1 -> RAM[6]",
+            ]
+        );
+        // The push of 2 is still fused into the `add`
+        assert!(annotated.contains(&"M=D+M".to_string()));
+    }
+
+    #[test]
+    fn starts_of_the_nodes_give_fused_code_to_the_command() {
+        let tokens: Result<Vec<_>, _> =
+            Scanner::new("// two\npush constant 2\n\npush constant 3\nadd\nlabel END").collect();
+        let mut parser = Parser::new(tokens.unwrap().into_iter());
+        let nodes: Result<Vec<_>, _> = parser.by_ref().collect();
+        assert_eq!(parser.lines(), [2, 4, 5, 6]);
+
+        let (instructions, starts) = Translator::new("Main", nodes.unwrap())
+            .with_opt_level(1)
+            .translate_with_starts();
+        let first: Vec<_> = starts.iter().map(|&start| &instructions[start]).collect();
+        assert_eq!(first, ["@2", "@3", "@SP", "(Main.END)"]);
+    }
+
+    /// Pops every comparison of `x` and `y` into `static 0` onwards, and returns them.
+    fn compare(x: &str, y: &str, opt_level: u8, comparison: Comparison) -> [i16; 6] {
+        let mut source = "function Main.main 0\n".to_string();
+        for (i, op) in ["eq", "gt", "lt", "eq", "gt", "lt"].iter().enumerate() {
+            source += &format!("{x}\n{y}\n{op}\npop static {i}\n");
+        }
+        source += "push constant 0\nreturn\n";
+
+        let ram = run_with(&source, opt_level, comparison);
+        ram[16..22].try_into().unwrap()
+    }
+
+    #[test]
+    fn comparison_strategies_agree() {
+        let operands = [
+            ("push constant 3", "push constant 3", [-1, 0, 0]),
+            ("push constant 3", "push constant 2", [0, -1, 0]),
+            ("push constant 1\nneg", "push constant 2", [0, 0, -1]),
+            ("push constant 0", "push constant 5\nneg", [0, -1, 0]),
+        ];
+
+        for (x, y, [eq, gt, lt]) in operands {
+            for comparison in [Comparison::Inline, Comparison::Shared, Comparison::Safe] {
+                for opt_level in [0, 1] {
+                    assert_eq!(
+                        compare(x, y, opt_level, comparison),
+                        [eq, gt, lt, eq, gt, lt],
+                        "{x} and {y} at -O {opt_level}, {comparison:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_routines_are_written_once() {
+        let source = "push constant 1\npush constant 2\ngt\npush constant 3\npush constant 4\ngt";
+        let shared = translate_with("Main", source, 0, Comparison::Shared);
+
+        let routines = shared
+            .iter()
+            .filter(|line| line.starts_with("(Main.compare_JGT."))
+            .count();
+        assert_eq!(routines, 2, "the routine and its `true` branch");
+    }
+
+    #[test]
+    fn safe_comparison_survives_overflow() {
+        let max = "push constant 32767";
+        let min = "push constant 32767\nneg\npush constant 1\nsub";
+        let minus_one = "push constant 1\nneg";
+
+        for opt_level in [0, 1] {
+            assert_eq!(
+                compare(max, minus_one, opt_level, Comparison::Safe)[1..3],
+                [-1, 0]
+            );
+            assert_eq!(
+                compare(min, "push constant 1", opt_level, Comparison::Safe)[1..3],
+                [0, -1]
+            );
+        }
+        // `x - y` overflows, and the inline form is wrong
+        assert_eq!(
+            compare(max, minus_one, 0, Comparison::Inline)[1..3],
+            [0, -1]
+        );
+    }
+}
+
+#[cfg(test)]
+mod compat_tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    /// `Main.vm` of a countdown loop as written by the course's JackCompiler.
+    const REFERENCE: &str = "function Main.main 1
+push constant 3
+pop local 0
+label WHILE_EXP0
+push local 0
+push constant 0
+gt
+not
+if-goto WHILE_END0
+push local 0
+push constant 1
+eq
+if-goto IF_TRUE0
+goto IF_FALSE0
+label IF_TRUE0
+push constant 0
+pop static 0
+label IF_FALSE0
+push local 0
+push constant 1
+sub
+pop local 0
+goto WHILE_EXP0
+label WHILE_END0
+push constant 0
+return
+";
+
+    fn translate(source: &str) -> anyhow::Result<Vec<String>> {
+        let tokens: Result<Vec<_>, _> = Scanner::new(source).collect();
+        let nodes: Result<Vec<_>, _> = Parser::new(tokens?.into_iter()).collect();
+
+        Ok(Translator::new("Main", nodes?).translate())
+    }
+
+    #[test]
+    fn variants_translate_to_the_same_bytes() {
+        let expected = translate(REFERENCE).unwrap();
+
+        let windows = format!("\u{feff}{}", REFERENCE.replace('\n', "\r\n"));
+        let spaced = REFERENCE.replace('\n', "\n\t \n").replace(' ', " \t ");
+        let commented = REFERENCE
+            .replace("label", "/* loop */ label")
+            .replace("\nreturn", "\nreturn // done");
+        let uppercase = REFERENCE
+            .replace("push constant", "PUSH CONSTANT")
+            .replace("pop local", "Pop Local");
+
+        for variant in [windows, spaced, commented, uppercase] {
+            assert_eq!(translate(&variant).unwrap(), expected, "{variant}");
+        }
+    }
+
+    /// The JackCompiler numbers `IF_TRUE0` and the like per function, so the functions
+    /// of a class use the same label names.
+    #[test]
+    fn labels_are_local_to_their_function() {
+        let other = REFERENCE.replace("Main.main", "Main.other");
+        let asm = translate(&format!("{REFERENCE}{other}")).unwrap();
+
+        let mut labels: Vec<_> = asm.iter().filter(|line| line.starts_with('(')).collect();
+        let count = labels.len();
+        labels.sort();
+        labels.dedup();
+        assert_eq!(labels.len(), count, "a label is defined twice");
+        assert!(asm.contains(&"(Main.main$IF_TRUE0)".to_string()));
+        assert!(asm.contains(&"@Main.other$IF_TRUE0".to_string()));
+    }
+
+    #[test]
+    fn names_may_be_spelled_like_keywords() {
+        let asm = translate("label not\ngoto not\nlabel END\ngoto END").unwrap();
+
+        assert!(asm.contains(&"(Main.not)".to_string()));
+        assert!(asm.contains(&"(Main.END)".to_string()));
+    }
+
+    #[test]
+    fn reports_numbers_out_of_range() {
+        let error = translate("push constant 1\npush local 70000").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2:12] Error: Number `70000` is out of range 0..=65535\n 2 | push local 70000\n   |            ^^^^^"
+        );
+
+        let error = translate("push constant 32767\npush constant 32768").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2:15] Error: Constant `32768` is out of range 0..=32767"
+        );
+
+        let error = translate("push constant 1\npop pointer 2").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2:13] Error: Pointer `2` is out of range 0..=1"
+        );
+
+        let error = translate("push constant 1\ngoto 2END").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2:6] Error: `2END` is not a number, and names cannot start with a digit\n 2 | goto 2END\n   |      ^^^^"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_commands() {
+        let error = translate("push constant 1\n\nfoo 2").unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "[line 3] Error: Expected a command but got `foo`; the commands are `push`, `pop`, \
+             `add`, `sub`, `neg`, `eq`, `gt`, `lt`, `and`, `or`, `not`, `label`, `goto`, \
+             `if-goto`, `function`, `call` and `return`"
+        );
+
+        let error = translate("push constant 1\npush heap 2").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2] Error: Expected a segment but got `heap`; the segments are `argument`, \
+             `local`, `static`, `constant`, `this`, `that`, `pointer` and `temp`"
+        );
+
+        let error = translate("function Main.main 0\npush local x").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2] Error: Expected a number but got `x`"
+        );
+
+        let error = translate("function Main.main 0\ncall Main.run").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2] Error: Expected a number but got the end of the file"
+        );
+    }
+}
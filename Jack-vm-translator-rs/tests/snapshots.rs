@@ -0,0 +1,114 @@
+//! Golden outputs: every `.vm` file and directory of `input/` is translated and
+//! compared with its `.asm` file in `tests/snapshots/`.
+//!
+//! `cargo test --test snapshots -- --bless` writes the outputs that changed instead,
+//! so changes of the generated assembly show up in the diff for review; the harness is
+//! [`n2t_core::snapshot`].
+//!
+//! Directories are whole programs: their output is also assembled and run on the
+//! assembler's headless CPU, which must halt, so a snapshot can't record a program
+//! that never ends. The assembler is built from its directory next to this one.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use anyhow::Context;
+use n2t_core::snapshot;
+
+const INPUT_DIR: &str = "input";
+const SNAPSHOT_DIR: &str = "tests/snapshots";
+/// Far more than any fixture program takes to halt.
+const MAX_STEPS: &str = "100000";
+/// SP, LCL and ARG at 256, as no bootstrap code sets them.
+const BOOT_RAM: &str = "0: 256\n1: 256\n2: 256\n";
+
+fn main() -> ExitCode {
+    snapshot::main("vm", |args, temp_dir| {
+        let fixtures = snapshot::fixtures(INPUT_DIR, |path| {
+            path.is_dir() || path.extension().is_some_and(|ext| ext == "vm")
+        })?;
+
+        let mut failed = 0;
+        for fixture in fixtures.iter() {
+            let name = fixture.file_stem().unwrap_or_default().to_string_lossy();
+            if !args.selects(&name) {
+                continue;
+            }
+
+            let golden = Path::new(SNAPSHOT_DIR).join(format!("{name}.asm"));
+            let actual = translate(fixture, &temp_dir.join(format!("{name}.asm")))?;
+            if !snapshot::check(&name, &golden, &actual, args.bless)? {
+                failed += 1;
+            }
+            if fixture.is_dir() && !halts(&name, temp_dir)? {
+                failed += 1;
+            }
+        }
+
+        Ok(failed)
+    })
+}
+
+fn translate(input: &Path, output: &Path) -> anyhow::Result<String> {
+    let run = Command::new(env!("CARGO_BIN_EXE_VMTranslator"))
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .output()?;
+    if !run.status.success() {
+        anyhow::bail!(
+            "Could not translate {}:\n{}",
+            input.display(),
+            String::from_utf8_lossy(&run.stderr)
+        );
+    }
+
+    fs::read_to_string(output).with_context(|| format!("No output for {}", input.display()))
+}
+
+/// Assembles the translated `name` in `dir` and runs it, reporting whether it halts.
+fn halts(name: &str, dir: &Path) -> anyhow::Result<bool> {
+    let ram = dir.join("boot.ram");
+    fs::write(&ram, BOOT_RAM)?;
+    let run = Command::new(assembler()?)
+        .arg(dir.join(format!("{name}.asm")))
+        .arg("-o")
+        .arg(dir.join(format!("{name}.hack")))
+        .arg("--run")
+        .arg("--ram")
+        .arg(&ram)
+        .arg("--max-steps")
+        .arg(MAX_STEPS)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    if run.status.success() && stdout.contains("[~] Halted after") {
+        println!("run {name} ... ok");
+        return Ok(true);
+    }
+
+    println!(
+        "run {name} ... FAILED: does not halt in {MAX_STEPS} steps\n{stdout}{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+
+    Ok(false)
+}
+
+/// Builds the assembler from its directory next to this one.
+fn assembler() -> anyhow::Result<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Hack-assembler-rs");
+    let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .arg("build")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(dir.join("target"))
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("Could not build {}", dir.display());
+    }
+
+    Ok(dir.join("target/debug/hack-assembler-rs"))
+}
@@ -0,0 +1,144 @@
+@0
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@LCL
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+(BasicLoop.LOOP)
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@LCL
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D+M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@LCL
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@ARG
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@BasicLoop.LOOP
+D;JNE
+@LCL
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
@@ -0,0 +1,474 @@
+(Sys.init)
+@0
+D=A
+@4
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push returnAddress
+@Sys.Main.fibonacci.return.0
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push LCL
+@LCL
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push ARG
+@ARG
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THIS
+@THIS
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THAT
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// ARG = SP-5-nArgs
+@SP
+D=M
+@5
+D=D-A
+@1
+D=D-A
+@ARG
+M=D
+// LCL = SP
+@SP
+D=M
+@LCL
+M=D
+// goto functionName
+@Main.fibonacci
+0;JMP
+// (returnaddress)
+(Sys.Main.fibonacci.return.0)
+(Sys.init$END)
+@Sys.init$END
+0;JMP
+(Main.fibonacci)
+@0
+D=A
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@2
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@Main.label_yes.0
+D;JLT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@Main.label_no.0
+0;JMP
+(Main.label_yes.0)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(Main.label_no.0)
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@Main.fibonacci$N_LT_2
+D;JNE
+@Main.fibonacci$N_GE_2
+0;JMP
+(Main.fibonacci$N_LT_2)
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// endFrame - LCL
+@LCL
+D=M
+@endFrame
+M=D
+// retAddr = *(endFrame - 5)
+@5
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@retAddr
+M=D
+// *ARG = pop()
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@ARG
+A=M
+M=D
+// SP = ARG + 1
+@ARG
+D=M
+D=D+1
+@SP
+M=D
+// THAT = *(endFrame - 1)
+@1
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@THAT
+M=D
+// THIS = *(endFrame - 2)
+@2
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@THIS
+M=D
+// ARG = *(endFrame - 3)
+@3
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@ARG
+M=D
+// LCL = *(endFrame - 4)
+@4
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@LCL
+M=D
+// goto retAddr
+@retAddr
+A=M
+0;JMP
+(Main.fibonacci$N_GE_2)
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@2
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push returnAddress
+@Main.Main.fibonacci.return.1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push LCL
+@LCL
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push ARG
+@ARG
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THIS
+@THIS
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THAT
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// ARG = SP-5-nArgs
+@SP
+D=M
+@5
+D=D-A
+@1
+D=D-A
+@ARG
+M=D
+// LCL = SP
+@SP
+D=M
+@LCL
+M=D
+// goto functionName
+@Main.fibonacci
+0;JMP
+// (returnaddress)
+(Main.Main.fibonacci.return.1)
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push returnAddress
+@Main.Main.fibonacci.return.2
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push LCL
+@LCL
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push ARG
+@ARG
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THIS
+@THIS
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push THAT
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// ARG = SP-5-nArgs
+@SP
+D=M
+@5
+D=D-A
+@1
+D=D-A
+@ARG
+M=D
+// LCL = SP
+@SP
+D=M
+@LCL
+M=D
+// goto functionName
+@Main.fibonacci
+0;JMP
+// (returnaddress)
+(Main.Main.fibonacci.return.2)
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D+M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// endFrame - LCL
+@LCL
+D=M
+@endFrame
+M=D
+// retAddr = *(endFrame - 5)
+@5
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@retAddr
+M=D
+// *ARG = pop()
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@ARG
+A=M
+M=D
+// SP = ARG + 1
+@ARG
+D=M
+D=D+1
+@SP
+M=D
+// THAT = *(endFrame - 1)
+@1
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@THAT
+M=D
+// THIS = *(endFrame - 2)
+@2
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@THIS
+M=D
+// ARG = *(endFrame - 3)
+@3
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@ARG
+M=D
+// LCL = *(endFrame - 4)
+@4
+D=A
+@endFrame
+D=M-D
+A=D
+D=M
+@LCL
+M=D
+// goto retAddr
+@retAddr
+A=M
+0;JMP
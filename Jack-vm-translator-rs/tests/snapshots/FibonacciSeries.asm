@@ -0,0 +1,260 @@
+@ARG
+D=M
+@1
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@THAT
+M=D
+@0
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@1
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@2
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@ARG
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+(FibonacciSeries.LOOP)
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@FibonacciSeries.COMPUTE_ELEMENT
+D;JNE
+@FibonacciSeries.END
+0;JMP
+(FibonacciSeries.COMPUTE_ELEMENT)
+@THAT
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@1
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D+M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@2
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D+M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@THAT
+M=D
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@ARG
+D=M
+@0
+D=D+A
+@tmp
+M=D
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@tmp
+A=M
+M=D
+@FibonacciSeries.LOOP
+0;JMP
+(FibonacciSeries.END)
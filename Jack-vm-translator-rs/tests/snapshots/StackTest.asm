@@ -0,0 +1,475 @@
+@17
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@17
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.0
+D;JEQ
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.0
+0;JMP
+(StackTest.label_yes.0)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.0)
+@17
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@16
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.1
+D;JEQ
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.1
+0;JMP
+(StackTest.label_yes.1)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.1)
+@16
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@17
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.2
+D;JEQ
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.2
+0;JMP
+(StackTest.label_yes.2)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.2)
+@892
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@891
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.3
+D;JLT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.3
+0;JMP
+(StackTest.label_yes.3)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.3)
+@891
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@892
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.4
+D;JLT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.4
+0;JMP
+(StackTest.label_yes.4)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.4)
+@891
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@891
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.5
+D;JLT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.5
+0;JMP
+(StackTest.label_yes.5)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.5)
+@32767
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@32766
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.6
+D;JGT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.6
+0;JMP
+(StackTest.label_yes.6)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.6)
+@32766
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@32767
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.7
+D;JGT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.7
+0;JMP
+(StackTest.label_yes.7)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.7)
+@32766
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@32766
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@StackTest.label_yes.8
+D;JGT
+@SP
+A=M
+M=0
+@SP
+M=M+1
+@StackTest.label_no.8
+0;JMP
+(StackTest.label_yes.8)
+@SP
+A=M
+M=-1
+@SP
+M=M+1
+(StackTest.label_no.8)
+@57
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@31
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@53
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D+M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@112
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+A=M
+M=-D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D&M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@82
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=D|M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M-1
+@SP
+A=M
+D=M
+@SP
+A=M
+M=!D
+@SP
+M=M+1
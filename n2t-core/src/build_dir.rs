@@ -0,0 +1,104 @@
+//! Where the tools write what is not their output, such as debug dumps: one build
+//! directory, `target-n2t/` by default, instead of directories next to the sources.
+//!
+//! A file about an input goes to `<build dir>/<tool>/<input path>.<suffix>`, with the
+//! input path as given, less its root, and `..` written as `__`. The same run always
+//! writes the same paths, and inputs of the same name in different directories do not
+//! overwrite each other.
+
+use std::fs::{File, create_dir_all};
+use std::path::{Component, Path, PathBuf};
+
+pub const DEFAULT: &str = "target-n2t";
+
+/// Environment variable naming the build directory, when no flag does.
+pub const ENV: &str = "N2T_BUILD_DIR";
+
+/// Environment variable turning every `DEBUG_*` output of a tool on.
+pub const DEBUG_ALL: &str = "DEBUG_ALL";
+
+/// Whether the debug output of the environment variable `name`, such as
+/// `DEBUG_TOKENS`, is on: it or [`DEBUG_ALL`] is set.
+pub fn debug_enabled(name: &str) -> bool {
+    std::env::var_os(DEBUG_ALL).is_some() || std::env::var_os(name).is_some()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildDir {
+    root: PathBuf,
+}
+
+impl Default for BuildDir {
+    fn default() -> Self {
+        Self::new(DEFAULT)
+    }
+}
+
+impl BuildDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The directory of `N2T_BUILD_DIR`, or [`DEFAULT`].
+    pub fn from_env() -> Self {
+        match std::env::var_os(ENV) {
+            Some(root) => Self::new(root),
+            None => Self::default(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the `suffix` file `tool` writes about `input`.
+    pub fn path(&self, tool: &str, input: &Path, suffix: &str) -> PathBuf {
+        let mut path = self.root.join(tool);
+        for component in input.components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::ParentDir => path.push("__"),
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            }
+        }
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(suffix);
+        path.set_file_name(file_name);
+
+        path
+    }
+
+    /// Creates the file of [`Self::path`], and the directories it is in.
+    pub fn create(&self, tool: &str, input: &Path, suffix: &str) -> anyhow::Result<File> {
+        let path = self.path(tool, input, suffix);
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        Ok(File::create(path)?)
+    }
+}
+
+#[cfg(test)]
+mod build_dir_tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_the_input_path() {
+        let build_dir = BuildDir::default();
+
+        assert_eq!(
+            build_dir.path("VMTranslator", Path::new("input/Pong/Main.vm"), "tokens"),
+            Path::new("target-n2t/VMTranslator/input/Pong/Main.vm.tokens")
+        );
+        assert_eq!(
+            build_dir.path("VMTranslator", Path::new("/tmp/./Main.vm"), "ast"),
+            Path::new("target-n2t/VMTranslator/tmp/Main.vm.ast")
+        );
+        assert_eq!(
+            BuildDir::new("out").path("asm", Path::new("../Max.asm"), "ast"),
+            Path::new("out/asm/__/Max.asm.ast")
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Errors pointing at a span of the source.
+
+use std::fmt::Display;
+
+/// A scan or parse error at `line`:`column` (1-based, in characters), `width` characters wide.
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub message: String,
+    /// Stable code of errors tools may want to match on, e.g. the assembler's `E001`.
+    pub code: Option<&'static str>,
+}
+
+impl SyntaxError {
+    pub fn new(line: usize, column: usize, width: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            width: width.max(1),
+            message: message.into(),
+            code: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// The message followed by the source line, with carets under the span:
+    ///
+    /// ```text
+    /// [line 2:4] Error: Unexpected character `#`
+    ///   2 | D=M#1
+    ///     |    ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let Some(text) = source.lines().nth(self.line - 1) else {
+            return self.to_string();
+        };
+        let gutter = self.line.to_string().len();
+
+        format!(
+            "{self}\n {:>gutter$} | {}\n {:gutter$} | {}{}",
+            self.line,
+            text.trim_end(),
+            "",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.width)
+        )
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}:{}] Error", self.line, self.column)?;
+        if let Some(code) = self.code {
+            write!(f, "[{code}]")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// Shows the source line of a [`SyntaxError`]; other errors are left as they are.
+pub fn with_source(error: anyhow::Error, source: &str) -> anyhow::Error {
+    match error.downcast_ref::<SyntaxError>() {
+        Some(syntax_error) => anyhow::anyhow!(syntax_error.render(source)),
+        None => error,
+    }
+}
+
+#[cfg(test)]
+mod diagnostic_tests {
+    use super::*;
+
+    #[test]
+    fn renders_carets_under_the_span() {
+        let error = SyntaxError::new(12, 3, 2, "Unknown").with_code("E042");
+
+        assert_eq!(error.to_string(), "[line 12:3] Error[E042]: Unknown");
+        assert_eq!(
+            error.render(&"\n".repeat(11)),
+            error.to_string(),
+            "A line past the source has nothing to point at"
+        );
+        assert_eq!(
+            error.render(&format!("{}@ABC\n", "\n".repeat(11))),
+            "[line 12:3] Error[E042]: Unknown\n 12 | @ABC\n    |   ^^"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! The input files of the tools, told apart by their extensions.
+
+use std::path::Path;
+
+/// Whether the extension of `path` is one of `exts`, ignoring case and a leading `.`
+/// of theirs.
+pub fn has_extension(path: &Path, exts: &[String]) -> bool {
+    path.extension().and_then(|s| s.to_str()).is_some_and(|e| {
+        exts.iter()
+            .any(|ext| e.eq_ignore_ascii_case(ext.trim_start_matches('.')))
+    })
+}
+
+#[cfg(test)]
+mod files_tests {
+    use super::*;
+
+    #[test]
+    fn ignores_case_and_dots() {
+        let exts = [".vm".to_string(), "jack".to_string()];
+
+        assert!(has_extension(Path::new("Main.VM"), &exts));
+        assert!(has_extension(Path::new("dir/Main.jack"), &exts));
+        assert!(!has_extension(Path::new("Main.asm"), &exts));
+        assert!(!has_extension(Path::new("vm"), &exts));
+    }
+}
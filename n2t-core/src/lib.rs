@@ -0,0 +1,14 @@
+//! What the assembler, the VM translator and the Jack compiler share: errors pointing
+//! at the source, how inputs are told apart, the provenance header they pass along, the
+//! build directory of their debug files and the `DEBUG_*` variables, the line maps
+//! from generated code to its source, stage profiling, what `--version` says of a
+//! build, and the harness of their snapshot tests.
+
+pub mod build_dir;
+pub mod diagnostic;
+pub mod files;
+pub mod line_map;
+pub mod profiling;
+pub mod provenance;
+pub mod snapshot;
+pub mod version;
@@ -0,0 +1,183 @@
+//! Line maps: for each file a tool generates, the source line every stretch of its
+//! lines comes from. The VM translator maps `.asm` lines to `.vm` lines and the Jack
+//! compiler `.vm` lines to `.jack` lines, so that the assembler can carry what it knows
+//! of `.asm` lines, such as how often they ran or where a program stopped, back
+//! through both.
+//!
+//! Files are matched by file name, since the tools may be given the same file under
+//! different directories.
+
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LineMap {
+    pub files: Vec<MappedFile>,
+}
+
+/// A generated file and the origins of its lines.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MappedFile {
+    pub file: String,
+    /// By `line`: each origin covers the lines up to the next one.
+    pub origins: Vec<Origin>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Origin {
+    /// The first generated line, 1-based.
+    pub line: usize,
+    pub source: String,
+    pub source_line: usize,
+}
+
+impl LineMap {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        serde_json::from_str(&read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write(path, serde_json::to_string_pretty(self)? + "\n")?;
+
+        Ok(())
+    }
+
+    /// Lines of `file` from `line` on come from `source_line` of `source`.
+    pub fn push(&mut self, file: &str, line: usize, source: &str, source_line: usize) {
+        let origin = Origin {
+            line,
+            source: source.to_string(),
+            source_line,
+        };
+        match self.files.iter_mut().find(|mapped| mapped.file == file) {
+            Some(mapped) => mapped.origins.push(origin),
+            None => self.files.push(MappedFile {
+                file: file.to_string(),
+                origins: vec![origin],
+            }),
+        }
+    }
+
+    /// The generated file named like `file`.
+    pub fn get(&self, file: &str) -> Option<&MappedFile> {
+        let name = file_name(file);
+        self.files
+            .iter()
+            .find(|mapped| file_name(&mapped.file) == name)
+    }
+}
+
+/// The lines `line` of `file` comes from through `maps`, given in any order: its
+/// source line, then the source line of that, and so on.
+pub fn trace(maps: &[LineMap], file: &str, line: usize) -> Vec<(String, usize)> {
+    let mut traced: Vec<(String, usize)> = vec![];
+    let (mut file, mut line) = (file.to_string(), line);
+    // A map per step at most, so maps that lead back to a file end
+    while traced.len() < maps.len() {
+        let Some(origin) = maps
+            .iter()
+            .filter_map(|map| map.get(&file))
+            .find_map(|mapped| mapped.origin(line))
+        else {
+            break;
+        };
+        (file, line) = (origin.source.clone(), origin.source_line);
+        traced.push((file.clone(), line));
+    }
+
+    traced
+}
+
+impl MappedFile {
+    /// The origin of the stretch generated `line` is in, unless it comes before the
+    /// first.
+    pub fn origin(&self, line: usize) -> Option<&Origin> {
+        let i = self.origins.partition_point(|origin| origin.line <= line);
+
+        i.checked_sub(1).map(|i| &self.origins[i])
+    }
+
+    /// `counts` of generated lines as counts of source lines, by source: a source line
+    /// counts what the first counted line of its stretch does, and the most of its
+    /// stretches when it has several. Lines without a count stay out, so a stretch of
+    /// only comments or labels does not count as never run.
+    pub fn carry(&self, counts: &BTreeMap<usize, u64>) -> BTreeMap<String, BTreeMap<usize, u64>> {
+        let mut carried: BTreeMap<String, BTreeMap<usize, u64>> = BTreeMap::new();
+        for (i, origin) in self.origins.iter().enumerate() {
+            let end = self.origins.get(i + 1).map_or(usize::MAX, |next| next.line);
+            let Some((_, &count)) = counts.range(origin.line..end.max(origin.line)).next() else {
+                continue;
+            };
+            let line = carried
+                .entry(origin.source.clone())
+                .or_default()
+                .entry(origin.source_line)
+                .or_default();
+            *line = (*line).max(count);
+        }
+
+        carried
+    }
+}
+
+fn file_name(file: &str) -> &str {
+    Path::new(file)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(file)
+}
+
+#[cfg(test)]
+mod line_map_tests {
+    use super::*;
+
+    #[test]
+    fn carries_counts_to_the_source_lines() {
+        let mut map = LineMap::default();
+        map.push("out/Prog.asm", 1, "Main.vm", 2);
+        map.push("out/Prog.asm", 4, "Main.vm", 3);
+        map.push("out/Prog.asm", 6, "Main.vm", 2);
+        map.push("out/Prog.asm", 7, "Util.vm", 1);
+        map.push("Other.asm", 1, "Other.vm", 1);
+
+        let mapped = map.get("build/Prog.asm").unwrap();
+        assert_eq!(mapped.file, "out/Prog.asm");
+        assert!(map.get("Prog.vm").is_none());
+
+        // Line 1 is a label, 4 and 5 never ran, 9 is past the last origin's first line
+        let counts = BTreeMap::from([(2, 1), (3, 1), (4, 0), (5, 0), (6, 7), (9, 3)]);
+        assert_eq!(
+            mapped.carry(&counts),
+            BTreeMap::from([
+                ("Main.vm".to_string(), BTreeMap::from([(2, 7), (3, 0)])),
+                ("Util.vm".to_string(), BTreeMap::from([(1, 3)])),
+            ])
+        );
+    }
+
+    #[test]
+    fn traces_a_line_through_every_map() {
+        let mut vm_map = LineMap::default();
+        vm_map.push("Prog.asm", 3, "Main.vm", 2);
+        vm_map.push("Prog.asm", 9, "Main.vm", 5);
+        let mut jack_map = LineMap::default();
+        jack_map.push("Main.vm", 1, "Main.jack", 4);
+        jack_map.push("Main.vm", 5, "Main.jack", 7);
+        let maps = [jack_map, vm_map];
+
+        assert_eq!(
+            trace(&maps, "out/Prog.asm", 10),
+            [("Main.vm".to_string(), 5), ("Main.jack".to_string(), 7)]
+        );
+        assert_eq!(
+            trace(&maps, "Prog.asm", 4),
+            [("Main.vm".to_string(), 2), ("Main.jack".to_string(), 4)]
+        );
+        assert!(trace(&maps, "Prog.asm", 2).is_empty());
+        assert!(trace(&maps, "Other.asm", 10).is_empty());
+    }
+}
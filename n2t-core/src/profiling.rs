@@ -0,0 +1,269 @@
+//! Time and allocations of every pipeline stage, with the `profiling` feature of a
+//! tool, which turns on this crate's:
+//!
+//! ```bash
+//! cargo run --release --features profiling -- input/Pong.asm -o output/Pong.hack
+//! ```
+//!
+//! Stages are wrapped in [`stage`], which only calls them without the feature. With
+//! it, every run of a stage is measured and handed to a [`Sink`]: a [`Report`] printed
+//! once the program is done, or a [`Csv`] file of every run when `PROFILE_CSV` names
+//! one. Allocations are counted across threads, by a global allocator wrapping the
+//! system's.
+
+#[cfg(feature = "profiling")]
+pub use enabled::*;
+
+/// Runs `f` as the stage `name`.
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
+pub fn stage<T>(_name: &'static str, f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn start() -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn finish() -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, Instant};
+
+    const PROFILE_CSV: &str = "PROFILE_CSV";
+
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+    /// The system allocator, counting what is allocated.
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    static SINK: Mutex<Option<Box<dyn Sink>>> = Mutex::new(None);
+
+    /// One run of a stage.
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct Sample {
+        pub elapsed: Duration,
+        /// Allocations and reallocations, frees aside.
+        pub allocations: u64,
+        pub bytes: u64,
+    }
+
+    impl Sample {
+        fn add(&mut self, other: &Sample) {
+            self.elapsed += other.elapsed;
+            self.allocations += other.allocations;
+            self.bytes += other.bytes;
+        }
+    }
+
+    /// Where the samples go.
+    pub trait Sink: Send {
+        fn record(&mut self, stage: &'static str, sample: Sample);
+
+        /// Called once the program is done.
+        fn finish(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Totals of every stage, in the order they first ran, printed as a table.
+    #[derive(Debug, Default)]
+    pub struct Report {
+        pub stages: Vec<(&'static str, usize, Sample)>,
+    }
+
+    impl Sink for Report {
+        fn record(&mut self, stage: &'static str, sample: Sample) {
+            match self.stages.iter_mut().find(|(name, ..)| *name == stage) {
+                Some((_, runs, total)) => {
+                    *runs += 1;
+                    total.add(&sample);
+                }
+                None => self.stages.push((stage, 1, sample)),
+            }
+        }
+
+        fn finish(&mut self) -> anyhow::Result<()> {
+            if self.stages.is_empty() {
+                return Ok(());
+            }
+
+            println!("[~] Profile:");
+            println!(
+                "[~]   {:<12} {:>6} {:>12} {:>12} {:>14}",
+                "stage", "runs", "time", "allocations", "bytes"
+            );
+            for (stage, runs, total) in self.stages.iter() {
+                println!(
+                    "[~]   {stage:<12} {runs:>6} {:>12} {:>12} {:>14}",
+                    format!("{:.3?}", total.elapsed),
+                    total.allocations,
+                    total.bytes
+                );
+            }
+
+            Ok(())
+        }
+    }
+
+    /// `stage,nanos,allocations,bytes` lines, one per run.
+    pub struct Csv {
+        file: BufWriter<File>,
+    }
+
+    impl Csv {
+        pub fn create(path: &Path) -> anyhow::Result<Self> {
+            let mut file = BufWriter::new(File::create(path)?);
+            writeln!(file, "stage,nanos,allocations,bytes")?;
+
+            Ok(Self { file })
+        }
+    }
+
+    impl Sink for Csv {
+        fn record(&mut self, stage: &'static str, sample: Sample) {
+            let _ = writeln!(
+                self.file,
+                "{stage},{},{},{}",
+                sample.elapsed.as_nanos(),
+                sample.allocations,
+                sample.bytes
+            );
+        }
+
+        fn finish(&mut self) -> anyhow::Result<()> {
+            self.file.flush()?;
+
+            Ok(())
+        }
+    }
+
+    /// Sends the samples of the stages run from now on to `sink`.
+    pub fn set_sink(sink: Box<dyn Sink>) {
+        *SINK.lock().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+    }
+
+    /// Picks the sink: the file of `PROFILE_CSV` when set, or a [`Report`].
+    pub fn start() -> anyhow::Result<()> {
+        match std::env::var_os(PROFILE_CSV) {
+            Some(path) => {
+                println!("[<-] Profile: {}", Path::new(&path).display());
+                set_sink(Box::new(Csv::create(Path::new(&path))?));
+            }
+            None => set_sink(Box::new(Report::default())),
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` as the stage `name`.
+    pub fn stage<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let result = f();
+        let sample = Sample {
+            elapsed: start.elapsed(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+        };
+
+        if let Some(sink) = SINK.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            sink.record(name, sample);
+        }
+
+        result
+    }
+
+    pub fn finish() -> anyhow::Result<()> {
+        match SINK.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(test)]
+    mod profiling_tests {
+        use std::sync::Arc;
+
+        use super::*;
+
+        struct Shared(Arc<Mutex<Vec<(&'static str, Sample)>>>);
+
+        impl Sink for Shared {
+            fn record(&mut self, stage: &'static str, sample: Sample) {
+                self.0.lock().unwrap().push((stage, sample));
+            }
+        }
+
+        #[test]
+        fn measures_the_allocations_of_a_stage() {
+            let samples = Arc::new(Mutex::new(vec![]));
+            set_sink(Box::new(Shared(samples.clone())));
+
+            let words = stage("allocate", || vec![0u16; 1000]);
+            assert_eq!(words.len(), 1000);
+
+            let samples = samples.lock().unwrap();
+            let (_, sample) = samples
+                .iter()
+                .find(|(name, _)| *name == "allocate")
+                .unwrap();
+            // Other tests allocate meanwhile
+            assert!(sample.allocations >= 1);
+            assert!(sample.bytes >= 2000);
+        }
+
+        #[test]
+        fn report_adds_up_every_stage() {
+            let mut report = Report::default();
+            let sample = Sample {
+                elapsed: Duration::from_millis(2),
+                allocations: 3,
+                bytes: 100,
+            };
+            report.record("scan", sample);
+            report.record("parse", sample);
+            report.record("scan", sample);
+
+            assert_eq!(report.stages.len(), 2);
+            assert_eq!(report.stages[0].0, "scan");
+            assert_eq!(report.stages[0].1, 2);
+            assert_eq!(report.stages[0].2.allocations, 6);
+            assert_eq!(report.stages[1].0, "parse");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! The part of provenance the tools agree on: the VM translator writes header lines
+//! starting with [`PREFIX`] and the assembler picks them up.
+
+use sha2::{Digest, Sha256};
+
+/// Every header line starts with it, so later tools can pick them up.
+pub const PREFIX: &str = "// provenance: ";
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod provenance_tests {
+    use super::*;
+
+    #[test]
+    fn sha256() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! The golden output tests of the tools, `cargo test --test snapshots`: each fixture
+//! is run through the tool and its output compared with a snapshot checked in.
+//!
+//! `-- --bless` writes the outputs that changed instead, so changes show up in the
+//! diff for review. Other arguments select the fixtures whose names contain them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// The arguments of a snapshot test.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub bless: bool,
+    filters: Vec<String>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+
+        Self {
+            bless: args.iter().any(|arg| arg == "--bless"),
+            filters: args
+                .into_iter()
+                .filter(|arg| !arg.starts_with('-'))
+                .collect(),
+        }
+    }
+
+    /// Whether the fixture `name` is selected, as every one is without filters.
+    pub fn selects(&self, name: &str) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|filter| name.contains(filter))
+    }
+}
+
+/// Runs `test` with a temporary directory named after `tool`, removed afterwards.
+/// `test` gives the number of fixtures that failed.
+pub fn main(tool: &str, test: impl FnOnce(&Args, &Path) -> anyhow::Result<usize>) -> ExitCode {
+    let args = Args::from_env();
+    let temp_dir = std::env::temp_dir().join(format!("{tool}-snapshots-{}", std::process::id()));
+    let result = fs::create_dir_all(&temp_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| test(&args, &temp_dir));
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(failed) => {
+            eprintln!("{failed} snapshot(s) differ; rerun with `-- --bless` to accept them");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The entries of `dir` that `keep` accepts, sorted.
+pub fn fixtures(dir: &str, keep: impl Fn(&Path) -> bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut fixtures: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<_>>()?;
+    fixtures.retain(|path| keep(path));
+    fixtures.sort();
+
+    Ok(fixtures)
+}
+
+/// Compares `actual` with the snapshot at `golden`, or writes it there with `bless`.
+pub fn check(name: &str, golden: &Path, actual: &str, bless: bool) -> anyhow::Result<bool> {
+    let expected = fs::read_to_string(golden).ok();
+    if expected.as_deref() == Some(actual) {
+        println!("snapshot {name} ... ok");
+        return Ok(true);
+    }
+    if bless {
+        fs::write(golden, actual)?;
+        println!("snapshot {name} ... blessed {}", golden.display());
+        return Ok(true);
+    }
+
+    match expected {
+        None => println!("snapshot {name} ... FAILED: no {}", golden.display()),
+        Some(expected) => println!(
+            "snapshot {name} ... FAILED: {}",
+            first_difference(&expected, actual)
+        ),
+    }
+
+    Ok(false)
+}
+
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => continue,
+            (Some(e), Some(a)) => return format!("line {line} is `{a}`, expected `{e}`"),
+            (Some(e), None) => return format!("ends at line {line}, expected `{e}`"),
+            (None, Some(a)) => return format!("line {line} is `{a}`, expected the end"),
+            (None, None) => break,
+        }
+    }
+
+    "line endings differ".to_string()
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+
+    #[test]
+    fn names_the_first_difference() {
+        assert_eq!(
+            first_difference("@1\nD=A\n", "@1\nD=M\n"),
+            "line 2 is `D=M`, expected `D=A`"
+        );
+        assert_eq!(
+            first_difference("@1\nD=A", "@1"),
+            "ends at line 2, expected `D=A`"
+        );
+        assert_eq!(
+            first_difference("@1", "@1\nD=A"),
+            "line 2 is `D=A`, expected the end"
+        );
+        assert_eq!(first_difference("@1\n", "@1\r\n"), "line endings differ");
+    }
+
+    #[test]
+    fn selects_fixtures_by_name() {
+        let args = Args {
+            bless: false,
+            filters: vec!["Fib".into()],
+        };
+
+        assert!(args.selects("FibonacciElement"));
+        assert!(!args.selects("StaticsTest"));
+        assert!(Args::default().selects("StaticsTest"));
+    }
+}
@@ -0,0 +1,115 @@
+//! What a build of each tool is, the same way for all of them: `--version` prints it,
+//! and provenance records it next to the outputs.
+
+/// Commit the tools were built from, or `unknown` outside a git checkout.
+pub const GIT_HASH: &str = env!("N2T_GIT_HASH");
+
+/// Target triple, such as `x86_64-unknown-linux-gnu`.
+pub const TARGET: &str = env!("N2T_TARGET");
+
+/// `debug` or `release`.
+pub const PROFILE: &str = env!("N2T_PROFILE");
+
+/// The [`Version`] of the crate it is used in, with the Cargo features of the tool:
+/// `version!("xml", "profiling")`.
+#[macro_export]
+macro_rules! version {
+    ($($feature:literal),* $(,)?) => {
+        $crate::version::Version::new(
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            &[$(($feature, cfg!(feature = $feature))),*],
+        )
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    pub tool: &'static str,
+    pub version: &'static str,
+    /// Cargo features of the tool that are enabled.
+    pub features: Vec<&'static str>,
+    long: String,
+}
+
+impl Version {
+    /// `features` pairs every feature of the tool with whether it is enabled, as
+    /// `("xml", cfg!(feature = "xml"))`.
+    pub fn new(
+        tool: &'static str,
+        version: &'static str,
+        features: &[(&'static str, bool)],
+    ) -> Self {
+        let features: Vec<_> = features
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        let long = format!(
+            "{version}\ncommit: {GIT_HASH}\nfeatures: {}\ntarget: {TARGET} ({PROFILE})",
+            feature_list(&features)
+        );
+
+        Self {
+            tool,
+            version,
+            features,
+            long,
+        }
+    }
+
+    /// The version, then a line each for the commit, features and target; clap's
+    /// `--version` prints it after the tool name.
+    pub fn long(&self) -> &str {
+        &self.long
+    }
+
+    /// One line of `key=value` pairs, for provenance headers.
+    pub fn provenance(&self) -> String {
+        format!(
+            "tool={} version={} commit={GIT_HASH} features={}",
+            self.tool,
+            self.version,
+            self.features.join(",")
+        )
+    }
+}
+
+fn feature_list(features: &[&str]) -> String {
+    match features.is_empty() {
+        true => "none".to_string(),
+        false => features.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod version_tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_enabled_features() {
+        let version = Version::new("tool", "1.2.3", &[("xml", true), ("profiling", false)]);
+
+        assert_eq!(version.features, ["xml"]);
+        assert!(version.long().starts_with(&format!(
+            "1.2.3\ncommit: {GIT_HASH}\nfeatures: xml\ntarget: "
+        )));
+        assert_eq!(
+            version.provenance(),
+            format!("tool=tool version=1.2.3 commit={GIT_HASH} features=xml")
+        );
+        assert!(
+            Version::new("tool", "1.2.3", &[])
+                .long()
+                .contains("features: none")
+        );
+    }
+
+    #[test]
+    fn names_the_crate_it_is_used_in() {
+        let version = crate::version!("profiling");
+
+        assert_eq!((version.tool, version.version), ("n2t-core", env!("CARGO_PKG_VERSION")));
+        assert_eq!(version.features.is_empty(), cfg!(not(feature = "profiling")));
+    }
+}